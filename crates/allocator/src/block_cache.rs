@@ -0,0 +1,446 @@
+//! Front-end cache of fixed-size blocks.
+//!
+//! This module provides [`BlockCache`], a small cache of free blocks kept in
+//! front of a shared allocator. It is intended to be instantiated once per
+//! CPU, so that most of the small allocations and deallocations are served
+//! from the cache of the current CPU without taking the lock of the shared
+//! allocator.
+//!
+//! # Algorithm
+//!
+//! The cache holds a *magazine*, a fixed-capacity stack of free blocks, for
+//! each block size of [`FixedSizeBlockAllocator`]:
+//!
+//! - **Allocation**: Pops a block from the magazine of the size class. When the
+//!   magazine is empty, the caller takes the lock of the shared allocator and
+//!   [refills](BlockCache::refill) the magazine up to half of its capacity
+//! - **Deallocation**: Pushes the block onto the magazine. When the magazine is
+//!   full, the caller takes the lock and [trims](BlockCache::trim) the magazine
+//!   down to half of its capacity
+//! - **Flush**: All the cached blocks are returned to the shared allocator by
+//!   [`BlockCache::flush`], e.g. when the CPU owning the cache goes offline
+//!
+//! Refilling and trimming to the half capacity leaves room for both
+//! allocations and deallocations, so that a CPU alternating between them does
+//! not take the lock on each call.
+//!
+//! The blocks are taken from and returned to the shared allocator with the
+//! layout of their size class, given by
+//! [`block_layout`](crate::fixed_size_block::block_layout), so the cached
+//! blocks are indistinguishable from the ones allocated directly.
+//!
+//! # Usage Example
+//!
+//! ```rust
+//! use core::alloc::Layout;
+//!
+//! use allocator::{block_cache::BlockCache, fixed_size_block::FixedSizeBlockAllocator};
+//!
+//! let mut shared = FixedSizeBlockAllocator::new();
+//! let mut heap = vec![0u8; 8192];
+//! unsafe {
+//!     shared.add_heap(heap.as_mut_ptr(), heap.len());
+//! }
+//!
+//! let mut cache = BlockCache::new();
+//! let layout = Layout::from_size_align(64, 8).unwrap();
+//!
+//! // The first allocation misses, and refills the magazine from the shared
+//! // allocator
+//! let ptr = cache.allocate(layout).or_else(|| {
+//!     cache.refill(layout, &mut shared);
+//!     cache.allocate(layout)
+//! });
+//!
+//! if let Some(ptr) = ptr {
+//!     unsafe {
+//!         if !cache.deallocate(ptr, layout) {
+//!             cache.trim(layout, &mut shared);
+//!             assert!(cache.deallocate(ptr, layout));
+//!         }
+//!     }
+//! }
+//!
+//! // Return the cached blocks before the shared allocator is dropped
+//! cache.flush(&mut shared);
+//! ```
+
+use core::{alloc::Layout, ptr};
+
+use crate::fixed_size_block::{self, FixedSizeBlockAllocator};
+
+/// Maximum number of the blocks cached for each block size.
+const MAGAZINE_CAPACITY: usize = 16;
+
+/// Shared allocator that a [`BlockCache`] takes the blocks from.
+pub trait BlockSource {
+    /// Allocates a block of `layout`.
+    ///
+    /// Returns `None` if the allocation fails.
+    fn allocate_block(&mut self, layout: Layout) -> Option<*mut u8>;
+
+    /// Deallocates a block previously allocated by
+    /// [`allocate_block`](Self::allocate_block).
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be allocated by this source with `layout`, and must not be
+    /// used after the deallocation.
+    unsafe fn deallocate_block(&mut self, ptr: *mut u8, layout: Layout);
+}
+
+impl BlockSource for FixedSizeBlockAllocator {
+    fn allocate_block(&mut self, layout: Layout) -> Option<*mut u8> {
+        self.allocate(layout)
+    }
+
+    unsafe fn deallocate_block(&mut self, ptr: *mut u8, layout: Layout) {
+        unsafe { self.deallocate(ptr, layout) }
+    }
+}
+
+/// Fixed-capacity stack of the free blocks of one block size.
+struct Magazine {
+    blocks: [*mut u8; MAGAZINE_CAPACITY],
+    len: usize,
+}
+
+impl Magazine {
+    const fn new() -> Self {
+        Self {
+            blocks: [ptr::null_mut(); MAGAZINE_CAPACITY],
+            len: 0,
+        }
+    }
+
+    fn pop(&mut self) -> Option<*mut u8> {
+        self.len = self.len.checked_sub(1)?;
+        Some(self.blocks[self.len])
+    }
+
+    fn push(&mut self, ptr: *mut u8) -> bool {
+        if self.len == MAGAZINE_CAPACITY {
+            return false;
+        }
+        self.blocks[self.len] = ptr;
+        self.len += 1;
+        true
+    }
+}
+
+/// Cache of free fixed-size blocks in front of a shared allocator.
+///
+/// The cache itself never allocates: the caller refills or trims it with the
+/// shared allocator, typically while holding the lock of the allocator, when
+/// [`allocate`](Self::allocate) or [`deallocate`](Self::deallocate) cannot be
+/// served from the cache.
+///
+/// The cached blocks are owned by the cache, and must be returned with
+/// [`flush`](Self::flush) before the cache is dropped, or they are leaked.
+pub struct BlockCache {
+    magazines: [Magazine; fixed_size_block::BLOCK_SIZES.len()],
+}
+
+// The blocks are owned by the cache, and not accessed through the pointers.
+unsafe impl Send for BlockCache {}
+
+impl Default for BlockCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BlockCache {
+    /// Creates a new empty cache.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            magazines: [const { Magazine::new() }; _],
+        }
+    }
+
+    fn size_class(layout: Layout) -> Option<usize> {
+        if layout.size() < layout.align() {
+            return None;
+        }
+        fixed_size_block::list_index(&layout)
+    }
+
+    /// Returns whether the allocations of `layout` can be cached.
+    ///
+    /// Layouts larger than the largest block size are never cached.
+    #[must_use]
+    pub fn is_cacheable(layout: Layout) -> bool {
+        Self::size_class(layout).is_some()
+    }
+
+    /// Allocates a cached block for `layout`.
+    ///
+    /// Returns `None` if the layout is not cacheable or no block of its size
+    /// is cached. The caller should [`refill`](Self::refill) the cache and
+    /// try again, or allocate from the shared allocator directly.
+    pub fn allocate(&mut self, layout: Layout) -> Option<*mut u8> {
+        let index = Self::size_class(layout)?;
+        self.magazines[index].pop()
+    }
+
+    /// Caches a block freed with `layout`.
+    ///
+    /// Returns `false` if the layout is not cacheable or the cache of its
+    /// size is full, in which case the block is left to the caller. The
+    /// caller should [`trim`](Self::trim) the cache and try again, or free
+    /// the block to the shared allocator directly.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be allocated with `layout` from the cache, or from the
+    /// shared allocator the cache is refilled from, and must not be used after
+    /// the deallocation.
+    #[must_use]
+    pub unsafe fn deallocate(&mut self, ptr: *mut u8, layout: Layout) -> bool {
+        let Some(index) = Self::size_class(layout) else {
+            return false;
+        };
+        self.magazines[index].push(ptr)
+    }
+
+    /// Fills the cache of the blocks for `layout` up to half of its capacity
+    /// from `source`.
+    ///
+    /// Returns `true` if at least one block of the size is cached afterwards.
+    pub fn refill<S>(&mut self, layout: Layout, source: &mut S) -> bool
+    where
+        S: BlockSource + ?Sized,
+    {
+        let Some(index) = Self::size_class(layout) else {
+            return false;
+        };
+        let block_layout = fixed_size_block::alloc_layout(index);
+        let magazine = &mut self.magazines[index];
+        while magazine.len < MAGAZINE_CAPACITY / 2 {
+            let Some(ptr) = source.allocate_block(block_layout) else {
+                break;
+            };
+            let pushed = magazine.push(ptr);
+            debug_assert!(pushed);
+        }
+        magazine.len > 0
+    }
+
+    /// Returns the cached blocks for `layout` to `source`, leaving half of
+    /// the capacity.
+    pub fn trim<S>(&mut self, layout: Layout, source: &mut S)
+    where
+        S: BlockSource + ?Sized,
+    {
+        let Some(index) = Self::size_class(layout) else {
+            return;
+        };
+        self.release(index, MAGAZINE_CAPACITY / 2, source);
+    }
+
+    /// Returns all the cached blocks to `source`.
+    pub fn flush<S>(&mut self, source: &mut S)
+    where
+        S: BlockSource + ?Sized,
+    {
+        for index in 0..fixed_size_block::BLOCK_SIZES.len() {
+            self.release(index, 0, source);
+        }
+    }
+
+    fn release<S>(&mut self, index: usize, keep: usize, source: &mut S)
+    where
+        S: BlockSource + ?Sized,
+    {
+        let block_layout = fixed_size_block::alloc_layout(index);
+        let magazine = &mut self.magazines[index];
+        while magazine.len > keep {
+            let ptr = magazine.pop().unwrap();
+            unsafe {
+                source.deallocate_block(ptr, block_layout);
+            }
+        }
+    }
+
+    /// Returns the total size of the cached blocks in bytes.
+    #[must_use]
+    pub fn cached_bytes(&self) -> usize {
+        self.magazines
+            .iter()
+            .enumerate()
+            .map(|(index, magazine)| magazine.len * fixed_size_block::alloc_layout(index).size())
+            .sum()
+    }
+}
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+#[cfg(test)]
+mod tests {
+    extern crate alloc;
+
+    use alloc::vec::Vec;
+    use core::iter;
+
+    use super::*;
+
+    /// Source counting the live blocks.
+    struct CountingSource {
+        allocator: FixedSizeBlockAllocator,
+        live: usize,
+    }
+
+    impl BlockSource for CountingSource {
+        fn allocate_block(&mut self, layout: Layout) -> Option<*mut u8> {
+            let ptr = self.allocator.allocate(layout)?;
+            self.live += 1;
+            Some(ptr)
+        }
+
+        unsafe fn deallocate_block(&mut self, ptr: *mut u8, layout: Layout) {
+            unsafe {
+                self.allocator.deallocate(ptr, layout);
+            }
+            self.live -= 1;
+        }
+    }
+
+    fn with_test_source<F>(heap_size: usize, test_fn: F)
+    where
+        F: FnOnce(&mut CountingSource),
+    {
+        let layout = Layout::from_size_align(heap_size, 4096).unwrap();
+        unsafe {
+            let heap = alloc::alloc::alloc(layout);
+            let mut source = CountingSource {
+                allocator: FixedSizeBlockAllocator::new(),
+                live: 0,
+            };
+            source.allocator.add_heap(heap, heap_size);
+            test_fn(&mut source);
+            alloc::alloc::dealloc(heap, layout);
+        }
+    }
+
+    #[test]
+    fn test_size_class() {
+        assert!(BlockCache::is_cacheable(
+            Layout::from_size_align(1, 1).unwrap()
+        ));
+        assert!(BlockCache::is_cacheable(
+            Layout::from_size_align(2048, 8).unwrap()
+        ));
+        assert!(!BlockCache::is_cacheable(
+            Layout::from_size_align(2049, 8).unwrap()
+        ));
+        assert!(!BlockCache::is_cacheable(
+            Layout::from_size_align(8, 16).unwrap()
+        ));
+    }
+
+    #[test]
+    fn test_empty_cache_misses() {
+        let mut cache = BlockCache::new();
+        let layout = Layout::from_size_align(64, 8).unwrap();
+        assert!(cache.allocate(layout).is_none());
+        assert_eq!(cache.cached_bytes(), 0);
+    }
+
+    #[test]
+    fn test_refill_and_allocate() {
+        with_test_source(16384, |source| {
+            let mut cache = BlockCache::new();
+            let layout = Layout::from_size_align(40, 8).unwrap();
+            assert!(cache.refill(layout, source));
+            assert_eq!(source.live, MAGAZINE_CAPACITY / 2);
+            assert_eq!(cache.cached_bytes(), MAGAZINE_CAPACITY / 2 * 64);
+
+            let mut ptrs = Vec::new();
+            while let Some(ptr) = cache.allocate(layout) {
+                assert_eq!(ptr.addr() % 64, 0);
+                ptrs.push(ptr);
+            }
+            assert_eq!(ptrs.len(), MAGAZINE_CAPACITY / 2);
+            assert_eq!(cache.cached_bytes(), 0);
+
+            // the blocks of the other sizes are not cached
+            let other = Layout::from_size_align(128, 8).unwrap();
+            assert!(cache.allocate(other).is_none());
+
+            for ptr in ptrs {
+                assert!(unsafe { cache.deallocate(ptr, layout) });
+            }
+            cache.flush(source);
+            assert_eq!(source.live, 0);
+        });
+    }
+
+    #[test]
+    fn test_deallocate_reuses_blocks() {
+        with_test_source(16384, |source| {
+            let mut cache = BlockCache::new();
+            let layout = Layout::from_size_align(256, 256).unwrap();
+            let ptr = source.allocate_block(layout).unwrap();
+            assert!(unsafe { cache.deallocate(ptr, layout) });
+            assert_eq!(cache.allocate(layout), Some(ptr));
+            unsafe {
+                source.deallocate_block(ptr, layout);
+            }
+            assert_eq!(source.live, 0);
+        });
+    }
+
+    #[test]
+    fn test_trim_full_magazine() {
+        with_test_source(65536, |source| {
+            let mut cache = BlockCache::new();
+            let layout = Layout::from_size_align(32, 8).unwrap();
+            let ptrs = iter::repeat_with(|| source.allocate_block(layout).unwrap())
+                .take(MAGAZINE_CAPACITY + 1)
+                .collect::<Vec<_>>();
+            let (last, rest) = ptrs.split_last().unwrap();
+            for &ptr in rest {
+                assert!(unsafe { cache.deallocate(ptr, layout) });
+            }
+            assert!(!unsafe { cache.deallocate(*last, layout) });
+
+            cache.trim(layout, source);
+            assert_eq!(cache.cached_bytes(), MAGAZINE_CAPACITY / 2 * 32);
+            assert!(unsafe { cache.deallocate(*last, layout) });
+
+            cache.flush(source);
+            assert_eq!(cache.cached_bytes(), 0);
+            assert_eq!(source.live, 0);
+        });
+    }
+
+    #[test]
+    fn test_refill_exhausted_source() {
+        with_test_source(4096, |source| {
+            let mut cache = BlockCache::new();
+            // the heap holds only one arena, which is split into two blocks
+            let layout = Layout::from_size_align(2048, 8).unwrap();
+            assert!(cache.refill(layout, source));
+            assert_eq!(source.live, 2);
+            assert_eq!(cache.cached_bytes(), 2 * 2048);
+            cache.flush(source);
+            assert_eq!(source.live, 0);
+        });
+    }
+
+    #[test]
+    fn test_uncacheable_layouts() {
+        with_test_source(16384, |source| {
+            let mut cache = BlockCache::new();
+            let layout = Layout::from_size_align(4096, 8).unwrap();
+            assert!(!cache.refill(layout, source));
+            let ptr = source.allocate_block(layout).unwrap();
+            assert!(!unsafe { cache.deallocate(ptr, layout) });
+            cache.trim(layout, source);
+            unsafe {
+                source.deallocate_block(ptr, layout);
+            }
+            assert_eq!(source.live, 0);
+        });
+    }
+}
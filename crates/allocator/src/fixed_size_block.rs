@@ -23,7 +23,7 @@ const ARENA_LAYOUT: Layout = match Layout::from_size_align(4096, 4096) {
 /// These sizes are chosen to cover common allocation patterns efficiently,
 /// ranging from 8 bytes to 2048 bytes. Allocations larger than 2048 bytes
 /// will use the fallback allocator.
-const BLOCK_SIZES: [usize; 9] = [8, 16, 32, 64, 128, 256, 512, 1024, 2048];
+pub(crate) const BLOCK_SIZES: [usize; 9] = [8, 16, 32, 64, 128, 256, 512, 1024, 2048];
 
 /// Determines the appropriate block size index for a given layout.
 ///
@@ -45,7 +45,7 @@ const BLOCK_SIZES: [usize; 9] = [8, 16, 32, 64, 128, 256, 512, 1024, 2048];
 /// # Panics
 ///
 /// Panics if the layout's size is smaller than its alignment requirement.
-fn list_index(layout: &Layout) -> Option<usize> {
+pub(crate) fn list_index(layout: &Layout) -> Option<usize> {
     assert!(layout.size() >= layout.align());
     let required_block_size = layout.size();
     BLOCK_SIZES.iter().position(|&s| s >= required_block_size)
@@ -70,12 +70,28 @@ fn list_index(layout: &Layout) -> Option<usize> {
 ///
 /// Panics if `index` is out of bounds for the `BLOCK_SIZES` array, or if
 /// the layout creation fails (which should never happen for valid block sizes).
-fn alloc_layout(index: usize) -> Layout {
+pub(crate) fn alloc_layout(index: usize) -> Layout {
     assert!(index < BLOCK_SIZES.len());
     let size = BLOCK_SIZES[index];
     Layout::from_size_align(size, size).unwrap()
 }
 
+/// Returns the layout of the block that [`FixedSizeBlockAllocator`] uses to
+/// satisfy `layout`.
+///
+/// Small layouts are rounded up to their fixed-size block, while the layouts
+/// served by the fallback allocator are returned as is. The block size is the
+/// memory actually consumed by an allocation.
+///
+/// # Panics
+///
+/// Panics if the layout's size is smaller than its alignment requirement and
+/// fits in a fixed-size block.
+#[must_use]
+pub fn block_layout(layout: Layout) -> Layout {
+    list_index(&layout).map_or(layout, alloc_layout)
+}
+
 /// A fixed-size block allocator that manages memory in predefined block sizes.
 ///
 /// This allocator maintains separate linked list allocators for each supported
@@ -388,6 +404,22 @@ mod tests {
         assert_eq!(list_index(&Layout::from_size_align(8192, 1).unwrap()), None);
     }
 
+    #[test]
+    fn test_block_layout() {
+        let layout = Layout::from_size_align(40, 8).unwrap();
+        assert_eq!(
+            block_layout(layout),
+            Layout::from_size_align(64, 64).unwrap()
+        );
+        let layout = Layout::from_size_align(2048, 1).unwrap();
+        assert_eq!(
+            block_layout(layout),
+            Layout::from_size_align(2048, 2048).unwrap()
+        );
+        let layout = Layout::from_size_align(4096, 8).unwrap();
+        assert_eq!(block_layout(layout), layout);
+    }
+
     #[test]
     fn test_basic_allocation() {
         with_test_allocator(8192, |allocator| unsafe {
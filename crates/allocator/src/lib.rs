@@ -31,6 +31,17 @@
 //! **Performance**: O(1) allocation and deallocation for supported block sizes,
 //! falls back to linked list allocation for larger sizes.
 //!
+//! ## [`BlockCache`](block_cache::BlockCache)
+//!
+//! A per-CPU front-end cache of fixed-size blocks, placed in front of a shared
+//! `FixedSizeBlockAllocator`. Best suited for:
+//!
+//! - SMP kernels where many CPUs allocate small objects concurrently
+//! - Reducing contention on the lock of the shared allocator
+//!
+//! **Performance**: O(1) allocation and deallocation without locking while the
+//! cache is neither empty nor full.
+//!
 //! # Usage Examples
 //!
 //! ## Basic `LinkedListAllocator` Usage
@@ -144,5 +155,6 @@
 #![no_std]
 #![cfg_attr(coverage_nightly, feature(coverage_attribute))]
 
+pub mod block_cache;
 pub mod fixed_size_block;
 pub mod linked_list;
@@ -0,0 +1,472 @@
+//! Parser for CPIO archives in the "newc" format.
+//!
+//! The "newc" format (also known as the SVR4 portable format) is the format
+//! used by Linux initramfs images. Each entry consists of a 110-byte ASCII
+//! header, a NUL-terminated file name and the file data, with the name and the
+//! data padded to 4-byte boundaries. The archive ends with an entry named
+//! `TRAILER!!!`.
+//!
+//! Both the `070701` (no checksum) and `070702` (with checksum) variants are
+//! accepted. Checksums are not verified.
+//!
+//! # Examples
+//!
+//! ```
+//! use cpio::{Archive, FileType};
+//!
+//! # fn entry(name: &str, mode: u32, data: &[u8]) -> Vec<u8> {
+//! #     let mut bytes = format!(
+//! #         "070701{:08x}{mode:08x}{:032x}{:08x}{:032x}{:08x}{:08x}",
+//! #         0, 0, data.len(), 0, name.len() + 1, 0
+//! #     )
+//! #     .into_bytes();
+//! #     bytes.extend(name.as_bytes());
+//! #     bytes.push(0);
+//! #     bytes.resize(bytes.len().next_multiple_of(4), 0);
+//! #     bytes.extend(data);
+//! #     bytes.resize(bytes.len().next_multiple_of(4), 0);
+//! #     bytes
+//! # }
+//! # let data = [
+//! #     entry(".", 0o040_755, b""),
+//! #     entry("hello.txt", 0o100_644, b"Hello, world!\n"),
+//! #     entry("TRAILER!!!", 0, b""),
+//! # ]
+//! # .concat();
+//! let archive = Archive::new(&data);
+//! for entry in archive.entries() {
+//!     let entry = entry.unwrap();
+//!     if entry.file_type() == FileType::Regular {
+//!         assert_eq!(entry.name(), "hello.txt");
+//!         assert_eq!(entry.data(), b"Hello, world!\n");
+//!     }
+//! }
+//! ```
+
+#![cfg_attr(coverage_nightly, feature(coverage_attribute))]
+#![no_std]
+
+use core::{iter::FusedIterator, str};
+
+use platform_cast::CastFrom as _;
+
+const MAGIC: &[u8] = b"070701";
+const MAGIC_CRC: &[u8] = b"070702";
+const HEADER_LEN: usize = 110;
+const TRAILER_NAME: &str = "TRAILER!!!";
+
+/// Errors that can occur while parsing an archive.
+#[derive(Debug, Clone, PartialEq, Eq, derive_more::Display, derive_more::Error)]
+#[non_exhaustive]
+pub enum ReadArchiveError {
+    #[display("truncated entry header: offset={offset}")]
+    TruncatedHeader { offset: usize },
+    #[display("invalid magic number: offset={offset}")]
+    InvalidMagic { offset: usize },
+    #[display("invalid header field: offset={offset}, field={field}")]
+    InvalidField { offset: usize, field: &'static str },
+    #[display("truncated entry name: offset={offset}")]
+    TruncatedName { offset: usize },
+    #[display("invalid entry name: offset={offset}")]
+    InvalidName { offset: usize },
+    #[display("truncated entry data: offset={offset}, size={size}")]
+    TruncatedData { offset: usize, size: usize },
+    #[display("missing trailer entry")]
+    MissingTrailer,
+}
+
+/// Type of an archive entry, decoded from the `S_IFMT` bits of the mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FileType {
+    Regular,
+    Directory,
+    Symlink,
+    CharDevice,
+    BlockDevice,
+    Fifo,
+    Socket,
+    Unknown(u32),
+}
+
+impl FileType {
+    const S_IFMT: u32 = 0o170_000;
+
+    #[must_use]
+    pub fn from_mode(mode: u32) -> Self {
+        match mode & Self::S_IFMT {
+            0o100_000 => Self::Regular,
+            0o040_000 => Self::Directory,
+            0o120_000 => Self::Symlink,
+            0o020_000 => Self::CharDevice,
+            0o060_000 => Self::BlockDevice,
+            0o010_000 => Self::Fifo,
+            0o140_000 => Self::Socket,
+            ty => Self::Unknown(ty),
+        }
+    }
+}
+
+/// A CPIO "newc" archive stored in memory.
+#[derive(Debug, Clone, Copy)]
+pub struct Archive<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Archive<'a> {
+    /// Creates an archive from its raw bytes.
+    ///
+    /// The contents are validated lazily while iterating over the entries.
+    #[must_use]
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    /// Returns an iterator over the entries of the archive.
+    ///
+    /// The trailer entry is not yielded. The iterator stops after the first
+    /// error.
+    #[must_use]
+    pub fn entries(&self) -> Entries<'a> {
+        Entries {
+            data: self.data,
+            offset: 0,
+            finished: false,
+        }
+    }
+}
+
+/// A file stored in an archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entry<'a> {
+    name: &'a str,
+    ino: u32,
+    mode: u32,
+    uid: u32,
+    gid: u32,
+    nlink: u32,
+    mtime: u32,
+    data: &'a [u8],
+}
+
+impl<'a> Entry<'a> {
+    /// Returns the path of the entry, as stored in the archive.
+    ///
+    /// Paths are usually relative to the archive root (e.g. `bin/sh`).
+    #[must_use]
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    #[must_use]
+    pub fn ino(&self) -> u32 {
+        self.ino
+    }
+
+    /// Returns the file type and permission bits.
+    #[must_use]
+    pub fn mode(&self) -> u32 {
+        self.mode
+    }
+
+    #[must_use]
+    pub fn file_type(&self) -> FileType {
+        FileType::from_mode(self.mode)
+    }
+
+    #[must_use]
+    pub fn uid(&self) -> u32 {
+        self.uid
+    }
+
+    #[must_use]
+    pub fn gid(&self) -> u32 {
+        self.gid
+    }
+
+    #[must_use]
+    pub fn nlink(&self) -> u32 {
+        self.nlink
+    }
+
+    #[must_use]
+    pub fn mtime(&self) -> u32 {
+        self.mtime
+    }
+
+    /// Returns the file contents.
+    ///
+    /// For symbolic links, this is the link target.
+    #[must_use]
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+}
+
+/// Iterator over the entries of an [`Archive`].
+#[derive(Debug, Clone)]
+pub struct Entries<'a> {
+    data: &'a [u8],
+    offset: usize,
+    finished: bool,
+}
+
+impl<'a> Entries<'a> {
+    fn read_entry(&mut self) -> Result<Option<Entry<'a>>, ReadArchiveError> {
+        let offset = self.offset;
+        let header = self
+            .data
+            .get(offset..)
+            .and_then(|rest| rest.get(..HEADER_LEN))
+            .ok_or(ReadArchiveError::TruncatedHeader { offset })?;
+        let (magic, fields) = header.split_at(MAGIC.len());
+        if magic != MAGIC && magic != MAGIC_CRC {
+            return Err(ReadArchiveError::InvalidMagic { offset });
+        }
+
+        let field = |index: usize, field: &'static str| {
+            let bytes = &fields[index * 8..][..8];
+            str::from_utf8(bytes)
+                .ok()
+                .and_then(|s| u32::from_str_radix(s, 16).ok())
+                .ok_or(ReadArchiveError::InvalidField { offset, field })
+        };
+        let ino = field(0, "ino")?;
+        let mode = field(1, "mode")?;
+        let uid = field(2, "uid")?;
+        let gid = field(3, "gid")?;
+        let nlink = field(4, "nlink")?;
+        let mtime = field(5, "mtime")?;
+        let file_size = usize::cast_from(field(6, "filesize")?);
+        let name_size = usize::cast_from(field(11, "namesize")?);
+
+        let name_start = offset + HEADER_LEN;
+        let name = name_start
+            .checked_add(name_size)
+            .and_then(|name_end| self.data.get(name_start..name_end))
+            .ok_or(ReadArchiveError::TruncatedName { offset })?;
+        let name = name
+            .strip_suffix(b"\0")
+            .and_then(|name| str::from_utf8(name).ok())
+            .ok_or(ReadArchiveError::InvalidName { offset })?;
+
+        let data_start = (name_start + name_size).next_multiple_of(4);
+        let data = data_start
+            .checked_add(file_size)
+            .and_then(|data_end| self.data.get(data_start..data_end))
+            .ok_or(ReadArchiveError::TruncatedData {
+                offset,
+                size: file_size,
+            })?;
+
+        if name == TRAILER_NAME {
+            return Ok(None);
+        }
+
+        self.offset = (data_start + file_size).next_multiple_of(4);
+        Ok(Some(Entry {
+            name,
+            ino,
+            mode,
+            uid,
+            gid,
+            nlink,
+            mtime,
+            data,
+        }))
+    }
+}
+
+impl<'a> Iterator for Entries<'a> {
+    type Item = Result<Entry<'a>, ReadArchiveError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        if self.offset >= self.data.len() {
+            self.finished = true;
+            return Some(Err(ReadArchiveError::MissingTrailer));
+        }
+        match self.read_entry() {
+            Ok(Some(entry)) => Some(Ok(entry)),
+            Ok(None) => {
+                self.finished = true;
+                None
+            }
+            Err(e) => {
+                self.finished = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+impl FusedIterator for Entries<'_> {}
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+#[cfg(test)]
+mod tests {
+    extern crate alloc;
+
+    use alloc::{format, vec::Vec};
+
+    use super::*;
+
+    fn push_entry(archive: &mut Vec<u8>, name: &str, mode: u32, data: &[u8]) {
+        let header = format!(
+            "070701{:08x}{mode:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:\
+             08x}",
+            archive.len(),
+            0,
+            0,
+            1,
+            0,
+            data.len(),
+            0,
+            0,
+            0,
+            0,
+            name.len() + 1,
+            0,
+        );
+        archive.extend_from_slice(header.as_bytes());
+        archive.extend_from_slice(name.as_bytes());
+        archive.push(0);
+        archive.resize(archive.len().next_multiple_of(4), 0);
+        archive.extend_from_slice(data);
+        archive.resize(archive.len().next_multiple_of(4), 0);
+    }
+
+    fn build(entries: &[(&str, u32, &[u8])]) -> Vec<u8> {
+        let mut archive = Vec::new();
+        for (name, mode, data) in entries {
+            push_entry(&mut archive, name, *mode, data);
+        }
+        push_entry(&mut archive, TRAILER_NAME, 0, b"");
+        archive
+    }
+
+    #[test]
+    fn test_entries() {
+        let data = build(&[
+            (".", 0o040_755, b""),
+            ("bin", 0o040_755, b""),
+            ("bin/init", 0o100_755, b"\x13\x00\x00\x00"),
+            ("hello.txt", 0o100_644, b"Hello"),
+            ("link", 0o120_777, b"hello.txt"),
+        ]);
+        let archive = Archive::new(&data);
+        let entries = archive.entries().collect::<Result<Vec<_>, _>>().unwrap();
+        let names = entries.iter().map(Entry::name).collect::<Vec<_>>();
+        assert_eq!(names, [".", "bin", "bin/init", "hello.txt", "link"]);
+
+        assert_eq!(entries[1].file_type(), FileType::Directory);
+        assert_eq!(entries[2].file_type(), FileType::Regular);
+        assert_eq!(entries[2].mode() & 0o777, 0o755);
+        assert_eq!(entries[2].data(), b"\x13\x00\x00\x00");
+        assert_eq!(entries[3].data(), b"Hello");
+        assert_eq!(entries[3].nlink(), 1);
+        assert_eq!(entries[4].file_type(), FileType::Symlink);
+        assert_eq!(entries[4].data(), b"hello.txt");
+    }
+
+    #[test]
+    fn test_empty_archive() {
+        let data = build(&[]);
+        assert_eq!(Archive::new(&data).entries().count(), 0);
+    }
+
+    #[test]
+    fn test_missing_trailer() {
+        let mut data = Vec::new();
+        push_entry(&mut data, "a", 0o100_644, b"a");
+        let mut entries = Archive::new(&data).entries();
+        assert_eq!(entries.next().unwrap().unwrap().name(), "a");
+        assert_eq!(
+            entries.next().unwrap(),
+            Err(ReadArchiveError::MissingTrailer)
+        );
+        assert!(entries.next().is_none());
+    }
+
+    #[test]
+    fn test_invalid_magic() {
+        let mut data = build(&[("a", 0o100_644, b"a")]);
+        data[5] = b'7';
+        let mut entries = Archive::new(&data).entries();
+        assert_eq!(
+            entries.next().unwrap(),
+            Err(ReadArchiveError::InvalidMagic { offset: 0 })
+        );
+        assert!(entries.next().is_none());
+    }
+
+    #[test]
+    fn test_invalid_field() {
+        let mut data = build(&[("a", 0o100_644, b"a")]);
+        data[6] = b'x';
+        assert_eq!(
+            Archive::new(&data).entries().next().unwrap(),
+            Err(ReadArchiveError::InvalidField {
+                offset: 0,
+                field: "ino"
+            })
+        );
+    }
+
+    #[test]
+    fn test_truncated() {
+        let data = build(&[("hello.txt", 0o100_644, b"Hello, world!")]);
+
+        let entries = Archive::new(&data[..50]).entries().collect::<Vec<_>>();
+        assert_eq!(
+            entries,
+            [Err(ReadArchiveError::TruncatedHeader { offset: 0 })]
+        );
+
+        let entries = Archive::new(&data[..HEADER_LEN + 4])
+            .entries()
+            .collect::<Vec<_>>();
+        assert_eq!(
+            entries,
+            [Err(ReadArchiveError::TruncatedName { offset: 0 })]
+        );
+
+        let entries = Archive::new(&data[..HEADER_LEN + 12 + 4])
+            .entries()
+            .collect::<Vec<_>>();
+        assert_eq!(
+            entries,
+            [Err(ReadArchiveError::TruncatedData {
+                offset: 0,
+                size: 13
+            })]
+        );
+    }
+
+    #[test]
+    fn test_invalid_name() {
+        let mut data = build(&[("a", 0o100_644, b"")]);
+        // replace the NUL terminator
+        data[HEADER_LEN + 1] = b'b';
+        assert_eq!(
+            Archive::new(&data).entries().next().unwrap(),
+            Err(ReadArchiveError::InvalidName { offset: 0 })
+        );
+    }
+
+    #[test]
+    fn test_file_type() {
+        assert_eq!(FileType::from_mode(0o020_600), FileType::CharDevice);
+        assert_eq!(FileType::from_mode(0o060_600), FileType::BlockDevice);
+        assert_eq!(FileType::from_mode(0o010_600), FileType::Fifo);
+        assert_eq!(FileType::from_mode(0o140_600), FileType::Socket);
+        assert_eq!(FileType::from_mode(0o030_600), FileType::Unknown(0o030_000));
+    }
+
+    #[test]
+    fn test_error_display() {
+        let e = ReadArchiveError::TruncatedData { offset: 4, size: 8 };
+        assert_eq!(format!("{e}"), "truncated entry data: offset=4, size=8");
+    }
+}
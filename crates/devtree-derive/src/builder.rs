@@ -1,11 +1,14 @@
-use proc_macro2::TokenStream;
-use quote::quote;
+use proc_macro2::{TokenStream, TokenTree};
+use quote::{ToTokens as _, quote};
 use syn::parse_quote;
 
 use crate::{
     Fallback, FieldSpec, ResolvedName, SymbolGenerator,
-    meta::{InputField, PropertyDefault},
-    sgen,
+    meta::{
+        ExtraChildrenSpec, InputField, ParseWithStr, PropertyDefault, PropertySpec,
+        RepeatedChildrenSpec,
+    },
+    schema, sgen,
 };
 
 impl quote::ToTokens for FieldIdent {
@@ -58,7 +61,10 @@ impl Builder {
         self.gen_var_defs(&var_de, &mut body)?;
         self.gen_with_items(&var_de, &mut body)?;
         self.gen_return_value(&var_de, &mut body)?;
-        let ts = self.gen_impl(lt_blob, &lt_de, &var_de, &body);
+        let mut ts = self.gen_impl(lt_blob, &lt_de, &var_de, &body);
+        if cfg!(feature = "schema") {
+            ts.extend(self.gen_schema_impl()?);
+        }
         Ok(ts)
     }
 
@@ -152,18 +158,25 @@ impl Builder {
         let gp_d = sgen::gen_generic_param_d();
         let ident = &self.ident;
 
-        let (_impl_generics, ty_generics, where_clause) = self.generics.split_for_impl();
+        let (_impl_generics, ty_generics, _where_clause) = self.generics.split_for_impl();
+        let mut generics = self.generics.clone();
         let found = self.generics.lifetimes().any(|lt| lt.lifetime == *lt_blob);
-        let new_generics = (!found).then(|| {
-            let mut generics = self.generics.clone();
+        if !found {
             generics
                 .params
                 .insert(0, syn::LifetimeParam::new(lt_blob.clone()).into());
-            generics
-        });
-        let (impl_generics, _ty_generics, _where_clause) = new_generics
-            .as_ref()
-            .map_or_else(|| self.generics.split_for_impl(), |g| g.split_for_impl());
+        }
+        let type_params = self
+            .generics
+            .type_params()
+            .map(|param| &param.ident)
+            .collect::<Vec<_>>();
+        let predicates = self
+            .fields
+            .iter()
+            .filter_map(|field| field.bound(&self.sgen, &type_params));
+        generics.make_where_clause().predicates.extend(predicates);
+        let (impl_generics, _ty_generics, where_clause) = generics.split_for_impl();
 
         quote! {
             #[automatically_derived]
@@ -177,6 +190,78 @@ impl Builder {
             }
         }
     }
+
+    fn gen_schema_impl(&self) -> Result<TokenStream, darling::Error> {
+        let private = self.sgen.private();
+        let ident = &self.ident;
+        let type_name = ident.to_string();
+        let (impl_generics, ty_generics, where_clause) = self.generics.split_for_impl();
+
+        let mut properties = vec![];
+        let mut children = vec![];
+        let mut node_types = vec![];
+        let mut extra_properties = false;
+        let mut extra_children = false;
+        for field in &self.fields {
+            let field_type = schema::type_name(&field.ty);
+            match &field.spec {
+                FieldSpec::Node(_) => node_types.push(field_type),
+                FieldSpec::RawNode(_) => {}
+                FieldSpec::Property(spec) => {
+                    let name = spec.name.resolve(&field.ident)?.to_lit_str();
+                    let required = spec.default == PropertyDefault::None;
+                    let inherited = spec.fallback == Fallback::Parent;
+                    properties.push(quote! {
+                        #private::PropertyDesc {
+                            name: #name,
+                            type_name: #field_type,
+                            required: #required,
+                            inherited: #inherited,
+                        }
+                    });
+                }
+                FieldSpec::ExtraProperties(_) => extra_properties = true,
+                FieldSpec::Child(spec) => {
+                    let name = spec.name.resolve(&field.ident)?.to_lit_str();
+                    let required = !spec.default;
+                    children.push(quote! {
+                        #private::ChildDesc {
+                            name: #name,
+                            type_name: #field_type,
+                            required: #required,
+                            repeated: false,
+                        }
+                    });
+                }
+                FieldSpec::RepeatedChildren(spec) => {
+                    let name = spec.name.resolve(&field.ident)?.to_lit_str();
+                    children.push(quote! {
+                        #private::ChildDesc {
+                            name: #name,
+                            type_name: #field_type,
+                            required: false,
+                            repeated: true,
+                        }
+                    });
+                }
+                FieldSpec::ExtraChildren(_) => extra_children = true,
+            }
+        }
+
+        Ok(quote! {
+            #[automatically_derived]
+            impl #impl_generics #private::NodeSchema for #ident #ty_generics #where_clause {
+                const NODE_SCHEMA: #private::NodeDesc = #private::NodeDesc {
+                    type_name: #type_name,
+                    properties: &[#( #properties ),*],
+                    children: &[#( #children ),*],
+                    node_types: &[#( #node_types ),*],
+                    extra_properties: #extra_properties,
+                    extra_children: #extra_children,
+                };
+            }
+        })
+    }
 }
 
 #[derive(Debug, Default)]
@@ -252,6 +337,69 @@ impl Field {
         }
     }
 
+    /// Returns the bound of the field type required by the generated code, if
+    /// the type depends on the type parameters of the struct.
+    ///
+    /// The bounds of the other fields are left to the compiler, as the bounds
+    /// on the concrete types are checked as they are written.
+    fn bound(
+        &self,
+        sgen: &SymbolGenerator,
+        type_params: &[&syn::Ident],
+    ) -> Option<syn::WherePredicate> {
+        if !mentions_type_params(self.ty.to_token_stream(), type_params) {
+            return None;
+        }
+        let private = sgen.private();
+        let lt_blob = sgen.lt_blob();
+        let ty = &self.ty;
+        let mut bounds: Vec<syn::TypeParamBound> = vec![];
+        match &self.spec {
+            FieldSpec::Node(spec) => {
+                if spec.deserialize_with.is_none() {
+                    bounds.push(parse_quote! { #private::DeserializeNode<#lt_blob> });
+                }
+            }
+            FieldSpec::RawNode(_) => {}
+            FieldSpec::Property(spec) => {
+                match (&spec.deserialize_with, &spec.parse_with_str) {
+                    (None, None) => {
+                        bounds.push(parse_quote! { #private::DeserializeProperty<#lt_blob> });
+                    }
+                    (None, Some(ParseWithStr::FromStr)) => {
+                        bounds.push(parse_quote! { #private::FromStr });
+                    }
+                    (Some(_), _) | (None, Some(ParseWithStr::Expr(_))) => {}
+                }
+                if spec.default == PropertyDefault::DefaultTrait {
+                    bounds.push(parse_quote! { #private::Default });
+                }
+            }
+            FieldSpec::ExtraProperties(spec) => {
+                bounds.push(parse_quote! { #private::Default });
+                if spec.insert_with.is_none() {
+                    bounds.push(parse_quote! { #private::PropertyCollection<#lt_blob> });
+                }
+            }
+            FieldSpec::Child(spec) => {
+                if spec.deserialize_with.is_none() {
+                    bounds.push(parse_quote! { #private::DeserializeNode<#lt_blob> });
+                }
+                if spec.default {
+                    bounds.push(parse_quote! { #private::Default });
+                }
+            }
+            FieldSpec::RepeatedChildren(RepeatedChildrenSpec { insert_with, .. })
+            | FieldSpec::ExtraChildren(ExtraChildrenSpec { insert_with, .. }) => {
+                bounds.push(parse_quote! { #private::Default });
+                if insert_with.is_none() {
+                    bounds.push(parse_quote! { #private::NodeCollection<#lt_blob> });
+                }
+            }
+        }
+        (!bounds.is_empty()).then(|| parse_quote! { #ty: #( #bounds )+* })
+    }
+
     fn var_def(
         &self,
         sgen: &SymbolGenerator,
@@ -260,7 +408,7 @@ impl Field {
         let private = sgen.private();
         let ty = &self.ty;
         let value: syn::Expr = match &self.spec {
-            FieldSpec::Node(_) => return Ok(None),
+            FieldSpec::Node(_) | FieldSpec::RawNode(_) => return Ok(None),
             FieldSpec::Property(spec) => {
                 let prop_name = spec.name.resolve(&self.ident)?.to_lit_str();
                 parse_quote! {  #private::PropertyCell::<#ty>::new(#de, #prop_name)? }
@@ -287,11 +435,9 @@ impl Field {
         let var_name = &self.var_name;
         let ty = &self.ty;
         match &self.spec {
-            FieldSpec::Node(_) => {}
+            FieldSpec::Node(_) | FieldSpec::RawNode(_) => {}
             FieldSpec::Property(spec) => {
-                let deserialize_with = spec.deserialize_with.clone().unwrap_or_else(|| {
-                    parse_quote! { <#ty as #private::DeserializeProperty>::deserialize_property }
-                });
+                let deserialize_with = self.property_deserializer(sgen, spec)?;
                 let handler = parse_quote! {
                     #private::PropertyCell::set(&mut #var_name, (#deserialize_with)(&mut #var_sub_de)?)?
                 };
@@ -340,6 +486,34 @@ impl Field {
         Ok(())
     }
 
+    /// Returns the function deserializing the property of the field.
+    fn property_deserializer(
+        &self,
+        sgen: &SymbolGenerator,
+        spec: &PropertySpec,
+    ) -> Result<syn::Expr, darling::Error> {
+        let private = sgen.private();
+        let ty = &self.ty;
+        let deserializer = match (&spec.deserialize_with, &spec.parse_with_str) {
+            (Some(_), Some(_)) => {
+                return Err(darling::Error::custom(
+                    "`deserialize_with` and `parse_with_str` cannot be used together",
+                ));
+            }
+            (Some(deserialize_with), None) => deserialize_with.clone(),
+            (None, Some(ParseWithStr::FromStr)) => {
+                parse_quote! { #private::deserialize_property_from_str::<_, #ty> }
+            }
+            (None, Some(ParseWithStr::Expr(parse))) => {
+                parse_quote! { |de| #private::deserialize_property_with_str(de, #parse) }
+            }
+            (None, None) => {
+                parse_quote! { <#ty as #private::DeserializeProperty>::deserialize_property }
+            }
+        };
+        Ok(deserializer)
+    }
+
     fn field_value(
         &self,
         sgen: &SymbolGenerator,
@@ -364,6 +538,11 @@ impl Field {
                     }
                 }
             }
+            FieldSpec::RawNode(_) => {
+                parse_quote! {
+                    #private::RawNode::from_tree_cursor(#private::NodeDeserializer::tree_cursor(#var_de))?
+                }
+            }
             FieldSpec::Property(spec) => {
                 let var_name = &self.var_name;
                 let mut field_value = match &spec.default {
@@ -380,11 +559,7 @@ impl Field {
                 match &spec.fallback {
                     Fallback::None => {}
                     Fallback::Parent => {
-                        let deserialize_with = spec.deserialize_with.clone().unwrap_or_else(|| {
-                            parse_quote! {
-                                <#ty as #private::DeserializeProperty>::deserialize_property
-                            }
-                        });
+                        let deserialize_with = self.property_deserializer(sgen, spec)?;
                         let prop_name = spec.name.resolve(&self.ident)?;
                         let prop_name = prop_name.to_lit_byte_str();
                         let var_cursor = sgen::gen_var("cursor");
@@ -443,3 +618,11 @@ impl Field {
         Ok(field_value)
     }
 }
+
+fn mentions_type_params(tokens: TokenStream, type_params: &[&syn::Ident]) -> bool {
+    tokens.into_iter().any(|token| match token {
+        TokenTree::Ident(ident) => type_params.contains(&&ident),
+        TokenTree::Group(group) => mentions_type_params(group.stream(), type_params),
+        TokenTree::Punct(_) | TokenTree::Literal(_) => false,
+    })
+}
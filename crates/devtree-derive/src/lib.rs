@@ -10,6 +10,7 @@ use self::{
 
 mod builder;
 mod meta;
+mod schema;
 mod sgen;
 
 /// Derive macro `#[derive(DeserializeNode)]` for deserializing devicetree
@@ -58,6 +59,30 @@ mod sgen;
 ///   * Multiple lifetimes are not supported. Each struct can have only one
 ///     devicetree blob lifetime.
 ///
+/// # Generic Type Parameters
+///
+/// The struct may have type parameters, so that a wrapper can be reused for
+/// the nodes or the properties of different types. The impl is bounded by the
+/// traits the fields depending on the type parameters need, e.g.
+/// `T: DeserializeNode<'blob>` for a `#[devtree(node)]` field of type `T`, and
+/// `Option<T>: DeserializeProperty<'blob> + Default` for a
+/// `#[devtree(property(default))]` field of type `Option<T>`, or `T: FromStr`
+/// for a `#[devtree(property(parse_with_str))]` field of type `T`. No bound is
+/// added for the traits replaced by `deserialize_with`, `parse_with_str = expr`
+/// or `insert_with`.
+///
+/// ```rust
+/// use devtree::{DeserializeNode, model::property::Status};
+///
+/// #[derive(DeserializeNode)]
+/// pub struct WithStatus<T> {
+///     #[devtree(node)]
+///     pub node: T,
+///     #[devtree(property(default))]
+///     pub status: Status,
+/// }
+/// ```
+///
 /// # Field-level Attributes
 ///
 /// Attributes that apply to individual fields within the struct.
@@ -80,6 +105,13 @@ mod sgen;
 ///
 ///   - `deserialize_with = expr` — Custom deserializer function
 ///
+/// * `#[devtree(raw_node)]`
+///
+///   Keep a handle to the current node in the field, so that the parts the
+///   struct does not model can be deserialized later without searching the
+///   tree again. The field type must be [`RawNode`]. A cursor is moved back to
+///   the node with [`StackBasedTreeCursor::seek_raw_node`].
+///
 /// ## Property fields
 ///
 /// Attributes used to deserialize properties from a devicetree node.
@@ -88,6 +120,9 @@ mod sgen;
 ///
 ///   Deserialize the property with the same name as the field. The field type
 ///   must implement [`DeserializeProperty`].
+///   Properties of a known shape can be deserialized into arrays such as
+///   `[u32; N]` and `[u64; N]`, or tuples such as `(u64, u64)`. The property
+///   length must match the total size of the cells exactly.
 ///
 ///   **Forms:**
 ///
@@ -107,6 +142,14 @@ mod sgen;
 ///     [`DeserializeProperty::deserialize_property`]. The `expr` must be
 ///     callable as `fn(&mut PropertyContext<'_, 'blob>) -> Result<T,
 ///     DeserializeError>` where `T` is field type.
+///   * `parse_with_str` — Deserialize the property as a string, and parse it
+///     with [`FromStr`]. The field type must implement [`FromStr`].
+///   * `parse_with_str = expr` — Deserialize the property as a string, and
+///     parse it with `expr`. The `expr` must be callable as `fn(&'blob str) ->
+///     Result<T, E>` where `T` is field type. Cannot be combined with
+///     `deserialize_with`.
+///
+///   The errors of `parse_with_str` include the string that failed to parse.
 ///
 ///   **Combination behavior:**
 ///
@@ -176,9 +219,18 @@ mod sgen;
 ///     NodeContext<'_, 'blob>) -> Result<T, DeserializeError>` where `T` is
 ///     field type.
 ///
+/// # Schema
+///
+/// With the `schema` feature of `devtree`, the macro also implements
+/// [`NodeSchema`] for the struct. Its `NODE_SCHEMA` lists the properties and
+/// the child nodes of the fields with their names, their types as written in
+/// the struct, and whether they are required.
+///
 /// [`NodeFullName`]: ::devtree::model::node::NodeFullName
 /// [`NodeName`]: ::devtree::model::node::NodeName
 /// [`NodeUnitAddress`]: ::devtree::model::node::NodeUnitAddress
+/// [`RawNode`]: ::devtree::tree_cursor::RawNode
+/// [`StackBasedTreeCursor::seek_raw_node`]: ::devtree::tree_cursor::types::StackBasedTreeCursor::seek_raw_node
 /// [`DeserializeProperty`]: ::devtree::de::DeserializeProperty
 /// [`DeserializeProperty::deserialize_property`]: ::devtree::de::DeserializeProperty::deserialize_property
 /// [`PropertyCollection`]: ::devtree::de::PropertyCollection
@@ -187,6 +239,8 @@ mod sgen;
 /// [`DeserializeNode::deserialize_node`]: ::devtree::de::DeserializeNode::deserialize_node
 /// [`NodeCollection`]: ::devtree::de::NodeCollection
 /// [`NodeCollection::insert_node`]: ::devtree::de::NodeCollection::insert_node
+/// [`NodeSchema`]: ::devtree::schema::NodeSchema
+/// [`FromStr`]: ::core::str::FromStr
 ///
 /// # Example
 ///
@@ -57,6 +57,7 @@ pub struct InputField {
 #[derive(Debug, FromMeta, Clone, PartialEq, Eq)]
 pub enum FieldSpec {
     Node(NodeSpec),
+    RawNode(RawNodeSpec),
     Property(PropertySpec),
     ExtraProperties(ExtraPropertiesSpec),
     Child(ChildSpec),
@@ -73,6 +74,10 @@ pub struct NodeSpec {
     pub deserialize_with: Option<syn::Expr>,
 }
 
+#[derive(Debug, Default, FromMeta, Clone, PartialEq, Eq)]
+#[darling(from_word = || Ok(Default::default()))]
+pub struct RawNodeSpec {}
+
 #[derive(Debug, Default, FromMeta, Clone, PartialEq, Eq)]
 #[darling(from_word = || Ok(Default::default()), from_expr = Self::from_expr)]
 pub struct PropertySpec {
@@ -86,6 +91,8 @@ pub struct PropertySpec {
     pub old_deserialize_with: Option<syn::Expr>,
     #[darling(default)]
     pub deserialize_with: Option<syn::Expr>,
+    #[darling(default)]
+    pub parse_with_str: Option<ParseWithStr>,
 }
 
 impl PropertySpec {
@@ -184,6 +191,22 @@ impl FromMeta for PropertyDefault {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseWithStr {
+    FromStr,
+    Expr(Box<syn::Expr>),
+}
+
+impl FromMeta for ParseWithStr {
+    fn from_expr(expr: &syn::Expr) -> darling::Result<Self> {
+        Ok(Self::Expr(Box::new(expr.clone())))
+    }
+
+    fn from_word() -> darling::Result<Self> {
+        Ok(Self::FromStr)
+    }
+}
+
 #[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub enum Name {
     #[default]
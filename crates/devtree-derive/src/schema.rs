@@ -0,0 +1,79 @@
+use proc_macro2::{Delimiter, TokenStream, TokenTree};
+use quote::ToTokens as _;
+
+/// Renders the type as written in the source, with the spaces only where
+/// rustfmt puts them, as in `Option<&'blob [u8]>`.
+pub fn type_name(ty: &syn::Type) -> String {
+    let mut s = String::new();
+    write_tokens(&mut s, ty.to_token_stream());
+    s
+}
+
+fn write_tokens(s: &mut String, tokens: TokenStream) {
+    // the last token is an identifier or a literal, which needs a space before
+    // the next word
+    let mut after_word = false;
+    // the last token is a lifetime or a keyword, which also needs a space
+    // before a group, as in `&'blob [u8]` and `&mut (u32, u32)`
+    let mut after_prefix = false;
+    let mut after_apostrophe = false;
+    for token in tokens {
+        match token {
+            TokenTree::Ident(ident) => {
+                if after_word {
+                    s.push(' ');
+                }
+                let ident = ident.to_string();
+                s.push_str(&ident);
+                after_prefix =
+                    after_apostrophe || matches!(&*ident, "mut" | "dyn" | "impl" | "const");
+                after_word = true;
+                after_apostrophe = false;
+            }
+            TokenTree::Literal(lit) => {
+                if after_word {
+                    s.push(' ');
+                }
+                s.push_str(&lit.to_string());
+                after_word = true;
+                after_prefix = false;
+                after_apostrophe = false;
+            }
+            TokenTree::Punct(punct) => {
+                let ch = punct.as_char();
+                if matches!(ch, '+' | '=') {
+                    s.push(' ');
+                }
+                s.push(ch);
+                if matches!(ch, ',' | ';' | '+' | '=') {
+                    s.push(' ');
+                }
+                after_word = false;
+                after_prefix = false;
+                after_apostrophe = ch == '\'';
+            }
+            TokenTree::Group(group) => {
+                let (open, close) = match group.delimiter() {
+                    Delimiter::Parenthesis => ("(", ")"),
+                    Delimiter::Brace => ("{", "}"),
+                    Delimiter::Bracket => ("[", "]"),
+                    Delimiter::None => ("", ""),
+                };
+                let spaced = match group.delimiter() {
+                    Delimiter::Parenthesis => after_prefix,
+                    Delimiter::Brace | Delimiter::Bracket => after_word,
+                    Delimiter::None => false,
+                };
+                if spaced {
+                    s.push(' ');
+                }
+                s.push_str(open);
+                write_tokens(s, group.stream());
+                s.push_str(close);
+                after_word = group.delimiter() == Delimiter::None;
+                after_prefix = false;
+                after_apostrophe = false;
+            }
+        }
+    }
+}
@@ -0,0 +1,118 @@
+//! Benchmarks over a synthetic devicetree with about 10,000 nodes.
+//!
+//! Run with `cargo bench -p devtree`.
+
+#![feature(test)]
+#![cfg(test)]
+
+extern crate test;
+
+use devtree::{
+    Devicetree,
+    blob::DEVICETREE_ALIGNMENT,
+    testing::{self, BlockBuilder},
+    tree_cursor::TreeCursor as _,
+    util::AlignedByteBuffer,
+};
+use test::{Bencher, black_box};
+
+const BUSES: u32 = 100;
+const DEVICES_PER_BUS: u32 = 100;
+
+/// Builds a tree with `BUSES` bus nodes under the root, each with
+/// `DEVICES_PER_BUS` device nodes.
+fn big_tree() -> AlignedByteBuffer<DEVICETREE_ALIGNMENT> {
+    let mut block = BlockBuilder::new();
+    block
+        .begin_node(b"")
+        .prop(b"#address-cells", &1_u32.to_be_bytes())
+        .prop(b"#size-cells", &1_u32.to_be_bytes())
+        .prop(b"compatible", b"vendor,board\0");
+    for bus in 0..BUSES {
+        block
+            .begin_node(format!("bus@{bus:x}").as_bytes())
+            .prop(b"compatible", b"simple-bus\0")
+            .prop(b"#address-cells", &1_u32.to_be_bytes())
+            .prop(b"#size-cells", &1_u32.to_be_bytes());
+        for dev in 0..DEVICES_PER_BUS {
+            let mut reg = [0; 8];
+            reg[..4].copy_from_slice(&(dev * 0x1000).to_be_bytes());
+            reg[4..].copy_from_slice(&0x1000_u32.to_be_bytes());
+            block
+                .begin_node(format!("dev@{:x}", dev * 0x1000).as_bytes())
+                .prop(b"compatible", b"vendor,dev\0")
+                .prop(b"reg", &reg)
+                .prop(b"status", b"okay\0")
+                .end_node();
+        }
+        block.end_node();
+    }
+    block.end_node().end();
+    testing::blob_from_block(&block)
+}
+
+#[bench]
+fn read_descendant_nodes(b: &mut Bencher) {
+    let blob = big_tree();
+    let dt = Devicetree::from_bytes(&blob).unwrap();
+    b.iter(|| {
+        let mut cursor = dt.tree_cursor().unwrap();
+        let count = cursor.read_descendant_nodes().count();
+        assert_eq!(
+            count,
+            usize::try_from(BUSES * (DEVICES_PER_BUS + 1)).unwrap()
+        );
+    });
+}
+
+#[bench]
+fn read_descendant_nodes_by_glob_last(b: &mut Bencher) {
+    let blob = big_tree();
+    let dt = Devicetree::from_bytes(&blob).unwrap();
+    b.iter(|| {
+        let mut cursor = dt.tree_cursor().unwrap();
+        let glob = black_box("/bus@63/dev@63000");
+        let count = cursor.read_descendant_nodes_by_glob(glob).count();
+        assert_eq!(count, 1);
+    });
+}
+
+#[bench]
+fn read_descendant_nodes_by_glob_wildcard(b: &mut Bencher) {
+    let blob = big_tree();
+    let dt = Devicetree::from_bytes(&blob).unwrap();
+    b.iter(|| {
+        let mut cursor = dt.tree_cursor().unwrap();
+        let glob = black_box("/*/dev@2a000");
+        let count = cursor.read_descendant_nodes_by_glob(glob).count();
+        assert_eq!(count, usize::try_from(BUSES).unwrap());
+    });
+}
+
+#[bench]
+fn seek_parent_next_over_buses(b: &mut Bencher) {
+    let blob = big_tree();
+    let dt = Devicetree::from_bytes(&blob).unwrap();
+    b.iter(|| {
+        let mut cursor = dt.tree_cursor().unwrap();
+        while let Some(item) = cursor.read_item_descend().unwrap() {
+            if item.is_node() {
+                cursor.seek_parent_next().unwrap();
+            }
+        }
+    });
+}
+
+#[bench]
+fn skip_subtree_over_buses(b: &mut Bencher) {
+    let blob = big_tree();
+    let dt = Devicetree::from_bytes(&blob).unwrap();
+    b.iter(|| {
+        let mut cursor = dt.tree_cursor().unwrap();
+        while let Some(item) = cursor.read_item_descend().unwrap() {
+            if item.is_node() {
+                cursor.skip_subtree().unwrap();
+            }
+        }
+    });
+}
@@ -1,13 +1,17 @@
-pub use core::{default::Default, marker::Sized, option::Option, result::Result};
+pub use core::{default::Default, marker::Sized, option::Option, result::Result, str::FromStr};
 
+#[cfg(feature = "schema")]
+pub use crate::schema::{ChildDesc, NodeDesc, NodeSchema, PropertyDesc};
 pub use crate::{
     de::{
         DeserializeNode, DeserializeProperty, ItemDeserializer, NodeCollection, NodeDeserializer,
         PropertyCollection, PropertyDeserializer,
         error::DeserializeError,
-        util::{NodeCell, PropertyCell},
+        util::{
+            NodeCell, PropertyCell, deserialize_property_from_str, deserialize_property_with_str,
+        },
     },
-    tree_cursor::{TreeCursor, TreeNodeRef},
+    tree_cursor::{RawNode, TreeCursor, TreeNodeRef},
 };
 
 pub fn node_de_name<'de, 'blob, D>(de: &D) -> &'blob [u8]
@@ -1,10 +1,17 @@
 extern crate alloc;
 
-use alloc::borrow::ToOwned;
+use alloc::borrow::{Cow, ToOwned};
 use core::{borrow::Borrow, fmt, ops::Deref};
 
 use super::{DEVICETREE_ALIGNMENT, Devicetree};
-use crate::util::AlignedByteBuffer;
+use crate::{
+    blob::error::ReadDevicetreeError,
+    de::error::DeserializeError,
+    model::node::NodePath,
+    tree_cursor::TreeCursor as _,
+    types::{ByteStr, ByteString},
+    util::AlignedByteBuffer,
+};
 
 #[derive(Clone)]
 pub struct OwnedDevicetree {
@@ -14,6 +21,56 @@ pub struct OwnedDevicetree {
 unsafe impl Send for OwnedDevicetree {}
 unsafe impl Sync for OwnedDevicetree {}
 
+impl Devicetree {
+    /// Reads a devicetree blob from bytes of any alignment.
+    ///
+    /// The bytes are borrowed if they are aligned to [`DEVICETREE_ALIGNMENT`],
+    /// and copied into an aligned buffer otherwise.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the blob is malformed.
+    pub fn from_bytes_or_copy(bytes: &[u8]) -> Result<Cow<'_, Self>, ReadDevicetreeError> {
+        if bytes.as_ptr().addr().is_multiple_of(DEVICETREE_ALIGNMENT) {
+            return Self::from_bytes(bytes).map(Cow::Borrowed);
+        }
+        OwnedDevicetree::from_bytes(bytes).map(Cow::Owned)
+    }
+
+    /// Returns the path of the node with the label.
+    ///
+    /// The labels are read from the `__symbols__` node, which is present when
+    /// the blob is compiled with `dtc -@`.
+    ///
+    /// Returns `None` if the blob has no `__symbols__` node or the label is
+    /// not defined.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the blob is malformed.
+    pub fn resolve_label(&self, label: &str) -> Result<Option<NodePath>, DeserializeError> {
+        let mut tree_cursor = self.tree_cursor()?;
+        let Some(mut symbols) = tree_cursor.read_node_by_path("/__symbols__")? else {
+            return Ok(None);
+        };
+        let path = symbols.read_property::<&ByteStr>(label)?;
+        Ok(path.map(|path| NodePath::new(ByteString::from(path))))
+    }
+}
+
+impl OwnedDevicetree {
+    /// Copies a devicetree blob of any alignment into an aligned buffer.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the blob is malformed.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ReadDevicetreeError> {
+        let buffer = AlignedByteBuffer::from_slice(bytes);
+        Devicetree::from_bytes(&buffer)?;
+        Ok(Self { buffer })
+    }
+}
+
 impl ToOwned for Devicetree {
     type Owned = OwnedDevicetree;
 
@@ -64,10 +121,49 @@ mod tests {
     use alloc::format;
 
     use super::*;
+    use crate::{
+        blob::error::ReadDevicetreeErrorKind,
+        testing::{self, BlockBuilder},
+    };
 
     #[repr(align(8))]
     struct Bytes<const N: usize>([u8; N]);
 
+    #[test]
+    fn test_from_bytes_or_copy_aligned() {
+        let blob = Bytes(*include_bytes!("../../../examples/assets/qemu-virt.dtb"));
+        let dt = Devicetree::from_bytes_or_copy(&blob.0).unwrap();
+        assert!(matches!(dt, Cow::Borrowed(_)));
+        assert_eq!(dt.as_bytes().as_ptr(), blob.0.as_ptr());
+    }
+
+    #[test]
+    fn test_from_bytes_or_copy_unaligned() {
+        let blob = include_bytes!("../../../examples/assets/qemu-virt.dtb");
+        let mut bytes = Bytes([0; 8200]);
+        bytes.0[1..=blob.len()].copy_from_slice(blob);
+        let unaligned = &bytes.0[1..=blob.len()];
+
+        let err = Devicetree::from_bytes(unaligned).unwrap_err();
+        assert!(
+            matches!(err.kind(), ReadDevicetreeErrorKind::UnalignedPointer { .. }),
+            "err: {err:?}",
+        );
+
+        let dt = Devicetree::from_bytes_or_copy(unaligned).unwrap();
+        assert!(matches!(dt, Cow::Owned(_)));
+        assert_eq!(dt.as_bytes(), blob);
+    }
+
+    #[test]
+    fn test_owned_from_bytes_invalid() {
+        let err = OwnedDevicetree::from_bytes(&[0xff; 64]).unwrap_err();
+        assert!(
+            matches!(err.kind(), ReadDevicetreeErrorKind::InvalidMagic { .. }),
+            "err: {err:?}",
+        );
+    }
+
     #[test]
     fn test_to_owned() {
         let blob = Bytes(*include_bytes!("../../../examples/assets/qemu-virt.dtb"));
@@ -117,4 +213,36 @@ mod tests {
         let dt_debug_str = format!("{dt:?}");
         assert_eq!(debug_str, dt_debug_str);
     }
+
+    #[test]
+    fn test_resolve_label() {
+        let mut block = BlockBuilder::new();
+        block
+            .begin_node(b"")
+            .begin_node(b"soc")
+            .begin_node(b"serial@10000000")
+            .end_node()
+            .end_node()
+            .begin_node(b"__symbols__")
+            .prop(b"uart0", b"/soc/serial@10000000\0")
+            .prop(b"soc", b"/soc\0")
+            .end_node()
+            .end_node()
+            .end();
+        let blob = testing::blob_from_block(&block);
+        let dt = Devicetree::from_bytes(&blob).unwrap();
+
+        let path = dt.resolve_label("uart0").unwrap().unwrap();
+        assert_eq!(path.value(), "/soc/serial@10000000");
+        let path = dt.resolve_label("soc").unwrap().unwrap();
+        assert_eq!(path.value(), "/soc");
+        assert!(dt.resolve_label("uart1").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_resolve_label_without_symbols() {
+        let blob = Bytes(*include_bytes!("../../../examples/assets/qemu-virt.dtb"));
+        let dt = Devicetree::from_bytes(&blob.0).unwrap();
+        assert!(dt.resolve_label("uart0").unwrap().is_none());
+    }
 }
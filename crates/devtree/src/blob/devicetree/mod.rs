@@ -38,14 +38,62 @@ impl fmt::Debug for Devicetree {
 }
 
 impl Devicetree {
-    pub unsafe fn from_ptr(ptr: *const u8) -> Result<&'static Self, ReadDevicetreeError> {
-        let header = unsafe { Header::from_ptr(ptr)? };
-        let total_size = header.total_size();
-        let bytes = unsafe { slice::from_raw_parts(ptr, total_size) };
-        Self::from_bytes_internal(bytes, header)
+    /// Reads a devicetree blob from a pointer, with the size given by its
+    /// header, and passes it to `f`.
+    ///
+    /// The blob is only borrowed while `f` runs, so that no reference to it
+    /// outlives the memory. Use [`Devicetree::from_slice`] to keep a reference
+    /// borrowing a buffer.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that the pointer is either null or points to a
+    /// devicetree blob whose whole size given by the header is readable, and
+    /// that the blob is not modified or freed until `f` returns.
+    pub unsafe fn with_ptr<F, R>(ptr: *const u8, f: F) -> Result<R, ReadDevicetreeError>
+    where
+        F: for<'blob> FnOnce(&'blob Self) -> R,
+    {
+        let header = unsafe { Header::read_from_ptr(ptr)? };
+        let bytes = unsafe { slice::from_raw_parts(ptr, header.total_size()) };
+        Self::from_bytes(bytes).map(f)
     }
 
+    /// Reads the devicetree blob at the start of `bytes`, borrowing it for the
+    /// lifetime of the slice.
+    ///
+    /// Unlike [`Devicetree::from_bytes`], the bytes after the size given by the
+    /// header are not part of the blob, so that a blob can be read from a
+    /// larger memory region.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ReadDevicetreeErrorKind::UnalignedPointer`] if the bytes are
+    /// not aligned to [`DEVICETREE_ALIGNMENT`], or an error if the blob is
+    /// malformed.
+    pub fn from_slice(bytes: &[u8]) -> Result<&Self, ReadDevicetreeError> {
+        let total_size = Self::from_bytes(bytes)?.header().total_size();
+        // SAFETY: the blob is validated above, and starts at the same address
+        Ok(unsafe { Self::from_bytes_unchecked(&bytes[..total_size]) })
+    }
+
+    /// Reads a devicetree blob from the bytes.
+    ///
+    /// The bytes must be aligned to [`DEVICETREE_ALIGNMENT`]. Use
+    /// `from_bytes_or_copy` to read a blob from a buffer of any alignment.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ReadDevicetreeErrorKind::UnalignedPointer`] if the bytes are
+    /// not aligned, or an error if the blob is malformed.
     pub fn from_bytes(bytes: &[u8]) -> Result<&Self, ReadDevicetreeError> {
+        ensure!(
+            bytes.as_ptr().addr().is_multiple_of(DEVICETREE_ALIGNMENT),
+            ReadDevicetreeErrorKind::UnalignedPointer {
+                address: bytes.as_ptr().addr(),
+                expected_alignment: DEVICETREE_ALIGNMENT,
+            }
+        );
         let header = Header::from_bytes(bytes)?;
         let total_size = header.total_size();
         ensure!(
@@ -78,8 +126,12 @@ impl Devicetree {
         unsafe { Ok(Self::from_bytes_unchecked(bytes)) }
     }
 
+    /// # Safety
+    ///
+    /// The bytes must be a valid devicetree blob aligned to
+    /// [`DEVICETREE_ALIGNMENT`].
     unsafe fn from_bytes_unchecked(bytes: &[u8]) -> &Self {
-        assert!(bytes.as_ptr().addr().is_multiple_of(DEVICETREE_ALIGNMENT));
+        debug_assert!(bytes.as_ptr().addr().is_multiple_of(DEVICETREE_ALIGNMENT));
         // SAFETY: Devicetree is #[repr(transparent)] over [u8]
         unsafe { (ptr::from_ref(bytes) as *const Self).as_ref().unwrap() }
     }
@@ -171,7 +223,7 @@ mod tests {
     use core::iter;
 
     use super::*;
-    use crate::testing::BlobBuilder;
+    use crate::{testing::BlobBuilder, util::AlignedByteBuffer};
 
     #[test]
     fn test_devicetree_from_bytes_and_as_bytes() {
@@ -183,12 +235,42 @@ mod tests {
     }
 
     #[test]
-    fn test_devicetree_from_ptr() {
+    fn test_devicetree_with_ptr() {
         let buffer = BlobBuilder::new()
             .extend_mem_rsvmap_from_slice(&[ReserveEntry::terminator()])
             .build();
-        let dt = unsafe { Devicetree::from_ptr(buffer.as_ptr()).unwrap() };
+        let len = unsafe { Devicetree::with_ptr(buffer.as_ptr(), |dt| dt.as_bytes().len()) };
+        assert_eq!(len.unwrap(), buffer.len());
+
+        let err = unsafe { Devicetree::with_ptr(ptr::null(), |_dt| ()) }.unwrap_err();
+        assert!(
+            matches!(err.kind(), ReadDevicetreeErrorKind::NullPointer),
+            "err: {err:?}",
+        );
+    }
+
+    #[test]
+    fn test_devicetree_from_slice() {
+        let blob = BlobBuilder::new()
+            .extend_mem_rsvmap_from_slice(&[ReserveEntry::terminator()])
+            .build();
+        let mut buffer = AlignedByteBuffer::<DEVICETREE_ALIGNMENT>::new_zeroed(blob.len() + 16);
+        buffer.as_mut_slice()[..blob.len()].copy_from_slice(&blob);
+
+        // the trailing bytes are not part of the blob
+        let dt = Devicetree::from_slice(&buffer).unwrap();
+        assert_eq!(dt.as_bytes(), &blob[..]);
+        let dt = Devicetree::from_bytes(&buffer).unwrap();
         assert_eq!(dt.as_bytes(), &buffer[..]);
+
+        let err = Devicetree::from_slice(&buffer[..blob.len() - 1]).unwrap_err();
+        assert!(
+            matches!(
+                err.kind(),
+                ReadDevicetreeErrorKind::InsufficientBytes { .. }
+            ),
+            "err: {err:?}",
+        );
     }
 
     #[test]
@@ -133,20 +133,24 @@ impl Header {
 }
 
 impl Header {
-    /// Constructs a reference to a DTB header from a pointer.
+    /// Reads a copy of a DTB header from a pointer.
+    ///
+    /// The header is copied out, so that nothing borrows the memory after
+    /// this returns.
     ///
     /// # Safety
     ///
-    /// The caller must ensure that the pointer is valid and points to a memory
-    /// region that is at least the size of `Header`.
-    pub unsafe fn from_ptr(ptr: *const u8) -> Result<&'static Self, ReadDevicetreeError> {
+    /// The caller must ensure that the pointer is either null or points to a
+    /// readable memory region that is at least the size of `Header`.
+    pub unsafe fn read_from_ptr(ptr: *const u8) -> Result<Self, ReadDevicetreeError> {
+        ensure!(!ptr.is_null(), ReadDevicetreeErrorKind::NullPointer);
         let ptr: *const Self = polyfill::ptr_cast_aligned(ptr).ok_or_else(|| {
             ReadDevicetreeErrorKind::UnalignedPointer {
                 address: ptr.addr(),
                 expected_alignment: align_of::<Self>(),
             }
         })?;
-        let header = unsafe { ptr.as_ref() }.ok_or(ReadDevicetreeErrorKind::NullPointer)?;
+        let header = unsafe { ptr.read() };
         header.validate()?;
         Ok(header)
     }
@@ -313,13 +317,13 @@ mod tests {
     fn test_valid_header() {
         let header = valid_header();
         let ptr = header_to_ptr(&header);
-        let _header = unsafe { Header::from_ptr(ptr) }.unwrap();
+        let _header = unsafe { Header::read_from_ptr(ptr) }.unwrap();
     }
 
     #[test]
     fn test_null_pointer() {
         let ptr: *const u8 = core::ptr::null();
-        let err = unsafe { Header::from_ptr(ptr) }.unwrap_err();
+        let err = unsafe { Header::read_from_ptr(ptr) }.unwrap_err();
         assert!(
             matches!(err.kind(), ReadDevicetreeErrorKind::NullPointer),
             "err: {err:?}",
@@ -330,7 +334,7 @@ mod tests {
     fn test_unaligned_header() {
         let header = valid_header();
         let ptr = header_to_ptr(&header).map_addr(|addr| addr + 1);
-        let err = unsafe { Header::from_ptr(ptr) }.unwrap_err();
+        let err = unsafe { Header::read_from_ptr(ptr) }.unwrap_err();
         assert!(
             matches!(err.kind(), ReadDevicetreeErrorKind::UnalignedPointer { .. }),
             "err: {err:?}",
@@ -344,7 +348,7 @@ mod tests {
             ..valid_header()
         };
         let ptr = header_to_ptr(&header);
-        let err = unsafe { Header::from_ptr(ptr) }.unwrap_err();
+        let err = unsafe { Header::read_from_ptr(ptr) }.unwrap_err();
         assert!(
             matches!(err.kind(), ReadDevicetreeErrorKind::InvalidMagic { .. }),
             "err: {err:?}",
@@ -358,7 +362,7 @@ mod tests {
             ..valid_header()
         };
         let ptr = header_to_ptr(&header);
-        let err = unsafe { Header::from_ptr(ptr) }.unwrap_err();
+        let err = unsafe { Header::read_from_ptr(ptr) }.unwrap_err();
         assert!(
             matches!(err.kind(), ReadDevicetreeErrorKind::InvalidTotalSize { .. }),
             "err: {err:?}",
@@ -373,7 +377,7 @@ mod tests {
             ..valid_header()
         };
         let ptr = header_to_ptr(&header);
-        let err = unsafe { Header::from_ptr(ptr) }.unwrap_err();
+        let err = unsafe { Header::read_from_ptr(ptr) }.unwrap_err();
         assert!(
             matches!(
                 err.kind(),
@@ -390,7 +394,7 @@ mod tests {
             ..valid_header()
         };
         let ptr = header_to_ptr(&header);
-        let err = unsafe { Header::from_ptr(ptr) }.unwrap_err();
+        let err = unsafe { Header::read_from_ptr(ptr) }.unwrap_err();
         assert!(
             matches!(err.kind(), ReadDevicetreeErrorKind::UnalignedBlock { block_name, .. } if *block_name == "memory reservation block"),
             "err: {err:?}",
@@ -404,7 +408,7 @@ mod tests {
             ..valid_header()
         };
         let ptr = header_to_ptr(&header);
-        let err = unsafe { Header::from_ptr(ptr) }.unwrap_err();
+        let err = unsafe { Header::read_from_ptr(ptr) }.unwrap_err();
         assert!(
             matches!(err.kind(), ReadDevicetreeErrorKind::UnalignedBlock { block_name, .. } if *block_name == "structure block"),
             "err: {err:?}",
@@ -418,7 +422,7 @@ mod tests {
             ..valid_header()
         };
         let ptr = header_to_ptr(&header);
-        let err = unsafe { Header::from_ptr(ptr) }.unwrap_err();
+        let err = unsafe { Header::read_from_ptr(ptr) }.unwrap_err();
         assert!(
             matches!(err.kind(), ReadDevicetreeErrorKind::BlockOutOfBounds { block_name, .. } if *block_name == "memory reservation block"),
             "err: {err:?}",
@@ -434,7 +438,7 @@ mod tests {
         };
         assert_eq!(header.total_size(), 104);
         let ptr = header_to_ptr(&header);
-        let err = unsafe { Header::from_ptr(ptr) }.unwrap_err();
+        let err = unsafe { Header::read_from_ptr(ptr) }.unwrap_err();
         assert!(
             matches!(err.kind(), ReadDevicetreeErrorKind::BlockOutOfBounds { block_name, .. } if *block_name == "structure block"),
             "err: {err:?}",
@@ -450,7 +454,7 @@ mod tests {
         };
         assert_eq!(header.total_size(), 104);
         let ptr = header_to_ptr(&header);
-        let err = unsafe { Header::from_ptr(ptr) }.unwrap_err();
+        let err = unsafe { Header::read_from_ptr(ptr) }.unwrap_err();
         assert!(
             matches!(err.kind(), ReadDevicetreeErrorKind::BlockOutOfBounds { block_name, .. } if *block_name == "strings block"),
             "err: {err:?}",
@@ -504,7 +508,7 @@ mod tests {
             ..valid_header()
         };
         let ptr = header_to_ptr(&header);
-        let err = unsafe { Header::from_ptr(ptr) }.unwrap_err();
+        let err = unsafe { Header::read_from_ptr(ptr) }.unwrap_err();
         assert!(
             matches!(err.kind(), ReadDevicetreeErrorKind::BlockOutOfBounds { block_name, .. } if *block_name == "structure block"),
             "err: {err:?}",
@@ -522,7 +526,7 @@ mod tests {
             ..valid_header()
         };
         let ptr = header_to_ptr(&header);
-        let _ = unsafe { Header::from_ptr(ptr) }.unwrap();
+        let _ = unsafe { Header::read_from_ptr(ptr) }.unwrap();
     }
 
     #[test]
@@ -532,7 +536,7 @@ mod tests {
             ..valid_header()
         };
         let ptr = header_to_ptr(&header);
-        let _ = unsafe { Header::from_ptr(ptr) }.unwrap();
+        let _ = unsafe { Header::read_from_ptr(ptr) }.unwrap();
     }
 
     #[test]
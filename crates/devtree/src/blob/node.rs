@@ -1,3 +1,5 @@
+use core::cmp::Ordering;
+
 use super::UNIT_ADDRESS_SEPARATOR;
 use crate::{
     de::{DeserializeNode, NodeDeserializer, error::DeserializeError},
@@ -46,6 +48,61 @@ impl<'blob> Node<'blob> {
     pub fn is_root(&self) -> bool {
         self.full_name().is_empty()
     }
+
+    /// Compares the nodes by the unit address, then by the name.
+    ///
+    /// The comma-separated cells of the unit addresses are compared as
+    /// hexadecimal numbers, so `cpu@a` comes after `cpu@9`. The cells that are
+    /// not numbers are compared as bytes, and a node without the unit address
+    /// comes first.
+    #[must_use]
+    pub fn cmp_by_unit_address(&self, other: &Self) -> Ordering {
+        let (name, unit_address) = self.split_name();
+        let (other_name, other_unit_address) = other.split_name();
+        cmp_unit_address(unit_address, other_unit_address).then_with(|| name.cmp(other_name))
+    }
+
+    /// Compares the nodes by the name, then by the unit address.
+    ///
+    /// See [`Self::cmp_by_unit_address`] for the order of the unit addresses.
+    #[must_use]
+    pub fn cmp_by_name(&self, other: &Self) -> Ordering {
+        let (name, unit_address) = self.split_name();
+        let (other_name, other_unit_address) = other.split_name();
+        name.cmp(other_name)
+            .then_with(|| cmp_unit_address(unit_address, other_unit_address))
+    }
+}
+
+fn cmp_unit_address(a: Option<&ByteStr>, b: Option<&ByteStr>) -> Ordering {
+    let (a, b) = match (a, b) {
+        (None, None) => return Ordering::Equal,
+        (None, Some(_)) => return Ordering::Less,
+        (Some(_), None) => return Ordering::Greater,
+        (Some(a), Some(b)) => (a, b),
+    };
+    let mut a_cells = a.split(|&c| c == b',');
+    let mut b_cells = b.split(|&c| c == b',');
+    loop {
+        let (a, b) = match (a_cells.next(), b_cells.next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(a), Some(b)) => (a, b),
+        };
+        let ordering = match (parse_hex(a), parse_hex(b)) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            _ => a.cmp(b),
+        };
+        if ordering.is_ne() {
+            return ordering;
+        }
+    }
+}
+
+fn parse_hex(cell: &[u8]) -> Option<u128> {
+    let cell = str::from_utf8(cell).ok()?;
+    u128::from_str_radix(cell, 16).ok()
 }
 
 impl<'blob> DeserializeNode<'blob> for Node<'blob> {
@@ -113,4 +170,26 @@ mod tests {
         let node = Node::new("notroot");
         assert!(!node.is_root());
     }
+
+    #[test]
+    fn test_cmp_by_unit_address() {
+        let cmp = |a, b| Node::new(a).cmp_by_unit_address(&Node::new(b));
+        assert_eq!(cmp("cpu@9", "cpu@a"), Ordering::Less);
+        assert_eq!(cmp("cpu@10", "cpu@9"), Ordering::Greater);
+        assert_eq!(cmp("cpu@0", "cpu@00"), Ordering::Equal);
+        assert_eq!(cmp("memory", "cpu@0"), Ordering::Less);
+        assert_eq!(cmp("b@1", "a@1"), Ordering::Greater);
+        assert_eq!(cmp("pci@1,2", "pci@1,10"), Ordering::Less);
+        assert_eq!(cmp("pci@1", "pci@1,0"), Ordering::Less);
+        assert_eq!(cmp("x@gpio", "x@10"), Ordering::Greater);
+    }
+
+    #[test]
+    fn test_cmp_by_name() {
+        let cmp = |a, b| Node::new(a).cmp_by_name(&Node::new(b));
+        assert_eq!(cmp("uart@10", "cpu@0"), Ordering::Greater);
+        assert_eq!(cmp("cpu@a", "cpu@9"), Ordering::Greater);
+        assert_eq!(cmp("cpu", "cpu@0"), Ordering::Less);
+        assert_eq!(cmp("cpu@1", "cpu@1"), Ordering::Equal);
+    }
 }
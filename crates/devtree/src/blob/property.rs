@@ -1,6 +1,10 @@
 use crate::{
     bytes::LazyCStr,
-    de::{DeserializeProperty, PropertyDeserializer, error::DeserializeError},
+    de::{
+        DeserializeProperty, PropertyDeserializer,
+        error::{DeserializeError, DeserializePropertyError},
+    },
+    model::property::CellReader,
     types::ByteStr,
 };
 
@@ -31,6 +35,71 @@ impl<'blob> Property<'blob> {
     pub fn value(&self) -> &'blob [u8] {
         self.value
     }
+
+    /// Returns whether the value is a single cell equal to `value`.
+    #[must_use]
+    pub fn value_eq_u32(&self, value: u32) -> bool {
+        self.value_eq_cells([value])
+    }
+
+    /// Returns whether the value is the cells yielded by `cells`.
+    ///
+    /// The cells are compared one by one, without collecting them.
+    #[must_use]
+    pub fn value_eq_cells<I>(&self, cells: I) -> bool
+    where
+        I: IntoIterator<Item = u32>,
+    {
+        let (chunks, rest) = self.value.as_chunks::<4>();
+        rest.is_empty()
+            && chunks
+                .iter()
+                .map(|chunk| u32::from_be_bytes(*chunk))
+                .eq(cells)
+    }
+
+    /// Returns whether the value is the null-terminated string `s`.
+    #[must_use]
+    pub fn value_eq_str<S>(&self, s: &S) -> bool
+    where
+        S: AsRef<[u8]> + ?Sized,
+    {
+        self.value.strip_suffix(b"\0") == Some(s.as_ref())
+    }
+
+    /// Returns whether the value is the list of the null-terminated strings
+    /// yielded by `strs`.
+    ///
+    /// The strings are compared one by one, without collecting them.
+    #[must_use]
+    pub fn value_eq_str_list<I>(&self, strs: I) -> bool
+    where
+        I: IntoIterator<Item: AsRef<[u8]>>,
+    {
+        let mut rest = self.value;
+        for s in strs {
+            let s = s.as_ref();
+            let Some((head, tail)) = rest.split_at_checked(s.len()) else {
+                return false;
+            };
+            if head != s || tail.first() != Some(&0) {
+                return false;
+            }
+            rest = &tail[1..];
+        }
+        rest.is_empty()
+    }
+
+    /// Returns a reader consuming the value as groups of cells.
+    pub fn cell_reader(&self) -> Result<CellReader<'blob>, DeserializePropertyError> {
+        let (cells, rest) = self.value.as_chunks();
+        if !rest.is_empty() {
+            return Err(DeserializePropertyError::value_length_is_not_multiple_of(
+                self, 4,
+            ));
+        }
+        Ok(CellReader::new(cells))
+    }
 }
 
 impl<'blob> DeserializeProperty<'blob> for Property<'blob> {
@@ -76,4 +145,69 @@ mod tests {
         assert_eq!(prop1, prop2);
         assert_ne!(prop1, prop3);
     }
+
+    #[test]
+    fn test_property_cell_reader() {
+        let prop = Property::new(b"reg", &[0, 0, 0, 1, 0, 0, 0, 2]);
+        let mut reader = prop.cell_reader().unwrap();
+        assert_eq!(reader.read_u32().unwrap(), 1);
+        assert_eq!(reader.read_u32().unwrap(), 2);
+        reader.finish().unwrap();
+
+        let prop = Property::new(b"reg", &[0, 0, 0, 1, 0]);
+        let err = prop.cell_reader().unwrap_err();
+        assert!(err.kind().is_value_length_is_not_multiple_of());
+    }
+
+    #[test]
+    fn test_property_value_eq_cells() {
+        let prop = Property::new(b"phandle", &[0, 0, 0, 5]);
+        assert!(prop.value_eq_u32(5));
+        assert!(!prop.value_eq_u32(6));
+        assert!(prop.value_eq_cells([5]));
+        assert!(!prop.value_eq_cells([5, 0]));
+
+        let prop = Property::new(b"reg", &[0, 0, 0, 1, 0, 0, 0, 2]);
+        assert!(!prop.value_eq_u32(1));
+        assert!(prop.value_eq_cells([1, 2]));
+        assert!(prop.value_eq_cells(1..=2));
+        assert!(!prop.value_eq_cells([1]));
+        assert!(!prop.value_eq_cells([2, 1]));
+
+        let prop = Property::new(b"odd", &[0, 0, 0, 1, 0]);
+        assert!(!prop.value_eq_u32(1));
+        assert!(!prop.value_eq_cells([1]));
+
+        let prop = Property::new(b"empty", &[]);
+        assert!(prop.value_eq_cells([]));
+        assert!(!prop.value_eq_u32(0));
+    }
+
+    #[test]
+    fn test_property_value_eq_str() {
+        let prop = Property::new(b"status", b"okay\0");
+        assert!(prop.value_eq_str("okay"));
+        assert!(prop.value_eq_str(b"okay"));
+        assert!(!prop.value_eq_str("ok"));
+        assert!(!prop.value_eq_str("okay\0"));
+
+        let prop = Property::new(b"status", b"okay");
+        assert!(!prop.value_eq_str("okay"));
+    }
+
+    #[test]
+    fn test_property_value_eq_str_list() {
+        let prop = Property::new(b"compatible", b"vendor,dev\0generic\0");
+        assert!(prop.value_eq_str_list(["vendor,dev", "generic"]));
+        assert!(!prop.value_eq_str_list(["vendor,dev"]));
+        assert!(!prop.value_eq_str_list(["vendor,dev", "generic", "more"]));
+        assert!(!prop.value_eq_str_list(["vendor", "generic"]));
+        assert!(!prop.value_eq_str("vendor,dev"));
+
+        let prop = Property::new(b"compatible", b"vendor,dev");
+        assert!(!prop.value_eq_str_list(["vendor,dev"]));
+
+        let prop = Property::new(b"empty", b"");
+        assert!(prop.value_eq_str_list::<[&str; 0]>([]));
+    }
 }
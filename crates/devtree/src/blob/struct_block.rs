@@ -13,13 +13,13 @@ impl TokenType {
     pub const END: u32 = 0x0000_0009;
 
     #[must_use]
-    pub fn new(value: u32) -> Self {
-        Self(Be::new(&value))
+    pub const fn new(value: u32) -> Self {
+        Self(Be::<u32>::from_native(value))
     }
 
     #[must_use]
-    pub fn value(&self) -> u32 {
-        self.0.read()
+    pub const fn value(&self) -> u32 {
+        self.0.to_native()
     }
 }
 
@@ -32,10 +32,10 @@ pub struct PropertyHeader {
 
 impl PropertyHeader {
     #[must_use]
-    pub fn new(len: u32, name_offset: u32) -> Self {
+    pub const fn new(len: u32, name_offset: u32) -> Self {
         Self {
-            len: Be::new(&len),
-            name_offset: Be::new(&name_offset),
+            len: Be::<u32>::from_native(len),
+            name_offset: Be::<u32>::from_native(name_offset),
         }
     }
 
@@ -1,9 +1,9 @@
-use core::str::Utf8Error;
+use core::{fmt, str::Utf8Error};
 
 use crate::{
     blob::{Node, Property},
-    tree_cursor::error::ReadTreeError,
     model::property::Phandle,
+    tree_cursor::error::ReadTreeError,
 };
 
 #[derive(Debug, derive_more::Display, derive_more::Error, derive_more::IsVariant)]
@@ -86,6 +86,10 @@ pub enum DeserializePropertyErrorKind {
     ValueLengthMismatch { expected: usize, actual: usize },
     #[display("value length is not multiple of {expected_unit}, got {actual}")]
     ValueLengthIsNotMultipleOf { expected_unit: usize, actual: usize },
+    #[display("expected {expected} cells, got {remaining} remaining cells")]
+    NotEnoughCells { expected: usize, remaining: usize },
+    #[display("{remaining} trailing cells remain unread")]
+    TrailingCells { remaining: usize },
     #[display("missing nul character in string value")]
     MissingNulInStringValue,
     #[display("invalid string value")]
@@ -93,6 +97,8 @@ pub enum DeserializePropertyErrorKind {
         #[error(source)]
         source: Utf8Error,
     },
+    #[display("unparsable string value \"{value}\"")]
+    UnparsableStringValue { value: StrExcerpt },
     #[display("{message}")]
     Custom { message: &'static str },
 }
@@ -122,6 +128,22 @@ impl DeserializePropertyError {
         .into()
     }
 
+    #[track_caller]
+    #[must_use]
+    pub fn not_enough_cells(expected: usize, remaining: usize) -> Self {
+        DeserializePropertyErrorKind::NotEnoughCells {
+            expected,
+            remaining,
+        }
+        .into()
+    }
+
+    #[track_caller]
+    #[must_use]
+    pub fn trailing_cells(remaining: usize) -> Self {
+        DeserializePropertyErrorKind::TrailingCells { remaining }.into()
+    }
+
     #[track_caller]
     #[must_use]
     pub fn missing_nul_in_string_value(_property: &Property<'_>) -> Self {
@@ -134,6 +156,13 @@ impl DeserializePropertyError {
         DeserializePropertyErrorKind::InvalidStringValue { source }.into()
     }
 
+    #[track_caller]
+    #[must_use]
+    pub fn unparsable_string_value(_property: &Property<'_>, value: &str) -> Self {
+        let value = StrExcerpt::new(value);
+        DeserializePropertyErrorKind::UnparsableStringValue { value }.into()
+    }
+
     #[track_caller]
     #[must_use]
     pub fn custom(_property: &Property<'_>, message: &'static str) -> Self {
@@ -141,6 +170,67 @@ impl DeserializePropertyError {
     }
 }
 
+/// Leading part of a string kept in an error without allocating.
+///
+/// Strings longer than [`StrExcerpt::CAPACITY`] bytes are cut at a character
+/// boundary, and displayed with a trailing `...`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct StrExcerpt {
+    bytes: [u8; Self::CAPACITY],
+    len: usize,
+    truncated: bool,
+}
+
+impl StrExcerpt {
+    pub const CAPACITY: usize = 32;
+
+    #[must_use]
+    pub fn new(s: &str) -> Self {
+        let len = (0..=usize::min(s.len(), Self::CAPACITY))
+            .rev()
+            .find(|&i| s.is_char_boundary(i))
+            .unwrap_or(0);
+        let mut bytes = [0; Self::CAPACITY];
+        bytes[..len].copy_from_slice(&s.as_bytes()[..len]);
+        Self {
+            bytes,
+            len,
+            truncated: len < s.len(),
+        }
+    }
+
+    /// Returns the kept part of the string.
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        // the bytes are cut at a character boundary
+        str::from_utf8(&self.bytes[..self.len]).unwrap_or_default()
+    }
+
+    #[must_use]
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+}
+
+impl fmt::Debug for StrExcerpt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StrExcerpt")
+            .field("value", &self.as_str())
+            .field("truncated", &self.truncated)
+            .finish_non_exhaustive()
+    }
+}
+
+impl fmt::Display for StrExcerpt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())?;
+        if self.truncated {
+            f.write_str("...")?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, derive_more::Display, derive_more::Error, derive_more::IsVariant)]
 #[non_exhaustive]
 pub enum DeserializeNodeErrorKind {
@@ -207,3 +297,27 @@ impl DeserializeNodeError {
         DeserializeNodeErrorKind::Custom { message }.into()
     }
 }
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+#[cfg(test)]
+mod tests {
+    extern crate alloc;
+
+    use alloc::format;
+
+    use super::StrExcerpt;
+
+    #[test]
+    fn test_str_excerpt() {
+        let short = StrExcerpt::new("riscv,sv39");
+        assert_eq!(short.as_str(), "riscv,sv39");
+        assert!(!short.is_truncated());
+        assert_eq!(format!("{short}"), "riscv,sv39");
+
+        // the multi-byte character crossing the capacity is dropped
+        let long = StrExcerpt::new("0123456789abcdef0123456789abcd\u{3042}xyz");
+        assert_eq!(long.as_str(), "0123456789abcdef0123456789abcd");
+        assert!(long.is_truncated());
+        assert_eq!(format!("{long}"), "0123456789abcdef0123456789abcd...");
+    }
+}
@@ -1,7 +1,9 @@
+use core::array;
+
 use crate::{
     blob::Property,
     de::{
-        DeserializeProperty, PropertyDeserializer,
+        DeserializeProperty, FixedSizeCell, PropertyDeserializer,
         error::{DeserializeError, DeserializePropertyError},
     },
     polyfill,
@@ -16,6 +18,22 @@ fn deserialize_byte_str_until_first_nul<'blob>(
     Ok(bytes)
 }
 
+impl FixedSizeCell for u32 {
+    const SIZE: usize = size_of::<Self>();
+
+    fn from_be_slice(bytes: &[u8]) -> Self {
+        Self::from_be_bytes(bytes.try_into().unwrap())
+    }
+}
+
+impl FixedSizeCell for u64 {
+    const SIZE: usize = size_of::<Self>();
+
+    fn from_be_slice(bytes: &[u8]) -> Self {
+        Self::from_be_bytes(bytes.try_into().unwrap())
+    }
+}
+
 impl<'blob, T> DeserializeProperty<'blob> for Option<T>
 where
     T: DeserializeProperty<'blob>,
@@ -42,6 +60,64 @@ impl<'blob, const N: usize> DeserializeProperty<'blob> for [u8; N] {
     }
 }
 
+impl<'blob, T, const N: usize> DeserializeProperty<'blob> for [T; N]
+where
+    T: FixedSizeCell,
+{
+    fn deserialize_property<'de, D>(de: &mut D) -> Result<Self, DeserializeError>
+    where
+        D: PropertyDeserializer<'de, 'blob> + ?Sized,
+    {
+        let property = de.property();
+        let value = property.value();
+        ensure!(
+            value.len() == T::SIZE * N,
+            DeserializePropertyError::value_length_mismatch(property, T::SIZE * N)
+        );
+        Ok(array::from_fn(|i| {
+            T::from_be_slice(&value[i * T::SIZE..][..T::SIZE])
+        }))
+    }
+}
+
+fn take_cell<T>(bytes: &mut &[u8]) -> T
+where
+    T: FixedSizeCell,
+{
+    let (head, tail) = bytes.split_at(T::SIZE);
+    *bytes = tail;
+    T::from_be_slice(head)
+}
+
+macro_rules! impl_deserialize_property_for_tuple {
+    ($($ty:ident),+) => {
+        impl<'blob, $($ty),+> DeserializeProperty<'blob> for ($($ty,)+)
+        where
+            $($ty: FixedSizeCell,)+
+        {
+            fn deserialize_property<'de, D>(de: &mut D) -> Result<Self, DeserializeError>
+            where
+                D: PropertyDeserializer<'de, 'blob> + ?Sized,
+            {
+                let property = de.property();
+                let value = property.value();
+                let size = 0 $(+ $ty::SIZE)+;
+                ensure!(
+                    value.len() == size,
+                    DeserializePropertyError::value_length_mismatch(property, size)
+                );
+                let mut rest = value;
+                Ok(($(take_cell::<$ty>(&mut rest),)+))
+            }
+        }
+    };
+}
+
+impl_deserialize_property_for_tuple!(T0);
+impl_deserialize_property_for_tuple!(T0, T1);
+impl_deserialize_property_for_tuple!(T0, T1, T2);
+impl_deserialize_property_for_tuple!(T0, T1, T2, T3);
+
 impl<'blob> DeserializeProperty<'blob> for &'blob [u8] {
     fn deserialize_property<'de, D>(de: &mut D) -> Result<Self, DeserializeError>
     where
@@ -142,3 +218,77 @@ impl<'blob> DeserializeProperty<'blob> for &'blob ByteStr {
         Ok(ByteStr::new(bytes))
     }
 }
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+#[cfg(test)]
+mod tests {
+    use core::fmt;
+
+    use super::*;
+    use crate::{
+        blob::Node,
+        de::{error::DeserializeErrorKind, types::DefaultPropertyDeserializer},
+        testing::SliceTokenCursor,
+        token_cursor::Token,
+        tree_cursor::types::StackBasedTreeCursor,
+    };
+
+    fn deserialize<'blob, T>(value: &'blob [u8]) -> Result<T, DeserializeError>
+    where
+        T: DeserializeProperty<'blob>,
+    {
+        let tokens = &[Token::BeginNode(Node::new("")), Token::EndNode];
+        let cursor = StackBasedTreeCursor::new(SliceTokenCursor::new(tokens)).unwrap();
+        let mut de = DefaultPropertyDeserializer::new(Property::new("prop", value), &cursor);
+        T::deserialize_property(&mut de)
+    }
+
+    #[track_caller]
+    fn assert_length_mismatch<T>(result: Result<T, DeserializeError>)
+    where
+        T: fmt::Debug,
+    {
+        let err = result.unwrap_err();
+        assert!(
+            matches!(
+                err.kind(),
+                DeserializeErrorKind::DeserializeProperty { source }
+                    if source.kind().is_value_length_mismatch()
+            ),
+            "err: {err:?}",
+        );
+    }
+
+    #[test]
+    fn test_u32_array() {
+        let value = [0, 0, 0, 1, 0, 0, 0, 2];
+        assert_eq!(deserialize::<[u32; 2]>(&value).unwrap(), [1, 2]);
+        assert_eq!(deserialize::<[u32; 0]>(&[]).unwrap(), []);
+        assert_length_mismatch(deserialize::<[u32; 1]>(&value));
+        assert_length_mismatch(deserialize::<[u32; 3]>(&value));
+    }
+
+    #[test]
+    fn test_u64_array() {
+        let value = [0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 3];
+        assert_eq!(deserialize::<[u64; 2]>(&value).unwrap(), [1, 0x2_0000_0003]);
+        assert_length_mismatch(deserialize::<[u64; 1]>(&value));
+        assert_length_mismatch(deserialize::<[u64; 2]>(&value[..15]));
+    }
+
+    #[test]
+    fn test_tuple() {
+        let value = [0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 3];
+        assert_eq!(
+            deserialize::<(u64, u64)>(&value).unwrap(),
+            (1, 0x2_0000_0003)
+        );
+        assert_eq!(
+            deserialize::<(u32, u64, u32)>(&value).unwrap(),
+            (0, 0x1_0000_0002, 3)
+        );
+        assert_eq!(deserialize::<(u32,)>(&value[..4]).unwrap(), (0,));
+        assert_length_mismatch(deserialize::<(u64, u32)>(&value));
+        assert_length_mismatch(deserialize::<(u64, u64, u32)>(&value));
+    }
+}
@@ -5,3 +5,198 @@ mod impls;
 mod traits;
 pub mod types;
 pub mod util;
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+#[cfg(test)]
+mod tests {
+    extern crate alloc;
+
+    use alloc::{string::ToString as _, vec::Vec};
+    use core::str::FromStr;
+
+    use super::error::{DeserializeError, DeserializeErrorKind};
+    use crate::{
+        DeserializeNode,
+        blob::{Node, Property},
+        model::{node::NodeName, property::Status},
+        testing::SliceTokenCursor,
+        token_cursor::Token,
+        tree_cursor::{RawNode, TreeCursor as _, TreeIterator as _, types::StackBasedTreeCursor},
+        types::ByteStr,
+    };
+
+    #[derive(DeserializeNode)]
+    #[devtree(crate = crate)]
+    struct WithStatus<T> {
+        #[devtree(node)]
+        node: T,
+        #[devtree(property(default))]
+        status: Status,
+    }
+
+    #[derive(DeserializeNode)]
+    #[devtree(crate = crate)]
+    struct Labeled<'blob, T, L> {
+        #[devtree(node)]
+        name: NodeName<'blob>,
+        #[devtree(property(default))]
+        label: Option<L>,
+        #[devtree(repeated_children(name = "child"))]
+        children: Vec<WithStatus<T>>,
+    }
+
+    #[derive(Debug, PartialEq, Eq)]
+    enum MmuType {
+        Sv39,
+        Sv48,
+    }
+
+    impl FromStr for MmuType {
+        type Err = ();
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "riscv,sv39" => Ok(Self::Sv39),
+                "riscv,sv48" => Ok(Self::Sv48),
+                _ => Err(()),
+            }
+        }
+    }
+
+    #[derive(Debug, DeserializeNode)]
+    #[devtree(crate = crate)]
+    struct Cpu {
+        #[devtree(property(name = "mmu-type", parse_with_str))]
+        mmu_type: MmuType,
+        #[devtree(property(
+            name = "riscv,cbom-block-size",
+            default,
+            parse_with_str = |s: &str| s.parse().map(Some),
+        ))]
+        cbom_block_size: Option<u32>,
+    }
+
+    #[derive(DeserializeNode)]
+    #[devtree(crate = crate)]
+    struct Matched<'blob> {
+        #[devtree(property(default))]
+        compatible: Option<&'blob str>,
+        #[devtree(raw_node)]
+        raw: RawNode<'blob>,
+    }
+
+    #[derive(DeserializeNode)]
+    #[devtree(crate = crate)]
+    struct Serial<'blob> {
+        #[devtree(node)]
+        name: NodeName<'blob>,
+        #[devtree(property(name = "clock-frequency"))]
+        clock_frequency: u32,
+    }
+
+    fn deserialize_cpu(properties: &[(&str, &str)]) -> Result<Cpu, DeserializeError> {
+        let mut tokens = alloc::vec![Token::BeginNode(Node::new("cpu"))];
+        tokens.extend(
+            properties
+                .iter()
+                .map(|&(name, value)| Token::Property(Property::new(name, value))),
+        );
+        tokens.push(Token::EndNode);
+        let mut cursor = StackBasedTreeCursor::new(SliceTokenCursor::new(&tokens)).unwrap();
+        cursor.read_node().deserialize_node::<Cpu>()
+    }
+
+    #[test]
+    fn test_parse_with_str() {
+        let cpu = deserialize_cpu(&[
+            ("mmu-type", "riscv,sv48\0"),
+            ("riscv,cbom-block-size", "64\0"),
+        ])
+        .unwrap();
+        assert_eq!(cpu.mmu_type, MmuType::Sv48);
+        assert_eq!(cpu.cbom_block_size, Some(64));
+
+        let cpu = deserialize_cpu(&[("mmu-type", "riscv,sv39\0")]).unwrap();
+        assert_eq!(cpu.mmu_type, MmuType::Sv39);
+        assert_eq!(cpu.cbom_block_size, None);
+
+        let err = deserialize_cpu(&[("mmu-type", "riscv,sv57\0")]).unwrap_err();
+        let DeserializeErrorKind::DeserializeProperty { source } = err.kind() else {
+            panic!("unexpected error: {err:?}");
+        };
+        assert_eq!(source.to_string(), "unparsable string value \"riscv,sv57\"");
+
+        // strings that are not well-formed are not parsed
+        let err = deserialize_cpu(&[("mmu-type", "riscv,sv39")]).unwrap_err();
+        let DeserializeErrorKind::DeserializeProperty { source } = err.kind() else {
+            panic!("unexpected error: {err:?}");
+        };
+        assert!(source.kind().is_missing_nul_in_string_value(), "{err:?}");
+    }
+
+    #[test]
+    fn test_raw_node() {
+        let tokens = &[
+            Token::BeginNode(Node::new("")),
+            Token::BeginNode(Node::new("soc")),
+            Token::BeginNode(Node::new("serial@10000000")),
+            Token::Property(Property::new("compatible", "ns16550a\0")),
+            Token::Property(Property::new(
+                "clock-frequency",
+                [0, 0x38, 0x40, 0].as_slice(),
+            )),
+            Token::EndNode,
+            Token::EndNode,
+            Token::EndNode,
+        ];
+        let tokens = SliceTokenCursor::new(tokens);
+        let mut cursor = StackBasedTreeCursor::new(tokens.clone()).unwrap();
+        let matched = cursor
+            .read_descendant_nodes()
+            .deserialize_node::<Matched<'_>>()
+            .map(Result::unwrap)
+            .find(|matched| matched.compatible.is_some())
+            .unwrap();
+        assert_eq!(matched.compatible, Some("ns16550a"));
+        assert_eq!(matched.raw.depth(), 2);
+
+        let mut cursor = StackBasedTreeCursor::new(tokens).unwrap();
+        cursor.seek_raw_node(&matched.raw).unwrap();
+        let serial = cursor.read_node().deserialize_node::<Serial<'_>>().unwrap();
+        assert_eq!(serial.name.value(), ByteStr::new("serial"));
+        assert_eq!(serial.clock_frequency, 0x0038_4000);
+    }
+
+    #[test]
+    fn test_generic_struct() {
+        let tokens = &[
+            Token::BeginNode(Node::new("")),
+            Token::Property(Property::new("label", "root\0")),
+            Token::BeginNode(Node::new("child")),
+            Token::EndNode,
+            Token::BeginNode(Node::new("child")),
+            Token::Property(Property::new("status", "disabled\0")),
+            Token::EndNode,
+            Token::EndNode,
+        ];
+        let mut cursor = StackBasedTreeCursor::new(SliceTokenCursor::new(tokens)).unwrap();
+        let root = cursor
+            .read_node()
+            .deserialize_node::<Labeled<'_, NodeName<'_>, &str>>()
+            .unwrap();
+        assert_eq!(root.name.value(), ByteStr::new(""));
+        assert_eq!(root.label, Some("root"));
+        let children = root
+            .children
+            .iter()
+            .map(|child| (child.node.value(), child.status))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            children,
+            [
+                (ByteStr::new("child"), Status::Okay),
+                (ByteStr::new("child"), Status::Disabled),
+            ]
+        );
+    }
+}
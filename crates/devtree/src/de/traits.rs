@@ -10,6 +10,18 @@ pub trait DeserializeProperty<'blob>: Sized {
         D: PropertyDeserializer<'de, 'blob> + ?Sized;
 }
 
+/// Big-endian cell value of a fixed size.
+///
+/// Arrays and tuples of the cell values are deserialized from properties of
+/// the exact total length.
+pub trait FixedSizeCell: Sized {
+    /// Size of the value in bytes.
+    const SIZE: usize;
+
+    /// Reads the value from exactly [`Self::SIZE`] bytes.
+    fn from_be_slice(bytes: &[u8]) -> Self;
+}
+
 pub trait DeserializeNode<'blob>: Sized {
     fn deserialize_node<'de, D>(de: &mut D) -> Result<Self, DeserializeError>
     where
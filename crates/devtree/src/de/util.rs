@@ -1,3 +1,5 @@
+use core::str::FromStr;
+
 use platform_cast::CastFrom as _;
 
 use super::{
@@ -33,6 +35,34 @@ where
     Ok(value)
 }
 
+/// Deserializes a string property, and parses the string with `parse`.
+///
+/// The error includes the string if `parse` fails.
+pub fn deserialize_property_with_str<'de, 'blob, D, T, E, F>(
+    de: &mut D,
+    parse: F,
+) -> Result<T, DeserializeError>
+where
+    D: PropertyDeserializer<'de, 'blob>,
+    F: FnOnce(&'blob str) -> Result<T, E>,
+{
+    let value = <&'blob str>::deserialize_property(de)?;
+    parse(value).map_err(|_e| {
+        DeserializePropertyError::unparsable_string_value(de.property(), value).into()
+    })
+}
+
+/// Deserializes a string property, and parses the string with [`FromStr`].
+///
+/// The error includes the string if it cannot be parsed.
+pub fn deserialize_property_from_str<'de, 'blob, D, T>(de: &mut D) -> Result<T, DeserializeError>
+where
+    D: PropertyDeserializer<'de, 'blob>,
+    T: FromStr,
+{
+    deserialize_property_with_str(de, str::parse)
+}
+
 pub fn deserialize_node_as_property_collection<'de, 'blob, D, T>(
     de: &mut D,
 ) -> Result<T, DeserializeError>
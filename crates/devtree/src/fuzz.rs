@@ -0,0 +1,350 @@
+//! Entry points for fuzzing the parser with arbitrary input.
+//!
+//! [`fuzz_parse`] runs every stage of the parser that reads an untrusted
+//! blob, and reports a panic in any of them as an error, so a fuzz target
+//! only has to check that the result is not [`FuzzErrorKind::Panic`].
+//!
+//! ```ignore
+//! libfuzzer_sys::fuzz_target!(|data: &[u8]| {
+//!     if let Err(err) = devtree::fuzz::fuzz_parse(data) {
+//!         assert!(!err.kind().is_panic(), "{err}");
+//!     }
+//! });
+//! ```
+
+extern crate alloc;
+extern crate std;
+
+use alloc::{borrow::ToOwned as _, string::String};
+use core::any::Any;
+use std::panic;
+
+use crate::{
+    Devicetree,
+    blob::{DEVICETREE_ALIGNMENT, error::ReadDevicetreeError},
+    de::{DeserializeProperty, error::DeserializeError},
+    model::{
+        node::{InterruptGeneratingDevice, NodePath},
+        property::{
+            AddressCells, Compatible, InterruptCells, Model, Phandle, Ranges, Reg, SizeCells,
+            Status, StrList,
+        },
+    },
+    token_cursor::{TokenCursor as _, error::ReadTokenError},
+    tree_cursor::{TreeCursor, TreeItemRef, TreePropertyRef, error::ReadTreeError},
+    util::{AlignedByteBuffer, NodePathBuf},
+};
+
+#[derive(Debug, derive_more::Display, derive_more::Error, derive_more::IsVariant)]
+#[non_exhaustive]
+pub enum FuzzErrorKind {
+    #[display("failed to read devicetree blob")]
+    ReadDevicetree {
+        #[error(source)]
+        source: ReadDevicetreeError,
+    },
+    #[display("failed to read DTB token")]
+    ReadToken {
+        #[error(source)]
+        source: ReadTokenError,
+    },
+    #[display("failed to read devicetree")]
+    ReadTree {
+        #[error(source)]
+        source: ReadTreeError,
+    },
+    #[display("parser panicked: {message}")]
+    Panic {
+        #[error(not(source))]
+        message: String,
+    },
+}
+
+define_error!(
+    /// The error type returned by [`fuzz_parse`].
+    pub struct FuzzError {
+        kind: FuzzErrorKind,
+    }
+);
+
+/// Counts of the items read from a blob by [`fuzz_parse`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FuzzSummary {
+    pub reserved_entries: usize,
+    pub tokens: usize,
+    pub nodes: usize,
+    pub properties: usize,
+    /// Number of the typed deserializations that returned an error.
+    pub rejected: usize,
+}
+
+/// Parses an arbitrary blob with every stage of the parser.
+///
+/// The bytes are copied into an aligned buffer, so the input can be of any
+/// alignment. The header, the memory reservation block, the token cursor and
+/// the tree cursor are run over the blob, and the well-known properties and
+/// nodes are deserialized into their typed models.
+///
+/// Errors of the blob structure are returned, while the errors of the typed
+/// deserialization are only counted in [`FuzzSummary::rejected`], as a
+/// structurally valid blob may well contain malformed property values.
+///
+/// # Errors
+///
+/// Returns an error if the blob is malformed, or [`FuzzErrorKind::Panic`] if
+/// the parser panics, which is always a bug of this crate.
+pub fn fuzz_parse(bytes: &[u8]) -> Result<FuzzSummary, FuzzError> {
+    let buffer = AlignedByteBuffer::<DEVICETREE_ALIGNMENT>::from_slice(bytes);
+    panic::catch_unwind(|| parse(&buffer)).unwrap_or_else(|payload| {
+        Err(FuzzErrorKind::Panic {
+            message: panic_message(&*payload),
+        }
+        .into())
+    })
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        return (*message).to_owned();
+    }
+    if let Some(message) = payload.downcast_ref::<String>() {
+        return message.clone();
+    }
+    "non-string panic payload".to_owned()
+}
+
+fn parse(bytes: &[u8]) -> Result<FuzzSummary, FuzzError> {
+    let mut summary = FuzzSummary::default();
+
+    let dt =
+        Devicetree::from_bytes(bytes).map_err(|source| FuzzErrorKind::ReadDevicetree { source })?;
+    summary.reserved_entries = dt.memory_reservation_map().len();
+
+    let mut token_cursor = dt.token_cursor();
+    while token_cursor
+        .read_token()
+        .map_err(|source| FuzzErrorKind::ReadToken { source })?
+        .is_some()
+    {
+        summary.tokens += 1;
+    }
+
+    let mut tree_cursor = dt
+        .tree_cursor()
+        .map_err(|source| FuzzErrorKind::ReadTree { source })?;
+    let mut path = NodePathBuf::<256>::new();
+    deserialize_node(&tree_cursor, &mut path, &mut summary);
+    while let Some(item) = tree_cursor
+        .read_tree_item_ref_descend()
+        .map_err(|source| FuzzErrorKind::ReadTree { source })?
+    {
+        match item {
+            TreeItemRef::Node(node) => {
+                deserialize_node(node.tree_cursor(), &mut path, &mut summary);
+            }
+            TreeItemRef::Property(property) => deserialize_property(&property, &mut summary),
+        }
+    }
+
+    Ok(summary)
+}
+
+fn deserialize_node<'blob, TC, const N: usize>(
+    tree_cursor: &TC,
+    path: &mut NodePathBuf<N>,
+    summary: &mut FuzzSummary,
+) where
+    TC: TreeCursor<'blob>,
+{
+    summary.nodes += 1;
+    // a path longer than the buffer is not an error of the blob
+    let _ = tree_cursor.path_into(path);
+    if let Some(mut cursor) = tree_cursor.try_clone() {
+        summary.count(cursor.read_node().deserialize_node::<NodePath>());
+    }
+    if let Some(mut cursor) = tree_cursor.try_clone() {
+        let mut node = cursor.read_node();
+        let has_interrupts = ["interrupts", "interrupts-extended"]
+            .into_iter()
+            .any(|name| matches!(node.read_property::<()>(name), Ok(Some(()))));
+        if has_interrupts {
+            summary.count(node.deserialize_node::<InterruptGeneratingDevice<'_>>());
+        }
+    }
+}
+
+fn deserialize_property<'blob, TC>(
+    property: &TreePropertyRef<'_, 'blob, TC>,
+    summary: &mut FuzzSummary,
+) where
+    TC: TreeCursor<'blob>,
+{
+    fn de<'blob, T, TC>(property: &TreePropertyRef<'_, 'blob, TC>) -> Result<T, DeserializeError>
+    where
+        T: DeserializeProperty<'blob>,
+        TC: TreeCursor<'blob>,
+    {
+        T::deserialize_property(&mut property.property_deserializer())
+    }
+
+    summary.properties += 1;
+
+    match &**property.property().name() {
+        b"compatible" => {
+            summary.count(de::<Compatible<'_>, _>(property));
+        }
+        b"model" => {
+            summary.count(de::<Model<'_>, _>(property));
+        }
+        b"status" => {
+            summary.count(de::<Status, _>(property));
+        }
+        b"phandle" | b"interrupt-parent" => {
+            summary.count(de::<Phandle, _>(property));
+        }
+        b"#address-cells" => {
+            summary.count(de::<AddressCells, _>(property));
+        }
+        b"#size-cells" => {
+            summary.count(de::<SizeCells, _>(property));
+        }
+        b"#interrupt-cells" => {
+            summary.count(de::<InterruptCells, _>(property));
+        }
+        b"reg" => {
+            if let Some(reg) = summary.count(de::<Reg<'_>, _>(property)) {
+                for value in reg {
+                    let _ = value.range();
+                }
+            }
+        }
+        b"ranges" | b"dma-ranges" => {
+            if let Some(ranges) = summary.count(de::<Ranges<'_>, _>(property)) {
+                ranges.into_iter().for_each(drop);
+            }
+        }
+        _ => {
+            summary.count(de::<StrList<'_>, _>(property));
+        }
+    }
+}
+
+impl FuzzSummary {
+    fn count<T>(&mut self, res: Result<T, DeserializeError>) -> Option<T> {
+        if res.is_err() {
+            self.rejected += 1;
+        }
+        res.ok()
+    }
+}
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, BlobBuilder, BlockBuilder};
+
+    static QEMU_VIRT: &[u8] = include_bytes!("../examples/assets/qemu-virt.dtb");
+    static QEMU_VIRT_OPENSBI: &[u8] = include_bytes!("../examples/assets/qemu-virt-opensbi.dtb");
+
+    fn assert_no_panic(bytes: &[u8]) {
+        if let Err(err) = fuzz_parse(bytes) {
+            assert!(!err.kind().is_panic(), "{err}");
+        }
+    }
+
+    #[test]
+    fn test_fuzz_parse_valid_blobs() {
+        for blob in [QEMU_VIRT, QEMU_VIRT_OPENSBI] {
+            let summary = fuzz_parse(blob).unwrap();
+            assert!(summary.nodes > 0);
+            assert!(summary.properties > 0);
+            assert!(summary.tokens >= 2 * summary.nodes + summary.properties);
+        }
+    }
+
+    #[test]
+    fn test_fuzz_parse_unaligned_input() {
+        let mut bytes = alloc::vec![0; QEMU_VIRT.len() + 1];
+        bytes[1..].copy_from_slice(QEMU_VIRT);
+        assert_eq!(
+            fuzz_parse(&bytes[1..]).unwrap(),
+            fuzz_parse(QEMU_VIRT).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_fuzz_parse_rejects_garbage() {
+        let err = fuzz_parse(&[]).unwrap_err();
+        assert!(err.kind().is_read_devicetree());
+        let err = fuzz_parse(&[0xff; 64]).unwrap_err();
+        assert!(err.kind().is_read_devicetree());
+    }
+
+    #[test]
+    fn test_fuzz_parse_counts_rejected_properties() {
+        let mut block = BlockBuilder::new();
+        block
+            .begin_node(b"")
+            .prop(b"#address-cells", &[0, 0])
+            .prop(b"status", b"broken\0")
+            .end_node()
+            .end();
+        let blob = testing::blob_from_block(&block);
+        let summary = fuzz_parse(&blob).unwrap();
+        assert_eq!(summary.nodes, 1);
+        assert_eq!(summary.properties, 2);
+        assert_eq!(summary.rejected, 2);
+    }
+
+    #[test]
+    fn test_panic_message() {
+        let payload: std::boxed::Box<dyn Any + Send> = std::boxed::Box::new("static");
+        assert_eq!(panic_message(&*payload), "static");
+        let payload: std::boxed::Box<dyn Any + Send> = std::boxed::Box::new(String::from("owned"));
+        assert_eq!(panic_message(&*payload), "owned");
+        let payload: std::boxed::Box<dyn Any + Send> = std::boxed::Box::new(0);
+        assert_eq!(panic_message(&*payload), "non-string panic payload");
+    }
+
+    #[test]
+    fn test_fuzz_parse_seed_corpus() {
+        for blob in testing::seed_corpus() {
+            assert_no_panic(&blob);
+        }
+    }
+
+    #[test]
+    fn test_fuzz_parse_truncations() {
+        for blob in [QEMU_VIRT, QEMU_VIRT_OPENSBI] {
+            for bytes in testing::truncations(blob, 4) {
+                assert_no_panic(&bytes);
+            }
+        }
+    }
+
+    #[test]
+    fn test_fuzz_parse_bit_flips() {
+        for bytes in testing::bit_flips(QEMU_VIRT, 13) {
+            assert_no_panic(&bytes);
+        }
+    }
+
+    #[test]
+    fn test_fuzz_parse_word_replacements() {
+        for bytes in testing::word_replacements(QEMU_VIRT) {
+            assert_no_panic(&bytes);
+        }
+        for blob in testing::seed_corpus() {
+            for bytes in testing::word_replacements(&blob) {
+                assert_no_panic(&bytes);
+            }
+        }
+    }
+
+    #[test]
+    fn test_blob_builder_output_is_parsable() {
+        let blob = BlobBuilder::new().build();
+        assert_no_panic(&blob);
+    }
+}
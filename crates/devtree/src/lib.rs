@@ -19,9 +19,13 @@ pub mod blob;
 mod bytes;
 pub mod de;
 mod debug;
+#[cfg(feature = "fuzz")]
+pub mod fuzz;
 pub mod model;
 pub mod node_stack;
 mod polyfill;
+#[cfg(feature = "schema")]
+pub mod schema;
 #[cfg(feature = "testing")]
 pub mod testing;
 pub mod token_cursor;
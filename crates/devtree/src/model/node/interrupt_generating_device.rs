@@ -10,7 +10,7 @@ use crate::{
         DeserializeNode, DeserializeProperty as _, NodeDeserializer, PropertyDeserializer as _,
         error::{DeserializeError, DeserializeNodeError, DeserializePropertyError},
     },
-    model::property::{InterruptCells, Phandle, U32Array},
+    model::property::{CellReader, InterruptCells, Phandle, U32Array},
     tree_cursor::TreeCursor as _,
     types::{ByteStr, ByteString},
 };
@@ -88,20 +88,18 @@ impl<'blob> DeserializeNode<'blob> for InterruptGeneratingDevice<'blob> {
                         Some((Phandle::deserialize_property(&mut sub_de)?, property));
                 }
                 b"interrupts-extended" => {
-                    interrupts_extended =
-                        Some((<&[[u8; 4]]>::deserialize_property(&mut sub_de)?, property));
+                    interrupts_extended = Some(CellReader::deserialize_property(&mut sub_de)?);
                 }
                 _ => {}
             }
             Ok(())
         })?;
 
-        if let Some((mut chunks, property)) = interrupts_extended {
+        if let Some(mut cells) = interrupts_extended {
             let mut interrupts = Vec::new();
 
-            while let Some((phandle, rest)) = chunks.split_first() {
-                let phandle = Phandle::new(u32::from_be_bytes(*phandle));
-                chunks = rest;
+            while !cells.is_empty() {
+                let phandle = Phandle::new(cells.read_u32()?);
 
                 let mut root_cursor = de.clone_tree_cursor()?;
                 root_cursor.seek_root_start();
@@ -113,17 +111,7 @@ impl<'blob> DeserializeNode<'blob> for InterruptGeneratingDevice<'blob> {
                     .ok_or_else(|| DeserializeError::missing_phandle_node(phandle))?
                     .deserialize_node()?;
 
-                let (specifier, rest) = chunks
-                    .split_at_checked(interrupt_cells.value())
-                    .ok_or_else(|| {
-                        DeserializePropertyError::custom(
-                            &property,
-                            "invalid property value length of `interrupts-extended`",
-                        )
-                    })?;
-                chunks = rest;
-
-                let specifier = U32Array::new(specifier);
+                let specifier = cells.read_cells(interrupt_cells.value())?;
                 interrupts.push(Interrupt::new(path.0, specifier));
             }
 
@@ -150,6 +138,13 @@ impl<'blob> DeserializeNode<'blob> for InterruptGeneratingDevice<'blob> {
                     .deserialize_node()?,
             };
 
+            ensure!(
+                interrupt_cells.value() > 0,
+                DeserializePropertyError::custom(
+                    &interrupts_property,
+                    "`#interrupt-cells` of the interrupt parent is zero",
+                )
+            );
             ensure!(
                 chunks.len().is_multiple_of(interrupt_cells.value()),
                 DeserializePropertyError::custom(
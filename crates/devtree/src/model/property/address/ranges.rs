@@ -112,7 +112,7 @@ pub(crate) mod iter {
     use core::iter::FusedIterator;
 
     use super::Ranges;
-    use crate::model::property::{AddressCells, SizeCells, U32Array};
+    use crate::model::property::{AddressCells, CellReader, SizeCells, U32Array};
 
     #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
     pub struct RangesValue<'blob> {
@@ -126,7 +126,7 @@ pub(crate) mod iter {
         child_address_cells: AddressCells,
         child_size_cells: SizeCells,
         parent_address_cells: AddressCells,
-        value: &'blob [[u8; 4]],
+        reader: CellReader<'blob>,
     }
 
     impl<'blob> RangesIter<'blob> {
@@ -135,7 +135,7 @@ pub(crate) mod iter {
                 child_address_cells: ranges.child_address_cells,
                 child_size_cells: ranges.child_size_cells,
                 parent_address_cells: ranges.parent_address_cells,
-                value: ranges.value,
+                reader: CellReader::new(ranges.value),
             }
         }
     }
@@ -144,18 +144,14 @@ pub(crate) mod iter {
         type Item = RangesValue<'blob>;
 
         fn next(&mut self) -> Option<Self::Item> {
-            if self.value.is_empty() {
+            if self.reader.is_empty() {
                 return None;
             }
 
-            let value = self.value;
-            let (child_bus_address, value) = value.split_at(self.child_address_cells.value());
-            let (parent_bus_address, value) = value.split_at(self.parent_address_cells.value());
-            let (len, value) = value.split_at(self.child_size_cells.value());
-            self.value = value;
-            let child_bus_address = U32Array::new(child_bus_address);
-            let parent_bus_address = U32Array::new(parent_bus_address);
-            let len = U32Array::new(len);
+            let reader = &mut self.reader;
+            let child_bus_address = reader.read_cells(self.child_address_cells.value()).ok()?;
+            let parent_bus_address = reader.read_cells(self.parent_address_cells.value()).ok()?;
+            let len = reader.read_cells(self.child_size_cells.value()).ok()?;
 
             Some(RangesValue {
                 child_bus_address,
@@ -165,7 +161,7 @@ pub(crate) mod iter {
         }
 
         fn size_hint(&self) -> (usize, Option<usize>) {
-            let len = self.value.len()
+            let len = self.reader.remaining()
                 / (self.child_address_cells.value()
                     + self.child_size_cells.value()
                     + self.parent_address_cells.value());
@@ -175,20 +171,18 @@ pub(crate) mod iter {
 
     impl DoubleEndedIterator for RangesIter<'_> {
         fn next_back(&mut self) -> Option<Self::Item> {
-            if self.value.is_empty() {
+            if self.reader.is_empty() {
                 return None;
             }
 
-            let value = self.value;
-            let (value, len) = value.split_at(value.len() - self.child_size_cells.value());
-            let (value, parent_bus_address) =
-                value.split_at(value.len() - self.parent_address_cells.value());
-            let (value, child_bus_address) =
-                value.split_at(value.len() - self.child_address_cells.value());
-            self.value = value;
-            let child_bus_address = U32Array::new(child_bus_address);
-            let parent_bus_address = U32Array::new(parent_bus_address);
-            let len = U32Array::new(len);
+            let reader = &mut self.reader;
+            let len = reader.read_cells_back(self.child_size_cells.value()).ok()?;
+            let parent_bus_address = reader
+                .read_cells_back(self.parent_address_cells.value())
+                .ok()?;
+            let child_bus_address = reader
+                .read_cells_back(self.child_address_cells.value())
+                .ok()?;
 
             Some(RangesValue {
                 child_bus_address,
@@ -96,7 +96,7 @@ pub(crate) mod iter {
     use platform_cast::CastFrom as _;
 
     use super::Reg;
-    use crate::model::property::{AddressCells, SizeCells, U32Array};
+    use crate::model::property::{AddressCells, CellReader, SizeCells, U32Array};
 
     #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
     pub struct RegValue<'blob> {
@@ -123,7 +123,7 @@ pub(crate) mod iter {
     pub struct RegIter<'blob> {
         address_cells: AddressCells,
         size_cells: SizeCells,
-        value: &'blob [[u8; 4]],
+        reader: CellReader<'blob>,
     }
 
     impl<'blob> RegIter<'blob> {
@@ -131,7 +131,7 @@ pub(crate) mod iter {
             Self {
                 address_cells: reg.address_cells,
                 size_cells: reg.size_cells,
-                value: reg.value,
+                reader: CellReader::new(reg.value),
             }
         }
     }
@@ -140,38 +140,34 @@ pub(crate) mod iter {
         type Item = RegValue<'blob>;
 
         fn next(&mut self) -> Option<Self::Item> {
-            if self.value.is_empty() {
+            if self.reader.is_empty() {
                 return None;
             }
 
-            let value = self.value;
-            let (address, value) = value.split_at(self.address_cells.value());
-            let (size, value) = value.split_at(self.size_cells.value());
-            self.value = value;
-            let address = U32Array::new(address);
-            let size = U32Array::new(size);
+            let address = self.reader.read_cells(self.address_cells.value()).ok()?;
+            let size = self.reader.read_cells(self.size_cells.value()).ok()?;
 
             Some(RegValue { address, size })
         }
 
         fn size_hint(&self) -> (usize, Option<usize>) {
-            let len = self.value.len() / (self.address_cells.value() + self.size_cells.value());
+            let len =
+                self.reader.remaining() / (self.address_cells.value() + self.size_cells.value());
             (len, Some(len))
         }
     }
 
     impl DoubleEndedIterator for RegIter<'_> {
         fn next_back(&mut self) -> Option<Self::Item> {
-            if self.value.is_empty() {
+            if self.reader.is_empty() {
                 return None;
             }
 
-            let value = self.value;
-            let (value, size) = value.split_at(value.len() - self.size_cells.value());
-            let (value, address) = value.split_at(value.len() - self.address_cells.value());
-            self.value = value;
-            let address = U32Array::new(address);
-            let size = U32Array::new(size);
+            let size = self.reader.read_cells_back(self.size_cells.value()).ok()?;
+            let address = self
+                .reader
+                .read_cells_back(self.address_cells.value())
+                .ok()?;
 
             Some(RegValue { address, size })
         }
@@ -0,0 +1,163 @@
+use super::U32Array;
+use crate::de::{
+    DeserializeProperty, PropertyDeserializer,
+    error::{DeserializeError, DeserializePropertyError},
+};
+
+/// Reader consuming groups of cells from a property value.
+///
+/// Each read is bounds checked, and a short read reports the number of the
+/// expected and the remaining cells instead of panicking.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CellReader<'blob> {
+    cells: &'blob [[u8; 4]],
+}
+
+impl<'blob> CellReader<'blob> {
+    #[must_use]
+    pub fn new(cells: &'blob [[u8; 4]]) -> Self {
+        Self { cells }
+    }
+
+    /// Returns the number of the cells not read yet.
+    #[must_use]
+    pub fn remaining(&self) -> usize {
+        self.cells.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    /// Returns the cells not read yet.
+    #[must_use]
+    pub fn as_u32_array(&self) -> &'blob U32Array {
+        U32Array::new(self.cells)
+    }
+
+    /// Reads `count` cells from the front.
+    pub fn read_cells(
+        &mut self,
+        count: usize,
+    ) -> Result<&'blob U32Array, DeserializePropertyError> {
+        let (cells, rest) = self
+            .cells
+            .split_at_checked(count)
+            .ok_or_else(|| DeserializePropertyError::not_enough_cells(count, self.remaining()))?;
+        self.cells = rest;
+        Ok(U32Array::new(cells))
+    }
+
+    /// Reads `count` cells from the back.
+    pub fn read_cells_back(
+        &mut self,
+        count: usize,
+    ) -> Result<&'blob U32Array, DeserializePropertyError> {
+        let (rest, cells) = self
+            .remaining()
+            .checked_sub(count)
+            .map(|mid| self.cells.split_at(mid))
+            .ok_or_else(|| DeserializePropertyError::not_enough_cells(count, self.remaining()))?;
+        self.cells = rest;
+        Ok(U32Array::new(cells))
+    }
+
+    /// Reads a single cell from the front.
+    pub fn read_u32(&mut self) -> Result<u32, DeserializePropertyError> {
+        let (cell, rest) = self
+            .cells
+            .split_first()
+            .ok_or_else(|| DeserializePropertyError::not_enough_cells(1, 0))?;
+        self.cells = rest;
+        Ok(u32::from_be_bytes(*cell))
+    }
+
+    /// Returns an error if any cells are left unread.
+    pub fn finish(self) -> Result<(), DeserializePropertyError> {
+        if !self.is_empty() {
+            return Err(DeserializePropertyError::trailing_cells(self.remaining()));
+        }
+        Ok(())
+    }
+}
+
+impl<'blob> DeserializeProperty<'blob> for CellReader<'blob> {
+    fn deserialize_property<'de, D>(de: &mut D) -> Result<Self, DeserializeError>
+    where
+        D: PropertyDeserializer<'de, 'blob> + ?Sized,
+    {
+        <_>::deserialize_property(de).map(Self::new)
+    }
+}
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::de::error::DeserializePropertyErrorKind;
+
+    const CELLS: &[[u8; 4]] = &[
+        1_u32.to_be_bytes(),
+        2_u32.to_be_bytes(),
+        3_u32.to_be_bytes(),
+        4_u32.to_be_bytes(),
+        5_u32.to_be_bytes(),
+    ];
+
+    fn assert_not_enough_cells(err: &DeserializePropertyError, expected: usize, remaining: usize) {
+        let DeserializePropertyErrorKind::NotEnoughCells {
+            expected: e,
+            remaining: r,
+        } = err.kind()
+        else {
+            panic!("unexpected error: {err:?}");
+        };
+        assert_eq!((*e, *r), (expected, remaining));
+    }
+
+    #[test]
+    fn test_read_cells() {
+        let mut reader = CellReader::new(CELLS);
+        assert_eq!(reader.remaining(), 5);
+        assert_eq!(reader.read_u32().unwrap(), 1);
+        assert!(reader.read_cells(2).unwrap().iter().eq([2, 3]));
+        assert!(reader.read_cells(0).unwrap().is_empty());
+        assert_eq!(reader.remaining(), 2);
+        assert!(reader.as_u32_array().iter().eq([4, 5]));
+        assert!(reader.read_cells(2).unwrap().iter().eq([4, 5]));
+        assert!(reader.is_empty());
+        reader.finish().unwrap();
+    }
+
+    #[test]
+    fn test_read_cells_back() {
+        let mut reader = CellReader::new(CELLS);
+        assert!(reader.read_cells_back(2).unwrap().iter().eq([4, 5]));
+        assert!(reader.read_cells(1).unwrap().iter().eq([1]));
+        assert!(reader.read_cells_back(2).unwrap().iter().eq([2, 3]));
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn test_not_enough_cells() {
+        let mut reader = CellReader::new(CELLS);
+        assert_not_enough_cells(&reader.read_cells(6).unwrap_err(), 6, 5);
+        assert_not_enough_cells(&reader.read_cells_back(6).unwrap_err(), 6, 5);
+        assert_eq!(reader.remaining(), 5);
+
+        reader.read_cells(5).unwrap();
+        assert_not_enough_cells(&reader.read_u32().unwrap_err(), 1, 0);
+    }
+
+    #[test]
+    fn test_trailing_cells() {
+        let mut reader = CellReader::new(CELLS);
+        reader.read_cells(3).unwrap();
+        let err = reader.finish().unwrap_err();
+        assert!(matches!(
+            err.kind(),
+            DeserializePropertyErrorKind::TrailingCells { remaining: 2 }
+        ));
+    }
+}
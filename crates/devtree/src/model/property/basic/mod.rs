@@ -1,5 +1,6 @@
-pub use self::{property_name::*, u32_array::*};
+pub use self::{cell_reader::*, property_name::*, u32_array::*};
 
+mod cell_reader;
 mod property_name;
 mod u32_array;
 
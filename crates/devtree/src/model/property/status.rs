@@ -18,19 +18,37 @@ pub enum Status {
     FailSss,
 }
 
+impl Status {
+    /// Parses the value of the `status` property, without the trailing nul.
+    ///
+    /// The legacy value `"ok"` is accepted as [`Status::Okay`].
+    #[must_use]
+    pub fn from_bytes(value: &[u8]) -> Option<Self> {
+        let status = match value {
+            b"okay" | b"ok" => Self::Okay,
+            b"disabled" => Self::Disabled,
+            b"reserved" => Self::Reserved,
+            b"fail" => Self::Fail,
+            b"fail-sss" => Self::FailSss,
+            _ => return None,
+        };
+        Some(status)
+    }
+
+    /// Returns `true` if the device is operational.
+    #[must_use]
+    pub fn is_enabled(&self) -> bool {
+        self.is_okay()
+    }
+}
+
 impl<'blob> DeserializeProperty<'blob> for Status {
     fn deserialize_property<'de, D>(de: &mut D) -> Result<Self, DeserializeError>
     where
         D: PropertyDeserializer<'de, 'blob> + ?Sized,
     {
         let s = <&ByteStr>::deserialize_property(de)?;
-        match &**s {
-            b"okay" => Ok(Self::Okay),
-            b"disabled" => Ok(Self::Disabled),
-            b"reserved" => Ok(Self::Reserved),
-            b"fail" => Ok(Self::Fail),
-            b"fail-sss" => Ok(Self::FailSss),
-            _ => Err(DeserializePropertyError::custom(de.property(), "invalid status").into()),
-        }
+        Self::from_bytes(s)
+            .ok_or_else(|| DeserializePropertyError::custom(de.property(), "invalid status").into())
     }
 }
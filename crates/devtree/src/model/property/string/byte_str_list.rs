@@ -20,6 +20,12 @@ impl<'blob> ByteStrList<'blob> {
         Self { value }
     }
 
+    /// Returns the strings separated by NUL, without the terminating NUL.
+    #[must_use]
+    pub fn as_byte_str(&self) -> &'blob ByteStr {
+        self.value
+    }
+
     #[must_use]
     pub fn iter(&self) -> iter::ByteStrListIter<'blob> {
         self.into_iter()
@@ -61,11 +67,21 @@ pub(crate) mod iter {
     #[derive(Clone)]
     pub struct ByteStrListIter<'blob> {
         remainder: &'blob ByteStr,
+        /// Number of the strings in `remainder`, which may be empty strings.
+        len: usize,
     }
 
     impl<'blob> ByteStrListIter<'blob> {
         pub(crate) fn new(value: &'blob ByteStr) -> Self {
-            Self { remainder: value }
+            let len = if value.is_empty() {
+                0
+            } else {
+                value.split(|&b| b == 0).count()
+            };
+            Self {
+                remainder: value,
+                len,
+            }
         }
     }
 
@@ -73,21 +89,21 @@ pub(crate) mod iter {
         type Item = &'blob ByteStr;
 
         fn next(&mut self) -> Option<Self::Item> {
-            if self.remainder.is_empty() {
-                return None;
-            }
+            self.len = self.len.checked_sub(1)?;
             let (s, rest) = polyfill::slice_split_once(self.remainder, |&b| b == 0)
                 .unwrap_or((self.remainder, &[]));
             self.remainder = ByteStr::new(rest);
             Some(ByteStr::new(s))
         }
+
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            (self.len, Some(self.len))
+        }
     }
 
     impl DoubleEndedIterator for ByteStrListIter<'_> {
         fn next_back(&mut self) -> Option<Self::Item> {
-            if self.remainder.is_empty() {
-                return None;
-            }
+            self.len = self.len.checked_sub(1)?;
             let (rest, s) = polyfill::slice_rsplit_once(self.remainder, |&b| b == 0)
                 .unwrap_or((&[], self.remainder));
             self.remainder = ByteStr::new(rest);
@@ -95,5 +111,25 @@ pub(crate) mod iter {
         }
     }
 
+    impl ExactSizeIterator for ByteStrListIter<'_> {}
     impl FusedIterator for ByteStrListIter<'_> {}
 }
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_iter_both_ends() {
+        let list = ByteStrList::new(ByteStr::new(b"virtio,mmio\0\0simple-bus"));
+        let mut iter = list.iter();
+        assert_eq!(iter.len(), 3);
+        assert_eq!(iter.next_back(), Some(ByteStr::new("simple-bus")));
+        assert_eq!(iter.len(), 2);
+        assert_eq!(iter.next(), Some(ByteStr::new("virtio,mmio")));
+        assert_eq!(iter.next_back(), Some(ByteStr::new("")));
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.len(), 0);
+    }
+}
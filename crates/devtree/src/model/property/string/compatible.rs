@@ -1,4 +1,4 @@
-use core::fmt;
+use core::{cmp::Ordering, fmt};
 
 use super::ByteStrList;
 use crate::{
@@ -30,6 +30,275 @@ impl<'blob> Compatible<'blob> {
         let model = model.as_ref();
         self.value.iter().any(|c| c == model)
     }
+
+    /// Returns `true` if any of the `compatible` strings matches the
+    /// pattern.
+    ///
+    /// The strings are compared ignoring the ASCII case and the surrounding
+    /// whitespace. A pattern of the form `"*,model"` matches `model` of any
+    /// vendor, including a string without the vendor prefix.
+    pub fn matches<B>(&self, pattern: B) -> bool
+    where
+        B: AsRef<ByteStr>,
+    {
+        self.match_score(&[pattern]).is_some()
+    }
+
+    /// Returns the index of the pattern matching the most specific
+    /// `compatible` string.
+    ///
+    /// See [`Self::match_score`] for the precedence of the matches.
+    pub fn best_match<B>(&self, patterns: &[B]) -> Option<usize>
+    where
+        B: AsRef<ByteStr>,
+    {
+        self.match_scores(patterns)
+            .reduce(|best, (i, score)| if score > best.1 { (i, score) } else { best })
+            .map(|(i, _score)| i)
+    }
+
+    /// Returns the score of the best match between the `compatible` strings
+    /// and the patterns, or `None` if no pattern matches.
+    ///
+    /// A match with an earlier, more specific `compatible` string is scored
+    /// higher, and an exact match is scored higher than a vendor wildcard
+    /// match of the same string. Comparing the scores of several drivers
+    /// selects the driver for the node.
+    pub fn match_score<B>(&self, patterns: &[B]) -> Option<MatchScore>
+    where
+        B: AsRef<ByteStr>,
+    {
+        self.match_scores(patterns).map(|(_i, score)| score).max()
+    }
+
+    /// Returns the score of the best match between the `compatible` strings
+    /// and the patterns of `table`, or `None` if no pattern matches.
+    ///
+    /// This is the same as [`Self::match_score`] with the patterns of the
+    /// table.
+    #[must_use]
+    pub fn match_table(&self, table: &CompatibleTable) -> Option<MatchScore> {
+        matches_table(self.value.as_byte_str(), table)
+    }
+
+    fn match_scores<'a, B>(
+        &'a self,
+        patterns: &'a [B],
+    ) -> impl Iterator<Item = (usize, MatchScore)> + 'a
+    where
+        B: AsRef<ByteStr>,
+    {
+        self.value
+            .iter()
+            .enumerate()
+            .flat_map(move |(index, model)| {
+                patterns.iter().enumerate().filter_map(move |(i, pattern)| {
+                    let wildcard = match_pattern(model, pattern.as_ref())?;
+                    Some((i, MatchScore { index, wildcard }))
+                })
+            })
+    }
+}
+
+/// Table of the `compatible` patterns of a driver, checked at compile time.
+///
+/// Created by the [`compatible_table!`](crate::compatible_table) macro.
+#[derive(Debug, Clone, Copy)]
+pub struct CompatibleTable {
+    patterns: &'static [&'static str],
+}
+
+impl CompatibleTable {
+    /// Creates a table of `patterns`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a pattern is empty, has surrounding whitespace or a NUL
+    /// character, or appears twice. In const context, the panic is a compile
+    /// error.
+    #[must_use]
+    pub const fn new(patterns: &'static [&'static str]) -> Self {
+        let mut i = 0;
+        while i < patterns.len() {
+            let pattern = patterns[i].as_bytes();
+            assert!(!pattern.is_empty(), "empty compatible pattern");
+            assert!(
+                pattern.trim_ascii().len() == pattern.len(),
+                "compatible pattern with surrounding whitespace"
+            );
+            let mut j = 0;
+            while j < pattern.len() {
+                assert!(pattern[j] != 0, "compatible pattern with NUL");
+                j += 1;
+            }
+            let mut j = 0;
+            while j < i {
+                assert!(
+                    !pattern.eq_ignore_ascii_case(patterns[j].as_bytes()),
+                    "duplicate compatible pattern"
+                );
+                j += 1;
+            }
+            i += 1;
+        }
+        Self { patterns }
+    }
+
+    #[must_use]
+    pub const fn patterns(&self) -> &'static [&'static str] {
+        self.patterns
+    }
+
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+}
+
+/// Creates a [`CompatibleTable`] of the patterns, checked at compile time.
+///
+/// # Examples
+///
+/// ```
+/// use devtree::{compatible_table, model::property::matches_table};
+///
+/// static TABLE: devtree::model::property::CompatibleTable =
+///     compatible_table!["sifive,uart0", "*,ns16550a"];
+///
+/// const SCORE: Option<devtree::model::property::MatchScore> =
+///     matches_table(b"sifive,fu540-c000-uart\0sifive,uart0", &TABLE);
+/// assert_eq!(SCORE.map(|score| score.index()), Some(1));
+/// ```
+#[macro_export]
+macro_rules! compatible_table {
+    ($($pattern:expr),* $(,)?) => {
+        const { $crate::model::property::CompatibleTable::new(&[$($pattern),*]) }
+    };
+}
+
+/// Returns the score of the best match between the `compatible` property
+/// value and the patterns of `table`, or `None` if no pattern matches.
+///
+/// `compatible` is the list of the strings separated by NUL, as the value of
+/// the property. This is usable in const context, and does not allocate.
+///
+/// See [`Compatible::match_score`] for the precedence of the matches.
+#[must_use]
+pub const fn matches_table(compatible: &[u8], table: &CompatibleTable) -> Option<MatchScore> {
+    let mut rest = compatible;
+    let mut index = 0;
+    while !rest.is_empty() {
+        let (model, next) = split_nul(rest);
+        let mut wildcard_match = false;
+        let mut i = 0;
+        while i < table.patterns.len() {
+            match match_pattern(model, table.patterns[i].as_bytes()) {
+                Some(false) => {
+                    return Some(MatchScore {
+                        index,
+                        wildcard: false,
+                    });
+                }
+                Some(true) => wildcard_match = true,
+                None => {}
+            }
+            i += 1;
+        }
+        if wildcard_match {
+            return Some(MatchScore {
+                index,
+                wildcard: true,
+            });
+        }
+        rest = next;
+        index += 1;
+    }
+    None
+}
+
+/// Splits off the string before the first NUL.
+const fn split_nul(bytes: &[u8]) -> (&[u8], &[u8]) {
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == 0 {
+            let (head, tail) = bytes.split_at(i);
+            return (head, tail.split_at(1).1);
+        }
+        i += 1;
+    }
+    (bytes, &[])
+}
+
+/// Returns whether the pattern matches by a vendor wildcard, or `None` if it
+/// does not match.
+const fn match_pattern(model: &[u8], pattern: &[u8]) -> Option<bool> {
+    let model = model.trim_ascii();
+    let pattern = pattern.trim_ascii();
+    if let [b'*', b',', pattern @ ..] = pattern {
+        let model = strip_vendor(model);
+        return if model.eq_ignore_ascii_case(pattern) {
+            Some(true)
+        } else {
+            None
+        };
+    }
+    if model.eq_ignore_ascii_case(pattern) {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+/// Strips the vendor prefix up to the first comma, if any.
+const fn strip_vendor(model: &[u8]) -> &[u8] {
+    let mut i = 0;
+    while i < model.len() {
+        if model[i] == b',' {
+            return model.split_at(i + 1).1;
+        }
+        i += 1;
+    }
+    model
+}
+
+/// Precedence of a match between `compatible` strings and a pattern.
+///
+/// A better match compares greater.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MatchScore {
+    /// Index of the matched `compatible` string.
+    index: usize,
+    /// Whether the pattern matched by a vendor wildcard.
+    wildcard: bool,
+}
+
+impl MatchScore {
+    /// Returns the index of the matched `compatible` string.
+    #[must_use]
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns `true` if the pattern matched by a vendor wildcard.
+    #[must_use]
+    pub fn is_wildcard(&self) -> bool {
+        self.wildcard
+    }
+}
+
+impl PartialOrd for MatchScore {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for MatchScore {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .index
+            .cmp(&self.index)
+            .then(other.wildcard.cmp(&self.wildcard))
+    }
 }
 
 impl<'blob> DeserializeProperty<'blob> for Compatible<'blob> {
@@ -40,3 +309,97 @@ impl<'blob> DeserializeProperty<'blob> for Compatible<'blob> {
         <_>::deserialize_property(de).map(Self::new)
     }
 }
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compatible(value: &[u8]) -> Compatible<'_> {
+        Compatible::new(ByteStrList::new(ByteStr::new(value)))
+    }
+
+    #[test]
+    fn test_matches() {
+        let c = compatible(b"sifive,fu540-c000-uart\0sifive,uart0");
+        assert!(c.matches("sifive,uart0"));
+        assert!(c.matches("SiFive,UART0"));
+        assert!(c.matches(" sifive,uart0 "));
+        assert!(c.matches("*,uart0"));
+        assert!(!c.matches("uart0"));
+        assert!(!c.matches("sifive,uart"));
+        assert!(!c.matches("*,sifive"));
+    }
+
+    #[test]
+    fn test_wildcard_without_vendor() {
+        let c = compatible(b"ns16550a");
+        assert!(c.matches("*,ns16550a"));
+        assert!(c.matches("ns16550a"));
+        assert!(!c.matches("*,ns16550"));
+    }
+
+    #[test]
+    fn test_best_match() {
+        let c = compatible(b"sifive,fu540-c000-uart\0sifive,uart0\0ns16550a");
+        assert_eq!(c.best_match(&["ns16550a", "sifive,uart0"]), Some(1));
+        assert_eq!(
+            c.best_match(&["*,uart0", "sifive,fu540-c000-uart"]),
+            Some(1)
+        );
+        assert_eq!(c.best_match(&["*,uart0", "sifive,uart0"]), Some(1));
+        assert_eq!(c.best_match(&["sifive,uart0", "sifive,uart0"]), Some(0));
+        assert_eq!(c.best_match(&["arm,pl011"]), None);
+        assert_eq!(c.best_match::<&str>(&[]), None);
+    }
+
+    #[test]
+    fn test_matches_table() {
+        const TABLE: CompatibleTable = compatible_table!["*,uart0", "sifive,uart0", "ns16550a"];
+        const SCORE: Option<MatchScore> =
+            matches_table(b"sifive,fu540-c000-uart\0sifive,uart0\0ns16550a", &TABLE);
+        assert_eq!(
+            SCORE,
+            Some(MatchScore {
+                index: 1,
+                wildcard: false
+            })
+        );
+
+        let c = compatible(b"sifive,fu540-c000-uart\0sifive,uart0");
+        assert_eq!(c.match_table(&TABLE), c.match_score(TABLE.patterns()));
+        let c = compatible(b"acme,uart0\0ns16550a");
+        let score = c.match_table(&TABLE).unwrap();
+        assert_eq!(score.index(), 0);
+        assert!(score.is_wildcard());
+        assert_eq!(matches_table(b"arm,pl011", &TABLE), None);
+        assert_eq!(matches_table(b"", &TABLE), None);
+        assert_eq!(matches_table(b"ns16550a", &compatible_table![]), None);
+    }
+
+    #[test]
+    #[should_panic = "duplicate compatible pattern"]
+    fn test_compatible_table_duplicate() {
+        let _ = CompatibleTable::new(&["ns16550a", "NS16550A"]);
+    }
+
+    #[test]
+    #[should_panic = "surrounding whitespace"]
+    fn test_compatible_table_whitespace() {
+        let _ = CompatibleTable::new(&[" ns16550a"]);
+    }
+
+    #[test]
+    fn test_match_score() {
+        let c = compatible(b"sifive,fu540-c000-uart\0sifive,uart0");
+        let exact = c.match_score(&["sifive,uart0"]).unwrap();
+        let wildcard = c.match_score(&["*,uart0"]).unwrap();
+        let specific = c.match_score(&["*,fu540-c000-uart"]).unwrap();
+        assert_eq!(exact.index(), 1);
+        assert!(!exact.is_wildcard());
+        assert!(wildcard.is_wildcard());
+        assert!(exact > wildcard);
+        assert!(specific > exact);
+        assert_eq!(c.match_score(&["ns16550a"]), None);
+    }
+}
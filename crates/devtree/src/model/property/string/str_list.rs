@@ -60,11 +60,21 @@ pub(crate) mod iter {
     #[derive(Clone)]
     pub struct StrListIter<'blob> {
         remainder: &'blob str,
+        /// Number of the strings in `remainder`, which may be empty strings.
+        len: usize,
     }
 
     impl<'blob> StrListIter<'blob> {
         pub(crate) fn new(value: &'blob str) -> Self {
-            Self { remainder: value }
+            let len = if value.is_empty() {
+                0
+            } else {
+                value.split('\0').count()
+            };
+            Self {
+                remainder: value,
+                len,
+            }
         }
     }
 
@@ -72,10 +82,7 @@ pub(crate) mod iter {
         type Item = &'blob str;
 
         fn next(&mut self) -> Option<Self::Item> {
-            if self.remainder.is_empty() {
-                return None;
-            }
-
+            self.len = self.len.checked_sub(1)?;
             let (s, rest) = self
                 .remainder
                 .split_once('\0')
@@ -83,14 +90,15 @@ pub(crate) mod iter {
             self.remainder = rest;
             Some(s)
         }
+
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            (self.len, Some(self.len))
+        }
     }
 
     impl DoubleEndedIterator for StrListIter<'_> {
         fn next_back(&mut self) -> Option<Self::Item> {
-            if self.remainder.is_empty() {
-                return None;
-            }
-
+            self.len = self.len.checked_sub(1)?;
             let (rest, s) = self
                 .remainder
                 .rsplit_once('\0')
@@ -100,5 +108,30 @@ pub(crate) mod iter {
         }
     }
 
+    impl ExactSizeIterator for StrListIter<'_> {}
     impl FusedIterator for StrListIter<'_> {}
 }
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+#[cfg(test)]
+mod tests {
+    extern crate alloc;
+
+    use alloc::vec::Vec;
+
+    use super::*;
+
+    #[test]
+    fn test_iter_both_ends() {
+        let list = StrList::new("a\0\0bc\0");
+        let mut iter = list.iter();
+        assert_eq!(iter.len(), 4);
+        assert_eq!(iter.next_back(), Some(""));
+        assert_eq!(iter.next(), Some("a"));
+        assert_eq!(iter.len(), 2);
+        assert_eq!(iter.collect::<Vec<_>>(), ["", "bc"]);
+        assert_eq!(list.iter().rev().collect::<Vec<_>>(), ["", "bc", "", "a"]);
+        assert_eq!(StrList::new("").iter().len(), 0);
+        assert_eq!(StrList::new("\0").iter().collect::<Vec<_>>(), ["", ""]);
+    }
+}
@@ -0,0 +1,307 @@
+//! Descriptions of the nodes expected by the deserializable types.
+//!
+//! With the `schema` feature, [`#[derive(DeserializeNode)]`][derive] also
+//! implements [`NodeSchema`] for the struct, describing the properties and
+//! the child nodes it reads. Tools can use the description to check a
+//! devicetree blob against the expectations of the types, or to generate the
+//! documentation of the bindings, without deserializing the blob.
+//!
+//! ```rust
+//! use devtree::{
+//!     DeserializeNode,
+//!     model::property::{Compatible, Reg},
+//!     schema::NodeSchema as _,
+//! };
+//!
+//! #[derive(DeserializeNode)]
+//! struct Device<'blob> {
+//!     #[devtree(property)]
+//!     compatible: Compatible<'blob>,
+//!     #[devtree(property(default))]
+//!     reg: Option<Reg<'blob>>,
+//! }
+//!
+//! let schema = Device::NODE_SCHEMA;
+//! assert_eq!(schema.type_name, "Device");
+//! assert_eq!(schema.properties[0].name, "compatible");
+//! assert!(schema.properties[0].required);
+//! assert_eq!(schema.properties[1].type_name, "Option<Reg<'blob>>");
+//! assert!(!schema.properties[1].required);
+//! ```
+//!
+//! [derive]: crate::DeserializeNode
+
+use core::fmt;
+
+/// Types with the description of the node they are deserialized from.
+pub trait NodeSchema {
+    /// Description of the node.
+    const NODE_SCHEMA: NodeDesc;
+}
+
+/// Description of a node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NodeDesc {
+    /// Name of the Rust type, without the module path.
+    pub type_name: &'static str,
+    /// Properties read from the node, in the field order.
+    pub properties: &'static [PropertyDesc],
+    /// Child nodes read from the node, in the field order.
+    pub children: &'static [ChildDesc],
+    /// Types the node itself is also deserialized into, by the
+    /// `#[devtree(node)]` fields.
+    pub node_types: &'static [&'static str],
+    /// Whether the properties not listed in [`Self::properties`] are
+    /// collected, rather than ignored.
+    pub extra_properties: bool,
+    /// Whether the child nodes not listed in [`Self::children`] are
+    /// collected, rather than ignored.
+    pub extra_children: bool,
+}
+
+/// Description of a property.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PropertyDesc {
+    pub name: &'static str,
+    /// Rust type the value is deserialized into.
+    pub type_name: &'static str,
+    /// Whether the deserialization fails if the property is missing.
+    pub required: bool,
+    /// Whether the property is read from the parent node if missing.
+    pub inherited: bool,
+}
+
+/// Description of a child node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChildDesc {
+    /// Name of the node, without the unit address.
+    pub name: &'static str,
+    /// Rust type the node is deserialized into.
+    pub type_name: &'static str,
+    /// Whether the deserialization fails if the node is missing.
+    pub required: bool,
+    /// Whether the nodes of the name can appear more than once.
+    pub repeated: bool,
+}
+
+impl NodeDesc {
+    /// Returns the property of the name.
+    #[must_use]
+    pub fn property(&self, name: &str) -> Option<&'static PropertyDesc> {
+        self.properties
+            .iter()
+            .find(|property| property.name == name)
+    }
+
+    /// Returns the child node of the name.
+    #[must_use]
+    pub fn child(&self, name: &str) -> Option<&'static ChildDesc> {
+        self.children.iter().find(|child| child.name == name)
+    }
+}
+
+/// Writes the description as a Markdown list, for the binding documents.
+impl fmt::Display for NodeDesc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn presence(required: bool) -> &'static str {
+            if required { "required" } else { "optional" }
+        }
+
+        writeln!(f, "`{}`", self.type_name)?;
+        for property in self.properties {
+            write!(
+                f,
+                "- property `{}`: `{}`, {}",
+                property.name,
+                property.type_name,
+                presence(property.required)
+            )?;
+            if property.inherited {
+                f.write_str(", inherited from the parent")?;
+            }
+            writeln!(f)?;
+        }
+        if self.extra_properties {
+            writeln!(f, "- other properties")?;
+        }
+        for child in self.children {
+            write!(
+                f,
+                "- child `{}`: `{}`, {}",
+                child.name,
+                child.type_name,
+                presence(child.required)
+            )?;
+            if child.repeated {
+                f.write_str(", repeated")?;
+            }
+            writeln!(f)?;
+        }
+        if self.extra_children {
+            writeln!(f, "- other children")?;
+        }
+        for node_type in self.node_types {
+            writeln!(f, "- node as `{node_type}`")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+#[cfg(test)]
+mod tests {
+    extern crate alloc;
+
+    use alloc::{collections::BTreeMap, string::ToString as _, vec::Vec};
+
+    use super::*;
+    use crate::{
+        DeserializeNode,
+        model::{
+            node::{NodeFullName, NodePath},
+            property::{Compatible, Reg},
+        },
+        types::ByteStr,
+    };
+
+    #[derive(DeserializeNode)]
+    #[devtree(crate = crate)]
+    struct Child<'blob> {
+        #[devtree(node)]
+        _name: NodeFullName<'blob>,
+    }
+
+    #[derive(DeserializeNode)]
+    #[devtree(crate = crate)]
+    struct Node<'blob> {
+        #[devtree(node)]
+        _path: NodePath,
+        #[devtree(property)]
+        _compatible: Compatible<'blob>,
+        #[devtree(property(name = "reg", default))]
+        _reg: Option<Reg<'blob>>,
+        #[devtree(property(name = "clock-frequency", fallback = "parent", default = 0))]
+        _clock_frequency: u32,
+        #[devtree(property(name = "cells", default))]
+        _cells: Option<[u32; 2]>,
+        #[devtree(property(name = "range", default))]
+        _range: Option<(u64, u64)>,
+        #[devtree(property(name = "raw", default))]
+        _raw: Option<&'blob [u8]>,
+        #[devtree(extra_properties)]
+        _properties: BTreeMap<&'blob ByteStr, &'blob [u8]>,
+        #[devtree(child(name = "chosen"))]
+        _chosen: Child<'blob>,
+        #[devtree(child(name = "aliases", default))]
+        _aliases: Option<Child<'blob>>,
+        #[devtree(repeated_children(name = "memory"))]
+        _memory: Vec<Child<'blob>>,
+    }
+
+    #[test]
+    fn test_node_schema() {
+        let schema = Node::NODE_SCHEMA;
+        assert_eq!(schema.type_name, "Node");
+        assert_eq!(schema.node_types, ["NodePath"]);
+        assert!(schema.extra_properties);
+        assert!(!schema.extra_children);
+
+        assert_eq!(
+            schema.properties,
+            [
+                PropertyDesc {
+                    name: "_compatible",
+                    type_name: "Compatible<'blob>",
+                    required: true,
+                    inherited: false,
+                },
+                PropertyDesc {
+                    name: "reg",
+                    type_name: "Option<Reg<'blob>>",
+                    required: false,
+                    inherited: false,
+                },
+                PropertyDesc {
+                    name: "clock-frequency",
+                    type_name: "u32",
+                    required: false,
+                    inherited: true,
+                },
+                PropertyDesc {
+                    name: "cells",
+                    type_name: "Option<[u32; 2]>",
+                    required: false,
+                    inherited: false,
+                },
+                PropertyDesc {
+                    name: "range",
+                    type_name: "Option<(u64, u64)>",
+                    required: false,
+                    inherited: false,
+                },
+                PropertyDesc {
+                    name: "raw",
+                    type_name: "Option<&'blob [u8]>",
+                    required: false,
+                    inherited: false,
+                },
+            ]
+        );
+        assert_eq!(
+            schema.children,
+            [
+                ChildDesc {
+                    name: "chosen",
+                    type_name: "Child<'blob>",
+                    required: true,
+                    repeated: false,
+                },
+                ChildDesc {
+                    name: "aliases",
+                    type_name: "Option<Child<'blob>>",
+                    required: false,
+                    repeated: false,
+                },
+                ChildDesc {
+                    name: "memory",
+                    type_name: "Vec<Child<'blob>>",
+                    required: false,
+                    repeated: true,
+                },
+            ]
+        );
+
+        let child = Child::NODE_SCHEMA;
+        assert_eq!(child.properties, []);
+        assert_eq!(child.children, []);
+        assert_eq!(child.node_types, ["NodeFullName<'blob>"]);
+    }
+
+    #[test]
+    fn test_lookup() {
+        let schema = Node::NODE_SCHEMA;
+        assert_eq!(
+            schema.property("reg").unwrap().type_name,
+            "Option<Reg<'blob>>"
+        );
+        assert!(schema.property("chosen").is_none());
+        assert!(schema.child("memory").unwrap().repeated);
+        assert!(schema.child("reg").is_none());
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(
+            Child::NODE_SCHEMA.to_string(),
+            "`Child`\n- node as `NodeFullName<'blob>`\n"
+        );
+        let s = Node::NODE_SCHEMA.to_string();
+        assert!(s.starts_with("`Node`\n- property `_compatible`: `Compatible<'blob>`, required\n"));
+        assert!(s.contains(
+            "- property `clock-frequency`: `u32`, optional, inherited from the parent\n"
+        ));
+        assert!(s.contains("- other properties\n"));
+        assert!(s.contains("- child `memory`: `Vec<Child<'blob>>`, optional, repeated\n"));
+        assert!(!s.contains("- other children\n"));
+    }
+}
@@ -0,0 +1,237 @@
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use super::{BlobBuilder, BlockBuilder};
+use crate::{
+    blob::{DEVICETREE_ALIGNMENT, ReserveEntry, struct_block::TokenType},
+    util::AlignedByteBuffer,
+};
+
+/// Words that are likely to hit the edge cases of the parser when written
+/// over a field of a blob.
+pub static INTERESTING_WORDS: &[u32] = &[
+    0,
+    1,
+    2,
+    3,
+    4,
+    0x7f,
+    0x100,
+    0xffff,
+    0x7fff_ffff,
+    0x8000_0000,
+    0xffff_fff8,
+    0xffff_ffff,
+    TokenType::BEGIN_NODE,
+    TokenType::END_NODE,
+    TokenType::PROP,
+    TokenType::NOP,
+    TokenType::END,
+];
+
+/// Builds a blob with an empty memory reservation map from the blocks.
+#[must_use]
+pub fn blob_from_block(block: &BlockBuilder) -> AlignedByteBuffer<DEVICETREE_ALIGNMENT> {
+    let (struct_block, strings_block) = block.build();
+    BlobBuilder::new()
+        .extend_mem_rsvmap([ReserveEntry::terminator()])
+        .extend_struct_block_from_slice(&struct_block)
+        .extend_strings_block_from_slice(&strings_block)
+        .build()
+}
+
+/// Returns the hand-written blobs covering the corner cases of the format,
+/// used as the seeds of the mutations.
+#[must_use]
+pub fn seed_corpus() -> Vec<AlignedByteBuffer<DEVICETREE_ALIGNMENT>> {
+    let mut blocks = Vec::new();
+
+    // empty tree
+    let mut block = BlockBuilder::new();
+    block.begin_node(b"").end_node().end();
+    blocks.push(block);
+
+    // addressed children with interrupts
+    let mut block = BlockBuilder::new();
+    block
+        .begin_node(b"")
+        .prop(b"#address-cells", &2_u32.to_be_bytes())
+        .prop(b"#size-cells", &1_u32.to_be_bytes())
+        .prop(b"compatible", b"vendor,board\0vendor,soc\0")
+        .prop(b"model", b"board\0")
+        .begin_node(b"intc@1000")
+        .prop(b"reg", &[0, 0, 0, 0, 0, 0, 0x10, 0, 0, 0, 0x10, 0])
+        .prop(b"#interrupt-cells", &1_u32.to_be_bytes())
+        .prop(b"phandle", &1_u32.to_be_bytes())
+        .end_node()
+        .begin_node(b"bus@2000")
+        .prop(b"#address-cells", &1_u32.to_be_bytes())
+        .prop(b"#size-cells", &1_u32.to_be_bytes())
+        .prop(
+            b"ranges",
+            &[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x20, 0, 0, 0, 0x10, 0],
+        )
+        .begin_node(b"dev@0")
+        .prop(b"reg", &[0, 0, 0, 0, 0, 0, 0x01, 0])
+        .prop(b"interrupt-parent", &1_u32.to_be_bytes())
+        .prop(b"interrupts", &5_u32.to_be_bytes())
+        .prop(b"interrupts-extended", &[0, 0, 0, 1, 0, 0, 0, 6])
+        .prop(b"status", b"okay\0")
+        .end_node()
+        .end_node()
+        .end_node()
+        .end();
+    blocks.push(block);
+
+    // zero-sized cells and missing cells properties
+    let mut block = BlockBuilder::new();
+    block
+        .begin_node(b"")
+        .prop(b"#address-cells", &0_u32.to_be_bytes())
+        .prop(b"#size-cells", &0_u32.to_be_bytes())
+        .prop(b"#interrupt-cells", &0_u32.to_be_bytes())
+        .begin_node(b"node")
+        .prop(b"reg", &[])
+        .prop(b"ranges", &[])
+        .prop(b"interrupts", &[])
+        .end_node()
+        .begin_node(b"node")
+        .prop(b"interrupts", &[0, 0, 0, 1])
+        .end_node()
+        .end_node()
+        .end();
+    blocks.push(block);
+
+    // cells wider than an address
+    let mut block = BlockBuilder::new();
+    block
+        .begin_node(b"")
+        .prop(b"#address-cells", &4_u32.to_be_bytes())
+        .prop(b"#size-cells", &4_u32.to_be_bytes())
+        .begin_node(b"node@ffffffff")
+        .prop(b"reg", &[0xff; 32])
+        .end_node()
+        .end_node()
+        .end();
+    blocks.push(block);
+
+    // NOP tokens, empty and unterminated values, and a deep tree
+    let mut block = BlockBuilder::new();
+    block
+        .nop()
+        .begin_node(b"")
+        .nop()
+        .prop(b"status", b"")
+        .prop(b"compatible", b"unterminated")
+        .prop(b"phandle", &[0xff; 3]);
+    for _ in 0..64 {
+        block.begin_node(b"n");
+    }
+    for _ in 0..64 {
+        block.end_node();
+    }
+    block.end_node().nop().end();
+    blocks.push(block);
+
+    let mut blobs = blocks.iter().map(blob_from_block).collect::<Vec<_>>();
+
+    // memory reservation entries
+    let mut block = BlockBuilder::new();
+    block.begin_node(b"").end_node().end();
+    let (struct_block, strings_block) = block.build();
+    blobs.push(
+        BlobBuilder::new()
+            .extend_mem_rsvmap([
+                ReserveEntry::new(0x8000_0000, 0x1000),
+                ReserveEntry::new(u64::MAX, u64::MAX),
+                ReserveEntry::terminator(),
+            ])
+            .extend_struct_block_from_slice(&struct_block)
+            .extend_strings_block_from_slice(&strings_block)
+            .build(),
+    );
+
+    blobs
+}
+
+/// Returns the prefixes of the blob, cut at every multiple of `step` bytes.
+///
+/// # Panics
+///
+/// Panics if `step` is zero.
+pub fn truncations(seed: &[u8], step: usize) -> impl Iterator<Item = Vec<u8>> + '_ {
+    assert!(step > 0);
+    (0..seed.len())
+        .step_by(step)
+        .map(|len| seed[..len].to_vec())
+}
+
+/// Returns the copies of the blob with one bit flipped, at every multiple of
+/// `stride` bits.
+///
+/// # Panics
+///
+/// Panics if `stride` is zero.
+pub fn bit_flips(seed: &[u8], stride: usize) -> impl Iterator<Item = Vec<u8>> + '_ {
+    assert!(stride > 0);
+    (0..seed.len() * 8).step_by(stride).map(|bit| {
+        let mut bytes = seed.to_vec();
+        bytes[bit / 8] ^= 1 << (bit % 8);
+        bytes
+    })
+}
+
+/// Returns the copies of the blob with one aligned big-endian word replaced
+/// by each of [`INTERESTING_WORDS`].
+pub fn word_replacements(seed: &[u8]) -> impl Iterator<Item = Vec<u8>> + '_ {
+    (0..seed.len() / 4).flat_map(move |index| {
+        INTERESTING_WORDS.iter().filter_map(move |word| {
+            let range = index * 4..(index + 1) * 4;
+            if seed[range.clone()] == word.to_be_bytes() {
+                return None;
+            }
+            let mut bytes = seed.to_vec();
+            bytes[range].copy_from_slice(&word.to_be_bytes());
+            Some(bytes)
+        })
+    })
+}
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Devicetree;
+
+    #[test]
+    fn test_seed_corpus_is_valid() {
+        for blob in seed_corpus() {
+            Devicetree::from_bytes(&blob).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_truncations() {
+        let truncated = truncations(&[1, 2, 3, 4, 5], 2).collect::<Vec<_>>();
+        assert_eq!(truncated, [&[][..], &[1, 2], &[1, 2, 3, 4]]);
+    }
+
+    #[test]
+    fn test_bit_flips() {
+        let flipped = bit_flips(&[0, 0], 5).collect::<Vec<_>>();
+        assert_eq!(flipped, [[1, 0], [0x20, 0], [0, 0x04], [0, 0x80]]);
+    }
+
+    #[test]
+    fn test_word_replacements() {
+        let replaced = word_replacements(&[0, 0, 0, 0, 9]).collect::<Vec<_>>();
+        assert_eq!(replaced.len(), INTERESTING_WORDS.len() - 1);
+        assert!(
+            replaced
+                .iter()
+                .all(|bytes| bytes.len() == 5 && bytes[4] == 9)
+        );
+        assert!(replaced.contains(&alloc::vec![0xff, 0xff, 0xff, 0xff, 9]));
+    }
+}
@@ -1,9 +1,11 @@
 pub use self::slice_token_cursor::*;
 #[cfg(feature = "alloc")]
-pub use self::{blob_builder::*, block_builder::*};
+pub use self::{blob_builder::*, block_builder::*, corpus::*};
 
 #[cfg(feature = "alloc")]
 mod blob_builder;
 #[cfg(feature = "alloc")]
 mod block_builder;
+#[cfg(feature = "alloc")]
+mod corpus;
 mod slice_token_cursor;
@@ -53,6 +53,27 @@ pub trait TokenCursor<'blob>: Clone {
     fn reset(&mut self);
     fn seek_item_start_of_node(&mut self, node_ref: &Self::NodeHandle);
     fn read_token(&mut self) -> Result<Option<Token<'blob>>, ReadTokenError>;
+
+    /// Skips the tokens up to and including the `EndNode` token of the
+    /// current node, including the tokens of its descendant nodes.
+    ///
+    /// Returns `None` if the tokens end before the `EndNode` token.
+    fn skip_node(&mut self) -> Result<Option<()>, ReadTokenError> {
+        let mut depth = 0_usize;
+        loop {
+            match self.read_token()? {
+                Some(Token::BeginNode(_)) => depth += 1,
+                Some(Token::Property(_)) => {}
+                Some(Token::EndNode) => {
+                    let Some(d) = depth.checked_sub(1) else {
+                        return Ok(Some(()));
+                    };
+                    depth = d;
+                }
+                None => return Ok(None),
+            }
+        }
+    }
 }
 
 #[cfg_attr(coverage_nightly, coverage(off))]
@@ -124,7 +124,7 @@ impl<'blob> TokenCursor<'blob> for BlobTokenCursor<'blob> {
             },
             TokenType::BEGIN_NODE
         );
-        assert_eq!(*struct_block.get::<u8>(name_end), 0);
+        assert_eq!(*struct_block.get::<u8>(name_end - 1), 0);
 
         let item_start = name_end.next_multiple_of(align_of::<TokenType>());
         self.done = false;
@@ -142,6 +142,17 @@ impl<'blob> TokenCursor<'blob> for BlobTokenCursor<'blob> {
         }
         res
     }
+
+    fn skip_node(&mut self) -> Result<Option<()>, ReadTokenError> {
+        if self.done {
+            return Ok(None);
+        }
+        let res = self.skip_node_inner();
+        if res.is_err() || res.as_ref().is_ok_and(Option::is_none) {
+            self.done = true;
+        }
+        res
+    }
 }
 
 impl<'blob> BlobTokenCursor<'blob> {
@@ -168,6 +179,38 @@ impl<'blob> BlobTokenCursor<'blob> {
         }
     }
 
+    // Unlike `read_token_inner`, the names of the nodes and the properties are
+    // not validated, so that a subtree is skipped without reading its items.
+    fn skip_node_inner(&mut self) -> Result<Option<()>, ReadTokenError> {
+        let mut depth = 0_usize;
+        loop {
+            let Some(raw_token) = self.read_raw_token() else {
+                return Ok(None);
+            };
+
+            let position = self.position;
+            match raw_token.value() {
+                TokenType::BEGIN_NODE => {
+                    self.skip_begin_node()
+                        .map_err(|source| ReadTokenErrorKind::BeginNode { source })?;
+                    depth += 1;
+                }
+                TokenType::END_NODE => {
+                    let Some(d) = depth.checked_sub(1) else {
+                        return Ok(Some(()));
+                    };
+                    depth = d;
+                }
+                TokenType::PROP => self
+                    .skip_prop_token()
+                    .map_err(|source| ReadTokenErrorKind::Prop { source })?,
+                TokenType::NOP => {}
+                TokenType::END => return Ok(None),
+                token => bail!(ReadTokenErrorKind::UnknownToken { token, position }),
+            }
+        }
+    }
+
     fn read_begin_node(&mut self) -> Result<Token<'blob>, ReadBeginNodeTokenError> {
         let position = self.position;
         let name = self
@@ -225,6 +268,26 @@ impl<'blob> BlobTokenCursor<'blob> {
         Ok(Token::Property(Property::new(name_bytes, value)))
     }
 
+    fn skip_begin_node(&mut self) -> Result<(), ReadBeginNodeTokenError> {
+        let position = self.position;
+        self.read_null_terminated_string()
+            .ok_or(ReadBeginNodeTokenErrorKind::UnterminatedNodeName { position })?;
+        self.skip_token_padding();
+        Ok(())
+    }
+
+    fn skip_prop_token(&mut self) -> Result<(), ReadPropTokenError> {
+        let position = self.position;
+        let header = self
+            .read_prop_header()
+            .ok_or(ReadPropTokenErrorKind::MissingPropertyHeader { position })?;
+        let len = usize::cast_from(header.len());
+        self.read_bytes(len)
+            .ok_or(ReadPropTokenErrorKind::PropertyValueExceedingBlock { position, len })?;
+        self.skip_token_padding();
+        Ok(())
+    }
+
     fn skip_token_padding(&mut self) {
         self.position = self.position.next_multiple_of(align_of::<TokenType>());
     }
@@ -639,4 +702,63 @@ mod tests {
             "source: {source:?}"
         );
     }
+
+    #[test]
+    fn test_blob_token_cursor_skip_node() {
+        let (struct_block, strings_block) = BlockBuilder::new()
+            .begin_node(b"")
+            .begin_node(b"skipped")
+            .prop(b"foo", b"bar")
+            .nop()
+            .begin_node(b"grandchild")
+            .prop(b"baz", b"")
+            .end_node()
+            .end_node()
+            .begin_node(b"next")
+            .end_node()
+            .end_node()
+            .end()
+            .build();
+        let mut cursor = BlobTokenCursor::new(&struct_block, &strings_block);
+        assert_eq!(
+            cursor.read_token().unwrap(),
+            Some(Token::BeginNode(Node::new("")))
+        );
+        assert_eq!(
+            cursor.read_token().unwrap(),
+            Some(Token::BeginNode(Node::new("skipped")))
+        );
+        assert_eq!(cursor.skip_node().unwrap(), Some(()));
+        assert_eq!(
+            cursor.read_token().unwrap(),
+            Some(Token::BeginNode(Node::new("next")))
+        );
+        assert_eq!(cursor.read_token().unwrap(), Some(Token::EndNode));
+        assert_eq!(cursor.skip_node().unwrap(), Some(()));
+        assert_eq!(cursor.skip_node().unwrap(), None);
+        assert_eq!(cursor.read_token().unwrap(), None);
+    }
+
+    #[test]
+    fn test_blob_token_cursor_skip_node_errors() {
+        let (struct_block, strings_block) = BlockBuilder::new()
+            .begin_node(b"")
+            .token(TokenType::PROP)
+            .extend_struct_block_from_slice(PropertyHeader::new(100, 0).as_bytes())
+            .extend_strings_block_from_slice(b"foo\0")
+            .end()
+            .build();
+        let mut cursor = BlobTokenCursor::new(&struct_block, &strings_block);
+        cursor.read_token().unwrap();
+        let err = cursor.skip_node().unwrap_err();
+        assert!(err.kind().is_prop(), "err: {err:?}");
+        assert_eq!(cursor.read_token().unwrap(), None);
+
+        let (struct_block, strings_block) =
+            BlockBuilder::new().begin_node(b"").token(0xdead).build();
+        let mut cursor = BlobTokenCursor::new(&struct_block, &strings_block);
+        cursor.read_token().unwrap();
+        let err = cursor.skip_node().unwrap_err();
+        assert!(err.kind().is_unknown_token(), "err: {err:?}");
+    }
 }
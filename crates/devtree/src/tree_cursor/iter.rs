@@ -1,6 +1,17 @@
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+#[cfg(feature = "alloc")]
+use alloc::vec::{self, Vec};
+#[cfg(feature = "alloc")]
+use core::cmp::Ordering;
 use core::{iter::FusedIterator, marker::PhantomData};
 
-use super::{Glob, GlobCursor, TreeCursor, TreeIterator, error::ReadTreeError};
+use super::{
+    Glob, GlobCursor, TreeCursor, TreeIterator,
+    error::ReadTreeError,
+    traits::{is_node_compatible, is_node_enabled},
+};
 use crate::{
     blob::{Item, Node, Property},
     de::{
@@ -9,6 +20,7 @@ use crate::{
         types::{DefaultNodeDeserializer, DefaultPropertyDeserializer},
     },
     tree_cursor::GlobComponent,
+    types::ByteStr,
 };
 
 #[derive(Debug)]
@@ -175,6 +187,7 @@ where
 pub struct ReadDescendantItems<'tc, 'blob, TC> {
     min_depth: usize,
     done: bool,
+    skip_disabled: bool,
     tree_cursor: &'tc mut TC,
     _phantom: PhantomData<&'blob ()>,
 }
@@ -187,11 +200,19 @@ where
         Self {
             min_depth: tree_cursor.depth(),
             done: false,
+            skip_disabled: false,
             tree_cursor,
             _phantom: PhantomData,
         }
     }
 
+    /// Skips the subtrees of the nodes whose `status` is not `"okay"`.
+    #[must_use]
+    pub fn skip_disabled(mut self) -> Self {
+        self.skip_disabled = true;
+        self
+    }
+
     fn try_next(&mut self) -> Result<Option<Item<'blob>>, ReadTreeError> {
         let res = self.try_next_inner();
         if res.is_err() {
@@ -207,6 +228,10 @@ where
 
         loop {
             if let Some(item) = self.tree_cursor.read_item_descend()? {
+                if self.skip_disabled && item.is_node() && !is_node_enabled(self.tree_cursor)? {
+                    self.tree_cursor.seek_parent_next()?;
+                    continue;
+                }
                 return Ok(Some(item));
             }
 
@@ -262,6 +287,14 @@ where
             iter: ReadDescendantItems::new(tree_cursor),
         }
     }
+
+    /// Skips the subtrees of the nodes whose `status` is not `"okay"`.
+    #[must_use]
+    pub fn skip_disabled(self) -> Self {
+        Self {
+            iter: self.iter.skip_disabled(),
+        }
+    }
 }
 
 impl<'blob, TC> Iterator for ReadDescendantProperties<'_, 'blob, TC>
@@ -307,6 +340,14 @@ where
             iter: ReadDescendantItems::new(tree_cursor),
         }
     }
+
+    /// Skips the subtrees of the nodes whose `status` is not `"okay"`.
+    #[must_use]
+    pub fn skip_disabled(self) -> Self {
+        Self {
+            iter: self.iter.skip_disabled(),
+        }
+    }
 }
 
 impl<'blob, TC> Iterator for ReadDescendantNodes<'_, 'blob, TC>
@@ -341,6 +382,7 @@ where
 pub struct ReadDescendantNodesByGlob<'tc, 'glob, 'blob, TC> {
     min_depth: usize,
     done: bool,
+    skip_disabled: bool,
     last_matched: bool,
     glob_cursor: GlobCursor<'glob>,
     tree_cursor: &'tc mut TC,
@@ -360,6 +402,7 @@ where
         Self {
             min_depth: tree_cursor.depth(),
             done: false,
+            skip_disabled: false,
             last_matched: false,
             glob_cursor,
             tree_cursor,
@@ -367,6 +410,13 @@ where
         }
     }
 
+    /// Skips the subtrees of the nodes whose `status` is not `"okay"`.
+    #[must_use]
+    pub fn skip_disabled(mut self) -> Self {
+        self.skip_disabled = true;
+        self
+    }
+
     fn try_next(&mut self) -> Result<Option<Node<'blob>>, ReadTreeError> {
         let res = self.try_next_inner();
         if res.is_err() {
@@ -381,7 +431,7 @@ where
         if depth == self.min_depth {
             return Ok(None);
         }
-        let is_root = self.tree_cursor.seek_parent_next()?.is_none();
+        let is_root = self.tree_cursor.skip_subtree()?.is_none();
         if is_root {
             return Ok(None);
         }
@@ -389,6 +439,24 @@ where
         Ok(Some(()))
     }
 
+    /// Moves the cursor into the next child matching the component.
+    ///
+    /// The children not matching the component are skipped with
+    /// [`TreeCursor::skip_subtree`], as none of their descendants can match
+    /// the glob.
+    fn seek_matching_child(&mut self, component: GlobComponent<'_>) -> Result<bool, ReadTreeError> {
+        while let Some(item) = self.tree_cursor.read_item_descend()? {
+            let Item::Node(child) = item else {
+                continue;
+            };
+            if component.match_node(&child) {
+                return Ok(true);
+            }
+            self.tree_cursor.skip_subtree()?;
+        }
+        Ok(false)
+    }
+
     fn try_next_inner(&mut self) -> Result<Option<Node<'blob>>, ReadTreeError> {
         if self.done {
             return Ok(None);
@@ -417,17 +485,7 @@ where
                 continue;
             }
 
-            let matched = self
-                .tree_cursor
-                .read_children()
-                .find(|res| {
-                    res.as_ref()
-                        .map_or(true, |child| component.match_node(child))
-                })
-                .transpose()?
-                .is_some();
-
-            if !matched {
+            if !self.seek_matching_child(component)? {
                 assert_eq!(self.tree_cursor.depth(), depth);
                 if self.rewind_cursors()?.is_none() {
                     self.done = true;
@@ -437,6 +495,11 @@ where
             }
 
             assert_eq!(self.tree_cursor.depth(), depth + 1);
+            if self.skip_disabled && !is_node_enabled(self.tree_cursor)? {
+                // continue with the next sibling of the disabled node
+                self.tree_cursor.skip_subtree()?;
+                continue;
+            }
             self.glob_cursor.seek_descend();
         }
     }
@@ -469,6 +532,154 @@ where
     }
 }
 
+#[derive(Debug)]
+pub struct ReadDescendantNodesByCompatible<'tc, 'pat, 'blob, TC> {
+    pattern: &'pat ByteStr,
+    iter: ReadDescendantNodes<'tc, 'blob, TC>,
+}
+
+impl<'tc, 'pat, 'blob, TC> ReadDescendantNodesByCompatible<'tc, 'pat, 'blob, TC>
+where
+    TC: TreeCursor<'blob>,
+{
+    #[must_use]
+    pub fn new<P>(tree_cursor: &'tc mut TC, pattern: &'pat P) -> Self
+    where
+        P: AsRef<[u8]> + ?Sized + 'pat,
+    {
+        Self {
+            pattern: ByteStr::new(pattern),
+            iter: ReadDescendantNodes::new(tree_cursor),
+        }
+    }
+
+    /// Skips the subtrees of the nodes whose `status` is not `"okay"`.
+    #[must_use]
+    pub fn skip_disabled(self) -> Self {
+        Self {
+            iter: self.iter.skip_disabled(),
+            ..self
+        }
+    }
+}
+
+impl<'blob, TC> Iterator for ReadDescendantNodesByCompatible<'_, '_, 'blob, TC>
+where
+    TC: TreeCursor<'blob>,
+{
+    type Item = Result<Node<'blob>, ReadTreeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let node = match self.iter.next()? {
+                Ok(node) => node,
+                Err(err) => return Some(Err(err)),
+            };
+            match is_node_compatible(self.iter.iter.tree_cursor, self.pattern) {
+                Ok(true) => return Some(Ok(node)),
+                Ok(false) => {}
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+}
+
+impl<'blob, TC> FusedIterator for ReadDescendantNodesByCompatible<'_, '_, 'blob, TC> where
+    TC: TreeCursor<'blob>
+{
+}
+
+impl<'blob, TC> TreeIterator<'blob> for ReadDescendantNodesByCompatible<'_, '_, 'blob, TC>
+where
+    TC: TreeCursor<'blob>,
+{
+    type TreeCursor = TC;
+
+    fn tree_cursor(&self) -> &Self::TreeCursor {
+        self.iter.tree_cursor()
+    }
+}
+
+/// Nodes read by a node iterator, yielded in a sorted order.
+///
+/// Each node is read with a clone of the tree cursor, so that the nodes can be
+/// deserialized as with the original iterator.
+#[cfg(feature = "alloc")]
+#[derive(Debug)]
+pub struct SortedNodes<'blob, TC> {
+    nodes: vec::IntoIter<(Node<'blob>, TC)>,
+    tree_cursor: TC,
+}
+
+#[cfg(feature = "alloc")]
+impl<'blob, TC> SortedNodes<'blob, TC>
+where
+    TC: TreeCursor<'blob>,
+{
+    pub fn new<I, F>(mut iter: I, mut compare: F) -> Result<Self, DeserializeError>
+    where
+        I: Iterator<Item = Result<Node<'blob>, ReadTreeError>>
+            + TreeIterator<'blob, TreeCursor = TC>,
+        F: FnMut(&Node<'blob>, &Node<'blob>) -> Ordering,
+    {
+        let mut nodes = Vec::new();
+        while let Some(node) = iter.next().transpose()? {
+            nodes.push((node, clone_tree_cursor(iter.tree_cursor())?));
+        }
+        nodes.sort_by(|(a, _), (b, _)| compare(a, b));
+        Ok(Self {
+            nodes: nodes.into_iter(),
+            tree_cursor: clone_tree_cursor(iter.tree_cursor())?,
+        })
+    }
+}
+
+#[cfg(feature = "alloc")]
+fn clone_tree_cursor<'blob, TC>(tree_cursor: &TC) -> Result<TC, DeserializeError>
+where
+    TC: TreeCursor<'blob>,
+{
+    tree_cursor
+        .try_clone()
+        .ok_or_else(DeserializeError::clone_not_supported)
+}
+
+#[cfg(feature = "alloc")]
+impl<'blob, TC> Iterator for SortedNodes<'blob, TC>
+where
+    TC: TreeCursor<'blob>,
+{
+    type Item = Result<Node<'blob>, ReadTreeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (node, tree_cursor) = self.nodes.next()?;
+        self.tree_cursor = tree_cursor;
+        Some(Ok(node))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.nodes.size_hint()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<'blob, TC> ExactSizeIterator for SortedNodes<'blob, TC> where TC: TreeCursor<'blob> {}
+
+#[cfg(feature = "alloc")]
+impl<'blob, TC> FusedIterator for SortedNodes<'blob, TC> where TC: TreeCursor<'blob> {}
+
+#[cfg(feature = "alloc")]
+impl<'blob, TC> TreeIterator<'blob> for SortedNodes<'blob, TC>
+where
+    TC: TreeCursor<'blob>,
+{
+    type TreeCursor = TC;
+
+    fn tree_cursor(&self) -> &Self::TreeCursor {
+        &self.tree_cursor
+    }
+}
+
 #[derive(Debug)]
 pub struct DeserializedProperties<T, I> {
     iter: I,
@@ -557,3 +768,263 @@ where
         self.try_next().transpose()
     }
 }
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+#[cfg(test)]
+mod tests {
+    extern crate alloc;
+
+    use alloc::vec::Vec;
+
+    use super::*;
+    use crate::{
+        testing::SliceTokenCursor,
+        token_cursor::Token,
+        tree_cursor::{
+            TreeCursorAllocExt as _, TreeIteratorAllocExt as _, types::StackBasedTreeCursor,
+        },
+    };
+
+    fn tokens() -> [Token<'static>; 18] {
+        [
+            Token::BeginNode(Node::new("")),
+            Token::BeginNode(Node::new("bus@0")),
+            Token::Property(Property::new("status", "disabled\0")),
+            Token::BeginNode(Node::new("uart@0")),
+            Token::EndNode,
+            Token::EndNode,
+            Token::BeginNode(Node::new("bus@1")),
+            Token::Property(Property::new("status", "ok\0")),
+            Token::BeginNode(Node::new("uart@1")),
+            Token::Property(Property::new("status", "fail\0")),
+            Token::EndNode,
+            Token::BeginNode(Node::new("uart@2")),
+            Token::Property(Property::new("status", "okay\0")),
+            Token::EndNode,
+            Token::BeginNode(Node::new("uart@3")),
+            Token::EndNode,
+            Token::EndNode,
+            Token::EndNode,
+        ]
+    }
+
+    fn node_names<'blob, I>(iter: I) -> Vec<&'blob str>
+    where
+        I: Iterator<Item = Result<Node<'blob>, ReadTreeError>>,
+    {
+        iter.map(|node| str::from_utf8(node.unwrap().full_name()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_read_descendant_nodes_skip_disabled() {
+        let tokens = tokens();
+        let mut cursor = StackBasedTreeCursor::new(SliceTokenCursor::new(&tokens)).unwrap();
+        assert_eq!(
+            node_names(cursor.read_descendant_nodes()),
+            ["bus@0", "uart@0", "bus@1", "uart@1", "uart@2", "uart@3"]
+        );
+        cursor.reset();
+        assert_eq!(
+            node_names(cursor.read_descendant_nodes().skip_disabled()),
+            ["bus@1", "uart@2", "uart@3"]
+        );
+    }
+
+    #[test]
+    fn test_read_descendant_properties_skip_disabled() {
+        let tokens = tokens();
+        let mut cursor = StackBasedTreeCursor::new(SliceTokenCursor::new(&tokens)).unwrap();
+        let values = cursor
+            .read_descendant_properties()
+            .skip_disabled()
+            .map(|property| property.unwrap().value())
+            .collect::<Vec<_>>();
+        assert_eq!(values, [b"ok\0".as_slice(), b"okay\0"]);
+    }
+
+    #[test]
+    fn test_read_descendant_nodes_by_glob_skip_disabled() {
+        let tokens = tokens();
+        let mut cursor = StackBasedTreeCursor::new(SliceTokenCursor::new(&tokens)).unwrap();
+        assert_eq!(
+            node_names(cursor.read_descendant_nodes_by_glob("/*/uart")),
+            ["uart@0", "uart@1", "uart@2", "uart@3"]
+        );
+        cursor.reset();
+        assert_eq!(
+            node_names(
+                cursor
+                    .read_descendant_nodes_by_glob("/*/uart")
+                    .skip_disabled()
+            ),
+            ["uart@2", "uart@3"]
+        );
+    }
+
+    #[test]
+    fn test_read_descendant_nodes_by_compatible() {
+        let tokens = [
+            Token::BeginNode(Node::new("")),
+            Token::Property(Property::new("compatible", "riscv-virtio\0")),
+            Token::BeginNode(Node::new("soc")),
+            Token::BeginNode(Node::new("serial@0")),
+            Token::Property(Property::new("compatible", "ns16550a\0")),
+            Token::EndNode,
+            Token::BeginNode(Node::new("serial@1")),
+            Token::Property(Property::new("status", "disabled\0")),
+            Token::Property(Property::new("compatible", "sifive,uart0\0ns16550a\0")),
+            Token::EndNode,
+            Token::BeginNode(Node::new("rtc@0")),
+            Token::Property(Property::new("compatible", "google,goldfish-rtc\0")),
+            Token::EndNode,
+            Token::EndNode,
+            Token::EndNode,
+        ];
+        let mut cursor = StackBasedTreeCursor::new(SliceTokenCursor::new(&tokens)).unwrap();
+        assert_eq!(
+            node_names(cursor.read_descendant_nodes_by_compatible("ns16550a")),
+            ["serial@0", "serial@1"]
+        );
+        cursor.reset();
+        assert_eq!(
+            node_names(
+                cursor
+                    .read_descendant_nodes_by_compatible("*,uart0")
+                    .skip_disabled()
+            ),
+            [] as [&str; 0]
+        );
+        cursor.reset();
+        assert_eq!(
+            node_names(cursor.read_descendant_nodes_by_compatible("*,goldfish-rtc")),
+            ["rtc@0"]
+        );
+    }
+
+    #[test]
+    fn test_read_property() {
+        let tokens = [
+            Token::BeginNode(Node::new("")),
+            Token::BeginNode(Node::new("cpu@0")),
+            Token::Property(Property::new("reg", &[0, 0, 0, 1])),
+            Token::Property(Property::new("device_type", "cpu\0")),
+            Token::Property(Property::new("timebase-frequency", &[0, 0x98, 0x96, 0x80])),
+            Token::BeginNode(Node::new("interrupt-controller")),
+            Token::Property(Property::new("compatible", "riscv,cpu-intc\0")),
+            Token::EndNode,
+            Token::EndNode,
+            Token::EndNode,
+        ];
+        let mut cursor = StackBasedTreeCursor::new(SliceTokenCursor::new(&tokens)).unwrap();
+        let mut cpu = cursor.read_node_by_path("/cpu@0").unwrap().unwrap();
+        assert_eq!(cpu.read_property::<u32>("reg").unwrap(), Some(1));
+        assert_eq!(
+            cpu.read_property::<&str>("device_type").unwrap(),
+            Some("cpu")
+        );
+        assert_eq!(
+            cpu.read_property::<u32>("timebase-frequency").unwrap(),
+            Some(10_000_000)
+        );
+        assert!(cpu.read_property::<u64>("reg").is_err());
+        // the properties of the child nodes are not read
+        assert_eq!(cpu.read_property::<&str>("compatible").unwrap(), None);
+        assert_eq!(cpu.read_property::<u32>("clock-frequency").unwrap(), None);
+        // the cursor stays at the start of the node
+        let item = cpu.into_tree_cursor().read_item_descend().unwrap();
+        assert_eq!(
+            item,
+            Some(Item::Property(Property::new("reg", &[0, 0, 0, 1])))
+        );
+    }
+
+    #[test]
+    fn test_is_enabled() {
+        let tokens = tokens();
+        let mut cursor = StackBasedTreeCursor::new(SliceTokenCursor::new(&tokens)).unwrap();
+        for (path, expected) in [
+            ("/", true),
+            ("/bus@0", false),
+            ("/bus@1", true),
+            ("/bus@1/uart@1", false),
+            ("/bus@1/uart@2", true),
+            ("/bus@1/uart@3", true),
+        ] {
+            cursor.reset();
+            let mut node = cursor.read_node_by_path(path).unwrap().unwrap();
+            assert_eq!(node.is_enabled().unwrap(), expected, "{path}");
+            // the cursor stays at the start of the node
+            let item = node.into_tree_cursor().read_item_descend().unwrap();
+            if path == "/" {
+                assert_eq!(item, Some(Item::Node(Node::new("bus@0"))));
+            }
+        }
+    }
+
+    #[test]
+    fn test_sorted_children() {
+        let tokens = [
+            Token::BeginNode(Node::new("")),
+            Token::BeginNode(Node::new("cpu@10")),
+            Token::Property(Property::new("reg", "10")),
+            Token::EndNode,
+            Token::BeginNode(Node::new("cpu@9")),
+            Token::Property(Property::new("reg", "9")),
+            Token::EndNode,
+            Token::BeginNode(Node::new("cpu-map")),
+            Token::EndNode,
+            Token::BeginNode(Node::new("cpu@a")),
+            Token::Property(Property::new("reg", "a")),
+            Token::EndNode,
+            Token::EndNode,
+        ];
+        let mut cursor = StackBasedTreeCursor::new(SliceTokenCursor::new(&tokens)).unwrap();
+        assert_eq!(
+            node_names(cursor.read_children_sorted_by_unit_address().unwrap()),
+            ["cpu-map", "cpu@9", "cpu@a", "cpu@10"]
+        );
+        assert_eq!(
+            node_names(cursor.read_children_sorted_by_name().unwrap()),
+            ["cpu@9", "cpu@a", "cpu@10", "cpu-map"]
+        );
+
+        // the cursor of each node is kept for reading the node
+        let mut iter = cursor
+            .read_descendant_nodes_by_glob("/cpu")
+            .sorted_by_unit_address()
+            .unwrap();
+        let mut regs = Vec::new();
+        while iter.next().is_some() {
+            let mut tree_cursor = iter.tree_cursor().try_clone().unwrap();
+            let property = tree_cursor.read_properties().next().unwrap().unwrap();
+            regs.push(property.value());
+        }
+        assert_eq!(regs, [b"9".as_slice(), b"a", b"10"]);
+    }
+
+    #[test]
+    fn test_read_nth_child_by_name() {
+        let tokens = tokens();
+        let mut cursor = StackBasedTreeCursor::new(SliceTokenCursor::new(&tokens)).unwrap();
+        let bus = cursor.read_nth_child_by_name("bus", 1).unwrap().unwrap();
+        assert_eq!(bus.node().full_name(), "bus@1");
+        let cursor = bus.into_tree_cursor();
+        for (name, index, expected) in [
+            ("uart", 0, Some("uart@1")),
+            ("uart", 2, Some("uart@3")),
+            ("uart", 3, None),
+            ("uart@2", 0, Some("uart@2")),
+            ("uart@2", 1, None),
+            ("bus", 0, None),
+        ] {
+            let child = cursor.read_nth_child_by_name(name, index).unwrap();
+            let child = child.map(|child| str::from_utf8(child.node().full_name()).unwrap());
+            assert_eq!(child, expected, "{name}[{index}]");
+            cursor.seek_parent_start();
+            if cursor.node().full_name() != "bus@1" {
+                cursor.read_nth_child_by_name("bus@1", 0).unwrap().unwrap();
+            }
+        }
+    }
+}
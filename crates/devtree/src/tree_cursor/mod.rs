@@ -1,8 +1,9 @@
-pub use self::{glob::*, traits::*};
+pub use self::{glob::*, raw_node::*, traits::*};
 
 mod debug_tree;
 pub mod error;
 mod glob;
 pub mod iter;
+mod raw_node;
 mod traits;
 pub mod types;
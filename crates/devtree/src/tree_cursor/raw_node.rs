@@ -0,0 +1,106 @@
+use core::{array, fmt};
+
+use crate::{blob::Node, de::error::DeserializeError, tree_cursor::TreeCursor};
+
+const CAPACITY: usize = 8;
+
+/// Handle to a node, kept to deserialize the node again later.
+///
+/// Unlike [`StackBasedNodeHandle`], the handle does not depend on the type of
+/// the cursor, so that it can be held in a deserialized struct with
+/// `#[devtree(raw_node)]`. A cursor over the same devicetree is moved back to
+/// the node with [`StackBasedTreeCursor::seek_raw_node`], without searching the
+/// tree again.
+///
+/// [`StackBasedNodeHandle`]: crate::tree_cursor::types::StackBasedNodeHandle
+/// [`StackBasedTreeCursor::seek_raw_node`]: crate::tree_cursor::types::StackBasedTreeCursor::seek_raw_node
+#[derive(Clone, PartialEq, Eq)]
+pub struct RawNode<'blob> {
+    /// The node and its parents, from the root node. The unused entries are
+    /// filled with the root node.
+    nodes: [Node<'blob>; CAPACITY],
+    len: usize,
+}
+
+impl<'blob> RawNode<'blob> {
+    /// Maximum number of the nodes from the root node to the node.
+    pub const CAPACITY: usize = CAPACITY;
+
+    /// Creates a handle to the current node of the cursor.
+    ///
+    /// Fails if the node is deeper than [`Self::CAPACITY`] allows.
+    pub fn from_tree_cursor<TC>(cursor: &TC) -> Result<Self, DeserializeError>
+    where
+        TC: TreeCursor<'blob>,
+    {
+        let len = cursor.depth() + 1;
+        if len > Self::CAPACITY {
+            return Err(DeserializeError::custom(
+                "node too deep for raw node handle",
+            ));
+        }
+        let mut nodes = array::from_fn(|_| Node::new(""));
+        for (slot, node) in nodes[..len].iter_mut().rev().zip(cursor.parents()) {
+            *slot = node;
+        }
+        Ok(Self { nodes, len })
+    }
+
+    /// Returns the node pointed by the handle.
+    #[must_use]
+    pub fn node(&self) -> &Node<'blob> {
+        &self.nodes[self.len - 1]
+    }
+
+    /// Returns the depth of the node pointed by the handle.
+    #[must_use]
+    pub fn depth(&self) -> usize {
+        self.len - 1
+    }
+
+    /// Returns the node and its parents, from the root node.
+    pub fn nodes(&self) -> impl ExactSizeIterator<Item = &Node<'blob>> + '_ {
+        self.nodes[..self.len].iter()
+    }
+}
+
+impl fmt::Debug for RawNode<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.nodes().map(Node::full_name))
+            .finish()
+    }
+}
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        testing::SliceTokenCursor, token_cursor::Token, tree_cursor::types::StackBasedTreeCursor,
+    };
+
+    #[test]
+    fn test_from_tree_cursor() {
+        let tokens = &[
+            Token::BeginNode(Node::new("")),
+            Token::BeginNode(Node::new("soc")),
+            Token::BeginNode(Node::new("serial@10000000")),
+            Token::EndNode,
+            Token::EndNode,
+            Token::EndNode,
+        ];
+        let mut cursor = StackBasedTreeCursor::new(SliceTokenCursor::new(tokens)).unwrap();
+        cursor.read_item_descend().unwrap(); // soc
+        cursor.read_item_descend().unwrap(); // serial@10000000
+
+        let raw = RawNode::from_tree_cursor(&cursor).unwrap();
+        assert_eq!(raw.depth(), 2);
+        assert_eq!(raw.node(), &Node::new("serial@10000000"));
+        assert!(
+            raw.nodes()
+                .map(Node::full_name)
+                .eq(["", "soc", "serial@10000000"])
+        );
+    }
+}
@@ -1,9 +1,15 @@
 extern crate alloc;
 
 use alloc::vec::Vec;
+use core::cmp::Ordering;
 
-use super::TreeCursor;
-use crate::types::ByteString;
+use super::{TreeCursor, TreeIterator};
+use crate::{
+    blob::Node,
+    de::error::DeserializeError,
+    tree_cursor::{error::ReadTreeError, iter},
+    types::ByteString,
+};
 
 pub trait TreeCursorAllocExt<'blob>: TreeCursor<'blob> {
     #[must_use]
@@ -24,6 +30,72 @@ pub trait TreeCursorAllocExt<'blob>: TreeCursor<'blob> {
         }
         path
     }
+
+    /// Returns all the children of the current node, sorted by the unit
+    /// addresses.
+    ///
+    /// See [`Node::cmp_by_unit_address`] for the order.
+    fn read_children_sorted_by_unit_address(
+        &mut self,
+    ) -> Result<iter::SortedNodes<'blob, Self>, DeserializeError> {
+        self.seek_node_start();
+        self.read_children().sorted_by_unit_address()
+    }
+
+    /// Returns all the children of the current node, sorted by the names.
+    ///
+    /// See [`Node::cmp_by_name`] for the order.
+    fn read_children_sorted_by_name(
+        &mut self,
+    ) -> Result<iter::SortedNodes<'blob, Self>, DeserializeError> {
+        self.seek_node_start();
+        self.read_children().sorted_by_name()
+    }
 }
 
 impl<'blob, T> TreeCursorAllocExt<'blob> for T where T: TreeCursor<'blob> {}
+
+pub trait TreeIteratorAllocExt<'blob>:
+    Iterator<Item = Result<Node<'blob>, ReadTreeError>> + TreeIterator<'blob> + Sized
+{
+    /// Collects the nodes and yields them in the order of `compare`.
+    ///
+    /// The nodes comparing equal are yielded in the original order.
+    fn sorted_by<F>(
+        self,
+        compare: F,
+    ) -> Result<iter::SortedNodes<'blob, Self::TreeCursor>, DeserializeError>
+    where
+        Self::TreeCursor: Sized,
+        F: FnMut(&Node<'blob>, &Node<'blob>) -> Ordering,
+    {
+        iter::SortedNodes::new(self, compare)
+    }
+
+    /// Collects the nodes and yields them in the order of the unit addresses.
+    ///
+    /// See [`Node::cmp_by_unit_address`] for the order.
+    fn sorted_by_unit_address(
+        self,
+    ) -> Result<iter::SortedNodes<'blob, Self::TreeCursor>, DeserializeError>
+    where
+        Self::TreeCursor: Sized,
+    {
+        self.sorted_by(Node::cmp_by_unit_address)
+    }
+
+    /// Collects the nodes and yields them in the order of the names.
+    ///
+    /// See [`Node::cmp_by_name`] for the order.
+    fn sorted_by_name(self) -> Result<iter::SortedNodes<'blob, Self::TreeCursor>, DeserializeError>
+    where
+        Self::TreeCursor: Sized,
+    {
+        self.sorted_by(Node::cmp_by_name)
+    }
+}
+
+impl<'blob, T> TreeIteratorAllocExt<'blob> for T where
+    T: Iterator<Item = Result<Node<'blob>, ReadTreeError>> + TreeIterator<'blob>
+{
+}
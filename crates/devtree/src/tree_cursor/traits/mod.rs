@@ -1,16 +1,18 @@
 #[cfg(feature = "alloc")]
 pub use self::alloc::*;
-use super::{Glob, debug_tree, error::ReadTreeError, iter};
+use super::{Glob, GlobComponent, debug_tree, error::ReadTreeError, iter};
 use crate::{
-    blob::{Item, Node, Property},
+    blob::{Item, Node, Property, UNIT_ADDRESS_SEPARATOR},
     de::{
         DeserializeNode, DeserializeProperty,
         error::DeserializeError,
         types::{DefaultNodeDeserializer, DefaultPropertyDeserializer},
     },
-    model::property::Phandle,
+    model::property::{ByteStrList, Compatible, Phandle, Status},
+    polyfill,
     token_cursor::TokenCursor,
     types::ByteStr,
+    util::{NodePathBuf, PathOverflowError},
 };
 
 #[cfg(feature = "alloc")]
@@ -35,12 +37,40 @@ pub trait TreeCursor<'blob>: Sized {
     #[must_use]
     fn parents(&self) -> Self::Parents<'_>;
 
+    /// Writes the path of the current node into the fixed-capacity buffer.
+    ///
+    /// Unlike `TreeCursorAllocExt::path`, this does not allocate. The buffer
+    /// is left unchanged if the path exceeds its capacity.
+    fn path_into<const N: usize>(
+        &self,
+        path: &mut NodePathBuf<N>,
+    ) -> Result<(), PathOverflowError> {
+        path.set_from_rev_components(|| {
+            self.parents()
+                .filter(|parent| !parent.is_root())
+                .map(|parent| parent.full_name())
+        })
+    }
+
     fn reset(&mut self);
     fn seek_node_start(&mut self);
     fn seek_node_end(&mut self) -> Result<(), ReadTreeError>;
     fn seek_root_start(&mut self);
     fn seek_parent_start(&mut self) -> Option<()>;
     fn seek_parent_next(&mut self) -> Result<Option<()>, ReadTreeError>;
+
+    /// Skips the rest of the current node and its descendants, and moves the
+    /// cursor to the next item of the parent node.
+    ///
+    /// Works like [`Self::seek_parent_next`], but the skipped properties and
+    /// nodes need not be read one by one. The cursor may skip them at the
+    /// token level, so malformed items inside the subtree may go unreported.
+    ///
+    /// Returns `None` if the current node is the root node.
+    fn skip_subtree(&mut self) -> Result<Option<()>, ReadTreeError> {
+        self.seek_parent_next()
+    }
+
     fn read_item_descend(&mut self) -> Result<Option<Item<'blob>>, ReadTreeError>;
 
     fn read_tree_item_ref_descend(
@@ -112,6 +142,21 @@ pub trait TreeCursor<'blob>: Sized {
         iter::ReadDescendantNodesByGlob::new(self, glob)
     }
 
+    /// Returns the descendant nodes whose `compatible` property matches the
+    /// pattern.
+    ///
+    /// See [`Compatible::matches`] for the matching rules.
+    #[must_use]
+    fn read_descendant_nodes_by_compatible<'pat, P>(
+        &mut self,
+        pattern: &'pat P,
+    ) -> iter::ReadDescendantNodesByCompatible<'_, 'pat, 'blob, Self>
+    where
+        P: AsRef<[u8]> + ?Sized + 'pat,
+    {
+        iter::ReadDescendantNodesByCompatible::new(self, pattern)
+    }
+
     fn read_node_by_phandle(
         &mut self,
         phandle: Phandle,
@@ -120,8 +165,7 @@ pub trait TreeCursor<'blob>: Sized {
             .read_descendant_properties()
             .find(|property| {
                 property.as_ref().map_or(true, |property| {
-                    property.name() == "phandle"
-                        && property.value() == phandle.value().to_be_bytes()
+                    property.name() == "phandle" && property.value_eq_u32(phandle.value())
                 })
             })
             .transpose()?;
@@ -132,6 +176,47 @@ pub trait TreeCursor<'blob>: Sized {
         Ok(Some(TreeNodeRef::new(self.node(), self)))
     }
 
+    /// Reads the `index`-th child of the current node with the name.
+    ///
+    /// A name containing a unit address is compared with the full names of
+    /// the children, and other names with the names without the unit
+    /// addresses.
+    fn read_nth_child_by_name<N>(
+        &mut self,
+        name: &N,
+        index: usize,
+    ) -> Result<Option<TreeNodeRef<'_, 'blob, Self>>, ReadTreeError>
+    where
+        N: AsRef<ByteStr> + ?Sized,
+    {
+        let name = name.as_ref();
+        let component = if name.contains(&UNIT_ADDRESS_SEPARATOR) {
+            GlobComponent::FullName(name)
+        } else {
+            GlobComponent::Name(name)
+        };
+        self.seek_node_start();
+        let mut children = self.read_children();
+        let mut remaining = index;
+        let child = loop {
+            let Some(child) = children.next().transpose()? else {
+                break None;
+            };
+            if !component.match_node(&child) {
+                continue;
+            }
+            if remaining == 0 {
+                break Some(child);
+            }
+            remaining -= 1;
+        };
+        let Some(child) = child else {
+            self.seek_node_start();
+            return Ok(None);
+        };
+        Ok(Some(TreeNodeRef::new(child, self)))
+    }
+
     fn read_node_by_path<'path, P>(
         &mut self,
         path: &'path P,
@@ -153,6 +238,70 @@ pub trait TreeCursor<'blob>: Sized {
     }
 }
 
+/// Returns `true` if the current node of the cursor has no `status`
+/// property, or its `status` is `"okay"` or `"ok"`.
+///
+/// The cursor is left at the start of the node.
+pub(crate) fn is_node_enabled<'blob, TC>(tree_cursor: &mut TC) -> Result<bool, ReadTreeError>
+where
+    TC: TreeCursor<'blob>,
+{
+    let Some(status) = read_node_property(tree_cursor, "status")? else {
+        return Ok(true);
+    };
+    let enabled = polyfill::slice_split_once(status.value(), |&c| c == 0)
+        .and_then(|(status, _)| Status::from_bytes(status))
+        .is_some_and(|status| status.is_enabled());
+    Ok(enabled)
+}
+
+/// Returns `true` if the `compatible` property of the current node of the
+/// cursor matches the pattern.
+///
+/// See [`Compatible::matches`] for the matching rules. The cursor is left at
+/// the start of the node.
+pub(crate) fn is_node_compatible<'blob, TC>(
+    tree_cursor: &mut TC,
+    pattern: &ByteStr,
+) -> Result<bool, ReadTreeError>
+where
+    TC: TreeCursor<'blob>,
+{
+    let Some(compatible) = read_node_property(tree_cursor, "compatible")? else {
+        return Ok(false);
+    };
+    let compatible = Compatible::new(ByteStrList::new(ByteStr::new(compatible.value())));
+    Ok(compatible.matches(pattern))
+}
+
+/// Returns the property of the current node of the cursor.
+///
+/// The cursor is left at the start of the node.
+fn read_node_property<'blob, TC>(
+    tree_cursor: &mut TC,
+    name: &str,
+) -> Result<Option<Property<'blob>>, ReadTreeError>
+where
+    TC: TreeCursor<'blob>,
+{
+    tree_cursor.seek_node_start();
+    let mut found = None;
+    // the properties precede the child nodes
+    while let Some(item) = tree_cursor.read_item_descend()? {
+        let Item::Property(property) = item else {
+            // the read entered the child node
+            tree_cursor.seek_parent_start();
+            break;
+        };
+        if property.name() == name {
+            found = Some(property);
+            break;
+        }
+    }
+    tree_cursor.seek_node_start();
+    Ok(found)
+}
+
 pub trait TreeIterator<'blob>: Iterator {
     type TreeCursor: TreeCursor<'blob>;
 
@@ -293,6 +442,26 @@ where
         self.tree_cursor
     }
 
+    /// Returns `true` if the node has no `status` property, or its `status`
+    /// is `"okay"` or `"ok"`.
+    pub fn is_enabled(&mut self) -> Result<bool, ReadTreeError> {
+        is_node_enabled(self.tree_cursor)
+    }
+
+    /// Reads the property of the node and deserializes its value.
+    ///
+    /// Returns `None` if the node has no such property.
+    pub fn read_property<T>(&mut self, name: &str) -> Result<Option<T>, DeserializeError>
+    where
+        T: DeserializeProperty<'blob>,
+    {
+        let Some(property) = read_node_property(self.tree_cursor, name)? else {
+            return Ok(None);
+        };
+        let mut de = DefaultPropertyDeserializer::new(property, self.tree_cursor);
+        T::deserialize_property(&mut de).map(Some)
+    }
+
     #[must_use]
     pub fn node_deserializer(&mut self) -> DefaultNodeDeserializer<'_, 'blob, TC> {
         DefaultNodeDeserializer::new(self.node.clone(), self.tree_cursor)
@@ -2,10 +2,10 @@ use core::{iter, marker::PhantomData, slice};
 
 use crate::{
     blob::{Item, Node},
-    node_stack::{NodeStack, types::ArrayNodeStack},
+    node_stack::{NodeStack, error::StackOverflowError, types::ArrayNodeStack},
     token_cursor::{Token, TokenCursor},
     tree_cursor::{
-        TreeCursor,
+        RawNode, TreeCursor,
         error::{ReadTreeError, ReadTreeErrorKind},
     },
 };
@@ -87,6 +87,71 @@ where
             _phantom: PhantomData,
         })
     }
+
+    /// Returns a handle to the current node.
+    ///
+    /// The cursor can jump back to the node later with [`Self::seek_to`],
+    /// without walking the tree from the root again. Returns `None` if the
+    /// node stack cannot be cloned.
+    #[must_use]
+    pub fn handle(&self) -> Option<StackBasedNodeHandle<S>> {
+        let node_stack = self.node_stack.try_clone()?;
+        Some(StackBasedNodeHandle { node_stack })
+    }
+
+    /// Moves the cursor to the start of the node pointed by the handle.
+    ///
+    /// The handle must be created by a cursor over the same devicetree. The
+    /// cursor is left unchanged if the node is deeper than the capacity of
+    /// the node stack.
+    pub fn seek_to<H>(&mut self, handle: &StackBasedNodeHandle<H>) -> Result<(), StackOverflowError>
+    where
+        H: NodeStack<TC::NodeHandle>,
+    {
+        self.node_stack.clone_from_stack(&handle.node_stack)?;
+        self.seek_node_start();
+        Ok(())
+    }
+
+    /// Moves the cursor to the start of the node pointed by the raw node
+    /// handle.
+    ///
+    /// The handle must be created from the same devicetree. The cursor is
+    /// moved to the root node if the node is deeper than the capacity of the
+    /// node stack.
+    pub fn seek_raw_node(&mut self, node: &RawNode<'blob>) -> Result<(), StackOverflowError> {
+        self.node_stack.clear();
+        for node in node.nodes() {
+            let node_ref = self.token_cursor.make_node_handle(node);
+            if let Err(e) = self.node_stack.push(node_ref) {
+                self.seek_root_start();
+                return Err(e);
+            }
+        }
+        self.seek_node_start();
+        Ok(())
+    }
+}
+
+/// Handle to a node, created by [`StackBasedTreeCursor::handle`].
+///
+/// The handle holds the node and all its parents, so that the cursor can be
+/// restored to the node in constant time.
+#[derive(Debug, Clone)]
+pub struct StackBasedNodeHandle<S> {
+    node_stack: S,
+}
+
+impl<S> StackBasedNodeHandle<S> {
+    /// Returns the depth of the node pointed by the handle.
+    #[must_use]
+    pub fn depth<T>(&self) -> usize
+    where
+        S: NodeStack<T>,
+    {
+        // the handle is taken from a cursor, whose stack holds the root node
+        self.node_stack.len().saturating_sub(1)
+    }
 }
 
 impl<'blob, TC, S> TreeCursor<'blob> for StackBasedTreeCursor<'blob, TC, S>
@@ -179,6 +244,20 @@ where
         Ok(Some(()))
     }
 
+    fn skip_subtree(&mut self) -> Result<Option<()>, ReadTreeError> {
+        if self.node_stack.len() <= 1 {
+            return Ok(None);
+        }
+        if self.state != ReadState::Done {
+            let res = self.skip_node_tokens();
+            self.state = ReadState::Done;
+            res?;
+        }
+        self.node_stack.pop().unwrap();
+        self.state = ReadState::Child;
+        Ok(Some(()))
+    }
+
     fn read_item_descend(&mut self) -> Result<Option<Item<'blob>>, ReadTreeError> {
         let res = self.read_item_descend_inner();
         if res.is_err() {
@@ -237,6 +316,20 @@ where
             }
         }
     }
+
+    fn skip_node_tokens(&mut self) -> Result<(), ReadTreeError> {
+        let skipped = self
+            .token_cursor
+            .skip_node()
+            .map_err(|source| ReadTreeErrorKind::ReadToken { source })?;
+        ensure!(
+            skipped.is_some(),
+            ReadTreeErrorKind::UnexpectedEndOfTokens {
+                position: self.token_cursor.position()
+            }
+        );
+        Ok(())
+    }
 }
 
 pub struct StackBasedParents<'tc, 'blob, TC>
@@ -286,6 +379,7 @@ mod tests {
         blob::Property,
         node_stack::types::SliceNodeStack,
         testing::{SliceNodeHandle, SliceTokenCursor},
+        util::NodePathBuf,
     };
 
     fn walk_tree<'blob, TC>(
@@ -543,6 +637,112 @@ mod tests {
         assert_eq!(&parents, &["child3", "child2", "child1", "root"]);
     }
 
+    #[test]
+    fn test_handle_seek_to() {
+        let tokens = &[
+            Token::BeginNode(Node::new("")),
+            Token::BeginNode(Node::new("soc")),
+            Token::BeginNode(Node::new("serial@10000000")),
+            Token::Property(Property::new("reg", "value")),
+            Token::EndNode,
+            Token::EndNode,
+            Token::BeginNode(Node::new("chosen")),
+            Token::EndNode,
+            Token::EndNode,
+        ];
+        let tokens = SliceTokenCursor::new(tokens);
+        let mut cursor = StackBasedTreeCursor::new(tokens.clone()).unwrap();
+
+        cursor.read_item_descend().unwrap(); // soc
+        cursor.read_item_descend().unwrap(); // serial@10000000
+        let handle = cursor.handle().unwrap();
+        assert_eq!(handle.depth(), 2);
+
+        cursor.seek_parent_next().unwrap().unwrap();
+        cursor.seek_parent_next().unwrap().unwrap();
+        assert_eq!(
+            cursor.read_item_descend().unwrap(),
+            Some(Item::Node(Node::new("chosen")))
+        );
+
+        cursor.seek_to(&handle).unwrap();
+        assert_eq!(cursor.node(), Node::new("serial@10000000"));
+        assert_eq!(cursor.depth(), 2);
+        let parents: Vec<_> = cursor.parents().map(|p| p.name()).collect();
+        assert_eq!(&parents, &["serial", "soc", ""]);
+        assert_eq!(
+            cursor.read_item_descend().unwrap(),
+            Some(Item::Property(Property::new("reg", "value")))
+        );
+        assert!(cursor.read_item_descend().unwrap().is_none());
+        cursor.seek_parent_next().unwrap().unwrap();
+        assert_eq!(cursor.read_item_descend().unwrap(), None);
+
+        let mut shallow =
+            StackBasedTreeCursor::<_, ArrayNodeStack<_, 2>>::with_stack_size(tokens).unwrap();
+        assert!(shallow.seek_to(&handle).is_err());
+        assert_eq!(shallow.node(), Node::new(""));
+    }
+
+    #[test]
+    fn test_seek_raw_node() {
+        let tokens = &[
+            Token::BeginNode(Node::new("")),
+            Token::BeginNode(Node::new("soc")),
+            Token::BeginNode(Node::new("serial@10000000")),
+            Token::Property(Property::new("reg", "value")),
+            Token::EndNode,
+            Token::EndNode,
+            Token::EndNode,
+        ];
+        let tokens = SliceTokenCursor::new(tokens);
+        let mut cursor = StackBasedTreeCursor::new(tokens.clone()).unwrap();
+        cursor.read_item_descend().unwrap(); // soc
+        cursor.read_item_descend().unwrap(); // serial@10000000
+        let raw = RawNode::from_tree_cursor(&cursor).unwrap();
+
+        let mut other = StackBasedTreeCursor::new(tokens.clone()).unwrap();
+        other.seek_raw_node(&raw).unwrap();
+        assert_eq!(other.node(), Node::new("serial@10000000"));
+        assert_eq!(other.depth(), 2);
+        assert_eq!(
+            other.read_item_descend().unwrap(),
+            Some(Item::Property(Property::new("reg", "value")))
+        );
+
+        let mut shallow =
+            StackBasedTreeCursor::<_, ArrayNodeStack<_, 2>>::with_stack_size(tokens).unwrap();
+        assert!(shallow.seek_raw_node(&raw).is_err());
+        assert_eq!(shallow.node(), Node::new(""));
+    }
+
+    #[test]
+    fn test_path_into() {
+        let tokens = &[
+            Token::BeginNode(Node::new("")),
+            Token::BeginNode(Node::new("soc")),
+            Token::BeginNode(Node::new("serial@10000000")),
+            Token::EndNode,
+            Token::EndNode,
+            Token::EndNode,
+        ];
+        let tokens = SliceTokenCursor::new(tokens);
+        let mut cursor = StackBasedTreeCursor::new(tokens).unwrap();
+
+        let mut path = NodePathBuf::<32>::new();
+        cursor.path_into(&mut path).unwrap();
+        assert_eq!(path, "");
+
+        cursor.read_item_descend().unwrap(); // soc
+        cursor.read_item_descend().unwrap(); // serial@10000000
+        cursor.path_into(&mut path).unwrap();
+        assert_eq!(path, "/soc/serial@10000000");
+
+        let mut small = NodePathBuf::<8>::new();
+        assert!(cursor.path_into(&mut small).is_err());
+        assert_eq!(small, "");
+    }
+
     #[test]
     fn test_reset() {
         let tokens = &[
@@ -662,6 +862,53 @@ mod tests {
         assert!(cursor.seek_parent_next().unwrap().is_none());
     }
 
+    #[test]
+    fn test_skip_subtree() {
+        let tokens = &[
+            Token::BeginNode(Node::new("root")),
+            Token::BeginNode(Node::new("child1")),
+            Token::Property(Property::new("child1_prop", "value")),
+            Token::BeginNode(Node::new("grandchild")),
+            Token::Property(Property::new("grandchild_prop", "value")),
+            Token::EndNode,
+            Token::EndNode,
+            Token::BeginNode(Node::new("child2")),
+            Token::EndNode,
+            Token::EndNode,
+        ];
+        let tokens = SliceTokenCursor::new(tokens);
+        let mut cursor = StackBasedTreeCursor::new(tokens).unwrap();
+
+        cursor.read_item_descend().unwrap(); // child1
+        assert_eq!(cursor.node().name(), "child1");
+        cursor.skip_subtree().unwrap().unwrap();
+        assert_eq!(cursor.depth(), 0);
+        let item = cursor.read_item_descend().unwrap().unwrap();
+        assert!(matches!(item, Item::Node(n) if n.name() == "child2"));
+
+        // the end of the node is already read
+        assert!(cursor.read_item_descend().unwrap().is_none());
+        cursor.skip_subtree().unwrap().unwrap();
+        assert!(cursor.read_item_descend().unwrap().is_none());
+
+        assert!(cursor.skip_subtree().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_skip_subtree_unexpected_end_of_tokens() {
+        let tokens = &[
+            Token::BeginNode(Node::new("root")),
+            Token::BeginNode(Node::new("child")),
+            Token::Property(Property::new("prop", "value")),
+        ];
+        let tokens = SliceTokenCursor::new(tokens);
+        let mut cursor = StackBasedTreeCursor::new(tokens).unwrap();
+        cursor.read_item_descend().unwrap(); // child
+        let err = cursor.skip_subtree().unwrap_err();
+        assert!(err.kind().is_unexpected_end_of_tokens(), "err: {err:?}");
+        assert!(cursor.read_item_descend().unwrap().is_none());
+    }
+
     #[test]
     fn test_empty_node() {
         let tokens = &[Token::BeginNode(Node::new("empty")), Token::EndNode];
@@ -1,5 +1,7 @@
 #[cfg(feature = "alloc")]
 pub use self::aligned_buffer::*;
+pub use self::node_path_buf::*;
 
 #[cfg(feature = "alloc")]
 mod aligned_buffer;
+mod node_path_buf;
@@ -0,0 +1,208 @@
+use core::{fmt, ops::Deref};
+
+use crate::{blob::PATH_SEPARATOR, types::ByteStr};
+
+#[derive(Debug, derive_more::Display, derive_more::Error)]
+#[display("node path exceeds the capacity")]
+pub struct PathOverflowError;
+
+/// Fixed-capacity node path buffer.
+///
+/// Holds an absolute path such as `/soc/serial@10000000` without allocating,
+/// so that the path of the current node can be recorded while walking the
+/// tree. The path of the root node is empty, in the same way as
+/// [`TreeCursorAllocExt::path`](crate::tree_cursor::TreeCursorAllocExt::path).
+#[derive(Clone, Copy)]
+pub struct NodePathBuf<const N: usize> {
+    bytes: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> Default for NodePathBuf<N> {
+    fn default() -> Self {
+        Self {
+            bytes: [0; N],
+            len: 0,
+        }
+    }
+}
+
+impl<const N: usize> NodePathBuf<N> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    #[must_use]
+    pub fn as_byte_str(&self) -> &ByteStr {
+        ByteStr::new(&self.bytes[..self.len])
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// Appends a node name as the last component of the path.
+    ///
+    /// The path is left unchanged if the capacity is exceeded.
+    pub fn push<C>(&mut self, component: C) -> Result<(), PathOverflowError>
+    where
+        C: AsRef<[u8]>,
+    {
+        let component = component.as_ref();
+        let end = self
+            .len
+            .checked_add(1 + component.len())
+            .filter(|&end| end <= N)
+            .ok_or(PathOverflowError)?;
+        self.bytes[self.len] = PATH_SEPARATOR;
+        self.bytes[self.len + 1..end].copy_from_slice(component);
+        self.len = end;
+        Ok(())
+    }
+
+    /// Removes the last component of the path.
+    ///
+    /// Returns `false` if the path is already empty.
+    pub fn pop(&mut self) -> bool {
+        let path = &self.bytes[..self.len];
+        let Some(sep) = path.iter().rposition(|&b| b == PATH_SEPARATOR) else {
+            return false;
+        };
+        self.len = sep;
+        true
+    }
+
+    /// Replaces the path with the components yielded from the last one to
+    /// the first one.
+    ///
+    /// The path is left unchanged if the capacity is exceeded.
+    pub(crate) fn set_from_rev_components<'a, F, I>(
+        &mut self,
+        components: F,
+    ) -> Result<(), PathOverflowError>
+    where
+        F: Fn() -> I,
+        I: Iterator<Item = &'a ByteStr>,
+    {
+        let len = components()
+            .try_fold(0_usize, |len, c| len.checked_add(1 + c.len()))
+            .filter(|&len| len <= N)
+            .ok_or(PathOverflowError)?;
+        let mut end = len;
+        for component in components() {
+            let start = end - component.len();
+            self.bytes[start..end].copy_from_slice(component);
+            self.bytes[start - 1] = PATH_SEPARATOR;
+            end = start - 1;
+        }
+        self.len = len;
+        Ok(())
+    }
+}
+
+impl<const N: usize> Deref for NodePathBuf<N> {
+    type Target = ByteStr;
+
+    fn deref(&self) -> &Self::Target {
+        self.as_byte_str()
+    }
+}
+
+impl<const N: usize> AsRef<ByteStr> for NodePathBuf<N> {
+    fn as_ref(&self) -> &ByteStr {
+        self.as_byte_str()
+    }
+}
+
+impl<const N: usize> AsRef<[u8]> for NodePathBuf<N> {
+    fn as_ref(&self) -> &[u8] {
+        self.as_byte_str()
+    }
+}
+
+impl<const N: usize, const M: usize> PartialEq<NodePathBuf<M>> for NodePathBuf<N> {
+    fn eq(&self, other: &NodePathBuf<M>) -> bool {
+        self.as_byte_str() == other.as_byte_str()
+    }
+}
+
+impl<const N: usize> Eq for NodePathBuf<N> {}
+
+impl<const N: usize> PartialEq<str> for NodePathBuf<N> {
+    fn eq(&self, other: &str) -> bool {
+        self.as_byte_str() == other
+    }
+}
+
+impl<const N: usize> PartialEq<&str> for NodePathBuf<N> {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_byte_str() == *other
+    }
+}
+
+impl<const N: usize> fmt::Debug for NodePathBuf<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_byte_str(), f)
+    }
+}
+
+impl<const N: usize> fmt::Display for NodePathBuf<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self.as_byte_str(), f)
+    }
+}
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_pop() {
+        let mut path = NodePathBuf::<32>::new();
+        assert!(path.is_empty());
+        path.push("soc").unwrap();
+        path.push("serial@10000000").unwrap();
+        assert_eq!(path, "/soc/serial@10000000");
+        assert!(path.pop());
+        assert_eq!(path, "/soc");
+        assert!(path.pop());
+        assert_eq!(path, "");
+        assert!(!path.pop());
+    }
+
+    #[test]
+    fn test_overflow() {
+        let mut path = NodePathBuf::<10>::new();
+        path.push("cpus").unwrap();
+        assert!(path.push("cpu@0").is_err());
+        assert_eq!(path, "/cpus");
+        path.push("cpu").unwrap();
+        assert_eq!(path, "/cpus/cpu");
+        assert_eq!(path.capacity(), 10);
+    }
+
+    #[test]
+    fn test_set_from_rev_components() {
+        let mut path = NodePathBuf::<16>::new();
+        let components = [ByteStr::new("c"), ByteStr::new("bb"), ByteStr::new("a")];
+        path.set_from_rev_components(|| components.iter().copied())
+            .unwrap();
+        assert_eq!(path, "/a/bb/c");
+
+        let mut small = NodePathBuf::<6>::new();
+        small.push("x").unwrap();
+        assert!(
+            small
+                .set_from_rev_components(|| components.iter().copied())
+                .is_err()
+        );
+        assert_eq!(small, "/x");
+    }
+}
@@ -0,0 +1,584 @@
+//! Parser for 64-bit little-endian ELF files.
+//!
+//! Only the parts needed to load an executable are decoded: the file header
+//! and the program header table. Each program header describes a segment, a
+//! range of the file mapped at a virtual address, with the bytes beyond the
+//! file contents up to the memory size filled with zeros. Section headers are
+//! ignored.
+
+#![cfg_attr(coverage_nightly, feature(coverage_attribute))]
+#![no_std]
+
+use core::{iter::FusedIterator, slice};
+
+use bitflags::bitflags;
+use dataview::{DataView, Pod};
+use endian::Le;
+
+const MAGIC: &[u8] = b"\x7fELF";
+const CLASS_64: u8 = 2;
+const DATA_LSB: u8 = 1;
+const VERSION_CURRENT: u8 = 1;
+const HEADER_LEN: usize = 64;
+const PROGRAM_HEADER_LEN: usize = 56;
+
+const _: () = {
+    assert!(HEADER_LEN == size_of::<FileHeader>());
+    assert!(PROGRAM_HEADER_LEN == size_of::<RawProgramHeader>());
+};
+
+/// File header, at the start of the file.
+#[repr(C)]
+#[derive(Debug, Pod)]
+struct FileHeader {
+    ident: [u8; 16],
+    file_type: Le<u16>,
+    machine: Le<u16>,
+    version: Le<u32>,
+    entry: Le<u64>,
+    program_header_offset: Le<u64>,
+    section_header_offset: Le<u64>,
+    flags: Le<u32>,
+    header_size: Le<u16>,
+    program_header_size: Le<u16>,
+    program_header_count: Le<u16>,
+    section_header_size: Le<u16>,
+    section_header_count: Le<u16>,
+    section_name_index: Le<u16>,
+}
+
+/// Entry of the program header table, as stored in the file.
+#[repr(C)]
+#[derive(Debug, Pod)]
+struct RawProgramHeader {
+    segment_type: Le<u32>,
+    flags: Le<u32>,
+    offset: Le<u64>,
+    vaddr: Le<u64>,
+    paddr: Le<u64>,
+    file_size: Le<u64>,
+    mem_size: Le<u64>,
+    align: Le<u64>,
+}
+
+/// Machine type of RISC-V.
+pub const EM_RISCV: u16 = 243;
+
+/// Errors that can occur while parsing an ELF file.
+#[derive(Debug, Clone, PartialEq, Eq, derive_more::Display, derive_more::Error)]
+#[non_exhaustive]
+pub enum ReadElfError {
+    #[display("truncated file header")]
+    TruncatedHeader,
+    #[display("invalid magic number")]
+    InvalidMagic,
+    #[display("unsupported class: {class}")]
+    UnsupportedClass { class: u8 },
+    #[display("unsupported data encoding: {encoding}")]
+    UnsupportedEncoding { encoding: u8 },
+    #[display("unsupported version: {version}")]
+    UnsupportedVersion { version: u32 },
+    #[display("invalid program header size: {size}")]
+    InvalidProgramHeaderSize { size: u16 },
+    #[display("truncated program header table: offset={offset}, count={count}")]
+    TruncatedProgramHeaders { offset: u64, count: u16 },
+    #[display("truncated segment: index={index}")]
+    TruncatedSegment { index: usize },
+    #[display("segment larger in the file than in memory: index={index}")]
+    InvalidSegmentSize { index: usize },
+    #[display("segment exceeds the address space: index={index}")]
+    SegmentOverflow { index: usize },
+}
+
+/// Type of an ELF file, decoded from `e_type`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FileType {
+    None,
+    Relocatable,
+    Executable,
+    SharedObject,
+    Core,
+    Unknown(u16),
+}
+
+impl FileType {
+    #[must_use]
+    pub fn from_raw(value: u16) -> Self {
+        match value {
+            0 => Self::None,
+            1 => Self::Relocatable,
+            2 => Self::Executable,
+            3 => Self::SharedObject,
+            4 => Self::Core,
+            ty => Self::Unknown(ty),
+        }
+    }
+}
+
+/// Type of a segment, decoded from `p_type`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SegmentType {
+    Null,
+    /// Segment mapped into memory.
+    Load,
+    Dynamic,
+    /// Path of the program interpreter.
+    Interp,
+    Note,
+    /// The program header table itself.
+    Phdr,
+    /// Thread-local storage template.
+    Tls,
+    Unknown(u32),
+}
+
+impl SegmentType {
+    #[must_use]
+    pub fn from_raw(value: u32) -> Self {
+        match value {
+            0 => Self::Null,
+            1 => Self::Load,
+            2 => Self::Dynamic,
+            3 => Self::Interp,
+            4 => Self::Note,
+            6 => Self::Phdr,
+            7 => Self::Tls,
+            ty => Self::Unknown(ty),
+        }
+    }
+}
+
+bitflags! {
+    /// Memory access permissions of a segment.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct SegmentFlags: u32 {
+        const X = 1 << 0;
+        const W = 1 << 1;
+        const R = 1 << 2;
+    }
+}
+
+/// An ELF file stored in memory.
+#[derive(Debug, Clone, Copy)]
+pub struct Elf<'a> {
+    data: &'a [u8],
+    file_type: FileType,
+    machine: u16,
+    entry: u64,
+    program_headers: &'a [u8],
+}
+
+impl<'a> Elf<'a> {
+    /// Parses the file header of an ELF file.
+    ///
+    /// The program header table is checked to be in the file, while the
+    /// segments are validated lazily while iterating over the program headers.
+    pub fn parse(data: &'a [u8]) -> Result<Self, ReadElfError> {
+        // the file may not be aligned, so the header is copied
+        let header = DataView::from(data)
+            .try_read::<FileHeader>(0)
+            .ok_or(ReadElfError::TruncatedHeader)?;
+        let ident = &header.ident;
+        if !ident.starts_with(MAGIC) {
+            return Err(ReadElfError::InvalidMagic);
+        }
+        if ident[4] != CLASS_64 {
+            return Err(ReadElfError::UnsupportedClass { class: ident[4] });
+        }
+        if ident[5] != DATA_LSB {
+            return Err(ReadElfError::UnsupportedEncoding { encoding: ident[5] });
+        }
+        let version = header.version.read();
+        if ident[6] != VERSION_CURRENT || version != u32::from(VERSION_CURRENT) {
+            return Err(ReadElfError::UnsupportedVersion { version });
+        }
+
+        let offset = header.program_header_offset.read();
+        let size = header.program_header_size.read();
+        let count = header.program_header_count.read();
+        if count > 0 && usize::from(size) != PROGRAM_HEADER_LEN {
+            return Err(ReadElfError::InvalidProgramHeaderSize { size });
+        }
+        let program_headers = usize::try_from(offset)
+            .ok()
+            .and_then(|start| {
+                let end = start.checked_add(usize::from(count) * PROGRAM_HEADER_LEN)?;
+                data.get(start..end)
+            })
+            .ok_or(ReadElfError::TruncatedProgramHeaders { offset, count })?;
+
+        Ok(Self {
+            data,
+            file_type: FileType::from_raw(header.file_type.read()),
+            machine: header.machine.read(),
+            entry: header.entry.read(),
+            program_headers,
+        })
+    }
+
+    #[must_use]
+    pub fn file_type(&self) -> FileType {
+        self.file_type
+    }
+
+    /// Returns the machine type, e.g. [`EM_RISCV`].
+    #[must_use]
+    pub fn machine(&self) -> u16 {
+        self.machine
+    }
+
+    /// Returns the virtual address of the entry point.
+    #[must_use]
+    pub fn entry(&self) -> u64 {
+        self.entry
+    }
+
+    /// Returns an iterator over the program headers.
+    ///
+    /// The iterator stops after the first error.
+    #[must_use]
+    pub fn program_headers(&self) -> ProgramHeaders<'a> {
+        ProgramHeaders {
+            data: self.data,
+            headers: self.program_headers.as_chunks().0.iter(),
+            index: 0,
+            finished: false,
+        }
+    }
+}
+
+/// A program header, describing a segment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProgramHeader<'a> {
+    segment_type: SegmentType,
+    flags: SegmentFlags,
+    vaddr: u64,
+    mem_size: u64,
+    align: u64,
+    data: &'a [u8],
+}
+
+impl<'a> ProgramHeader<'a> {
+    #[must_use]
+    pub fn segment_type(&self) -> SegmentType {
+        self.segment_type
+    }
+
+    /// Returns the access permissions of the segment.
+    ///
+    /// Unknown bits are retained.
+    #[must_use]
+    pub fn flags(&self) -> SegmentFlags {
+        self.flags
+    }
+
+    /// Returns the virtual address of the first byte of the segment.
+    #[must_use]
+    pub fn vaddr(&self) -> u64 {
+        self.vaddr
+    }
+
+    /// Returns the size of the segment in memory, which is not less than the
+    /// size of its contents in the file.
+    #[must_use]
+    pub fn mem_size(&self) -> u64 {
+        self.mem_size
+    }
+
+    #[must_use]
+    pub fn align(&self) -> u64 {
+        self.align
+    }
+
+    /// Returns the contents of the segment in the file.
+    ///
+    /// The rest of the segment in memory is filled with zeros.
+    #[must_use]
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+}
+
+/// Iterator over the program headers of an [`Elf`].
+#[derive(Debug, Clone)]
+pub struct ProgramHeaders<'a> {
+    data: &'a [u8],
+    headers: slice::Iter<'a, [u8; PROGRAM_HEADER_LEN]>,
+    index: usize,
+    finished: bool,
+}
+
+impl<'a> ProgramHeaders<'a> {
+    fn read_header(
+        &self,
+        header: &[u8; PROGRAM_HEADER_LEN],
+    ) -> Result<ProgramHeader<'a>, ReadElfError> {
+        let index = self.index;
+        let header = DataView::from(header.as_slice()).read::<RawProgramHeader>(0);
+        let offset = header.offset.read();
+        let vaddr = header.vaddr.read();
+        let file_size = header.file_size.read();
+        let mem_size = header.mem_size.read();
+        if file_size > mem_size {
+            return Err(ReadElfError::InvalidSegmentSize { index });
+        }
+        if vaddr.checked_add(mem_size).is_none() {
+            return Err(ReadElfError::SegmentOverflow { index });
+        }
+        let data = usize::try_from(offset)
+            .ok()
+            .zip(usize::try_from(file_size).ok())
+            .and_then(|(start, len)| self.data.get(start..start.checked_add(len)?))
+            .ok_or(ReadElfError::TruncatedSegment { index })?;
+
+        Ok(ProgramHeader {
+            segment_type: SegmentType::from_raw(header.segment_type.read()),
+            flags: SegmentFlags::from_bits_retain(header.flags.read()),
+            vaddr,
+            mem_size,
+            align: header.align.read(),
+            data,
+        })
+    }
+}
+
+impl<'a> Iterator for ProgramHeaders<'a> {
+    type Item = Result<ProgramHeader<'a>, ReadElfError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        let Some(header) = self.headers.next() else {
+            self.finished = true;
+            return None;
+        };
+        let res = self.read_header(header);
+        self.index += 1;
+        if res.is_err() {
+            self.finished = true;
+        }
+        Some(res)
+    }
+}
+
+impl FusedIterator for ProgramHeaders<'_> {}
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+#[cfg(test)]
+mod tests {
+    extern crate alloc;
+
+    use alloc::vec::Vec;
+
+    use super::*;
+
+    struct Segment<'a> {
+        kind: u32,
+        flags: u32,
+        vaddr: u64,
+        mem_size: u64,
+        data: &'a [u8],
+    }
+
+    fn load(flags: u32, vaddr: u64, mem_size: u64, data: &[u8]) -> Segment<'_> {
+        Segment {
+            kind: 1,
+            flags,
+            vaddr,
+            mem_size,
+            data,
+        }
+    }
+
+    /// Builds an executable with the program header table right after the
+    /// file header, followed by the segment contents.
+    fn build(entry: u64, segments: &[Segment<'_>]) -> Vec<u8> {
+        let mut elf = Vec::new();
+        elf.extend_from_slice(MAGIC);
+        elf.extend_from_slice(&[CLASS_64, DATA_LSB, VERSION_CURRENT]);
+        elf.resize(16, 0);
+        elf.extend_from_slice(&2_u16.to_le_bytes());
+        elf.extend_from_slice(&EM_RISCV.to_le_bytes());
+        elf.extend_from_slice(&1_u32.to_le_bytes());
+        elf.extend_from_slice(&entry.to_le_bytes());
+        elf.extend_from_slice(&(HEADER_LEN as u64).to_le_bytes());
+        elf.extend_from_slice(&0_u64.to_le_bytes());
+        elf.extend_from_slice(&0_u32.to_le_bytes());
+        elf.extend_from_slice(&u16::try_from(HEADER_LEN).unwrap().to_le_bytes());
+        elf.extend_from_slice(&u16::try_from(PROGRAM_HEADER_LEN).unwrap().to_le_bytes());
+        elf.extend_from_slice(&u16::try_from(segments.len()).unwrap().to_le_bytes());
+        elf.resize(HEADER_LEN, 0);
+
+        let mut offset = HEADER_LEN + segments.len() * PROGRAM_HEADER_LEN;
+        for segment in segments {
+            elf.extend_from_slice(&segment.kind.to_le_bytes());
+            elf.extend_from_slice(&segment.flags.to_le_bytes());
+            elf.extend_from_slice(&(offset as u64).to_le_bytes());
+            elf.extend_from_slice(&segment.vaddr.to_le_bytes());
+            elf.extend_from_slice(&segment.vaddr.to_le_bytes());
+            elf.extend_from_slice(&(segment.data.len() as u64).to_le_bytes());
+            elf.extend_from_slice(&segment.mem_size.to_le_bytes());
+            elf.extend_from_slice(&0x1000_u64.to_le_bytes());
+            offset += segment.data.len();
+        }
+        for segment in segments {
+            elf.extend_from_slice(segment.data);
+        }
+        elf
+    }
+
+    #[test]
+    fn test_parse() {
+        let data = build(
+            0x1_0000,
+            &[
+                load(0b101, 0x1_0000, 4, b"\x13\x00\x00\x00"),
+                load(0b110, 0x2_0000, 0x100, b"data"),
+                Segment {
+                    kind: 0x6474_e551,
+                    flags: 0b110,
+                    vaddr: 0,
+                    mem_size: 0,
+                    data: b"",
+                },
+            ],
+        );
+        let elf = Elf::parse(&data).unwrap();
+        assert_eq!(elf.file_type(), FileType::Executable);
+        assert_eq!(elf.machine(), EM_RISCV);
+        assert_eq!(elf.entry(), 0x1_0000);
+
+        let headers = elf
+            .program_headers()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(headers.len(), 3);
+        assert_eq!(headers[0].segment_type(), SegmentType::Load);
+        assert_eq!(headers[0].flags(), SegmentFlags::R | SegmentFlags::X);
+        assert_eq!(headers[0].vaddr(), 0x1_0000);
+        assert_eq!(headers[0].mem_size(), 4);
+        assert_eq!(headers[0].data(), b"\x13\x00\x00\x00");
+        assert_eq!(headers[1].flags(), SegmentFlags::R | SegmentFlags::W);
+        assert_eq!(headers[1].mem_size(), 0x100);
+        assert_eq!(headers[1].align(), 0x1000);
+        assert_eq!(headers[1].data(), b"data");
+        assert_eq!(headers[2].segment_type(), SegmentType::Unknown(0x6474_e551));
+    }
+
+    #[test]
+    fn test_invalid_header() {
+        let data = build(0, &[]);
+        assert_eq!(
+            Elf::parse(&data[..HEADER_LEN - 1]).unwrap_err(),
+            ReadElfError::TruncatedHeader
+        );
+
+        let mut bad = data.clone();
+        bad[1] = b'e';
+        assert_eq!(Elf::parse(&bad).unwrap_err(), ReadElfError::InvalidMagic);
+
+        let mut bad = data.clone();
+        bad[4] = 1;
+        assert_eq!(
+            Elf::parse(&bad).unwrap_err(),
+            ReadElfError::UnsupportedClass { class: 1 }
+        );
+
+        let mut bad = data.clone();
+        bad[5] = 2;
+        assert_eq!(
+            Elf::parse(&bad).unwrap_err(),
+            ReadElfError::UnsupportedEncoding { encoding: 2 }
+        );
+
+        let mut bad = data;
+        bad[20] = 2;
+        assert_eq!(
+            Elf::parse(&bad).unwrap_err(),
+            ReadElfError::UnsupportedVersion { version: 2 }
+        );
+    }
+
+    #[test]
+    fn test_invalid_program_header_table() {
+        let data = build(0, &[load(0b100, 0x1000, 4, b"abcd")]);
+
+        let mut bad = data.clone();
+        bad[54] = 32;
+        assert_eq!(
+            Elf::parse(&bad).unwrap_err(),
+            ReadElfError::InvalidProgramHeaderSize { size: 32 }
+        );
+
+        let table_end = HEADER_LEN + PROGRAM_HEADER_LEN;
+        assert_eq!(
+            Elf::parse(&data[..table_end - 1]).unwrap_err(),
+            ReadElfError::TruncatedProgramHeaders {
+                offset: HEADER_LEN as u64,
+                count: 1
+            }
+        );
+
+        let mut bad = data;
+        bad[32..40].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert_eq!(
+            Elf::parse(&bad).unwrap_err(),
+            ReadElfError::TruncatedProgramHeaders {
+                offset: u64::MAX,
+                count: 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_invalid_segment() {
+        let data = build(
+            0,
+            &[
+                load(0b100, 0x1000, 4, b"abcd"),
+                load(0b100, 0x2000, 4, b"efgh"),
+            ],
+        );
+        let second = HEADER_LEN + PROGRAM_HEADER_LEN;
+
+        // the file is truncated in the contents of the second segment
+        let elf = Elf::parse(&data[..data.len() - 1]).unwrap();
+        let mut headers = elf.program_headers();
+        headers.next().unwrap().unwrap();
+        assert_eq!(
+            headers.next().unwrap().unwrap_err(),
+            ReadElfError::TruncatedSegment { index: 1 }
+        );
+        assert!(headers.next().is_none());
+
+        let mut bad = data.clone();
+        bad[second + 40..second + 48].copy_from_slice(&3_u64.to_le_bytes());
+        let elf = Elf::parse(&bad).unwrap();
+        assert_eq!(
+            elf.program_headers().nth(1).unwrap().unwrap_err(),
+            ReadElfError::InvalidSegmentSize { index: 1 }
+        );
+
+        let mut bad = data;
+        bad[second + 16..second + 24].copy_from_slice(&(u64::MAX - 2).to_le_bytes());
+        let elf = Elf::parse(&bad).unwrap();
+        assert_eq!(
+            elf.program_headers().nth(1).unwrap().unwrap_err(),
+            ReadElfError::SegmentOverflow { index: 1 }
+        );
+    }
+
+    #[test]
+    fn test_unknown_values() {
+        let mut data = build(0, &[load(0x8000_0004, 0x1000, 4, b"abcd")]);
+        data[16..18].copy_from_slice(&0xfe00_u16.to_le_bytes());
+        let elf = Elf::parse(&data).unwrap();
+        assert_eq!(elf.file_type(), FileType::Unknown(0xfe00));
+        let header = elf.program_headers().next().unwrap().unwrap();
+        assert_eq!(
+            header.flags(),
+            SegmentFlags::R | SegmentFlags::from_bits_retain(1 << 31)
+        );
+    }
+}
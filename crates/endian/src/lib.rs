@@ -10,7 +10,7 @@
 
 use core::fmt;
 
-use dataview::Pod;
+use dataview::{DataView, Pod};
 
 /// Trait for converting values between different byte orders.
 pub trait ByteOrder {
@@ -109,6 +109,131 @@ where
     }
 }
 
+macro_rules! impl_const_conversions {
+    ($($t:ty => $be:ident, $le:ident;)+) => {
+        $(
+            impl Be<$t> {
+                /// Creates a new big-endian wrapper from a native-endian
+                /// value, usable in const contexts.
+                #[must_use]
+                pub const fn from_native(value: $t) -> Self {
+                    Self(value.to_be())
+                }
+
+                /// Creates a new big-endian wrapper from the bytes stored
+                /// in big-endian byte order.
+                #[must_use]
+                pub const fn from_bytes(bytes: &[u8; size_of::<$t>()]) -> Self {
+                    Self(<$t>::from_ne_bytes(*bytes))
+                }
+
+                /// Returns the value in native endianness, usable in const
+                /// contexts.
+                #[must_use]
+                pub const fn to_native(self) -> $t {
+                    <$t>::from_be(self.0)
+                }
+            }
+
+            impl Le<$t> {
+                /// Creates a new little-endian wrapper from a native-endian
+                /// value, usable in const contexts.
+                #[must_use]
+                pub const fn from_native(value: $t) -> Self {
+                    Self(value.to_le())
+                }
+
+                /// Creates a new little-endian wrapper from the bytes stored
+                /// in little-endian byte order.
+                #[must_use]
+                pub const fn from_bytes(bytes: &[u8; size_of::<$t>()]) -> Self {
+                    Self(<$t>::from_ne_bytes(*bytes))
+                }
+
+                /// Returns the value in native endianness, usable in const
+                /// contexts.
+                #[must_use]
+                pub const fn to_native(self) -> $t {
+                    <$t>::from_le(self.0)
+                }
+            }
+
+            #[doc = concat!("Converts a native-endian `", stringify!($t), "` to big-endian at compile time.")]
+            #[must_use]
+            pub const fn $be(value: $t) -> Be<$t> {
+                Be::<$t>::from_native(value)
+            }
+
+            #[doc = concat!("Converts a native-endian `", stringify!($t), "` to little-endian at compile time.")]
+            #[must_use]
+            pub const fn $le(value: $t) -> Le<$t> {
+                Le::<$t>::from_native(value)
+            }
+        )+
+    };
+}
+
+impl_const_conversions! {
+    u8 => be_u8, le_u8;
+    u16 => be_u16, le_u16;
+    u32 => be_u32, le_u32;
+    u64 => be_u64, le_u64;
+    i8 => be_i8, le_i8;
+    i16 => be_i16, le_i16;
+    i32 => be_i32, le_i32;
+    i64 => be_i64, le_i64;
+}
+
+/// Views `bytes` as a slice of big-endian values.
+///
+/// Returns `None` if `bytes` is not aligned to `T` or its length is not a
+/// multiple of the size of `T`.
+///
+/// # Examples
+///
+/// ```
+/// use endian::{Be, slice_of_be};
+///
+/// #[repr(align(4))]
+/// struct Aligned([u8; 8]);
+///
+/// let bytes = Aligned([0, 0, 0, 1, 0, 0, 0, 2]);
+/// let cells = slice_of_be::<u32>(&bytes.0).unwrap();
+/// assert_eq!(cells, [Be::new(&1), Be::new(&2)]);
+/// assert!(slice_of_be::<u32>(&bytes.0[1..5]).is_none());
+/// assert!(slice_of_be::<u32>(&bytes.0[..6]).is_none());
+/// ```
+#[must_use]
+pub fn slice_of_be<T>(bytes: &[u8]) -> Option<&[Be<T>]>
+where
+    T: ByteOrder + Pod,
+{
+    slice_of(bytes)
+}
+
+/// Views `bytes` as a slice of little-endian values.
+///
+/// Returns `None` if `bytes` is not aligned to `T` or its length is not a
+/// multiple of the size of `T`.
+#[must_use]
+pub fn slice_of_le<T>(bytes: &[u8]) -> Option<&[Le<T>]>
+where
+    T: ByteOrder + Pod,
+{
+    slice_of(bytes)
+}
+
+fn slice_of<T>(bytes: &[u8]) -> Option<&[T]>
+where
+    T: Pod,
+{
+    let size = size_of::<T>();
+    if !bytes.len().is_multiple_of(size) {
+        return None;
+    }
+    DataView::from(bytes).try_slice(0, bytes.len() / size)
+}
+
 macro_rules! impl_fmt_traits {
     ($($trait:tt),+ for $ty:tt) => {
         $(
@@ -145,7 +270,7 @@ impl_common_traits!(Be, Le);
 mod tests {
     extern crate alloc;
 
-    use alloc::format;
+    use alloc::{format, vec::Vec};
 
     use super::*;
 
@@ -194,6 +319,21 @@ mod tests {
         assert_eq!(le.read(), -42);
     }
 
+    #[test]
+    fn test_const_conversions() {
+        const MAGIC: Be<u32> = be_u32(0xd00d_feed);
+        const VERSIONS: [Le<u16>; 2] = [le_u16(16), le_u16(17)];
+        const _: () = assert!(MAGIC.to_native() == 0xd00d_feed);
+        const _: () = assert!(be_i64(-1).to_native() == -1);
+
+        assert_eq!(MAGIC.read(), 0xd00d_feed);
+        assert_eq!(MAGIC, Be::new(&0xd00d_feed));
+        assert_eq!(VERSIONS.map(|v| v.read()), [16, 17]);
+        assert_eq!(VERSIONS[1], Le::<u16>::from_native(17));
+        assert_eq!(be_u8(0xab).to_native(), 0xab);
+        assert_eq!(le_i32(-5).0, (-5_i32).to_le());
+    }
+
     #[test]
     fn test_fmt_traits() {
         let be = Be(0xABCD_u16.to_be());
@@ -202,4 +342,30 @@ mod tests {
         assert_eq!(format!("{be:?}"), "43981");
         assert_eq!(format!("{le:x}"), "1234");
     }
+
+    #[test]
+    fn test_from_bytes() {
+        const MAGIC: Be<u32> = Be::<u32>::from_bytes(&[0xd0, 0x0d, 0xfe, 0xed]);
+        assert_eq!(MAGIC.read(), 0xd00d_feed);
+        assert_eq!(Le::<u16>::from_bytes(&[0x34, 0x12]).read(), 0x1234);
+        assert_eq!(Be::<i16>::from_bytes(&[0xff, 0xfe]).read(), -2);
+    }
+
+    #[test]
+    fn test_slice_of() {
+        #[repr(C, align(8))]
+        struct Aligned([u8; 16]);
+
+        let bytes = Aligned([0, 0, 0, 1, 0, 0, 0, 2, 3, 0, 0, 0, 0, 0, 0, 0]);
+        let cells = slice_of_be::<u32>(&bytes.0[..8]).unwrap();
+        assert_eq!(cells.iter().map(Be::read).collect::<Vec<_>>(), [1, 2]);
+        let values = slice_of_le::<u64>(&bytes.0[8..]).unwrap();
+        assert_eq!(values.iter().map(Le::read).collect::<Vec<_>>(), [3]);
+        assert_eq!(slice_of_be::<u64>(&bytes.0[..0]).map(<[_]>::len), Some(0));
+
+        // misaligned
+        assert!(slice_of_be::<u32>(&bytes.0[2..6]).is_none());
+        // not a multiple of the size
+        assert!(slice_of_be::<u32>(&bytes.0[..6]).is_none());
+    }
 }
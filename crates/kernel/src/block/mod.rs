@@ -0,0 +1,262 @@
+use alloc::{collections::vec_deque::VecDeque, sync::Arc, vec::Vec};
+use core::fmt;
+
+use crate::{
+    memory::dma::DmaBuffer,
+    sync::spinlock::{SpinMutex, SpinMutexCondVar},
+};
+
+/// Unit of the block device addressing, in bytes.
+pub const SECTOR_SIZE: usize = 512;
+
+static BLOCK_DEVICES: SpinMutex<Vec<Arc<dyn BlockDevice>>> = SpinMutex::new(Vec::new());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockError {
+    /// The device failed to process the request.
+    #[cfg_attr(not(feature = "virtio-blk"), expect(dead_code))]
+    Io,
+    /// The device does not support the operation.
+    #[cfg_attr(not(feature = "virtio-blk"), expect(dead_code))]
+    Unsupported,
+    /// The device is read-only.
+    ReadOnly,
+    /// The request is not sector-aligned or exceeds the device capacity.
+    InvalidRequest,
+    /// The buffer of the request cannot be allocated.
+    NoMemory,
+}
+
+impl fmt::Display for BlockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::Io => "I/O error",
+            Self::Unsupported => "operation not supported",
+            Self::ReadOnly => "read-only device",
+            Self::InvalidRequest => "invalid block request",
+            Self::NoMemory => "out of memory",
+        };
+        f.write_str(s)
+    }
+}
+
+impl core::error::Error for BlockError {}
+
+/// Block device, addressed in [`SECTOR_SIZE`] units.
+pub trait BlockDevice: fmt::Debug + Send + Sync {
+    fn name(&self) -> &str;
+    fn num_sectors(&self) -> u64;
+    fn is_read_only(&self) -> bool;
+
+    /// Queues `bio` to the device.
+    ///
+    /// The request completes asynchronously, see [`Bio::wait`].
+    fn submit(&self, bio: Arc<Bio>) -> Result<(), BlockError>;
+}
+
+#[cfg_attr(not(feature = "virtio-blk"), expect(dead_code))]
+pub fn register(device: Arc<dyn BlockDevice>) {
+    let mut devices = BLOCK_DEVICES.lock();
+    assert!(
+        !devices.iter().any(|d| d.name() == device.name()),
+        "block device {} already registered",
+        device.name()
+    );
+    info!(
+        "block device {}: {} sectors{}",
+        device.name(),
+        device.num_sectors(),
+        if device.is_read_only() {
+            " (read-only)"
+        } else {
+            ""
+        }
+    );
+    devices.push(device);
+}
+
+#[cfg_attr(not(feature = "shell"), expect(dead_code))]
+pub fn devices() -> Vec<Arc<dyn BlockDevice>> {
+    BLOCK_DEVICES.lock().clone()
+}
+
+pub fn find(name: &str) -> Option<Arc<dyn BlockDevice>> {
+    BLOCK_DEVICES
+        .lock()
+        .iter()
+        .find(|device| device.name() == name)
+        .cloned()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BioOp {
+    Read,
+    Write,
+    /// Flushes the volatile write cache of the device.
+    Flush,
+}
+
+/// Block I/O request.
+///
+/// The request owns its data buffer, which is accessed by the device while the
+/// request is in flight. Flush requests have no data buffer.
+#[derive(Debug)]
+pub struct Bio {
+    #[cfg_attr(not(any(feature = "virtio-blk", feature = "ktest")), expect(dead_code))]
+    op: BioOp,
+    #[cfg_attr(not(any(feature = "virtio-blk", feature = "ktest")), expect(dead_code))]
+    sector: u64,
+    state: SpinMutex<BioState>,
+    completed: SpinMutexCondVar,
+}
+
+#[derive(Debug)]
+struct BioState {
+    data: Option<DmaBuffer>,
+    result: Option<Result<(), BlockError>>,
+}
+
+impl Bio {
+    pub fn new(op: BioOp, sector: u64, data: Option<DmaBuffer>) -> Arc<Self> {
+        Arc::new(Self {
+            op,
+            sector,
+            state: SpinMutex::new(BioState { data, result: None }),
+            completed: SpinMutexCondVar::new(),
+        })
+    }
+
+    #[cfg_attr(not(any(feature = "virtio-blk", feature = "ktest")), expect(dead_code))]
+    pub fn op(&self) -> BioOp {
+        self.op
+    }
+
+    #[cfg_attr(not(any(feature = "virtio-blk", feature = "ktest")), expect(dead_code))]
+    pub fn sector(&self) -> u64 {
+        self.sector
+    }
+
+    /// Calls `f` with the data buffer, if any.
+    ///
+    /// The buffer stays at the same address until [`Self::take_data`] is
+    /// called.
+    #[cfg_attr(not(any(feature = "virtio-blk", feature = "ktest")), expect(dead_code))]
+    pub fn with_data<T>(&self, f: impl FnOnce(Option<&DmaBuffer>) -> T) -> T {
+        let state = self.state.lock();
+        f(state.data.as_ref())
+    }
+
+    /// Returns the number of sectors transferred by the request.
+    #[cfg_attr(not(feature = "virtio-blk"), expect(dead_code))]
+    pub fn num_sectors(&self) -> u64 {
+        let len = self.with_data(|data| data.map_or(0, DmaBuffer::len));
+        u64::try_from(len.div_ceil(SECTOR_SIZE)).unwrap()
+    }
+
+    /// Marks the request as completed and wakes up the waiters.
+    ///
+    /// This can be called in the interrupt context.
+    #[cfg_attr(not(any(feature = "virtio-blk", feature = "ktest")), expect(dead_code))]
+    pub fn complete(&self, result: Result<(), BlockError>) {
+        let mut state = self.state.lock();
+        assert!(state.result.is_none(), "bio completed twice");
+        state.result = Some(result);
+        state.unlock();
+        self.completed.notify_all();
+    }
+
+    /// Blocks the current task until the request completes.
+    pub fn wait(&self) -> Result<(), BlockError> {
+        let mut state = self.state.lock();
+        loop {
+            if let Some(result) = state.result {
+                return result;
+            }
+            state = self.completed.wait(state);
+        }
+    }
+
+    /// Takes the data buffer out of the completed request.
+    pub fn take_data(&self) -> Option<DmaBuffer> {
+        let mut state = self.state.lock();
+        assert!(state.result.is_some(), "bio is still in flight");
+        state.data.take()
+    }
+}
+
+/// Requests waiting for free slots in the hardware queue of a device.
+#[derive(Debug, Default)]
+#[cfg_attr(not(feature = "virtio-blk"), expect(dead_code))]
+pub struct RequestQueue {
+    pending: VecDeque<Arc<Bio>>,
+}
+
+impl RequestQueue {
+    #[cfg_attr(not(feature = "virtio-blk"), expect(dead_code))]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[cfg_attr(not(feature = "virtio-blk"), expect(dead_code))]
+    pub fn push(&mut self, bio: Arc<Bio>) {
+        self.pending.push_back(bio);
+    }
+
+    #[cfg_attr(not(feature = "virtio-blk"), expect(dead_code))]
+    pub fn pop(&mut self) -> Option<Arc<Bio>> {
+        self.pending.pop_front()
+    }
+}
+
+fn check_range(device: &dyn BlockDevice, sector: u64, len: usize) -> Result<(), BlockError> {
+    if !len.is_multiple_of(SECTOR_SIZE) {
+        return Err(BlockError::InvalidRequest);
+    }
+    let count = u64::try_from(len / SECTOR_SIZE).unwrap();
+    match sector.checked_add(count) {
+        Some(end) if end <= device.num_sectors() => Ok(()),
+        _ => Err(BlockError::InvalidRequest),
+    }
+}
+
+/// Reads the sectors starting at `sector` into `buf`, blocking until done.
+pub fn read(device: &dyn BlockDevice, sector: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+    check_range(device, sector, buf.len())?;
+    if buf.is_empty() {
+        return Ok(());
+    }
+    let data = DmaBuffer::new(buf.len(), SECTOR_SIZE).map_err(|e| {
+        warn!("failed to allocate block buffer: {e}");
+        BlockError::NoMemory
+    })?;
+    let bio = Bio::new(BioOp::Read, sector, Some(data));
+    device.submit(Arc::clone(&bio))?;
+    bio.wait()?;
+    buf.copy_from_slice(bio.take_data().unwrap().as_slice());
+    Ok(())
+}
+
+/// Writes `buf` to the sectors starting at `sector`, blocking until done.
+pub fn write(device: &dyn BlockDevice, sector: u64, buf: &[u8]) -> Result<(), BlockError> {
+    check_range(device, sector, buf.len())?;
+    if device.is_read_only() {
+        return Err(BlockError::ReadOnly);
+    }
+    if buf.is_empty() {
+        return Ok(());
+    }
+    let data = DmaBuffer::from_slice(buf, SECTOR_SIZE).map_err(|e| {
+        warn!("failed to allocate block buffer: {e}");
+        BlockError::NoMemory
+    })?;
+    let bio = Bio::new(BioOp::Write, sector, Some(data));
+    device.submit(Arc::clone(&bio))?;
+    bio.wait()
+}
+
+/// Flushes the write cache of the device, blocking until done.
+pub fn flush(device: &dyn BlockDevice) -> Result<(), BlockError> {
+    let bio = Bio::new(BioOp::Flush, 0, None);
+    device.submit(Arc::clone(&bio))?;
+    bio.wait()
+}
@@ -1,6 +1,6 @@
 use core::arch::naked_asm;
 
-use sbi::hart_state_management;
+use sbi::{SbiError, hart_state_management};
 
 use super::super::BOOT_STACK_TOP;
 use crate::cpu::Cpuid;
@@ -24,7 +24,7 @@ unsafe extern "C" fn entry(hartid: usize, dtb_pa: usize) -> ! {
 
         // call the main function on the allocated stack
         "mv sp, a0",
-        "j {primary_cpu_reentry}",
+        "tail {primary_cpu_reentry}",
 
         boot_stack_top = sym BOOT_STACK_TOP,
         primary_cpu_entry = sym super::super::primary_cpu_entry,
@@ -38,6 +38,17 @@ pub unsafe fn start_secondary_cpu(cpuid: Cpuid) {
     }
 }
 
+/// Restarts a stopped secondary CPU on the stack at `stack_top`.
+pub unsafe fn restart_secondary_cpu(cpuid: Cpuid, stack_top: usize) -> Result<(), SbiError> {
+    unsafe {
+        hart_state_management::hart_start(
+            cpuid.value(),
+            secondary_cpu_restart_entry as usize,
+            stack_top,
+        )
+    }
+}
+
 #[unsafe(naked)]
 unsafe extern "C" fn secondary_cpu_entry(cpuid: usize, opaque: usize) -> ! {
     naked_asm!(
@@ -49,10 +60,23 @@ unsafe extern "C" fn secondary_cpu_entry(cpuid: usize, opaque: usize) -> ! {
 
         // call the main function on the allocated stack
         "mv sp, a0",
-        "j {secondary_cpu_reentry}",
+        "tail {secondary_cpu_reentry}",
 
         boot_stack_top = sym BOOT_STACK_TOP,
         secondary_cpu_entry = sym super::super::secondary_cpu_entry,
         secondary_cpu_reentry = sym super::super::secondary_cpu_reentry,
     )
 }
+
+#[unsafe(naked)]
+unsafe extern "C" fn secondary_cpu_restart_entry(cpuid: usize, stack_top: usize) -> ! {
+    naked_asm!(
+        "mv tp, zero",
+
+        // the boot stack is reused as heap, so run on the stack of the CPU
+        "mv sp, a1",
+        "tail {secondary_cpu_restart}",
+
+        secondary_cpu_restart = sym super::super::secondary_cpu_restart,
+    )
+}
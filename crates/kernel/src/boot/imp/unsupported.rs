@@ -1,3 +1,5 @@
+use sbi::SbiError;
+
 use crate::cpu::Cpuid;
 
 #[unsafe(link_section = ".text.entry")]
@@ -16,3 +18,8 @@ pub unsafe fn start_secondary_cpu(_cpuid: Cpuid) {
     let _ = unsafe { super::super::BOOT_STACK_TOP };
     unimplemented!("unsupported architecture");
 }
+
+pub unsafe fn restart_secondary_cpu(_cpuid: Cpuid, _stack_top: usize) -> Result<(), SbiError> {
+    let _ = super::super::secondary_cpu_restart;
+    unimplemented!("unsupported architecture");
+}
@@ -1,9 +1,10 @@
 use alloc::format;
 use core::{
     hint, mem, ptr,
-    sync::atomic::{AtomicBool, Ordering},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
 };
 
+use sbi::SbiError;
 use snafu::ResultExt as _;
 
 use crate::{
@@ -53,6 +54,12 @@ unsafe extern "C" fn primary_cpu_reentry() -> ! {
 
 static CPU_STARTED: AtomicBool = AtomicBool::new(false);
 
+cpu_local! {
+    /// Top of the stack that the secondary CPU runs the scheduler on, reused
+    /// when the CPU is restarted.
+    static STACK_TOP: AtomicUsize = AtomicUsize::new(0);
+}
+
 pub unsafe fn start_secondary_cpu(cpuid: Cpuid) {
     CPU_STARTED.store(false, Ordering::Release);
     unsafe {
@@ -71,6 +78,7 @@ unsafe extern "C" fn secondary_cpu_entry(cpuid: usize) -> *mut u8 {
         .unwrap_or_else(|e: GenericError| error::report(e));
     let stack_top = stack.top();
     mem::forget(stack);
+    STACK_TOP.get().store(stack_top, Ordering::Relaxed);
     ptr::with_exposed_provenance_mut(stack_top)
 }
 
@@ -82,3 +90,23 @@ unsafe extern "C" fn secondary_cpu_reentry() -> ! {
         .unwrap_or_else(|e: GenericError| error::report(e));
     unreachable!();
 }
+
+/// Restarts the secondary CPU stopped by `hart_stop`.
+///
+/// The CPU starts over on its scheduler stack, as nothing on it is used after
+/// the CPU is stopped.
+pub unsafe fn restart_secondary_cpu(cpuid: Cpuid) -> Result<(), SbiError> {
+    let stack_top = STACK_TOP
+        .try_get_for(cpuid)
+        .unwrap()
+        .load(Ordering::Relaxed);
+    assert_ne!(stack_top, 0, "CPU#{cpuid} has not been started");
+    unsafe { imp::restart_secondary_cpu(cpuid, stack_top) }
+}
+
+unsafe extern "C" fn secondary_cpu_restart(cpuid: usize) -> ! {
+    let cpuid = Cpuid::from_raw(cpuid);
+    crate::secondary_cpu_restart(cpuid)
+        .with_whatever_context(|_| format!("failed to restart secondary CPU#{cpuid}"))
+        .unwrap_or_else(|e: GenericError| error::report(e))
+}
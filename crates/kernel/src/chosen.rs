@@ -1,12 +1,18 @@
+use core::ops::Range;
+
 use devtree::{
     DeserializeNode, Devicetree,
+    de::util,
     tree_cursor::{TreeCursor as _, TreeNodeRef},
     types::{ByteStr, ByteString},
 };
-use snafu::ResultExt as _;
+use platform_cast::CastFrom as _;
+use snafu::{ResultExt as _, ensure_whatever};
 use snafu_utils::GenericError;
 use spin::Once;
 
+use crate::cmdline::{FromParam as _, ParamDescriptor};
+
 #[derive(Debug, Default, DeserializeNode)]
 struct ChosenNode<'blob> {
     // Optional properties with defaults.
@@ -16,16 +22,73 @@ struct ChosenNode<'blob> {
     pub stdout_path: Option<&'blob ByteStr>,
     #[devtree(property(name = "stdin-path", default))]
     pub stdin_path: Option<&'blob ByteStr>,
+    #[devtree(property(
+        name = "linux,initrd-start",
+        default,
+        deserialize_with = util::deserialize_u64_or_u32_property,
+    ))]
+    pub initrd_start: u64,
+    #[devtree(property(
+        name = "linux,initrd-end",
+        default,
+        deserialize_with = util::deserialize_u64_or_u32_property,
+    ))]
+    pub initrd_end: u64,
+    #[devtree(property(
+        name = "kaslr-seed",
+        default,
+        deserialize_with = util::deserialize_u64_or_u32_property,
+    ))]
+    pub kaslr_seed: u64,
+}
+
+impl ChosenNode<'_> {
+    fn initrd_range(&self) -> Result<Option<Range<usize>>, GenericError> {
+        let range = usize::cast_from(self.initrd_start)..usize::cast_from(self.initrd_end);
+        if range == (0..0) {
+            return Ok(None);
+        }
+        ensure_whatever!(
+            range.start < range.end,
+            "invalid initrd range in chosen node, range={range:#x?}"
+        );
+        Ok(Some(range))
+    }
 }
 
 struct Chosen {
+    bootargs: Option<ByteString>,
     stdout_path: Option<ByteString>,
+    stdout_options: Option<ByteString>,
     stdin_path: Option<ByteString>,
+    initrd_range: Option<Range<usize>>,
+    kaslr_seed: Option<u64>,
 }
 
 static CHOSEN: Once<Chosen> = Once::new();
 
-pub fn init(dt: &Devicetree) -> Result<(), GenericError> {
+/// Console device given by the `console` option, in the format of
+/// `stdout-path`.
+struct Console {
+    path: ByteString,
+    options: Option<ByteString>,
+}
+
+static CONSOLE: Once<Console> = Once::new();
+
+pub static CONSOLE_PARAM: ParamDescriptor = ParamDescriptor {
+    name: "console",
+    handler: |value| {
+        let (path, options) = split_options(<&ByteStr>::from_param(value)?);
+        CONSOLE.call_once(|| Console {
+            path: ByteString::from(path),
+            options: options.map(ByteString::from),
+        });
+        Ok(())
+    },
+};
+
+fn read_chosen_node(dt: &Devicetree) -> Result<ChosenNode<'_>, GenericError> {
     let chosen = dt
         .tree_cursor()
         .whatever_context("failed to create tree cursor")?
@@ -35,19 +98,89 @@ pub fn init(dt: &Devicetree) -> Result<(), GenericError> {
         .transpose()
         .whatever_context("failed to deserialize chosen node")?
         .unwrap_or_default();
+    Ok(chosen)
+}
+
+/// Reads the physical address range of the initrd from the chosen node.
+///
+/// This does not require the heap, so it can be used to compute the heap
+/// layout before [`init`] is called.
+pub fn read_initrd_range(dt: &Devicetree) -> Result<Option<Range<usize>>, GenericError> {
+    read_chosen_node(dt)?.initrd_range()
+}
+
+pub fn init(dt: &Devicetree) -> Result<(), GenericError> {
+    let chosen = read_chosen_node(dt)?;
+    let initrd_range = chosen.initrd_range()?;
+    let (stdout_path, stdout_options) = chosen.stdout_path.map(split_options).unzip();
     CHOSEN.call_once(|| Chosen {
-        stdout_path: chosen.stdout_path.map(ByteString::from),
-        stdin_path: chosen.stdin_path.map(ByteString::from),
+        bootargs: chosen.bootargs.map(ByteString::from),
+        stdout_path: stdout_path.map(ByteString::from),
+        stdout_options: stdout_options.flatten().map(ByteString::from),
+        stdin_path: chosen
+            .stdin_path
+            .map(|path| ByteString::from(split_options(path).0)),
+        initrd_range,
+        kaslr_seed: (chosen.kaslr_seed != 0).then_some(chosen.kaslr_seed),
     });
     Ok(())
 }
 
+/// Splits `path` into the device path and the options after `:`, such as the
+/// baud rate in `/soc/serial@10000000:115200n8`.
+fn split_options(path: &ByteStr) -> (&ByteStr, Option<&ByteStr>) {
+    match path.iter().position(|&b| b == b':') {
+        Some(i) => (ByteStr::new(&path[..i]), Some(ByteStr::new(&path[i + 1..]))),
+        None => (path, None),
+    }
+}
+
+/// Returns the kernel command line given in `bootargs`.
+pub fn bootargs() -> Option<&'static ByteString> {
+    let chosen = CHOSEN.get()?;
+    chosen.bootargs.as_ref()
+}
+
+/// Returns the path of the stdout device, without the options.
+///
+/// The device given by the `console` option overrides `stdout-path`.
 pub fn stdout_path() -> Option<&'static ByteString> {
+    if let Some(console) = CONSOLE.get() {
+        return Some(&console.path);
+    }
     let chosen = CHOSEN.get()?;
     chosen.stdout_path.as_ref()
 }
 
+/// Returns the options of the stdout device given in `stdout-path` or the
+/// `console` option.
+pub fn stdout_options() -> Option<&'static ByteString> {
+    if let Some(console) = CONSOLE.get() {
+        return console.options.as_ref();
+    }
+    let chosen = CHOSEN.get()?;
+    chosen.stdout_options.as_ref()
+}
+
+/// Returns the path of the stdin device, without the options.
+///
+/// The device given by the `console` option overrides `stdin-path`.
 pub fn stdin_path() -> Option<&'static ByteString> {
+    if let Some(console) = CONSOLE.get() {
+        return Some(&console.path);
+    }
     let chosen = CHOSEN.get()?;
     chosen.stdin_path.as_ref().or_else(stdout_path)
 }
+
+pub fn initrd_range() -> Option<Range<usize>> {
+    let chosen = CHOSEN.get()?;
+    chosen.initrd_range.clone()
+}
+
+/// Returns the seed for randomizing the kernel virtual address layout, given
+/// by the bootloader in `kaslr-seed`.
+pub fn kaslr_seed() -> Option<u64> {
+    let chosen = CHOSEN.get()?;
+    chosen.kaslr_seed
+}
@@ -0,0 +1,255 @@
+//! Kernel command line given in `/chosen/bootargs`.
+//!
+//! The command line is a whitespace-separated list of options. An option is
+//! either `name=value`, or a flag `name` without a value. Each option the
+//! kernel handles has a [`ParamDescriptor`], whose handler is called by
+//! [`init`] with the value of the option. Other options of the form
+//! `<name>=<value>` set the [tunable](crate::tunables) of the name. If an
+//! option is given more than once, the last one takes effect.
+
+use alloc::{format, vec::Vec};
+use core::{str::FromStr, time::Duration};
+
+use devtree::types::ByteStr;
+use snafu::{OptionExt as _, ensure_whatever, whatever};
+use spin::Once;
+
+use crate::{
+    chosen, cpu, error::GenericError, interrupt::timer::watchdog, log, memory::layout, trace,
+    tunables,
+};
+
+/// Options handled by the kernel.
+static PARAMS: &[&ParamDescriptor] = &[
+    &log::LOG_LEVEL_PARAM,
+    &chosen::CONSOLE_PARAM,
+    &layout::MEMORY_LIMIT_PARAM,
+    &layout::NOKASLR_PARAM,
+    &cpu::NOSMP_PARAM,
+    &watchdog::HARD_WATCHDOG_PARAM,
+    &trace::TRACE_PARAM,
+];
+
+/// Options of the command line, in the given order.
+static OPTIONS: Once<Vec<Param>> = Once::new();
+
+/// Option handled by the kernel.
+#[derive(Debug)]
+pub struct ParamDescriptor {
+    pub name: &'static str,
+    /// Applies the value of the option.
+    ///
+    /// This is called before the CPU table and the heap are initialized, with
+    /// only the boot heap available.
+    pub handler: fn(Option<&'static ByteStr>) -> Result<(), GenericError>,
+}
+
+#[derive(Debug)]
+struct Param {
+    name: &'static ByteStr,
+    value: Option<&'static ByteStr>,
+}
+
+/// Parses the command line, and calls the handlers of the given options.
+///
+/// This must be called after [`chosen::init`].
+pub fn init() {
+    let options = OPTIONS.call_once(|| {
+        chosen::bootargs().map_or_else(Vec::new, |bootargs| parse(ByteStr::new(bootargs)).collect())
+    });
+
+    for (i, option) in options.iter().enumerate() {
+        // only the last one of the same options takes effect
+        if options[i + 1..]
+            .iter()
+            .any(|later| later.name == option.name)
+        {
+            continue;
+        }
+        let res = if let Some(param) = PARAMS
+            .iter()
+            .find(|param| *option.name == param.name.as_bytes())
+        {
+            (param.handler)(option.value)
+        } else if let Some(tunable) = tunables::find(option.name) {
+            require_value(option.value).and_then(|value| tunable.set_str(value))
+        } else {
+            warn!("unknown kernel parameter `{}`", option.name);
+            continue;
+        };
+        if let Err(e) = res {
+            warn!("invalid kernel parameter `{}`: {e}", option.name);
+        }
+    }
+}
+
+fn parse(bootargs: &'static ByteStr) -> impl Iterator<Item = Param> {
+    bootargs
+        .split(u8::is_ascii_whitespace)
+        .filter(|option| !option.is_empty())
+        .map(|option| match option.iter().position(|&b| b == b'=') {
+            Some(i) => Param {
+                name: ByteStr::new(&option[..i]),
+                value: Some(ByteStr::new(&option[i + 1..])),
+            },
+            None => Param {
+                name: ByteStr::new(option),
+                value: None,
+            },
+        })
+}
+
+/// Value of an option.
+pub trait FromParam: Sized {
+    fn from_param(value: Option<&'static ByteStr>) -> Result<Self, GenericError>;
+}
+
+/// Returns the value of an option that requires one.
+fn require_value(value: Option<&'static ByteStr>) -> Result<&'static str, GenericError> {
+    let value = value.whatever_context("value required")?;
+    let Ok(value) = str::from_utf8(value) else {
+        whatever!("invalid UTF-8 value: {value}");
+    };
+    Ok(value)
+}
+
+fn parse_number<T>(value: Option<&'static ByteStr>) -> Result<T, GenericError>
+where
+    T: FromStr,
+{
+    let value = require_value(value)?;
+    let Ok(number) = value.parse() else {
+        whatever!("invalid number: {value}");
+    };
+    Ok(number)
+}
+
+impl FromParam for &'static ByteStr {
+    fn from_param(value: Option<&'static ByteStr>) -> Result<Self, GenericError> {
+        value.whatever_context("value required")
+    }
+}
+
+/// A flag is true if it has no value.
+impl FromParam for bool {
+    fn from_param(value: Option<&'static ByteStr>) -> Result<Self, GenericError> {
+        if value.is_none() {
+            return Ok(true);
+        }
+        match require_value(value)? {
+            "1" | "y" | "yes" | "on" | "true" => Ok(true),
+            "0" | "n" | "no" | "off" | "false" => Ok(false),
+            value => {
+                whatever!("invalid boolean: {value}");
+            }
+        }
+    }
+}
+
+impl FromParam for u64 {
+    fn from_param(value: Option<&'static ByteStr>) -> Result<Self, GenericError> {
+        parse_number(value)
+    }
+}
+
+impl FromParam for usize {
+    fn from_param(value: Option<&'static ByteStr>) -> Result<Self, GenericError> {
+        parse_number(value)
+    }
+}
+
+/// A duration is given in seconds.
+impl FromParam for Duration {
+    fn from_param(value: Option<&'static ByteStr>) -> Result<Self, GenericError> {
+        parse_number(value).map(Self::from_secs)
+    }
+}
+
+/// Size in bytes, given with an optional `K`, `M` or `G` suffix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Size(pub usize);
+
+impl FromParam for Size {
+    fn from_param(value: Option<&'static ByteStr>) -> Result<Self, GenericError> {
+        let value = require_value(value)?;
+        let (number, shift) = match value.as_bytes().last() {
+            Some(b'k' | b'K') => (&value[..value.len() - 1], 10),
+            Some(b'm' | b'M') => (&value[..value.len() - 1], 20),
+            Some(b'g' | b'G') => (&value[..value.len() - 1], 30),
+            _ => (value, 0),
+        };
+        let Ok(number) = number.parse::<usize>() else {
+            whatever!("invalid size: {value}");
+        };
+        let size = number
+            .checked_shl(shift)
+            .filter(|size| size >> shift == number);
+        let size = size.with_whatever_context(|| format!("size too large: {value}"))?;
+        ensure_whatever!(size > 0, "size must not be zero");
+        Ok(Self(size))
+    }
+}
+
+#[cfg(feature = "ktest")]
+pub mod ktests {
+    use snafu::ensure_whatever;
+
+    use super::{ByteStr, FromParam as _, GenericError, Size, Vec, parse};
+    use crate::ktest::KernelTest;
+
+    pub static TESTS: &[KernelTest] = kernel_tests![parse_options, parse_size, parse_bool];
+
+    fn parse_options() -> Result<(), GenericError> {
+        let options = parse(ByteStr::new(b" loglevel=info  nosmp mem= ")).collect::<Vec<_>>();
+        ensure_whatever!(options.len() == 3, "unexpected options: {options:?}");
+        ensure_whatever!(
+            *options[0].name == *b"loglevel" && options[0].value == Some(ByteStr::new(b"info")),
+            "unexpected option: {:?}",
+            options[0]
+        );
+        ensure_whatever!(
+            *options[1].name == *b"nosmp" && options[1].value.is_none(),
+            "unexpected option: {:?}",
+            options[1]
+        );
+        ensure_whatever!(
+            *options[2].name == *b"mem" && options[2].value == Some(ByteStr::new(b"")),
+            "unexpected option: {:?}",
+            options[2]
+        );
+        Ok(())
+    }
+
+    fn parse_size() -> Result<(), GenericError> {
+        for (value, expected) in [
+            ("4096", 4096),
+            ("4K", 4 << 10),
+            ("2m", 2 << 20),
+            ("1G", 1 << 30),
+        ] {
+            let size = Size::from_param(Some(ByteStr::new(value)))?;
+            ensure_whatever!(size == Size(expected), "{value} parsed as {size:?}");
+        }
+        for value in ["", "K", "0", "-1", "1T", "99999999999G"] {
+            let res = Size::from_param(Some(ByteStr::new(value)));
+            ensure_whatever!(res.is_err(), "{value} parsed as {res:?}");
+        }
+        Ok(())
+    }
+
+    fn parse_bool() -> Result<(), GenericError> {
+        ensure_whatever!(
+            bool::from_param(None)?,
+            "flag without value parsed as false"
+        );
+        ensure_whatever!(
+            !bool::from_param(Some(ByteStr::new("off")))?,
+            "off parsed as true"
+        );
+        ensure_whatever!(
+            bool::from_param(Some(ByteStr::new("maybe"))).is_err(),
+            "maybe parsed as boolean"
+        );
+        Ok(())
+    }
+}
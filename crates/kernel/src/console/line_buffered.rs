@@ -20,6 +20,10 @@ where
         }
     }
 
+    pub(super) fn console_mut(&mut self) -> &mut C {
+        &mut self.console
+    }
+
     fn flush(&mut self) -> Result<(), C::Error> {
         while self.filled > 0 {
             let nwritten = self.console.write_bytes(&self.buffer[..self.filled])?;
@@ -1,31 +1,156 @@
+//! Kernel console.
+//!
+//! Each line is prefixed with the time since boot and the CPU writing it.
+//!
+//! The console may be written to while the current CPU is writing to it, e.g.
+//! by a warning or a panic in the console path or in a trap taken there. Such
+//! nested output is kept in a per-CPU emergency buffer, and written after the
+//! outer output, instead of waiting for the lock held by the same CPU forever.
+
 use core::{
     fmt::{self, Write as _},
     hint,
     panic::PanicInfo,
-    sync::atomic::{AtomicBool, Ordering},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
 };
 
 use ansi_term::{Color, WithFg};
+use snafu::ensure_whatever;
 
-use self::{line_buffered::LineBufferedConsole, sbi_debug::SbiDebugConsole};
+use self::{
+    line_buffered::LineBufferedConsole,
+    prefix::{Prefix, Prefixed},
+    ring_buffer::RingBuffer,
+    sbi::SbiConsole,
+    tee::TeeConsole,
+};
 use crate::{
     cpu::{self, Cpu},
+    crash_dump,
+    error::GenericError,
+    interrupt,
+    power::ShutdownKind,
     sync::spinlock::SpinMutex,
     task::scheduler,
 };
 
 mod line_buffered;
-mod sbi_debug;
+mod prefix;
+mod ring_buffer;
+mod sbi;
+mod tee;
+
+type KernelConsole = Prefixed<LineBufferedConsole<TeeConsole<SbiConsole>>>;
 
-static CONSOLE: SpinMutex<LineBufferedConsole<SbiDebugConsole>> =
-    SpinMutex::new(LineBufferedConsole::new(SbiDebugConsole {}));
+static CONSOLE: SpinMutex<KernelConsole> = SpinMutex::new(Prefixed::new(LineBufferedConsole::new(
+    TeeConsole::new(SbiConsole::new()),
+)));
 static PANICKED: AtomicBool = AtomicBool::new(false);
 
+/// Cpuid plus one of the CPU writing to [`CONSOLE`], [`UNKNOWN_CPU`] if the
+/// CPU is not set up yet, or zero if not written to.
+static WRITER: AtomicUsize = AtomicUsize::new(0);
+const UNKNOWN_CPU: usize = usize::MAX;
+
+/// Emergency buffer of the boot CPU before the per-CPU data is set up.
+static BOOT_EMERGENCY: SpinMutex<Prefixed<RingBuffer>> =
+    SpinMutex::new(Prefixed::new(RingBuffer::new()));
+
+cpu_local! {
+    static EMERGENCY: SpinMutex<Prefixed<RingBuffer>> =
+        SpinMutex::new(Prefixed::new(RingBuffer::new()));
+}
+
 trait Console {
     type Error;
     fn write_bytes(&mut self, bytes: &[u8]) -> Result<usize, Self::Error>;
 }
 
+/// Output device showing a copy of the console output, such as a display.
+pub trait ConsoleSink: Sync {
+    /// Writes the console output.
+    ///
+    /// This is called with the console locked and the interrupts disabled, and
+    /// must not write to the console.
+    fn write_bytes(&self, bytes: &[u8]);
+}
+
+/// Shows `bytes` on the sinks, without writing them to the console.
+///
+/// This is for the output written to the serial console directly, bypassing
+/// the kernel console, such as the echo of the console TTY.
+pub fn write_to_sinks(bytes: &[u8]) {
+    let interrupt_guard = interrupt::push_disabled();
+    let this_cpu = current_writer();
+    // the sinks are being written to by this CPU
+    if WRITER.load(Ordering::Relaxed) == this_cpu {
+        interrupt_guard.pop();
+        return;
+    }
+
+    let mut console = CONSOLE.lock();
+    WRITER.store(this_cpu, Ordering::Relaxed);
+    console.inner_mut().console_mut().write_sinks(bytes);
+    WRITER.store(0, Ordering::Relaxed);
+    console.unlock();
+    interrupt_guard.pop();
+}
+
+/// Initializes the console, and writes the output buffered until now.
+///
+/// Output written before this is called is kept in a static ring buffer, so
+/// that the messages are not lost even if the debug console of the SBI
+/// implementation is not available.
+pub fn init() {
+    CONSOLE
+        .lock()
+        .inner_mut()
+        .console_mut()
+        .console_mut()
+        .init();
+}
+
+/// Writes out the unfinished line and the nested output left in the emergency
+/// buffer of the current CPU, registered as a shutdown hook.
+#[expect(clippy::unnecessary_wraps)]
+pub fn flush(kind: ShutdownKind) -> Result<(), GenericError> {
+    let interrupt_guard = interrupt::push_disabled();
+    let this_cpu = current_writer();
+    // the console is being written to by this CPU, as in the panic handler
+    if WRITER.load(Ordering::Relaxed) == this_cpu {
+        interrupt_guard.pop();
+        return Ok(());
+    }
+
+    // the lock may be held by a CPU that never releases it after a panic
+    let console = if kind == ShutdownKind::Panic {
+        CONSOLE.try_lock()
+    } else {
+        Some(CONSOLE.lock())
+    };
+    if let Some(mut console) = console {
+        WRITER.store(this_cpu, Ordering::Relaxed);
+        let _ = console.finish_line();
+        with_emergency(|emergency| {
+            let _ = flush_emergency(&mut console, emergency);
+        });
+        WRITER.store(0, Ordering::Relaxed);
+        console.unlock();
+    }
+    interrupt_guard.pop();
+    Ok(())
+}
+
+/// Registers `sink` to show the console output written after this.
+#[cfg_attr(not(feature = "display"), expect(dead_code))]
+pub fn register_sink(sink: &'static dyn ConsoleSink) -> Result<(), GenericError> {
+    let mut console = CONSOLE.lock();
+    let added = console.inner_mut().console_mut().add_sink(sink);
+    console.unlock();
+    ensure_whatever!(added, "too many console sinks");
+    Ok(())
+}
+
 pub fn print(args: fmt::Arguments) {
     if PANICKED.load(Ordering::Acquire) {
         loop {
@@ -33,7 +158,77 @@ pub fn print(args: fmt::Arguments) {
             hint::spin_loop();
         }
     }
-    CONSOLE.lock().write_fmt(args).unwrap();
+
+    // stays on the CPU, so that the writer is the current CPU until the
+    // console is unlocked
+    let interrupt_guard = interrupt::push_disabled();
+    let prefix = Prefix::current();
+    let this_cpu = current_writer();
+    // there is nowhere to report the error of the console
+    if WRITER.load(Ordering::Relaxed) == this_cpu {
+        with_emergency(|emergency| {
+            let _ = emergency.with_prefix(prefix).write_fmt(args);
+        });
+        interrupt_guard.pop();
+        return;
+    }
+
+    let mut console = CONSOLE.lock();
+    WRITER.store(this_cpu, Ordering::Relaxed);
+    let _ = console.with_prefix(prefix).write_fmt(args);
+    with_emergency(|emergency| {
+        let _ = flush_emergency(&mut console, emergency);
+    });
+    WRITER.store(0, Ordering::Relaxed);
+    console.unlock();
+    interrupt_guard.pop();
+}
+
+fn current_writer() -> usize {
+    cpu::try_current().map_or(UNKNOWN_CPU, |cpu| cpu.id().value() + 1)
+}
+
+/// Calls `f` with the emergency buffer of the current CPU.
+///
+/// The output is dropped if the buffer is already in use, i.e. the console is
+/// written to while the buffer is written or flushed.
+fn with_emergency(f: impl FnOnce(&mut Prefixed<RingBuffer>)) {
+    let emergency = EMERGENCY.try_get().unwrap_or(&BOOT_EMERGENCY);
+    if let Some(mut emergency) = emergency.try_lock() {
+        f(&mut emergency);
+        emergency.unlock();
+    }
+}
+
+/// Writes the nested output kept in the emergency buffer to the console.
+fn flush_emergency(
+    console: &mut KernelConsole,
+    emergency: &mut Prefixed<RingBuffer>,
+) -> fmt::Result {
+    if emergency.inner_mut().as_slices().0.is_empty() {
+        return Ok(());
+    }
+    // the nested output is written between the lines of the outer output
+    console.finish_line()?;
+    emergency.finish_line()?;
+    let buffer = emergency.inner_mut();
+    let dropped = buffer.dropped();
+    if dropped > 0 {
+        writeln!(
+            console.with_prefix(Prefix::current()),
+            "[{dropped} bytes of nested console output dropped]"
+        )?;
+    }
+    let (head, tail) = buffer.as_slices();
+    // the oldest bytes may be overwritten in the middle of a character
+    for chunk in head.utf8_chunks().chain(tail.utf8_chunks()) {
+        console.write_raw(chunk.valid())?;
+        if !chunk.invalid().is_empty() {
+            console.write_raw(char::REPLACEMENT_CHARACTER.encode_utf8(&mut [0; 4]))?;
+        }
+    }
+    buffer.clear();
+    Ok(())
 }
 
 #[macro_export]
@@ -71,12 +266,27 @@ where
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
     PANICKED.store(true, Ordering::Release);
+    crash_dump::save(info);
     let header = WithFg::new(Color::Red, "!!! KERNEL PANIC !!!");
     let cpuid = OrUnknown(cpu::try_current().map(Cpu::id));
     let taskid = OrUnknown(scheduler::try_current_task().map(|task| task.id()));
     let loc = OrUnknown(info.location());
 
-    let mut console = CONSOLE.lock();
+    let _interrupt_guard = interrupt::push_disabled();
+    // the console is taken over if the panic happens while this CPU is
+    // writing to it, as the lock is never released
+    let this_cpu = current_writer();
+    let mut console = if WRITER.load(Ordering::Relaxed) == this_cpu {
+        unsafe { CONSOLE.remember_locked() }
+    } else {
+        CONSOLE.lock()
+    };
+    WRITER.store(this_cpu, Ordering::Relaxed);
+    let _ = console.finish_line();
+    // write out the early output, as the panic may happen before the console
+    // is initialized
+    console.inner_mut().console_mut().console_mut().init();
+    let mut console = console.with_prefix(Prefix::current());
     let _ = writeln!(console);
     let _ = writeln!(console);
     let _ = writeln!(console, "{header}");
@@ -93,7 +303,16 @@ fn panic(info: &PanicInfo) -> ! {
     let _ = writeln!(console, "Message:");
     let _ = writeln!(console, "  {}", info.message());
     let _ = writeln!(console);
-    loop {
-        hint::spin_loop();
+    #[cfg(feature = "ktest")]
+    crate::ktest::exit(false);
+    #[cfg(not(feature = "ktest"))]
+    {
+        if let Some(delay) = crate::power::panic_reboot_delay() {
+            let _ = writeln!(console, "Rebooting in {delay:?}");
+            crate::power::reboot_after_panic(delay);
+        }
+        loop {
+            hint::spin_loop();
+        }
     }
 }
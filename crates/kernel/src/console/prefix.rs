@@ -0,0 +1,111 @@
+use core::{fmt, time::Duration};
+
+use crate::{
+    cpu::{self, Cpu, Cpuid},
+    interrupt::timer,
+};
+
+/// Prefix of the console lines, with the time since boot and the CPU writing
+/// the line, e.g. `[   12.345678] [cpu1] `.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Prefix {
+    time: Option<Duration>,
+    cpuid: Option<Cpuid>,
+}
+
+impl Prefix {
+    /// Returns the prefix of the lines written now by the current CPU.
+    ///
+    /// The time and the CPU are unknown until the CPU is set up.
+    pub(super) fn current() -> Self {
+        Self {
+            time: timer::try_now().map(|now| now.duration_since_epoc()),
+            cpuid: cpu::try_current().map(Cpu::id),
+        }
+    }
+}
+
+impl fmt::Display for Prefix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let time = self.time.unwrap_or_default();
+        write!(f, "[{:5}.{:06}] ", time.as_secs(), time.subsec_micros())?;
+        match self.cpuid {
+            Some(cpuid) => write!(f, "[cpu{cpuid}] "),
+            None => write!(f, "[cpu?] "),
+        }
+    }
+}
+
+/// Writer that puts a [`Prefix`] at the start of each line.
+///
+/// Whether the last write ended a line is kept across the writes, so that a
+/// line written by several writes gets a single prefix.
+pub(super) struct Prefixed<W> {
+    inner: W,
+    at_line_start: bool,
+}
+
+impl<W> Prefixed<W>
+where
+    W: fmt::Write,
+{
+    pub(super) const fn new(inner: W) -> Self {
+        Self {
+            inner,
+            at_line_start: true,
+        }
+    }
+
+    pub(super) fn inner_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Ends the current line if it is not empty.
+    pub(super) fn finish_line(&mut self) -> fmt::Result {
+        if !self.at_line_start {
+            self.inner.write_char('\n')?;
+            self.at_line_start = true;
+        }
+        Ok(())
+    }
+
+    /// Writes the output to the inner writer as is, without the prefixes.
+    pub(super) fn write_raw(&mut self, s: &str) -> fmt::Result {
+        if let Some(last) = s.chars().last() {
+            self.inner.write_str(s)?;
+            self.at_line_start = last == '\n';
+        }
+        Ok(())
+    }
+
+    /// Returns a writer putting `prefix` at the start of the lines.
+    pub(super) fn with_prefix(&mut self, prefix: Prefix) -> PrefixWriter<'_, W> {
+        PrefixWriter {
+            prefixed: self,
+            prefix,
+        }
+    }
+}
+
+pub(super) struct PrefixWriter<'a, W> {
+    prefixed: &'a mut Prefixed<W>,
+    prefix: Prefix,
+}
+
+impl<W> fmt::Write for PrefixWriter<'_, W>
+where
+    W: fmt::Write,
+{
+    fn write_str(&mut self, mut s: &str) -> fmt::Result {
+        while !s.is_empty() {
+            if self.prefixed.at_line_start {
+                write!(self.prefixed.inner, "{}", self.prefix)?;
+                self.prefixed.at_line_start = false;
+            }
+            let (line, rest) = s.split_at(s.find('\n').map_or(s.len(), |n| n + 1));
+            self.prefixed.write_raw(line)?;
+            s = rest;
+        }
+        Ok(())
+    }
+}
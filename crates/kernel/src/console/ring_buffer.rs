@@ -0,0 +1,67 @@
+use core::fmt;
+
+const BUFFER_LEN: usize = 4096;
+
+/// Ring buffer holding the output that cannot be written to the console yet,
+/// such as the output before the console is initialized.
+///
+/// This is a static buffer, as the output may be written before the heap is
+/// available. If the buffer is full, the oldest bytes are overwritten.
+pub(super) struct RingBuffer {
+    buffer: [u8; BUFFER_LEN],
+    start: usize,
+    len: usize,
+    dropped: usize,
+}
+
+impl RingBuffer {
+    pub(super) const fn new() -> Self {
+        Self {
+            buffer: [0; BUFFER_LEN],
+            start: 0,
+            len: 0,
+            dropped: 0,
+        }
+    }
+
+    pub(super) fn push(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            let end = (self.start + self.len) % BUFFER_LEN;
+            self.buffer[end] = byte;
+            if self.len < BUFFER_LEN {
+                self.len += 1;
+            } else {
+                self.start = (self.start + 1) % BUFFER_LEN;
+                self.dropped += 1;
+            }
+        }
+    }
+
+    /// Returns the number of the overwritten bytes.
+    pub(super) fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    /// Returns the buffered bytes, as the two contiguous parts of the ring.
+    pub(super) fn as_slices(&self) -> (&[u8], &[u8]) {
+        let end = self.start + self.len;
+        if end <= BUFFER_LEN {
+            (&self.buffer[self.start..end], &[])
+        } else {
+            (&self.buffer[self.start..], &self.buffer[..end - BUFFER_LEN])
+        }
+    }
+
+    pub(super) fn clear(&mut self) {
+        self.start = 0;
+        self.len = 0;
+        self.dropped = 0;
+    }
+}
+
+impl fmt::Write for RingBuffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push(s.as_bytes());
+        Ok(())
+    }
+}
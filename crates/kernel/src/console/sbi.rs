@@ -0,0 +1,108 @@
+use core::fmt::{self, Write as _};
+
+use sbi::{SbiError, base, debug_console, legacy};
+
+use super::{Console, ring_buffer::RingBuffer};
+
+/// SBI extension used to write to the console.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Backend {
+    /// Debug Console Extension.
+    DebugConsole,
+    /// Legacy Console Putchar Extension, for SBI implementations without the
+    /// Debug Console Extension.
+    Legacy,
+}
+
+/// Console writing to the SBI console.
+///
+/// The output is kept in the early buffer until [`SbiConsole::init`] selects
+/// the SBI extension to write with.
+pub(super) struct SbiConsole {
+    backend: Option<Backend>,
+    early: RingBuffer,
+}
+
+impl SbiConsole {
+    pub(super) const fn new() -> Self {
+        Self {
+            backend: None,
+            early: RingBuffer::new(),
+        }
+    }
+
+    pub(super) fn is_initialized(&self) -> bool {
+        self.backend.is_some()
+    }
+
+    /// Selects the SBI extension to write with, and writes the early output.
+    pub(super) fn init(&mut self) {
+        if self.is_initialized() {
+            return;
+        }
+        let backend = if base::probe_extension(debug_console::EXTENSION_ID) {
+            Backend::DebugConsole
+        } else {
+            Backend::Legacy
+        };
+        self.backend = Some(backend);
+
+        let dropped = self.early.dropped();
+        if dropped > 0 {
+            let _ = writeln!(
+                Writer(backend),
+                "[{dropped} bytes of early boot log dropped]"
+            );
+        }
+        let (head, tail) = self.early.as_slices();
+        let _ = write_all(backend, head);
+        let _ = write_all(backend, tail);
+        self.early.clear();
+    }
+}
+
+impl Console for SbiConsole {
+    type Error = SbiError;
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<usize, Self::Error> {
+        let Some(backend) = self.backend else {
+            self.early.push(bytes);
+            return Ok(bytes.len());
+        };
+        write(backend, bytes)
+    }
+}
+
+fn write(backend: Backend, bytes: &[u8]) -> Result<usize, SbiError> {
+    match backend {
+        Backend::DebugConsole => debug_console::write(bytes),
+        Backend::Legacy => {
+            for &byte in bytes {
+                legacy::console_putchar(byte)?;
+            }
+            Ok(bytes.len())
+        }
+    }
+}
+
+fn write_all(backend: Backend, mut bytes: &[u8]) -> Result<(), SbiError> {
+    while !bytes.is_empty() {
+        let nwritten = write(backend, bytes)?;
+        if nwritten == 0 {
+            break;
+        }
+        bytes = &bytes[nwritten..];
+    }
+    Ok(())
+}
+
+struct Writer(Backend);
+
+impl fmt::Write for Writer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if let Err(_e) = write_all(self.0, s.as_bytes()) {
+            return Err(fmt::Error);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,52 @@
+use super::{Console, ConsoleSink};
+
+/// Maximum number of the sinks registered to the console.
+const MAX_SINKS: usize = 4;
+
+/// Console copying the output of the inner console to the registered sinks.
+pub(super) struct TeeConsole<C> {
+    console: C,
+    sinks: [Option<&'static dyn ConsoleSink>; MAX_SINKS],
+}
+
+impl<C> TeeConsole<C> {
+    pub(super) const fn new(console: C) -> Self {
+        Self {
+            console,
+            sinks: [None; MAX_SINKS],
+        }
+    }
+
+    pub(super) fn console_mut(&mut self) -> &mut C {
+        &mut self.console
+    }
+
+    /// Writes `bytes` to the sinks only.
+    pub(super) fn write_sinks(&self, bytes: &[u8]) {
+        for sink in self.sinks.iter().flatten() {
+            sink.write_bytes(bytes);
+        }
+    }
+
+    /// Adds `sink`, and returns `false` if there is no room for it.
+    pub(super) fn add_sink(&mut self, sink: &'static dyn ConsoleSink) -> bool {
+        let Some(slot) = self.sinks.iter_mut().find(|slot| slot.is_none()) else {
+            return false;
+        };
+        *slot = Some(sink);
+        true
+    }
+}
+
+impl<C> Console for TeeConsole<C>
+where
+    C: Console,
+{
+    type Error = C::Error;
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<usize, Self::Error> {
+        let nwritten = self.console.write_bytes(bytes)?;
+        self.write_sinks(&bytes[..nwritten]);
+        Ok(nwritten)
+    }
+}
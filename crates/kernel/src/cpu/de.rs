@@ -3,9 +3,11 @@ use alloc::vec::Vec;
 use devtree::{
     DeserializeNode, Devicetree,
     de::util,
-    model::property::Reg,
-    tree_cursor::{TreeCursor as _, TreeIterator as _},
+    model::property::{ByteStrList, Reg},
+    tree_cursor::{TreeCursor as _, TreeIterator as _, TreeIteratorAllocExt as _},
+    types::ByteStr,
 };
+use platform_cast::CastFrom as _;
 use snafu::{OptionExt as _, ResultExt as _};
 
 use super::Cpu;
@@ -22,6 +24,35 @@ struct CpuNode<'blob> {
         deserialize_with = util::deserialize_u64_or_u32_property,
     ))]
     timebase_frequency: u64,
+    #[devtree(property(name = "riscv,isa", default))]
+    isa: Option<&'blob ByteStr>,
+    #[devtree(property(name = "riscv,isa-extensions", default))]
+    isa_extensions: Option<ByteStrList<'blob>>,
+    #[devtree(property(name = "numa-node-id", default))]
+    numa_node_id: Option<u32>,
+    #[devtree(property(name = "riscv,cbom-block-size", default))]
+    cbom_block_size: Option<u32>,
+}
+
+impl CpuNode<'_> {
+    /// Returns whether the CPU supports the multi-letter extension `name`.
+    ///
+    /// `riscv,isa-extensions` is preferred, and the deprecated `riscv,isa`
+    /// string is used if it is missing.
+    fn has_extension(&self, name: &str) -> bool {
+        if let Some(extensions) = &self.isa_extensions {
+            return extensions
+                .iter()
+                .any(|extension| extension == name.as_bytes());
+        }
+        let Some(isa) = self.isa else {
+            return false;
+        };
+        // the first component is the base ISA and the single-letter extensions
+        isa.split(|&byte| byte == b'_')
+            .skip(1)
+            .any(|extension| extension.eq_ignore_ascii_case(name.as_bytes()))
+    }
 }
 
 pub fn deserialize(dt: &Devicetree) -> Result<Vec<Cpu>, GenericError> {
@@ -31,19 +62,28 @@ pub fn deserialize(dt: &Devicetree) -> Result<Vec<Cpu>, GenericError> {
         .whatever_context("failed to create tree cursor")?;
     let iter = cursor
         .read_descendant_nodes_by_glob("/cpus/cpu")
+        .skip_disabled()
+        .sorted_by_unit_address()
+        .whatever_context("failed to read cpu nodes in devicetree")?
         .deserialize_node::<CpuNode>();
     for cpu_node in iter {
-        let CpuNode {
-            reg,
-            timebase_frequency,
-        } = cpu_node.whatever_context("failed to deserialize cpu node in devicetree")?;
-        let reg = reg
+        let cpu_node = cpu_node.whatever_context("failed to deserialize cpu node in devicetree")?;
+        let has_svpbmt = cpu_node.has_extension("svpbmt");
+        let cbom_block_size = cpu_node
+            .cbom_block_size
+            .filter(|_| cpu_node.has_extension("zicbom"))
+            .map(usize::cast_from);
+        let reg = cpu_node
+            .reg
             .into_iter()
             .assume_one()
             .whatever_context("invalid 'reg' entries in cpu node")?;
         let cpu = Cpu {
             id: Cpuid::from_raw(reg.range().start),
-            timer_frequency: timebase_frequency,
+            timer_frequency: cpu_node.timebase_frequency,
+            has_svpbmt,
+            numa_node: cpu_node.numa_node_id,
+            cbom_block_size,
         };
         all_cpus.push(cpu);
     }
@@ -0,0 +1,290 @@
+//! Stopping the secondary CPUs and restarting them at runtime.
+//!
+//! [`offline`] asks a CPU to stop itself. Its scheduler returns from the
+//! current task, runs the offline hooks of the per-CPU subsystems, and gives
+//! the hart back to the SBI by `hart_stop`. The run queue is shared by all the
+//! CPUs, so the other tasks are left to the other CPUs. [`online`] restarts
+//! the hart by `hart_start`, which runs the online hooks and then the
+//! scheduler.
+//!
+//! The boot CPU is never stopped, so there is always a CPU to take over. The
+//! other CPUs are stopped before the system is reset, by a shutdown hook.
+
+use core::{
+    sync::atomic::{AtomicU8, Ordering},
+    time::Duration,
+};
+
+use riscv_utils::asm;
+use sbi::hart_state_management::{self, HartState};
+use snafu::{OptionExt as _, ResultExt as _, ensure_whatever};
+
+use super::Cpuid;
+use crate::{
+    boot,
+    drivers::irq::aplic,
+    error::GenericError,
+    interrupt::{
+        self, ipi,
+        timer::{self, Instant, watchdog},
+    },
+    power::{self, ShutdownKind},
+    sync::{mutex::Mutex, rcu},
+};
+
+/// Hooks of the per-CPU subsystems, in the order of the online hooks.
+static HOOKS: &[&HotplugHook] = &[
+    // flushed last, after the other subsystems free their per-CPU memory
+    #[cfg(all(feature = "heap-cache", not(feature = "heap-debug")))]
+    &crate::memory::allocator::cache::HOTPLUG_HOOK,
+    &rcu::HOTPLUG_HOOK,
+    &timer::HOTPLUG_HOOK,
+    &watchdog::HOTPLUG_HOOK,
+    #[cfg(feature = "plic")]
+    &crate::drivers::irq::plic::HOTPLUG_HOOK,
+    &aplic::HOTPLUG_HOOK,
+];
+
+initcall!(INITCALL, late, init);
+
+fn init() {
+    power::register_shutdown_hook(power::PRIORITY_CPU, stop_all);
+}
+
+/// Stops all the CPUs but the boot CPU, so that the devices are reset while no
+/// other CPU uses them.
+fn stop_all(kind: ShutdownKind) -> Result<(), GenericError> {
+    // the CPUs cannot be waited for in the panic handler
+    if kind == ShutdownKind::Panic {
+        return Ok(());
+    }
+    for cpu in super::get_all() {
+        // the current task moves to the boot CPU if the current CPU is stopped
+        if cpu.id() != super::boot_cpuid() && is_online(cpu.id()) {
+            offline(cpu.id())?;
+        }
+    }
+    Ok(())
+}
+
+/// Time to wait for a CPU to change its state.
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Serializes the state changes.
+static HOTPLUG_LOCK: Mutex<()> = Mutex::new(());
+
+cpu_local! {
+    static STATE: AtomicU8 = AtomicU8::new(CpuState::Online as u8);
+}
+
+/// Notification of the state changes of the CPUs for a per-CPU subsystem.
+#[derive(Debug)]
+pub struct HotplugHook {
+    pub name: &'static str,
+    /// Called on a restarted CPU after it is marked online, with the
+    /// interrupts disabled.
+    pub online: fn(),
+    /// Called on a stopping CPU after it is marked going offline, with the
+    /// interrupts disabled.
+    ///
+    /// The offline hooks are called in the reverse order of the online hooks.
+    pub offline: fn(),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum CpuState {
+    Online,
+    /// Requested to stop, and still running until its scheduler stops it.
+    GoingOffline,
+    Offline,
+    /// Requested to restart, and not running yet.
+    GoingOnline,
+}
+
+impl CpuState {
+    fn from_raw(value: u8) -> Self {
+        [
+            Self::Online,
+            Self::GoingOffline,
+            Self::Offline,
+            Self::GoingOnline,
+        ][usize::from(value)]
+    }
+}
+
+pub fn state(cpuid: Cpuid) -> Option<CpuState> {
+    let state = STATE.try_get_for(cpuid)?;
+    Some(CpuState::from_raw(state.load(Ordering::Acquire)))
+}
+
+fn set_state(cpuid: Cpuid, state: CpuState) {
+    STATE
+        .try_get_for(cpuid)
+        .unwrap()
+        .store(state as u8, Ordering::Release);
+}
+
+/// Returns whether the CPU is online and takes the interrupts routed to it.
+pub fn is_online(cpuid: Cpuid) -> bool {
+    state(cpuid) == Some(CpuState::Online)
+}
+
+/// Returns whether the CPU is executing, and needs the TLB shootdowns and the
+/// IPIs.
+pub fn is_running(cpuid: Cpuid) -> bool {
+    matches!(
+        state(cpuid),
+        Some(CpuState::Online | CpuState::GoingOffline)
+    )
+}
+
+/// Stops the CPU.
+///
+/// Returns after the hart is stopped. This can be called on the CPU itself,
+/// in which case the current task continues on another CPU.
+pub fn offline(cpuid: Cpuid) -> Result<(), GenericError> {
+    ensure_whatever!(
+        cpuid != super::boot_cpuid(),
+        "boot CPU#{cpuid} cannot be stopped"
+    );
+    let guard = HOTPLUG_LOCK.lock();
+    ensure_whatever!(
+        state(cpuid).whatever_context("no such CPU")? == CpuState::Online,
+        "CPU#{cpuid} is not online"
+    );
+    set_state(cpuid, CpuState::GoingOffline);
+    // wake up the CPU if it is idle, so that its scheduler stops it
+    ipi::send_reschedule(cpuid).whatever_context("failed to send IPI")?;
+
+    wait_for(cpuid, || {
+        state(cpuid) == Some(CpuState::Offline)
+            && matches!(
+                hart_state_management::hart_get_status(cpuid.value()),
+                Ok(HartState::Stopped)
+            )
+    })?;
+    drop(guard);
+    info!("CPU#{cpuid} is offline");
+    Ok(())
+}
+
+/// Restarts the CPU stopped by [`offline`].
+///
+/// Returns after the CPU is marked online.
+#[cfg_attr(not(feature = "shell"), expect(dead_code))]
+pub fn online(cpuid: Cpuid) -> Result<(), GenericError> {
+    let guard = HOTPLUG_LOCK.lock();
+    ensure_whatever!(
+        state(cpuid).whatever_context("no such CPU")? == CpuState::Offline,
+        "CPU#{cpuid} is not offline"
+    );
+    // the CPU reads RCU data before its scheduler reports a quiescent state
+    rcu::prepare_online(cpuid);
+    set_state(cpuid, CpuState::GoingOnline);
+    if let Err(e) = unsafe { boot::restart_secondary_cpu(cpuid) } {
+        set_state(cpuid, CpuState::Offline);
+        return Err(e).whatever_context("failed to start hart");
+    }
+
+    wait_for(cpuid, || is_online(cpuid))?;
+    drop(guard);
+    info!("CPU#{cpuid} is online");
+    Ok(())
+}
+
+fn wait_for<F>(cpuid: Cpuid, mut done: F) -> Result<(), GenericError>
+where
+    F: FnMut() -> bool,
+{
+    let start = Instant::now();
+    while !done() {
+        ensure_whatever!(
+            start.elapsed() < TIMEOUT,
+            "CPU#{cpuid} did not respond in {TIMEOUT:?}, state={:?}",
+            state(cpuid)
+        );
+        timer::sleep(Duration::from_millis(1));
+    }
+    Ok(())
+}
+
+/// Returns whether the current CPU is requested to stop.
+pub fn is_stop_requested() -> bool {
+    STATE.get().load(Ordering::Acquire) == CpuState::GoingOffline as u8
+}
+
+/// Stops the current CPU, called by its scheduler with no current task.
+pub fn stop_current() -> ! {
+    assert!(!interrupt::is_enabled());
+    let cpuid = super::current().id();
+    for hook in HOOKS.iter().rev() {
+        trace!("running offline hook of {}", hook.name);
+        (hook.offline)();
+    }
+    debug!("CPU#{cpuid} stopping");
+    set_state(cpuid, CpuState::Offline);
+
+    let Err(e) = hart_state_management::hart_stop();
+    panic!("failed to stop CPU#{cpuid}: {e}");
+}
+
+/// Marks the restarted CPU online.
+///
+/// This is called before the CPU uses the kernel page table, as the TLB
+/// shootdowns are sent to the CPU from then on. The TLB entries kept while
+/// the CPU was stopped are flushed here.
+pub fn mark_online() {
+    let cpuid = super::current().id();
+    assert_eq!(state(cpuid), Some(CpuState::GoingOnline));
+    set_state(cpuid, CpuState::Online);
+    asm::sfence_vma_all();
+}
+
+/// Runs the online hooks on the restarted CPU.
+pub fn run_online_hooks() {
+    assert!(!interrupt::is_enabled());
+    for hook in HOOKS {
+        trace!("running online hook of {}", hook.name);
+        (hook.online)();
+    }
+}
+
+#[cfg(feature = "ktest")]
+pub mod ktests {
+    use snafu::ensure_whatever;
+
+    use super::{CpuState, offline, online, state};
+    use crate::{cpu, error::GenericError, ktest::KernelTest};
+
+    pub static TESTS: &[KernelTest] = kernel_tests![offline_and_online];
+
+    fn offline_and_online() -> Result<(), GenericError> {
+        let Some(cpu) = cpu::get_all()
+            .iter()
+            .rfind(|cpu| cpu.id() != cpu::boot_cpuid())
+        else {
+            // nothing to stop on a single CPU
+            return Ok(());
+        };
+        for _ in 0..3 {
+            offline(cpu.id())?;
+            ensure_whatever!(
+                state(cpu.id()) == Some(CpuState::Offline),
+                "CPU#{} in {:?} after offline",
+                cpu.id(),
+                state(cpu.id())
+            );
+            ensure_whatever!(offline(cpu.id()).is_err(), "offline CPU stopped again");
+            online(cpu.id())?;
+            ensure_whatever!(
+                state(cpu.id()) == Some(CpuState::Online),
+                "CPU#{} in {:?} after online",
+                cpu.id(),
+                state(cpu.id())
+            );
+        }
+        ensure_whatever!(offline(cpu::boot_cpuid()).is_err(), "boot CPU stopped");
+        Ok(())
+    }
+}
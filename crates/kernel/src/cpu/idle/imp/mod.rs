@@ -0,0 +1,9 @@
+cfg_if::cfg_if! {
+    if #[cfg(target_arch = "riscv64")] {
+        mod riscv64;
+        pub use riscv64::*;
+    } else {
+        mod unsupported;
+        pub use unsupported::*;
+    }
+}
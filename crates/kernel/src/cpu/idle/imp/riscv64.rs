@@ -0,0 +1,226 @@
+use core::{arch::naked_asm, mem::offset_of};
+
+use sbi::{
+    SbiError,
+    hart_state_management::{self, SUSPEND_TYPE_DEFAULT_NON_RETENTIVE},
+};
+
+/// Registers and CSRs not preserved by the non-retentive suspend.
+///
+/// The suspend is entered by a function call, so only the callee-saved
+/// registers are saved.
+#[derive(Debug)]
+#[repr(C)]
+pub struct SuspendContext {
+    ra: usize,
+    sp: usize,
+    gp: usize,
+    tp: usize,
+    s0: usize,
+    s1: usize,
+    s2: usize,
+    s3: usize,
+    s4: usize,
+    s5: usize,
+    s6: usize,
+    s7: usize,
+    s8: usize,
+    s9: usize,
+    s10: usize,
+    s11: usize,
+    satp: usize,
+    stvec: usize,
+    sscratch: usize,
+    sie: usize,
+    sstatus: usize,
+    stimecmp: usize,
+    /// Error of the suspend call, set if it returns instead of suspending.
+    error: Option<SbiError>,
+}
+
+impl SuspendContext {
+    pub const fn new() -> Self {
+        Self {
+            ra: 0,
+            sp: 0,
+            gp: 0,
+            tp: 0,
+            s0: 0,
+            s1: 0,
+            s2: 0,
+            s3: 0,
+            s4: 0,
+            s5: 0,
+            s6: 0,
+            s7: 0,
+            s8: 0,
+            s9: 0,
+            s10: 0,
+            s11: 0,
+            satp: 0,
+            stvec: 0,
+            sscratch: 0,
+            sie: 0,
+            sstatus: 0,
+            stimecmp: 0,
+            error: None,
+        }
+    }
+}
+
+/// Suspends the current hart by the default non-retentive suspend, and
+/// returns after it resumes.
+///
+/// # Safety
+///
+/// The interrupts must be disabled, and `context` must be at the same address
+/// with and without the address translation, as the hart resumes with the
+/// translation disabled.
+pub unsafe fn suspend_non_retentive(context: &mut SuspendContext) -> Result<(), SbiError> {
+    context.error = None;
+    unsafe {
+        save_and_suspend(context);
+    }
+    context.error.take().map_or(Ok(()), Err)
+}
+
+#[unsafe(naked)]
+unsafe extern "C" fn save_and_suspend(context: *mut SuspendContext) {
+    naked_asm!(
+        "sd ra, {c_ra}(a0)",
+        "sd sp, {c_sp}(a0)",
+        "sd gp, {c_gp}(a0)",
+        "sd tp, {c_tp}(a0)",
+        "sd s0, {c_s0}(a0)",
+        "sd s1, {c_s1}(a0)",
+        "sd s2, {c_s2}(a0)",
+        "sd s3, {c_s3}(a0)",
+        "sd s4, {c_s4}(a0)",
+        "sd s5, {c_s5}(a0)",
+        "sd s6, {c_s6}(a0)",
+        "sd s7, {c_s7}(a0)",
+        "sd s8, {c_s8}(a0)",
+        "sd s9, {c_s9}(a0)",
+        "sd s10, {c_s10}(a0)",
+        "sd s11, {c_s11}(a0)",
+        "csrr t0, satp",
+        "sd t0, {c_satp}(a0)",
+        "csrr t0, stvec",
+        "sd t0, {c_stvec}(a0)",
+        "csrr t0, sscratch",
+        "sd t0, {c_sscratch}(a0)",
+        "csrr t0, sie",
+        "sd t0, {c_sie}(a0)",
+        "csrr t0, sstatus",
+        "sd t0, {c_sstatus}(a0)",
+        "csrr t0, stimecmp",
+        "sd t0, {c_stimecmp}(a0)",
+
+        // returns to the caller only if the suspend fails, and the hart
+        // resumes at `resume_entry` otherwise
+        "tail {suspend}",
+
+        c_ra = const offset_of!(SuspendContext, ra),
+        c_sp = const offset_of!(SuspendContext, sp),
+        c_gp = const offset_of!(SuspendContext, gp),
+        c_tp = const offset_of!(SuspendContext, tp),
+        c_s0 = const offset_of!(SuspendContext, s0),
+        c_s1 = const offset_of!(SuspendContext, s1),
+        c_s2 = const offset_of!(SuspendContext, s2),
+        c_s3 = const offset_of!(SuspendContext, s3),
+        c_s4 = const offset_of!(SuspendContext, s4),
+        c_s5 = const offset_of!(SuspendContext, s5),
+        c_s6 = const offset_of!(SuspendContext, s6),
+        c_s7 = const offset_of!(SuspendContext, s7),
+        c_s8 = const offset_of!(SuspendContext, s8),
+        c_s9 = const offset_of!(SuspendContext, s9),
+        c_s10 = const offset_of!(SuspendContext, s10),
+        c_s11 = const offset_of!(SuspendContext, s11),
+        c_satp = const offset_of!(SuspendContext, satp),
+        c_stvec = const offset_of!(SuspendContext, stvec),
+        c_sscratch = const offset_of!(SuspendContext, sscratch),
+        c_sie = const offset_of!(SuspendContext, sie),
+        c_sstatus = const offset_of!(SuspendContext, sstatus),
+        c_stimecmp = const offset_of!(SuspendContext, stimecmp),
+        suspend = sym suspend,
+    )
+}
+
+unsafe extern "C" fn suspend(context: *mut SuspendContext) {
+    let result = unsafe {
+        hart_state_management::hart_suspend(
+            SUSPEND_TYPE_DEFAULT_NON_RETENTIVE,
+            resume_entry as *const () as usize,
+            context.addr(),
+        )
+    };
+    // a successful suspend never returns here
+    let error = result.err().unwrap_or(SbiError::FAILED);
+    unsafe {
+        (*context).error = Some(error);
+    }
+}
+
+/// Resumes the hart suspended by `save_and_suspend`, returning from it.
+///
+/// The SBI implementation enters with the address translation and the
+/// interrupts disabled, and the context in `a1`.
+#[unsafe(naked)]
+unsafe extern "C" fn resume_entry(hartid: usize, context: *mut SuspendContext) -> ! {
+    naked_asm!(
+        "ld t0, {c_satp}(a1)",
+        "csrw satp, t0",
+        "sfence.vma",
+        "ld t0, {c_stvec}(a1)",
+        "csrw stvec, t0",
+        "ld t0, {c_sscratch}(a1)",
+        "csrw sscratch, t0",
+        "ld t0, {c_sie}(a1)",
+        "csrw sie, t0",
+        "ld t0, {c_sstatus}(a1)",
+        "csrw sstatus, t0",
+        "ld t0, {c_stimecmp}(a1)",
+        "csrw stimecmp, t0",
+
+        "ld ra, {c_ra}(a1)",
+        "ld sp, {c_sp}(a1)",
+        "ld gp, {c_gp}(a1)",
+        "ld tp, {c_tp}(a1)",
+        "ld s0, {c_s0}(a1)",
+        "ld s1, {c_s1}(a1)",
+        "ld s2, {c_s2}(a1)",
+        "ld s3, {c_s3}(a1)",
+        "ld s4, {c_s4}(a1)",
+        "ld s5, {c_s5}(a1)",
+        "ld s6, {c_s6}(a1)",
+        "ld s7, {c_s7}(a1)",
+        "ld s8, {c_s8}(a1)",
+        "ld s9, {c_s9}(a1)",
+        "ld s10, {c_s10}(a1)",
+        "ld s11, {c_s11}(a1)",
+        "ret",
+
+        c_ra = const offset_of!(SuspendContext, ra),
+        c_sp = const offset_of!(SuspendContext, sp),
+        c_gp = const offset_of!(SuspendContext, gp),
+        c_tp = const offset_of!(SuspendContext, tp),
+        c_s0 = const offset_of!(SuspendContext, s0),
+        c_s1 = const offset_of!(SuspendContext, s1),
+        c_s2 = const offset_of!(SuspendContext, s2),
+        c_s3 = const offset_of!(SuspendContext, s3),
+        c_s4 = const offset_of!(SuspendContext, s4),
+        c_s5 = const offset_of!(SuspendContext, s5),
+        c_s6 = const offset_of!(SuspendContext, s6),
+        c_s7 = const offset_of!(SuspendContext, s7),
+        c_s8 = const offset_of!(SuspendContext, s8),
+        c_s9 = const offset_of!(SuspendContext, s9),
+        c_s10 = const offset_of!(SuspendContext, s10),
+        c_s11 = const offset_of!(SuspendContext, s11),
+        c_satp = const offset_of!(SuspendContext, satp),
+        c_stvec = const offset_of!(SuspendContext, stvec),
+        c_sscratch = const offset_of!(SuspendContext, sscratch),
+        c_sie = const offset_of!(SuspendContext, sie),
+        c_sstatus = const offset_of!(SuspendContext, sstatus),
+        c_stimecmp = const offset_of!(SuspendContext, stimecmp),
+    )
+}
@@ -0,0 +1,14 @@
+use sbi::SbiError;
+
+#[derive(Debug)]
+pub struct SuspendContext;
+
+impl SuspendContext {
+    pub const fn new() -> Self {
+        Self
+    }
+}
+
+pub unsafe fn suspend_non_retentive(_context: &mut SuspendContext) -> Result<(), SbiError> {
+    unimplemented!("unsupported architecture");
+}
@@ -0,0 +1,256 @@
+//! Idle states of the CPUs and the governor choosing among them.
+//!
+//! A CPU with no runnable task waits for interrupts in one of the
+//! [`IdleState`]s. The deeper states save more power, but cost more to enter
+//! and exit, so they pay off only for long idle periods. The governor predicts
+//! the idle period from the next timer event of the CPU, as the scheduler tick
+//! is stopped while the CPU is idle, and chooses the deepest state whose
+//! target residency fits in it.
+//!
+//! The SBI suspend states are disabled on the first failure, e.g. if the SBI
+//! implementation does not support them, and `wfi` is used instead.
+
+use core::{
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::Duration,
+};
+
+use sbi::hart_state_management::{self, SUSPEND_TYPE_DEFAULT_RETENTIVE};
+use snafu::ensure_whatever;
+
+use super::Cpuid;
+use crate::{
+    interrupt::{self, timer},
+    sync::spinlock::IrqSpinMutex,
+    tunables::Tunable,
+};
+
+mod imp;
+
+/// Deepest idle state the governor may choose.
+pub static MAX_STATE: Tunable<u32> = Tunable::new(
+    "idle.max_state",
+    "deepest idle state (0: wfi, 1: retentive suspend, 2: non-retentive suspend)",
+    2,
+)
+.with_check(|value| {
+    ensure_whatever!(
+        usize::try_from(value).is_ok_and(|value| value < IdleState::ALL.len()),
+        "no such idle state"
+    );
+    Ok(())
+});
+
+/// Whether each idle state is usable, cleared on the first failure to enter
+/// it.
+static USABLE: [AtomicBool; IdleState::ALL.len()] = [const { AtomicBool::new(true) }; _];
+
+cpu_local! {
+    static STATS: [AtomicStateStats; IdleState::ALL.len()] =
+        [const { AtomicStateStats::new() }; _];
+    static SUSPEND_CONTEXT: IrqSpinMutex<imp::SuspendContext> =
+        IrqSpinMutex::new(imp::SuspendContext::new());
+}
+
+/// Low power state of an idle CPU, from the shallowest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdleState {
+    /// Waits for interrupts by `wfi`.
+    Wfi,
+    /// SBI default retentive suspend, which preserves the hart state.
+    RetentiveSuspend,
+    /// SBI default non-retentive suspend, which loses the hart state, so the
+    /// registers are saved before the suspend and restored on resume.
+    NonRetentiveSuspend,
+}
+
+impl IdleState {
+    pub const ALL: [Self; 3] = [Self::Wfi, Self::RetentiveSuspend, Self::NonRetentiveSuspend];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Wfi => "wfi",
+            Self::RetentiveSuspend => "retentive",
+            Self::NonRetentiveSuspend => "non-retentive",
+        }
+    }
+
+    /// Shortest idle period for which the state saves power over the
+    /// shallower states.
+    pub fn target_residency(self) -> Duration {
+        match self {
+            Self::Wfi => Duration::ZERO,
+            Self::RetentiveSuspend => Duration::from_micros(100),
+            Self::NonRetentiveSuspend => Duration::from_millis(5),
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    /// Returns whether the state has not failed to be entered.
+    pub fn is_usable(self) -> bool {
+        USABLE[self.index()].load(Ordering::Relaxed)
+    }
+}
+
+/// Residency statistics of an idle state of a CPU.
+#[derive(Debug, Clone, Copy, Default)]
+#[cfg_attr(not(feature = "shell"), expect(dead_code))]
+pub struct StateStats {
+    /// Number of times the state was entered.
+    pub entries: u64,
+    /// Time spent in the state.
+    pub residency: Duration,
+    /// Number of wakeups before the target residency of the state, for which
+    /// a shallower state would have been better.
+    pub early_wakeups: u64,
+}
+
+#[derive(Debug)]
+struct AtomicStateStats {
+    entries: AtomicU64,
+    residency_nanos: AtomicU64,
+    early_wakeups: AtomicU64,
+}
+
+impl AtomicStateStats {
+    const fn new() -> Self {
+        Self {
+            entries: AtomicU64::new(0),
+            residency_nanos: AtomicU64::new(0),
+            early_wakeups: AtomicU64::new(0),
+        }
+    }
+
+    fn record(&self, state: IdleState, residency: Duration) {
+        self.entries.fetch_add(1, Ordering::Relaxed);
+        self.residency_nanos.fetch_add(
+            u64::try_from(residency.as_nanos()).unwrap_or(u64::MAX),
+            Ordering::Relaxed,
+        );
+        if residency < state.target_residency() {
+            self.early_wakeups.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn load(&self) -> StateStats {
+        StateStats {
+            entries: self.entries.load(Ordering::Relaxed),
+            residency: Duration::from_nanos(self.residency_nanos.load(Ordering::Relaxed)),
+            early_wakeups: self.early_wakeups.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Waits for interrupts in the idle state chosen for the predicted idle
+/// period, and returns the time spent idle.
+///
+/// Called by the scheduler with the interrupts disabled. The pending
+/// interrupts wake up the CPU, and are taken after the interrupts are
+/// enabled again.
+pub fn enter() -> Duration {
+    assert!(!interrupt::is_enabled());
+
+    let start = timer::now();
+    let predicted =
+        timer::next_deadline().map(|deadline| deadline.saturating_duration_since(start));
+    let state = select(predicted);
+    let state = match enter_state(state) {
+        Ok(()) => state,
+        Err(e) => {
+            warn!("failed to enter idle state {}, disabled: {e}", state.name());
+            USABLE[state.index()].store(false, Ordering::Relaxed);
+            interrupt::wait();
+            IdleState::Wfi
+        }
+    };
+    let residency = timer::now().saturating_duration_since(start);
+    STATS.get()[state.index()].record(state, residency);
+    residency
+}
+
+/// Chooses the deepest usable state whose target residency fits in the
+/// predicted idle period.
+///
+/// The idle period is unbounded if no timer event is queued.
+fn select(predicted: Option<Duration>) -> IdleState {
+    let max_state = usize::try_from(MAX_STATE.get()).unwrap_or(usize::MAX);
+    IdleState::ALL
+        .into_iter()
+        .take(max_state.saturating_add(1))
+        .rev()
+        .find(|state| {
+            state.is_usable()
+                && predicted.is_none_or(|predicted| predicted >= state.target_residency())
+        })
+        .unwrap_or(IdleState::Wfi)
+}
+
+fn enter_state(state: IdleState) -> Result<(), sbi::SbiError> {
+    match state {
+        IdleState::Wfi => {
+            interrupt::wait();
+            Ok(())
+        }
+        IdleState::RetentiveSuspend => unsafe {
+            hart_state_management::hart_suspend(SUSPEND_TYPE_DEFAULT_RETENTIVE, 0, 0)
+        },
+        IdleState::NonRetentiveSuspend => {
+            // the per-CPU data is allocated from the identity mapped heap, so
+            // the context is at the same address after resuming with the
+            // address translation disabled
+            let mut context = SUSPEND_CONTEXT.get().lock();
+            let result = unsafe { imp::suspend_non_retentive(&mut context) };
+            context.unlock();
+            result
+        }
+    }
+}
+
+/// Returns the residency statistics of the idle states of the given CPU, in
+/// the order of [`IdleState::ALL`].
+#[cfg_attr(not(feature = "shell"), expect(dead_code))]
+pub fn stats(cpuid: Cpuid) -> Option<[StateStats; IdleState::ALL.len()]> {
+    let stats = STATS.try_get_for(cpuid)?;
+    Some(core::array::from_fn(|index| stats[index].load()))
+}
+
+#[cfg(feature = "ktest")]
+pub mod ktests {
+    use core::time::Duration;
+
+    use snafu::ensure_whatever;
+
+    use super::{IdleState, MAX_STATE, select};
+    use crate::{error::GenericError, ktest::KernelTest};
+
+    pub static TESTS: &[KernelTest] = kernel_tests![select_by_prediction];
+
+    fn select_by_prediction() -> Result<(), GenericError> {
+        let state = select(Some(Duration::ZERO));
+        ensure_whatever!(
+            state == IdleState::Wfi,
+            "{} selected for no idle period",
+            state.name()
+        );
+        let max_state = usize::try_from(MAX_STATE.get()).unwrap();
+        let deepest = IdleState::ALL[..=max_state]
+            .iter()
+            .rev()
+            .copied()
+            .find(|state| state.is_usable())
+            .unwrap();
+        for predicted in [Some(Duration::from_secs(1)), None] {
+            let state = select(predicted);
+            ensure_whatever!(
+                state == deepest,
+                "{} selected for {predicted:?}, expected {}",
+                state.name(),
+                deepest.name()
+            );
+        }
+        Ok(())
+    }
+}
@@ -2,16 +2,23 @@ use alloc::{slice, vec::Vec};
 use core::{
     fmt,
     iter::{FusedIterator, Peekable},
+    sync::atomic::{AtomicBool, Ordering},
 };
 
 use devtree::Devicetree;
 use platform_cast::CastFrom as _;
+use sbi::HartMask;
 use snafu::ResultExt as _;
 use spin::Once;
 
-use crate::error::GenericError;
+use crate::{
+    cmdline::{FromParam as _, ParamDescriptor},
+    error::GenericError,
+};
 
 mod de;
+pub mod hotplug;
+pub mod idle;
 
 cpu_local! {
     static CURRENT_CPU: Once<&'static Cpu> = Once::new();
@@ -41,6 +48,13 @@ impl Cpuid {
 pub struct Cpu {
     id: Cpuid,
     timer_frequency: u64,
+    /// Whether the CPU supports the Svpbmt extension, for the page-based
+    /// memory types.
+    has_svpbmt: bool,
+    numa_node: Option<u32>,
+    /// Size of the cache blocks managed by the Zicbom instructions, or `None`
+    /// if the CPU does not support the extension.
+    cbom_block_size: Option<usize>,
 }
 
 unsafe impl Send for Cpu {}
@@ -55,20 +69,54 @@ impl Cpu {
         self.timer_frequency
     }
 
+    pub fn has_svpbmt(&self) -> bool {
+        self.has_svpbmt
+    }
+
+    /// Returns the NUMA node of the CPU from `numa-node-id`.
+    pub fn numa_node(&self) -> Option<u32> {
+        self.numa_node
+    }
+
+    pub fn cbom_block_size(&self) -> Option<usize> {
+        self.cbom_block_size
+    }
+
     pub fn is_current(&self) -> bool {
         try_current().is_some_and(|cpu| cpu.id() == self.id)
     }
 }
 
 static ALL_CPUS: Once<Vec<Cpu>> = Once::new();
+static BOOT_CPUID: Once<Cpuid> = Once::new();
+
+/// Whether only the boot CPU is used, given by the `nosmp` option.
+static NOSMP: AtomicBool = AtomicBool::new(false);
+
+pub static NOSMP_PARAM: ParamDescriptor = ParamDescriptor {
+    name: "nosmp",
+    handler: |value| {
+        NOSMP.store(bool::from_param(value)?, Ordering::Relaxed);
+        Ok(())
+    },
+};
 
-pub fn init(dt: &Devicetree) -> Result<(), GenericError> {
+/// Initializes the table of the CPUs used by the kernel.
+///
+/// With the `nosmp` option, the table contains only the boot CPU, and the
+/// other CPUs are never started.
+pub fn init(dt: &Devicetree, boot_cpuid: Cpuid) -> Result<(), GenericError> {
     let mut all_cpus = de::deserialize(dt).whatever_context("failed to deserialize devicetree")?;
+    if NOSMP.load(Ordering::Relaxed) {
+        all_cpus.retain(|cpu| cpu.id == boot_cpuid);
+        info!("SMP disabled, using only CPU#{boot_cpuid}");
+    }
 
     // sort cpus by cpuid
     all_cpus.sort_by(|a, b| Cpuid::cmp(&a.id, &b.id));
 
     ALL_CPUS.call_once(|| all_cpus);
+    BOOT_CPUID.call_once(|| boot_cpuid);
 
     Ok(())
 }
@@ -98,21 +146,98 @@ pub fn get_all() -> &'static [Cpu] {
     ALL_CPUS.get().unwrap()
 }
 
-#[derive(Clone, Copy)]
-pub struct CpuMask {
-    pub mask: usize,
-    pub base: usize,
+#[track_caller]
+pub fn boot_cpuid() -> Cpuid {
+    *BOOT_CPUID.get().unwrap()
+}
+
+/// Set of the CPUs, e.g. the CPUs a task may run on.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct CpuSet {
+    words: [u64; Self::WORDS],
+}
+
+impl CpuSet {
+    /// Maximum number of the CPU ids in a set.
+    pub const CAPACITY: usize = 512;
+    const WORDS: usize = Self::CAPACITY / 64;
+
+    pub const fn empty() -> Self {
+        Self {
+            words: [0; Self::WORDS],
+        }
+    }
+
+    /// Returns the set of all the CPUs, including the ones added later.
+    pub const fn all() -> Self {
+        Self {
+            words: [u64::MAX; Self::WORDS],
+        }
+    }
+
+    #[cfg_attr(not(feature = "ktest"), expect(dead_code))]
+    pub fn single(cpuid: Cpuid) -> Self {
+        let mut set = Self::empty();
+        set.insert(cpuid);
+        set
+    }
+
+    /// Adds the CPU to the set.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the CPU id is not less than [`Self::CAPACITY`].
+    pub fn insert(&mut self, cpuid: Cpuid) {
+        assert!(
+            cpuid.value() < Self::CAPACITY,
+            "CPU#{cpuid} exceeds CPU set capacity"
+        );
+        self.words[cpuid.value() / 64] |= 1 << (cpuid.value() % 64);
+    }
+
+    pub fn contains(&self, cpuid: Cpuid) -> bool {
+        self.words
+            .get(cpuid.value() / 64)
+            .is_some_and(|word| word & (1 << (cpuid.value() % 64)) != 0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.iter().all(|&word| word == 0)
+    }
+
+    /// Returns the CPUs of the set, in the order of their ids.
+    pub fn iter(&self) -> impl Iterator<Item = Cpuid> + '_ {
+        (0..Self::CAPACITY)
+            .map(Cpuid)
+            .filter(|&cpuid| self.contains(cpuid))
+    }
+}
+
+impl Default for CpuSet {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+impl FromIterator<Cpuid> for CpuSet {
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = Cpuid>,
+    {
+        let mut set = Self::empty();
+        for cpuid in iter {
+            set.insert(cpuid);
+        }
+        set
+    }
 }
 
-impl fmt::Debug for CpuMask {
+impl fmt::Debug for CpuSet {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_set()
-            .entries(
-                (0..usize::BITS)
-                    .filter(|&i| self.mask & (1 << i) != 0)
-                    .map(|i| Cpuid(self.base + usize::cast_from(i))),
-            )
-            .finish()
+        if *self == Self::all() {
+            return f.write_str("all");
+        }
+        f.debug_set().entries(self.iter()).finish()
     }
 }
 
@@ -123,12 +248,12 @@ pub struct RemoteCpuMaskIter {
 }
 
 impl Iterator for RemoteCpuMaskIter {
-    type Item = CpuMask;
+    type Item = HartMask;
 
     fn next(&mut self) -> Option<Self::Item> {
         loop {
             let base_cpu = self.cpus.next()?;
-            if base_cpu.id() == self.current_cpuid {
+            if base_cpu.id() == self.current_cpuid || !hotplug::is_running(base_cpu.id()) {
                 continue;
             }
 
@@ -138,18 +263,20 @@ impl Iterator for RemoteCpuMaskIter {
                 .cpus
                 .next_if(|cpu| cpu.id().value() - base < usize::cast_from(usize::BITS))
             {
-                if cpu.id() != self.current_cpuid {
+                if cpu.id() != self.current_cpuid && hotplug::is_running(cpu.id()) {
                     mask |= 1 << (cpu.id().value() - base);
                 }
             }
 
-            return Some(CpuMask { mask, base });
+            return Some(HartMask::new(mask, base));
         }
     }
 }
 
 impl FusedIterator for RemoteCpuMaskIter {}
 
+/// Returns the masks of the running CPUs other than the current one, for the
+/// SBI calls taking the harts.
 pub fn remote_cpu_masks() -> RemoteCpuMaskIter {
     let current_cpuid = current().id();
     let cpus = ALL_CPUS.get().unwrap().iter().peekable();
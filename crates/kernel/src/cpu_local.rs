@@ -45,17 +45,29 @@ impl<T> CpuLocal<T> {
         if tp.is_null() {
             return None;
         }
+        Some(unsafe { self.get_in_arena(tp) })
+    }
+
+    /// Returns the instance of the given CPU.
+    ///
+    /// The returned value may be accessed concurrently by the owner CPU, so
+    /// `T` should only be accessed through atomic or lock-protected fields.
+    pub fn try_get_for(&self, cpuid: Cpuid) -> Option<&T> {
+        let arena = ARENAS.get()?.iter().find(|arena| arena.cpuid == cpuid)?;
+        Some(unsafe { self.get_in_arena(arena.arena) })
+    }
+
+    unsafe fn get_in_arena(&self, arena: *mut u8) -> &T {
         let template_offset = unsafe {
             self.template
                 .cast::<u8>()
                 .byte_offset_from_unsigned(&raw const PERCPU_START)
         };
-        let data = unsafe {
-            ptr::with_exposed_provenance::<T>(tp.addr() + template_offset)
+        unsafe {
+            ptr::with_exposed_provenance::<T>(arena.addr() + template_offset)
                 .as_ref()
                 .unwrap()
-        };
-        Some(data)
+        }
     }
 }
 
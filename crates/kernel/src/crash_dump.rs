@@ -0,0 +1,243 @@
+//! Post-mortem dump of the kernel panics.
+//!
+//! The panic handler writes the panic message, the registers and the recent
+//! trace events into a reserved memory region, which is kept across warm
+//! reboots. The dump left by the previous kernel is printed on the next boot,
+//! so that the crashes on the machines without a debugger can be diagnosed.
+//!
+//! The region is given by a child of `/reserved-memory` compatible with
+//! `onix,crash-dump`, without `no-map`:
+//!
+//! ```dts
+//! crash-dump@bfff0000 {
+//!     compatible = "onix,crash-dump";
+//!     reg = <0x0 0xbfff0000 0x0 0x10000>;
+//! };
+//! ```
+//!
+//! The region starts with a header of the magic number and the length of the
+//! dump, followed by the dump as text. The magic number is written last, so a
+//! dump interrupted by a reset is ignored.
+
+use alloc::string::String;
+use core::{
+    fmt::{self, Write as _},
+    panic::PanicInfo,
+    ptr, slice,
+    sync::atomic::{self, AtomicBool, Ordering},
+};
+
+use platform_cast::CastFrom as _;
+use snafu::ensure_whatever;
+use spin::Once;
+
+use crate::{
+    cpu::{self, Cpu},
+    error::GenericError,
+    interrupt::{timer, trap},
+    memory::reserved::{self, ReservedRegion},
+    task::scheduler,
+    trace,
+};
+
+const COMPATIBLE: &str = "onix,crash-dump";
+const MAGIC: [u8; 8] = *b"ONIXDUMP";
+const HEADER_SIZE: usize = 16;
+
+/// Number of the trace events dumped for each CPU.
+const TRACE_RECORDS_PER_CPU: usize = 16;
+
+static REGION: Once<&'static ReservedRegion> = Once::new();
+
+/// Whether the dump is being written, so that only the first panic is
+/// recorded.
+static SAVING: AtomicBool = AtomicBool::new(false);
+
+initcall!(INITCALL, memory, init);
+
+/// Prints the dump left by the previous kernel, and arms the region for the
+/// panics of this kernel.
+fn init() -> Result<(), GenericError> {
+    let Some(region) = reserved::regions()
+        .iter()
+        .find(|region| region.is_compatible_to(COMPATIBLE))
+    else {
+        return Ok(());
+    };
+    ensure_whatever!(
+        region.is_mapped(),
+        "crash dump region {} must not have no-map",
+        region.path()
+    );
+    ensure_whatever!(
+        region.range().len() > HEADER_SIZE,
+        "crash dump region {} is too small",
+        region.path()
+    );
+    region.claim()?;
+
+    // the region is not used by anyone else, and the panics are not recorded
+    // until `REGION` is set
+    let bytes = unsafe { region_bytes(region) };
+    if let Some(dump) = read_dump(bytes) {
+        let dump = String::from_utf8_lossy(dump);
+        let mut lines = dump.lines();
+        if let Some(line) = lines.next() {
+            warn!("previous kernel {line}");
+        }
+        for line in lines {
+            warn!("  {line}");
+        }
+    }
+    clear_dump(bytes);
+
+    REGION.call_once(|| region);
+    info!("crash dump enabled, range={:#x?}", region.range());
+    Ok(())
+}
+
+/// Writes the dump of the panic to the crash dump region, if any.
+///
+/// Called first in the panic handler, so that the dump is recorded even if
+/// the console output hangs.
+pub fn save(info: &PanicInfo<'_>) {
+    let Some(region) = REGION.get() else {
+        return;
+    };
+    if SAVING.swap(true, Ordering::AcqRel) {
+        return;
+    }
+    let registers = trap::current_registers();
+    // only the first panicking CPU gets here
+    let bytes = unsafe { region_bytes(region) };
+    write_dump(bytes, |writer| {
+        match info.location() {
+            Some(location) => writeln!(writer, "panicked at {location}")?,
+            None => writeln!(writer, "panicked at <Unknown>")?,
+        }
+        writeln!(writer, "Message: {}", info.message())?;
+        match cpu::try_current() {
+            Some(cpu) => writeln!(writer, "CPU: {}", cpu.id())?,
+            None => writeln!(writer, "CPU: <Unknown>")?,
+        }
+        match scheduler::try_current_task_id() {
+            Some(task) => writeln!(writer, "Task: {task}")?,
+            None => writeln!(writer, "Task: <Unknown>")?,
+        }
+        if let Some(now) = timer::try_now() {
+            writeln!(writer, "Uptime: {:?}", now.duration_since_epoc())?;
+        }
+        writeln!(writer, "Registers:")?;
+        write!(writer, "{registers:?}")?;
+        writeln!(writer, "Trace (newest first):")?;
+        for cpu in cpu::get_all() {
+            write_trace(writer, cpu)?;
+        }
+        Ok(())
+    });
+}
+
+fn write_trace(writer: &mut DumpWriter<'_>, cpu: &Cpu) -> fmt::Result {
+    for record in trace::recent_records(cpu, TRACE_RECORDS_PER_CPU) {
+        writeln!(writer, "{record}")?;
+    }
+    Ok(())
+}
+
+/// Returns the bytes of the crash dump region.
+///
+/// # Safety
+///
+/// The region must be mapped and not accessed by others while the returned
+/// slice is alive.
+unsafe fn region_bytes(region: &ReservedRegion) -> &'static mut [u8] {
+    let range = region.range();
+    unsafe { slice::from_raw_parts_mut(ptr::with_exposed_provenance_mut(range.start), range.len()) }
+}
+
+/// Returns the dump stored in `region`, if any.
+fn read_dump(region: &[u8]) -> Option<&[u8]> {
+    let (header, data) = region.split_at(HEADER_SIZE);
+    let (magic, len) = header.split_at(MAGIC.len());
+    if magic != MAGIC {
+        return None;
+    }
+    let len = u64::from_le_bytes(len.try_into().unwrap());
+    data.get(..usize::try_from(len).ok()?)
+}
+
+/// Stores the dump written by `f` in `region`, truncated to the region size.
+fn write_dump<F>(region: &mut [u8], f: F)
+where
+    F: FnOnce(&mut DumpWriter<'_>) -> fmt::Result,
+{
+    clear_dump(region);
+    let (header, data) = region.split_at_mut(HEADER_SIZE);
+    let mut writer = DumpWriter { data, len: 0 };
+    // the output is truncated if the region is full
+    let _ = f(&mut writer);
+    let len = u64::cast_from(writer.len);
+    header[MAGIC.len()..].copy_from_slice(&len.to_le_bytes());
+    atomic::fence(Ordering::SeqCst);
+    header[..MAGIC.len()].copy_from_slice(&MAGIC);
+}
+
+fn clear_dump(region: &mut [u8]) {
+    region[..MAGIC.len()].fill(0);
+    atomic::fence(Ordering::SeqCst);
+}
+
+struct DumpWriter<'a> {
+    data: &'a mut [u8],
+    len: usize,
+}
+
+impl fmt::Write for DumpWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let rest = &mut self.data[self.len..];
+        let n = s.len().min(rest.len());
+        rest[..n].copy_from_slice(&s.as_bytes()[..n]);
+        self.len += n;
+        if n < s.len() {
+            return Err(fmt::Error);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "ktest")]
+pub mod ktests {
+    use core::fmt::Write as _;
+
+    use snafu::ensure_whatever;
+
+    use super::{HEADER_SIZE, clear_dump, read_dump, write_dump};
+    use crate::{error::GenericError, ktest::KernelTest};
+
+    pub static TESTS: &[KernelTest] = kernel_tests![dump_round_trip, dump_truncated];
+
+    fn dump_round_trip() -> Result<(), GenericError> {
+        let mut region = [0xff; 64];
+        ensure_whatever!(read_dump(&region).is_none(), "dump found in empty region");
+
+        write_dump(&mut region, |writer| writeln!(writer, "panicked at {}", 42));
+        let dump = read_dump(&region);
+        ensure_whatever!(dump == Some(b"panicked at 42\n"), "got {dump:?}");
+
+        clear_dump(&mut region);
+        ensure_whatever!(read_dump(&region).is_none(), "dump found after clear");
+        Ok(())
+    }
+
+    fn dump_truncated() -> Result<(), GenericError> {
+        let mut region = [0; HEADER_SIZE + 8];
+        write_dump(&mut region, |writer| {
+            writer.write_str("0123")?;
+            writer.write_str("456789")?;
+            writer.write_str("abc")
+        });
+        let dump = read_dump(&region);
+        ensure_whatever!(dump == Some(b"01234567"), "got {dump:?}");
+        Ok(())
+    }
+}
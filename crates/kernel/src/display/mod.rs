@@ -0,0 +1,123 @@
+//! Displays showing the kernel console output.
+//!
+//! A display driver registers its display with [`register`], and the first
+//! registered display shows the console output as text.
+
+use alloc::sync::Arc;
+
+use snafu::ResultExt as _;
+use spin::Once;
+
+use self::text::TextConsole;
+use crate::{console, error::GenericError};
+
+mod font;
+pub mod text;
+
+static TEXT_CONSOLE: Once<TextConsole> = Once::new();
+
+/// Rectangle on a display, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    /// Returns the smallest rectangle containing both rectangles.
+    #[must_use]
+    pub fn union(self, other: Self) -> Self {
+        let x = usize::min(self.x, other.x);
+        let y = usize::min(self.y, other.y);
+        let right = usize::max(self.x + self.width, other.x + other.width);
+        let bottom = usize::max(self.y + self.height, other.y + other.height);
+        Self {
+            x,
+            y,
+            width: right - x,
+            height: bottom - y,
+        }
+    }
+
+    /// Returns whether the rectangle is inside a display of the size.
+    pub fn is_within(self, width: usize, height: usize) -> bool {
+        self.x + self.width <= width && self.y + self.height <= height
+    }
+}
+
+/// Display whose pixels are written by the CPU.
+pub trait Display: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Returns the width and the height in pixels.
+    fn size(&self) -> (usize, usize);
+
+    /// Writes the `0xRRGGBB` colors of the pixels in `rect`, row by row.
+    ///
+    /// The pixels may not be shown until `rect` is flushed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rect` is out of the display, or if the number of the pixels
+    /// does not match.
+    fn write_rect(&self, rect: Rect, pixels: &[u32]);
+
+    /// Shows the pixels written in `rect`.
+    fn flush(&self, rect: Rect);
+}
+
+/// Registers `display`, which shows the console output if no display does.
+pub fn register(display: Arc<dyn Display>) -> Result<(), GenericError> {
+    let (width, height) = display.size();
+    info!("display {}: {width}x{height}", display.name());
+    if TEXT_CONSOLE.is_completed() {
+        return Ok(());
+    }
+    let text = TextConsole::new(display)?;
+    let (cols, rows) = text.size();
+    let text = TEXT_CONSOLE.call_once(|| text);
+    console::register_sink(text).whatever_context("failed to show console on display")?;
+    info!("showing console in {cols}x{rows} characters");
+    Ok(())
+}
+
+#[cfg(feature = "ktest")]
+pub mod ktests {
+    use snafu::ensure_whatever;
+
+    use super::Rect;
+    use crate::{error::GenericError, ktest::KernelTest};
+
+    pub static TESTS: &[KernelTest] = kernel_tests![rect_union];
+
+    fn rect_union() -> Result<(), GenericError> {
+        let a = Rect {
+            x: 8,
+            y: 0,
+            width: 8,
+            height: 8,
+        };
+        let b = Rect {
+            x: 0,
+            y: 16,
+            width: 8,
+            height: 8,
+        };
+        let union = a.union(b);
+        ensure_whatever!(
+            union
+                == Rect {
+                    x: 0,
+                    y: 0,
+                    width: 16,
+                    height: 24,
+                },
+            "unexpected union: {union:?}"
+        );
+        ensure_whatever!(union.is_within(16, 24), "union out of 16x24");
+        ensure_whatever!(!union.is_within(16, 23), "union within 16x23");
+        Ok(())
+    }
+}
@@ -0,0 +1,238 @@
+//! Console output drawn as text on a display.
+//!
+//! The output is drawn with the built-in 8x8 font in light gray on black. The
+//! escape sequences are skipped, and the screen is scrolled up when the cursor
+//! goes past the last row. Only the characters changed since the last write
+//! are drawn and flushed, as the display memory may be uncached or copied to
+//! the device.
+
+use alloc::{sync::Arc, vec, vec::Vec};
+
+use snafu::ensure_whatever;
+
+use super::{Display, Rect, font};
+use crate::{console::ConsoleSink, error::GenericError, sync::spinlock::SpinMutex};
+
+const FOREGROUND: u32 = 0x00aa_aaaa;
+const BACKGROUND: u32 = 0x0000_0000;
+const TAB_WIDTH: usize = 8;
+const ESC: u8 = 0x1b;
+const BACKSPACE: u8 = 0x08;
+
+/// Display showing the console output.
+pub(super) struct TextConsole {
+    display: Arc<dyn Display>,
+    cols: usize,
+    state: SpinMutex<State>,
+}
+
+struct State {
+    screen: TextScreen,
+    /// Characters drawn on the display.
+    drawn: Vec<u8>,
+}
+
+impl TextConsole {
+    /// Clears `display`, and shows the console output written after this.
+    pub(super) fn new(display: Arc<dyn Display>) -> Result<Self, GenericError> {
+        let (width, height) = display.size();
+        let cols = width / font::WIDTH;
+        let rows = height / font::HEIGHT;
+        ensure_whatever!(
+            cols > 0 && rows > 0,
+            "display {width}x{height} too small for text"
+        );
+
+        let line = vec![BACKGROUND; width];
+        for y in 0..height {
+            let rect = Rect {
+                x: 0,
+                y,
+                width,
+                height: 1,
+            };
+            display.write_rect(rect, &line);
+        }
+        display.flush(Rect {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        });
+        Ok(Self {
+            display,
+            cols,
+            state: SpinMutex::new(State {
+                screen: TextScreen::new(cols, rows),
+                drawn: vec![b' '; cols * rows],
+            }),
+        })
+    }
+
+    /// Returns the number of the columns and the rows.
+    pub(super) fn size(&self) -> (usize, usize) {
+        let state = self.state.lock();
+        let size = (state.screen.cols, state.screen.rows);
+        state.unlock();
+        size
+    }
+
+    /// Draws `ch` at the cell, and returns the rectangle of the cell.
+    fn draw_char(&self, col: usize, row: usize, ch: u8) -> Rect {
+        let mut pixels = [BACKGROUND; font::WIDTH * font::HEIGHT];
+        for (line, bits) in pixels.chunks_mut(font::WIDTH).zip(font::glyph(ch)) {
+            for (x, pixel) in line.iter_mut().enumerate() {
+                if bits & (1 << x) != 0 {
+                    *pixel = FOREGROUND;
+                }
+            }
+        }
+        let rect = Rect {
+            x: col * font::WIDTH,
+            y: row * font::HEIGHT,
+            width: font::WIDTH,
+            height: font::HEIGHT,
+        };
+        self.display.write_rect(rect, &pixels);
+        rect
+    }
+}
+
+impl ConsoleSink for TextConsole {
+    fn write_bytes(&self, bytes: &[u8]) {
+        let mut state = self.state.lock();
+        let State { screen, drawn } = &mut *state;
+        for &byte in bytes {
+            screen.feed(byte);
+        }
+        let mut dirty = None::<Rect>;
+        for (i, (drawn, &ch)) in drawn.iter_mut().zip(&screen.cells).enumerate() {
+            if *drawn != ch {
+                let rect = self.draw_char(i % self.cols, i / self.cols, ch);
+                dirty = Some(dirty.map_or(rect, |dirty| dirty.union(rect)));
+                *drawn = ch;
+            }
+        }
+        if let Some(dirty) = dirty {
+            self.display.flush(dirty);
+        }
+        state.unlock();
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Escape {
+    None,
+    /// `ESC` is received.
+    Started,
+    /// Inside a control sequence, started by `ESC [`.
+    Csi,
+}
+
+/// Characters on the screen, updated by the console output.
+#[derive(Debug)]
+struct TextScreen {
+    cols: usize,
+    rows: usize,
+    /// Characters of the rows from the top.
+    cells: Vec<u8>,
+    /// Column of the cursor, which is `cols` after the last column is written
+    /// until the next character wraps the line.
+    col: usize,
+    row: usize,
+    escape: Escape,
+}
+
+impl TextScreen {
+    fn new(cols: usize, rows: usize) -> Self {
+        Self {
+            cols,
+            rows,
+            cells: vec![b' '; cols * rows],
+            col: 0,
+            row: 0,
+            escape: Escape::None,
+        }
+    }
+
+    fn feed(&mut self, byte: u8) {
+        match (self.escape, byte) {
+            (Escape::None, ESC) => self.escape = Escape::Started,
+            (Escape::Started, b'[') => self.escape = Escape::Csi,
+            (Escape::Started, _) | (Escape::Csi, 0x40..=0x7e) => self.escape = Escape::None,
+            (Escape::None, b'\n') => self.new_line(),
+            (Escape::None, b'\r') => self.col = 0,
+            (Escape::None, b'\t') => {
+                self.col = usize::min((self.col / TAB_WIDTH + 1) * TAB_WIDTH, self.cols);
+            }
+            (Escape::None, BACKSPACE) => self.col = self.col.saturating_sub(1),
+            (Escape::None, b' '..=b'~') => self.put(byte),
+            // the first byte of a non-ASCII character
+            (Escape::None, 0xc0..) => self.put(b'?'),
+            (Escape::None | Escape::Csi, _) => {}
+        }
+    }
+
+    fn put(&mut self, ch: u8) {
+        if self.col == self.cols {
+            self.new_line();
+        }
+        self.cells[self.row * self.cols + self.col] = ch;
+        self.col += 1;
+    }
+
+    fn new_line(&mut self) {
+        self.col = 0;
+        if self.row + 1 < self.rows {
+            self.row += 1;
+            return;
+        }
+        self.cells.copy_within(self.cols.., 0);
+        let last = (self.rows - 1) * self.cols;
+        self.cells[last..].fill(b' ');
+    }
+}
+
+#[cfg(feature = "ktest")]
+pub mod ktests {
+    use snafu::ensure_whatever;
+
+    use super::TextScreen;
+    use crate::{error::GenericError, ktest::KernelTest};
+
+    pub static TESTS: &[KernelTest] = kernel_tests![skip_escape_sequences, wrap_and_scroll];
+
+    fn feed_all(screen: &mut TextScreen, input: &[u8]) {
+        for &byte in input {
+            screen.feed(byte);
+        }
+    }
+
+    fn skip_escape_sequences() -> Result<(), GenericError> {
+        let mut screen = TextScreen::new(8, 3);
+        feed_all(&mut screen, b"a\x1b[31mb\x1b[0m\xc3\xa9\tc\r\nd");
+        ensure_whatever!(
+            screen.cells == b"ab?     c       d       ",
+            "unexpected cells: {:?}",
+            screen.cells
+        );
+        Ok(())
+    }
+
+    fn wrap_and_scroll() -> Result<(), GenericError> {
+        let mut screen = TextScreen::new(4, 2);
+        feed_all(&mut screen, b"abcdef\nghij");
+        ensure_whatever!(
+            screen.cells == b"ef  ghij",
+            "unexpected cells after scroll: {:?}",
+            screen.cells
+        );
+        ensure_whatever!(
+            (screen.col, screen.row) == (4, 1),
+            "unexpected cursor: ({}, {})",
+            screen.col,
+            screen.row
+        );
+        Ok(())
+    }
+}
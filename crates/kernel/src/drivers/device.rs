@@ -0,0 +1,84 @@
+//! Devices bound to the devicetree nodes.
+//!
+//! A [`Device`] is created by the driver registry for the node being probed,
+//! and owns the resources its driver claims: the mapped registers, the
+//! interrupt lines, and the DMA buffers the device may access. The resources
+//! are released when the device is dropped, so tearing down a driver, e.g. to
+//! reset a virtio device, leaves neither mappings nor interrupt handlers
+//! behind.
+
+use alloc::{string::ToString as _, vec::Vec};
+
+use devtree::{
+    model::node::Interrupt,
+    types::{ByteStr, ByteString},
+};
+
+use crate::{
+    error::GenericError,
+    irq::{self, IrqHandler, IrqLine},
+    memory::{dma::DmaBuffer, kernel_space::MmioToken},
+    sync::spinlock::SpinMutex,
+};
+
+/// Device bound to a devicetree node, owning the resources claimed by its
+/// driver.
+///
+/// The interrupt lines are released first when the device is dropped, so
+/// that no handler runs while the buffers and the registers are released.
+#[derive(Debug)]
+pub struct Device {
+    path: ByteString,
+    // the resources are released in the field order
+    irqs: SpinMutex<Vec<IrqLine>>,
+    dma_buffers: SpinMutex<Vec<DmaBuffer>>,
+    /// Registers of the `reg` entries of the node, in their order.
+    mmio: Vec<MmioToken>,
+}
+
+impl Device {
+    pub(super) fn new(path: ByteString, mmio: Vec<MmioToken>) -> Self {
+        Self {
+            path,
+            irqs: SpinMutex::new(Vec::new()),
+            dma_buffers: SpinMutex::new(Vec::new()),
+            mmio,
+        }
+    }
+
+    pub fn path(&self) -> &ByteStr {
+        ByteStr::new(&self.path)
+    }
+
+    /// Returns the registers of the `index`th `reg` entry of the node.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the node has no such entry.
+    pub fn mmio(&self, index: usize) -> &MmioToken {
+        &self.mmio[index]
+    }
+
+    /// Requests and enables the interrupt line of `interrupt`, dispatched to
+    /// `handler` until the device is released.
+    ///
+    /// `handler` must not own the device, or the device is never released.
+    pub fn request_irq(
+        &self,
+        interrupt: &Interrupt<'_>,
+        handler: IrqHandler,
+    ) -> Result<(), GenericError> {
+        let line = irq::request_irq(&self.path.to_string(), interrupt, handler)?;
+        line.enable();
+        self.irqs.lock().push(line);
+        Ok(())
+    }
+
+    /// Keeps `buffer` until the device is released, for the buffers the device
+    /// may still access after their users are gone, such as the buffers of
+    /// the requests in flight when the driver is torn down.
+    #[expect(dead_code)]
+    pub fn keep_dma_buffer(&self, buffer: DmaBuffer) {
+        self.dma_buffers.lock().push(buffer);
+    }
+}
@@ -0,0 +1,178 @@
+//! Simple framebuffer (`simple-framebuffer`) set up by the firmware.
+
+use alloc::{
+    format,
+    string::{String, ToString as _},
+    sync::Arc,
+};
+use core::str::FromStr;
+
+use devtree::{DeserializeNode, compatible_table, model::property::Reg};
+use platform_cast::CastFrom as _;
+use snafu::{OptionExt as _, ResultExt as _, ensure_whatever};
+
+use crate::{
+    display::{self, Display, Rect},
+    drivers::registry::{ProbeContext, ProbeError},
+    error::GenericError,
+    iter::IteratorExt as _,
+    memory::kernel_space::MmioToken,
+};
+
+driver!(DRIVER {
+    name: "simple-framebuffer",
+    compatibles: compatible_table!["simple-framebuffer"],
+    probe,
+});
+
+#[derive(Debug, DeserializeNode)]
+struct FramebufferNode<'blob> {
+    #[devtree(property)]
+    reg: Reg<'blob>,
+    #[devtree(property)]
+    width: u32,
+    #[devtree(property)]
+    height: u32,
+    #[devtree(property)]
+    stride: u32,
+    #[devtree(property(parse_with_str))]
+    format: PixelFormat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PixelFormat {
+    R5G6B5,
+    X8R8G8B8,
+    A8R8G8B8,
+    X8B8G8R8,
+    A8B8G8R8,
+}
+
+/// Value of a pixel in the framebuffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pixel {
+    U16(u16),
+    U32(u32),
+}
+
+impl FromStr for PixelFormat {
+    type Err = ();
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let format = match name {
+            "r5g6b5" => Self::R5G6B5,
+            "x8r8g8b8" => Self::X8R8G8B8,
+            "a8r8g8b8" => Self::A8R8G8B8,
+            "x8b8g8r8" => Self::X8B8G8R8,
+            "a8b8g8r8" => Self::A8B8G8R8,
+            _ => return Err(()),
+        };
+        Ok(format)
+    }
+}
+
+impl PixelFormat {
+    fn bytes_per_pixel(self) -> usize {
+        match self {
+            Self::R5G6B5 => 2,
+            Self::X8R8G8B8 | Self::A8R8G8B8 | Self::X8B8G8R8 | Self::A8B8G8R8 => 4,
+        }
+    }
+
+    /// Encodes a `0xRRGGBB` color.
+    fn encode(self, rgb: u32) -> Pixel {
+        let [_, r, g, b] = rgb.to_be_bytes();
+        match self {
+            Self::R5G6B5 => {
+                Pixel::U16((u16::from(r >> 3) << 11) | (u16::from(g >> 2) << 5) | u16::from(b >> 3))
+            }
+            Self::X8R8G8B8 | Self::A8R8G8B8 => Pixel::U32(0xff00_0000 | rgb),
+            Self::X8B8G8R8 | Self::A8B8G8R8 => Pixel::U32(u32::from_le_bytes([r, g, b, 0xff])),
+        }
+    }
+}
+
+fn probe(ctx: &ProbeContext<'_>) -> Result<(), ProbeError> {
+    let framebuffer = SimpleFramebuffer::new(ctx)?;
+    display::register(Arc::new(framebuffer))?;
+    Ok(())
+}
+
+/// Framebuffer mapped in the kernel address space, shown as it is written.
+struct SimpleFramebuffer {
+    name: String,
+    pixels: MmioToken,
+    width: usize,
+    height: usize,
+    stride: usize,
+    format: PixelFormat,
+}
+
+impl SimpleFramebuffer {
+    fn new(ctx: &ProbeContext<'_>) -> Result<Self, GenericError> {
+        let node = ctx.deserialize_node::<FramebufferNode>()?;
+        let path = ctx.path();
+        let reg = node
+            .reg
+            .into_iter()
+            .assume_one()
+            .with_whatever_context(|| format!("invalid 'reg' entries in {path}"))?;
+        let format = node.format;
+        let width = usize::cast_from(node.width);
+        let height = usize::cast_from(node.height);
+        let stride = usize::cast_from(node.stride);
+        let range = reg.range();
+        ensure_whatever!(
+            width * format.bytes_per_pixel() <= stride
+                && stride.is_multiple_of(format.bytes_per_pixel())
+                && stride * height <= range.len(),
+            "framebuffer {width}x{height} does not fit in stride={stride}, range={range:#x?}"
+        );
+
+        // the firmware keeps the framebuffer out of the memory given to the
+        // kernel
+        let pixels = unsafe { ctx.map_mmio(range.clone()) }
+            .with_whatever_context(|_| format!("failed to map framebuffer, range={range:#x?}"))?;
+        Ok(Self {
+            name: path.to_string(),
+            pixels,
+            width,
+            height,
+            stride,
+            format,
+        })
+    }
+}
+
+impl Display for SimpleFramebuffer {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    fn write_rect(&self, rect: Rect, pixels: &[u32]) {
+        assert!(rect.is_within(self.width, self.height));
+        assert_eq!(pixels.len(), rect.width * rect.height);
+        let bytes_per_pixel = self.format.bytes_per_pixel();
+        for (dy, line) in pixels.chunks(rect.width).enumerate() {
+            let line_offset = (rect.y + dy) * self.stride + rect.x * bytes_per_pixel;
+            for (dx, &rgb) in line.iter().enumerate() {
+                let offset = line_offset + dx * bytes_per_pixel;
+                // the framebuffer is plain memory without side effects
+                unsafe {
+                    match self.format.encode(rgb) {
+                        Pixel::U16(value) => self.pixels.write(offset, value),
+                        Pixel::U32(value) => self.pixels.write(offset, value),
+                    }
+                }
+            }
+        }
+    }
+
+    fn flush(&self, _rect: Rect) {
+        // the writes are shown as they are
+    }
+}
@@ -0,0 +1,141 @@
+use alloc::{
+    collections::btree_map::BTreeMap,
+    sync::{Arc, Weak},
+};
+
+use devtree::{
+    DeserializeNode, Devicetree,
+    de::util,
+    model::{
+        node::{InterruptGeneratingDevice, NodePath},
+        property::{Phandle, Reg},
+    },
+    tree_cursor::TreeCursor as _,
+    types::ByteStr,
+};
+use snafu::{OptionExt as _, ResultExt as _};
+
+use super::{Aplic, AplicMmio, Delivery};
+use crate::{
+    cpu::Cpuid,
+    drivers::{
+        irq::{cpu_intc, imsic},
+        registry::ProbeContext,
+    },
+    error::GenericError,
+    iter::IteratorExt as _,
+    sync::spinlock::SpinMutex,
+};
+
+#[derive(Debug, DeserializeNode)]
+struct AplicNode<'blob> {
+    #[devtree(node)]
+    path: NodePath,
+    #[devtree(property)]
+    reg: Reg<'blob>,
+    #[devtree(property(
+        name = "riscv,num-sources",
+        deserialize_with = util::deserialize_property_as_usize_via_u32,
+    ))]
+    num_sources: usize,
+    #[devtree(property(name = "msi-parent", default))]
+    msi_parent: Option<Phandle>,
+}
+
+#[derive(Debug, DeserializeNode)]
+struct DirectAplicNode<'blob> {
+    #[devtree(node)]
+    device: InterruptGeneratingDevice<'blob>,
+}
+
+#[derive(Debug, DeserializeNode)]
+struct MsiParentNode {
+    #[devtree(node)]
+    path: NodePath,
+}
+
+pub fn deserialize(ctx: &ProbeContext<'_>) -> Result<Option<Arc<Aplic>>, GenericError> {
+    let aplic_node = ctx.deserialize_node::<AplicNode>()?;
+    // APLIC domains for M-mode are connected to the machine external interrupt
+    // or to the M-mode IMSIC, and are skipped here.
+    let Some(delivery) = deserialize_delivery(ctx.devicetree(), &aplic_node)? else {
+        return Ok(None);
+    };
+    let AplicNode {
+        path,
+        reg,
+        num_sources,
+        ..
+    } = aplic_node;
+    let reg = reg
+        .into_iter()
+        .assume_one()
+        .whatever_context("invalid 'reg' entries in aplic node")?;
+    let regs =
+        unsafe { ctx.map_mmio(reg.range()) }.whatever_context("failed to map aplic registers")?;
+    Ok(Some(Arc::new_cyclic(|this: &Weak<Aplic>| Aplic {
+        this: Weak::clone(this),
+        path: path.0,
+        mmio: SpinMutex::new(AplicMmio { regs, num_sources }),
+        delivery,
+        source_modes: SpinMutex::new(BTreeMap::new()),
+        lines: SpinMutex::new(BTreeMap::new()),
+    })))
+}
+
+fn deserialize_delivery(
+    dt: &Devicetree,
+    aplic_node: &AplicNode<'_>,
+) -> Result<Option<Delivery>, GenericError> {
+    let mut cursor = dt
+        .tree_cursor()
+        .whatever_context("failed to create tree cursor")?;
+
+    if let Some(phandle) = aplic_node.msi_parent {
+        let MsiParentNode { path } = cursor
+            .read_node_by_phandle(phandle)
+            .whatever_context("failed to read devicetree")?
+            .whatever_context("msi-parent node not found")?
+            .deserialize_node()
+            .whatever_context("failed to deserialize msi-parent node")?;
+        let Some(imsic) = imsic::find_imsic_by_dtree_path(&path.0) else {
+            return Ok(None);
+        };
+        return Ok(Some(Delivery::Msi { imsic }));
+    }
+
+    let DirectAplicNode { device } = cursor
+        .read_node_by_path(ByteStr::new(&aplic_node.path.0))
+        .whatever_context("failed to read devicetree")?
+        .whatever_context("aplic node not found")?
+        .deserialize_node()
+        .whatever_context("failed to deserialize aplic node")?;
+    let idc_map = deserialize_idc_map(&device)?;
+    if idc_map.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(Delivery::Direct { idc_map }))
+}
+
+fn deserialize_idc_map(
+    device: &InterruptGeneratingDevice<'_>,
+) -> Result<BTreeMap<Cpuid, usize>, GenericError> {
+    let mut map = BTreeMap::new();
+    for (hart_index, interrupt) in device.interrupts().iter().enumerate() {
+        let specifier = interrupt
+            .specifier()
+            .into_iter()
+            .assume_one()
+            .whatever_context("invalid interrupt specifier length")?;
+        // 9 means supervisor interrupt
+        if specifier != 9 {
+            continue;
+        }
+
+        let Some(intc) = cpu_intc::find_cpu_intc_by_dtree_path(interrupt.parent_path()) else {
+            continue;
+        };
+        map.insert(intc.cpuid(), hart_index);
+    }
+    Ok(map)
+}
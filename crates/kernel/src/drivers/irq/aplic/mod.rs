@@ -0,0 +1,456 @@
+use alloc::{
+    borrow::ToOwned as _,
+    collections::{btree_map::BTreeMap, btree_set::BTreeSet},
+    format,
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+
+use devtree::{
+    compatible_table,
+    model::property::U32Array,
+    types::{ByteStr, ByteString},
+};
+use platform_cast::CastFrom as _;
+use snafu::{OptionExt as _, ensure_whatever, whatever};
+
+use crate::{
+    cpu::{
+        self, Cpuid,
+        hotplug::{self, HotplugHook},
+    },
+    drivers::{
+        irq::{cpu_intc, imsic::Imsic},
+        registry::{ProbeContext, ProbeError},
+    },
+    error::GenericError,
+    irq::{self, HwIrq, IrqDomain, IrqHandler, IrqLine},
+    memory::kernel_space::MmioToken,
+    sync::spinlock::{IrqSpinMutex, SpinMutex},
+};
+
+mod de;
+
+const DEFAULT_PRIORITY: u32 = 1;
+const DEFAULT_THRESHOLD: u32 = 0;
+
+const DOMAINCFG: usize = 0x0000;
+const SOURCECFG_BASE: usize = 0x0004;
+const SETIPNUM: usize = 0x1cdc;
+const SETIENUM: usize = 0x1edc;
+const CLRIENUM: usize = 0x1fdc;
+const TARGET_BASE: usize = 0x3004;
+const IDC_BASE: usize = 0x4000;
+const IDC_SIZE: usize = 0x20;
+const IDC_IDELIVERY: usize = 0x00;
+const IDC_ITHRESHOLD: usize = 0x08;
+const IDC_CLAIMI: usize = 0x1c;
+
+const DOMAINCFG_IE: u32 = 1 << 8;
+const DOMAINCFG_DM: u32 = 1 << 2;
+const TARGET_HART_INDEX_SHIFT: u32 = 18;
+
+static APLIC_DEVICES: SpinMutex<Vec<Arc<Aplic>>> = SpinMutex::new(Vec::new());
+/// CPUs whose external interrupt line is requested.
+///
+/// In direct mode, a CPU has a single external interrupt line shared by all
+/// APLICs.
+static EXTERNAL_LINES: SpinMutex<BTreeSet<Cpuid>> = SpinMutex::new(BTreeSet::new());
+
+driver!(DRIVER {
+    name: "aplic",
+    compatibles: compatible_table!["riscv,aplic"],
+    probe,
+});
+
+fn probe(ctx: &ProbeContext<'_>) -> Result<(), ProbeError> {
+    ctx.require_interrupt_parents()?;
+    ctx.require_msi_parent()?;
+    attach(ctx)?;
+    Ok(())
+}
+
+fn attach(ctx: &ProbeContext<'_>) -> Result<(), GenericError> {
+    let Some(aplic) = de::deserialize(ctx)? else {
+        return Ok(());
+    };
+    let mmio = aplic.mmio.lock();
+    mmio.init(&aplic.delivery);
+    mmio.unlock();
+    irq::register_domain(Arc::clone(&aplic) as Arc<dyn IrqDomain>);
+    let cpuids = match &aplic.delivery {
+        Delivery::Direct { idc_map } => idc_map.keys().copied().collect(),
+        Delivery::Msi { .. } => Vec::new(),
+    };
+    APLIC_DEVICES.lock().push(aplic);
+
+    for cpuid in cpuids {
+        if !EXTERNAL_LINES.lock().insert(cpuid) {
+            continue;
+        }
+        let intc = cpu_intc::find_cpu_intc_for_cpu(cpuid)
+            .with_whatever_context(|| format!("no interrupt controller for CPU#{cpuid}"))?;
+        let handler = Arc::new(move || handle_external_interrupt(cpuid));
+        let line = IrqLine::request(intc, cpu_intc::SUPERVISOR_EXTERNAL, "aplic", handler)?;
+        line.enable();
+        line.leak();
+    }
+    Ok(())
+}
+
+/// Moves the interrupts targeting the CPU going offline to an online CPU.
+///
+/// The interrupts are not moved back when the CPU comes online again.
+pub static HOTPLUG_HOOK: HotplugHook = HotplugHook {
+    name: "aplic",
+    online: || {},
+    offline: || {
+        let cpuid = cpu::current().id();
+        for aplic in APLIC_DEVICES.lock().iter() {
+            aplic.retarget_from(cpuid);
+        }
+    },
+};
+
+fn handle_external_interrupt(cpuid: Cpuid) {
+    // the list is only locked to look up each APLIC, so that the handlers run
+    // without it
+    for index in 0.. {
+        let Some(aplic) = APLIC_DEVICES.lock().get(index).map(Arc::clone) else {
+            break;
+        };
+        if let Delivery::Direct { idc_map } = &aplic.delivery
+            && let Some(idc) = idc_map.get(&cpuid)
+        {
+            loop {
+                // the registers are unlocked before the handler runs, as the
+                // handler may enable or disable its own line
+                let claimed = aplic.mmio.lock().claim(*idc);
+                let Some(source) = claimed else {
+                    break;
+                };
+                aplic.dispatch(source);
+            }
+        }
+    }
+}
+
+/// RISC-V advanced platform-level interrupt controller (`riscv,aplic`).
+///
+/// Supports an interrupt domain in direct delivery mode, where interrupts are
+/// delivered through per-hart interrupt delivery controllers (IDCs), and in
+/// MSI delivery mode, where interrupts are forwarded to an [`Imsic`].
+#[derive(derive_more::Debug)]
+pub struct Aplic {
+    #[debug(skip)]
+    this: Weak<Self>,
+    path: ByteString,
+    mmio: IrqSpinMutex<AplicMmio>,
+    delivery: Delivery,
+    /// Source modes specified in the devicetree interrupt specifiers.
+    source_modes: SpinMutex<BTreeMap<usize, SourceMode>>,
+    #[debug(skip)]
+    lines: SpinMutex<BTreeMap<usize, AplicLine>>,
+}
+
+#[derive(Debug)]
+enum Delivery {
+    /// Maps CPUs to their IDC index.
+    Direct {
+        idc_map: BTreeMap<Cpuid, usize>,
+    },
+    Msi {
+        imsic: Arc<Imsic>,
+    },
+}
+
+impl Delivery {
+    fn hart_index(&self, cpuid: Cpuid) -> Option<usize> {
+        match self {
+            Self::Direct { idc_map } => idc_map.get(&cpuid).copied(),
+            Self::Msi { imsic } => imsic.hart_index(cpuid),
+        }
+    }
+
+    /// Returns the first online CPU that interrupts can be delivered to.
+    fn first_cpu(&self) -> Option<Cpuid> {
+        match self {
+            Self::Direct { idc_map } => idc_map
+                .keys()
+                .copied()
+                .find(|cpuid| hotplug::is_online(*cpuid)),
+            Self::Msi { imsic } => imsic.cpus().find(|cpuid| hotplug::is_online(*cpuid)),
+        }
+    }
+}
+
+struct AplicLine {
+    name: String,
+    handler: IrqHandler,
+    mode: SourceMode,
+    target: Cpuid,
+    /// Interrupt identity of the IMSIC in MSI delivery mode.
+    eiid: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SourceMode {
+    Inactive = 0,
+    EdgeRising = 4,
+    EdgeFalling = 5,
+    LevelHigh = 6,
+    LevelLow = 7,
+}
+
+impl SourceMode {
+    fn is_level(self) -> bool {
+        matches!(self, Self::LevelHigh | Self::LevelLow)
+    }
+}
+
+#[derive(Debug)]
+struct AplicMmio {
+    regs: MmioToken,
+    num_sources: usize,
+}
+
+impl Aplic {
+    fn dispatch(&self, source: usize) {
+        let handler = self
+            .lines
+            .lock()
+            .get(&source)
+            .map(|line| Arc::clone(&line.handler));
+        if let Some(handler) = handler {
+            handler();
+        } else {
+            warn!("no handler for APLIC source {source}");
+        }
+    }
+
+    fn handle_msi(&self, source: usize) {
+        self.dispatch(source);
+        let is_level = self
+            .lines
+            .lock()
+            .get(&source)
+            .is_some_and(|line| line.mode.is_level());
+        // The pending bit of a level-sensitive source is cleared when its MSI
+        // is sent. Set it again so that a still-asserted source is redelivered.
+        if is_level {
+            self.mmio
+                .lock()
+                .write(SETIPNUM, u32::try_from(source).unwrap());
+        }
+    }
+
+    fn source_of(&self, hwirq: HwIrq) -> usize {
+        let source = hwirq.value();
+        assert!(
+            self.mmio.lock().is_valid_source(source),
+            "invalid interrupt source id"
+        );
+        source
+    }
+
+    fn retarget_from(&self, cpuid: Cpuid) {
+        let mut lines = self.lines.lock();
+        for (source, line) in lines.iter_mut() {
+            if line.target != cpuid {
+                continue;
+            }
+            let Some(target) = self.delivery.first_cpu() else {
+                warn!("no online CPU to deliver APLIC source {source}");
+                continue;
+            };
+            line.target = target;
+            self.write_target(*source, line);
+        }
+        lines.unlock();
+    }
+
+    fn write_target(&self, source: usize, line: &AplicLine) {
+        let hart_index = self.delivery.hart_index(line.target).unwrap();
+        let low = match line.eiid {
+            Some(eiid) => eiid,
+            None => usize::cast_from(DEFAULT_PRIORITY),
+        };
+        let target = (hart_index << TARGET_HART_INDEX_SHIFT) | low;
+        self.mmio
+            .lock()
+            .write_target(source, u32::try_from(target).unwrap());
+    }
+}
+
+impl IrqDomain for Aplic {
+    fn dtree_path(&self) -> &ByteStr {
+        ByteStr::new(&self.path)
+    }
+
+    fn translate(&self, specifier: &U32Array) -> Result<HwIrq, GenericError> {
+        ensure_whatever!(
+            (1..=2).contains(&specifier.len()),
+            "invalid interrupt specifier length"
+        );
+        let source = usize::cast_from(specifier.get(0).unwrap());
+        ensure_whatever!(
+            self.mmio.lock().is_valid_source(source),
+            "invalid interrupt source id {source}"
+        );
+        // IRQ_TYPE_* flags of the devicetree binding
+        let mode = match specifier.get(1).map_or(4, |flags| flags & 0xf) {
+            1 => SourceMode::EdgeRising,
+            2 => SourceMode::EdgeFalling,
+            4 => SourceMode::LevelHigh,
+            8 => SourceMode::LevelLow,
+            flags => {
+                whatever!("unsupported interrupt type {flags:#x}");
+            }
+        };
+        self.source_modes.lock().insert(source, mode);
+        Ok(HwIrq::from_raw(source))
+    }
+
+    fn register_handler(
+        &self,
+        hwirq: HwIrq,
+        name: &str,
+        handler: IrqHandler,
+    ) -> Result<(), GenericError> {
+        let source = self.source_of(hwirq);
+        if let Some(line) = self.lines.lock().get(&source) {
+            whatever!(
+                "handler already registered for APLIC source {source} by {}",
+                line.name
+            );
+        }
+        let mode = self
+            .source_modes
+            .lock()
+            .get(&source)
+            .copied()
+            .unwrap_or(SourceMode::LevelHigh);
+        let target = self
+            .delivery
+            .first_cpu()
+            .whatever_context("no CPU to deliver APLIC interrupts")?;
+        let eiid = match &self.delivery {
+            Delivery::Direct { .. } => None,
+            Delivery::Msi { imsic } => {
+                let this = Weak::clone(&self.this);
+                let eiid = imsic.allocate_vector(Arc::new(move || {
+                    if let Some(aplic) = this.upgrade() {
+                        aplic.handle_msi(source);
+                    }
+                }))?;
+                Some(eiid)
+            }
+        };
+        let line = AplicLine {
+            name: name.to_owned(),
+            handler,
+            mode,
+            target,
+            eiid,
+        };
+        self.mmio.lock().write_sourcecfg(source, mode);
+        self.write_target(source, &line);
+        self.lines.lock().insert(source, line);
+        Ok(())
+    }
+
+    fn unregister_handler(&self, hwirq: HwIrq) {
+        let source = self.source_of(hwirq);
+        let Some(line) = self.lines.lock().remove(&source) else {
+            return;
+        };
+        self.mmio
+            .lock()
+            .write_sourcecfg(source, SourceMode::Inactive);
+        if let (Delivery::Msi { imsic }, Some(eiid)) = (&self.delivery, line.eiid) {
+            imsic.free_vector(eiid);
+        }
+    }
+
+    fn enable(&self, hwirq: HwIrq) {
+        let source = self.source_of(hwirq);
+        self.mmio
+            .lock()
+            .write(SETIENUM, u32::try_from(source).unwrap());
+    }
+
+    fn disable(&self, hwirq: HwIrq) {
+        let source = self.source_of(hwirq);
+        self.mmio
+            .lock()
+            .write(CLRIENUM, u32::try_from(source).unwrap());
+    }
+
+    fn set_affinity(&self, hwirq: HwIrq, cpus: &[Cpuid]) -> Result<(), GenericError> {
+        let source = self.source_of(hwirq);
+        // An APLIC interrupt is delivered to a single hart.
+        let target = cpus
+            .iter()
+            .copied()
+            .find(|cpuid| self.delivery.hart_index(*cpuid).is_some())
+            .with_whatever_context(|| format!("no APLIC target for CPUs {cpus:?}"))?;
+        let mut lines = self.lines.lock();
+        let line = lines
+            .get_mut(&source)
+            .with_whatever_context(|| format!("no handler registered for APLIC source {source}"))?;
+        line.target = target;
+        self.write_target(source, line);
+        Ok(())
+    }
+}
+
+impl AplicMmio {
+    fn is_valid_source(&self, source: usize) -> bool {
+        (1..=self.num_sources).contains(&source)
+    }
+
+    fn read(&self, offset: usize) -> u32 {
+        unsafe { self.regs.read(offset) }
+    }
+
+    fn write(&self, offset: usize, value: u32) {
+        unsafe {
+            self.regs.write(offset, value);
+        }
+    }
+
+    fn init(&self, delivery: &Delivery) {
+        match delivery {
+            Delivery::Direct { idc_map } => {
+                self.write(DOMAINCFG, DOMAINCFG_IE);
+                for idc in idc_map.values() {
+                    self.write(idc_offset(*idc, IDC_ITHRESHOLD), DEFAULT_THRESHOLD);
+                    self.write(idc_offset(*idc, IDC_IDELIVERY), 1);
+                }
+            }
+            // MSI target addresses are configured by the root domain (M-mode firmware).
+            Delivery::Msi { .. } => self.write(DOMAINCFG, DOMAINCFG_IE | DOMAINCFG_DM),
+        }
+    }
+
+    fn write_sourcecfg(&self, source: usize, mode: SourceMode) {
+        assert!(self.is_valid_source(source));
+        self.write(SOURCECFG_BASE + (source - 1) * 4, mode as u32);
+    }
+
+    fn write_target(&self, source: usize, target: u32) {
+        assert!(self.is_valid_source(source));
+        self.write(TARGET_BASE + (source - 1) * 4, target);
+    }
+
+    fn claim(&self, idc: usize) -> Option<usize> {
+        let claimi = self.read(idc_offset(idc, IDC_CLAIMI));
+        let source = usize::cast_from((claimi >> 16) & 0x3ff);
+        (source != 0).then_some(source)
+    }
+}
+
+fn idc_offset(idc: usize, offset: usize) -> usize {
+    IDC_BASE + idc * IDC_SIZE + offset
+}
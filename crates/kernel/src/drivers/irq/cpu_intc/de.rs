@@ -0,0 +1,53 @@
+use alloc::sync::Arc;
+
+use devtree::{
+    DeserializeNode,
+    model::{node::NodePath, property::Reg},
+    tree_cursor::TreeCursor as _,
+};
+use snafu::{OptionExt as _, ResultExt as _};
+
+use super::CpuIntc;
+use crate::{
+    cpu::Cpuid, drivers::registry::ProbeContext, error::GenericError, iter::IteratorExt as _,
+};
+
+#[derive(Debug, DeserializeNode)]
+struct CpuIntcNode {
+    #[devtree(node)]
+    path: NodePath,
+}
+
+pub fn deserialize(ctx: &ProbeContext<'_>) -> Result<Arc<CpuIntc>, GenericError> {
+    let CpuIntcNode { path } = ctx.deserialize_node()?;
+    let cpuid = deserialize_cpuid(ctx)?;
+    Ok(Arc::new(CpuIntc::new(path.0, cpuid)))
+}
+
+#[derive(DeserializeNode)]
+struct CpuNode<'blob> {
+    #[devtree(property)]
+    reg: Reg<'blob>,
+}
+
+fn deserialize_cpuid(ctx: &ProbeContext<'_>) -> Result<Cpuid, GenericError> {
+    let mut cursor = ctx
+        .devicetree()
+        .tree_cursor()
+        .whatever_context("failed to create tree cursor")?;
+    cursor
+        .read_node_by_path(ctx.path())
+        .whatever_context("failed to read devicetree")?
+        .whatever_context("cpu interrupt controller node not found")?;
+    let parent = cursor
+        .read_parent()
+        .whatever_context("cpu interrupt controller node has no parent")?;
+    let CpuNode { reg } = parent
+        .deserialize_node()
+        .whatever_context("failed to deserialize devicetree cpu node")?;
+    let reg = reg
+        .into_iter()
+        .assume_one()
+        .whatever_context("invalid 'reg' entries in cpu node")?;
+    Ok(Cpuid::from_raw(reg.range().start))
+}
@@ -0,0 +1,222 @@
+use alloc::{collections::btree_map::BTreeMap, sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use devtree::{
+    compatible_table,
+    model::property::U32Array,
+    types::{ByteStr, ByteString},
+};
+use platform_cast::CastFrom as _;
+use riscv::{interrupt::Interrupt, register::sie};
+use snafu::ensure_whatever;
+
+use crate::{
+    cpu::{self, Cpuid},
+    drivers::registry::{ProbeContext, ProbeError},
+    error::GenericError,
+    interrupt,
+    irq::{self, HwIrq, IrqDomain, IrqHandler},
+    stats::{self, Counter},
+    sync::rcu::Rcu,
+};
+
+mod de;
+
+pub const SUPERVISOR_SOFT: HwIrq = HwIrq::from_raw(Interrupt::SupervisorSoft as usize);
+pub const SUPERVISOR_TIMER: HwIrq = HwIrq::from_raw(Interrupt::SupervisorTimer as usize);
+pub const SUPERVISOR_EXTERNAL: HwIrq = HwIrq::from_raw(Interrupt::SupervisorExternal as usize);
+
+static CPU_INTC_DEVICES: Rcu<Vec<Arc<CpuIntc>>> = Rcu::new(Vec::new());
+
+driver!(DRIVER {
+    name: "cpu-intc",
+    compatibles: compatible_table!["riscv,cpu-intc"],
+    probe,
+});
+
+fn probe(ctx: &ProbeContext<'_>) -> Result<(), ProbeError> {
+    let intc = de::deserialize(ctx)?;
+    irq::register_domain(Arc::clone(&intc) as Arc<dyn IrqDomain>);
+    CPU_INTC_DEVICES.update(|devices| devices.push(intc));
+    Ok(())
+}
+
+/// Applies the enabled interrupts of the current CPU's interrupt controller to
+/// `sie`.
+pub fn apply() {
+    let cpu = cpu::current();
+    let intc = find_cpu_intc_for_cpu(cpu.id()).unwrap();
+    for hwirq in [SUPERVISOR_SOFT, SUPERVISOR_TIMER, SUPERVISOR_EXTERNAL] {
+        write_sie(hwirq, intc.is_enabled(hwirq));
+    }
+}
+
+pub fn find_cpu_intc_for_cpu(cpuid: Cpuid) -> Option<Arc<CpuIntc>> {
+    CPU_INTC_DEVICES
+        .read()
+        .iter()
+        .find(|intc| intc.cpuid == cpuid)
+        .cloned()
+}
+
+pub fn find_cpu_intc_by_dtree_path<P>(path: P) -> Option<Arc<CpuIntc>>
+where
+    P: AsRef<ByteStr>,
+{
+    let path = path.as_ref();
+    CPU_INTC_DEVICES
+        .read()
+        .iter()
+        .find(|intc| intc.path == path)
+        .cloned()
+}
+
+/// Dispatches an interrupt taken on the current CPU.
+///
+/// Returns `false` if no handler is registered for the interrupt.
+pub fn handle_interrupt(interrupt: Interrupt) -> bool {
+    assert!(!interrupt::is_enabled());
+    let cpu = cpu::current();
+    let Some(intc) = find_cpu_intc_for_cpu(cpu.id()) else {
+        return false;
+    };
+    let hwirq = HwIrq::from_raw(interrupt as usize);
+    match interrupt {
+        Interrupt::SupervisorSoft => stats::incr(Counter::SoftwareInterrupts),
+        Interrupt::SupervisorTimer => stats::incr(Counter::TimerInterrupts),
+        Interrupt::SupervisorExternal => stats::incr(Counter::ExternalInterrupts),
+    }
+    let handler = intc.handlers.read().get(&hwirq).map(Arc::clone);
+    let Some(handler) = handler else {
+        return false;
+    };
+    trace_event!(IrqEnter, hwirq.value(), 0_u64);
+    handler();
+    trace_event!(IrqExit, hwirq.value(), 0_u64);
+    true
+}
+
+/// RISC-V hart-local interrupt controller (`riscv,cpu-intc`).
+///
+/// Handles the supervisor software, timer and external interrupts of a single
+/// CPU. The `sie` CSR can only be written by its own CPU, so enabling a line
+/// of a remote CPU takes effect when the CPU calls [`apply`].
+#[derive(derive_more::Debug)]
+pub struct CpuIntc {
+    path: ByteString,
+    cpuid: Cpuid,
+    enabled: AtomicUsize,
+    #[debug(skip)]
+    handlers: Rcu<BTreeMap<HwIrq, IrqHandler>>,
+}
+
+impl CpuIntc {
+    fn new(path: ByteString, cpuid: Cpuid) -> Self {
+        Self {
+            path,
+            cpuid,
+            enabled: AtomicUsize::new(0),
+            handlers: Rcu::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn cpuid(&self) -> Cpuid {
+        self.cpuid
+    }
+
+    fn is_valid_hwirq(hwirq: HwIrq) -> bool {
+        [SUPERVISOR_SOFT, SUPERVISOR_TIMER, SUPERVISOR_EXTERNAL].contains(&hwirq)
+    }
+
+    fn is_enabled(&self, hwirq: HwIrq) -> bool {
+        self.enabled.load(Ordering::Acquire) & (1 << hwirq.value()) != 0
+    }
+
+    fn set_enabled(&self, hwirq: HwIrq, enable: bool) {
+        assert!(Self::is_valid_hwirq(hwirq), "invalid hwirq {hwirq}");
+        let interrupt_guard = interrupt::push_disabled();
+        if enable {
+            self.enabled.fetch_or(1 << hwirq.value(), Ordering::AcqRel);
+        } else {
+            self.enabled
+                .fetch_and(!(1 << hwirq.value()), Ordering::AcqRel);
+        }
+        if cpu::try_current().is_some_and(|cpu| cpu.id() == self.cpuid) {
+            write_sie(hwirq, enable);
+        }
+        interrupt_guard.pop();
+    }
+}
+
+impl IrqDomain for CpuIntc {
+    fn dtree_path(&self) -> &ByteStr {
+        ByteStr::new(&self.path)
+    }
+
+    fn translate(&self, specifier: &U32Array) -> Result<HwIrq, GenericError> {
+        ensure_whatever!(specifier.len() == 1, "invalid interrupt specifier length");
+        let hwirq = HwIrq::from_raw(usize::cast_from(specifier.get(0).unwrap()));
+        ensure_whatever!(
+            Self::is_valid_hwirq(hwirq),
+            "unsupported cpu interrupt {hwirq}"
+        );
+        Ok(hwirq)
+    }
+
+    fn register_handler(
+        &self,
+        hwirq: HwIrq,
+        _name: &str,
+        handler: IrqHandler,
+    ) -> Result<(), GenericError> {
+        ensure_whatever!(
+            Self::is_valid_hwirq(hwirq),
+            "unsupported cpu interrupt {hwirq}"
+        );
+        self.handlers.try_update(|handlers| {
+            ensure_whatever!(
+                !handlers.contains_key(&hwirq),
+                "handler already registered for cpu interrupt {hwirq}"
+            );
+            handlers.insert(hwirq, handler);
+            Ok(())
+        })
+    }
+
+    fn unregister_handler(&self, hwirq: HwIrq) {
+        self.handlers.update(|handlers| {
+            handlers.remove(&hwirq);
+        });
+    }
+
+    fn enable(&self, hwirq: HwIrq) {
+        self.set_enabled(hwirq, true);
+    }
+
+    fn disable(&self, hwirq: HwIrq) {
+        self.set_enabled(hwirq, false);
+    }
+
+    fn set_affinity(&self, hwirq: HwIrq, cpus: &[Cpuid]) -> Result<(), GenericError> {
+        ensure_whatever!(
+            cpus == [self.cpuid],
+            "cpu interrupt {hwirq} can only be delivered to CPU#{}",
+            self.cpuid
+        );
+        Ok(())
+    }
+}
+
+fn write_sie(hwirq: HwIrq, enable: bool) {
+    unsafe {
+        match (hwirq, enable) {
+            (SUPERVISOR_SOFT, true) => sie::set_ssoft(),
+            (SUPERVISOR_SOFT, false) => sie::clear_ssoft(),
+            (SUPERVISOR_TIMER, true) => sie::set_stimer(),
+            (SUPERVISOR_TIMER, false) => sie::clear_stimer(),
+            (SUPERVISOR_EXTERNAL, true) => sie::set_sext(),
+            (SUPERVISOR_EXTERNAL, false) => sie::clear_sext(),
+            _ => panic!("invalid hwirq {hwirq}"),
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use alloc::{collections::btree_map::BTreeMap, sync::Arc};
+
+use devtree::{
+    DeserializeNode,
+    de::util,
+    model::node::{InterruptGeneratingDevice, NodePath},
+};
+use snafu::OptionExt as _;
+
+use super::Imsic;
+use crate::{
+    cpu::Cpuid,
+    drivers::{irq::cpu_intc, registry::ProbeContext},
+    error::GenericError,
+    iter::IteratorExt as _,
+    sync::spinlock::SpinMutex,
+};
+
+#[derive(Debug, DeserializeNode)]
+struct ImsicNode<'blob> {
+    #[devtree(node)]
+    path: NodePath,
+    #[devtree(node)]
+    device: InterruptGeneratingDevice<'blob>,
+    #[devtree(property(
+        name = "riscv,num-ids",
+        deserialize_with = util::deserialize_property_as_usize_via_u32,
+    ))]
+    num_ids: usize,
+}
+
+pub fn deserialize(ctx: &ProbeContext<'_>) -> Result<Option<Arc<Imsic>>, GenericError> {
+    let imsic_node = ctx.deserialize_node::<ImsicNode>()?;
+    let hart_map = deserialize_hart_map(&imsic_node.device)?;
+    // Interrupt files for M-mode are connected to the machine external
+    // interrupt.
+    if hart_map.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(Arc::new(Imsic {
+        path: imsic_node.path.0,
+        num_ids: imsic_node.num_ids,
+        hart_map,
+        vectors: SpinMutex::new(BTreeMap::new()),
+    })))
+}
+
+fn deserialize_hart_map(
+    device: &InterruptGeneratingDevice<'_>,
+) -> Result<BTreeMap<Cpuid, usize>, GenericError> {
+    let mut map = BTreeMap::new();
+    for (hart_index, interrupt) in device.interrupts().iter().enumerate() {
+        let specifier = interrupt
+            .specifier()
+            .into_iter()
+            .assume_one()
+            .whatever_context("invalid interrupt specifier length")?;
+        // 9 means supervisor interrupt
+        if specifier != 9 {
+            continue;
+        }
+
+        let Some(intc) = cpu_intc::find_cpu_intc_by_dtree_path(interrupt.parent_path()) else {
+            continue;
+        };
+        map.insert(intc.cpuid(), hart_index);
+    }
+    Ok(map)
+}
@@ -0,0 +1,178 @@
+use alloc::{collections::btree_map::BTreeMap, format, sync::Arc, vec::Vec};
+use core::arch::asm;
+
+use devtree::{
+    compatible_table,
+    types::{ByteStr, ByteString},
+};
+use snafu::OptionExt as _;
+
+use crate::{
+    cpu::{self, Cpuid},
+    drivers::{
+        irq::cpu_intc,
+        registry::{ProbeContext, ProbeError},
+    },
+    error::GenericError,
+    interrupt,
+    irq::{IrqHandler, IrqLine},
+    sync::spinlock::SpinMutex,
+};
+
+mod de;
+
+// Indirectly accessed interrupt-file registers (via `siselect`/`sireg`).
+const EIDELIVERY: usize = 0x70;
+const EITHRESHOLD: usize = 0x72;
+const EIE0: usize = 0xc0;
+
+static IMSIC_DEVICES: SpinMutex<Vec<Arc<Imsic>>> = SpinMutex::new(Vec::new());
+
+driver!(DRIVER {
+    name: "imsic",
+    compatibles: compatible_table!["riscv,imsics"],
+    probe,
+});
+
+fn probe(ctx: &ProbeContext<'_>) -> Result<(), ProbeError> {
+    ctx.require_interrupt_parents()?;
+    attach(ctx)?;
+    Ok(())
+}
+
+fn attach(ctx: &ProbeContext<'_>) -> Result<(), GenericError> {
+    let Some(imsic) = de::deserialize(ctx)? else {
+        return Ok(());
+    };
+    for cpuid in imsic.hart_map.keys() {
+        let intc = cpu_intc::find_cpu_intc_for_cpu(*cpuid)
+            .with_whatever_context(|| format!("no interrupt controller for CPU#{cpuid}"))?;
+        let handler = Arc::new({
+            let imsic = Arc::clone(&imsic);
+            move || imsic.handle_interrupt()
+        });
+        let line = IrqLine::request(intc, cpu_intc::SUPERVISOR_EXTERNAL, "imsic", handler)?;
+        line.enable();
+        line.leak();
+    }
+    IMSIC_DEVICES.lock().push(imsic);
+    Ok(())
+}
+
+/// Enables the interrupt file of the current CPU.
+///
+/// All identities are enabled on every interrupt file; MSI senders select
+/// the destination CPU and mask interrupts at the source.
+pub fn apply() {
+    let cpu = cpu::current();
+    for imsic in IMSIC_DEVICES.lock().iter() {
+        if imsic.hart_map.contains_key(&cpu.id()) {
+            imsic.init_interrupt_file();
+        }
+    }
+}
+
+pub fn find_imsic_by_dtree_path<P>(path: P) -> Option<Arc<Imsic>>
+where
+    P: AsRef<ByteStr>,
+{
+    let path = path.as_ref();
+    IMSIC_DEVICES
+        .lock()
+        .iter()
+        .find(|imsic| imsic.path == path)
+        .cloned()
+}
+
+/// RISC-V incoming MSI controller (`riscv,imsics`) for supervisor mode.
+///
+/// The IMSIC has no wired interrupt sources of its own. Interrupt identities
+/// are allocated by MSI-capable controllers such as the APLIC in MSI mode.
+#[derive(derive_more::Debug)]
+pub struct Imsic {
+    path: ByteString,
+    num_ids: usize,
+    /// Maps CPUs to their hart index in the IMSIC.
+    hart_map: BTreeMap<Cpuid, usize>,
+    #[debug(skip)]
+    vectors: SpinMutex<BTreeMap<usize, IrqHandler>>,
+}
+
+impl Imsic {
+    pub fn hart_index(&self, cpuid: Cpuid) -> Option<usize> {
+        self.hart_map.get(&cpuid).copied()
+    }
+
+    pub fn cpus(&self) -> impl Iterator<Item = Cpuid> + '_ {
+        self.hart_map.keys().copied()
+    }
+
+    /// Allocates an interrupt identity dispatched to `handler`.
+    pub fn allocate_vector(&self, handler: IrqHandler) -> Result<usize, GenericError> {
+        let mut vectors = self.vectors.lock();
+        let id = (1..=self.num_ids)
+            .find(|id| !vectors.contains_key(id))
+            .whatever_context("no free IMSIC interrupt identity")?;
+        vectors.insert(id, handler);
+        Ok(id)
+    }
+
+    /// Frees an interrupt identity allocated by [`Self::allocate_vector`].
+    pub fn free_vector(&self, id: usize) {
+        self.vectors.lock().remove(&id);
+    }
+
+    fn init_interrupt_file(&self) {
+        assert!(!interrupt::is_enabled());
+        unsafe {
+            write_ireg(EIDELIVERY, 1);
+            write_ireg(EITHRESHOLD, 0);
+        }
+        // On RV64, each even-numbered `eie` register covers 64 identities.
+        for id_base in (0..=self.num_ids).step_by(64) {
+            let count = usize::min(64, self.num_ids + 1 - id_base);
+            let mut bits = if count == 64 { !0 } else { (1 << count) - 1 };
+            if id_base == 0 {
+                // identity 0 is reserved
+                bits &= !1;
+            }
+            unsafe {
+                write_ireg(EIE0 + id_base / 32, bits);
+            }
+        }
+    }
+
+    fn handle_interrupt(&self) {
+        while let Some(id) = claim() {
+            let handler = self.vectors.lock().get(&id).map(Arc::clone);
+            if let Some(handler) = handler {
+                handler();
+            } else {
+                warn!("no handler for IMSIC interrupt identity {id}");
+            }
+        }
+    }
+}
+
+unsafe fn write_ireg(reg: usize, value: usize) {
+    unsafe {
+        asm!(
+            // siselect, sireg
+            "csrw 0x150, {reg}",
+            "csrw 0x151, {value}",
+            reg = in(reg) reg,
+            value = in(reg) value,
+        );
+    }
+}
+
+/// Claims the highest-priority pending interrupt of the current interrupt file.
+fn claim() -> Option<usize> {
+    let topei: usize;
+    unsafe {
+        // stopei
+        asm!("csrrw {}, 0x15c, zero", out(reg) topei);
+    }
+    let id = (topei >> 16) & 0x7ff;
+    (id != 0).then_some(id)
+}
@@ -1 +1,11 @@
+pub mod aplic;
+pub mod cpu_intc;
+pub mod imsic;
+#[cfg(feature = "plic")]
 pub mod plic;
+
+/// Applies per-CPU interrupt controller state to the current CPU.
+pub fn apply() {
+    cpu_intc::apply();
+    imsic::apply();
+}
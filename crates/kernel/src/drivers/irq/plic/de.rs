@@ -1,86 +1,100 @@
-use alloc::{collections::btree_map::BTreeMap, sync::Arc, vec::Vec};
+use alloc::{
+    collections::btree_map::{self, BTreeMap},
+    sync::Arc,
+};
+use core::sync::atomic::AtomicU64;
 
 use devtree::{
-    DeserializeNode, Devicetree,
-    de::util,
+    DeserializeNode,
     model::{
         node::{InterruptGeneratingDevice, NodePath},
         property::Reg,
     },
-    tree_cursor::{TreeCursor as _, TreeIterator as _},
-    types::ByteStr,
 };
-use snafu::{OptionExt as _, ResultExt as _};
+use platform_cast::CastFrom as _;
+use snafu::{OptionExt as _, ResultExt as _, ensure_whatever};
 
 use super::{Plic, PlicContext};
 use crate::{
-    cpu::Cpuid, drivers::irq::plic::PlicMmio, error::GenericError, iter::IteratorExt as _,
-    sync::spinlock::SpinMutex,
+    cpu::Cpuid,
+    drivers::{
+        irq::{cpu_intc, plic::PlicMmio},
+        registry::ProbeContext,
+    },
+    error::GenericError,
+    iter::IteratorExt as _,
+    sync::{rcu::Rcu, spinlock::SpinMutex},
 };
 
+/// Maximum number of interrupt sources defined by the PLIC specification.
+///
+/// Used when the node has no `riscv,ndev` property.
+const MAX_NDEV: usize = 1023;
+
 #[derive(Debug, DeserializeNode)]
 struct PlicNode<'blob> {
     #[devtree(node)]
     path: NodePath,
     #[devtree(node)]
     device: InterruptGeneratingDevice<'blob>,
-    #[devtree(property(
-        name = "riscv,ndev",
-        deserialize_with = util::deserialize_property_as_usize_via_u32,
-    ))]
-    ndev: usize,
+    #[devtree(property(name = "riscv,ndev", default))]
+    ndev: Option<u32>,
     #[devtree(property)]
     reg: Reg<'blob>,
 }
 
-pub fn deserialize(dt: &Devicetree) -> Result<Vec<Arc<Plic>>, GenericError> {
-    let mut plic_devices = Vec::new();
-    let mut cursor = dt
-        .tree_cursor()
-        .whatever_context("failed to create tree cursor")?;
-    let iter = cursor
-        .read_descendant_nodes_by_glob("/soc/plic")
-        .deserialize_node::<PlicNode>();
-    for plic_node in iter {
-        let plic_node =
-            plic_node.whatever_context("failed to deserialize plic node in devicetree")?;
-        let plic_device = Plic::from_node(dt, plic_node)?;
-        plic_devices.push(plic_device);
-    }
-    Ok(plic_devices)
+pub fn deserialize(ctx: &ProbeContext<'_>) -> Result<Arc<Plic>, GenericError> {
+    let plic_node = ctx.deserialize_node::<PlicNode>()?;
+    Plic::from_node(ctx, plic_node)
 }
 
 impl Plic {
-    fn from_node(dt: &Devicetree, plic_node: PlicNode) -> Result<Arc<Self>, GenericError> {
+    fn from_node(ctx: &ProbeContext<'_>, plic_node: PlicNode) -> Result<Arc<Self>, GenericError> {
         let PlicNode {
             path,
             device,
             ndev,
             reg,
         } = plic_node;
+        let ndev = match ndev {
+            Some(ndev) => {
+                let ndev = usize::cast_from(ndev);
+                ensure_whatever!(
+                    ndev <= MAX_NDEV,
+                    "invalid 'riscv,ndev' in plic node: {ndev}"
+                );
+                ndev
+            }
+            None => MAX_NDEV,
+        };
         let reg = reg
             .into_iter()
             .assume_one()
             .whatever_context("invalid 'reg' entries in plic node")?;
-        let range = reg.range();
-        let context_map = deserialize_context_map(dt, &device)
+        let regs = unsafe { ctx.map_mmio(reg.range()) }
+            .whatever_context("failed to map plic registers")?;
+        let context_map = deserialize_context_map(&device)
             .whatever_context("failed to deserialize devicetree plic node")?;
         let plic = Arc::new(Self {
             path: path.0,
-            mmio: SpinMutex::new(PlicMmio {
-                base_addr: range.start,
-                size: range.len(),
-                ndev,
-            }),
+            mmio: SpinMutex::new(PlicMmio { regs, ndev }),
             context_map,
-            callbacks: SpinMutex::new(BTreeMap::new()),
+            lines: Rcu::new(BTreeMap::new()),
+            stats: SpinMutex::new(BTreeMap::new()),
+            spurious_claims: AtomicU64::new(0),
         });
         Ok(plic)
     }
 }
 
+/// Maps the CPUs to the PLIC contexts taking their supervisor external
+/// interrupts.
+///
+/// The index of an entry in `interrupts-extended` is the context ID. The
+/// entries may reference the interrupt controllers of any harts, such as only
+/// the harts of a socket in a multi-PLIC system, and the entries of the other
+/// privilege modes or of the unconnected contexts (`0xffffffff`) are skipped.
 fn deserialize_context_map(
-    dt: &Devicetree,
     device: &InterruptGeneratingDevice<'_>,
 ) -> Result<BTreeMap<Cpuid, PlicContext>, GenericError> {
     let mut map = BTreeMap::new();
@@ -95,45 +109,20 @@ fn deserialize_context_map(
             continue;
         }
 
-        let Some(cpuid) = deserialize_cpuid(dt, interrupt.parent_path())
-            .whatever_context("failed to deserialize devicetree cpu node")?
-        else {
+        let Some(intc) = cpu_intc::find_cpu_intc_by_dtree_path(interrupt.parent_path()) else {
             continue;
         };
-        map.insert(cpuid, PlicContext { id });
+        match map.entry(intc.cpuid()) {
+            btree_map::Entry::Vacant(entry) => {
+                entry.insert(PlicContext { id });
+            }
+            btree_map::Entry::Occupied(entry) => {
+                warn!(
+                    "plic: CPU#{} has multiple supervisor contexts, ignoring context {id}",
+                    entry.key()
+                );
+            }
+        }
     }
     Ok(map)
 }
-
-#[derive(DeserializeNode)]
-struct CpuNode<'blob> {
-    #[devtree(property)]
-    reg: Reg<'blob>,
-}
-
-fn deserialize_cpuid(dt: &Devicetree, intc_path: &ByteStr) -> Result<Option<Cpuid>, GenericError> {
-    let mut cursor = dt
-        .tree_cursor()
-        .whatever_context("failed to create tree cursor")?;
-    let Some(_intc_node) = cursor
-        .read_node_by_path(intc_path)
-        .whatever_context("failed to read devicetree")?
-    else {
-        return Ok(None);
-    };
-    let Some(parent) = cursor.read_parent() else {
-        return Ok(None);
-    };
-    if parent.node().name() != "cpu" {
-        return Ok(None);
-    }
-    let CpuNode { reg } = parent
-        .deserialize_node()
-        .whatever_context("failed to deserialize devicetree cpu node")?;
-    let reg = reg
-        .into_iter()
-        .assume_one()
-        .whatever_context("invalid 'reg' entries in cpu node")?;
-    let cpuid = Cpuid::from_raw(reg.range().start);
-    Ok(Some(cpuid))
-}
@@ -1,66 +1,129 @@
-use alloc::{collections::btree_map::BTreeMap, sync::Arc, vec::Vec};
-use core::{ops::Range, ptr};
+use alloc::{
+    borrow::ToOwned as _,
+    collections::{btree_map::BTreeMap, btree_set::BTreeSet},
+    format,
+    string::String,
+    sync::Arc,
+    vec::Vec,
+};
+use core::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
 
 use devtree::{
-    Devicetree,
+    compatible_table,
     model::property::U32Array,
     types::{ByteStr, ByteString},
 };
 use platform_cast::CastFrom as _;
-use snafu::ResultExt as _;
-use spin::Once;
-use sv39::MapPageFlags;
+use snafu::{OptionExt as _, ensure_whatever, whatever};
 
 use crate::{
-    cpu::Cpuid, error::GenericError, interrupt, memory::kernel_space, sync::spinlock::SpinMutex,
+    cpu::{
+        Cpuid,
+        hotplug::{self, HotplugHook},
+    },
+    drivers::{
+        irq::cpu_intc,
+        registry::{ProbeContext, ProbeError},
+    },
+    error::GenericError,
+    interrupt::timer::Instant,
+    irq::{self, HwIrq, IrqDomain, IrqHandler, IrqLine},
+    memory::kernel_space::MmioToken,
+    sync::{
+        rcu::Rcu,
+        spinlock::{IrqSpinMutex, SpinMutex},
+    },
 };
 
 mod de;
 
-static PLIC_DEVICES: Once<Vec<Arc<Plic>>> = Once::new();
+const DEFAULT_PRIORITY: u32 = 1;
+const DEFAULT_THRESHOLD: u32 = 0;
+
+static PLIC_DEVICES: Rcu<Vec<Arc<Plic>>> = Rcu::new(Vec::new());
+/// CPUs whose external interrupt line is requested.
+///
+/// A CPU has a single external interrupt line shared by all PLICs routed to
+/// it.
+static EXTERNAL_LINES: SpinMutex<BTreeSet<Cpuid>> = SpinMutex::new(BTreeSet::new());
+
+driver!(DRIVER {
+    name: "plic",
+    compatibles: compatible_table!["riscv,plic0", "sifive,plic-1.0.0"],
+    probe,
+});
+
+fn probe(ctx: &ProbeContext<'_>) -> Result<(), ProbeError> {
+    ctx.require_interrupt_parents()?;
+    attach(ctx)?;
+    Ok(())
+}
 
-pub fn init(dt: &Devicetree) -> Result<(), GenericError> {
-    let plic_devices = de::deserialize(dt).whatever_context("failed to deserialize devicetree")?;
-    for plic in &plic_devices {
-        let mmio = plic.mmio.lock();
-        kernel_space::identity_map_range(mmio.range(), MapPageFlags::RW)
-            .whatever_context("failed to identity map pages")?;
+fn attach(ctx: &ProbeContext<'_>) -> Result<(), GenericError> {
+    let plic = de::deserialize(ctx)?;
+    let mut mmio = plic.mmio.lock();
+    for context in plic.context_map.values() {
+        mmio.set_priority_threshold(*context, DEFAULT_THRESHOLD);
+    }
+    mmio.unlock();
+    irq::register_domain(Arc::clone(&plic) as Arc<dyn IrqDomain>);
+    let cpuids = plic.context_map.keys().copied().collect::<Vec<_>>();
+    PLIC_DEVICES.update(|devices| devices.push(plic));
+
+    for cpuid in cpuids {
+        if !EXTERNAL_LINES.lock().insert(cpuid) {
+            continue;
+        }
+        let intc = cpu_intc::find_cpu_intc_for_cpu(cpuid)
+            .with_whatever_context(|| format!("no interrupt controller for CPU#{cpuid}"))?;
+        let handler = Arc::new(move || handle_external_interrupt(cpuid));
+        let line = IrqLine::request(intc, cpu_intc::SUPERVISOR_EXTERNAL, "plic", handler)?;
+        line.enable();
+        line.leak();
     }
-    PLIC_DEVICES.call_once(|| plic_devices);
     Ok(())
 }
 
-pub fn find_plic_context_for_cpu(cpuid: Cpuid) -> Option<(Arc<Plic>, PlicContext)> {
-    let plic_devices = PLIC_DEVICES.get()?;
-    for plic in plic_devices {
-        if let Some(context) = plic.find_context_for_cpu(cpuid) {
-            return Some((Arc::clone(plic), context));
-        }
+/// Reroutes the interrupts around the CPU going offline or online.
+pub static HOTPLUG_HOOK: HotplugHook = HotplugHook {
+    name: "plic",
+    online: refresh_all,
+    offline: refresh_all,
+};
+
+fn refresh_all() {
+    for plic in PLIC_DEVICES.read().iter() {
+        plic.refresh_enables();
     }
-    None
 }
 
-pub fn find_plic_by_dtree_path<P>(path: P) -> Option<Arc<Plic>>
-where
-    P: AsRef<ByteStr>,
-{
-    let path = path.as_ref();
-    PLIC_DEVICES
-        .get()?
-        .iter()
-        .find(|plic| plic.path == path)
-        .cloned()
+/// Returns all PLIC devices, in the probe order.
+#[cfg_attr(not(feature = "shell"), expect(dead_code))]
+pub fn get_all() -> Vec<Arc<Plic>> {
+    PLIC_DEVICES.read().clone()
 }
 
-pub type PlicCallback = Arc<dyn Fn(PlicContext) + Send + Sync>;
+fn handle_external_interrupt(cpuid: Cpuid) {
+    for plic in PLIC_DEVICES.read().iter() {
+        if let Some(context) = plic.find_context_for_cpu(cpuid) {
+            let _handled = plic.handle_interrupt(context);
+        }
+    }
+}
 
 #[derive(derive_more::Debug)]
 pub struct Plic {
     path: ByteString,
-    mmio: SpinMutex<PlicMmio>,
+    mmio: IrqSpinMutex<PlicMmio>,
     context_map: BTreeMap<Cpuid, PlicContext>,
     #[debug(skip)]
-    callbacks: SpinMutex<BTreeMap<PlicSource, PlicCallback>>,
+    lines: Rcu<BTreeMap<PlicSource, PlicLine>>,
+    #[debug(skip)]
+    stats: SpinMutex<BTreeMap<PlicSource, PlicSourceStats>>,
+    spurious_claims: AtomicU64,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -73,36 +136,126 @@ pub struct PlicSource {
     id: usize,
 }
 
+impl PlicSource {
+    #[cfg_attr(not(feature = "shell"), expect(dead_code))]
+    pub fn value(self) -> usize {
+        self.id
+    }
+}
+
+#[derive(Clone)]
+struct PlicLine {
+    name: String,
+    handler: IrqHandler,
+    enabled: bool,
+    masked: bool,
+    /// CPUs the interrupt is delivered to, or `None` for all CPUs.
+    affinity: Option<Vec<Cpuid>>,
+}
+
+/// Per-source interrupt statistics.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PlicSourceStats {
+    /// Number of claimed interrupts dispatched to the handler.
+    pub fires: u64,
+    /// Number of claimed interrupts without a registered handler.
+    pub spurious: u64,
+    pub total_handler_time: Duration,
+    pub max_handler_time: Duration,
+}
+
+/// Snapshot of a PLIC interrupt source state.
+#[derive(Debug, Clone)]
+#[cfg_attr(not(feature = "shell"), expect(dead_code))]
+pub struct PlicSourceInfo {
+    pub source: PlicSource,
+    pub name: Option<String>,
+    pub enabled: bool,
+    pub masked: bool,
+    pub affinity: Option<Vec<Cpuid>>,
+    pub stats: PlicSourceStats,
+}
+
 #[derive(Debug)]
 struct PlicMmio {
-    base_addr: usize,
-    size: usize,
+    regs: MmioToken,
     ndev: usize,
 }
 
 impl Plic {
+    pub fn path(&self) -> &ByteStr {
+        ByteStr::new(&self.path)
+    }
+
     pub fn find_context_for_cpu(&self, cpuid: Cpuid) -> Option<PlicContext> {
         self.context_map.get(&cpuid).copied()
     }
 
-    pub fn handle_interrupt(&self, context: PlicContext) -> bool {
+    /// Returns the interrupt source with the given ID, if valid.
+    pub fn source(&self, id: usize) -> Option<PlicSource> {
+        let source = PlicSource { id };
+        self.mmio.lock().is_valid_source(source).then_some(source)
+    }
+
+    /// Returns the number of claims that did not return any interrupt source.
+    #[cfg_attr(not(feature = "shell"), expect(dead_code))]
+    pub fn spurious_claims(&self) -> u64 {
+        self.spurious_claims.load(Ordering::Relaxed)
+    }
+
+    /// Returns the state of interrupt sources which have a handler or have
+    /// fired.
+    #[cfg_attr(not(feature = "shell"), expect(dead_code))]
+    pub fn source_infos(&self) -> Vec<PlicSourceInfo> {
+        let lines = self.lines.read();
+        let stats = self.stats.lock();
+        let sources = lines.keys().chain(stats.keys()).collect::<BTreeSet<_>>();
+        sources
+            .into_iter()
+            .map(|source| {
+                let line = lines.get(source);
+                PlicSourceInfo {
+                    source: *source,
+                    name: line.map(|line| line.name.clone()),
+                    enabled: line.is_some_and(|line| line.enabled),
+                    masked: line.is_some_and(|line| line.masked),
+                    affinity: line.and_then(|line| line.affinity.clone()),
+                    stats: stats.get(source).copied().unwrap_or_default(),
+                }
+            })
+            .collect()
+    }
+
+    fn handle_interrupt(&self, context: PlicContext) -> bool {
         let Some(source) = self.mmio.lock().claim(context) else {
+            self.spurious_claims.fetch_add(1, Ordering::Relaxed);
             return false;
         };
-        let callback = self.callbacks.lock().get(&source).map(Arc::clone);
-        if let Some(callback) = callback {
-            callback(context);
+        let handler = self
+            .lines
+            .read()
+            .get(&source)
+            .map(|line| Arc::clone(&line.handler));
+        if let Some(handler) = handler {
+            let start = Instant::now();
+            handler();
+            let elapsed = start.elapsed();
+
+            let mut stats = self.stats.lock();
+            let stats = stats.entry(source).or_default();
+            stats.fires += 1;
+            stats.total_handler_time += elapsed;
+            stats.max_handler_time = stats.max_handler_time.max(elapsed);
         } else {
             warn!("no handler for PLIC source {source:?}");
+            self.stats.lock().entry(source).or_default().spurious += 1;
         }
         self.mmio.lock().complete(source, context);
         true
     }
 
-    pub fn translate_interrupt_specifier(&self, specifier: &U32Array) -> PlicSource {
-        assert_eq!(specifier.len(), 1, "invalid interrupt specifier");
-        let id = usize::cast_from(specifier.get(0).unwrap());
-        let source = PlicSource { id };
+    fn source_of(&self, hwirq: HwIrq) -> PlicSource {
+        let source = PlicSource { id: hwirq.value() };
         assert!(
             self.mmio.lock().is_valid_source(source),
             "invalid interrupt source id"
@@ -110,25 +263,175 @@ impl Plic {
         source
     }
 
-    pub fn register_callback(&self, source: PlicSource, callback: PlicCallback) {
-        let mut callbacks = self.callbacks.lock();
-        assert!(
-            !callbacks.contains_key(&source),
-            "callback already registered for source {source:?}"
-        );
-        callbacks.insert(source, callback);
+    /// Enables the interrupt on the contexts of the online CPUs in its
+    /// affinity, or of all the online CPUs if none in its affinity is online.
+    fn update_enables(&self, source: PlicSource, line: &PlicLine) {
+        let affinity = line
+            .affinity
+            .as_ref()
+            .filter(|affinity| affinity.iter().any(|cpuid| hotplug::is_online(*cpuid)));
+        let mut mmio = self.mmio.lock();
+        for (cpuid, context) in &self.context_map {
+            let deliver = line.enabled
+                && !line.masked
+                && hotplug::is_online(*cpuid)
+                && affinity.is_none_or(|affinity| affinity.contains(cpuid));
+            if deliver {
+                mmio.enable_interrupt(source, *context);
+            } else {
+                mmio.disable_interrupt(source, *context);
+            }
+        }
+    }
+
+    fn refresh_enables(&self) {
+        self.lines.update(|lines| {
+            for (source, line) in lines.iter() {
+                self.update_enables(*source, line);
+            }
+        });
+    }
+
+    fn update_line<F>(&self, source: PlicSource, f: F) -> Result<(), GenericError>
+    where
+        F: FnOnce(&mut PlicLine),
+    {
+        self.lines.try_update(|lines| {
+            let line = lines.get_mut(&source).with_whatever_context(|| {
+                format!("no handler registered for PLIC source {source:?}")
+            })?;
+            f(line);
+            self.update_enables(source, line);
+            Ok(())
+        })
+    }
+
+    /// Temporarily stops delivering the interrupt without changing its enabled
+    /// state.
+    #[cfg_attr(not(feature = "shell"), expect(dead_code))]
+    pub fn mask(&self, source: PlicSource) -> Result<(), GenericError> {
+        self.update_line(source, |line| line.masked = true)
+    }
+
+    #[cfg_attr(not(feature = "shell"), expect(dead_code))]
+    pub fn unmask(&self, source: PlicSource) -> Result<(), GenericError> {
+        self.update_line(source, |line| line.masked = false)
     }
 
-    pub fn set_priority(&self, source: PlicSource, priority: u32) {
-        self.mmio.lock().set_priority(source, priority);
+    /// Routes the interrupt only to the given CPU.
+    #[cfg_attr(not(feature = "shell"), expect(dead_code))]
+    pub fn set_affinity(&self, source: PlicSource, cpuid: Cpuid) -> Result<(), GenericError> {
+        self.set_affinity_cpus(source, &[cpuid])
+    }
+
+    fn set_affinity_cpus(&self, source: PlicSource, cpus: &[Cpuid]) -> Result<(), GenericError> {
+        for cpuid in cpus {
+            ensure_whatever!(
+                self.context_map.contains_key(cpuid),
+                "no PLIC context for CPU#{cpuid}"
+            );
+        }
+        self.update_line(source, |line| line.affinity = Some(cpus.to_vec()))
     }
 
-    pub fn set_priority_threshold(&self, context: PlicContext, threshold: u32) {
+    #[cfg_attr(not(feature = "shell"), expect(dead_code))]
+    pub fn threshold(&self, cpuid: Cpuid) -> Option<u32> {
+        let context = self.find_context_for_cpu(cpuid)?;
+        Some(self.mmio.lock().priority_threshold(context))
+    }
+
+    /// Sets the priority threshold of the given CPU's context.
+    ///
+    /// Interrupts with a priority less than or equal to the threshold are not
+    /// delivered.
+    #[cfg_attr(not(feature = "shell"), expect(dead_code))]
+    pub fn set_threshold(&self, cpuid: Cpuid, threshold: u32) -> Result<(), GenericError> {
+        let context = self
+            .find_context_for_cpu(cpuid)
+            .with_whatever_context(|| format!("no PLIC context for CPU#{cpuid}"))?;
         self.mmio.lock().set_priority_threshold(context, threshold);
+        Ok(())
     }
 
-    pub fn enable_interrupt(&self, source: PlicSource, context: PlicContext) {
-        self.mmio.lock().enable_interrupt(source, context);
+    /// Restores the default priority threshold of all contexts.
+    #[cfg_attr(not(feature = "shell"), expect(dead_code))]
+    pub fn reset_thresholds(&self) {
+        let mut mmio = self.mmio.lock();
+        for context in self.context_map.values() {
+            mmio.set_priority_threshold(*context, DEFAULT_THRESHOLD);
+        }
+    }
+}
+
+impl IrqDomain for Plic {
+    fn dtree_path(&self) -> &ByteStr {
+        self.path()
+    }
+
+    fn translate(&self, specifier: &U32Array) -> Result<HwIrq, GenericError> {
+        ensure_whatever!(specifier.len() == 1, "invalid interrupt specifier length");
+        let id = usize::cast_from(specifier.get(0).unwrap());
+        ensure_whatever!(
+            self.source(id).is_some(),
+            "invalid interrupt source id {id}"
+        );
+        Ok(HwIrq::from_raw(id))
+    }
+
+    fn register_handler(
+        &self,
+        hwirq: HwIrq,
+        name: &str,
+        handler: IrqHandler,
+    ) -> Result<(), GenericError> {
+        let source = self.source_of(hwirq);
+        self.lines.try_update(|lines| {
+            if let Some(line) = lines.get(&source) {
+                whatever!(
+                    "handler already registered for PLIC source {source:?} by {}",
+                    line.name
+                );
+            }
+            lines.insert(
+                source,
+                PlicLine {
+                    name: name.to_owned(),
+                    handler,
+                    enabled: false,
+                    masked: false,
+                    affinity: None,
+                },
+            );
+            Ok(())
+        })?;
+        self.mmio.lock().set_priority(source, DEFAULT_PRIORITY);
+        Ok(())
+    }
+
+    fn unregister_handler(&self, hwirq: HwIrq) {
+        let source = self.source_of(hwirq);
+        self.lines.update(|lines| {
+            lines.remove(&source);
+        });
+        // priority 0 never interrupts
+        self.mmio.lock().set_priority(source, 0);
+    }
+
+    fn enable(&self, hwirq: HwIrq) {
+        let source = self.source_of(hwirq);
+        self.update_line(source, |line| line.enabled = true)
+            .unwrap();
+    }
+
+    fn disable(&self, hwirq: HwIrq) {
+        let source = self.source_of(hwirq);
+        self.update_line(source, |line| line.enabled = false)
+            .unwrap();
+    }
+
+    fn set_affinity(&self, hwirq: HwIrq, cpus: &[Cpuid]) -> Result<(), GenericError> {
+        let source = self.source_of(hwirq);
+        self.set_affinity_cpus(source, cpus)
     }
 }
 
@@ -137,102 +440,94 @@ impl PlicMmio {
         (1..=self.ndev).contains(&source.id)
     }
 
-    fn range(&self) -> Range<usize> {
-        self.base_addr..self.base_addr + self.size
-    }
-
-    fn priority_addr(&self, source: PlicSource) -> *mut u32 {
+    fn priority_offset(&self, source: PlicSource) -> usize {
         assert!(self.is_valid_source(source));
-        ptr::with_exposed_provenance_mut(self.base_addr + source.id * 4)
+        source.id * 4
     }
 
-    fn pending_addr_bit(&self, source: PlicSource) -> (*mut u32, usize) {
+    fn pending_offset_bit(&self, source: PlicSource) -> (usize, usize) {
         assert!(self.is_valid_source(source));
-        let base = self.base_addr + 0x00_1000;
+        let base = 0x00_1000;
         let bit = source.id % 32;
         let word = source.id / 32;
-        (ptr::with_exposed_provenance_mut(base + word * 4), bit)
+        (base + word * 4, bit)
     }
 
-    fn enable_addr_bit(&self, source: PlicSource, context: PlicContext) -> (*mut u32, usize) {
+    fn enable_offset_bit(&self, source: PlicSource, context: PlicContext) -> (usize, usize) {
         assert!(self.is_valid_source(source));
-        let base = self.base_addr + 0x00_2000 + 0x80 * context.id;
+        let base = 0x00_2000 + 0x80 * context.id;
         let bit = source.id % 32;
         let word = source.id / 32;
-        (ptr::with_exposed_provenance_mut(base + word * 4), bit)
+        (base + word * 4, bit)
     }
 
-    fn priority_threshold_addr(&self, context: PlicContext) -> *mut u32 {
-        ptr::with_exposed_provenance_mut(self.base_addr + 0x20_0000 + 0x1000 * context.id)
+    fn priority_threshold_offset(context: PlicContext) -> usize {
+        0x20_0000 + 0x1000 * context.id
     }
 
-    fn claim_addr(&self, context: PlicContext) -> *mut u32 {
-        ptr::with_exposed_provenance_mut(self.base_addr + 0x20_0000 + 0x1000 * context.id + 0x4)
+    fn claim_offset(context: PlicContext) -> usize {
+        0x20_0000 + 0x1000 * context.id + 0x4
     }
 
+    fn read(&self, offset: usize) -> u32 {
+        unsafe { self.regs.read(offset) }
+    }
+
+    fn write(&self, offset: usize, value: u32) {
+        unsafe { self.regs.write(offset, value) }
+    }
+
+    #[expect(clippy::needless_pass_by_ref_mut)]
     fn set_priority(&mut self, source: PlicSource, priority: u32) {
-        assert!(!interrupt::is_enabled());
         assert!(self.is_valid_source(source));
-        unsafe {
-            self.priority_addr(source).write_volatile(priority);
-        }
+        self.write(self.priority_offset(source), priority);
     }
 
     #[expect(dead_code)]
     #[expect(clippy::needless_pass_by_ref_mut)]
     fn is_pending(&mut self, source: PlicSource) -> bool {
-        assert!(!interrupt::is_enabled());
         assert!(self.is_valid_source(source));
-        let (addr, bit) = self.pending_addr_bit(source);
-        unsafe { (addr.read_volatile() & (1 << bit)) != 0 }
+        let (offset, bit) = self.pending_offset_bit(source);
+        (self.read(offset) & (1 << bit)) != 0
     }
 
     #[expect(clippy::needless_pass_by_ref_mut)]
     fn enable_interrupt(&mut self, source: PlicSource, context: PlicContext) {
-        assert!(!interrupt::is_enabled());
         assert!(self.is_valid_source(source));
-        let (addr, bit) = self.enable_addr_bit(source, context);
-        unsafe {
-            let value = addr.read_volatile();
-            addr.write_volatile(value | (1 << bit));
-        }
+        let (offset, bit) = self.enable_offset_bit(source, context);
+        let value = self.read(offset);
+        self.write(offset, value | (1 << bit));
     }
 
-    #[expect(dead_code)]
     #[expect(clippy::needless_pass_by_ref_mut)]
     fn disable_interrupt(&mut self, source: PlicSource, context: PlicContext) {
-        assert!(!interrupt::is_enabled());
         assert!(self.is_valid_source(source));
-        let (addr, bit) = self.enable_addr_bit(source, context);
-        unsafe {
-            let value = addr.read_volatile();
-            addr.write_volatile(value & !(1 << bit));
-        }
+        let (offset, bit) = self.enable_offset_bit(source, context);
+        let value = self.read(offset);
+        self.write(offset, value & !(1 << bit));
+    }
+
+    #[cfg_attr(not(feature = "shell"), expect(dead_code))]
+    fn priority_threshold(&self, context: PlicContext) -> u32 {
+        self.read(Self::priority_threshold_offset(context))
     }
 
     #[expect(clippy::needless_pass_by_ref_mut)]
     fn set_priority_threshold(&mut self, context: PlicContext, threshold: u32) {
-        assert!(!interrupt::is_enabled());
-        let addr = self.priority_threshold_addr(context);
-        unsafe {
-            addr.write_volatile(threshold);
-        }
+        self.write(Self::priority_threshold_offset(context), threshold);
     }
 
+    #[expect(clippy::needless_pass_by_ref_mut)]
     fn claim(&mut self, context: PlicContext) -> Option<PlicSource> {
-        assert!(!interrupt::is_enabled());
         let source = PlicSource {
-            id: usize::cast_from(unsafe { self.claim_addr(context).read_volatile() }),
+            id: usize::cast_from(self.read(Self::claim_offset(context))),
         };
         self.is_valid_source(source).then_some(source)
     }
 
+    #[expect(clippy::needless_pass_by_ref_mut)]
     fn complete(&mut self, source: PlicSource, context: PlicContext) {
-        assert!(!interrupt::is_enabled());
         assert!(self.is_valid_source(source));
-        unsafe {
-            self.claim_addr(context)
-                .write_volatile(source.id.try_into().unwrap());
-        }
+        self.write(Self::claim_offset(context), source.id.try_into().unwrap());
     }
 }
@@ -1,2 +1,11 @@
+#[macro_use]
+pub mod registry;
+
+pub mod device;
+#[cfg(feature = "framebuffer")]
+pub mod framebuffer;
 pub mod irq;
+pub mod rtc;
 pub mod serial;
+pub mod test_finisher;
+pub mod virtio;
@@ -0,0 +1,444 @@
+//! Devicetree driver model.
+//!
+//! Each driver registers a [`DriverDescriptor`] listing the `compatible`
+//! strings it binds to with [`driver!`], which places it in the `.drivers`
+//! linker section, so a driver left out of the build by its cargo feature is
+//! simply not registered. The devicetree is walked once, and the matching nodes
+//! are probed in passes: a probe whose dependencies, such as its interrupt
+//! parents, are not bound yet is deferred and retried in the next pass, until
+//! a pass binds no more nodes. The disabled nodes and their subtrees are not
+//! bound.
+//!
+//! A driver claims the registers and the interrupts of its node through the
+//! [`Device`] created with [`ProbeContext::create_device`], which releases them
+//! when it is dropped.
+
+use alloc::{borrow::ToOwned as _, collections::btree_set::BTreeSet, format, vec::Vec};
+use core::{ops::Range, slice};
+
+use devtree::{
+    DeserializeNode, Devicetree, de,
+    model::{
+        node::{InterruptGeneratingDevice, NodePath},
+        property::{Compatible, CompatibleTable, Phandle, Reg},
+    },
+    tree_cursor::{RawNode, TreeCursor as _, TreeIterator as _},
+    types::{ByteStr, ByteString},
+};
+use snafu::{OptionExt as _, ResultExt as _};
+
+use super::device::Device;
+use crate::{
+    error::GenericError,
+    memory::{
+        kernel_space::{self, MmioToken},
+        reserved::{self, ReservedRegion},
+    },
+};
+
+/// Registers a driver bound to the devicetree nodes.
+///
+/// The registration is defined as a static named `$name`.
+///
+/// ```ignore
+/// driver!(DRIVER {
+///     name: "rtc",
+///     compatibles: compatible_table!["google,goldfish-rtc"],
+///     probe,
+/// });
+/// ```
+macro_rules! driver {
+    ($name:ident { $($field:tt)* }) => {
+        #[used]
+        #[unsafe(link_section = ".drivers")]
+        pub(crate) static $name: $crate::drivers::registry::DriverDescriptor =
+            $crate::drivers::registry::DriverDescriptor { $($field)* };
+    };
+}
+
+unsafe extern "C" {
+    #[link_name = "__onix_drivers_start"]
+    static DRIVERS_START: usize;
+    #[link_name = "__onix_drivers_end"]
+    static DRIVERS_END: usize;
+}
+
+/// Driver probed for the devicetree nodes compatible with it.
+#[derive(Debug)]
+pub struct DriverDescriptor {
+    pub name: &'static str,
+    /// `compatible` strings of the nodes the driver binds to.
+    pub compatibles: CompatibleTable,
+    /// Initializes the device of a node.
+    ///
+    /// A node the driver does not handle, such as an M-mode interrupt
+    /// controller, is skipped by returning `Ok(())`.
+    pub probe: fn(&ProbeContext<'_>) -> Result<(), ProbeError>,
+}
+
+/// Error returned by the probe of a driver.
+#[derive(Debug)]
+pub enum ProbeError {
+    /// The node at the path is not bound yet, and the probe is retried later.
+    DeferredProbe(ByteString),
+    Failed(GenericError),
+}
+
+impl From<GenericError> for ProbeError {
+    fn from(e: GenericError) -> Self {
+        Self::Failed(e)
+    }
+}
+
+/// Devicetree node being probed.
+#[derive(Debug)]
+pub struct ProbeContext<'a> {
+    dt: &'a Devicetree,
+    path: &'a ByteStr,
+    node: &'a RawNode<'a>,
+    driver: &'static DriverDescriptor,
+    /// Paths of the nodes bound so far.
+    bound: &'a BTreeSet<ByteString>,
+}
+
+impl<'a> ProbeContext<'a> {
+    pub fn devicetree(&self) -> &'a Devicetree {
+        self.dt
+    }
+
+    pub fn path(&self) -> &'a ByteStr {
+        self.path
+    }
+
+    /// Deserializes the node being probed.
+    ///
+    /// The cursor is moved to the node kept when it is matched with the
+    /// driver, without searching the tree by the path.
+    pub fn deserialize_node<T>(&self) -> Result<T, GenericError>
+    where
+        T: de::DeserializeNode<'a>,
+    {
+        let mut cursor = self
+            .dt
+            .tree_cursor()
+            .whatever_context("failed to create tree cursor")?;
+        cursor
+            .seek_raw_node(self.node)
+            .with_whatever_context(|_| format!("node {} too deep", self.path))?;
+        cursor
+            .read_node()
+            .deserialize_node()
+            .with_whatever_context(|_| format!("failed to deserialize node {}", self.path))
+    }
+
+    /// Maps the device registers at the physical address range `range`,
+    /// claiming the range for the node and the driver.
+    ///
+    /// # Safety
+    ///
+    /// `range` must be the registers of a device, and must not overlap with the
+    /// memory.
+    pub unsafe fn map_mmio(&self, range: Range<usize>) -> Result<MmioToken, GenericError> {
+        let owner = format!("{} ({} driver)", self.path, self.driver.name);
+        unsafe { kernel_space::map_mmio(range, &owner) }
+    }
+
+    /// Creates the device of the node, mapping the registers of its `reg`
+    /// entries.
+    pub fn create_device(&self) -> Result<Device, GenericError> {
+        let RegNode { path, reg } = self.deserialize_node()?;
+        let mmio = reg
+            .into_iter()
+            .flatten()
+            .map(|reg| {
+                let range = reg.range();
+                unsafe { self.map_mmio(range.clone()) }.with_whatever_context(|_| {
+                    format!("failed to map registers of {}, range={range:#x?}", path.0)
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Device::new(path.0, mmio))
+    }
+
+    /// Defers the probe until the node at `path` is bound.
+    pub fn require(&self, path: &ByteStr) -> Result<(), ProbeError> {
+        if !self.bound.contains(path) {
+            return Err(ProbeError::DeferredProbe(path.to_owned()));
+        }
+        Ok(())
+    }
+
+    /// Defers the probe until the node referenced by `phandle` is bound.
+    ///
+    /// Returns the path of the node.
+    pub fn require_phandle(&self, phandle: Phandle) -> Result<ByteString, ProbeError> {
+        let path = deserialize_path_by_phandle(self.dt, phandle)?;
+        self.require(ByteStr::new(&path))?;
+        Ok(path)
+    }
+
+    /// Claims the reserved memory region referenced by the `index`th entry of
+    /// the `memory-region` property of the node.
+    #[expect(dead_code)]
+    pub fn claim_memory_region(
+        &self,
+        index: usize,
+    ) -> Result<&'static ReservedRegion, GenericError> {
+        let MemoryRegionNode { memory_region } = self.deserialize_node()?;
+        let phandle = memory_region
+            .and_then(|phandles| phandles.get(index))
+            .map(|phandle| Phandle::new(u32::from_be_bytes(*phandle)))
+            .with_whatever_context(|| {
+                format!("memory-region #{index} of {} not found", self.path)
+            })?;
+        reserved::claim(phandle)
+            .with_whatever_context(|_| format!("failed to claim memory region of {}", self.path))
+    }
+
+    /// Defers the probe until the `msi-parent` of the node is bound.
+    pub fn require_msi_parent(&self) -> Result<(), ProbeError> {
+        let MsiParentNode { msi_parent } = self.deserialize_node()?;
+        if let Some(phandle) = msi_parent {
+            self.require_phandle(phandle)?;
+        }
+        Ok(())
+    }
+
+    /// Defers the probe until the interrupt parents of the node are bound.
+    pub fn require_interrupt_parents(&self) -> Result<(), ProbeError> {
+        let InterruptPropertiesNode {
+            interrupts,
+            interrupts_extended,
+        } = self.deserialize_node()?;
+        if interrupts.is_none() && interrupts_extended.is_none() {
+            return Ok(());
+        }
+        let InterruptsNode { device } = self.deserialize_node()?;
+        for interrupt in device.interrupts() {
+            self.require(interrupt.parent_path())?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, DeserializeNode)]
+struct ProbeNode<'blob> {
+    #[devtree(node)]
+    path: NodePath,
+    #[devtree(raw_node)]
+    node: RawNode<'blob>,
+    #[devtree(property(default))]
+    compatible: Option<Compatible<'blob>>,
+}
+
+#[derive(Debug, DeserializeNode)]
+struct RegNode<'blob> {
+    #[devtree(node)]
+    path: NodePath,
+    #[devtree(property(default))]
+    reg: Option<Reg<'blob>>,
+}
+
+#[derive(Debug, DeserializeNode)]
+struct InterruptPropertiesNode<'blob> {
+    #[devtree(property(default))]
+    interrupts: Option<&'blob [u8]>,
+    #[devtree(property(name = "interrupts-extended", default))]
+    interrupts_extended: Option<&'blob [u8]>,
+}
+
+#[derive(Debug, DeserializeNode)]
+struct MsiParentNode {
+    #[devtree(property(name = "msi-parent", default))]
+    msi_parent: Option<Phandle>,
+}
+
+#[derive(Debug, DeserializeNode)]
+struct MemoryRegionNode<'blob> {
+    #[devtree(property(name = "memory-region", default))]
+    memory_region: Option<&'blob [[u8; 4]]>,
+}
+
+#[derive(Debug, DeserializeNode)]
+struct InterruptsNode<'blob> {
+    #[devtree(node)]
+    device: InterruptGeneratingDevice<'blob>,
+}
+
+#[derive(Debug, DeserializeNode)]
+struct PathNode {
+    #[devtree(node)]
+    path: NodePath,
+}
+
+#[derive(Debug)]
+struct Binding<'dt> {
+    path: ByteString,
+    /// Node matched with the driver, deserialized again by the probe.
+    node: RawNode<'dt>,
+    driver: &'static DriverDescriptor,
+    /// Dependency the last probe is deferred by.
+    deferred_by: Option<ByteString>,
+}
+
+initcall!(INITCALL, drivers, init);
+
+fn init() -> Result<(), GenericError> {
+    let dt = crate::DEVICETREE
+        .get()
+        .whatever_context("devicetree not parsed")?;
+    probe_all(dt)
+}
+
+/// Probes the drivers of all nodes in the devicetree.
+///
+/// The nodes are probed in the devicetree order in each pass. Nodes whose
+/// dependencies are never bound are left unprobed with a warning.
+fn probe_all(dt: &Devicetree) -> Result<(), GenericError> {
+    let mut pending = bind_nodes(dt)?;
+    let mut bound = BTreeSet::new();
+    loop {
+        let count = pending.len();
+        let mut deferred = Vec::new();
+        for mut binding in pending {
+            let driver = binding.driver;
+            debug!("probing {} with {} driver", binding.path, driver.name);
+            let ctx = ProbeContext {
+                dt,
+                path: ByteStr::new(&binding.path),
+                node: &binding.node,
+                driver,
+                bound: &bound,
+            };
+            match (driver.probe)(&ctx) {
+                Ok(()) => {
+                    bound.insert(binding.path);
+                }
+                Err(ProbeError::DeferredProbe(dependency)) => {
+                    debug!("probe of {} deferred by {dependency}", binding.path);
+                    binding.deferred_by = Some(dependency);
+                    deferred.push(binding);
+                }
+                Err(ProbeError::Failed(e)) => {
+                    return Err(e).with_whatever_context(|_| {
+                        format!(
+                            "failed to probe {} with {} driver",
+                            binding.path, driver.name
+                        )
+                    });
+                }
+            }
+        }
+        pending = deferred;
+        if pending.is_empty() || pending.len() == count {
+            break;
+        }
+    }
+    for binding in pending {
+        warn!(
+            "{} is not probed, as {} is never bound",
+            binding.path,
+            binding.deferred_by.unwrap()
+        );
+    }
+    Ok(())
+}
+
+fn bind_nodes(dt: &Devicetree) -> Result<Vec<Binding<'_>>, GenericError> {
+    let mut bindings = Vec::new();
+    let mut cursor = dt
+        .tree_cursor()
+        .whatever_context("failed to create tree cursor")?;
+    let iter = cursor
+        .read_descendant_nodes()
+        .skip_disabled()
+        .deserialize_node::<ProbeNode>();
+    for node in iter {
+        let node = node.whatever_context("failed to deserialize node in devicetree")?;
+        let Some(driver) = node
+            .compatible
+            .and_then(|compatible| find_driver(&compatible))
+        else {
+            continue;
+        };
+        bindings.push(Binding {
+            path: node.path.0,
+            node: node.node,
+            driver,
+            deferred_by: None,
+        });
+    }
+    Ok(bindings)
+}
+
+/// Returns the registered drivers.
+fn drivers() -> &'static [DriverDescriptor] {
+    let start = (&raw const DRIVERS_START).cast::<DriverDescriptor>();
+    let end = (&raw const DRIVERS_END).cast::<DriverDescriptor>();
+    let len = (end.addr() - start.addr()) / size_of::<DriverDescriptor>();
+    unsafe { slice::from_raw_parts(start, len) }
+}
+
+/// Returns the driver of a node.
+///
+/// If a node is compatible with several drivers, the driver matching its most
+/// specific `compatible` string is used.
+fn find_driver(compatible: &Compatible<'_>) -> Option<&'static DriverDescriptor> {
+    let mut best = None;
+    for driver in drivers() {
+        let Some(score) = compatible.match_table(&driver.compatibles) else {
+            continue;
+        };
+        if best.is_none_or(|(_, best_score)| score > best_score) {
+            best = Some((driver, score));
+        }
+    }
+    best.map(|(driver, _score)| driver)
+}
+
+fn deserialize_path_by_phandle(
+    dt: &Devicetree,
+    phandle: Phandle,
+) -> Result<ByteString, GenericError> {
+    let mut cursor = dt
+        .tree_cursor()
+        .whatever_context("failed to create tree cursor")?;
+    let PathNode { path } = cursor
+        .read_node_by_phandle(phandle)
+        .whatever_context("failed to read devicetree")?
+        .with_whatever_context(|| format!("node of phandle {phandle:?} not found"))?
+        .deserialize_node()
+        .with_whatever_context(|_| format!("failed to deserialize node of phandle {phandle:?}"))?;
+    Ok(path.0)
+}
+
+#[cfg(feature = "ktest")]
+pub mod ktests {
+    use snafu::ensure_whatever;
+
+    use super::{GenericError, drivers};
+    use crate::ktest::KernelTest;
+
+    pub static TESTS: &[KernelTest] = kernel_tests![drivers_are_registered];
+
+    fn drivers_are_registered() -> Result<(), GenericError> {
+        let drivers = drivers();
+        ensure_whatever!(
+            drivers.iter().any(|driver| driver.name == "cpu-intc"),
+            "cpu-intc driver is not registered"
+        );
+        for (i, driver) in drivers.iter().enumerate() {
+            ensure_whatever!(
+                !driver.compatibles.is_empty(),
+                "{} driver has no compatibles",
+                driver.name
+            );
+            ensure_whatever!(
+                drivers[..i].iter().all(|other| other.name != driver.name),
+                "{} driver is registered twice",
+                driver.name
+            );
+        }
+        Ok(())
+    }
+}
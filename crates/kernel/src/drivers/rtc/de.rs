@@ -0,0 +1,51 @@
+use alloc::{boxed::Box, sync::Arc};
+
+use devtree::{
+    DeserializeNode,
+    model::{
+        node::NodePath,
+        property::{Compatible, Reg},
+    },
+};
+use snafu::{OptionExt as _, ResultExt as _, whatever};
+
+use super::{RtcDevice, goldfish};
+use crate::{drivers::registry::ProbeContext, error::GenericError, iter::IteratorExt as _};
+
+#[derive(Debug, DeserializeNode)]
+struct RtcNode<'blob> {
+    #[devtree(node)]
+    path: NodePath,
+    #[devtree(property)]
+    reg: Reg<'blob>,
+    #[devtree(property)]
+    compatible: Compatible<'blob>,
+}
+
+pub fn deserialize(ctx: &ProbeContext<'_>) -> Result<Arc<RtcDevice>, GenericError> {
+    let rtc_node = ctx.deserialize_node::<RtcNode>()?;
+    let device = RtcDevice::from_node(ctx, rtc_node)?;
+    Ok(Arc::new(device))
+}
+
+impl RtcDevice {
+    fn from_node(ctx: &ProbeContext<'_>, rtc_node: RtcNode<'_>) -> Result<Self, GenericError> {
+        let RtcNode {
+            path,
+            reg,
+            compatible,
+        } = rtc_node;
+        let reg = reg
+            .into_iter()
+            .assume_one()
+            .whatever_context("invalid 'reg' entries in rtc node")?;
+        let driver = if compatible.matches("google,goldfish-rtc") {
+            let regs = unsafe { ctx.map_mmio(reg.range()) }
+                .whatever_context("failed to map rtc registers")?;
+            Box::new(goldfish::Driver::new(regs))
+        } else {
+            whatever!("unsupported rtc device, compatible={compatible:?}");
+        };
+        Ok(Self::new(path.0, driver))
+    }
+}
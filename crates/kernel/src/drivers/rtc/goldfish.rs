@@ -0,0 +1,58 @@
+use alloc::boxed::Box;
+use core::{error::Error, time::Duration};
+
+use super::RtcDriver;
+use crate::memory::kernel_space::MmioToken;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Register {
+    offset: usize,
+}
+
+// the Goldfish RTC control registers.
+// see <https://android.googlesource.com/platform/external/qemu/+/master/docs/GOLDFISH-VIRTUAL-HARDWARE.TXT>
+
+impl Register {
+    /// Lower 32 bits of the current time in nanoseconds (readonly)
+    ///
+    /// Reading this register latches the upper 32 bits into `TIME_HIGH`.
+    const TIME_LOW: Self = Self::new(0x00);
+    /// Upper 32 bits of the current time in nanoseconds (readonly)
+    const TIME_HIGH: Self = Self::new(0x04);
+
+    const fn new(offset: usize) -> Self {
+        Self { offset }
+    }
+}
+
+#[derive(Debug)]
+pub(super) struct Driver {
+    regs: MmioToken,
+}
+
+impl Driver {
+    pub(super) fn new(regs: MmioToken) -> Self {
+        Self { regs }
+    }
+
+    unsafe fn read_register(&mut self, reg: Register) -> u32 {
+        unsafe { self.regs.read(reg.offset) }
+    }
+}
+
+impl RtcDriver for Driver {
+    fn init(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    fn read_time(&mut self) -> Duration {
+        // TIME_LOW must be read first to latch TIME_HIGH.
+        let (low, high) = unsafe {
+            let low = self.read_register(Register::TIME_LOW);
+            let high = self.read_register(Register::TIME_HIGH);
+            (low, high)
+        };
+        let nanos = (u64::from(high) << 32) | u64::from(low);
+        Duration::from_nanos(nanos)
+    }
+}
@@ -0,0 +1,100 @@
+use alloc::{boxed::Box, format, sync::Arc, vec::Vec};
+use core::{error::Error, fmt, time::Duration};
+
+use devtree::{
+    compatible_table,
+    types::{ByteStr, ByteString},
+};
+use snafu::ResultExt as _;
+
+use crate::{
+    drivers::registry::{ProbeContext, ProbeError},
+    error::GenericError,
+    sync::spinlock::SpinMutex,
+};
+
+mod de;
+mod goldfish;
+
+trait RtcDriver: fmt::Debug + Send + Sync {
+    fn init(&mut self) -> Result<(), Box<dyn Error>>;
+    /// Returns the current wall-clock time as a duration since the UNIX epoch.
+    fn read_time(&mut self) -> Duration;
+}
+
+static RTC_DEVICES: SpinMutex<Vec<Arc<RtcDevice>>> = SpinMutex::new(Vec::new());
+
+driver!(DRIVER {
+    name: "rtc",
+    compatibles: compatible_table!["google,goldfish-rtc"],
+    probe,
+});
+
+fn probe(ctx: &ProbeContext<'_>) -> Result<(), ProbeError> {
+    let device = de::deserialize(ctx)?;
+    device.init()?;
+    RTC_DEVICES.lock().push(device);
+    Ok(())
+}
+
+/// Returns the RTC device used as the source of the system wall-clock time.
+pub fn system_rtc() -> Option<Arc<RtcDevice>> {
+    RTC_DEVICES.lock().first().cloned()
+}
+
+#[derive(Debug)]
+pub struct RtcDevice {
+    path: ByteString,
+    driver: SpinMutex<Box<dyn RtcDriver>>,
+}
+
+impl RtcDevice {
+    fn new(path: ByteString, driver: Box<dyn RtcDriver>) -> Self {
+        Self {
+            path,
+            driver: SpinMutex::new(driver),
+        }
+    }
+
+    fn init(&self) -> Result<(), GenericError> {
+        let mut driver = self.driver.lock();
+        driver.init().with_whatever_context(|_| {
+            format!("failed to initialize RTC device driver, path={}", self.path)
+        })?;
+        Ok(())
+    }
+
+    pub fn path(&self) -> &ByteStr {
+        ByteStr::new(&self.path)
+    }
+
+    /// Reads the current wall-clock time as a duration since the UNIX epoch.
+    pub fn read_time(&self) -> Duration {
+        self.driver.lock().read_time()
+    }
+}
+
+#[cfg(feature = "ktest")]
+pub mod ktests {
+    use snafu::ensure_whatever;
+
+    use super::{Duration, GenericError, system_rtc};
+    use crate::{interrupt::timer, ktest::KernelTest};
+
+    pub static TESTS: &[KernelTest] = kernel_tests![system_rtc_advances];
+
+    /// Passes if no RTC is bound.
+    fn system_rtc_advances() -> Result<(), GenericError> {
+        // 2020-01-01T00:00:00Z
+        const EARLIEST: Duration = Duration::from_hours(438_288);
+        let Some(rtc) = system_rtc() else {
+            return Ok(());
+        };
+        let start = rtc.read_time();
+        ensure_whatever!(start >= EARLIEST, "RTC time {start:?} before 2020");
+        timer::sleep(Duration::from_millis(20));
+        let end = rtc.read_time();
+        ensure_whatever!(end > start, "RTC time did not advance from {start:?}");
+        Ok(())
+    }
+}
@@ -1,24 +1,16 @@
-use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use alloc::sync::Arc;
 
 use devtree::{
-    DeserializeNode, Devicetree,
-    tree_cursor::{TreeCursor as _, TreeIterator as _},
+    DeserializeNode,
     model::{
         node::{Interrupt, InterruptGeneratingDevice, NodePath},
-        property::{Compatible, Reg},
+        property::Reg,
     },
 };
-use snafu::{OptionExt as _, ResultExt as _, whatever};
+use snafu::OptionExt as _;
 
-use super::SerialDevice;
-use crate::{
-    drivers::{
-        irq::plic::{self, Plic, PlicSource},
-        serial::ns16550a,
-    },
-    error::GenericError,
-    iter::IteratorExt as _,
-};
+use super::{NewDriver, SerialConfig, SerialDevice};
+use crate::{chosen, drivers::registry::ProbeContext, error::GenericError, iter::IteratorExt as _};
 
 #[derive(Debug, DeserializeNode)]
 struct SerialNode<'blob> {
@@ -26,65 +18,62 @@ struct SerialNode<'blob> {
     path: NodePath,
     #[devtree(node)]
     device: InterruptGeneratingDevice<'blob>,
-    #[devtree(property(name = "clock-frequency"))]
-    clock_frequency: u32,
+    #[devtree(property(name = "clock-frequency", default))]
+    clock_frequency: Option<u32>,
+    #[devtree(property(name = "current-speed", default))]
+    current_speed: Option<u32>,
     #[devtree(property)]
     reg: Reg<'blob>,
-    #[devtree(property)]
-    compatible: Compatible<'blob>,
 }
 
-pub fn deserialize(dt: &Devicetree) -> Result<Vec<Arc<SerialDevice>>, GenericError> {
-    let mut serial_devices = Vec::new();
-
-    let mut cursor = dt
-        .tree_cursor()
-        .whatever_context("failed to create tree cursor")?;
-    let iter = cursor
-        .read_descendant_nodes_by_glob("/soc/serial")
-        .deserialize_node::<SerialNode>();
-    for serial_node in iter {
-        let serial_node =
-            serial_node.whatever_context("failed to deserialize serial node in devicetree")?;
-        let device = SerialDevice::from_node(serial_node)?;
-        serial_devices.push(Arc::new(device));
-    }
-    Ok(serial_devices)
-}
-
-fn find_plic_source(interrupts: &[Interrupt]) -> Result<(Arc<Plic>, PlicSource), GenericError> {
-    for interrupt in interrupts {
-        let Some(plic) = plic::find_plic_by_dtree_path(interrupt.parent_path()) else {
-            continue;
-        };
-        let source = plic.translate_interrupt_specifier(interrupt.specifier());
-        return Ok((plic, source));
-    }
-    whatever!("no plic device found")
+pub fn deserialize<'a>(
+    ctx: &ProbeContext<'a>,
+    new_driver: NewDriver,
+) -> Result<(SerialDevice, Interrupt<'a>), GenericError> {
+    let serial_node = ctx.deserialize_node::<SerialNode>()?;
+    SerialDevice::from_node(ctx, serial_node, new_driver)
 }
 
 impl SerialDevice {
-    fn from_node(serial_node: SerialNode<'_>) -> Result<Self, GenericError> {
+    fn from_node<'a>(
+        ctx: &ProbeContext<'a>,
+        serial_node: SerialNode<'a>,
+        new_driver: NewDriver,
+    ) -> Result<(Self, Interrupt<'a>), GenericError> {
         let SerialNode {
             path,
             device,
             clock_frequency,
+            current_speed,
             reg,
-            compatible,
         } = serial_node;
-        let (plic, source) = find_plic_source(device.interrupts())?;
-        let reg = reg
-            .into_iter()
+        let config = Self::config(&path, current_speed);
+        let interrupt = device
+            .interrupts()
+            .first()
+            .cloned()
+            .whatever_context("no interrupts in serial node")?;
+        reg.into_iter()
             .assume_one()
             .whatever_context("invalid 'reg' entries in serial node")?;
-        let base_addr = reg.range().start;
-        let size = reg.range().len();
+        let device = Arc::new(ctx.create_device()?);
+        let driver = new_driver(Arc::clone(&device), clock_frequency)?;
+        Ok((Self::new(device, config, driver), interrupt))
+    }
 
-        let driver = if compatible.is_compatible_to("ns16550a") {
-            Box::new(unsafe { ns16550a::Driver::new(base_addr, size, clock_frequency) })
-        } else {
-            whatever!("unsupported serial device, compatible={compatible:?}");
-        };
-        Ok(Self::new(path.0, plic, source, driver))
+    /// Returns the line settings from `current-speed`, overridden by the
+    /// options in `stdout-path` if this is the stdout device.
+    fn config(path: &NodePath, current_speed: Option<u32>) -> SerialConfig {
+        let mut config = SerialConfig::default();
+        if let Some(baud_rate) = current_speed.filter(|&baud_rate| baud_rate > 0) {
+            config.baud_rate = baud_rate;
+        }
+        if chosen::stdout_path().is_some_and(|stdout| *stdout == path.0)
+            && let Some(options) = chosen::stdout_options()
+            && let Err(e) = config.apply_options(options.as_ref())
+        {
+            warn!("ignoring invalid options in stdout-path of {}: {e}", path.0);
+        }
+        config
     }
 }
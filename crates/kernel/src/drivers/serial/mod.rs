@@ -1,70 +1,198 @@
-use alloc::{boxed::Box, format, sync::Arc, vec::Vec};
+use alloc::{boxed::Box, collections::vec_deque::VecDeque, format, sync::Arc, vec::Vec};
 use core::{error::Error, fmt};
 
-use devtree::{
-    Devicetree,
-    types::{ByteStr, ByteString},
-};
-use snafu::ResultExt as _;
-use spin::Once;
+use devtree::types::ByteStr;
+use snafu::{OptionExt as _, ResultExt as _, ensure_whatever, whatever};
 
-use super::irq::plic::{Plic, PlicSource};
 use crate::{
-    cpu,
+    drivers::{
+        device::Device,
+        registry::{ProbeContext, ProbeError},
+    },
     error::GenericError,
-    sync::spinlock::{SpinMutex, SpinMutexCondVar},
+    sync::{
+        channel::{Notifier, SpscProducer, SpscRing},
+        spinlock::{SpinMutex, SpinMutexCondVar},
+    },
+    tunables::{self, Tunable},
 };
 
 mod de;
+#[cfg(feature = "ns16550a")]
 mod ns16550a;
+mod pl011;
+mod sifive;
+
+/// Capacity of the receive buffer; bytes received while it is full are dropped.
+const RX_BUFFER_SIZE: usize = 1024;
+/// Capacity of the transmit buffer; writers wait while it is full.
+const TX_BUFFER_SIZE: usize = 1024;
+
+/// Baud rate of the devices without `current-speed`, applied to the devices
+/// probed after it is set.
+pub static DEFAULT_BAUD_RATE: Tunable<u32> = Tunable::new(
+    "serial.baud",
+    "baud rate of the serial devices without current-speed",
+    38400,
+)
+.with_check(tunables::non_zero);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Parity {
+    None,
+    Odd,
+    Even,
+}
+
+/// Line settings of a serial device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SerialConfig {
+    pub baud_rate: u32,
+    /// Number of data bits, from 5 to 8.
+    pub data_bits: u8,
+    pub parity: Parity,
+    /// Number of stop bits, 1 or 2.
+    pub stop_bits: u8,
+}
+
+impl Default for SerialConfig {
+    fn default() -> Self {
+        Self {
+            baud_rate: DEFAULT_BAUD_RATE.get(),
+            data_bits: 8,
+            parity: Parity::None,
+            stop_bits: 1,
+        }
+    }
+}
 
+impl SerialConfig {
+    /// Applies the options in the `stdout-path` property.
+    ///
+    /// The format is `<baud>[<parity>[<bits>[<stop bits>]]]`, where `<parity>`
+    /// is one of `n`, `o` and `e`, as in `115200n8`.
+    fn apply_options(&mut self, options: &ByteStr) -> Result<(), GenericError> {
+        let options = str::from_utf8(options)
+            .ok()
+            .whatever_context("non-UTF-8 options")?;
+        let digits = options
+            .find(|ch: char| !ch.is_ascii_digit())
+            .unwrap_or(options.len());
+        let (baud_rate, rest) = options.split_at(digits);
+        let mut config = Self {
+            baud_rate: baud_rate
+                .parse()
+                .with_whatever_context(|_| format!("invalid baud rate in `{options}`"))?,
+            ..*self
+        };
+        let mut rest = rest.chars();
+        if let Some(parity) = rest.next() {
+            config.parity = match parity {
+                'n' => Parity::None,
+                'o' => Parity::Odd,
+                'e' => Parity::Even,
+                _ => {
+                    whatever!("invalid parity `{parity}` in `{options}`");
+                }
+            };
+        }
+        if let Some(bits) = rest.next() {
+            config.data_bits = bits
+                .to_digit(10)
+                .and_then(|bits| u8::try_from(bits).ok())
+                .whatever_context("invalid data bits")?;
+        }
+        if let Some(bits) = rest.next() {
+            config.stop_bits = bits
+                .to_digit(10)
+                .and_then(|bits| u8::try_from(bits).ok())
+                .whatever_context("invalid stop bits")?;
+        }
+        ensure_whatever!(rest.next().is_none(), "trailing characters in `{options}`");
+        config.validate()?;
+        *self = config;
+        Ok(())
+    }
+
+    fn validate(self) -> Result<(), GenericError> {
+        ensure_whatever!(self.baud_rate > 0, "baud rate must be positive");
+        ensure_whatever!(
+            (5..=8).contains(&self.data_bits),
+            "unsupported number of data bits {}",
+            self.data_bits
+        );
+        ensure_whatever!(
+            matches!(self.stop_bits, 1 | 2),
+            "unsupported number of stop bits {}",
+            self.stop_bits
+        );
+        Ok(())
+    }
+}
+
+impl fmt::Display for SerialConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parity = match self.parity {
+            Parity::None => 'N',
+            Parity::Odd => 'O',
+            Parity::Even => 'E',
+        };
+        write!(
+            f,
+            "{} {}{parity}{}",
+            self.baud_rate, self.data_bits, self.stop_bits
+        )
+    }
+}
+
+/// Serial device driver, called while the device is locked.
 trait SerialDriver: fmt::Debug + Send + Sync {
-    fn init(&mut self) -> Result<(), Box<dyn Error>>;
-    fn is_tx_idle(&mut self) -> bool;
-    fn is_rx_ready(&mut self) -> bool;
+    /// Maps the registers and programs the line settings, leaving the
+    /// interrupts disabled.
+    fn init(&mut self, config: SerialConfig) -> Result<(), Box<dyn Error>>;
+    /// Enables the interrupt raised when the device can accept bytes to send.
     fn set_tx_idle_interrupt(&mut self, enable: bool);
+    /// Enables the interrupt raised when the device has received bytes.
     fn set_rx_ready_interrupt(&mut self, enable: bool);
+    /// Writes as many bytes as the device accepts without waiting.
     fn write(&mut self, bytes: &[u8]) -> usize;
+    /// Reads the received bytes without waiting.
     fn read(&mut self, bytes: &mut [u8]) -> usize;
+    /// Completes the handling of the interrupt.
     fn complete(&mut self);
 }
 
-const SERIAL_PRIORITY: u32 = 1;
-const SERIAL_THRESHOLD: u32 = 0;
-static SERIAL_DRIVERS: Once<Vec<Arc<SerialDevice>>> = Once::new();
+static SERIAL_DRIVERS: SpinMutex<Vec<Arc<SerialDevice>>> = SpinMutex::new(Vec::new());
+
+/// Creates the driver of a device model from the device, whose first `reg`
+/// entry is the registers, and the `clock-frequency` of the node.
+type NewDriver = fn(Arc<Device>, Option<u32>) -> Result<Box<dyn SerialDriver>, GenericError>;
 
-pub fn init(dt: &Devicetree) -> Result<(), GenericError> {
-    let drivers = de::deserialize(dt).whatever_context("failed to deserialize devicetree")?;
-    for driver in &drivers {
-        driver.init()?;
-        let plic = Arc::clone(&driver.plic);
-        let source = driver.source;
+/// Probes a serial device, registered by the driver of each device model.
+fn probe(ctx: &ProbeContext<'_>, new_driver: NewDriver) -> Result<(), ProbeError> {
+    ctx.require_interrupt_parents()?;
 
-        let callback = Arc::new({
-            let driver = Arc::clone(driver);
-            move |_context| {
+    let (driver, interrupt) = de::deserialize(ctx, new_driver)?;
+    let driver = Arc::new(driver);
+    driver.init()?;
+
+    let handler = Arc::new({
+        let driver = Arc::downgrade(&driver);
+        move || {
+            if let Some(driver) = driver.upgrade() {
                 driver.handle_interrupt();
             }
-        });
+        }
+    });
+    driver.device.request_irq(&interrupt, handler)?;
 
-        plic.set_priority(source, SERIAL_PRIORITY);
-        plic.register_callback(source, callback);
-    }
-    SERIAL_DRIVERS.call_once(|| drivers);
+    SERIAL_DRIVERS.lock().push(driver);
     Ok(())
 }
 
-pub fn apply() {
-    for driver in SERIAL_DRIVERS.get().unwrap() {
-        let cpu = cpu::current();
-        let Some(context) = driver.plic.find_context_for_cpu(cpu.id()) else {
-            continue;
-        };
-        driver
-            .plic
-            .set_priority_threshold(context, SERIAL_THRESHOLD);
-        driver.plic.enable_interrupt(driver.source, context);
-    }
+/// Returns all serial devices, in the probe order.
+pub fn devices() -> Vec<Arc<SerialDevice>> {
+    SERIAL_DRIVERS.lock().clone()
 }
 
 pub fn find_serial_by_dtree_path<P>(path: P) -> Option<Arc<SerialDevice>>
@@ -73,98 +201,187 @@ where
 {
     let path = path.as_ref();
     SERIAL_DRIVERS
-        .get()?
+        .lock()
         .iter()
-        .find(|device| device.path == path)
+        .find(|device| device.path() == path)
         .cloned()
 }
 
+#[derive(Debug)]
+struct State {
+    driver: Box<dyn SerialDriver>,
+    /// Bytes written and not passed to the device yet.
+    tx: VecDeque<u8>,
+}
+
+impl State {
+    /// Passes the buffered bytes to the device while it accepts them.
+    ///
+    /// Returns whether any byte is passed.
+    fn flush_tx(&mut self) -> bool {
+        let mut flushed = false;
+        while !self.tx.is_empty() {
+            let nwritten = self.driver.write(self.tx.as_slices().0);
+            if nwritten == 0 {
+                break;
+            }
+            self.tx.drain(..nwritten);
+            flushed = true;
+        }
+        // the interrupt is needed only to send the rest
+        self.driver.set_tx_idle_interrupt(!self.tx.is_empty());
+        flushed
+    }
+
+    /// Moves the received bytes from the device to `rx`.
+    ///
+    /// Returns the number of the received bytes and the dropped ones among
+    /// them.
+    fn fill_rx(&mut self, rx: &mut SpscProducer<'_, u8, RX_BUFFER_SIZE>) -> (usize, usize) {
+        let mut received = 0;
+        let mut dropped = 0;
+        loop {
+            let mut bytes = [0; 16];
+            let nread = self.driver.read(&mut bytes);
+            if nread == 0 {
+                break;
+            }
+            for &byte in &bytes[..nread] {
+                if rx.push(byte).is_err() {
+                    dropped += 1;
+                }
+            }
+            received += nread;
+        }
+        (received, dropped)
+    }
+}
+
+/// Serial device with interrupt-driven receive and transmit buffers.
+///
+/// Bytes are received into the buffer even if no reader is waiting, so they
+/// are not lost unless the buffer overflows.
 #[derive(Debug)]
 pub struct SerialDevice {
-    path: ByteString,
-    plic: Arc<Plic>,
-    source: PlicSource,
-    driver: SpinMutex<Box<dyn SerialDriver>>,
-    rx_ready: SpinMutexCondVar,
-    tx_idle: SpinMutexCondVar,
+    device: Arc<Device>,
+    config: SerialConfig,
+    state: SpinMutex<State>,
+    /// Bytes received by the interrupt handler and not read yet.
+    ///
+    /// The interrupt handler pushes with the state locked, and the readers pop
+    /// in [`Notifier::wait_until`] of `rx_ready`, so that each side has a
+    /// single user at a time.
+    rx: SpscRing<u8, RX_BUFFER_SIZE>,
+    rx_ready: Notifier,
+    tx_space: SpinMutexCondVar,
 }
 
 impl SerialDevice {
-    fn new(
-        path: ByteString,
-        plic: Arc<Plic>,
-        source: PlicSource,
-        driver: Box<dyn SerialDriver>,
-    ) -> Self {
+    fn new(device: Arc<Device>, config: SerialConfig, driver: Box<dyn SerialDriver>) -> Self {
         Self {
-            path,
-            plic,
-            source,
-            driver: SpinMutex::new(driver),
-            rx_ready: SpinMutexCondVar::new(),
-            tx_idle: SpinMutexCondVar::new(),
+            device,
+            config,
+            state: SpinMutex::new(State {
+                driver,
+                tx: VecDeque::with_capacity(TX_BUFFER_SIZE),
+            }),
+            rx: SpscRing::new(),
+            rx_ready: Notifier::new(),
+            tx_space: SpinMutexCondVar::new(),
         }
     }
 
+    pub fn path(&self) -> &ByteStr {
+        self.device.path()
+    }
+
     fn init(&self) -> Result<(), GenericError> {
-        let mut driver = self.driver.lock();
-        driver.init().with_whatever_context(|_| {
+        let mut state = self.state.lock();
+        state.driver.init(self.config).with_whatever_context(|_| {
             format!(
                 "failed to initialize serial device driver, path={}",
-                self.path,
+                self.path(),
             )
         })?;
-        driver.set_rx_ready_interrupt(true);
+        state.driver.set_rx_ready_interrupt(true);
+        info!("serial {}: {}", self.path(), self.config);
         Ok(())
     }
 
     fn handle_interrupt(&self) {
-        let mut driver = self.driver.lock();
+        let mut state = self.state.lock();
+        let mut rx = self.rx.try_producer().unwrap();
+        let (received, dropped) = state.fill_rx(&mut rx);
+        drop(rx);
+        let sent = state.flush_tx();
+        state.driver.complete();
+        drop(state);
 
-        if driver.is_rx_ready() {
+        if dropped > 0 {
+            warn!(
+                "serial {}: receive buffer full, {dropped} bytes dropped",
+                self.path()
+            );
+        }
+        if received > 0 {
             self.rx_ready.notify_all();
-            driver.set_rx_ready_interrupt(false);
         }
-        if driver.is_tx_idle() {
-            self.tx_idle.notify_all();
-            driver.set_tx_idle_interrupt(false);
+        if sent {
+            self.tx_space.notify_all();
+        }
+    }
+
+    /// Passes `bytes` to the readers as if they were received by the device.
+    ///
+    /// This merges the input of another device, such as a keyboard, into the
+    /// input of the serial console.
+    #[cfg_attr(not(feature = "input"), expect(dead_code))]
+    pub fn push_received(&self, bytes: &[u8]) {
+        let state = self.state.lock();
+        let mut rx = self.rx.try_producer().unwrap();
+        let dropped = bytes.iter().filter(|&&byte| rx.push(byte).is_err()).count();
+        drop(rx);
+        drop(state);
+
+        if dropped > 0 {
+            warn!(
+                "serial {}: receive buffer full, {dropped} bytes dropped",
+                self.path()
+            );
         }
-        driver.complete();
+        self.rx_ready.notify_all();
     }
 
+    /// Reads the received bytes, waiting until at least one byte is received.
     pub fn read(&self, bytes: &mut [u8]) -> usize {
         if bytes.is_empty() {
             return 0;
         }
 
-        let mut driver = self.driver.lock();
-
-        loop {
-            let nread = driver.read(bytes);
-            if nread > 0 {
-                return nread;
-            }
-
-            driver.set_rx_ready_interrupt(true);
-            driver = self.rx_ready.wait(driver);
-        }
+        self.rx_ready.wait_until(|| {
+            let mut rx = self.rx.try_consumer().unwrap();
+            let nread = rx.pop_slice(bytes);
+            (nread > 0).then_some(nread)
+        })
     }
 
+    /// Queues the bytes for transmission, waiting until the buffer has space
+    /// for at least one byte.
     pub fn write(&self, bytes: &[u8]) -> usize {
         if bytes.is_empty() {
             return 0;
         }
 
-        let mut driver = self.driver.lock();
-
+        let mut state = self.state.lock();
         loop {
-            let nwritten = driver.write(bytes);
-            if nwritten > 0 {
+            let space = TX_BUFFER_SIZE - state.tx.len();
+            if space > 0 {
+                let nwritten = usize::min(bytes.len(), space);
+                state.tx.extend(&bytes[..nwritten]);
+                state.flush_tx();
                 return nwritten;
             }
-
-            driver.set_tx_idle_interrupt(true);
-            driver = self.tx_idle.wait(driver);
+            state = self.tx_space.wait(state);
         }
     }
 }
@@ -1,11 +1,15 @@
-use alloc::boxed::Box;
-use core::{error::Error, ops::Range, ptr};
+use alloc::{boxed::Box, format, sync::Arc};
+use core::error::Error;
 
 use bitflags::bitflags;
-use sv39::MapPageFlags;
+use devtree::compatible_table;
+use snafu::OptionExt as _;
 
-use super::SerialDriver;
-use crate::memory::{self, kernel_space};
+use super::{Parity, SerialConfig, SerialDriver};
+use crate::drivers::{
+    device::Device,
+    registry::{ProbeContext, ProbeError},
+};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 struct Register {
@@ -70,7 +74,12 @@ bitflags! {
     }
 
     struct LineControl : u8 {
-        const EIGHT_BITS = 0b11;
+        /// Word length minus 5.
+        const WORD_LENGTH = 0b11;
+        /// Two stop bits (1.5 for 5-bit words), otherwise one.
+        const TWO_STOP_BITS = 1 << 2;
+        const PARITY_ENABLE = 1 << 3;
+        const EVEN_PARITY = 1 << 4;
         const BAUD_LATCH = 1 << 7;
     }
 
@@ -80,50 +89,77 @@ bitflags! {
     }
 }
 
+/// Depth of the transmit FIFO.
+const TX_FIFO_SIZE: usize = 16;
+
+driver!(DRIVER {
+    name: "ns16550a",
+    compatibles: compatible_table!["ns16550a"],
+    probe,
+});
+
+fn probe(ctx: &ProbeContext<'_>) -> Result<(), ProbeError> {
+    super::probe(ctx, |device, clock_frequency| {
+        let clock_frequency =
+            clock_frequency.whatever_context("no 'clock-frequency' in serial node")?;
+        Ok(Box::new(Driver::new(device, clock_frequency)))
+    })
+}
+
 #[derive(Debug)]
 pub(super) struct Driver {
-    base_addr: usize,
-    size: usize,
+    device: Arc<Device>,
     uart_clock_frequency: u32,
 }
 
 impl Driver {
-    pub(super) unsafe fn new(base_addr: usize, size: usize, uart_clock_frequency: u32) -> Self {
+    pub(super) fn new(device: Arc<Device>, uart_clock_frequency: u32) -> Self {
         Self {
-            base_addr,
-            size,
+            device,
             uart_clock_frequency,
         }
     }
 
-    fn range(&self) -> Range<usize> {
-        self.base_addr..self.base_addr + self.size
-    }
-
-    fn register_addr(&self, reg: Register) -> usize {
-        assert!(reg.offset < self.size);
-        self.base_addr + reg.offset
-    }
-
     unsafe fn write_register(&mut self, reg: Register, value: u8) {
-        let addr = self.register_addr(reg);
         unsafe {
-            ptr::with_exposed_provenance_mut::<u8>(addr).write_volatile(value);
+            self.device.mmio(0).write(reg.offset, value);
         }
     }
 
     unsafe fn read_register(&mut self, reg: Register) -> u8 {
-        let addr = self.register_addr(reg);
-        unsafe { ptr::with_exposed_provenance::<u8>(addr).read_volatile() }
+        unsafe { self.device.mmio(0).read(reg.offset) }
+    }
+
+    fn is_tx_idle(&mut self) -> bool {
+        unsafe { self.read_register(Register::LINE_STATUS) & LineStatus::TX_IDLE.bits() != 0 }
+    }
+
+    fn is_rx_ready(&mut self) -> bool {
+        unsafe { self.read_register(Register::LINE_STATUS) & LineStatus::RX_READY.bits() != 0 }
     }
 }
 
 impl SerialDriver for Driver {
-    fn init(&mut self) -> Result<(), Box<dyn Error>> {
-        kernel_space::identity_map_range(
-            memory::expand_to_page_boundaries(self.range()),
-            MapPageFlags::RW,
-        )?;
+    fn init(&mut self, config: SerialConfig) -> Result<(), Box<dyn Error>> {
+        // the baud rate is the UART clock divided by 16 times the divisor
+        let (clock, baud_rate) = (
+            u64::from(self.uart_clock_frequency),
+            u64::from(config.baud_rate),
+        );
+        let divisor = (clock + baud_rate * 8) / (baud_rate * 16);
+        let divisor = u16::try_from(divisor)
+            .ok()
+            .filter(|&divisor| divisor > 0)
+            .ok_or_else(|| {
+                format!(
+                    "unsupported baud rate {} for clock frequency {}",
+                    config.baud_rate, self.uart_clock_frequency
+                )
+            })?;
+        let mut line_control = LineControl::from_bits_retain(config.data_bits - 5);
+        line_control.set(LineControl::TWO_STOP_BITS, config.stop_bits == 2);
+        line_control.set(LineControl::PARITY_ENABLE, config.parity != Parity::None);
+        line_control.set(LineControl::EVEN_PARITY, config.parity == Parity::Even);
 
         unsafe {
             // disable interrupts
@@ -131,35 +167,25 @@ impl SerialDriver for Driver {
 
             // special mode to set baud rate
             self.write_register(Register::LINE_CONTROL, LineControl::BAUD_LATCH.bits());
-            let baud_rate = 38400; // 38.4k
-            let divisor = u16::try_from(self.uart_clock_frequency / baud_rate).unwrap();
             let [divisor_msb, divisor_lsb] = divisor.to_be_bytes();
             self.write_register(Register::DIVISOR_LATCH_LSB, divisor_lsb);
             self.write_register(Register::DIVISOR_LATCH_MSB, divisor_msb);
 
-            // leave set-baud mode and set word length to 8 bits, no parity
-            self.write_register(Register::LINE_CONTROL, LineControl::EIGHT_BITS.bits());
+            // leave set-baud mode and set word length, parity and stop bits
+            self.write_register(Register::LINE_CONTROL, line_control.bits());
             // reset and enable FIFOs
             self.write_register(
                 Register::FIFO_CONTROL,
                 (FifoControl::FIFO_ENABLE | FifoControl::FIFO_RESET).bits(),
             );
 
-            // enable transmit and receive interrupts
+            // interrupts are enabled by the caller
             self.write_register(Register::INTERRUPT_ENABLE, InterruptEnable::empty().bits());
         }
 
         Ok(())
     }
 
-    fn is_tx_idle(&mut self) -> bool {
-        unsafe { self.read_register(Register::LINE_STATUS) & LineStatus::TX_IDLE.bits() != 0 }
-    }
-
-    fn is_rx_ready(&mut self) -> bool {
-        unsafe { self.read_register(Register::LINE_STATUS) & LineStatus::RX_READY.bits() != 0 }
-    }
-
     fn set_tx_idle_interrupt(&mut self, enable: bool) {
         unsafe {
             let mut ier =
@@ -179,15 +205,15 @@ impl SerialDriver for Driver {
     }
 
     fn write(&mut self, bytes: &[u8]) -> usize {
-        let mut count = 0;
-        for byte in bytes {
-            if !self.is_tx_idle() {
-                break;
-            }
+        // the transmit FIFO is empty when the holding register is idle
+        if !self.is_tx_idle() {
+            return 0;
+        }
+        let count = usize::min(bytes.len(), TX_FIFO_SIZE);
+        for byte in &bytes[..count] {
             unsafe {
                 self.write_register(Register::TX_HOLDING, *byte);
             };
-            count += 1;
         }
         count
     }
@@ -0,0 +1,221 @@
+use alloc::{boxed::Box, format, sync::Arc};
+use core::error::Error;
+
+use bitflags::bitflags;
+use devtree::compatible_table;
+
+use super::{Parity, SerialConfig, SerialDriver};
+use crate::drivers::{
+    device::Device,
+    registry::{ProbeContext, ProbeError},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Register {
+    offset: usize,
+}
+
+// see the ARM PrimeCell UART (PL011) Technical Reference Manual, section 3.2
+// "Summary of registers"
+
+impl Register {
+    /// Data Register
+    const DATA: Self = Self::new(0x00);
+    /// Flag Register (readonly)
+    const FLAG: Self = Self::new(0x18);
+    /// Integer Baud Rate Register
+    const INTEGER_BAUD_RATE: Self = Self::new(0x24);
+    /// Fractional Baud Rate Register
+    const FRACTIONAL_BAUD_RATE: Self = Self::new(0x28);
+    /// Line Control Register
+    const LINE_CONTROL: Self = Self::new(0x2c);
+    /// Control Register
+    const CONTROL: Self = Self::new(0x30);
+    /// Interrupt FIFO Level Select Register
+    const INTERRUPT_FIFO_LEVEL: Self = Self::new(0x34);
+    /// Interrupt Mask Set/Clear Register
+    const INTERRUPT_MASK: Self = Self::new(0x38);
+    /// Interrupt Clear Register (writeonly)
+    const INTERRUPT_CLEAR: Self = Self::new(0x44);
+
+    const fn new(offset: usize) -> Self {
+        Self { offset }
+    }
+}
+
+bitflags! {
+    #[repr(transparent)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    struct Flag : u32 {
+        const RX_FIFO_EMPTY = 1 << 4;
+        const TX_FIFO_FULL = 1 << 5;
+    }
+
+    struct LineControl : u32 {
+        const PARITY_ENABLE = 1 << 1;
+        const EVEN_PARITY = 1 << 2;
+        const TWO_STOP_BITS = 1 << 3;
+        const FIFO_ENABLE = 1 << 4;
+        /// Word length minus 5.
+        const WORD_LENGTH = 0b11 << 5;
+    }
+
+    struct Control : u32 {
+        const UART_ENABLE = 1 << 0;
+        const TX_ENABLE = 1 << 8;
+        const RX_ENABLE = 1 << 9;
+    }
+
+    struct Interrupt : u32 {
+        const RX = 1 << 4;
+        const TX = 1 << 5;
+        const RX_TIMEOUT = 1 << 6;
+        const ALL = 0x7ff;
+    }
+}
+
+driver!(DRIVER {
+    name: "pl011",
+    compatibles: compatible_table!["arm,pl011"],
+    probe,
+});
+
+fn probe(ctx: &ProbeContext<'_>) -> Result<(), ProbeError> {
+    super::probe(ctx, |device, clock_frequency| {
+        Ok(Box::new(Driver::new(device, clock_frequency)))
+    })
+}
+
+/// ARM PL011 UART driver.
+#[derive(Debug)]
+pub(super) struct Driver {
+    device: Arc<Device>,
+    /// Frequency of the reference clock, or `None` to keep the baud rate
+    /// set by the firmware.
+    clock_frequency: Option<u32>,
+}
+
+impl Driver {
+    pub(super) fn new(device: Arc<Device>, clock_frequency: Option<u32>) -> Self {
+        Self {
+            device,
+            clock_frequency,
+        }
+    }
+
+    unsafe fn write_register(&mut self, reg: Register, value: u32) {
+        unsafe {
+            self.device.mmio(0).write(reg.offset, value);
+        }
+    }
+
+    unsafe fn read_register(&mut self, reg: Register) -> u32 {
+        unsafe { self.device.mmio(0).read(reg.offset) }
+    }
+
+    fn flag(&mut self) -> Flag {
+        Flag::from_bits_retain(unsafe { self.read_register(Register::FLAG) })
+    }
+
+    fn set_interrupt(&mut self, mask: Interrupt, enable: bool) {
+        unsafe {
+            let mut imsc =
+                Interrupt::from_bits_retain(self.read_register(Register::INTERRUPT_MASK));
+            imsc.set(mask, enable);
+            self.write_register(Register::INTERRUPT_MASK, imsc.bits());
+        }
+    }
+}
+
+impl SerialDriver for Driver {
+    fn init(&mut self, config: SerialConfig) -> Result<(), Box<dyn Error>> {
+        unsafe {
+            // disable the UART while changing the settings
+            self.write_register(Register::CONTROL, 0);
+            self.write_register(Register::INTERRUPT_MASK, 0);
+            self.write_register(Register::INTERRUPT_CLEAR, Interrupt::ALL.bits());
+
+            // the divisor is the reference clock divided by 16 times the baud
+            // rate, with a 6-bit fractional part
+            if let Some(clock) = self.clock_frequency {
+                let divisor = (u64::from(clock) * 4 + u64::from(config.baud_rate) / 2)
+                    / u64::from(config.baud_rate);
+                let integer = u32::try_from(divisor >> 6).unwrap_or(u32::MAX);
+                if integer == 0 || integer > 0xffff {
+                    return Err(format!(
+                        "unsupported baud rate {} for clock frequency {clock}",
+                        config.baud_rate
+                    )
+                    .into());
+                }
+                let fraction = u32::try_from(divisor & 0x3f).unwrap();
+                self.write_register(Register::INTEGER_BAUD_RATE, integer);
+                self.write_register(Register::FRACTIONAL_BAUD_RATE, fraction);
+            }
+
+            // writing the line control register latches the baud rate
+            let mut lcr = LineControl::FIFO_ENABLE;
+            lcr |= LineControl::from_bits_retain(u32::from(config.data_bits - 5) << 5)
+                & LineControl::WORD_LENGTH;
+            lcr.set(LineControl::TWO_STOP_BITS, config.stop_bits == 2);
+            lcr.set(LineControl::PARITY_ENABLE, config.parity != Parity::None);
+            lcr.set(LineControl::EVEN_PARITY, config.parity == Parity::Even);
+            self.write_register(Register::LINE_CONTROL, lcr.bits());
+
+            // interrupt when the TX FIFO is at most 1/2 full or the RX FIFO is
+            // at least 1/8 full
+            self.write_register(Register::INTERRUPT_FIFO_LEVEL, 0b000_010);
+
+            self.write_register(
+                Register::CONTROL,
+                (Control::UART_ENABLE | Control::TX_ENABLE | Control::RX_ENABLE).bits(),
+            );
+        }
+
+        Ok(())
+    }
+
+    fn set_tx_idle_interrupt(&mut self, enable: bool) {
+        self.set_interrupt(Interrupt::TX, enable);
+    }
+
+    fn set_rx_ready_interrupt(&mut self, enable: bool) {
+        // the timeout interrupt reports the bytes below the FIFO level
+        self.set_interrupt(Interrupt::RX | Interrupt::RX_TIMEOUT, enable);
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> usize {
+        let mut count = 0;
+        for byte in bytes {
+            if self.flag().contains(Flag::TX_FIFO_FULL) {
+                break;
+            }
+            unsafe {
+                self.write_register(Register::DATA, u32::from(*byte));
+            }
+            count += 1;
+        }
+        count
+    }
+
+    fn read(&mut self, bytes: &mut [u8]) -> usize {
+        let mut count = 0;
+        for byte in bytes {
+            if self.flag().contains(Flag::RX_FIFO_EMPTY) {
+                break;
+            }
+            // the upper bits hold the error flags of the received byte
+            *byte = unsafe { self.read_register(Register::DATA) }.to_le_bytes()[0];
+            count += 1;
+        }
+        count
+    }
+
+    fn complete(&mut self) {
+        // the TX and RX interrupts are cleared by the FIFOs leaving the trigger
+        // levels, and the others are only cleared here
+        unsafe {
+            self.write_register(Register::INTERRUPT_CLEAR, Interrupt::ALL.bits());
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use alloc::{boxed::Box, format, sync::Arc};
+use core::error::Error;
+
+use bitflags::bitflags;
+use devtree::compatible_table;
+
+use super::{Parity, SerialConfig, SerialDriver};
+use crate::drivers::{
+    device::Device,
+    registry::{ProbeContext, ProbeError},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Register {
+    offset: usize,
+}
+
+// see the SiFive FU540-C000 manual, chapter 13 "Universal Asynchronous
+// Receiver/Transmitter (UART)"
+
+impl Register {
+    /// Transmit Data Register
+    const TX_DATA: Self = Self::new(0x00);
+    /// Receive Data Register
+    const RX_DATA: Self = Self::new(0x04);
+    /// Transmit Control Register
+    const TX_CONTROL: Self = Self::new(0x08);
+    /// Receive Control Register
+    const RX_CONTROL: Self = Self::new(0x0c);
+    /// Interrupt Enable Register
+    const INTERRUPT_ENABLE: Self = Self::new(0x10);
+    /// Baud Rate Divisor Register
+    const DIVISOR: Self = Self::new(0x18);
+
+    const fn new(offset: usize) -> Self {
+        Self { offset }
+    }
+}
+
+/// Set in `TX_DATA` if the transmit FIFO is full.
+const TX_FULL: u32 = 1 << 31;
+/// Set in `RX_DATA` if the receive FIFO is empty.
+const RX_EMPTY: u32 = 1 << 31;
+
+/// Shift of the watermark level in `TX_CONTROL` and `RX_CONTROL`.
+const WATERMARK_SHIFT: u32 = 16;
+/// The transmit watermark interrupt is pending while fewer entries are in the
+/// FIFO.
+const TX_WATERMARK: u32 = 1;
+/// The receive watermark interrupt is pending while more entries are in the
+/// FIFO.
+const RX_WATERMARK: u32 = 0;
+
+bitflags! {
+    #[repr(transparent)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    struct TxControl : u32 {
+        const ENABLE = 1 << 0;
+        const TWO_STOP_BITS = 1 << 1;
+    }
+
+    struct RxControl : u32 {
+        const ENABLE = 1 << 0;
+    }
+
+    struct InterruptEnable : u32 {
+        const TX_WATERMARK = 1 << 0;
+        const RX_WATERMARK = 1 << 1;
+    }
+}
+
+driver!(DRIVER {
+    name: "sifive-uart",
+    compatibles: compatible_table!["sifive,uart0"],
+    probe,
+});
+
+fn probe(ctx: &ProbeContext<'_>) -> Result<(), ProbeError> {
+    super::probe(ctx, |device, clock_frequency| {
+        Ok(Box::new(Driver::new(device, clock_frequency)))
+    })
+}
+
+/// `sifive,uart0` UART driver.
+///
+/// The UART only supports 8 data bits without parity.
+#[derive(Debug)]
+pub(super) struct Driver {
+    device: Arc<Device>,
+    /// Frequency of the bus clock, or `None` to keep the divisor set by the
+    /// firmware.
+    clock_frequency: Option<u32>,
+}
+
+impl Driver {
+    pub(super) fn new(device: Arc<Device>, clock_frequency: Option<u32>) -> Self {
+        Self {
+            device,
+            clock_frequency,
+        }
+    }
+
+    unsafe fn write_register(&mut self, reg: Register, value: u32) {
+        unsafe {
+            self.device.mmio(0).write(reg.offset, value);
+        }
+    }
+
+    unsafe fn read_register(&mut self, reg: Register) -> u32 {
+        unsafe { self.device.mmio(0).read(reg.offset) }
+    }
+
+    fn set_interrupt(&mut self, flag: InterruptEnable, enable: bool) {
+        unsafe {
+            let mut ie =
+                InterruptEnable::from_bits_retain(self.read_register(Register::INTERRUPT_ENABLE));
+            ie.set(flag, enable);
+            self.write_register(Register::INTERRUPT_ENABLE, ie.bits());
+        }
+    }
+}
+
+impl SerialDriver for Driver {
+    fn init(&mut self, config: SerialConfig) -> Result<(), Box<dyn Error>> {
+        if config.data_bits != 8 || config.parity != Parity::None {
+            return Err(format!("unsupported line settings {config}").into());
+        }
+
+        unsafe {
+            self.write_register(Register::INTERRUPT_ENABLE, 0);
+
+            // the baud rate is the bus clock divided by the divisor plus 1
+            if let Some(clock) = self.clock_frequency {
+                let divisor = (clock + config.baud_rate / 2) / config.baud_rate;
+                if divisor == 0 {
+                    return Err(format!(
+                        "unsupported baud rate {} for clock frequency {clock}",
+                        config.baud_rate
+                    )
+                    .into());
+                }
+                self.write_register(Register::DIVISOR, divisor - 1);
+            }
+
+            let mut tx_control = TxControl::ENABLE;
+            tx_control.set(TxControl::TWO_STOP_BITS, config.stop_bits == 2);
+            self.write_register(
+                Register::TX_CONTROL,
+                tx_control.bits() | (TX_WATERMARK << WATERMARK_SHIFT),
+            );
+            self.write_register(
+                Register::RX_CONTROL,
+                RxControl::ENABLE.bits() | (RX_WATERMARK << WATERMARK_SHIFT),
+            );
+        }
+
+        Ok(())
+    }
+
+    fn set_tx_idle_interrupt(&mut self, enable: bool) {
+        self.set_interrupt(InterruptEnable::TX_WATERMARK, enable);
+    }
+
+    fn set_rx_ready_interrupt(&mut self, enable: bool) {
+        self.set_interrupt(InterruptEnable::RX_WATERMARK, enable);
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> usize {
+        let mut count = 0;
+        for byte in bytes {
+            if unsafe { self.read_register(Register::TX_DATA) } & TX_FULL != 0 {
+                break;
+            }
+            unsafe {
+                self.write_register(Register::TX_DATA, u32::from(*byte));
+            }
+            count += 1;
+        }
+        count
+    }
+
+    fn read(&mut self, bytes: &mut [u8]) -> usize {
+        let mut count = 0;
+        for byte in bytes {
+            // reading the register takes the byte from the FIFO
+            let data = unsafe { self.read_register(Register::RX_DATA) };
+            if data & RX_EMPTY != 0 {
+                break;
+            }
+            *byte = data.to_le_bytes()[0];
+            count += 1;
+        }
+        count
+    }
+
+    fn complete(&mut self) {
+        // the interrupts are pending while the watermark conditions hold
+    }
+}
@@ -0,0 +1,67 @@
+//! Test finisher device (`sifive,test0`), which exits QEMU with a status code.
+
+use devtree::{DeserializeNode, compatible_table, model::property::Reg};
+use snafu::{OptionExt as _, ResultExt as _};
+use spin::Once;
+
+use crate::{
+    drivers::registry::{ProbeContext, ProbeError},
+    error::GenericError,
+    iter::IteratorExt as _,
+    memory::kernel_space::MmioToken,
+};
+
+/// Value of the finisher register that exits with the status code in the upper
+/// 16 bits.
+const FINISHER_FAIL: u32 = 0x3333;
+/// Value of the finisher register that exits with status 0.
+const FINISHER_PASS: u32 = 0x5555;
+
+static FINISHER: Once<MmioToken> = Once::new();
+
+driver!(DRIVER {
+    name: "test-finisher",
+    compatibles: compatible_table!["sifive,test1", "sifive,test0"],
+    probe,
+});
+
+#[derive(Debug, DeserializeNode)]
+struct TestFinisherNode<'blob> {
+    #[devtree(property)]
+    reg: Reg<'blob>,
+}
+
+fn probe(ctx: &ProbeContext<'_>) -> Result<(), ProbeError> {
+    let regs = map_registers(ctx)?;
+    FINISHER.call_once(|| regs);
+    Ok(())
+}
+
+fn map_registers(ctx: &ProbeContext<'_>) -> Result<MmioToken, GenericError> {
+    let node = ctx.deserialize_node::<TestFinisherNode>()?;
+    let reg = node
+        .reg
+        .into_iter()
+        .assume_one()
+        .whatever_context("invalid 'reg' entries in test finisher node")?;
+    let regs = unsafe { ctx.map_mmio(reg.range()) }
+        .whatever_context("failed to map test finisher registers")?;
+    Ok(regs)
+}
+
+/// Exits QEMU with `status`.
+///
+/// Returns if no test finisher is bound, or if the write did not exit.
+#[cfg_attr(not(feature = "ktest"), expect(dead_code))]
+pub fn exit(status: u16) {
+    let Some(regs) = FINISHER.get() else {
+        return;
+    };
+    let value = match status {
+        0 => FINISHER_PASS,
+        _ => (u32::from(status) << 16) | FINISHER_FAIL,
+    };
+    unsafe {
+        regs.write(0, value);
+    }
+}
@@ -0,0 +1,258 @@
+use alloc::{collections::btree_map::BTreeMap, format, string::String, sync::Arc};
+
+use dataview::{Pod, PodMethods as _};
+use snafu::ResultExt as _;
+
+use super::{
+    DeviceType, VirtioDevice,
+    queue::{Buffer, VirtQueue},
+};
+use crate::{
+    block::{self, Bio, BioOp, BlockDevice, BlockError, RequestQueue},
+    error::{GenericError, MultiError},
+    memory::dma::DmaBuffer,
+    sync::spinlock::SpinMutex,
+};
+
+/// The device is read-only.
+const F_RO: u64 = 1 << 5;
+/// The device supports the cache flush command.
+const F_FLUSH: u64 = 1 << 9;
+
+const REQUEST_QUEUE_INDEX: u16 = 0;
+const REQUEST_QUEUE_SIZE: u16 = 128;
+
+const T_IN: u32 = 0;
+const T_OUT: u32 = 1;
+const T_FLUSH: u32 = 4;
+
+const S_OK: u8 = 0;
+const S_UNSUPP: u8 = 2;
+
+/// Header of the request, placed before the data buffer.
+#[derive(Debug, Clone, Copy, Pod)]
+#[repr(C)]
+struct RequestHeader {
+    req_type: u32,
+    reserved: u32,
+    sector: u64,
+}
+
+const HEADER_LEN: usize = size_of::<RequestHeader>();
+/// Offset of the status byte written by the device, after the header.
+const STATUS_OFFSET: usize = HEADER_LEN;
+
+#[derive(Debug)]
+struct InFlight {
+    bio: Arc<Bio>,
+    /// Header and status of the request, referenced by the descriptors.
+    buffers: DmaBuffer,
+}
+
+#[derive(Debug)]
+struct Inner {
+    queue: VirtQueue,
+    inflight: BTreeMap<u16, InFlight>,
+    pending: RequestQueue,
+}
+
+/// virtio-blk device driver.
+#[derive(Debug)]
+struct VirtioBlk {
+    name: String,
+    device: Arc<VirtioDevice>,
+    num_sectors: u64,
+    read_only: bool,
+    flush_supported: bool,
+    inner: SpinMutex<Inner>,
+}
+
+initcall!(
+    INITCALL,
+    drivers,
+    init,
+    after = [crate::drivers::registry::INITCALL]
+);
+
+/// Initializes the virtio block devices and registers them as `vda`, `vdb`,
+/// and so on.
+fn init() -> Result<(), GenericError> {
+    let mut errors = MultiError::new("virtio-blk devices");
+    for (i, device) in super::find_devices(DeviceType::Block).enumerate() {
+        let name = format!("vd{}", char::from(b'a' + u8::try_from(i).unwrap()));
+        if let Some(blk) = errors.record(VirtioBlk::new(name, device)) {
+            block::register(blk);
+        }
+    }
+    errors
+        .into_result()
+        .whatever_context("failed to initialize virtio-blk devices")
+}
+
+impl VirtioBlk {
+    fn new(name: String, device: Arc<VirtioDevice>) -> Result<Arc<Self>, GenericError> {
+        let (features, num_sectors, queue) = {
+            let mut transport = device.transport();
+            let features = transport.begin_init(F_RO | F_FLUSH)?;
+            // capacity, in 512-byte sectors
+            let num_sectors =
+                transport.read_config(|read| u64::from(read(0)) | (u64::from(read(4)) << 32));
+            let queue = transport.setup_queue(
+                REQUEST_QUEUE_INDEX,
+                REQUEST_QUEUE_SIZE,
+                device.coherence(),
+            )?;
+            transport.finish_init();
+            (features, num_sectors, queue)
+        };
+
+        info!(
+            "{name}: virtio-blk at {}, queue size {}",
+            device.path(),
+            queue.size()
+        );
+        let blk = Arc::new(Self {
+            name,
+            device,
+            num_sectors,
+            read_only: features & F_RO != 0,
+            flush_supported: features & F_FLUSH != 0,
+            inner: SpinMutex::new(Inner {
+                queue,
+                inflight: BTreeMap::new(),
+                pending: RequestQueue::new(),
+            }),
+        });
+        blk.device.set_handler(Arc::new({
+            let blk = Arc::clone(&blk);
+            move || blk.handle_interrupt()
+        }));
+        Ok(blk)
+    }
+
+    /// Moves the pending requests to the virtqueue while there are free
+    /// descriptors.
+    fn dispatch(&self, inner: &mut Inner) {
+        let mut added = false;
+        // header, data and status descriptors
+        while inner.queue.num_free() >= 3 {
+            let Some(bio) = inner.pending.pop() else {
+                break;
+            };
+            let (req_type, device_writable) = match bio.op() {
+                BioOp::Read => (T_IN, true),
+                BioOp::Write => (T_OUT, false),
+                BioOp::Flush => (T_FLUSH, false),
+            };
+            let mut buffers = match DmaBuffer::new(HEADER_LEN + 1, align_of::<RequestHeader>()) {
+                Ok(buffers) => buffers,
+                Err(e) => {
+                    warn!("{}: failed to allocate request: {e}", self.name);
+                    bio.complete(Err(BlockError::NoMemory));
+                    continue;
+                }
+            };
+            let header = RequestHeader {
+                req_type,
+                reserved: 0,
+                sector: bio.sector(),
+            };
+            buffers.as_mut_slice()[..HEADER_LEN].copy_from_slice(header.as_bytes());
+            buffers.as_mut_slice()[STATUS_OFFSET] = 0xff;
+            buffers.sync_for_device(0..buffers.len(), self.device.coherence());
+            let header = Buffer::readable(&buffers, 0..HEADER_LEN);
+            let status = Buffer::writable(&buffers, STATUS_OFFSET..STATUS_OFFSET + 1);
+            let data = bio.with_data(|data| {
+                data.map(|data| {
+                    data.sync_for_device(0..data.len(), self.device.coherence());
+                    if device_writable {
+                        Buffer::writable(data, 0..data.len())
+                    } else {
+                        Buffer::readable(data, 0..data.len())
+                    }
+                })
+            });
+            // the buffers are owned by `inflight` until the device returns
+            // them.
+            let res = match data {
+                Some(data) => unsafe { inner.queue.add(&[header, data, status]) },
+                None => unsafe { inner.queue.add(&[header, status]) },
+            };
+            let head = match res {
+                Ok(head) => head,
+                Err(e) => {
+                    warn!("{}: failed to queue request: {e}", self.name);
+                    bio.complete(Err(BlockError::Io));
+                    continue;
+                }
+            };
+            inner.inflight.insert(head, InFlight { bio, buffers });
+            added = true;
+        }
+        if added {
+            self.device.transport().notify(inner.queue.index());
+        }
+    }
+
+    fn handle_interrupt(&self) {
+        let mut inner = self.inner.lock();
+        while let Some(used) = inner.queue.pop_used() {
+            let Some(InFlight { bio, buffers }) = inner.inflight.remove(&used.head) else {
+                warn!("{}: unknown request {} completed", self.name, used.head);
+                continue;
+            };
+            let coherence = self.device.coherence();
+            buffers.sync_for_cpu(STATUS_OFFSET..STATUS_OFFSET + 1, coherence);
+            if bio.op() == BioOp::Read {
+                bio.with_data(|data| {
+                    if let Some(data) = data {
+                        data.sync_for_cpu(0..data.len(), coherence);
+                    }
+                });
+            }
+            let result = match buffers.as_slice()[STATUS_OFFSET] {
+                S_OK => Ok(()),
+                S_UNSUPP => Err(BlockError::Unsupported),
+                _ => Err(BlockError::Io),
+            };
+            bio.complete(result);
+        }
+        self.dispatch(&mut inner);
+    }
+}
+
+impl BlockDevice for VirtioBlk {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn num_sectors(&self) -> u64 {
+        self.num_sectors
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    fn submit(&self, bio: Arc<Bio>) -> Result<(), BlockError> {
+        if bio.op() == BioOp::Write && self.read_only {
+            return Err(BlockError::ReadOnly);
+        }
+        if bio.op() == BioOp::Flush && !self.flush_supported {
+            // no volatile write cache, nothing to flush
+            bio.complete(Ok(()));
+            return Ok(());
+        }
+        if bio.op() != BioOp::Flush {
+            let end = bio.sector().checked_add(bio.num_sectors());
+            if end.is_none_or(|end| end > self.num_sectors) {
+                return Err(BlockError::InvalidRequest);
+            }
+        }
+
+        let mut inner = self.inner.lock();
+        inner.pending.push(bio);
+        self.dispatch(&mut inner);
+        Ok(())
+    }
+}
@@ -0,0 +1,64 @@
+use alloc::sync::Arc;
+
+use devtree::{
+    DeserializeNode,
+    model::{
+        node::{Interrupt, InterruptGeneratingDevice},
+        property::Reg,
+    },
+};
+use snafu::OptionExt as _;
+
+use super::mmio::MmioTransport;
+use crate::{
+    drivers::{device::Device, registry::ProbeContext},
+    error::GenericError,
+    iter::IteratorExt as _,
+    memory::dma::Coherence,
+};
+
+#[derive(Debug, DeserializeNode)]
+struct VirtioMmioNode<'blob> {
+    #[devtree(node)]
+    device: InterruptGeneratingDevice<'blob>,
+    #[devtree(property)]
+    reg: Reg<'blob>,
+    #[devtree(property(name = "dma-noncoherent", fallback = "parent", default))]
+    dma_noncoherent: bool,
+}
+
+pub struct VirtioMmioDesc<'blob> {
+    pub device: Arc<Device>,
+    pub transport: MmioTransport,
+    pub interrupt: Interrupt<'blob>,
+    pub coherence: Coherence,
+}
+
+pub fn deserialize<'a>(ctx: &ProbeContext<'a>) -> Result<VirtioMmioDesc<'a>, GenericError> {
+    let VirtioMmioNode {
+        device: node,
+        reg,
+        dma_noncoherent,
+    } = ctx.deserialize_node()?;
+    let interrupt = node
+        .interrupts()
+        .first()
+        .cloned()
+        .whatever_context("no interrupts in virtio_mmio node")?;
+    reg.into_iter()
+        .assume_one()
+        .whatever_context("invalid 'reg' entries in virtio_mmio node")?;
+    let device = Arc::new(ctx.create_device()?);
+    let transport = MmioTransport::new(Arc::clone(&device));
+    let coherence = if dma_noncoherent {
+        Coherence::NonCoherent
+    } else {
+        Coherence::Coherent
+    };
+    Ok(VirtioMmioDesc {
+        device,
+        transport,
+        interrupt,
+        coherence,
+    })
+}
@@ -0,0 +1,441 @@
+//! virtio-gpu device driver.
+//!
+//! Only the 2D commands on the control queue are used: a host resource is
+//! backed by a guest buffer, shown on the first scanout, and the written
+//! rectangles are transferred to the host and flushed. The commands are
+//! polled for completion, as the console is drawn with interrupts disabled.
+
+use alloc::{format, string::String, sync::Arc};
+use core::{hint, time::Duration};
+
+use dataview::{Pod, PodMethods as _};
+use platform_cast::CastFrom as _;
+use snafu::{ResultExt as _, ensure_whatever, whatever};
+
+use super::{
+    DeviceType, VirtioDevice,
+    queue::{Buffer, VirtQueue},
+};
+use crate::{
+    display::{self, Display, Rect},
+    error::{GenericError, MultiError},
+    interrupt::timer::Instant,
+    memory::{PAGE_SIZE, dma::DmaBuffer},
+    sync::spinlock::SpinMutex,
+};
+
+const CONTROL_QUEUE_INDEX: u16 = 0;
+const CONTROL_QUEUE_SIZE: u16 = 8;
+
+/// Time to wait for the device to complete a command.
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(1);
+
+const CMD_GET_DISPLAY_INFO: u32 = 0x0100;
+const CMD_RESOURCE_CREATE_2D: u32 = 0x0101;
+const CMD_SET_SCANOUT: u32 = 0x0103;
+const CMD_RESOURCE_FLUSH: u32 = 0x0104;
+const CMD_TRANSFER_TO_HOST_2D: u32 = 0x0105;
+const CMD_RESOURCE_ATTACH_BACKING: u32 = 0x0106;
+
+const RESP_OK_NODATA: u32 = 0x1100;
+const RESP_OK_DISPLAY_INFO: u32 = 0x1101;
+
+const MAX_SCANOUTS: usize = 16;
+const FORMAT_B8G8R8X8_UNORM: u32 = 2;
+const BYTES_PER_PIXEL: usize = 4;
+
+const RESOURCE_ID: u32 = 1;
+const SCANOUT_ID: u32 = 0;
+
+#[derive(Debug, Clone, Copy, Pod)]
+#[repr(C)]
+struct ControlHeader {
+    cmd_type: u32,
+    flags: u32,
+    fence_id: u64,
+    ctx_id: u32,
+    ring_idx: u8,
+    padding: [u8; 3],
+}
+
+impl ControlHeader {
+    fn new(cmd_type: u32) -> Self {
+        Self {
+            cmd_type,
+            flags: 0,
+            fence_id: 0,
+            ctx_id: 0,
+            ring_idx: 0,
+            padding: [0; 3],
+        }
+    }
+
+    fn read(bytes: &[u8]) -> Self {
+        let mut header = dataview::zeroed::<Self>();
+        header
+            .as_bytes_mut()
+            .copy_from_slice(&bytes[..size_of::<Self>()]);
+        header
+    }
+}
+
+#[derive(Debug, Clone, Copy, Pod)]
+#[repr(C)]
+struct GpuRect {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+impl GpuRect {
+    /// Converts a rectangle on the display, whose size fits in `u32`.
+    fn new(rect: Rect) -> Self {
+        Self {
+            x: u32::try_from(rect.x).unwrap(),
+            y: u32::try_from(rect.y).unwrap(),
+            width: u32::try_from(rect.width).unwrap(),
+            height: u32::try_from(rect.height).unwrap(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Pod)]
+#[repr(C)]
+struct DisplayOne {
+    rect: GpuRect,
+    enabled: u32,
+    flags: u32,
+}
+
+#[derive(Debug, Clone, Copy, Pod)]
+#[repr(C)]
+struct RespDisplayInfo {
+    header: ControlHeader,
+    pmodes: [DisplayOne; MAX_SCANOUTS],
+}
+
+#[derive(Debug, Clone, Copy, Pod)]
+#[repr(C)]
+struct ResourceCreate2d {
+    header: ControlHeader,
+    resource_id: u32,
+    format: u32,
+    width: u32,
+    height: u32,
+}
+
+#[derive(Debug, Clone, Copy, Pod)]
+#[repr(C)]
+struct MemEntry {
+    addr: u64,
+    length: u32,
+    padding: u32,
+}
+
+/// `RESOURCE_ATTACH_BACKING` with a single entry, as the backing buffer is
+/// physically contiguous.
+#[derive(Debug, Clone, Copy, Pod)]
+#[repr(C)]
+struct ResourceAttachBacking {
+    header: ControlHeader,
+    resource_id: u32,
+    nr_entries: u32,
+    entry: MemEntry,
+}
+
+#[derive(Debug, Clone, Copy, Pod)]
+#[repr(C)]
+struct SetScanout {
+    header: ControlHeader,
+    rect: GpuRect,
+    scanout_id: u32,
+    resource_id: u32,
+}
+
+#[derive(Debug, Clone, Copy, Pod)]
+#[repr(C)]
+struct ResourceFlush {
+    header: ControlHeader,
+    rect: GpuRect,
+    resource_id: u32,
+    padding: u32,
+}
+
+#[derive(Debug, Clone, Copy, Pod)]
+#[repr(C)]
+struct TransferToHost2d {
+    header: ControlHeader,
+    rect: GpuRect,
+    offset: u64,
+    resource_id: u32,
+    padding: u32,
+}
+
+/// Size of the largest request.
+const REQUEST_LEN: usize = size_of::<ResourceAttachBacking>();
+/// Size of the largest response.
+const RESPONSE_LEN: usize = size_of::<RespDisplayInfo>();
+
+/// Control queue with the buffers of a request and its response.
+#[derive(Debug)]
+struct ControlQueue {
+    queue: VirtQueue,
+    request: DmaBuffer,
+    response: DmaBuffer,
+}
+
+#[derive(Debug)]
+struct Inner {
+    control: ControlQueue,
+    /// Pixels of the resource, in `B8G8R8X8` format.
+    backing: DmaBuffer,
+    /// Whether a command has failed, after which the display is not updated.
+    failed: bool,
+}
+
+/// virtio-gpu device driver, showing a resource on the first scanout.
+#[derive(Debug)]
+struct VirtioGpu {
+    name: String,
+    device: Arc<VirtioDevice>,
+    width: usize,
+    height: usize,
+    inner: SpinMutex<Inner>,
+}
+
+initcall!(
+    INITCALL,
+    drivers,
+    init,
+    after = [crate::drivers::registry::INITCALL]
+);
+
+/// Initializes the virtio GPU devices and registers them as displays.
+fn init() -> Result<(), GenericError> {
+    let mut errors = MultiError::new("virtio-gpu devices");
+    for (i, device) in super::find_devices(DeviceType::Gpu).enumerate() {
+        let name = format!("virtio-gpu{i}");
+        if let Some(gpu) = errors.record(VirtioGpu::new(name, device)) {
+            errors.record(display::register(gpu));
+        }
+    }
+    errors
+        .into_result()
+        .whatever_context("failed to initialize virtio-gpu devices")
+}
+
+impl VirtioGpu {
+    fn new(name: String, device: Arc<VirtioDevice>) -> Result<Arc<Self>, GenericError> {
+        let queue = {
+            let mut transport = device.transport();
+            transport.begin_init(0)?;
+            let queue = transport.setup_queue(
+                CONTROL_QUEUE_INDEX,
+                CONTROL_QUEUE_SIZE,
+                device.coherence(),
+            )?;
+            transport.finish_init();
+            queue
+        };
+        let mut control = ControlQueue {
+            queue,
+            request: DmaBuffer::new(REQUEST_LEN, align_of::<ControlHeader>())
+                .whatever_context("failed to allocate request buffer")?,
+            response: DmaBuffer::new(RESPONSE_LEN, align_of::<ControlHeader>())
+                .whatever_context("failed to allocate response buffer")?,
+        };
+
+        let info: RespDisplayInfo = control.command(
+            &device,
+            &ControlHeader::new(CMD_GET_DISPLAY_INFO),
+            RESP_OK_DISPLAY_INFO,
+        )?;
+        let mode = info.pmodes[usize::cast_from(SCANOUT_ID)];
+        ensure_whatever!(mode.enabled != 0, "scanout {SCANOUT_ID} is not enabled");
+        let rect = GpuRect {
+            x: 0,
+            y: 0,
+            ..mode.rect
+        };
+        let width = usize::cast_from(rect.width);
+        let height = usize::cast_from(rect.height);
+        let len = width * height * BYTES_PER_PIXEL;
+        let backing =
+            DmaBuffer::new(len, PAGE_SIZE).whatever_context("failed to allocate backing buffer")?;
+
+        let _: ControlHeader = control.command(
+            &device,
+            &ResourceCreate2d {
+                header: ControlHeader::new(CMD_RESOURCE_CREATE_2D),
+                resource_id: RESOURCE_ID,
+                format: FORMAT_B8G8R8X8_UNORM,
+                width: rect.width,
+                height: rect.height,
+            },
+            RESP_OK_NODATA,
+        )?;
+        let _: ControlHeader = control.command(
+            &device,
+            &ResourceAttachBacking {
+                header: ControlHeader::new(CMD_RESOURCE_ATTACH_BACKING),
+                resource_id: RESOURCE_ID,
+                nr_entries: 1,
+                entry: MemEntry {
+                    addr: backing.bus_addr(),
+                    length: u32::try_from(len).whatever_context("display too large")?,
+                    padding: 0,
+                },
+            },
+            RESP_OK_NODATA,
+        )?;
+        let _: ControlHeader = control.command(
+            &device,
+            &SetScanout {
+                header: ControlHeader::new(CMD_SET_SCANOUT),
+                rect,
+                scanout_id: SCANOUT_ID,
+                resource_id: RESOURCE_ID,
+            },
+            RESP_OK_NODATA,
+        )?;
+
+        info!("{name}: virtio-gpu at {}", device.path());
+        Ok(Arc::new(Self {
+            name,
+            device,
+            width,
+            height,
+            inner: SpinMutex::new(Inner {
+                control,
+                backing,
+                failed: false,
+            }),
+        }))
+    }
+
+    /// Copies `rect` of the backing buffer to the resource, and shows it.
+    fn transfer_and_flush(&self, inner: &mut Inner, rect: Rect) -> Result<(), GenericError> {
+        let offset = (rect.y * self.width + rect.x) * BYTES_PER_PIXEL;
+        let end = ((rect.y + rect.height - 1) * self.width + rect.x + rect.width) * BYTES_PER_PIXEL;
+        inner
+            .backing
+            .sync_for_device(offset..end, self.device.coherence());
+        let _: ControlHeader = inner.control.command(
+            &self.device,
+            &TransferToHost2d {
+                header: ControlHeader::new(CMD_TRANSFER_TO_HOST_2D),
+                rect: GpuRect::new(rect),
+                offset: u64::cast_from(offset),
+                resource_id: RESOURCE_ID,
+                padding: 0,
+            },
+            RESP_OK_NODATA,
+        )?;
+        let _: ControlHeader = inner.control.command(
+            &self.device,
+            &ResourceFlush {
+                header: ControlHeader::new(CMD_RESOURCE_FLUSH),
+                rect: GpuRect::new(rect),
+                resource_id: RESOURCE_ID,
+                padding: 0,
+            },
+            RESP_OK_NODATA,
+        )?;
+        Ok(())
+    }
+}
+
+impl ControlQueue {
+    /// Sends `request`, and waits for the response of `resp_type`.
+    ///
+    /// The device is reset if it does not respond in time, as the buffers
+    /// cannot be reused while the device may still write to them.
+    fn command<Req, Resp>(
+        &mut self,
+        device: &VirtioDevice,
+        request: &Req,
+        resp_type: u32,
+    ) -> Result<Resp, GenericError>
+    where
+        Req: Pod,
+        Resp: Pod,
+    {
+        let request = request.as_bytes();
+        let cmd_type = ControlHeader::read(request).cmd_type;
+        let response_len = size_of::<Resp>();
+        let coherence = device.coherence();
+        self.request.as_mut_slice()[..request.len()].copy_from_slice(request);
+        self.request.sync_for_device(0..request.len(), coherence);
+
+        // the buffers are owned by the device until it returns them
+        unsafe {
+            self.queue.add(&[
+                Buffer::readable(&self.request, 0..request.len()),
+                Buffer::writable(&self.response, 0..response_len),
+            ])?;
+        }
+        device.transport().notify(self.queue.index());
+        let start = Instant::now();
+        while self.queue.pop_used().is_none() {
+            if start.elapsed() > COMMAND_TIMEOUT {
+                device.transport().reset();
+                whatever!("command {cmd_type:#x} timed out");
+            }
+            hint::spin_loop();
+        }
+
+        self.response.sync_for_cpu(0..response_len, coherence);
+        let response = &self.response.as_slice()[..response_len];
+        let header = ControlHeader::read(response);
+        ensure_whatever!(
+            header.cmd_type == resp_type,
+            "command {cmd_type:#x} failed with {:#x}",
+            header.cmd_type
+        );
+        let mut value = dataview::zeroed::<Resp>();
+        value.as_bytes_mut().copy_from_slice(response);
+        Ok(value)
+    }
+}
+
+impl Display for VirtioGpu {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    fn write_rect(&self, rect: Rect, pixels: &[u32]) {
+        assert!(rect.is_within(self.width, self.height));
+        assert_eq!(pixels.len(), rect.width * rect.height);
+        let mut inner = self.inner.lock();
+        let backing = inner.backing.as_mut_slice();
+        for (dy, line) in pixels.chunks(rect.width).enumerate() {
+            let start = ((rect.y + dy) * self.width + rect.x) * BYTES_PER_PIXEL;
+            let dest = &mut backing[start..start + rect.width * BYTES_PER_PIXEL];
+            for (dest, &rgb) in dest
+                .as_chunks_mut::<BYTES_PER_PIXEL>()
+                .0
+                .iter_mut()
+                .zip(line)
+            {
+                // B8G8R8X8 is `0x00RRGGBB` in little endian
+                *dest = rgb.to_le_bytes();
+            }
+        }
+        inner.unlock();
+    }
+
+    fn flush(&self, rect: Rect) {
+        let mut inner = self.inner.lock();
+        // the error cannot be reported here, as the console is being written
+        // to, and the display is left as it is
+        if !inner.failed && self.transfer_and_flush(&mut inner, rect).is_err() {
+            inner.failed = true;
+        }
+        inner.unlock();
+    }
+}
@@ -0,0 +1,156 @@
+//! virtio-input device driver.
+//!
+//! The events written by the device to the event queue are reported to an
+//! input device. The status queue, which carries the LED states to the device,
+//! is not used.
+
+use alloc::{collections::btree_map::BTreeMap, format, string::String, sync::Arc, vec::Vec};
+use core::ops::Range;
+
+use dataview::PodMethods as _;
+use snafu::ResultExt as _;
+
+use super::{
+    DeviceType, VirtioDevice,
+    queue::{Buffer, VirtQueue},
+};
+use crate::{
+    error::{GenericError, MultiError},
+    input::{self, EventDevice, InputEvent},
+    memory::dma::DmaBuffer,
+    sync::spinlock::SpinMutex,
+};
+
+const EVENT_QUEUE_INDEX: u16 = 0;
+const EVENT_QUEUE_SIZE: u16 = 64;
+
+const EVENT_LEN: usize = size_of::<InputEvent>();
+
+#[derive(Debug)]
+struct EventQueue {
+    queue: VirtQueue,
+    /// Slots of the events written by the device, one per descriptor.
+    buffer: DmaBuffer,
+    /// Slots owned by the device, keyed by the descriptor head.
+    slots: BTreeMap<u16, usize>,
+}
+
+impl EventQueue {
+    fn slot_range(slot: usize) -> Range<usize> {
+        slot * EVENT_LEN..(slot + 1) * EVENT_LEN
+    }
+
+    /// Passes the slot to the device to write an event.
+    fn post(&mut self, slot: usize) -> Result<(), GenericError> {
+        // the slot is only read by the CPU, so no write-back is needed. it is
+        // owned by `slots` until the device returns it.
+        let head = unsafe {
+            self.queue
+                .add(&[Buffer::writable(&self.buffer, Self::slot_range(slot))])?
+        };
+        self.slots.insert(head, slot);
+        Ok(())
+    }
+}
+
+/// virtio-input device driver.
+#[derive(Debug)]
+struct VirtioInput {
+    name: String,
+    device: Arc<VirtioDevice>,
+    events: SpinMutex<EventQueue>,
+    input: Arc<EventDevice>,
+}
+
+initcall!(
+    INITCALL,
+    drivers,
+    init,
+    after = [crate::drivers::registry::INITCALL]
+);
+
+/// Initializes the virtio input devices and registers them as input devices.
+fn init() -> Result<(), GenericError> {
+    let mut errors = MultiError::new("virtio-input devices");
+    for (i, device) in super::find_devices(DeviceType::Input).enumerate() {
+        let name = format!("virtio-input{i}");
+        errors.record(VirtioInput::new(name, device));
+    }
+    errors
+        .into_result()
+        .whatever_context("failed to initialize virtio-input devices")
+}
+
+impl VirtioInput {
+    fn new(name: String, device: Arc<VirtioDevice>) -> Result<Arc<Self>, GenericError> {
+        let queue = {
+            let mut transport = device.transport();
+            transport.begin_init(0)?;
+            let queue =
+                transport.setup_queue(EVENT_QUEUE_INDEX, EVENT_QUEUE_SIZE, device.coherence())?;
+            transport.finish_init();
+            queue
+        };
+
+        let nslots = usize::from(queue.size());
+        let buffer = DmaBuffer::new(nslots * EVENT_LEN, align_of::<InputEvent>())
+            .whatever_context("failed to allocate event buffer")?;
+        let mut events = EventQueue {
+            queue,
+            buffer,
+            slots: BTreeMap::new(),
+        };
+        for slot in 0..nslots {
+            events.post(slot)?;
+        }
+        device.transport().notify(events.queue.index());
+
+        info!("{name}: virtio-input at {}", device.path());
+        let input = input::register();
+        let dev = Arc::new(Self {
+            name,
+            device,
+            events: SpinMutex::new(events),
+            input,
+        });
+        dev.device.set_handler(Arc::new({
+            let dev = Arc::clone(&dev);
+            move || dev.handle_interrupt()
+        }));
+        Ok(dev)
+    }
+
+    fn handle_interrupt(&self) {
+        let mut received = Vec::new();
+        {
+            let mut events = self.events.lock();
+            while let Some(used) = events.queue.pop_used() {
+                let Some(slot) = events.slots.remove(&used.head) else {
+                    warn!("{}: unknown event buffer {}", self.name, used.head);
+                    continue;
+                };
+                let range = EventQueue::slot_range(slot);
+                events
+                    .buffer
+                    .sync_for_cpu(range.clone(), self.device.coherence());
+                let mut event = dataview::zeroed::<InputEvent>();
+                event
+                    .as_bytes_mut()
+                    .copy_from_slice(&events.buffer.as_slice()[range]);
+                received.push(event);
+                if let Err(e) = events.post(slot) {
+                    warn!("{}: failed to repost event buffer: {e}", self.name);
+                }
+            }
+            if !received.is_empty() {
+                self.device.transport().notify(events.queue.index());
+            }
+        }
+
+        // the events may be typed into the console TTY, which takes its own
+        // locks
+        for event in received {
+            self.input.report(event);
+        }
+    }
+}
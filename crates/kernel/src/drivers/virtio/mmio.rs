@@ -0,0 +1,306 @@
+use alloc::sync::Arc;
+
+use bitflags::bitflags;
+use snafu::{ensure_whatever, whatever};
+
+use super::queue::VirtQueue;
+use crate::{
+    drivers::device::Device,
+    error::GenericError,
+    memory::{dma::Coherence, kernel_space::MmioToken},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Register {
+    offset: usize,
+}
+
+// the virtio-mmio (version 2) registers.
+// see <https://docs.oasis-open.org/virtio/virtio/v1.2/csd01/virtio-v1.2-csd01.html#x1-1650002>
+
+impl Register {
+    /// Magic value, `0x74726976` ("virt") (readonly)
+    const MAGIC_VALUE: Self = Self::new(0x000);
+    /// Device version number (readonly)
+    const VERSION: Self = Self::new(0x004);
+    /// Virtio subsystem device ID (readonly)
+    const DEVICE_ID: Self = Self::new(0x008);
+    /// Virtio subsystem vendor ID (readonly)
+    const VENDOR_ID: Self = Self::new(0x00c);
+    /// Flags representing features the device supports (readonly)
+    const DEVICE_FEATURES: Self = Self::new(0x010);
+    /// Device (host) features word selection (writeonly)
+    const DEVICE_FEATURES_SEL: Self = Self::new(0x014);
+    /// Flags representing device features understood and activated by the
+    /// driver (writeonly)
+    const DRIVER_FEATURES: Self = Self::new(0x020);
+    /// Activated (guest) features word selection (writeonly)
+    const DRIVER_FEATURES_SEL: Self = Self::new(0x024);
+    /// Virtual queue index (writeonly)
+    const QUEUE_SEL: Self = Self::new(0x030);
+    /// Maximum virtual queue size (readonly)
+    const QUEUE_NUM_MAX: Self = Self::new(0x034);
+    /// Virtual queue size (writeonly)
+    const QUEUE_NUM: Self = Self::new(0x038);
+    /// Virtual queue ready bit (read/write)
+    const QUEUE_READY: Self = Self::new(0x044);
+    /// Queue notifier (writeonly)
+    const QUEUE_NOTIFY: Self = Self::new(0x050);
+    /// Interrupt status (readonly)
+    const INTERRUPT_STATUS: Self = Self::new(0x060);
+    /// Interrupt acknowledge (writeonly)
+    const INTERRUPT_ACK: Self = Self::new(0x064);
+    /// Device status (read/write)
+    const STATUS: Self = Self::new(0x070);
+    /// Virtual queue's Descriptor Area 64 bit long physical address
+    /// (writeonly)
+    const QUEUE_DESC_LOW: Self = Self::new(0x080);
+    const QUEUE_DESC_HIGH: Self = Self::new(0x084);
+    /// Virtual queue's Driver Area 64 bit long physical address (writeonly)
+    const QUEUE_DRIVER_LOW: Self = Self::new(0x090);
+    const QUEUE_DRIVER_HIGH: Self = Self::new(0x094);
+    /// Virtual queue's Device Area 64 bit long physical address (writeonly)
+    const QUEUE_DEVICE_LOW: Self = Self::new(0x0a0);
+    const QUEUE_DEVICE_HIGH: Self = Self::new(0x0a4);
+    /// Configuration atomicity value (readonly)
+    const CONFIG_GENERATION: Self = Self::new(0x0fc);
+    /// Start of the device-specific configuration space (read/write)
+    const CONFIG: Self = Self::new(0x100);
+
+    const fn new(offset: usize) -> Self {
+        Self { offset }
+    }
+}
+
+const MAGIC: u32 = 0x7472_6976;
+const VERSION: u32 = 2;
+
+bitflags! {
+    #[repr(transparent)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct DeviceStatus : u32 {
+        const ACKNOWLEDGE = 1 << 0;
+        const DRIVER = 1 << 1;
+        const DRIVER_OK = 1 << 2;
+        const FEATURES_OK = 1 << 3;
+        const DEVICE_NEEDS_RESET = 1 << 6;
+        const FAILED = 1 << 7;
+    }
+
+    #[repr(transparent)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct InterruptStatus : u32 {
+        /// The device has used a buffer in at least one of the active virtual
+        /// queues.
+        const USED_BUFFER = 1 << 0;
+        /// The configuration of the device has changed.
+        const CONFIG_CHANGE = 1 << 1;
+    }
+}
+
+/// Register interface of a virtio-mmio device.
+#[derive(Debug)]
+pub struct MmioTransport {
+    device: Arc<Device>,
+}
+
+impl MmioTransport {
+    /// Creates the transport of `device`, whose first `reg` entry is the
+    /// registers.
+    pub fn new(device: Arc<Device>) -> Self {
+        Self { device }
+    }
+
+    fn regs(&self) -> &MmioToken {
+        self.device.mmio(0)
+    }
+
+    unsafe fn read_register(&self, reg: Register) -> u32 {
+        unsafe { self.regs().read(reg.offset) }
+    }
+
+    unsafe fn write_register(&mut self, reg: Register, value: u32) {
+        unsafe { self.regs().write(reg.offset, value) }
+    }
+
+    unsafe fn write_register_u64(&mut self, low: Register, high: Register, value: u64) {
+        let (low_value, high_value) = split_u64(value);
+        unsafe {
+            self.write_register(low, low_value);
+            self.write_register(high, high_value);
+        }
+    }
+
+    /// Checks the device is a virtio-mmio version 2 device.
+    ///
+    /// Returns the device ID, which is 0 for placeholder slots without a
+    /// device.
+    pub fn probe(&mut self) -> Result<u32, GenericError> {
+        let (magic, version, device_id) = unsafe {
+            (
+                self.read_register(Register::MAGIC_VALUE),
+                self.read_register(Register::VERSION),
+                self.read_register(Register::DEVICE_ID),
+            )
+        };
+        ensure_whatever!(magic == MAGIC, "invalid virtio magic value {magic:#x}");
+        ensure_whatever!(
+            version == VERSION,
+            "unsupported virtio-mmio version {version} (legacy devices are not supported)"
+        );
+        Ok(device_id)
+    }
+
+    pub fn vendor_id(&self) -> u32 {
+        unsafe { self.read_register(Register::VENDOR_ID) }
+    }
+
+    pub fn status(&self) -> DeviceStatus {
+        DeviceStatus::from_bits_retain(unsafe { self.read_register(Register::STATUS) })
+    }
+
+    fn set_status(&mut self, status: DeviceStatus) {
+        unsafe { self.write_register(Register::STATUS, status.bits()) }
+    }
+
+    fn add_status(&mut self, status: DeviceStatus) {
+        let status = self.status() | status;
+        self.set_status(status);
+    }
+
+    /// Resets the device.
+    pub fn reset(&mut self) {
+        self.set_status(DeviceStatus::empty());
+        // writing 0 initiates the reset, which completes when 0 is read back.
+        while !self.status().is_empty() {
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Resets the device and negotiates the features with it.
+    ///
+    /// `VIRTIO_F_VERSION_1` is always requested. Returns the negotiated
+    /// features, which are the intersection of the device features and
+    /// `driver_features`.
+    pub fn begin_init(&mut self, driver_features: u64) -> Result<u64, GenericError> {
+        self.reset();
+        self.add_status(DeviceStatus::ACKNOWLEDGE);
+        self.add_status(DeviceStatus::DRIVER);
+
+        let device_features = unsafe {
+            self.write_register(Register::DEVICE_FEATURES_SEL, 0);
+            let low = self.read_register(Register::DEVICE_FEATURES);
+            self.write_register(Register::DEVICE_FEATURES_SEL, 1);
+            let high = self.read_register(Register::DEVICE_FEATURES);
+            (u64::from(high) << 32) | u64::from(low)
+        };
+        let features = device_features & (driver_features | super::F_VERSION_1);
+        if features & super::F_VERSION_1 == 0 {
+            self.add_status(DeviceStatus::FAILED);
+            whatever!("device does not support VIRTIO_F_VERSION_1");
+        }
+        let (low, high) = split_u64(features);
+        unsafe {
+            self.write_register(Register::DRIVER_FEATURES_SEL, 0);
+            self.write_register(Register::DRIVER_FEATURES, low);
+            self.write_register(Register::DRIVER_FEATURES_SEL, 1);
+            self.write_register(Register::DRIVER_FEATURES, high);
+        }
+
+        self.add_status(DeviceStatus::FEATURES_OK);
+        if !self.status().contains(DeviceStatus::FEATURES_OK) {
+            self.add_status(DeviceStatus::FAILED);
+            whatever!("device rejected features {features:#x}");
+        }
+        Ok(features)
+    }
+
+    /// Marks the device as ready after the queues are set up.
+    pub fn finish_init(&mut self) {
+        self.add_status(DeviceStatus::DRIVER_OK);
+    }
+
+    /// Creates the virtual queue `index` with up to `max_size` entries and
+    /// makes it available to the device.
+    ///
+    /// The caches of the queue are maintained according to `coherence`.
+    pub fn setup_queue(
+        &mut self,
+        index: u16,
+        max_size: u16,
+        coherence: Coherence,
+    ) -> Result<VirtQueue, GenericError> {
+        unsafe {
+            self.write_register(Register::QUEUE_SEL, u32::from(index));
+        }
+        let (ready, num_max) = unsafe {
+            (
+                self.read_register(Register::QUEUE_READY),
+                self.read_register(Register::QUEUE_NUM_MAX),
+            )
+        };
+        ensure_whatever!(ready == 0, "virtqueue {index} is already in use");
+        ensure_whatever!(num_max != 0, "virtqueue {index} is not available");
+
+        let size = u16::try_from(num_max).unwrap_or(u16::MAX).min(max_size);
+        // queue sizes must be powers of 2 for split virtqueues.
+        let size = 1 << size.ilog2();
+        let queue = VirtQueue::new(index, size, coherence)?;
+
+        unsafe {
+            self.write_register(Register::QUEUE_NUM, u32::from(size));
+            self.write_register_u64(
+                Register::QUEUE_DESC_LOW,
+                Register::QUEUE_DESC_HIGH,
+                queue.desc_addr(),
+            );
+            self.write_register_u64(
+                Register::QUEUE_DRIVER_LOW,
+                Register::QUEUE_DRIVER_HIGH,
+                queue.driver_addr(),
+            );
+            self.write_register_u64(
+                Register::QUEUE_DEVICE_LOW,
+                Register::QUEUE_DEVICE_HIGH,
+                queue.device_addr(),
+            );
+            self.write_register(Register::QUEUE_READY, 1);
+        }
+        Ok(queue)
+    }
+
+    /// Notifies the device that new buffers are available in the queue.
+    pub fn notify(&mut self, queue_index: u16) {
+        unsafe { self.write_register(Register::QUEUE_NOTIFY, u32::from(queue_index)) }
+    }
+
+    /// Reads and acknowledges the pending interrupts.
+    pub fn ack_interrupt(&mut self) -> InterruptStatus {
+        let status = unsafe { self.read_register(Register::INTERRUPT_STATUS) };
+        unsafe {
+            self.write_register(Register::INTERRUPT_ACK, status);
+        }
+        InterruptStatus::from_bits_retain(status)
+    }
+
+    /// Reads the device-specific configuration space.
+    ///
+    /// `read` is retried until the configuration is read without concurrent
+    /// updates by the device.
+    #[cfg_attr(not(any(feature = "virtio-blk", feature = "net")), expect(dead_code))]
+    pub fn read_config<T>(&self, mut read: impl FnMut(&dyn Fn(usize) -> u32) -> T) -> T {
+        loop {
+            let generation = unsafe { self.read_register(Register::CONFIG_GENERATION) };
+            let value =
+                read(&|offset| unsafe { self.regs().read(Register::CONFIG.offset + offset) });
+            if generation == unsafe { self.read_register(Register::CONFIG_GENERATION) } {
+                return value;
+            }
+        }
+    }
+}
+
+#[expect(clippy::cast_possible_truncation)]
+fn split_u64(value: u64) -> (u32, u32) {
+    (value as u32, (value >> 32) as u32)
+}
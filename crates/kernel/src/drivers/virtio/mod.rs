@@ -0,0 +1,227 @@
+use alloc::{sync::Arc, vec::Vec};
+use core::fmt;
+
+use devtree::{compatible_table, types::ByteStr};
+
+pub use self::mmio::{InterruptStatus, MmioTransport};
+use crate::{
+    drivers::{
+        device::Device,
+        registry::{ProbeContext, ProbeError},
+    },
+    error::GenericError,
+    irq::IrqHandler,
+    memory::dma::{self, Coherence},
+    power::{self, ShutdownKind},
+    sync::spinlock::{SpinMutex, SpinMutexGuard},
+};
+
+#[cfg(feature = "virtio-blk")]
+pub mod blk;
+mod de;
+#[cfg(feature = "virtio-gpu")]
+pub mod gpu;
+#[cfg(feature = "virtio-input")]
+pub mod input;
+mod mmio;
+#[cfg(feature = "net")]
+pub mod net;
+pub mod queue;
+pub mod rng;
+
+/// The device complies with the virtio 1.0 or later (non-legacy) interface.
+pub const F_VERSION_1: u64 = 1 << 32;
+
+/// Virtio device type, identified by the device ID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DeviceType {
+    Network,
+    Block,
+    Console,
+    Entropy,
+    Balloon,
+    Gpu,
+    Input,
+    Other(u32),
+}
+
+impl DeviceType {
+    fn from_id(id: u32) -> Self {
+        match id {
+            1 => Self::Network,
+            2 => Self::Block,
+            3 => Self::Console,
+            4 => Self::Entropy,
+            5 => Self::Balloon,
+            16 => Self::Gpu,
+            18 => Self::Input,
+            _ => Self::Other(id),
+        }
+    }
+}
+
+impl fmt::Display for DeviceType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::Network => "network",
+            Self::Block => "block",
+            Self::Console => "console",
+            Self::Entropy => "entropy",
+            Self::Balloon => "balloon",
+            Self::Gpu => "gpu",
+            Self::Input => "input",
+            Self::Other(id) => return write!(f, "unknown({id})"),
+        };
+        f.write_str(s)
+    }
+}
+
+static VIRTIO_DEVICES: SpinMutex<Vec<Arc<VirtioDevice>>> = SpinMutex::new(Vec::new());
+
+initcall!(INITCALL, drivers, init);
+
+fn init() {
+    power::register_shutdown_hook(power::PRIORITY_DEVICE, reset_all);
+}
+
+/// Resets the probed devices, so that they stop the DMA before the system is
+/// reset.
+///
+/// The devices whose transport is locked are skipped, as this is also called
+/// in the panic handler.
+#[expect(clippy::unnecessary_wraps)]
+fn reset_all(_kind: ShutdownKind) -> Result<(), GenericError> {
+    let Some(devices) = VIRTIO_DEVICES.try_lock() else {
+        return Ok(());
+    };
+    for device in devices.iter() {
+        if let Some(mut transport) = device.transport.try_lock() {
+            transport.reset();
+        }
+    }
+    Ok(())
+}
+
+driver!(DRIVER {
+    name: "virtio-mmio",
+    compatibles: compatible_table!["virtio,mmio"],
+    probe,
+});
+
+/// Probes a virtio-mmio device.
+///
+/// Slots without a device and legacy devices are skipped. The devices are left
+/// reset until a driver initializes them.
+fn probe(ctx: &ProbeContext<'_>) -> Result<(), ProbeError> {
+    ctx.require_interrupt_parents()?;
+
+    let de::VirtioMmioDesc {
+        device,
+        mut transport,
+        interrupt,
+        coherence,
+    } = de::deserialize(ctx)?;
+    let device_id = match transport.probe() {
+        Ok(device_id) => device_id,
+        Err(e) => {
+            warn!("skipping virtio-mmio device {}: {e}", device.path());
+            return Ok(());
+        }
+    };
+    if device_id == 0 {
+        return Ok(());
+    }
+    transport.reset();
+    if coherence == Coherence::NonCoherent && !dma::has_cache_maintenance() {
+        warn!(
+            "{} is not DMA coherent, but the CPUs cannot maintain the caches",
+            device.path()
+        );
+    }
+
+    let device = Arc::new(VirtioDevice {
+        device,
+        device_type: DeviceType::from_id(device_id),
+        coherence,
+        transport: SpinMutex::new(transport),
+        handler: SpinMutex::new(None),
+    });
+    let handler = Arc::new({
+        let device = Arc::downgrade(&device);
+        move || {
+            if let Some(device) = device.upgrade() {
+                device.handle_interrupt();
+            }
+        }
+    });
+    device.device.request_irq(&interrupt, handler)?;
+
+    info!(
+        "virtio {} device found at {}, vendor={:#x}",
+        device.device_type,
+        device.path(),
+        device.transport.lock().vendor_id()
+    );
+    VIRTIO_DEVICES.lock().push(device);
+    Ok(())
+}
+
+/// Returns the probed devices of `device_type`.
+pub fn find_devices(device_type: DeviceType) -> impl Iterator<Item = Arc<VirtioDevice>> {
+    VIRTIO_DEVICES
+        .lock()
+        .iter()
+        .filter(|device| device.device_type() == device_type)
+        .cloned()
+        .collect::<Vec<_>>()
+        .into_iter()
+}
+
+/// Virtio device found on the virtio-mmio transport.
+#[derive(derive_more::Debug)]
+pub struct VirtioDevice {
+    device: Arc<Device>,
+    device_type: DeviceType,
+    coherence: Coherence,
+    transport: SpinMutex<MmioTransport>,
+    #[debug(skip)]
+    handler: SpinMutex<Option<IrqHandler>>,
+}
+
+impl VirtioDevice {
+    pub fn path(&self) -> &ByteStr {
+        self.device.path()
+    }
+
+    pub fn device_type(&self) -> DeviceType {
+        self.device_type
+    }
+
+    /// Returns whether the device snoops the CPU caches on its DMA accesses.
+    pub fn coherence(&self) -> Coherence {
+        self.coherence
+    }
+
+    /// Locks the transport, used by the device driver for initialization and
+    /// queue notifications.
+    pub fn transport(&self) -> SpinMutexGuard<'_, MmioTransport> {
+        self.transport.lock()
+    }
+
+    /// Sets the handler called in the interrupt context when the device uses
+    /// buffers or changes its configuration.
+    pub fn set_handler(&self, handler: IrqHandler) {
+        *self.handler.lock() = Some(handler);
+    }
+
+    fn handle_interrupt(&self) {
+        let status = self.transport.lock().ack_interrupt();
+        if status.contains(InterruptStatus::CONFIG_CHANGE) {
+            debug!("virtio device {} configuration changed", self.path());
+        }
+        let handler = self.handler.lock().clone();
+        if let Some(handler) = handler {
+            handler();
+        }
+    }
+}
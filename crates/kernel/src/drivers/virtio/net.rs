@@ -0,0 +1,218 @@
+use alloc::{collections::btree_map::BTreeMap, format, string::String, sync::Arc, vec::Vec};
+
+use snafu::ResultExt as _;
+use spin::Once;
+
+use super::{
+    DeviceType, VirtioDevice,
+    queue::{Buffer, VirtQueue},
+};
+use crate::{
+    error::{GenericError, MultiError},
+    memory::dma::DmaBuffer,
+    net::{self, Interface, MacAddr, NetDevice, NetError},
+    sync::spinlock::SpinMutex,
+};
+
+/// The device has the MAC address in the configuration space.
+const F_MAC: u64 = 1 << 5;
+
+const RX_QUEUE_INDEX: u16 = 0;
+const TX_QUEUE_INDEX: u16 = 1;
+const QUEUE_SIZE: u16 = 128;
+
+/// Length of `virtio_net_hdr`, placed before each frame.
+const HEADER_LEN: usize = 12;
+/// Receive buffer length, enough for the header and a 1514-byte frame.
+const RX_BUFFER_LEN: usize = 2048;
+
+/// Locally administered address used if the device has none.
+const DEFAULT_MAC: MacAddr = MacAddr([0x52, 0x54, 0x00, 0x12, 0x34, 0x56]);
+
+#[derive(Debug)]
+struct RxQueue {
+    queue: VirtQueue,
+    /// Buffers owned by the device, keyed by the descriptor head.
+    buffers: BTreeMap<u16, DmaBuffer>,
+}
+
+#[derive(Debug)]
+struct TxQueue {
+    queue: VirtQueue,
+    /// Frames being sent, keyed by the descriptor head.
+    buffers: BTreeMap<u16, DmaBuffer>,
+}
+
+/// virtio-net device driver.
+#[derive(Debug)]
+struct VirtioNet {
+    name: String,
+    device: Arc<VirtioDevice>,
+    mac: MacAddr,
+    rx: SpinMutex<RxQueue>,
+    tx: SpinMutex<TxQueue>,
+    iface: Once<Arc<Interface>>,
+}
+
+initcall!(
+    INITCALL,
+    drivers,
+    init,
+    after = [crate::drivers::registry::INITCALL]
+);
+
+/// Initializes the virtio network devices and registers them as `eth0`,
+/// `eth1`, and so on.
+fn init() -> Result<(), GenericError> {
+    let mut errors = MultiError::new("virtio-net devices");
+    for (i, device) in super::find_devices(DeviceType::Network).enumerate() {
+        let name = format!("eth{i}");
+        if let Some(dev) = errors.record(VirtioNet::new(name, device)) {
+            let iface = net::register(Arc::clone(&dev) as Arc<dyn NetDevice>);
+            dev.iface.call_once(|| iface);
+        }
+    }
+    errors
+        .into_result()
+        .whatever_context("failed to initialize virtio-net devices")
+}
+
+impl VirtioNet {
+    fn new(name: String, device: Arc<VirtioDevice>) -> Result<Arc<Self>, GenericError> {
+        let (mac, mut rx, tx) = {
+            let mut transport = device.transport();
+            let features = transport.begin_init(F_MAC)?;
+            let mac = if features & F_MAC != 0 {
+                transport.read_config(|read| {
+                    let mut mac = [0; 6];
+                    mac[..4].copy_from_slice(&read(0).to_le_bytes());
+                    mac[4..].copy_from_slice(&read(4).to_le_bytes()[..2]);
+                    MacAddr(mac)
+                })
+            } else {
+                DEFAULT_MAC
+            };
+            let rx = transport.setup_queue(RX_QUEUE_INDEX, QUEUE_SIZE, device.coherence())?;
+            let tx = transport.setup_queue(TX_QUEUE_INDEX, QUEUE_SIZE, device.coherence())?;
+            transport.finish_init();
+            (mac, rx, tx)
+        };
+
+        info!(
+            "{name}: virtio-net at {}, mac={mac}, queue size {}",
+            device.path(),
+            rx.size()
+        );
+        let mut rx_buffers = BTreeMap::new();
+        while rx.num_free() > 0 {
+            let buffer = DmaBuffer::new(RX_BUFFER_LEN, 1)
+                .whatever_context("failed to allocate receive buffer")?;
+            // the buffer is owned by `rx_buffers` until the device returns it.
+            let head = unsafe { rx.add(&[Buffer::writable(&buffer, 0..RX_BUFFER_LEN)])? };
+            rx_buffers.insert(head, buffer);
+        }
+        device.transport().notify(rx.index());
+
+        let dev = Arc::new(Self {
+            name,
+            device,
+            mac,
+            rx: SpinMutex::new(RxQueue {
+                queue: rx,
+                buffers: rx_buffers,
+            }),
+            tx: SpinMutex::new(TxQueue {
+                queue: tx,
+                buffers: BTreeMap::new(),
+            }),
+            iface: Once::new(),
+        });
+        dev.device.set_handler(Arc::new({
+            let dev = Arc::clone(&dev);
+            move || dev.handle_interrupt()
+        }));
+        Ok(dev)
+    }
+
+    /// Frees the transmitted frames.
+    fn reclaim_tx(tx: &mut TxQueue) {
+        while let Some(used) = tx.queue.pop_used() {
+            tx.buffers.remove(&used.head);
+        }
+    }
+
+    fn handle_interrupt(&self) {
+        let mut frames = Vec::new();
+        {
+            let mut rx = self.rx.lock();
+            let RxQueue { queue, buffers } = &mut *rx;
+            while let Some(used) = queue.pop_used() {
+                let Some(buffer) = buffers.remove(&used.head) else {
+                    warn!("{}: unknown receive buffer {}", self.name, used.head);
+                    continue;
+                };
+                let len = usize::min(usize::try_from(used.len).unwrap(), RX_BUFFER_LEN);
+                buffer.sync_for_cpu(0..len, self.device.coherence());
+                if len > HEADER_LEN {
+                    frames.push(buffer.as_slice()[HEADER_LEN..len].to_vec());
+                }
+                // the buffer is only read by the CPU, so no write-back is
+                // needed. it is owned by `buffers` until the device returns it.
+                match unsafe { queue.add(&[Buffer::writable(&buffer, 0..RX_BUFFER_LEN)]) } {
+                    Ok(head) => {
+                        buffers.insert(head, buffer);
+                    }
+                    Err(e) => {
+                        warn!("{}: failed to repost receive buffer: {e}", self.name);
+                    }
+                }
+            }
+            if !frames.is_empty() {
+                self.device.transport().notify(queue.index());
+            }
+        }
+        Self::reclaim_tx(&mut self.tx.lock());
+
+        // the handlers may transmit replies, so the queues must be unlocked
+        if let Some(iface) = self.iface.get() {
+            for frame in frames {
+                iface.receive(&frame);
+            }
+        }
+    }
+}
+
+impl NetDevice for VirtioNet {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn mac_addr(&self) -> MacAddr {
+        self.mac
+    }
+
+    fn transmit(&self, frame: &[u8]) -> Result<(), NetError> {
+        let mut buffer = DmaBuffer::new(HEADER_LEN + frame.len(), 1).map_err(|e| {
+            warn!("{}: failed to allocate frame: {e}", self.name);
+            NetError::NoBufferSpace
+        })?;
+        buffer.as_mut_slice()[HEADER_LEN..].copy_from_slice(frame);
+        buffer.sync_for_device(0..buffer.len(), self.device.coherence());
+
+        let mut tx = self.tx.lock();
+        Self::reclaim_tx(&mut tx);
+        if tx.queue.num_free() == 0 {
+            return Err(NetError::NoBufferSpace);
+        }
+        // the buffer is owned by `buffers` until the device returns it.
+        let head = unsafe { tx.queue.add(&[Buffer::readable(&buffer, 0..buffer.len())]) }.map_err(
+            |e| {
+                warn!("{}: failed to queue frame: {e}", self.name);
+                NetError::NoBufferSpace
+            },
+        )?;
+        tx.buffers.insert(head, buffer);
+        self.device.transport().notify(tx.queue.index());
+        Ok(())
+    }
+}
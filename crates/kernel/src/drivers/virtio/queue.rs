@@ -0,0 +1,319 @@
+use core::{
+    ops::Range,
+    sync::atomic::{self, Ordering},
+};
+
+use snafu::{ResultExt as _, ensure_whatever};
+
+use crate::{
+    error::GenericError,
+    memory::{
+        PAGE_SIZE,
+        dma::{self, Coherence, DmaBuffer},
+    },
+};
+
+/// Buffer descriptor in the descriptor table.
+#[derive(Debug, Clone, Copy)]
+#[repr(C, align(16))]
+struct Descriptor {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+impl Descriptor {
+    /// The buffer continues via the `next` field.
+    const F_NEXT: u16 = 1;
+    /// The buffer is device write-only (otherwise device read-only).
+    const F_WRITE: u16 = 2;
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct UsedElem {
+    id: u32,
+    len: u32,
+}
+
+/// Part of a [`DmaBuffer`] passed to the device, given by its bus address.
+///
+/// The caches of the part must be synchronized by the owner of the buffer.
+#[derive(Debug, Clone, Copy)]
+pub struct Buffer {
+    pub addr: u64,
+    pub len: u32,
+    /// Whether the device writes to the buffer.
+    pub device_writable: bool,
+}
+
+impl Buffer {
+    #[cfg_attr(
+        not(any(feature = "virtio-blk", feature = "virtio-gpu", feature = "net")),
+        expect(dead_code)
+    )]
+    pub fn readable(buffer: &DmaBuffer, range: Range<usize>) -> Self {
+        Self::new(buffer, range, false)
+    }
+
+    pub fn writable(buffer: &DmaBuffer, range: Range<usize>) -> Self {
+        Self::new(buffer, range, true)
+    }
+
+    fn new(buffer: &DmaBuffer, range: Range<usize>, device_writable: bool) -> Self {
+        assert!(buffer.as_slice().get(range.clone()).is_some());
+        Self {
+            addr: buffer.bus_addr() + u64::try_from(range.start).unwrap(),
+            len: u32::try_from(range.len()).unwrap(),
+            device_writable,
+        }
+    }
+}
+
+/// Buffer chain returned by the device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UsedBuffer {
+    /// Token returned by [`VirtQueue::add`].
+    pub head: u16,
+    /// Number of bytes written to the device writable buffers.
+    pub len: u32,
+}
+
+/// Split virtqueue.
+///
+/// The descriptor table, the available ring and the used ring are placed in a
+/// page-aligned DMA buffer. The used ring written by the device starts at a
+/// separate cache block, so that its maintenance does not discard the driver
+/// writes.
+#[derive(Debug)]
+pub struct VirtQueue {
+    index: u16,
+    size: u16,
+    ring: DmaBuffer,
+    coherence: Coherence,
+    avail_offset: usize,
+    used_offset: usize,
+    free_head: u16,
+    num_free: u16,
+    avail_idx: u16,
+    last_used_idx: u16,
+}
+
+impl VirtQueue {
+    pub(super) fn new(index: u16, size: u16, coherence: Coherence) -> Result<Self, GenericError> {
+        ensure_whatever!(
+            size.is_power_of_two(),
+            "invalid virtqueue size {size}, must be a power of 2"
+        );
+        let n = usize::from(size);
+        // flags, idx, ring[n], used_event
+        let avail_offset = n * size_of::<Descriptor>();
+        let avail_len = 2 + 2 + 2 * n + 2;
+        // flags, idx, ring[n], avail_event
+        let used_offset = (avail_offset + avail_len).next_multiple_of(dma::cache_block_size());
+        let used_len = 2 + 2 + size_of::<UsedElem>() * n + 2;
+        let ring = DmaBuffer::new(used_offset + used_len, PAGE_SIZE)
+            .whatever_context("failed to allocate virtqueue")?;
+
+        let queue = Self {
+            index,
+            size,
+            ring,
+            coherence,
+            avail_offset,
+            used_offset,
+            free_head: 0,
+            num_free: size,
+            avail_idx: 0,
+            last_used_idx: 0,
+        };
+        for i in 0..size {
+            unsafe {
+                queue.desc_ptr(i).write(Descriptor {
+                    addr: 0,
+                    len: 0,
+                    flags: 0,
+                    next: i.wrapping_add(1),
+                });
+            }
+        }
+        queue.sync_driver_area();
+        Ok(queue)
+    }
+
+    pub fn index(&self) -> u16 {
+        self.index
+    }
+
+    #[cfg_attr(
+        not(any(feature = "virtio-blk", feature = "virtio-input", feature = "net")),
+        expect(dead_code)
+    )]
+    pub fn size(&self) -> u16 {
+        self.size
+    }
+
+    #[cfg_attr(not(any(feature = "virtio-blk", feature = "net")), expect(dead_code))]
+    pub fn num_free(&self) -> u16 {
+        self.num_free
+    }
+
+    pub(super) fn desc_addr(&self) -> u64 {
+        self.ring.bus_addr()
+    }
+
+    pub(super) fn driver_addr(&self) -> u64 {
+        self.desc_addr() + u64::try_from(self.avail_offset).unwrap()
+    }
+
+    pub(super) fn device_addr(&self) -> u64 {
+        self.desc_addr() + u64::try_from(self.used_offset).unwrap()
+    }
+
+    // the descriptor table is at the start of the page-aligned buffer.
+    #[expect(clippy::cast_ptr_alignment)]
+    fn desc_ptr(&self, index: u16) -> *mut Descriptor {
+        assert!(index < self.size);
+        unsafe {
+            self.ring
+                .as_ptr()
+                .cast::<Descriptor>()
+                .add(usize::from(index))
+        }
+    }
+
+    fn avail_idx_offset(&self) -> usize {
+        self.avail_offset + 2
+    }
+
+    fn avail_ring_offset(&self, slot: u16) -> usize {
+        self.avail_offset + 4 + 2 * usize::from(slot % self.size)
+    }
+
+    fn used_idx_offset(&self) -> usize {
+        self.used_offset + 2
+    }
+
+    fn used_ring_offset(&self, slot: u16) -> usize {
+        self.used_offset + 4 + size_of::<UsedElem>() * usize::from(slot % self.size)
+    }
+
+    fn ptr_at<T>(&self, offset: usize) -> *mut T {
+        unsafe { self.ring.as_ptr().add(offset).cast() }
+    }
+
+    /// Writes back the descriptor table and the available ring.
+    fn sync_driver_area(&self) {
+        self.ring
+            .sync_for_device(0..self.used_offset, self.coherence);
+    }
+
+    fn sync_used(&self, offset: usize, len: usize) {
+        self.ring.sync_for_cpu(offset..offset + len, self.coherence);
+    }
+
+    /// Makes the chain of `buffers` available to the device.
+    ///
+    /// Returns the head descriptor index, which identifies the chain in
+    /// [`UsedBuffer`]. The device must be notified afterwards.
+    ///
+    /// # Safety
+    ///
+    /// The buffers must stay valid until the device returns the chain.
+    pub unsafe fn add(&mut self, buffers: &[Buffer]) -> Result<u16, GenericError> {
+        ensure_whatever!(!buffers.is_empty(), "empty buffer chain");
+        ensure_whatever!(
+            buffers.len() <= usize::from(self.num_free),
+            "virtqueue {} is full, free={}, requested={}",
+            self.index,
+            self.num_free,
+            buffers.len()
+        );
+
+        let head = self.free_head;
+        let mut last = head;
+        for (i, buffer) in buffers.iter().enumerate() {
+            let index = if i == 0 {
+                head
+            } else {
+                unsafe { (*self.desc_ptr(last)).next }
+            };
+            let desc = self.desc_ptr(index);
+            let next = unsafe { (*desc).next };
+            let mut flags = 0;
+            if buffer.device_writable {
+                flags |= Descriptor::F_WRITE;
+            }
+            if i + 1 < buffers.len() {
+                flags |= Descriptor::F_NEXT;
+            }
+            unsafe {
+                desc.write(Descriptor {
+                    addr: buffer.addr,
+                    len: buffer.len,
+                    flags,
+                    next,
+                });
+            }
+            last = index;
+        }
+        self.free_head = unsafe { (*self.desc_ptr(last)).next };
+        self.num_free -= u16::try_from(buffers.len()).unwrap();
+
+        unsafe {
+            self.ptr_at::<u16>(self.avail_ring_offset(self.avail_idx))
+                .write_volatile(head);
+        }
+        self.avail_idx = self.avail_idx.wrapping_add(1);
+        // the ring entry must be visible before the index update.
+        self.sync_driver_area();
+        atomic::fence(Ordering::SeqCst);
+        unsafe {
+            self.ptr_at::<u16>(self.avail_idx_offset())
+                .write_volatile(self.avail_idx);
+        }
+        self.ring.sync_for_device(
+            self.avail_idx_offset()..self.avail_idx_offset() + 2,
+            self.coherence,
+        );
+        atomic::fence(Ordering::SeqCst);
+        Ok(head)
+    }
+
+    /// Takes the next buffer chain returned by the device, and frees its
+    /// descriptors.
+    pub fn pop_used(&mut self) -> Option<UsedBuffer> {
+        self.sync_used(self.used_idx_offset(), 2);
+        let used_idx = unsafe { self.ptr_at::<u16>(self.used_idx_offset()).read_volatile() };
+        if used_idx == self.last_used_idx {
+            return None;
+        }
+        // read the ring entry after the index.
+        atomic::fence(Ordering::SeqCst);
+        let elem_offset = self.used_ring_offset(self.last_used_idx);
+        self.sync_used(elem_offset, size_of::<UsedElem>());
+        let elem = unsafe { self.ptr_at::<UsedElem>(elem_offset).read_volatile() };
+        self.last_used_idx = self.last_used_idx.wrapping_add(1);
+
+        let head = u16::try_from(elem.id).unwrap();
+        let mut index = head;
+        let mut freed = 1;
+        loop {
+            let desc = unsafe { &mut *self.desc_ptr(index) };
+            if desc.flags & Descriptor::F_NEXT == 0 {
+                desc.next = self.free_head;
+                break;
+            }
+            index = desc.next;
+            freed += 1;
+        }
+        self.free_head = head;
+        self.num_free += freed;
+
+        Some(UsedBuffer {
+            head,
+            len: elem.len,
+        })
+    }
+}
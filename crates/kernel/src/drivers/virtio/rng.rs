@@ -0,0 +1,137 @@
+use alloc::{format, string::String, sync::Arc};
+
+use snafu::ResultExt as _;
+
+use super::{
+    DeviceType, VirtioDevice,
+    queue::{Buffer, VirtQueue},
+};
+use crate::{
+    error::{GenericError, MultiError},
+    memory::dma::DmaBuffer,
+    rand,
+    sync::spinlock::SpinMutex,
+};
+
+const REQUEST_QUEUE_INDEX: u16 = 0;
+const REQUEST_QUEUE_SIZE: u16 = 8;
+
+/// Number of random bytes requested at once.
+const REQUEST_LEN: usize = 64;
+
+#[derive(Debug)]
+struct Inner {
+    queue: VirtQueue,
+    buffer: DmaBuffer,
+    /// Whether the buffer is owned by the device.
+    pending: bool,
+}
+
+/// virtio-rng (entropy) device driver.
+#[derive(Debug)]
+struct VirtioRng {
+    name: String,
+    device: Arc<VirtioDevice>,
+    inner: SpinMutex<Inner>,
+}
+
+initcall!(
+    INITCALL,
+    drivers,
+    init,
+    after = [crate::drivers::registry::INITCALL]
+);
+
+/// Initializes the virtio entropy devices and registers them as the entropy
+/// sources of the random number generator.
+fn init() -> Result<(), GenericError> {
+    let mut errors = MultiError::new("virtio-rng devices");
+    for (i, device) in super::find_devices(DeviceType::Entropy).enumerate() {
+        let name = format!("virtio-rng{i}");
+        if let Some(rng) = errors.record(VirtioRng::new(name, device)) {
+            rand::register_source(rng);
+        }
+    }
+    errors
+        .into_result()
+        .whatever_context("failed to initialize virtio-rng devices")
+}
+
+impl VirtioRng {
+    fn new(name: String, device: Arc<VirtioDevice>) -> Result<Arc<Self>, GenericError> {
+        let queue = {
+            let mut transport = device.transport();
+            transport.begin_init(0)?;
+            let queue = transport.setup_queue(
+                REQUEST_QUEUE_INDEX,
+                REQUEST_QUEUE_SIZE,
+                device.coherence(),
+            )?;
+            transport.finish_init();
+            queue
+        };
+
+        let buffer =
+            DmaBuffer::new(REQUEST_LEN, 1).whatever_context("failed to allocate request buffer")?;
+        info!("{name}: virtio-rng at {}", device.path());
+        let rng = Arc::new(Self {
+            name,
+            device,
+            inner: SpinMutex::new(Inner {
+                queue,
+                buffer,
+                pending: false,
+            }),
+        });
+        rng.device.set_handler(Arc::new({
+            let rng = Arc::clone(&rng);
+            move || rng.handle_interrupt()
+        }));
+        Ok(rng)
+    }
+
+    fn handle_interrupt(&self) {
+        let mut data = [0; REQUEST_LEN];
+        let mut len = 0;
+        {
+            let mut inner = self.inner.lock();
+            while let Some(used) = inner.queue.pop_used() {
+                len = usize::min(usize::try_from(used.len).unwrap(), REQUEST_LEN);
+                inner.buffer.sync_for_cpu(0..len, self.device.coherence());
+                data[..len].copy_from_slice(&inner.buffer.as_slice()[..len]);
+                inner.pending = false;
+            }
+        }
+        if len == 0 {
+            return;
+        }
+        rand::add_entropy(&data[..len]);
+        if rand::wants_entropy() {
+            rand::EntropySource::request(self);
+        }
+    }
+}
+
+impl rand::EntropySource for VirtioRng {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn request(&self) {
+        let mut inner = self.inner.lock();
+        if inner.pending {
+            return;
+        }
+        let Inner { queue, buffer, .. } = &mut *inner;
+        // the buffer is owned by the device until it returns it.
+        match unsafe { queue.add(&[Buffer::writable(buffer, 0..REQUEST_LEN)]) } {
+            Ok(_head) => {
+                inner.pending = true;
+                self.device.transport().notify(inner.queue.index());
+            }
+            Err(e) => {
+                warn!("{}: failed to request random bytes: {e}", self.name);
+            }
+        }
+    }
+}
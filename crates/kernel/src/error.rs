@@ -1,6 +1,6 @@
 use ansi_term::{Color, WithFg};
-pub use snafu_utils::GenericError;
 use snafu_utils::Report;
+pub use snafu_utils::{GenericError, MultiError};
 
 #[track_caller]
 pub fn report<E>(err: E) -> !
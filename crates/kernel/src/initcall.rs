@@ -0,0 +1,141 @@
+//! Initialization functions of the subsystems, run at boot by levels.
+//!
+//! Each subsystem registers its init function with [`initcall!`], which places
+//! an [`InitCall`] in the `.initcall.*` linker sections. The linker script
+//! sorts the sections by the level, and the boot code runs the init functions
+//! of each level with [`run`] when the kernel is ready for them. Within a
+//! level, an init function runs after the ones listed in its `after`.
+
+use alloc::{format, vec::Vec};
+use core::{ptr, slice};
+
+use snafu::{ResultExt as _, whatever};
+
+use crate::error::GenericError;
+
+/// Registers an init function of the subsystem.
+///
+/// The function returns `()` or `Result<(), GenericError>`. The registration
+/// is defined as a static named `$name`, which other registrations in the
+/// same level can list in their `after`.
+///
+/// ```ignore
+/// initcall!(INITCALL, drivers, init, after = [crate::drivers::registry::INITCALL]);
+/// ```
+macro_rules! initcall {
+    ($name:ident, early, $func:path $(, after = [$($after:path),* $(,)?])?) => {
+        initcall!(@define ".initcall.0.early", Early, $name, $func, [$($($after),*)?]);
+    };
+    ($name:ident, memory, $func:path $(, after = [$($after:path),* $(,)?])?) => {
+        initcall!(@define ".initcall.1.memory", Memory, $name, $func, [$($($after),*)?]);
+    };
+    ($name:ident, drivers, $func:path $(, after = [$($after:path),* $(,)?])?) => {
+        initcall!(@define ".initcall.2.drivers", Drivers, $name, $func, [$($($after),*)?]);
+    };
+    ($name:ident, late, $func:path $(, after = [$($after:path),* $(,)?])?) => {
+        initcall!(@define ".initcall.3.late", Late, $name, $func, [$($($after),*)?]);
+    };
+    (@define $section:literal, $level:ident, $name:ident, $func:path, [$($after:path),*]) => {
+        #[used]
+        #[unsafe(link_section = $section)]
+        pub(crate) static $name: $crate::initcall::InitCall = $crate::initcall::InitCall {
+            name: concat!(module_path!(), "::", stringify!($func)),
+            level: $crate::initcall::InitLevel::$level,
+            run: {
+                fn run() -> Result<(), $crate::error::GenericError> {
+                    $crate::initcall::InitResult::into_result($func())
+                }
+                run
+            },
+            after: &[$(&$after),*],
+        };
+    };
+}
+
+unsafe extern "C" {
+    #[link_name = "__onix_initcall_start"]
+    static INITCALL_START: usize;
+    #[link_name = "__onix_initcall_end"]
+    static INITCALL_END: usize;
+}
+
+/// Boot stage at which the init functions run, in the running order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum InitLevel {
+    /// After the boot CPU and its trap handling are set up, before the kernel
+    /// page table is applied.
+    Early,
+    /// After the kernel page table is applied, before the secondary CPUs
+    /// start.
+    Memory,
+    /// After the secondary CPUs start, for binding the devices.
+    Drivers,
+    /// After the devices are bound, for the subsystems using them.
+    Late,
+}
+
+#[derive(Debug)]
+pub struct InitCall {
+    pub name: &'static str,
+    pub level: InitLevel,
+    pub run: fn() -> Result<(), GenericError>,
+    /// Init functions of the same level that must run before this.
+    pub after: &'static [&'static Self],
+}
+
+/// Return type of an init function.
+pub trait InitResult {
+    fn into_result(self) -> Result<(), GenericError>;
+}
+
+impl InitResult for () {
+    fn into_result(self) -> Result<(), GenericError> {
+        Ok(())
+    }
+}
+
+impl InitResult for Result<(), GenericError> {
+    fn into_result(self) -> Result<(), GenericError> {
+        self
+    }
+}
+
+fn initcalls() -> &'static [InitCall] {
+    let start = (&raw const INITCALL_START).cast::<InitCall>();
+    let end = (&raw const INITCALL_END).cast::<InitCall>();
+    let len = (end.addr() - start.addr()) / size_of::<InitCall>();
+    unsafe { slice::from_raw_parts(start, len) }
+}
+
+/// Runs the init functions of the level.
+///
+/// Returns the error of the first init function that fails, without running
+/// the rest.
+pub fn run(level: InitLevel) -> Result<(), GenericError> {
+    let calls = initcalls()
+        .iter()
+        .filter(|call| call.level == level)
+        .collect::<Vec<_>>();
+    let mut done = Vec::with_capacity(calls.len());
+    while done.len() < calls.len() {
+        let ready = calls.iter().find(|call| {
+            !done.iter().any(|&d| ptr::eq(d, **call))
+                && call
+                    .after
+                    .iter()
+                    .all(|&after| after.level < level || done.iter().any(|&d| ptr::eq(d, after)))
+        });
+        let Some(&call) = ready else {
+            let blocked = calls
+                .iter()
+                .filter(|call| !done.iter().any(|&d| ptr::eq(d, **call)))
+                .map(|call| call.name)
+                .collect::<Vec<_>>();
+            whatever!("unsatisfiable init order in {level:?} level: {blocked:?}");
+        };
+        debug!("initcall {}", call.name);
+        (call.run)().with_whatever_context(|_| format!("initcall {} failed", call.name))?;
+        done.push(call);
+    }
+    Ok(())
+}
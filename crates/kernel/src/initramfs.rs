@@ -0,0 +1,141 @@
+use alloc::{collections::btree_map::BTreeMap, format, string::String};
+use core::{
+    ops::{Bound, Range},
+    ptr, slice,
+};
+
+use cpio::{Archive, FileType};
+use snafu::ResultExt as _;
+use spin::Once;
+
+use crate::{chosen, error::GenericError};
+
+/// File or directory stored in the initramfs.
+#[derive(Debug)]
+pub struct Node {
+    ino: u32,
+    file_type: FileType,
+    mode: u32,
+    data: &'static [u8],
+}
+
+impl Node {
+    const ROOT: Self = Self {
+        ino: 0,
+        file_type: FileType::Directory,
+        mode: 0o040_755,
+        data: &[],
+    };
+
+    pub fn ino(&self) -> u32 {
+        self.ino
+    }
+
+    pub fn file_type(&self) -> FileType {
+        self.file_type
+    }
+
+    pub fn mode(&self) -> u32 {
+        self.mode
+    }
+
+    pub fn data(&self) -> &'static [u8] {
+        self.data
+    }
+}
+
+/// Read-only in-memory filesystem unpacked from the initrd.
+///
+/// Files refer to the initrd memory directly, which is never freed.
+#[derive(Debug)]
+struct Initramfs {
+    // absolute path (e.g. `/bin/init`) to node, the root is stored as `/`
+    nodes: BTreeMap<String, Node>,
+}
+
+static INITRAMFS: Once<Initramfs> = Once::new();
+
+initcall!(INITCALL, memory, init);
+
+fn init() -> Result<(), GenericError> {
+    let Some(range) = chosen::initrd_range() else {
+        info!("no initramfs found");
+        INITRAMFS.call_once(|| Initramfs {
+            nodes: BTreeMap::from([("/".into(), Node::ROOT)]),
+        });
+        return Ok(());
+    };
+
+    let initramfs = unpack(range.clone())
+        .with_whatever_context(|_| format!("failed to unpack initramfs at {range:#x?}"))?;
+    info!(
+        "initramfs: {} entries at {range:#x?}",
+        initramfs.nodes.len()
+    );
+    INITRAMFS.call_once(|| initramfs);
+    Ok(())
+}
+
+fn unpack(range: Range<usize>) -> Result<Initramfs, GenericError> {
+    // the initrd is excluded from the heap and identity mapped as read-only.
+    let data: &'static [u8] =
+        unsafe { slice::from_raw_parts(ptr::with_exposed_provenance(range.start), range.len()) };
+
+    let mut nodes = BTreeMap::from([("/".into(), Node::ROOT)]);
+    for entry in Archive::new(data).entries() {
+        let entry = entry.whatever_context("invalid CPIO archive")?;
+        let path = normalize_path(entry.name());
+        nodes.insert(
+            path,
+            Node {
+                ino: entry.ino(),
+                file_type: entry.file_type(),
+                mode: entry.mode(),
+                data: entry.data(),
+            },
+        );
+    }
+    Ok(Initramfs { nodes })
+}
+
+/// Converts an archive entry name (e.g. `./bin/init`) into an absolute path.
+fn normalize_path(name: &str) -> String {
+    let mut path = String::from("/");
+    for component in name.split('/') {
+        if component.is_empty() || component == "." {
+            continue;
+        }
+        if path.len() > 1 {
+            path.push('/');
+        }
+        path.push_str(component);
+    }
+    path
+}
+
+fn get() -> &'static Initramfs {
+    INITRAMFS.get().expect("initramfs is not initialized")
+}
+
+/// Returns the node at the absolute `path`.
+pub fn lookup(path: &str) -> Option<&'static Node> {
+    get().nodes.get(normalize_path(path).as_str())
+}
+
+/// Returns the names and nodes of the direct children of the directory at
+/// `path`.
+pub fn read_dir(path: &str) -> impl Iterator<Item = (&'static str, &'static Node)> {
+    let mut prefix = normalize_path(path);
+    if prefix.len() > 1 {
+        prefix.push('/');
+    }
+    let prefix_len = prefix.len();
+    get()
+        .nodes
+        .range::<str, _>((Bound::Included(prefix.as_str()), Bound::Unbounded))
+        .take_while(move |(path, _node)| path.starts_with(prefix.as_str()))
+        .filter_map(move |(path, node)| {
+            let name = &path[prefix_len..];
+            (!name.is_empty() && !name.contains('/')).then_some((name, node))
+        })
+}
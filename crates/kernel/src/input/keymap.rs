@@ -0,0 +1,193 @@
+//! Translation of the key events into the bytes typed on a terminal.
+//!
+//! The keys are mapped with the US layout. Ctrl turns the letters and
+//! `@[\]^_` into the control characters, and the cursor keys are sent as the
+//! VT100 escape sequences. The other keys are ignored.
+
+use alloc::vec::Vec;
+
+use bitflags::bitflags;
+
+use super::{EV_KEY, InputEvent};
+
+const KEY_LEFTCTRL: u16 = 29;
+const KEY_LEFTSHIFT: u16 = 42;
+const KEY_RIGHTSHIFT: u16 = 54;
+const KEY_CAPSLOCK: u16 = 58;
+const KEY_RIGHTCTRL: u16 = 97;
+const KEY_HOME: u16 = 102;
+const KEY_UP: u16 = 103;
+const KEY_LEFT: u16 = 105;
+const KEY_RIGHT: u16 = 106;
+const KEY_END: u16 = 107;
+const KEY_DOWN: u16 = 108;
+const KEY_DELETE: u16 = 111;
+
+const RELEASED: u32 = 0;
+const PRESSED: u32 = 1;
+
+/// Characters of the keys indexed by the key code, or zero for the keys
+/// without one.
+const PLAIN: &[u8; 58] =
+    b"\0\x1b1234567890-=\x7f\tqwertyuiop[]\r\0asdfghjkl;'`\0\\zxcvbnm,./\0*\0 ";
+/// Characters of the keys with Shift.
+const SHIFTED: &[u8; 58] =
+    b"\0\x1b!@#$%^&*()_+\x7f\tQWERTYUIOP{}\r\0ASDFGHJKL:\"~\0|ZXCVBNM<>?\0*\0 ";
+
+bitflags! {
+    /// Modifier keys held down.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    struct Modifiers: u8 {
+        const LEFT_SHIFT = 1 << 0;
+        const RIGHT_SHIFT = 1 << 1;
+        const LEFT_CTRL = 1 << 2;
+        const RIGHT_CTRL = 1 << 3;
+
+        const SHIFT = Self::LEFT_SHIFT.bits() | Self::RIGHT_SHIFT.bits();
+        const CTRL = Self::LEFT_CTRL.bits() | Self::RIGHT_CTRL.bits();
+    }
+}
+
+/// State of the modifier keys of a keyboard.
+#[derive(Debug)]
+pub(super) struct Keyboard {
+    modifiers: Modifiers,
+    caps_lock: bool,
+}
+
+impl Keyboard {
+    pub(super) fn new() -> Self {
+        Self {
+            modifiers: Modifiers::empty(),
+            caps_lock: false,
+        }
+    }
+
+    /// Applies `event`, appending the bytes typed by it to `bytes`.
+    pub(super) fn feed(&mut self, event: InputEvent, bytes: &mut Vec<u8>) {
+        if event.event_type != EV_KEY {
+            return;
+        }
+        let pressed = event.value != RELEASED;
+        match event.code {
+            KEY_LEFTSHIFT => self.modifiers.set(Modifiers::LEFT_SHIFT, pressed),
+            KEY_RIGHTSHIFT => self.modifiers.set(Modifiers::RIGHT_SHIFT, pressed),
+            KEY_LEFTCTRL => self.modifiers.set(Modifiers::LEFT_CTRL, pressed),
+            KEY_RIGHTCTRL => self.modifiers.set(Modifiers::RIGHT_CTRL, pressed),
+            // auto-repeat does not toggle it
+            KEY_CAPSLOCK if event.value == PRESSED => self.caps_lock = !self.caps_lock,
+            _ if pressed => self.type_key(event.code, bytes),
+            _ => {}
+        }
+    }
+
+    fn type_key(&self, code: u16, bytes: &mut Vec<u8>) {
+        let sequence: &[u8] = match code {
+            KEY_UP => b"\x1b[A",
+            KEY_DOWN => b"\x1b[B",
+            KEY_RIGHT => b"\x1b[C",
+            KEY_LEFT => b"\x1b[D",
+            KEY_HOME => b"\x1b[H",
+            KEY_END => b"\x1b[F",
+            KEY_DELETE => b"\x1b[3~",
+            _ => &[],
+        };
+        if !sequence.is_empty() {
+            bytes.extend_from_slice(sequence);
+            return;
+        }
+
+        let Some(&plain) = PLAIN.get(usize::from(code)) else {
+            return;
+        };
+        let shift = self.modifiers.intersects(Modifiers::SHIFT);
+        let mut ch = if shift ^ (self.caps_lock && plain.is_ascii_lowercase()) {
+            SHIFTED[usize::from(code)]
+        } else {
+            plain
+        };
+        if ch == 0 {
+            return;
+        }
+        if self.modifiers.intersects(Modifiers::CTRL) && matches!(ch, b'@'..=b'_' | b'a'..=b'z') {
+            ch &= 0x1f;
+        }
+        bytes.push(ch);
+    }
+}
+
+#[cfg(feature = "ktest")]
+pub mod ktests {
+    use alloc::vec::Vec;
+
+    use snafu::ensure_whatever;
+
+    use super::{
+        super::{EV_SYN, InputEvent},
+        EV_KEY, KEY_CAPSLOCK, KEY_LEFTCTRL, KEY_LEFTSHIFT, KEY_UP, Keyboard, PRESSED, RELEASED,
+    };
+    use crate::{error::GenericError, ktest::KernelTest};
+
+    pub static TESTS: &[KernelTest] = kernel_tests![shift_and_caps_lock, ctrl_and_cursor_keys];
+
+    const KEY_C: u16 = 46;
+    const KEY_1: u16 = 2;
+
+    fn key(code: u16, value: u32) -> InputEvent {
+        InputEvent {
+            event_type: EV_KEY,
+            code,
+            value,
+        }
+    }
+
+    fn feed_all(keyboard: &mut Keyboard, events: &[InputEvent]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for &event in events {
+            keyboard.feed(event, &mut bytes);
+        }
+        bytes
+    }
+
+    fn shift_and_caps_lock() -> Result<(), GenericError> {
+        let mut keyboard = Keyboard::new();
+        let bytes = feed_all(
+            &mut keyboard,
+            &[
+                key(KEY_C, PRESSED),
+                key(KEY_C, RELEASED),
+                key(KEY_LEFTSHIFT, PRESSED),
+                key(KEY_C, PRESSED),
+                key(KEY_1, PRESSED),
+                key(KEY_LEFTSHIFT, RELEASED),
+                key(KEY_CAPSLOCK, PRESSED),
+                key(KEY_CAPSLOCK, RELEASED),
+                key(KEY_C, PRESSED),
+                key(KEY_1, PRESSED),
+                InputEvent {
+                    event_type: EV_SYN,
+                    code: 0,
+                    value: 0,
+                },
+            ],
+        );
+        ensure_whatever!(bytes == b"cC!C1", "unexpected bytes: {bytes:?}");
+        Ok(())
+    }
+
+    fn ctrl_and_cursor_keys() -> Result<(), GenericError> {
+        let mut keyboard = Keyboard::new();
+        let bytes = feed_all(
+            &mut keyboard,
+            &[
+                key(KEY_LEFTCTRL, PRESSED),
+                key(KEY_C, PRESSED),
+                key(KEY_C, 2),
+                key(KEY_LEFTCTRL, RELEASED),
+                key(KEY_UP, PRESSED),
+            ],
+        );
+        ensure_whatever!(bytes == b"\x03\x03\x1b[A", "unexpected bytes: {bytes:?}");
+        Ok(())
+    }
+}
@@ -0,0 +1,111 @@
+//! Input devices, such as keyboards.
+//!
+//! An input driver registers an [`EventDevice`] and reports the events of the
+//! device to it. The events are kept for the readers of the device file, and
+//! the key presses are typed into the console TTY, so that a keyboard can be
+//! used in place of the serial console input.
+
+use alloc::{collections::vec_deque::VecDeque, sync::Arc, vec::Vec};
+
+use dataview::{Pod, PodMethods as _};
+
+use self::keymap::Keyboard;
+use crate::{
+    sync::{channel::Notifier, spinlock::SpinMutex},
+    tty,
+};
+
+pub mod keymap;
+
+/// Separates the groups of the events reported at the same time.
+#[cfg_attr(not(feature = "ktest"), expect(dead_code))]
+pub const EV_SYN: u16 = 0x00;
+/// Key press (value 1), release (value 0), or auto-repeat (value 2).
+pub const EV_KEY: u16 = 0x01;
+
+/// Number of the events kept for the readers; the oldest events are dropped
+/// when it is exceeded.
+const MAX_PENDING_EVENTS: usize = 256;
+
+static DEVICES: SpinMutex<Vec<Arc<EventDevice>>> = SpinMutex::new(Vec::new());
+
+/// Event reported by an input device, with the evdev event types and codes.
+///
+/// The layout is that of `virtio_input_event`, which is also the format read
+/// from the device files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Pod)]
+#[repr(C)]
+pub struct InputEvent {
+    pub event_type: u16,
+    pub code: u16,
+    pub value: u32,
+}
+
+/// Input device reporting evdev events.
+#[derive(Debug)]
+pub struct EventDevice {
+    /// Events not read yet, pushed by the interrupt handler of the driver.
+    events: SpinMutex<VecDeque<InputEvent>>,
+    ready: Notifier,
+    keyboard: SpinMutex<Keyboard>,
+}
+
+/// Registers an input device, to which the driver reports the events.
+pub fn register() -> Arc<EventDevice> {
+    let device = Arc::new(EventDevice {
+        events: SpinMutex::new(VecDeque::new()),
+        ready: Notifier::new(),
+        keyboard: SpinMutex::new(Keyboard::new()),
+    });
+    DEVICES.lock().push(Arc::clone(&device));
+    device
+}
+
+/// Returns all input devices, in the registration order.
+pub fn devices() -> Vec<Arc<EventDevice>> {
+    DEVICES.lock().clone()
+}
+
+impl EventDevice {
+    /// Reports `event`, and types the pressed key into the console TTY.
+    pub fn report(&self, event: InputEvent) {
+        let mut events = self.events.lock();
+        if events.len() == MAX_PENDING_EVENTS {
+            events.pop_front();
+        }
+        events.push_back(event);
+        events.unlock();
+        self.ready.notify_all();
+
+        let mut bytes = Vec::new();
+        self.keyboard.lock().feed(event, &mut bytes);
+        if !bytes.is_empty()
+            && let Some(console) = tty::console()
+        {
+            console.receive_input(&bytes);
+        }
+    }
+
+    /// Reads the events as [`InputEvent`]s, waiting until at least one event
+    /// is reported.
+    ///
+    /// Only whole events are read, and nothing is read if `bytes` is shorter
+    /// than an event.
+    pub fn read(&self, bytes: &mut [u8]) -> usize {
+        let max_events = bytes.len() / size_of::<InputEvent>();
+        if max_events == 0 {
+            return 0;
+        }
+
+        self.ready.wait_until(|| {
+            let mut events = self.events.lock();
+            let nevents = usize::min(max_events, events.len());
+            let (chunks, _rest) = bytes.as_chunks_mut::<{ size_of::<InputEvent>() }>();
+            for (chunk, event) in chunks.iter_mut().zip(events.drain(..nevents)) {
+                chunk.copy_from_slice(event.as_bytes());
+            }
+            events.unlock();
+            (nevents > 0).then_some(nevents * size_of::<InputEvent>())
+        })
+    }
+}
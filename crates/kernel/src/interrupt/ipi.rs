@@ -0,0 +1,43 @@
+use alloc::sync::Arc;
+
+use sbi::{HartMask, SbiError, ipi};
+use snafu::OptionExt as _;
+
+use crate::{
+    cpu::{self, Cpuid},
+    drivers::irq::cpu_intc,
+    error::GenericError,
+    interrupt::timer,
+    irq::IrqLine,
+};
+
+pub fn start() -> Result<(), GenericError> {
+    let cpu = cpu::current();
+    let intc = cpu_intc::find_cpu_intc_for_cpu(cpu.id())
+        .whatever_context("no interrupt controller for current CPU")?;
+    let line = IrqLine::request(
+        intc,
+        cpu_intc::SUPERVISOR_SOFT,
+        "ipi",
+        Arc::new(handle_interrupt),
+    )?;
+    line.enable();
+    line.leak();
+    Ok(())
+}
+
+/// Sends a supervisor software interrupt to the given CPU.
+///
+/// The receiving CPU re-evaluates its timer tick and, if idle, returns to the
+/// scheduler loop to pick up newly runnable tasks.
+pub fn send_reschedule(cpuid: Cpuid) -> Result<(), SbiError> {
+    ipi::send_ipi(HartMask::from_hart(cpuid.value()))
+}
+
+fn handle_interrupt() {
+    assert!(!super::is_enabled());
+    unsafe {
+        riscv::register::sip::clear_ssoft();
+    }
+    timer::restart_tick();
+}
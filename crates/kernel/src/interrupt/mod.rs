@@ -7,6 +7,7 @@ use core::{
 use crate::cpu::{self, Cpuid};
 
 mod imp;
+pub mod ipi;
 pub mod timer;
 pub mod trap;
 
@@ -1,11 +1,12 @@
 use core::{
     fmt,
-    ops::{Add, Sub},
+    ops::{Add, AddAssign, Sub, SubAssign},
     time::Duration,
 };
 
 const NANOS_PER_SEC: u64 = 1_000_000_000;
 
+/// Point in time measured by the timer, since the timer started.
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Instant(Duration);
 
@@ -31,38 +32,213 @@ impl Instant {
         self.duration_since(Self::ZERO)
     }
 
+    /// Returns the time elapsed from `earlier` to `self`, or zero if `earlier`
+    /// is later.
     pub fn duration_since(&self, earlier: Self) -> Duration {
-        self.0 - earlier.0
+        self.saturating_duration_since(earlier)
     }
 
+    #[cfg_attr(not(all(feature = "net", feature = "shell")), expect(dead_code))]
+    pub fn checked_duration_since(&self, earlier: Self) -> Option<Duration> {
+        self.0.checked_sub(earlier.0)
+    }
+
+    pub fn saturating_duration_since(&self, earlier: Self) -> Duration {
+        self.0.saturating_sub(earlier.0)
+    }
+
+    pub fn checked_add(&self, duration: Duration) -> Option<Self> {
+        self.0.checked_add(duration).map(Self)
+    }
+
+    pub fn checked_sub(&self, duration: Duration) -> Option<Self> {
+        self.0.checked_sub(duration).map(Self)
+    }
+
+    /// Returns `self + duration`, or [`Instant::MAX`] on overflow.
+    pub fn saturating_add(&self, duration: Duration) -> Self {
+        Self(self.0.saturating_add(duration))
+    }
+
+    /// Returns `self - duration`, or [`Instant::ZERO`] on underflow.
+    #[cfg_attr(not(feature = "ktest"), expect(dead_code))]
+    pub fn saturating_sub(&self, duration: Duration) -> Self {
+        Self(self.0.saturating_sub(duration))
+    }
+
+    /// Converts the timer ticks counted at `timer_frequency` Hz.
+    ///
+    /// The time is rounded down to nanoseconds.
     pub fn from_timer_ticks(timer_ticks: u64, timer_frequency: u64) -> Self {
         let sec = timer_ticks / timer_frequency;
         let subsec = timer_ticks % timer_frequency;
-        let subsec_nanos = (subsec * NANOS_PER_SEC / timer_frequency)
-            .try_into()
-            .unwrap();
-        Self(Duration::new(sec, subsec_nanos))
+        let subsec_nanos =
+            u128::from(subsec) * u128::from(NANOS_PER_SEC) / u128::from(timer_frequency);
+        Self(Duration::new(sec, subsec_nanos.try_into().unwrap()))
     }
 
+    /// Converts to the timer ticks counted at `timer_frequency` Hz.
+    ///
+    /// The ticks are rounded up, so that a deadline armed in ticks does not
+    /// fire before the instant, and saturate at `u64::MAX`. Converting the
+    /// ticks back by [`Instant::from_timer_ticks`] gives the same ticks if
+    /// the frequency is at most 1 GHz.
     pub fn as_timer_ticks(&self, timer_frequency: u64) -> u64 {
         let sec = self.0.as_secs();
-        let subsec_nanos = self.0.subsec_nanos();
-        sec * timer_frequency + u64::from(subsec_nanos) * timer_frequency / NANOS_PER_SEC
+        let subsec_nanos = u64::from(self.0.subsec_nanos());
+        let subsec_ticks = (u128::from(subsec_nanos) * u128::from(timer_frequency))
+            .div_ceil(u128::from(NANOS_PER_SEC));
+        sec.saturating_mul(timer_frequency)
+            .saturating_add(subsec_ticks.try_into().unwrap_or(u64::MAX))
     }
 }
 
 impl Add<Duration> for Instant {
     type Output = Self;
 
+    /// # Panics
+    ///
+    /// Panics on overflow. Use [`Instant::checked_add`] or
+    /// [`Instant::saturating_add`] for deadlines that may overflow.
     fn add(self, rhs: Duration) -> Self::Output {
-        Self(self.0 + rhs)
+        self.checked_add(rhs)
+            .expect("overflow when adding duration to instant")
+    }
+}
+
+impl AddAssign<Duration> for Instant {
+    fn add_assign(&mut self, rhs: Duration) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub<Duration> for Instant {
+    type Output = Self;
+
+    /// # Panics
+    ///
+    /// Panics on underflow.
+    fn sub(self, rhs: Duration) -> Self::Output {
+        self.checked_sub(rhs)
+            .expect("overflow when subtracting duration from instant")
+    }
+}
+
+impl SubAssign<Duration> for Instant {
+    fn sub_assign(&mut self, rhs: Duration) {
+        *self = *self - rhs;
     }
 }
 
 impl Sub<Self> for Instant {
     type Output = Duration;
 
+    /// Returns the time elapsed from `rhs` to `self`, or zero if `rhs` is
+    /// later.
     fn sub(self, rhs: Self) -> Self::Output {
         self.duration_since(rhs)
     }
 }
+
+#[cfg(feature = "ktest")]
+pub mod ktests {
+    use snafu::ensure_whatever;
+
+    use super::{Duration, Instant, NANOS_PER_SEC};
+    use crate::{error::GenericError, ktest::KernelTest};
+
+    pub static TESTS: &[KernelTest] = kernel_tests![
+        ticks_round_trip,
+        ticks_round_up,
+        ticks_saturate,
+        arithmetic_checks_overflow,
+    ];
+
+    /// Frequencies of the common timers, and of one not dividing a second.
+    const FREQUENCIES: [u64; 6] = [
+        32_768,
+        1_000_000,
+        3_000_000,
+        10_000_000,
+        24_000_000,
+        NANOS_PER_SEC,
+    ];
+
+    fn ticks_round_trip() -> Result<(), GenericError> {
+        for frequency in FREQUENCIES {
+            for ticks in (0..1000).chain([frequency - 1, frequency, frequency + 1, u64::MAX / 2]) {
+                let instant = Instant::from_timer_ticks(ticks, frequency);
+                let round_trip = instant.as_timer_ticks(frequency);
+                ensure_whatever!(
+                    round_trip == ticks,
+                    "{ticks} ticks at {frequency} Hz converted back to {round_trip} ticks"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn ticks_round_up() -> Result<(), GenericError> {
+        for frequency in FREQUENCIES {
+            for nanos in [1, 999, 1_000, 30_517, 30_518, 333_333, 999_999_999] {
+                let instant = Instant::ZERO + Duration::from_nanos(nanos);
+                let ticks = instant.as_timer_ticks(frequency);
+                let fired = Instant::from_timer_ticks(ticks, frequency);
+                ensure_whatever!(
+                    fired >= instant,
+                    "{instant:?} armed at {ticks} ticks at {frequency} Hz fires at {fired:?}"
+                );
+                let early = Instant::from_timer_ticks(ticks - 1, frequency);
+                ensure_whatever!(
+                    early < instant,
+                    "{instant:?} armed at {ticks} ticks at {frequency} Hz, not the earliest"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn ticks_saturate() -> Result<(), GenericError> {
+        for frequency in FREQUENCIES {
+            let ticks = Instant::MAX.as_timer_ticks(frequency);
+            ensure_whatever!(
+                ticks == u64::MAX,
+                "MAX converted to {ticks} ticks at {frequency} Hz"
+            );
+        }
+        Ok(())
+    }
+
+    fn arithmetic_checks_overflow() -> Result<(), GenericError> {
+        let instant = Instant::ZERO + Duration::from_secs(1);
+        ensure_whatever!(
+            instant.checked_add(Duration::MAX).is_none(),
+            "overflow not detected"
+        );
+        ensure_whatever!(
+            instant.checked_sub(Duration::from_secs(2)).is_none(),
+            "underflow not detected"
+        );
+        ensure_whatever!(
+            instant.saturating_add(Duration::MAX) == Instant::MAX,
+            "addition not saturated"
+        );
+        ensure_whatever!(
+            instant.saturating_sub(Duration::from_secs(2)) == Instant::ZERO,
+            "subtraction not saturated"
+        );
+        ensure_whatever!(
+            Instant::ZERO.duration_since(instant) == Duration::ZERO,
+            "duration since a later instant not zero"
+        );
+        let mut moved = instant;
+        moved += Duration::from_millis(1500);
+        moved -= Duration::from_millis(500);
+        ensure_whatever!(
+            moved - instant == Duration::from_secs(1),
+            "moved by {:?}",
+            moved - instant
+        );
+        Ok(())
+    }
+}
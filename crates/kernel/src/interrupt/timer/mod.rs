@@ -1,21 +1,48 @@
 use alloc::{
-    collections::binary_heap::BinaryHeap,
     sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::{
+    arch::asm,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::Duration,
 };
-use core::{arch::asm, cmp, time::Duration};
 
 use riscv::register::scounteren;
+use snafu::OptionExt as _;
 
 pub use self::instant::Instant;
+#[cfg(feature = "ktest")]
+pub use self::instant::ktests as instant_ktests;
+use self::wheel::TimerWheel;
+#[cfg(feature = "ktest")]
+pub use self::wheel::ktests as wheel_ktests;
 use super::super::cpu;
 use crate::{
-    sync::spinlock::SpinMutex,
-    task::{self, Task, TaskId, scheduler},
+    cpu::hotplug::{self, HotplugHook},
+    drivers::irq::cpu_intc,
+    error::GenericError,
+    irq::IrqLine,
+    sync::spinlock::IrqSpinMutex,
+    task::{self, Task, scheduler},
+    tunables::{self, Tunable},
 };
 
 mod instant;
+pub mod watchdog;
+mod wheel;
+
+/// Interval of the scheduler ticks preempting the running task.
+pub static SCHED_SLICE_MS: Tunable<u64> = Tunable::new(
+    "sched.slice_ms",
+    "time slice of the scheduler in milliseconds",
+    100,
+)
+.with_check(tunables::non_zero);
 
-const SCHEDULER_INTERVAL: Duration = Duration::from_millis(100);
+/// Number of the events the queue of each CPU has room for, so that queueing
+/// them in the timer interrupt does not allocate memory.
+const RESERVED_EVENTS: usize = 256;
 
 cpu_local! {
     static TIMER_QUEUE: TimerState = TimerState::new();
@@ -23,62 +50,115 @@ cpu_local! {
 
 #[derive(Debug)]
 struct TimerState {
-    queue: SpinMutex<BinaryHeap<Event>>,
+    queue: IrqSpinMutex<TimerWheel<EventKind>>,
+    /// Whether a [`EventKind::Tick`] event is queued.
+    ///
+    /// The scheduler tick is stopped while there is nothing to preempt, and
+    /// `stimecmp` is armed only for the next wakeup or watchdog deadline.
+    tick_active: AtomicBool,
+    stats: AtomicTimerStats,
 }
 
 impl TimerState {
     const fn new() -> Self {
         Self {
-            queue: SpinMutex::new(BinaryHeap::new()),
+            queue: IrqSpinMutex::new(TimerWheel::new()),
+            tick_active: AtomicBool::new(false),
+            stats: AtomicTimerStats::new(),
         }
     }
 }
 
-#[derive(Debug, Clone)]
-struct Event {
-    deadline: Instant,
-    kind: EventKind,
+/// Per-CPU timer statistics.
+#[cfg_attr(not(feature = "shell"), expect(dead_code))]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TimerStats {
+    /// Number of timer interrupts taken.
+    pub interrupts: u64,
+    /// Number of timer interrupts with no expired event.
+    pub spurious_interrupts: u64,
+    /// Number of scheduler ticks processed.
+    pub ticks: u64,
+    /// Number of tasks woken up by their sleep deadline.
+    pub task_wakeups: u64,
+    /// Number of times the scheduler tick was stopped.
+    pub tick_stops: u64,
+    /// Number of times the scheduler tick was restarted.
+    pub tick_restarts: u64,
 }
 
-#[derive(Debug, Clone)]
-enum EventKind {
-    Tick,
-    Wakeup(Weak<Task>),
+#[derive(Debug)]
+struct AtomicTimerStats {
+    interrupts: AtomicU64,
+    spurious_interrupts: AtomicU64,
+    ticks: AtomicU64,
+    task_wakeups: AtomicU64,
+    tick_stops: AtomicU64,
+    tick_restarts: AtomicU64,
 }
 
-impl Ord for Event {
-    fn cmp(&self, other: &Self) -> cmp::Ordering {
-        other
-            .deadline
-            .cmp(&self.deadline)
-            .then_with(|| match (&self.kind, &other.kind) {
-                (EventKind::Tick, EventKind::Tick) => cmp::Ordering::Equal,
-                (EventKind::Tick, _) => cmp::Ordering::Less,
-                (_, EventKind::Tick) => cmp::Ordering::Greater,
-                (EventKind::Wakeup(t1), EventKind::Wakeup(t2)) => {
-                    let tid1 = Weak::upgrade(t1).map_or(TaskId::INVALID, |t| t.id());
-                    let tid2 = Weak::upgrade(t2).map_or(TaskId::INVALID, |t| t.id());
-                    tid1.cmp(&tid2)
-                }
-            })
+impl AtomicTimerStats {
+    const fn new() -> Self {
+        Self {
+            interrupts: AtomicU64::new(0),
+            spurious_interrupts: AtomicU64::new(0),
+            ticks: AtomicU64::new(0),
+            task_wakeups: AtomicU64::new(0),
+            tick_stops: AtomicU64::new(0),
+            tick_restarts: AtomicU64::new(0),
+        }
     }
-}
 
-impl PartialOrd for Event {
-    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
-        Some(self.cmp(other))
+    fn load(&self) -> TimerStats {
+        TimerStats {
+            interrupts: self.interrupts.load(Ordering::Relaxed),
+            spurious_interrupts: self.spurious_interrupts.load(Ordering::Relaxed),
+            ticks: self.ticks.load(Ordering::Relaxed),
+            task_wakeups: self.task_wakeups.load(Ordering::Relaxed),
+            tick_stops: self.tick_stops.load(Ordering::Relaxed),
+            tick_restarts: self.tick_restarts.load(Ordering::Relaxed),
+        }
     }
 }
 
-impl PartialEq for Event {
-    fn eq(&self, other: &Self) -> bool {
-        self.cmp(other).is_eq()
-    }
+fn incr(counter: &AtomicU64) {
+    counter.fetch_add(1, Ordering::Relaxed);
 }
 
-impl Eq for Event {}
+#[derive(Debug, Clone)]
+enum EventKind {
+    Tick,
+    Watchdog,
+    Wakeup(Weak<Task>),
+}
+
+pub static HOTPLUG_HOOK: HotplugHook = HotplugHook {
+    name: "timer",
+    online: arm,
+    offline: disarm,
+};
+
+pub fn start() -> Result<(), GenericError> {
+    assert!(!super::is_enabled());
+
+    let cpu = cpu::current();
+    let intc = cpu_intc::find_cpu_intc_for_cpu(cpu.id())
+        .whatever_context("no interrupt controller for current CPU")?;
+    let line = IrqLine::request(
+        intc,
+        cpu_intc::SUPERVISOR_TIMER,
+        "timer",
+        Arc::new(handle_interrupt),
+    )?;
+    line.enable();
+    line.leak();
+
+    arm();
+    Ok(())
+}
 
-pub fn start() {
+/// Starts the scheduler tick and the watchdog events of the current CPU.
+fn arm() {
     assert!(!super::is_enabled());
 
     // allow user to use time.
@@ -86,20 +166,111 @@ pub fn start() {
         scounteren::set_tm();
     }
 
-    let cpu = cpu::current();
-    let cpu_frequency = cpu.timer_frequency();
+    let cpu_frequency = cpu::current().timer_frequency();
     let state = &TIMER_QUEUE.get();
     let now = now();
 
+    watchdog::heartbeat(now);
     let mut queue = state.queue.lock();
-    queue.push(Event {
-        deadline: now,
-        kind: EventKind::Tick,
-    });
+    assert!(queue.is_empty());
+    queue.reserve(RESERVED_EVENTS);
+    queue.push(now, EventKind::Tick);
+    queue.push(now + watchdog::WATCHDOG_INTERVAL, EventKind::Watchdog);
+    state.tick_active.store(true, Ordering::Relaxed);
     update_timer(&queue, cpu_frequency);
+    queue.unlock();
+}
+
+/// Stops the events of the current CPU going offline.
+///
+/// The sleeping tasks are woken up early, and wait for the rest of their
+/// sleep on the CPUs they are resumed on.
+fn disarm() {
+    assert!(!super::is_enabled());
+    let state = &TIMER_QUEUE.get();
+
+    let mut queue = state.queue.lock();
+    let events = queue.drain().collect::<Vec<_>>();
+    state.tick_active.store(false, Ordering::Relaxed);
+    update_timer(&queue, cpu::current().timer_frequency());
+    queue.unlock();
+
+    for event in events {
+        if let EventKind::Wakeup(weak) = event
+            && let Some(task) = Weak::upgrade(&weak)
+        {
+            let mut shared = task.shared.lock();
+            task::resume(&mut shared);
+        }
+    }
+}
+
+/// Restarts the scheduler tick of the current CPU if it is stopped.
+pub fn restart_tick() {
+    let interrupt_guard = super::push_disabled();
+    let cpu = cpu::current();
+    let state = &TIMER_QUEUE.get();
+
+    let mut queue = state.queue.lock();
+    if !state.tick_active.swap(true, Ordering::Relaxed) {
+        incr(&state.stats.tick_restarts);
+        queue.push(
+            now() + Duration::from_millis(SCHED_SLICE_MS.get()),
+            EventKind::Tick,
+        );
+        update_timer(&queue, cpu.timer_frequency());
+    }
+    queue.unlock();
+    interrupt_guard.pop();
 }
 
-pub(super) fn handle_interrupt() {
+/// Returns when the timer of the current CPU fires next.
+///
+/// This may be earlier than the deadline of the next event, as the timer also
+/// fires when the events are moved down the timer wheel. The scheduler tick is
+/// stopped while the CPU is idle, so this is when the idle CPU is woken up by
+/// the timer at the latest.
+pub fn next_deadline() -> Option<Instant> {
+    assert!(!super::is_enabled());
+    let queue = TIMER_QUEUE.get().queue.lock();
+    let deadline = queue.next_deadline();
+    queue.unlock();
+    deadline
+}
+
+/// Notifies CPUs that a task has become runnable.
+///
+/// Restarts the local scheduler tick, and sends an IPI to every remote CPU
+/// whose tick is stopped so that idle CPUs can pick up the task.
+pub fn notify_runnable() {
+    let Some(current_cpu) = cpu::try_current() else {
+        return;
+    };
+    restart_tick();
+
+    for cpu in cpu::get_all() {
+        if cpu.id() == current_cpu.id() {
+            continue;
+        }
+        let Some(state) = TIMER_QUEUE.try_get_for(cpu.id()) else {
+            continue;
+        };
+        if state.tick_active.load(Ordering::Relaxed) || !hotplug::is_running(cpu.id()) {
+            continue;
+        }
+        if let Err(e) = super::ipi::send_reschedule(cpu.id()) {
+            warn!("failed to send reschedule IPI to CPU#{}: {e}", cpu.id());
+        }
+    }
+}
+
+/// Returns the timer statistics of the given CPU.
+#[cfg_attr(not(feature = "shell"), expect(dead_code))]
+pub fn stats(cpuid: cpu::Cpuid) -> Option<TimerStats> {
+    Some(TIMER_QUEUE.try_get_for(cpuid)?.stats.load())
+}
+
+fn handle_interrupt() {
     assert!(!super::is_enabled());
     let cpu = cpu::current();
     let cpu_frequency = cpu.timer_frequency();
@@ -108,26 +279,40 @@ pub(super) fn handle_interrupt() {
     let now = now();
 
     let mut do_sched = false;
+    let mut expired = false;
+    incr(&state.stats.interrupts);
+    watchdog::heartbeat(now);
 
     let mut queue = state.queue.lock();
-    while let Some(event) = queue.peek() {
-        if event.deadline > now {
-            break;
-        }
-        let event = queue.pop().unwrap();
+    while let Some((_, event)) = queue.pop_expired(now) {
         queue.unlock();
+        expired = true;
 
-        match event.kind {
+        match event {
             EventKind::Tick => {
+                incr(&state.stats.ticks);
                 queue = state.queue.lock();
-                queue.push(Event {
-                    deadline: now + SCHEDULER_INTERVAL,
-                    kind: EventKind::Tick,
-                });
-                do_sched = true;
+                if scheduler::has_runnable_tasks() {
+                    queue.push(
+                        now + Duration::from_millis(SCHED_SLICE_MS.get()),
+                        EventKind::Tick,
+                    );
+                    do_sched = true;
+                } else {
+                    // Nothing to preempt for; stop the tick until a task
+                    // becomes runnable.
+                    state.tick_active.store(false, Ordering::Relaxed);
+                    incr(&state.stats.tick_stops);
+                }
+            }
+            EventKind::Watchdog => {
+                watchdog::check(now);
+                queue = state.queue.lock();
+                queue.push(now + watchdog::WATCHDOG_INTERVAL, EventKind::Watchdog);
             }
             EventKind::Wakeup(weak) => {
                 if let Some(task) = Weak::upgrade(&weak) {
+                    incr(&state.stats.task_wakeups);
                     let mut shared = task.shared.lock();
                     task::resume(&mut shared);
                 }
@@ -135,6 +320,9 @@ pub(super) fn handle_interrupt() {
             }
         }
     }
+    if !expired {
+        incr(&state.stats.spurious_interrupts);
+    }
 
     update_timer(&queue, cpu_frequency);
     queue.unlock();
@@ -145,26 +333,30 @@ pub(super) fn handle_interrupt() {
     }
 }
 
-fn update_timer(queue: &BinaryHeap<Event>, cpu_frequency: u64) {
+fn update_timer(queue: &TimerWheel<EventKind>, cpu_frequency: u64) {
     assert!(!super::is_enabled());
     let timer_ticks = queue
-        .peek()
-        .map_or(Instant::MAX, |e| e.deadline)
+        .next_deadline()
+        .unwrap_or(Instant::MAX)
         .as_timer_ticks(cpu_frequency);
     unsafe {
         asm!("csrw stimecmp, {}", in(reg) timer_ticks);
     }
 }
 
-pub fn try_now() -> Option<Instant> {
-    let interrupt_guard = super::push_disabled();
-    let timer_frequency = cpu::try_current()?.timer_frequency();
-
+/// Reads the timer ticks counted at the timer frequency of the current CPU.
+pub fn read_ticks() -> u64 {
     let timer_ticks: u64;
     unsafe {
         asm!("csrr {}, time", out(reg) timer_ticks);
     }
+    timer_ticks
+}
 
+pub fn try_now() -> Option<Instant> {
+    let interrupt_guard = super::push_disabled();
+    let timer_frequency = cpu::try_current()?.timer_frequency();
+    let timer_ticks = read_ticks();
     interrupt_guard.pop();
     Some(Instant::from_timer_ticks(timer_ticks, timer_frequency))
 }
@@ -175,26 +367,62 @@ pub fn now() -> Instant {
 }
 
 pub fn sleep(dur: Duration) {
+    let task = scheduler::current_task();
+    let deadline = now().saturating_add(dur);
+
+    // the wakeup is armed again if the task is woken up early, as when the CPU
+    // it was armed on goes offline
+    while now() < deadline {
+        arm_wakeup(&task, deadline);
+        let mut shared = task.shared.lock();
+        task::pause(&mut shared);
+    }
+}
+
+/// Resumes `task` at `deadline` if it is paused then.
+///
+/// The wakeup is not cancelled, so the task may be resumed while it pauses
+/// for another reason later, and must check its condition again after
+/// resumed.
+pub fn arm_wakeup(task: &Arc<Task>, deadline: Instant) {
     let interrupt_guard = super::push_disabled();
     let cpu = cpu::current();
-
-    let task = scheduler::current_task();
-    let deadline = now() + dur;
     let state = &TIMER_QUEUE.get();
     let mut queue = state.queue.lock();
-    queue.push(Event {
-        deadline,
-        kind: EventKind::Wakeup(Arc::downgrade(&task)),
-    });
+    queue.push(deadline, EventKind::Wakeup(Arc::downgrade(task)));
     update_timer(&queue, cpu.timer_frequency());
     queue.unlock();
     interrupt_guard.pop();
+}
 
-    loop {
-        let mut shared = task.shared.lock();
-        task::pause(&mut shared);
-        if now() >= deadline {
-            break;
+#[cfg(feature = "ktest")]
+pub mod ktests {
+    use snafu::ensure_whatever;
+
+    use super::{Duration, GenericError, now, sleep};
+    use crate::ktest::KernelTest;
+
+    pub static TESTS: &[KernelTest] = kernel_tests![now_is_monotonic, sleep_waits_for_duration];
+
+    fn now_is_monotonic() -> Result<(), GenericError> {
+        let mut prev = now();
+        for _ in 0..1000 {
+            let current = now();
+            ensure_whatever!(
+                current >= prev,
+                "time went back from {prev:?} to {current:?}"
+            );
+            prev = current;
         }
+        Ok(())
+    }
+
+    fn sleep_waits_for_duration() -> Result<(), GenericError> {
+        const DURATION: Duration = Duration::from_millis(20);
+        let start = now();
+        sleep(DURATION);
+        let elapsed = now().duration_since(start);
+        ensure_whatever!(elapsed >= DURATION, "slept only {elapsed:?}");
+        Ok(())
     }
 }
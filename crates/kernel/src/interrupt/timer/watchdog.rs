@@ -0,0 +1,136 @@
+//! Lockup detector of the CPUs.
+//!
+//! Each CPU records a heartbeat on every timer interrupt, and a watchdog event
+//! keeps the interrupts coming even while the scheduler tick is stopped. On the
+//! watchdog events, each CPU checks the heartbeats of the others, so a CPU
+//! spinning with the interrupts disabled is reported by the CPUs still alive.
+//!
+//! The hard watchdog, enabled by the `watchdog.hard=<seconds>` option, resets
+//! the system by SBI when a CPU does not recover within the given time.
+
+use core::{
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::Duration,
+};
+
+use sbi::{
+    base,
+    system_reset::{self, ResetReason, ResetType},
+};
+use snafu::ensure_whatever;
+use spin::Once;
+
+use super::Instant;
+use crate::{
+    cmdline::{FromParam as _, ParamDescriptor},
+    cpu::{self, Cpuid, hotplug::HotplugHook},
+    task::scheduler,
+};
+
+/// Interval of the watchdog events on each CPU.
+pub(super) const WATCHDOG_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Time without heartbeat after which a CPU is reported as locked up.
+const SOFT_LOCKUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Time without heartbeat after which the hard watchdog resets the system.
+static HARD_LOCKUP_TIMEOUT: Once<Duration> = Once::new();
+
+cpu_local! {
+    /// Time of the last timer interrupt in nanoseconds, or zero if the timer
+    /// is not started.
+    static HEARTBEAT: AtomicU64 = AtomicU64::new(0);
+    /// Whether the current lockup of the CPU has been reported.
+    static REPORTED: AtomicBool = AtomicBool::new(false);
+}
+
+pub static HARD_WATCHDOG_PARAM: ParamDescriptor = ParamDescriptor {
+    name: "watchdog.hard",
+    handler: |value| {
+        let timeout = Duration::from_param(value)?.max(SOFT_LOCKUP_TIMEOUT);
+        ensure_whatever!(
+            base::probe_extension(system_reset::EXTENSION_ID),
+            "SBI system reset extension not available"
+        );
+        HARD_LOCKUP_TIMEOUT.call_once(|| timeout);
+        info!("hard watchdog enabled, timeout={timeout:?}");
+        Ok(())
+    },
+};
+
+/// Stops checking the heartbeat of the CPU going offline.
+pub static HOTPLUG_HOOK: HotplugHook = HotplugHook {
+    name: "watchdog",
+    online: || {},
+    offline: || {
+        HEARTBEAT.get().store(0, Ordering::Relaxed);
+        REPORTED.get().store(false, Ordering::Relaxed);
+    },
+};
+
+fn hard_lockup_timeout() -> Option<Duration> {
+    HARD_LOCKUP_TIMEOUT.get().copied()
+}
+
+/// Records that the current CPU is handling its timer interrupts.
+pub(super) fn heartbeat(now: Instant) {
+    let nanos = u64::try_from(now.duration_since_epoc().as_nanos()).unwrap();
+    // zero means the timer is not started
+    HEARTBEAT.get().store(nanos.max(1), Ordering::Relaxed);
+}
+
+/// Checks the heartbeats of the other CPUs.
+pub(super) fn check(now: Instant) {
+    let current = cpu::current().id();
+    for cpu in cpu::get_all() {
+        if cpu.id() == current {
+            continue;
+        }
+        let (Some(heartbeat), Some(reported)) = (
+            HEARTBEAT.try_get_for(cpu.id()),
+            REPORTED.try_get_for(cpu.id()),
+        ) else {
+            continue;
+        };
+        let last = heartbeat.load(Ordering::Relaxed);
+        if last == 0 {
+            continue;
+        }
+        let last = Instant::ZERO + Duration::from_nanos(last);
+        let stalled = now.saturating_duration_since(last);
+
+        if stalled < SOFT_LOCKUP_TIMEOUT {
+            if reported.swap(false, Ordering::Relaxed) {
+                info!("CPU#{} recovered from lockup", cpu.id());
+            }
+            continue;
+        }
+        if !reported.swap(true, Ordering::Relaxed) {
+            report(cpu.id(), stalled);
+        }
+        if let Some(timeout) = hard_lockup_timeout()
+            && stalled >= timeout
+        {
+            reset(cpu.id(), stalled);
+        }
+    }
+}
+
+fn report(cpuid: Cpuid, stalled: Duration) {
+    // the backtrace of the locked up CPU is not available, as it does not
+    // take the interrupts
+    match scheduler::current_task_id_on(cpuid) {
+        Some(task) => {
+            error!("lockup detected, CPU#{cpuid} stuck for {stalled:?} while running task {task}");
+        }
+        None => {
+            error!("lockup detected, CPU#{cpuid} stuck for {stalled:?} in scheduler");
+        }
+    }
+}
+
+fn reset(cpuid: Cpuid, stalled: Duration) {
+    error!("hard watchdog expired, CPU#{cpuid} stuck for {stalled:?}, resetting system");
+    let Err(e) = system_reset::system_reset(ResetType::ColdReboot, ResetReason::SystemFailure);
+    error!("failed to reset system: {e}");
+}
@@ -0,0 +1,386 @@
+//! Hierarchical timing wheel of the timer events.
+//!
+//! The events are hashed by their deadlines into [`LEVELS`] levels of
+//! [`SLOTS`] slots, a slot of the level `n` covering `SLOTS^n` ticks of
+//! [`GRANULARITY`]. Queueing and expiring an event take constant time, and the
+//! events of the higher levels are moved down when their slots come around.
+//! The events are stored in a slab whose entries are reused, so the timer
+//! interrupt does not allocate memory as long as the slab has room.
+//!
+//! The deadlines are rounded up to the ticks, so an event expires up to one
+//! tick late, but never early.
+
+use alloc::vec::Vec;
+use core::{mem, time::Duration};
+
+use platform_cast::CastFrom as _;
+
+use super::Instant;
+
+/// Length of a tick of the wheel.
+const GRANULARITY: Duration = Duration::from_millis(1);
+const SLOT_BITS: usize = 6;
+const SLOTS: usize = 1 << SLOT_BITS;
+const LEVELS: usize = 6;
+
+/// Number of the ticks covered by the wheel.
+///
+/// The events beyond are queued to the last slot of the top level, and queued
+/// again when the slot comes around.
+const MAX_DELTA: u64 = (1 << (SLOT_BITS * LEVELS)) - 1;
+
+/// Index of no entry.
+const NIL: usize = usize::MAX;
+
+/// Queue of the values expiring at their deadlines.
+#[derive(Debug)]
+pub(super) struct TimerWheel<T> {
+    /// Tick to be processed next.
+    current: u64,
+    levels: [Level; LEVELS],
+    /// List of the events of the processed ticks, not popped yet.
+    expired: usize,
+    entries: Vec<Entry<T>>,
+    /// List of the unused entries.
+    free: usize,
+    len: usize,
+}
+
+#[derive(Debug)]
+struct Level {
+    /// Bitmap of the non-empty slots.
+    occupied: u64,
+    /// Lists of the events in the slots.
+    heads: [usize; SLOTS],
+}
+
+impl Level {
+    const fn new() -> Self {
+        Self {
+            occupied: 0,
+            heads: [NIL; SLOTS],
+        }
+    }
+
+    fn take(&mut self, slot: usize) -> usize {
+        self.occupied &= !(1 << slot);
+        mem::replace(&mut self.heads[slot], NIL)
+    }
+}
+
+#[derive(Debug)]
+struct Entry<T> {
+    deadline: Instant,
+    /// Value of the event, or `None` if the entry is unused.
+    value: Option<T>,
+    /// Next entry in the same list.
+    next: usize,
+}
+
+impl<T> TimerWheel<T> {
+    pub(super) const fn new() -> Self {
+        Self {
+            current: 0,
+            levels: [const { Level::new() }; LEVELS],
+            expired: NIL,
+            entries: Vec::new(),
+            free: NIL,
+            len: 0,
+        }
+    }
+
+    /// Reserves room for `additional` more events, so that queueing them does
+    /// not allocate memory.
+    pub(super) fn reserve(&mut self, additional: usize) {
+        let unused = self.entries.len() - self.len;
+        self.entries.reserve(additional.saturating_sub(unused));
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Queues `value` expiring at `deadline`.
+    pub(super) fn push(&mut self, deadline: Instant, value: T) {
+        let index = if self.free == NIL {
+            self.entries.push(Entry {
+                deadline,
+                value: Some(value),
+                next: NIL,
+            });
+            self.entries.len() - 1
+        } else {
+            let index = self.free;
+            let entry = &mut self.entries[index];
+            self.free = entry.next;
+            entry.deadline = deadline;
+            entry.value = Some(value);
+            index
+        };
+        self.len += 1;
+        self.enqueue(index);
+    }
+
+    /// Pops an event whose deadline is at or before `now`.
+    pub(super) fn pop_expired(&mut self, now: Instant) -> Option<(Instant, T)> {
+        let now_tick = tick_floor(now);
+        loop {
+            if self.expired != NIL {
+                let index = self.expired;
+                self.expired = self.entries[index].next;
+                return Some(self.release(index));
+            }
+
+            let Some(next) = self.next_tick().filter(|&next| next <= now_tick) else {
+                // no event until then, so the empty ticks are skipped
+                self.current = self.current.max(now_tick.saturating_add(1));
+                return None;
+            };
+            self.current = next;
+            self.process_tick();
+        }
+    }
+
+    /// Returns when the events have to be processed next.
+    ///
+    /// This is the start of the tick of the earliest event, or of the tick when
+    /// the events of a higher level are moved down, whichever is earlier.
+    pub(super) fn next_deadline(&self) -> Option<Instant> {
+        if self.expired != NIL {
+            return Some(instant_of(self.current - 1));
+        }
+        self.next_tick().map(instant_of)
+    }
+
+    /// Removes all the events.
+    pub(super) fn drain(&mut self) -> impl Iterator<Item = T> {
+        self.levels = [const { Level::new() }; LEVELS];
+        self.expired = NIL;
+        self.free = NIL;
+        self.len = 0;
+        self.entries.drain(..).filter_map(|entry| entry.value)
+    }
+
+    /// Links the entry to the slot of its deadline.
+    fn enqueue(&mut self, index: usize) {
+        let tick = tick_ceil(self.entries[index].deadline)
+            .clamp(self.current, self.current.saturating_add(MAX_DELTA));
+        let level = level_of(tick - self.current);
+        let slot = slot_of(tick, level);
+        let level = &mut self.levels[level];
+        self.entries[index].next = level.heads[slot];
+        level.heads[slot] = index;
+        level.occupied |= 1 << slot;
+    }
+
+    fn release(&mut self, index: usize) -> (Instant, T) {
+        let entry = &mut self.entries[index];
+        let value = entry.value.take().unwrap();
+        entry.next = self.free;
+        self.free = index;
+        self.len -= 1;
+        (entry.deadline, value)
+    }
+
+    /// Returns the earliest tick at or after `current` with a non-empty slot
+    /// to process.
+    fn next_tick(&self) -> Option<u64> {
+        self.levels
+            .iter()
+            .enumerate()
+            .filter(|(_, level)| level.occupied != 0)
+            .map(|(level_index, level)| {
+                let shift = SLOT_BITS * level_index;
+                // the first slot of the level starting at or after `current`
+                let block = self.current.div_ceil(1 << shift);
+                let rotation = u32::try_from(slot_of(block, 0)).unwrap();
+                let offset = level.occupied.rotate_right(rotation).trailing_zeros();
+                (block + u64::from(offset)) << shift
+            })
+            .min()
+    }
+
+    /// Moves down the events of the higher level slots starting at `current`,
+    /// and expires the events of `current`.
+    fn process_tick(&mut self) {
+        let tick = self.current;
+        for level in (1..LEVELS).rev() {
+            if tick & ((1 << (SLOT_BITS * level)) - 1) != 0 {
+                continue;
+            }
+            let mut index = self.levels[level].take(slot_of(tick, level));
+            while index != NIL {
+                let next = self.entries[index].next;
+                self.enqueue(index);
+                index = next;
+            }
+        }
+
+        let mut index = self.levels[0].take(slot_of(tick, 0));
+        while index != NIL {
+            let next = self.entries[index].next;
+            self.entries[index].next = self.expired;
+            self.expired = index;
+            index = next;
+        }
+        self.current += 1;
+    }
+}
+
+fn level_of(delta: u64) -> usize {
+    usize::cast_from(delta.checked_ilog2().unwrap_or(0)) / SLOT_BITS
+}
+
+fn slot_of(tick: u64, level: usize) -> usize {
+    usize::cast_from(tick >> (SLOT_BITS * level)) % SLOTS
+}
+
+fn tick_ceil(instant: Instant) -> u64 {
+    let nanos = instant.duration_since_epoc().as_nanos();
+    u64::try_from(nanos.div_ceil(GRANULARITY.as_nanos())).unwrap_or(u64::MAX)
+}
+
+fn tick_floor(instant: Instant) -> u64 {
+    let nanos = instant.duration_since_epoc().as_nanos();
+    u64::try_from(nanos / GRANULARITY.as_nanos()).unwrap_or(u64::MAX)
+}
+
+fn instant_of(tick: u64) -> Instant {
+    let granularity = u64::try_from(GRANULARITY.as_nanos()).unwrap();
+    Instant::ZERO.saturating_add(Duration::from_nanos(tick.saturating_mul(granularity)))
+}
+
+#[cfg(feature = "ktest")]
+pub mod ktests {
+    use alloc::vec::Vec;
+
+    use snafu::ensure_whatever;
+
+    use super::{Duration, GRANULARITY, Instant, MAX_DELTA, TimerWheel, instant_of};
+    use crate::{error::GenericError, ktest::KernelTest};
+
+    pub static TESTS: &[KernelTest] = kernel_tests![
+        expires_on_time,
+        expires_beyond_wheel,
+        reuses_entries,
+        drains_all,
+    ];
+
+    fn at(nanos: u64) -> Instant {
+        Instant::ZERO + Duration::from_nanos(nanos)
+    }
+
+    /// Pops the events at the deadlines given by the wheel until it is empty,
+    /// and returns the deadlines and the values in the order expired.
+    fn run(wheel: &mut TimerWheel<usize>) -> Result<Vec<(Instant, usize)>, GenericError> {
+        let mut expired = Vec::new();
+        while let Some(now) = wheel.next_deadline() {
+            while let Some((deadline, value)) = wheel.pop_expired(now) {
+                ensure_whatever!(deadline <= now, "{deadline:?} expired early at {now:?}");
+                ensure_whatever!(
+                    now - deadline < GRANULARITY,
+                    "{deadline:?} expired late at {now:?}"
+                );
+                expired.push((deadline, value));
+            }
+            ensure_whatever!(
+                wheel.next_deadline().is_none_or(|next| next > now),
+                "next deadline not after {now:?}"
+            );
+        }
+        Ok(expired)
+    }
+
+    fn expires_on_time() -> Result<(), GenericError> {
+        const DEADLINES: [u64; 9] = [
+            0,
+            500_000,
+            1_000_000,
+            3_000_001,
+            64_000_000,
+            70_000_000,
+            4_096_000_000,
+            300_000_000_000,
+            3_600_000_000_000,
+        ];
+
+        let mut wheel = TimerWheel::new();
+        // queued in reverse, and some after the wheel started
+        for (i, &nanos) in DEADLINES.iter().enumerate().rev() {
+            if i % 3 != 2 {
+                wheel.push(at(nanos), i);
+            }
+        }
+        let popped = wheel.pop_expired(at(0));
+        ensure_whatever!(popped == Some((at(0), 0)), "got {popped:?}");
+        for (i, &nanos) in DEADLINES.iter().enumerate().skip(2).step_by(3) {
+            wheel.push(at(nanos), i);
+        }
+
+        let mut expired = run(&mut wheel)?;
+        expired.push(popped.unwrap());
+        let mut values = expired.iter().map(|&(_, value)| value).collect::<Vec<_>>();
+        values.sort_unstable();
+        ensure_whatever!(
+            values == (0..DEADLINES.len()).collect::<Vec<_>>(),
+            "got {values:?}"
+        );
+        ensure_whatever!(wheel.is_empty(), "events left");
+        Ok(())
+    }
+
+    fn expires_beyond_wheel() -> Result<(), GenericError> {
+        let far = instant_of(MAX_DELTA * 3 / 2);
+        let mut wheel = TimerWheel::new();
+        wheel.push(far, 1);
+        wheel.push(Instant::MAX, 2);
+
+        let mut now = at(0);
+        while now < far {
+            let popped = wheel.pop_expired(now);
+            ensure_whatever!(popped.is_none(), "{popped:?} expired at {now:?}");
+            now = wheel.next_deadline().unwrap();
+        }
+        let popped = wheel.pop_expired(now);
+        ensure_whatever!(popped == Some((far, 1)), "got {popped:?}");
+        ensure_whatever!(!wheel.is_empty(), "event at MAX expired");
+        Ok(())
+    }
+
+    fn reuses_entries() -> Result<(), GenericError> {
+        let mut wheel = TimerWheel::new();
+        wheel.reserve(4);
+        let capacity = wheel.entries.capacity();
+        for round in 0..100 {
+            let now = at(round * 10_000_000);
+            for i in 0..4 {
+                wheel.push(now, i);
+            }
+            let mut count = 0;
+            while wheel.pop_expired(now).is_some() {
+                count += 1;
+            }
+            ensure_whatever!(count == 4, "{count} events expired in round {round}");
+        }
+        ensure_whatever!(
+            wheel.entries.capacity() == capacity,
+            "entries grown from {capacity} to {}",
+            wheel.entries.capacity()
+        );
+        Ok(())
+    }
+
+    fn drains_all() -> Result<(), GenericError> {
+        let mut wheel = TimerWheel::new();
+        for i in 0..10 {
+            wheel.push(at(i * 100_000_000), i);
+        }
+        ensure_whatever!(wheel.pop_expired(at(0)) == Some((at(0), 0)), "not expired");
+        let mut drained = wheel.drain().collect::<Vec<_>>();
+        drained.sort_unstable();
+        ensure_whatever!(drained == (1..10).collect::<Vec<_>>(), "got {drained:?}");
+        ensure_whatever!(wheel.is_empty(), "events left");
+        ensure_whatever!(wheel.next_deadline().is_none(), "deadline left");
+        Ok(())
+    }
+}
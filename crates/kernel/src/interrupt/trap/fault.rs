@@ -0,0 +1,245 @@
+use alloc::vec::Vec;
+use core::fmt;
+
+use riscv::interrupt::Exception;
+use sv39::{MapPageFlags, Mapping, address::VirtAddr};
+
+use super::TrapFrame;
+use crate::{
+    memory::kernel_space::{self, MappingLookup},
+    sync::spinlock::SpinMutex,
+};
+
+/// Kind of memory access that caused a fault.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessType {
+    Read,
+    Write,
+    Execute,
+}
+
+impl fmt::Display for AccessType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::Read => "read",
+            Self::Write => "write",
+            Self::Execute => "execute",
+        };
+        f.write_str(s)
+    }
+}
+
+/// Exception taken in the kernel, decoded from the trap registers.
+#[derive(Debug, Clone, Copy)]
+pub enum Fault {
+    /// No valid mapping with the required permission for the address.
+    Page {
+        addr: usize,
+        access: AccessType,
+    },
+    /// The access was denied by PMP or the address does not exist.
+    Access {
+        addr: usize,
+        access: AccessType,
+    },
+    /// The address is not aligned to the access size.
+    Misaligned {
+        addr: usize,
+        access: AccessType,
+    },
+    /// The instruction at `sepc` could not be decoded.
+    ///
+    /// `instruction` is the faulting instruction bits, or 0 if the
+    /// implementation does not report them.
+    IllegalInstruction {
+        instruction: usize,
+    },
+    Breakpoint,
+    EnvCall,
+}
+
+impl Fault {
+    /// Decodes the exception `exception` recorded in `frame`.
+    pub fn decode(exception: Exception, frame: &TrapFrame) -> Self {
+        let addr = frame.stval;
+        match exception {
+            Exception::InstructionPageFault => Self::Page {
+                addr,
+                access: AccessType::Execute,
+            },
+            Exception::LoadPageFault => Self::Page {
+                addr,
+                access: AccessType::Read,
+            },
+            Exception::StorePageFault => Self::Page {
+                addr,
+                access: AccessType::Write,
+            },
+            Exception::InstructionFault => Self::Access {
+                addr,
+                access: AccessType::Execute,
+            },
+            Exception::LoadFault => Self::Access {
+                addr,
+                access: AccessType::Read,
+            },
+            Exception::StoreFault => Self::Access {
+                addr,
+                access: AccessType::Write,
+            },
+            Exception::InstructionMisaligned => Self::Misaligned {
+                addr,
+                access: AccessType::Execute,
+            },
+            Exception::LoadMisaligned => Self::Misaligned {
+                addr,
+                access: AccessType::Read,
+            },
+            Exception::StoreMisaligned => Self::Misaligned {
+                addr,
+                access: AccessType::Write,
+            },
+            Exception::IllegalInstruction => Self::IllegalInstruction {
+                instruction: frame.stval,
+            },
+            Exception::Breakpoint => Self::Breakpoint,
+            Exception::UserEnvCall | Exception::SupervisorEnvCall => Self::EnvCall,
+        }
+    }
+
+    /// Returns the faulting virtual address and access type of memory faults.
+    pub fn memory_access(&self) -> Option<(usize, AccessType)> {
+        match *self {
+            Self::Page { addr, access }
+            | Self::Access { addr, access }
+            | Self::Misaligned { addr, access } => Some((addr, access)),
+            Self::IllegalInstruction { .. } | Self::Breakpoint | Self::EnvCall => None,
+        }
+    }
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Page { addr, access } => write!(f, "page fault on {access} at {addr:#x}"),
+            Self::Access { addr, access } => {
+                write!(f, "access fault on {access} at {addr:#x}")
+            }
+            Self::Misaligned { addr, access } => {
+                write!(f, "misaligned {access} at {addr:#x}")
+            }
+            Self::IllegalInstruction { instruction } => {
+                write!(f, "illegal instruction {instruction:#010x}")
+            }
+            Self::Breakpoint => write!(f, "breakpoint"),
+            Self::EnvCall => write!(f, "environment call"),
+        }
+    }
+}
+
+/// Human readable report of a fault that no hook has handled.
+pub struct FaultReport<'a> {
+    fault: Fault,
+    frame: &'a TrapFrame,
+    mapping: Option<MappingLookup>,
+}
+
+impl<'a> FaultReport<'a> {
+    pub fn new(fault: Fault, frame: &'a TrapFrame) -> Self {
+        let mapping = fault
+            .memory_access()
+            .and_then(|(addr, _access)| VirtAddr::try_from_addr(addr))
+            .and_then(kernel_space::try_lookup);
+        Self {
+            fault,
+            frame,
+            mapping,
+        }
+    }
+}
+
+impl fmt::Display for FaultReport<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "unexpected kernel exception: {}, sepc={:#x}",
+            self.fault, self.frame.sepc
+        )?;
+        match &self.mapping {
+            Some(MappingLookup::Mapped(mapping)) => {
+                writeln!(f, "mapping: {}", DisplayMapping(mapping))?;
+            }
+            Some(MappingLookup::Unmapped { below, above }) => {
+                writeln!(f, "mapping: not mapped")?;
+                if let Some(below) = below {
+                    writeln!(f, "nearest mapping below: {}", DisplayMapping(below))?;
+                }
+                if let Some(above) = above {
+                    writeln!(f, "nearest mapping above: {}", DisplayMapping(above))?;
+                }
+            }
+            None if self.fault.memory_access().is_some() => {
+                writeln!(f, "mapping: unavailable")?;
+            }
+            None => {}
+        }
+        write!(f, "{:?}", self.frame)
+    }
+}
+
+struct DisplayMapping<'a>(&'a Mapping);
+
+impl fmt::Display for DisplayMapping<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Mapping {
+            min_virt_addr,
+            max_virt_addr,
+            min_phys_addr,
+            flags,
+            level,
+        } = *self.0;
+        write!(
+            f,
+            "{min_virt_addr:#p}..={max_virt_addr:#p} -> {min_phys_addr:#p} ("
+        )?;
+        for (flag, ch) in [
+            (MapPageFlags::R, 'r'),
+            (MapPageFlags::W, 'w'),
+            (MapPageFlags::X, 'x'),
+            (MapPageFlags::U, 'u'),
+        ] {
+            let ch = if flags.contains(flag) { ch } else { '-' };
+            write!(f, "{ch}")?;
+        }
+        write!(f, ", level {level})")
+    }
+}
+
+/// Handler that can claim a kernel fault before it panics.
+///
+/// Returns `true` if the fault is handled. The handler may update `frame` to
+/// change the context that the trap returns to.
+pub type FaultHook = fn(&Fault, &mut TrapFrame) -> bool;
+
+static FAULT_HOOKS: SpinMutex<Vec<FaultHook>> = SpinMutex::new(Vec::new());
+
+/// Registers a hook that is called on kernel faults.
+///
+/// Hooks are called in registration order until one of them handles the
+/// fault. Hooks must not register other hooks.
+pub fn register_hook(hook: FaultHook) {
+    FAULT_HOOKS.lock().push(hook);
+}
+
+/// Calls the registered hooks for `fault`.
+///
+/// Returns `true` if one of the hooks handled the fault.
+pub(super) fn run_hooks(fault: &Fault, frame: &mut TrapFrame) -> bool {
+    // A fault while the hook list is being updated cannot be handled by hooks.
+    let Some(hooks) = FAULT_HOOKS.try_lock() else {
+        return false;
+    };
+    let handled = hooks.iter().any(|hook| hook(fault, frame));
+    hooks.unlock();
+    handled
+}
@@ -0,0 +1,98 @@
+use core::fmt;
+
+/// Registers of the interrupted context, saved by the trap entry path.
+///
+/// Modifications to the frame (including `sepc` and `sstatus`) are written
+/// back to the registers when the trap handler returns.
+#[derive(Clone, Copy, Default)]
+#[repr(C, align(16))]
+pub struct TrapFrame {
+    pub ra: usize,
+    pub sp: usize,
+    pub gp: usize,
+    pub tp: usize,
+    pub t0: usize,
+    pub t1: usize,
+    pub t2: usize,
+    pub s0: usize,
+    pub s1: usize,
+    pub a0: usize,
+    pub a1: usize,
+    pub a2: usize,
+    pub a3: usize,
+    pub a4: usize,
+    pub a5: usize,
+    pub a6: usize,
+    pub a7: usize,
+    pub s2: usize,
+    pub s3: usize,
+    pub s4: usize,
+    pub s5: usize,
+    pub s6: usize,
+    pub s7: usize,
+    pub s8: usize,
+    pub s9: usize,
+    pub s10: usize,
+    pub s11: usize,
+    pub t3: usize,
+    pub t4: usize,
+    pub t5: usize,
+    pub t6: usize,
+    pub sepc: usize,
+    pub sstatus: usize,
+    pub stval: usize,
+    pub scause: usize,
+}
+
+impl TrapFrame {
+    const REGISTER_NAMES: [&str; 31] = [
+        "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3", "a4", "a5",
+        "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4", "t5",
+        "t6",
+    ];
+
+    fn registers(&self) -> [usize; 31] {
+        [
+            self.ra, self.sp, self.gp, self.tp, self.t0, self.t1, self.t2, self.s0, self.s1,
+            self.a0, self.a1, self.a2, self.a3, self.a4, self.a5, self.a6, self.a7, self.s2,
+            self.s3, self.s4, self.s5, self.s6, self.s7, self.s8, self.s9, self.s10, self.s11,
+            self.t3, self.t4, self.t5, self.t6,
+        ]
+    }
+}
+
+impl fmt::Debug for TrapFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "sepc={:#018x} sstatus={:#018x} stval={:#018x} scause={:#018x}",
+            self.sepc, self.sstatus, self.stval, self.scause
+        )?;
+        for (i, (name, value)) in Self::REGISTER_NAMES
+            .iter()
+            .zip(self.registers())
+            .enumerate()
+        {
+            write!(f, "{name:>3}={value:#018x}")?;
+            if i % 4 == 3 || i == Self::REGISTER_NAMES.len() - 1 {
+                writeln!(f)?;
+            } else {
+                write!(f, " ")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Saved user context of a task running in U-mode.
+///
+/// `kernel_sp`, `kernel_tp` and `kernel_sscratch` are filled in when entering
+/// U-mode and used by the user trap entry path to get back to the kernel.
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C, align(16))]
+pub struct UserTrapFrame {
+    pub regs: TrapFrame,
+    pub(super) kernel_sp: usize,
+    pub(super) kernel_tp: usize,
+    pub(super) kernel_sscratch: usize,
+}
@@ -1,5 +1,6 @@
 cfg_if::cfg_if! {
     if #[cfg(target_arch = "riscv64")] {
+        #[expect(clippy::too_many_lines)]
         mod riscv64;
         pub use self::riscv64::*;
     } else {
@@ -1,21 +1,42 @@
-use core::arch::naked_asm;
+use core::{arch::naked_asm, mem::offset_of};
 
 use riscv::register::{
-    sie,
+    sscratch,
     stvec::{self, Stvec, TrapMode},
 };
 
+use super::super::{TrapFrame, UserTrapFrame};
+
 pub fn apply() {
+    write_stvec(kernel_vec as usize);
+}
+
+/// Sets the top of the stack that the kernel trap vector saves the context
+/// on.
+///
+/// # Safety
+///
+/// `top` must be the top of a stack reserved for this CPU, whose pages are
+/// all mapped.
+pub unsafe fn set_exception_stack(top: usize) {
     unsafe {
-        let mut sie = sie::read();
-        sie.set_sext(true);
-        sie.set_stimer(true);
-        sie.set_ssoft(true);
-        sie::write(sie);
+        sscratch::write(top);
     }
+}
+
+/// Points `stvec` to the trap vector for traps from U-mode.
+///
+/// # Safety
+///
+/// Interrupts must be disabled until [`apply`] restores the kernel trap
+/// vector.
+pub unsafe fn apply_user() {
+    write_stvec(user_vec as usize);
+}
 
+fn write_stvec(address: usize) {
     let mut stvec = Stvec::from_bits(0);
-    stvec.set_address(kernel_vec as usize);
+    stvec.set_address(address);
     stvec.set_trap_mode(TrapMode::Direct);
     unsafe {
         stvec::write(stvec);
@@ -25,57 +46,503 @@ pub fn apply() {
 #[unsafe(naked)]
 extern "C" fn kernel_vec() {
     naked_asm!(
+        // sscratch holds the top of the exception stack of this CPU, or 0
+        // while a trap is handled on it. Traps are taken on the exception
+        // stack, so that faults on the demand-paged kernel stacks can be
+        // handled.
+        "csrrw sp, sscratch, sp",
+        "bnez sp, 1f",
+        // nested trap on the exception stack: stay on the current stack.
+        "csrrw sp, sscratch, sp",
+        "1:",
+
         // make room to save registers.
-        "addi sp, sp, -8 * 18",
+        "addi sp, sp, -{size}",
 
-        // save caller-saved registers.
-        "sd ra, 8 * 0(sp)",
-        "sd gp, 8 * 1(sp)",
-        "sd tp, 8 * 2(sp)",
-        "sd t0, 8 * 3(sp)",
-        "sd t1, 8 * 4(sp)",
-        "sd t2, 8 * 5(sp)",
-        "sd a0, 8 * 6(sp)",
-        "sd a1, 8 * 7(sp)",
-        "sd a2, 8 * 8(sp)",
-        "sd a3, 8 * 9(sp)",
-        "sd a4, 8 * 10(sp)",
-        "sd a5, 8 * 11(sp)",
-        "sd a6, 8 * 12(sp)",
-        "sd a7, 8 * 13(sp)",
-        "sd t3, 8 * 14(sp)",
-        "sd t4, 8 * 15(sp)",
-        "sd t5, 8 * 16(sp)",
-        "sd t6, 8 * 17(sp)",
-
-        // call the Rust trap handler in trap.rs
+        // save general purpose registers.
+        "sd ra, {f_ra}(sp)",
+        "sd gp, {f_gp}(sp)",
+        "sd tp, {f_tp}(sp)",
+        "sd t0, {f_t0}(sp)",
+        "sd t1, {f_t1}(sp)",
+        "sd t2, {f_t2}(sp)",
+        "sd s0, {f_s0}(sp)",
+        "sd s1, {f_s1}(sp)",
+        "sd a0, {f_a0}(sp)",
+        "sd a1, {f_a1}(sp)",
+        "sd a2, {f_a2}(sp)",
+        "sd a3, {f_a3}(sp)",
+        "sd a4, {f_a4}(sp)",
+        "sd a5, {f_a5}(sp)",
+        "sd a6, {f_a6}(sp)",
+        "sd a7, {f_a7}(sp)",
+        "sd s2, {f_s2}(sp)",
+        "sd s3, {f_s3}(sp)",
+        "sd s4, {f_s4}(sp)",
+        "sd s5, {f_s5}(sp)",
+        "sd s6, {f_s6}(sp)",
+        "sd s7, {f_s7}(sp)",
+        "sd s8, {f_s8}(sp)",
+        "sd s9, {f_s9}(sp)",
+        "sd s10, {f_s10}(sp)",
+        "sd s11, {f_s11}(sp)",
+        "sd t3, {f_t3}(sp)",
+        "sd t4, {f_t4}(sp)",
+        "sd t5, {f_t5}(sp)",
+        "sd t6, {f_t6}(sp)",
+        // sscratch holds the interrupted stack pointer if the stack has been
+        // switched, or 0 otherwise.
+        "csrrw t0, sscratch, zero",
+        "bnez t0, 2f",
+        "addi t0, sp, {size}",
+        "2:",
+        "sd t0, {f_sp}(sp)",
+
+        // save trap registers.
+        "csrr t0, sepc",
+        "sd t0, {f_sepc}(sp)",
+        "csrr t0, sstatus",
+        "sd t0, {f_sstatus}(sp)",
+        "csrr t0, stval",
+        "sd t0, {f_stval}(sp)",
+        "csrr t0, scause",
+        "sd t0, {f_scause}(sp)",
+
+        // interrupts are handled on the interrupted kernel stack, as the
+        // handler may switch to other tasks.
+        "bgez t0, 4f",
+        "ld t0, {f_sp}(sp)",
+        "addi t1, sp, {size}",
+        "beq t0, t1, 4f",
+        // touch the kernel stack before copying the frame to it, as the
+        // trap registers are saved only in the frame on the exception stack
+        // if this faults.
+        "addi t1, t0, -{size}",
+        "sd zero, 0(t1)",
+        "sd zero, {size} - 8(t1)",
+        "mv t2, sp",
+        "addi t3, sp, {size}",
+        "3:",
+        "ld t4, 0(t2)",
+        "sd t4, 0(t1)",
+        "addi t2, t2, 8",
+        "addi t1, t1, 8",
+        "bne t2, t3, 3b",
+        // release the exception stack.
+        "csrw sscratch, t3",
+        "addi sp, t0, -{size}",
+        "4:",
+
+        // call the Rust trap handler in trap.rs with the trap frame
+        "mv a0, sp",
         "call {trap_kernel}",
 
-        // restore registers.
-        "ld ra, 8 * 0(sp)",
-        "ld gp, 8 * 1(sp)",
+        // restore trap registers, as the current task may have been moved to
+        // other CPUs.
+        "ld t0, {f_sepc}(sp)",
+        "csrw sepc, t0",
+        "ld t0, {f_sstatus}(sp)",
+        "csrw sstatus, t0",
+
+        // release the exception stack if the frame is on it.
+        "ld t0, {f_sp}(sp)",
+        "addi t1, sp, {size}",
+        "beq t0, t1, 5f",
+        "csrw sscratch, t1",
+        "5:",
+
+        // restore general purpose registers.
+        "ld ra, {f_ra}(sp)",
+        "ld gp, {f_gp}(sp)",
         // not tp (contains hartid), in case we moved CPUs
-        // "ld tp, 8 * 2(sp)",
-        "ld t0, 8 * 3(sp)",
-        "ld t1, 8 * 4(sp)",
-        "ld t2, 8 * 5(sp)",
-        "ld a0, 8 * 6(sp)",
-        "ld a1, 8 * 7(sp)",
-        "ld a2, 8 * 8(sp)",
-        "ld a3, 8 * 9(sp)",
-        "ld a4, 8 * 10(sp)",
-        "ld a5, 8 * 11(sp)",
-        "ld a6, 8 * 12(sp)",
-        "ld a7, 8 * 13(sp)",
-        "ld t3, 8 * 14(sp)",
-        "ld t4, 8 * 15(sp)",
-        "ld t5, 8 * 16(sp)",
-        "ld t6, 8 * 17(sp)",
-
-        "addi sp, sp, 8 * 18",
+        // "ld tp, {f_tp}(sp)",
+        "ld t0, {f_t0}(sp)",
+        "ld t1, {f_t1}(sp)",
+        "ld t2, {f_t2}(sp)",
+        "ld s0, {f_s0}(sp)",
+        "ld s1, {f_s1}(sp)",
+        "ld a0, {f_a0}(sp)",
+        "ld a1, {f_a1}(sp)",
+        "ld a2, {f_a2}(sp)",
+        "ld a3, {f_a3}(sp)",
+        "ld a4, {f_a4}(sp)",
+        "ld a5, {f_a5}(sp)",
+        "ld a6, {f_a6}(sp)",
+        "ld a7, {f_a7}(sp)",
+        "ld s2, {f_s2}(sp)",
+        "ld s3, {f_s3}(sp)",
+        "ld s4, {f_s4}(sp)",
+        "ld s5, {f_s5}(sp)",
+        "ld s6, {f_s6}(sp)",
+        "ld s7, {f_s7}(sp)",
+        "ld s8, {f_s8}(sp)",
+        "ld s9, {f_s9}(sp)",
+        "ld s10, {f_s10}(sp)",
+        "ld s11, {f_s11}(sp)",
+        "ld t3, {f_t3}(sp)",
+        "ld t4, {f_t4}(sp)",
+        "ld t5, {f_t5}(sp)",
+        "ld t6, {f_t6}(sp)",
+
+        "ld sp, {f_sp}(sp)",
 
         // return to whatever we were doing in the kernel.
         "sret",
-        trap_kernel = sym super::super::trap_kernel
+        size = const size_of::<TrapFrame>(),
+        f_ra = const offset_of!(TrapFrame, ra),
+        f_sp = const offset_of!(TrapFrame, sp),
+        f_gp = const offset_of!(TrapFrame, gp),
+        f_tp = const offset_of!(TrapFrame, tp),
+        f_t0 = const offset_of!(TrapFrame, t0),
+        f_t1 = const offset_of!(TrapFrame, t1),
+        f_t2 = const offset_of!(TrapFrame, t2),
+        f_s0 = const offset_of!(TrapFrame, s0),
+        f_s1 = const offset_of!(TrapFrame, s1),
+        f_a0 = const offset_of!(TrapFrame, a0),
+        f_a1 = const offset_of!(TrapFrame, a1),
+        f_a2 = const offset_of!(TrapFrame, a2),
+        f_a3 = const offset_of!(TrapFrame, a3),
+        f_a4 = const offset_of!(TrapFrame, a4),
+        f_a5 = const offset_of!(TrapFrame, a5),
+        f_a6 = const offset_of!(TrapFrame, a6),
+        f_a7 = const offset_of!(TrapFrame, a7),
+        f_s2 = const offset_of!(TrapFrame, s2),
+        f_s3 = const offset_of!(TrapFrame, s3),
+        f_s4 = const offset_of!(TrapFrame, s4),
+        f_s5 = const offset_of!(TrapFrame, s5),
+        f_s6 = const offset_of!(TrapFrame, s6),
+        f_s7 = const offset_of!(TrapFrame, s7),
+        f_s8 = const offset_of!(TrapFrame, s8),
+        f_s9 = const offset_of!(TrapFrame, s9),
+        f_s10 = const offset_of!(TrapFrame, s10),
+        f_s11 = const offset_of!(TrapFrame, s11),
+        f_t3 = const offset_of!(TrapFrame, t3),
+        f_t4 = const offset_of!(TrapFrame, t4),
+        f_t5 = const offset_of!(TrapFrame, t5),
+        f_t6 = const offset_of!(TrapFrame, t6),
+        f_sepc = const offset_of!(TrapFrame, sepc),
+        f_sstatus = const offset_of!(TrapFrame, sstatus),
+        f_stval = const offset_of!(TrapFrame, stval),
+        f_scause = const offset_of!(TrapFrame, scause),
+        trap_kernel = sym super::super::trap_kernel,
+    )
+}
+
+/// Saves the current registers into `frame`.
+///
+/// `ra` and `sp` are those of the caller, and the trap registers hold the
+/// values of the last trap taken.
+#[unsafe(naked)]
+pub extern "C" fn save_registers(frame: &mut TrapFrame) {
+    naked_asm!(
+        "sd ra, {f_ra}(a0)",
+        "sd sp, {f_sp}(a0)",
+        "sd gp, {f_gp}(a0)",
+        "sd tp, {f_tp}(a0)",
+        "sd t0, {f_t0}(a0)",
+        "sd t1, {f_t1}(a0)",
+        "sd t2, {f_t2}(a0)",
+        "sd s0, {f_s0}(a0)",
+        "sd s1, {f_s1}(a0)",
+        "sd a0, {f_a0}(a0)",
+        "sd a1, {f_a1}(a0)",
+        "sd a2, {f_a2}(a0)",
+        "sd a3, {f_a3}(a0)",
+        "sd a4, {f_a4}(a0)",
+        "sd a5, {f_a5}(a0)",
+        "sd a6, {f_a6}(a0)",
+        "sd a7, {f_a7}(a0)",
+        "sd s2, {f_s2}(a0)",
+        "sd s3, {f_s3}(a0)",
+        "sd s4, {f_s4}(a0)",
+        "sd s5, {f_s5}(a0)",
+        "sd s6, {f_s6}(a0)",
+        "sd s7, {f_s7}(a0)",
+        "sd s8, {f_s8}(a0)",
+        "sd s9, {f_s9}(a0)",
+        "sd s10, {f_s10}(a0)",
+        "sd s11, {f_s11}(a0)",
+        "sd t3, {f_t3}(a0)",
+        "sd t4, {f_t4}(a0)",
+        "sd t5, {f_t5}(a0)",
+        "sd t6, {f_t6}(a0)",
+        "csrr t0, sepc",
+        "sd t0, {f_sepc}(a0)",
+        "csrr t0, sstatus",
+        "sd t0, {f_sstatus}(a0)",
+        "csrr t0, stval",
+        "sd t0, {f_stval}(a0)",
+        "csrr t0, scause",
+        "sd t0, {f_scause}(a0)",
+        "ret",
+        f_ra = const offset_of!(TrapFrame, ra),
+        f_sp = const offset_of!(TrapFrame, sp),
+        f_gp = const offset_of!(TrapFrame, gp),
+        f_tp = const offset_of!(TrapFrame, tp),
+        f_t0 = const offset_of!(TrapFrame, t0),
+        f_t1 = const offset_of!(TrapFrame, t1),
+        f_t2 = const offset_of!(TrapFrame, t2),
+        f_s0 = const offset_of!(TrapFrame, s0),
+        f_s1 = const offset_of!(TrapFrame, s1),
+        f_a0 = const offset_of!(TrapFrame, a0),
+        f_a1 = const offset_of!(TrapFrame, a1),
+        f_a2 = const offset_of!(TrapFrame, a2),
+        f_a3 = const offset_of!(TrapFrame, a3),
+        f_a4 = const offset_of!(TrapFrame, a4),
+        f_a5 = const offset_of!(TrapFrame, a5),
+        f_a6 = const offset_of!(TrapFrame, a6),
+        f_a7 = const offset_of!(TrapFrame, a7),
+        f_s2 = const offset_of!(TrapFrame, s2),
+        f_s3 = const offset_of!(TrapFrame, s3),
+        f_s4 = const offset_of!(TrapFrame, s4),
+        f_s5 = const offset_of!(TrapFrame, s5),
+        f_s6 = const offset_of!(TrapFrame, s6),
+        f_s7 = const offset_of!(TrapFrame, s7),
+        f_s8 = const offset_of!(TrapFrame, s8),
+        f_s9 = const offset_of!(TrapFrame, s9),
+        f_s10 = const offset_of!(TrapFrame, s10),
+        f_s11 = const offset_of!(TrapFrame, s11),
+        f_t3 = const offset_of!(TrapFrame, t3),
+        f_t4 = const offset_of!(TrapFrame, t4),
+        f_t5 = const offset_of!(TrapFrame, t5),
+        f_t6 = const offset_of!(TrapFrame, t6),
+        f_sepc = const offset_of!(TrapFrame, sepc),
+        f_sstatus = const offset_of!(TrapFrame, sstatus),
+        f_stval = const offset_of!(TrapFrame, stval),
+        f_scause = const offset_of!(TrapFrame, scause),
+    )
+}
+
+#[unsafe(naked)]
+extern "C" fn user_vec() {
+    naked_asm!(
+        // sscratch holds the user trap frame while running in U-mode.
+        "csrrw a0, sscratch, a0",
+
+        // save user registers.
+        "sd ra, {u_ra}(a0)",
+        "sd sp, {u_sp}(a0)",
+        "sd gp, {u_gp}(a0)",
+        "sd tp, {u_tp}(a0)",
+        "sd t0, {u_t0}(a0)",
+        "sd t1, {u_t1}(a0)",
+        "sd t2, {u_t2}(a0)",
+        "sd s0, {u_s0}(a0)",
+        "sd s1, {u_s1}(a0)",
+        "sd a1, {u_a1}(a0)",
+        "sd a2, {u_a2}(a0)",
+        "sd a3, {u_a3}(a0)",
+        "sd a4, {u_a4}(a0)",
+        "sd a5, {u_a5}(a0)",
+        "sd a6, {u_a6}(a0)",
+        "sd a7, {u_a7}(a0)",
+        "sd s2, {u_s2}(a0)",
+        "sd s3, {u_s3}(a0)",
+        "sd s4, {u_s4}(a0)",
+        "sd s5, {u_s5}(a0)",
+        "sd s6, {u_s6}(a0)",
+        "sd s7, {u_s7}(a0)",
+        "sd s8, {u_s8}(a0)",
+        "sd s9, {u_s9}(a0)",
+        "sd s10, {u_s10}(a0)",
+        "sd s11, {u_s11}(a0)",
+        "sd t3, {u_t3}(a0)",
+        "sd t4, {u_t4}(a0)",
+        "sd t5, {u_t5}(a0)",
+        "sd t6, {u_t6}(a0)",
+        "csrr t0, sscratch",
+        "sd t0, {u_a0}(a0)",
+        "ld t0, {kernel_sscratch}(a0)",
+        "csrw sscratch, t0",
+
+        // save trap registers.
+        "csrr t0, sepc",
+        "sd t0, {u_sepc}(a0)",
+        "csrr t0, sstatus",
+        "sd t0, {u_sstatus}(a0)",
+        "csrr t0, stval",
+        "sd t0, {u_stval}(a0)",
+        "csrr t0, scause",
+        "sd t0, {u_scause}(a0)",
+
+        // switch back to the kernel context saved by enter_user.
+        "ld sp, {kernel_sp}(a0)",
+        "ld tp, {kernel_tp}(a0)",
+        "ld ra, 8 * 0(sp)",
+        "ld s0, 8 * 1(sp)",
+        "ld s1, 8 * 2(sp)",
+        "ld s2, 8 * 3(sp)",
+        "ld s3, 8 * 4(sp)",
+        "ld s4, 8 * 5(sp)",
+        "ld s5, 8 * 6(sp)",
+        "ld s6, 8 * 7(sp)",
+        "ld s7, 8 * 8(sp)",
+        "ld s8, 8 * 9(sp)",
+        "ld s9, 8 * 10(sp)",
+        "ld s10, 8 * 11(sp)",
+        "ld s11, 8 * 12(sp)",
+        "addi sp, sp, 8 * 14",
+
+        // return from enter_user.
+        "ret",
+        kernel_sp = const offset_of!(UserTrapFrame, kernel_sp),
+        kernel_tp = const offset_of!(UserTrapFrame, kernel_tp),
+        kernel_sscratch = const offset_of!(UserTrapFrame, kernel_sscratch),
+        u_ra = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, ra),
+        u_sp = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, sp),
+        u_gp = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, gp),
+        u_tp = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, tp),
+        u_t0 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, t0),
+        u_t1 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, t1),
+        u_t2 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, t2),
+        u_s0 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, s0),
+        u_s1 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, s1),
+        u_a0 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, a0),
+        u_a1 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, a1),
+        u_a2 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, a2),
+        u_a3 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, a3),
+        u_a4 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, a4),
+        u_a5 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, a5),
+        u_a6 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, a6),
+        u_a7 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, a7),
+        u_s2 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, s2),
+        u_s3 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, s3),
+        u_s4 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, s4),
+        u_s5 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, s5),
+        u_s6 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, s6),
+        u_s7 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, s7),
+        u_s8 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, s8),
+        u_s9 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, s9),
+        u_s10 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, s10),
+        u_s11 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, s11),
+        u_t3 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, t3),
+        u_t4 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, t4),
+        u_t5 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, t5),
+        u_t6 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, t6),
+        u_sepc = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, sepc),
+        u_sstatus = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, sstatus),
+        u_stval = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, stval),
+        u_scause = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, scause),
+    )
+}
+
+/// Faults in the stack pages below the stack pointer that [`enter_user`] saves
+/// the kernel registers on.
+///
+/// Faults cannot be handled while `stvec` points to the user trap vector.
+#[unsafe(naked)]
+pub extern "C" fn touch_user_entry_stack() {
+    naked_asm!("sd zero, -8 * 14(sp)", "sd zero, -8(sp)", "ret")
+}
+
+/// Switches to U-mode with the registers in `frame`.
+///
+/// Returns when the user context traps back to the kernel, with the user
+/// registers saved in `frame`.
+///
+/// # Safety
+///
+/// Interrupts must be disabled, `stvec` must point to the user trap vector
+/// and `frame.regs.sstatus` must have `SPP` set to user mode.
+#[unsafe(naked)]
+pub unsafe extern "C" fn enter_user(frame: *mut UserTrapFrame) {
+    naked_asm!(
+        // save callee-saved kernel registers.
+        "addi sp, sp, -8 * 14",
+        "sd ra, 8 * 0(sp)",
+        "sd s0, 8 * 1(sp)",
+        "sd s1, 8 * 2(sp)",
+        "sd s2, 8 * 3(sp)",
+        "sd s3, 8 * 4(sp)",
+        "sd s4, 8 * 5(sp)",
+        "sd s5, 8 * 6(sp)",
+        "sd s6, 8 * 7(sp)",
+        "sd s7, 8 * 8(sp)",
+        "sd s8, 8 * 9(sp)",
+        "sd s9, 8 * 10(sp)",
+        "sd s10, 8 * 11(sp)",
+        "sd s11, 8 * 12(sp)",
+        "sd sp, {kernel_sp}(a0)",
+        "sd tp, {kernel_tp}(a0)",
+        "csrr t0, sscratch",
+        "sd t0, {kernel_sscratch}(a0)",
+        "csrw sscratch, a0",
+
+        // restore trap registers.
+        "ld t0, {u_sepc}(a0)",
+        "csrw sepc, t0",
+        "ld t0, {u_sstatus}(a0)",
+        "csrw sstatus, t0",
+
+        // restore user registers.
+        "ld ra, {u_ra}(a0)",
+        "ld sp, {u_sp}(a0)",
+        "ld gp, {u_gp}(a0)",
+        "ld tp, {u_tp}(a0)",
+        "ld t0, {u_t0}(a0)",
+        "ld t1, {u_t1}(a0)",
+        "ld t2, {u_t2}(a0)",
+        "ld s0, {u_s0}(a0)",
+        "ld s1, {u_s1}(a0)",
+        "ld a1, {u_a1}(a0)",
+        "ld a2, {u_a2}(a0)",
+        "ld a3, {u_a3}(a0)",
+        "ld a4, {u_a4}(a0)",
+        "ld a5, {u_a5}(a0)",
+        "ld a6, {u_a6}(a0)",
+        "ld a7, {u_a7}(a0)",
+        "ld s2, {u_s2}(a0)",
+        "ld s3, {u_s3}(a0)",
+        "ld s4, {u_s4}(a0)",
+        "ld s5, {u_s5}(a0)",
+        "ld s6, {u_s6}(a0)",
+        "ld s7, {u_s7}(a0)",
+        "ld s8, {u_s8}(a0)",
+        "ld s9, {u_s9}(a0)",
+        "ld s10, {u_s10}(a0)",
+        "ld s11, {u_s11}(a0)",
+        "ld t3, {u_t3}(a0)",
+        "ld t4, {u_t4}(a0)",
+        "ld t5, {u_t5}(a0)",
+        "ld t6, {u_t6}(a0)",
+        "ld a0, {u_a0}(a0)",
+
+        "sret",
+        kernel_sp = const offset_of!(UserTrapFrame, kernel_sp),
+        kernel_tp = const offset_of!(UserTrapFrame, kernel_tp),
+        kernel_sscratch = const offset_of!(UserTrapFrame, kernel_sscratch),
+        u_ra = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, ra),
+        u_sp = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, sp),
+        u_gp = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, gp),
+        u_tp = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, tp),
+        u_t0 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, t0),
+        u_t1 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, t1),
+        u_t2 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, t2),
+        u_s0 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, s0),
+        u_s1 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, s1),
+        u_a0 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, a0),
+        u_a1 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, a1),
+        u_a2 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, a2),
+        u_a3 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, a3),
+        u_a4 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, a4),
+        u_a5 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, a5),
+        u_a6 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, a6),
+        u_a7 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, a7),
+        u_s2 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, s2),
+        u_s3 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, s3),
+        u_s4 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, s4),
+        u_s5 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, s5),
+        u_s6 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, s6),
+        u_s7 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, s7),
+        u_s8 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, s8),
+        u_s9 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, s9),
+        u_s10 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, s10),
+        u_s11 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, s11),
+        u_t3 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, t3),
+        u_t4 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, t4),
+        u_t5 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, t5),
+        u_t6 = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, t6),
+        u_sepc = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, sepc),
+        u_sstatus = const offset_of!(UserTrapFrame, regs) + offset_of!(TrapFrame, sstatus),
     )
 }
@@ -1,5 +1,19 @@
+use super::super::{TrapFrame, UserTrapFrame};
+
 pub fn apply() {
     // to suppress warnings
-    super::super::trap_kernel();
+    super::super::trap_kernel(&mut TrapFrame::default());
+    unimplemented!("unsupported architecture");
+}
+
+pub extern "C" fn save_registers(_frame: &mut TrapFrame) {
+    unimplemented!("unsupported architecture");
+}
+
+pub unsafe fn apply_user() {
+    unimplemented!("unsupported architecture");
+}
+
+pub unsafe extern "C" fn enter_user(_frame: *mut UserTrapFrame) {
     unimplemented!("unsupported architecture");
 }
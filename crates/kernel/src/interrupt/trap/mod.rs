@@ -1,66 +1,152 @@
-use alloc::sync::Arc;
-
 use riscv::{
     interrupt::{Exception, Interrupt, Trap},
     register::{
-        scause, sepc,
-        sstatus::{self, SPP},
-        stval,
+        satp::Satp,
+        scause::Scause,
+        sstatus::{self, SPP, Sstatus},
     },
 };
+use riscv_utils::asm;
+use snafu::ResultExt as _;
 use spin::Once;
 
+use self::fault::{Fault, FaultReport};
+pub use self::frame::{TrapFrame, UserTrapFrame};
 use crate::{
-    cpu,
-    drivers::irq::plic::{self, Plic, PlicContext},
+    drivers::irq::{self, cpu_intc},
+    error::GenericError,
+    interrupt::timer,
+    memory::{
+        asid::Asid,
+        kernel_space::{self, KernelStack},
+    },
+    stats::{self, Counter},
+    task::scheduler,
 };
 
+pub mod fault;
+mod frame;
+mod imp;
+
 cpu_local! {
-    static PLIC_CONTEXT: Once<(Arc<Plic>, PlicContext)> = Once::new();
+    static EXCEPTION_STACK: Once<KernelStack> = Once::new();
 }
 
-mod imp;
+pub fn apply() -> Result<(), GenericError> {
+    let stack = EXCEPTION_STACK.get().try_call_once(|| {
+        kernel_space::allocate_committed_kernel_stack()
+            .whatever_context("failed to allocate exception stack")
+    })?;
+    unsafe {
+        imp::set_exception_stack(stack.top());
+    }
+    imp::apply();
+    irq::apply();
+    Ok(())
+}
+
+/// Returns the current registers, for the post-mortem reports.
+pub fn current_registers() -> TrapFrame {
+    let mut frame = TrapFrame::default();
+    imp::save_registers(&mut frame);
+    frame
+}
+
+/// Runs the user context of `frame` in the address space of `satp` until it
+/// traps back to the kernel.
+///
+/// The ASID field of `satp` is replaced with `asid` activated on the current
+/// CPU. Interrupts taken in U-mode are handled before returning, so the task
+/// is preempted at the end of its time slice as in S-mode. The time spent in
+/// U-mode is added to the user time of the current task.
+pub fn run_user(
+    frame: &mut UserTrapFrame,
+    mut satp: Satp,
+    asid: &mut Asid,
+) -> Trap<Interrupt, Exception> {
+    let interrupt_guard = super::push_disabled();
+
+    let (asid, needs_flush) = asid.activate();
+    satp.set_asid(asid.into());
+
+    let mut sstatus = sstatus::read();
+    sstatus.set_spp(SPP::User);
+    sstatus.set_spie(true);
+    frame.regs.sstatus = sstatus.bits();
+
+    imp::touch_user_entry_stack();
+    unsafe {
+        imp::apply_user();
+    }
+    // the kernel mappings are shared with the user page table
+    let kernel_satp = unsafe { sv39::activate_satp(satp) };
+    // a rollover leaves the entries of the older generations in the TLB.
+    if needs_flush {
+        asm::sfence_vma_all();
+    }
+
+    let entered_at = timer::now();
+    unsafe {
+        imp::enter_user(frame);
+    }
+    let user_time = timer::now().saturating_duration_since(entered_at);
 
-pub fn apply() {
+    let _user_satp = unsafe { sv39::activate_satp(kernel_satp) };
     imp::apply();
-    let cpu = cpu::current();
-    let plic_ctx = plic::find_plic_context_for_cpu(cpu.id()).unwrap();
-    PLIC_CONTEXT.get().call_once(|| plic_ctx);
+    scheduler::account_user_time(user_time);
+
+    let scause: Trap<Interrupt, Exception> = Scause::from_bits(frame.regs.scause)
+        .cause()
+        .try_into()
+        .unwrap();
+    if let Trap::Interrupt(int) = scause {
+        super::cpu_state().increment_irq_depth();
+        assert!(
+            cpu_intc::handle_interrupt(int),
+            "unexpected user interrupt {int:#?}, sepc={:#x}, stval={:#x}",
+            frame.regs.sepc,
+            frame.regs.stval
+        );
+        super::cpu_state().decrement_irq_depth();
+    }
+
+    interrupt_guard.pop();
+    scause
 }
 
-pub(super) extern "C" fn trap_kernel() {
+pub(super) extern "C" fn trap_kernel(frame: &mut TrapFrame) {
     super::cpu_state().increment_irq_depth();
-    let sepc = sepc::read();
-    let sstatus = sstatus::read();
-    let stval = stval::read();
-    let scause: Trap<Interrupt, Exception> = scause::read().cause().try_into().unwrap();
+    let sstatus = Sstatus::from_bits(frame.sstatus);
+    let scause: Trap<Interrupt, Exception> =
+        Scause::from_bits(frame.scause).cause().try_into().unwrap();
 
     assert_eq!(sstatus.spp(), SPP::Supervisor, "from supervisor mode");
     assert!(!super::is_enabled());
 
     match scause {
         Trap::Exception(e) => {
-            panic!("unexpected kernel exception {e:#?}, sepc={sepc:#x}, stval={stval:#x}");
-        }
-        Trap::Interrupt(Interrupt::SupervisorTimer) => {
-            super::timer::handle_interrupt();
-        }
-        Trap::Interrupt(Interrupt::SupervisorExternal) => {
-            let (plic, plic_ctx) = PLIC_CONTEXT.get().get().unwrap();
-            let _handled = plic.handle_interrupt(*plic_ctx);
+            let fault = Fault::decode(e, frame);
+            if matches!(fault, Fault::Page { .. }) {
+                stats::incr(Counter::PageFaults);
+            }
+            assert!(
+                fault::run_hooks(&fault, frame),
+                "{}",
+                FaultReport::new(fault, frame)
+            );
         }
         Trap::Interrupt(int) => {
-            panic!("unexpected kernel interrupt {int:#?}, sepc={sepc:#x}, stval={stval:#x}");
+            assert!(
+                cpu_intc::handle_interrupt(int),
+                "unexpected kernel interrupt {int:#?}, sepc={:#x}, stval={:#x}",
+                frame.sepc,
+                frame.stval
+            );
         }
     }
 
     // yield_execution (called in timer::handle_interrupt()) may transition the
-    // current task to other CPUs, so restore trap registers.
-    unsafe {
-        sepc::write(sepc);
-    }
-    unsafe {
-        sstatus::write(sstatus);
-    }
+    // current task to other CPUs, so the trap entry path restores trap
+    // registers from the frame.
     super::cpu_state().decrement_irq_depth();
 }
@@ -0,0 +1,279 @@
+use alloc::{format, sync::Arc, vec::Vec};
+use core::{fmt, mem};
+
+use devtree::{
+    model::{node::Interrupt, property::U32Array},
+    types::ByteStr,
+};
+use snafu::{OptionExt as _, ResultExt as _};
+
+use crate::{cpu::Cpuid, error::GenericError, sync::spinlock::SpinMutex};
+
+/// Interrupt handler called in the interrupt context.
+pub type IrqHandler = Arc<dyn Fn() + Send + Sync>;
+
+/// Interrupt number local to an [`IrqDomain`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(transparent)]
+pub struct HwIrq(usize);
+
+impl fmt::Display for HwIrq {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl HwIrq {
+    pub const fn from_raw(value: usize) -> Self {
+        Self(value)
+    }
+
+    pub fn value(self) -> usize {
+        self.0
+    }
+}
+
+/// Interrupt controller that dispatches its interrupt lines to handlers.
+pub trait IrqDomain: fmt::Debug + Send + Sync {
+    /// Returns the devicetree path of the interrupt controller node.
+    fn dtree_path(&self) -> &ByteStr;
+
+    /// Translates an interrupt specifier of the devicetree into an interrupt
+    /// number.
+    fn translate(&self, specifier: &U32Array) -> Result<HwIrq, GenericError>;
+
+    fn register_handler(
+        &self,
+        hwirq: HwIrq,
+        name: &str,
+        handler: IrqHandler,
+    ) -> Result<(), GenericError>;
+
+    /// Removes the handler of a disabled interrupt.
+    ///
+    /// The handler may still be running on another CPU when this returns.
+    fn unregister_handler(&self, hwirq: HwIrq);
+
+    fn enable(&self, hwirq: HwIrq);
+    fn disable(&self, hwirq: HwIrq);
+
+    /// Restricts the CPUs the interrupt is delivered to.
+    #[expect(dead_code)]
+    fn set_affinity(&self, hwirq: HwIrq, cpus: &[Cpuid]) -> Result<(), GenericError>;
+}
+
+static IRQ_DOMAINS: SpinMutex<Vec<Arc<dyn IrqDomain>>> = SpinMutex::new(Vec::new());
+
+pub fn register_domain(domain: Arc<dyn IrqDomain>) {
+    let mut domains = IRQ_DOMAINS.lock();
+    assert!(
+        !domains
+            .iter()
+            .any(|d| d.dtree_path() == domain.dtree_path()),
+        "IRQ domain already registered for {}",
+        domain.dtree_path()
+    );
+    domains.push(domain);
+}
+
+pub fn find_domain_by_dtree_path<P>(path: P) -> Option<Arc<dyn IrqDomain>>
+where
+    P: AsRef<ByteStr>,
+{
+    let path = path.as_ref();
+    IRQ_DOMAINS
+        .lock()
+        .iter()
+        .find(|domain| domain.dtree_path() == path)
+        .cloned()
+}
+
+/// Requests the interrupt line described by a devicetree interrupt specifier.
+///
+/// The returned line is initially disabled.
+pub fn request_irq(
+    name: &str,
+    interrupt: &Interrupt<'_>,
+    handler: IrqHandler,
+) -> Result<IrqLine, GenericError> {
+    let domain =
+        find_domain_by_dtree_path(interrupt.parent_path()).with_whatever_context(|| {
+            format!(
+                "no interrupt controller found for {name}, interrupt-parent={}",
+                interrupt.parent_path()
+            )
+        })?;
+    let hwirq = domain
+        .translate(interrupt.specifier())
+        .with_whatever_context(|_| format!("invalid interrupt specifier for {name}"))?;
+    IrqLine::request(domain, hwirq, name, handler)
+}
+
+/// Interrupt line requested from an [`IrqDomain`].
+///
+/// The line is disabled and its handler is unregistered when this is dropped.
+#[derive(Debug)]
+pub struct IrqLine {
+    domain: Arc<dyn IrqDomain>,
+    hwirq: HwIrq,
+}
+
+impl IrqLine {
+    /// Requests the interrupt line `hwirq` of `domain`.
+    ///
+    /// The returned line is initially disabled.
+    pub fn request(
+        domain: Arc<dyn IrqDomain>,
+        hwirq: HwIrq,
+        name: &str,
+        handler: IrqHandler,
+    ) -> Result<Self, GenericError> {
+        domain
+            .register_handler(hwirq, name, handler)
+            .with_whatever_context(|_| {
+                format!(
+                    "failed to register handler for {name}, domain={}, hwirq={hwirq}",
+                    domain.dtree_path()
+                )
+            })?;
+        Ok(Self { domain, hwirq })
+    }
+
+    pub fn enable(&self) {
+        self.domain.enable(self.hwirq);
+    }
+
+    pub fn disable(&self) {
+        self.domain.disable(self.hwirq);
+    }
+
+    /// Keeps the line requested until the kernel stops, for the lines that
+    /// are never released, such as the per-CPU lines of the interrupt
+    /// controllers.
+    pub fn leak(self) {
+        mem::forget(self);
+    }
+
+    #[expect(dead_code)]
+    pub fn set_affinity(&self, cpus: &[Cpuid]) -> Result<(), GenericError> {
+        self.domain
+            .set_affinity(self.hwirq, cpus)
+            .with_whatever_context(|_| {
+                format!(
+                    "failed to set affinity, domain={}, hwirq={}",
+                    self.domain.dtree_path(),
+                    self.hwirq
+                )
+            })
+    }
+}
+
+impl Drop for IrqLine {
+    fn drop(&mut self) {
+        self.disable();
+        self.domain.unregister_handler(self.hwirq);
+    }
+}
+
+#[cfg(feature = "ktest")]
+pub mod ktests {
+    use alloc::{sync::Arc, vec::Vec};
+
+    use devtree::{model::property::U32Array, types::ByteStr};
+    use snafu::ensure_whatever;
+
+    use super::{HwIrq, IrqDomain, IrqHandler, IrqLine};
+    use crate::{cpu::Cpuid, error::GenericError, ktest::KernelTest, sync::spinlock::SpinMutex};
+
+    pub static TESTS: &[KernelTest] = kernel_tests![dropped_line_is_released, leaked_line_is_kept];
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Call {
+        Register(HwIrq),
+        Unregister(HwIrq),
+        Enable(HwIrq),
+        Disable(HwIrq),
+    }
+
+    /// Domain recording the calls from the interrupt lines.
+    #[derive(Debug, Default)]
+    struct RecordingDomain {
+        calls: SpinMutex<Vec<Call>>,
+    }
+
+    impl IrqDomain for RecordingDomain {
+        fn dtree_path(&self) -> &ByteStr {
+            ByteStr::new("/ktest-intc")
+        }
+
+        fn translate(&self, _specifier: &U32Array) -> Result<HwIrq, GenericError> {
+            unimplemented!()
+        }
+
+        fn register_handler(
+            &self,
+            hwirq: HwIrq,
+            _name: &str,
+            _handler: IrqHandler,
+        ) -> Result<(), GenericError> {
+            self.calls.lock().push(Call::Register(hwirq));
+            Ok(())
+        }
+
+        fn unregister_handler(&self, hwirq: HwIrq) {
+            self.calls.lock().push(Call::Unregister(hwirq));
+        }
+
+        fn enable(&self, hwirq: HwIrq) {
+            self.calls.lock().push(Call::Enable(hwirq));
+        }
+
+        fn disable(&self, hwirq: HwIrq) {
+            self.calls.lock().push(Call::Disable(hwirq));
+        }
+
+        fn set_affinity(&self, _hwirq: HwIrq, _cpus: &[Cpuid]) -> Result<(), GenericError> {
+            Ok(())
+        }
+    }
+
+    fn dropped_line_is_released() -> Result<(), GenericError> {
+        let domain = Arc::new(RecordingDomain::default());
+        let hwirq = HwIrq::from_raw(3);
+        let line = IrqLine::request(
+            Arc::clone(&domain) as Arc<dyn IrqDomain>,
+            hwirq,
+            "ktest",
+            Arc::new(|| {}),
+        )?;
+        line.enable();
+        drop(line);
+        let calls = domain.calls.lock().clone();
+        ensure_whatever!(
+            calls
+                == [
+                    Call::Register(hwirq),
+                    Call::Enable(hwirq),
+                    Call::Disable(hwirq),
+                    Call::Unregister(hwirq),
+                ],
+            "got {calls:?}"
+        );
+        Ok(())
+    }
+
+    fn leaked_line_is_kept() -> Result<(), GenericError> {
+        let domain = Arc::new(RecordingDomain::default());
+        let hwirq = HwIrq::from_raw(3);
+        let line = IrqLine::request(
+            Arc::clone(&domain) as Arc<dyn IrqDomain>,
+            hwirq,
+            "ktest",
+            Arc::new(|| {}),
+        )?;
+        line.leak();
+        let calls = domain.calls.lock().clone();
+        ensure_whatever!(calls == [Call::Register(hwirq)], "got {calls:?}");
+        Ok(())
+    }
+}
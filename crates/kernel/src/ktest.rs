@@ -0,0 +1,133 @@
+//! In-kernel test framework, enabled by the `ktest` feature.
+//!
+//! The tests run in a kernel task after boot, in place of the shell and the
+//! init process. Each test reports its result to the console, and QEMU exits
+//! with status 0 if all the tests pass, or 1 otherwise. A test fails by
+//! returning an error, or by panicking, in which case the panic handler exits
+//! at once.
+//!
+//! QEMU is exited by the test finisher device if it is bound, and by the SBI
+//! system reset extension otherwise, which may not pass on the status code.
+
+use core::hint;
+
+use sbi::system_reset::{self, ResetReason, ResetType};
+
+#[cfg(feature = "display")]
+use crate::display;
+#[cfg(feature = "input")]
+use crate::input;
+use crate::{
+    cmdline, cpu, crash_dump, drivers, drivers::test_finisher, error::GenericError, interrupt, irq,
+    memory, power, stats, sync, task, trace, tty, tunables, user, vfs,
+};
+
+/// Lists the tests of the current module for [`SUITES`].
+macro_rules! kernel_tests {
+    ($($test:ident),* $(,)?) => {
+        &[$($crate::ktest::KernelTest {
+            name: concat!(module_path!(), "::", stringify!($test)),
+            run: $test,
+        }),*]
+    };
+}
+
+/// Test suites of the modules, run in the given order.
+static SUITES: &[&[KernelTest]] = &[
+    cmdline::ktests::TESTS,
+    tty::ktests::TESTS,
+    tunables::ktests::TESTS,
+    memory::allocator::ktests::TESTS,
+    #[cfg(all(feature = "heap-cache", not(feature = "heap-debug")))]
+    memory::allocator::cache::ktests::TESTS,
+    memory::layout::ktests::TESTS,
+    memory::reserved::ktests::TESTS,
+    memory::kernel_space::ktests::TESTS,
+    memory::kernel_space::stack_ktests::TESTS,
+    memory::kernel_space::mmio_ktests::TESTS,
+    memory::kernel_space::vmalloc_ktests::TESTS,
+    interrupt::timer::instant_ktests::TESTS,
+    interrupt::timer::ktests::TESTS,
+    interrupt::timer::wheel_ktests::TESTS,
+    irq::ktests::TESTS,
+    task::scheduler::ktests::TESTS,
+    cpu::hotplug::ktests::TESTS,
+    cpu::idle::ktests::TESTS,
+    sync::channel::ktests::TESTS,
+    trace::ktests::TESTS,
+    stats::ktests::TESTS,
+    crash_dump::ktests::TESTS,
+    power::ktests::TESTS,
+    drivers::registry::ktests::TESTS,
+    drivers::rtc::ktests::TESTS,
+    #[cfg(feature = "display")]
+    display::ktests::TESTS,
+    #[cfg(feature = "display")]
+    display::text::ktests::TESTS,
+    #[cfg(feature = "input")]
+    input::keymap::ktests::TESTS,
+    user::ktests::TESTS,
+    user::wait::ktests::TESTS,
+    user::futex::ktests::TESTS,
+    user::signal::ktests::TESTS,
+    vfs::fat32_ktests::TESTS,
+];
+
+#[derive(Debug)]
+pub struct KernelTest {
+    pub name: &'static str,
+    /// Runs the test in a kernel task, and returns an error if it fails.
+    pub run: fn() -> Result<(), GenericError>,
+}
+
+/// Spawns the task running all the tests.
+pub fn spawn() -> Result<(), GenericError> {
+    task::kthread::Builder::new()
+        .name("ktest")
+        .spawn(run_tests)?;
+    Ok(())
+}
+
+fn run_tests() -> ! {
+    let tests = SUITES.iter().copied().flatten();
+    println!();
+    println!("running {} kernel tests", tests.clone().count());
+
+    let mut passed = 0;
+    let mut failed = 0;
+    for test in tests {
+        print!("test {} ... ", test.name);
+        match (test.run)() {
+            Ok(()) => {
+                println!("ok");
+                passed += 1;
+            }
+            Err(e) => {
+                println!("FAILED: {e}");
+                failed += 1;
+            }
+        }
+    }
+
+    let result = if failed == 0 { "ok" } else { "FAILED" };
+    println!();
+    println!("test result: {result}. {passed} passed; {failed} failed");
+    exit(failed == 0);
+}
+
+/// Exits QEMU with the status of the tests.
+pub fn exit(passed: bool) -> ! {
+    test_finisher::exit(u16::from(!passed));
+
+    let reason = if passed {
+        ResetReason::NoReason
+    } else {
+        ResetReason::SystemFailure
+    };
+    // nothing can be done if the reset fails, and the console may be locked
+    // by the panic handler
+    let _ = system_reset::system_reset(ResetType::Shutdown, reason);
+    loop {
+        hint::spin_loop();
+    }
+}
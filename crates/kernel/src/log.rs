@@ -2,14 +2,14 @@ use alloc::sync::Arc;
 use core::{fmt, panic::Location};
 
 use ansi_term::{Color, WithFg};
+use devtree::types::ByteStr;
+use snafu::whatever;
 
 use crate::{
-    cpu::{self, Cpu},
-    interrupt::{
-        self,
-        timer::{self, Instant},
-    },
+    cmdline::{FromParam, ParamDescriptor},
+    error::GenericError,
     task::{Task, scheduler},
+    tunables::{Tunable, TunableValue},
 };
 
 macro_rules! log {
@@ -18,14 +18,12 @@ macro_rules! log {
     };
 }
 
-#[expect(unused_macros)]
 macro_rules! trace {
     ($($arg:tt)*) => {
         log!($crate::log::LogLevel::Trace, $($arg)*);
     };
 }
 
-#[expect(unused_macros)]
 macro_rules! debug {
     ($($arg:tt)*) => {
         log!($crate::log::LogLevel::Debug, $($arg)*);
@@ -44,27 +42,39 @@ macro_rules! warn {
     };
 }
 
-#[expect(unused_macros)]
+#[cfg_attr(not(debug_assertions), expect(unused_macros))]
 macro_rules! error {
     ($($arg:tt)*) => {
         log!($crate::log::LogLevel::Error, $($arg)*);
     };
 }
 
+/// Lowest level of the messages written, also given by the `loglevel`
+/// option.
+pub static LOG_LEVEL: Tunable<LogLevel> = Tunable::new(
+    "log.level",
+    "lowest level of the messages written",
+    LogLevel::Trace,
+);
+
+pub static LOG_LEVEL_PARAM: ParamDescriptor = ParamDescriptor {
+    name: "loglevel",
+    handler: |value| LOG_LEVEL.set(LogLevel::from_param(value)?),
+};
+
 #[track_caller]
 pub fn log(level: LogLevel, message: fmt::Arguments) {
-    let interrupt_guard = interrupt::push_disabled();
-    let now = TimeFormat(timer::try_now());
+    if level < LOG_LEVEL.get() {
+        return;
+    }
+    // the time and the CPU are put by the console
     let level = LevelFormat(level);
     let task = TaskFormat(scheduler::try_current_task());
-    let cpu = CpuFormat(cpu::try_current());
     let location = LocationFormat(Location::caller());
-    interrupt_guard.pop();
 
-    println!("{now} [{task}@{cpu}] {level} {message} {location}");
+    println!("[{task}] {level} {message} {location}");
 }
 
-#[expect(dead_code)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum LogLevel {
     Trace,
@@ -74,19 +84,61 @@ pub enum LogLevel {
     Error,
 }
 
-#[derive(Debug)]
-struct TimeFormat(Option<Instant>);
-
-impl fmt::Display for TimeFormat {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self.0 {
-            Some(now) => {
-                let dur = now.duration_since_epoc();
-                write!(f, "{}.{:09}", dur.as_secs(), dur.subsec_nanos())
-            }
-            None => write!(f, "{0}.{1:09}", 0, 0),
+impl LogLevel {
+    const ALL: [Self; 5] = [
+        Self::Trace,
+        Self::Debug,
+        Self::Info,
+        Self::Warn,
+        Self::Error,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Trace => "trace",
+            Self::Debug => "debug",
+            Self::Info => "info",
+            Self::Warn => "warn",
+            Self::Error => "error",
         }
     }
+
+    fn from_name(name: &[u8]) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|level| level.name().as_bytes() == name)
+    }
+}
+
+impl FromParam for LogLevel {
+    fn from_param(value: Option<&'static ByteStr>) -> Result<Self, GenericError> {
+        let value = <&ByteStr>::from_param(value)?;
+        let Some(level) = Self::from_name(value) else {
+            whatever!("invalid log level: {value}");
+        };
+        Ok(level)
+    }
+}
+
+impl TunableValue for LogLevel {
+    fn parse(value: &str) -> Result<Self, GenericError> {
+        let Some(level) = Self::from_name(value.as_bytes()) else {
+            whatever!("invalid log level: {value}");
+        };
+        Ok(level)
+    }
+
+    fn fmt(self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+
+    fn into_raw(self) -> u64 {
+        self as u64
+    }
+
+    fn from_raw(raw: u64) -> Self {
+        Self::ALL[usize::try_from(raw).unwrap()]
+    }
 }
 
 #[derive(Debug)]
@@ -101,18 +153,6 @@ impl fmt::Display for TaskFormat {
     }
 }
 
-#[derive(Debug)]
-struct CpuFormat<'a>(Option<&'a Cpu>);
-
-impl fmt::Display for CpuFormat<'_> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self.0 {
-            Some(cpu) => write!(f, "{}", cpu.id()),
-            None => write!(f, "?"),
-        }
-    }
-}
-
 #[derive(Debug)]
 struct LevelFormat(LogLevel);
 
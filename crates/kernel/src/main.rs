@@ -6,7 +6,6 @@
 use alloc::{borrow::ToOwned as _, collections::vec_deque::VecDeque, format, sync::Arc};
 use core::{
     convert::Infallible,
-    ffi::c_void,
     hint, ptr,
     sync::atomic::{AtomicBool, Ordering},
     time::Duration,
@@ -19,31 +18,60 @@ use spin::Once;
 use self::{
     cpu::Cpuid,
     error::GenericError,
+    initcall::InitLevel,
     interrupt::timer::{self, Instant},
-    memory::{kernel_space::KernelStack, layout::HeapLayout},
+    memory::{allocator::HeapRange, kernel_space::KernelStack, layout::HeapLayout},
     sync::spinlock::{SpinMutex, SpinMutexCondVar},
-    task::{TaskId, scheduler},
+    task::{TaskId, kthread, scheduler},
 };
 
 extern crate alloc;
 
+#[macro_use]
+mod cpu_local;
 #[macro_use]
 mod console;
 #[macro_use]
 mod log;
 #[macro_use]
-mod cpu_local;
+mod initcall;
+#[cfg(feature = "ktest")]
+#[macro_use]
+mod ktest;
+#[macro_use]
+mod trace;
 
+mod block;
 mod boot;
 mod chosen;
+mod cmdline;
 mod cpu;
+mod crash_dump;
+#[cfg(feature = "display")]
+mod display;
 mod drivers;
 mod error;
+mod initramfs;
+#[cfg(feature = "input")]
+mod input;
 mod interrupt;
+mod irq;
 mod iter;
 mod memory;
+#[cfg(feature = "net")]
+mod net;
+mod power;
+mod rand;
+#[cfg(feature = "shell")]
+mod shell;
+mod stats;
 mod sync;
 mod task;
+mod time;
+mod tty;
+mod tunables;
+mod user;
+mod vfs;
 
 const ONIX_VERSION: &str = env!("CARGO_PKG_VERSION");
 // Generated by https://www.asciiart.eu/text-to-ascii-art
@@ -61,6 +89,7 @@ static DEVICETREE: Once<OwnedDevicetree> = Once::new();
 
 fn primary_cpu_entry(cpuid: Cpuid, dtb_pa: usize) -> Result<KernelStack, GenericError> {
     memory::allocator::init();
+    console::init();
 
     println!();
     println!();
@@ -68,34 +97,41 @@ fn primary_cpu_entry(cpuid: Cpuid, dtb_pa: usize) -> Result<KernelStack, Generic
     println!("{ONIX_LOGO}");
 
     let dt = DEVICETREE.try_call_once(|| {
-        let dt = unsafe { Devicetree::from_ptr(ptr::with_exposed_provenance(dtb_pa)) }
+        unsafe { Devicetree::with_ptr(ptr::with_exposed_provenance(dtb_pa), Devicetree::to_owned) }
             .with_whatever_context(|_| {
                 format!("failed to parse devicetree at physical address {dtb_pa:#x}")
-            })?;
-        Ok(dt.to_owned())
+            })
     })?;
 
-    let heap_layout =
-        HeapLayout::new(dt).whatever_context("failed to compute heap layout from devicetree")?;
+    chosen::init(dt).whatever_context("failed to initialize chosen node")?;
+    cmdline::init();
+
+    let boot_info = memory::layout::init_boot_info(dtb_pa, dt.as_bytes().len());
+    let heap_layout = HeapLayout::new(dt, boot_info)
+        .whatever_context("failed to compute heap layout from devicetree")?;
     unsafe {
         memory::allocator::add_heap_ranges(heap_layout.heap_ranges());
     }
 
-    chosen::init(dt).whatever_context("failed to initialize chosen node")?;
-
-    cpu::init(dt).whatever_context("failed to initialize CPU table")?;
+    cpu::init(dt, cpuid).whatever_context("failed to initialize CPU table")?;
     cpu_local::init();
     cpu_local::apply(cpuid);
     cpu::set_current_cpuid(cpuid);
     interrupt::init(cpuid);
+    initcall::run(InitLevel::Early)?;
     memory::kernel_space::init().whatever_context("failed to initialize kernel space")?;
     memory::layout::update_kernel_page_table(&heap_layout)
         .whatever_context("failed to update kernel page table")?;
     memory::kernel_space::apply();
+    initcall::run(InitLevel::Memory)?;
 
-    let stack = memory::kernel_space::allocate_kernel_stack()
+    let stack = memory::kernel_space::allocate_committed_kernel_stack()
         .with_whatever_context(|_| format!("failed to allocate kernel stack for CPU#{cpuid}"))?;
 
+    #[cfg(debug_assertions)]
+    memory::kernel_space::verify(&heap_layout)
+        .whatever_context("failed to verify kernel page table")?;
+
     Ok(stack)
 }
 
@@ -104,12 +140,28 @@ fn secondary_cpu_entry(cpuid: Cpuid) -> Result<KernelStack, GenericError> {
     cpu::set_current_cpuid(cpuid);
     memory::kernel_space::apply();
 
-    let stack = memory::kernel_space::allocate_kernel_stack()
+    let stack = memory::kernel_space::allocate_committed_kernel_stack()
         .with_whatever_context(|_| format!("failed to allocate kernel stack for CPU#{cpuid}"))?;
 
     Ok(stack)
 }
 
+/// Restarts the secondary CPU stopped by [`cpu::hotplug::offline`].
+///
+/// The per-CPU state set up by the first start is kept, and only the CPU
+/// registers are set up again.
+fn secondary_cpu_restart(cpuid: Cpuid) -> Result<Infallible, GenericError> {
+    cpu_local::apply(cpuid);
+    cpu::hotplug::mark_online();
+    memory::kernel_space::apply();
+    interrupt::trap::apply().whatever_context("failed to initialize trap handling")?;
+    cpu::hotplug::run_online_hooks();
+
+    info!("CPU restarted");
+
+    task::scheduler::start()
+}
+
 fn main(is_primary: bool) -> Result<Infallible, GenericError> {
     static INIT_COMPLETED: AtomicBool = AtomicBool::new(false);
 
@@ -118,10 +170,8 @@ fn main(is_primary: bool) -> Result<Infallible, GenericError> {
             start_secondary_cpus();
         }
 
-        let dt = DEVICETREE.get().unwrap();
-        drivers::irq::plic::init(dt)
-            .whatever_context("failed to initialize PLIC device drivers")?;
-        drivers::serial::init(dt).whatever_context("failed to initialize serial device drivers")?;
+        initcall::run(InitLevel::Drivers)?;
+        initcall::run(InitLevel::Late)?;
 
         INIT_COMPLETED.store(true, Ordering::Release);
     } else {
@@ -130,15 +180,22 @@ fn main(is_primary: bool) -> Result<Infallible, GenericError> {
         }
     }
 
-    drivers::serial::apply();
-    interrupt::trap::apply();
-    interrupt::timer::start();
+    interrupt::trap::apply().whatever_context("failed to initialize trap handling")?;
+    interrupt::timer::start().whatever_context("failed to start timer")?;
+    interrupt::ipi::start().whatever_context("failed to start IPI handling")?;
 
     info!("CPU initialized");
 
     if is_primary {
-        spawn_test_tasks();
-        spawn_console_task();
+        if cfg!(feature = "ktest") {
+            #[cfg(feature = "ktest")]
+            ktest::spawn().whatever_context("failed to spawn kernel test task")?;
+        } else {
+            spawn_test_tasks();
+            #[cfg(feature = "shell")]
+            shell::spawn();
+            user::spawn_init().whatever_context("failed to spawn init user task")?;
+        }
     }
 
     task::scheduler::start()
@@ -156,7 +213,10 @@ unsafe fn start_secondary_cpus() {
 
     // reuse boot stack as heap
     unsafe {
-        memory::allocator::add_heap_ranges([memory::layout::kernel_boot_stack_range()]);
+        memory::allocator::add_heap_ranges([HeapRange {
+            range: memory::layout::boot_info().boot_stack.clone(),
+            numa_node: None,
+        }]);
     }
 }
 
@@ -166,29 +226,6 @@ struct TaskState {
     message_received: SpinMutexCondVar,
 }
 
-fn spawn_console_task() {
-    task::spawn(console_task, ptr::null_mut()).unwrap();
-}
-
-extern "C" fn console_task(_arg: *mut c_void) -> ! {
-    let stdout_path = chosen::stdout_path().unwrap();
-    let stdin_path = chosen::stdin_path().unwrap();
-
-    let serial_stdout = drivers::serial::find_serial_by_dtree_path(stdout_path).unwrap();
-    let serial_stdin = drivers::serial::find_serial_by_dtree_path(stdin_path).unwrap();
-    loop {
-        let mut bytes = [0; 64];
-        let nread = serial_stdin.read(&mut bytes);
-        if nread > 0 {
-            // echo back
-            let mut nwritten = 0;
-            while nwritten < nread {
-                nwritten += serial_stdout.write(&bytes[..nread][nwritten..]);
-            }
-        }
-    }
-}
-
 fn spawn_test_tasks() {
     let state = Arc::new(TaskState {
         queue: SpinMutex::new(VecDeque::new()),
@@ -196,21 +233,25 @@ fn spawn_test_tasks() {
         message_received: SpinMutexCondVar::new(),
     });
 
-    task::spawn(rx_task, Arc::into_raw(Arc::clone(&state)).cast_mut().cast()).unwrap();
-    task::spawn(rx_task, Arc::into_raw(Arc::clone(&state)).cast_mut().cast()).unwrap();
-    task::spawn(rx_task, Arc::into_raw(Arc::clone(&state)).cast_mut().cast()).unwrap();
-    task::spawn(rx_task, Arc::into_raw(Arc::clone(&state)).cast_mut().cast()).unwrap();
-    task::spawn(tx_task, Arc::into_raw(Arc::clone(&state)).cast_mut().cast()).unwrap();
-    task::spawn(tx_task, Arc::into_raw(Arc::clone(&state)).cast_mut().cast()).unwrap();
-    task::spawn(tx_task, Arc::into_raw(Arc::clone(&state)).cast_mut().cast()).unwrap();
-    task::spawn(tx_task, Arc::into_raw(Arc::clone(&state)).cast_mut().cast()).unwrap();
+    for _ in 0..4 {
+        let state = Arc::clone(&state);
+        kthread::Builder::new()
+            .name("test-rx")
+            .spawn(move || rx_task(&state))
+            .unwrap();
+    }
+    for _ in 0..4 {
+        let state = Arc::clone(&state);
+        kthread::Builder::new()
+            .name("test-tx")
+            .spawn(move || tx_task(&state))
+            .unwrap();
+    }
 }
 
-extern "C" fn tx_task(arg: *mut c_void) -> ! {
+fn tx_task(state: &TaskState) -> ! {
     let task_id = scheduler::current_task().id();
 
-    let state: Arc<TaskState> = unsafe { Arc::from_raw(arg.cast()) };
-
     let mut i = 0;
     let mut queue = state.queue.lock();
     loop {
@@ -227,8 +268,7 @@ extern "C" fn tx_task(arg: *mut c_void) -> ! {
     }
 }
 
-extern "C" fn rx_task(arg: *mut c_void) -> ! {
-    let state: Arc<TaskState> = unsafe { Arc::from_raw(arg.cast()) };
+fn rx_task(state: &TaskState) -> ! {
     let task = scheduler::current_task();
 
     let mut handled = 0;
@@ -0,0 +1,126 @@
+//! Per-CPU caches of the small heap blocks.
+//!
+//! Each CPU keeps a [`BlockCache`] in front of the heaps, so that most of the
+//! small allocations and deallocations do not take the heap lock. The cache is
+//! refilled from and trimmed to the heaps in batches under the heap lock, and
+//! flushed when the CPU goes offline.
+//!
+//! Only the allocations with the default hint go through the caches, so the
+//! cached blocks are those of the heaps preferred by the CPU.
+
+use core::alloc::Layout;
+
+use allocator::block_cache::{BlockCache, BlockSource};
+
+use super::{ALLOCATOR, KernelAllocator, default_hint};
+use crate::{
+    cpu::hotplug::HotplugHook,
+    sync::spinlock::{self, IrqSpinMutex},
+};
+
+cpu_local! {
+    static CACHE: IrqSpinMutex<BlockCache> =
+        IrqSpinMutex::with_class(BlockCache::new(), &spinlock::classes::HEAP_CACHE);
+}
+
+pub static HOTPLUG_HOOK: HotplugHook = HotplugHook {
+    name: "heap-cache",
+    online: || {},
+    offline: flush,
+};
+
+impl BlockSource for KernelAllocator {
+    fn allocate_block(&mut self, layout: Layout) -> Option<*mut u8> {
+        Self::allocate_block(self, layout, default_hint())
+    }
+
+    unsafe fn deallocate_block(&mut self, ptr: *mut u8, layout: Layout) {
+        unsafe { Self::deallocate_block(self, ptr, layout) }
+    }
+}
+
+/// Allocates a block from the cache of the current CPU, refilling the cache
+/// from the heaps if it is empty.
+///
+/// Returns `None` if the layout is not cached, the per-CPU data is not set up
+/// yet, or the heaps are exhausted.
+pub(super) fn allocate(layout: Layout) -> Option<*mut u8> {
+    if !BlockCache::is_cacheable(layout) {
+        return None;
+    }
+    let mut cache = CACHE.try_get()?.lock();
+    let ptr = cache.allocate(layout).or_else(|| {
+        let mut allocator = ALLOCATOR.0.lock();
+        cache.refill(layout, &mut *allocator);
+        allocator.unlock();
+        cache.allocate(layout)
+    });
+    cache.unlock();
+    ptr
+}
+
+/// Returns a block to the cache of the current CPU, trimming the cache to the
+/// heaps if it is full.
+///
+/// Returns `false` if the block is not cached, in which case the caller frees
+/// it to the heaps.
+pub(super) unsafe fn deallocate(ptr: *mut u8, layout: Layout) -> bool {
+    if !BlockCache::is_cacheable(layout) {
+        return false;
+    }
+    let Some(cache) = CACHE.try_get() else {
+        return false;
+    };
+    let mut cache = cache.lock();
+    let cached = unsafe { cache.deallocate(ptr, layout) } || {
+        let mut allocator = ALLOCATOR.0.lock();
+        cache.trim(layout, &mut *allocator);
+        allocator.unlock();
+        unsafe { cache.deallocate(ptr, layout) }
+    };
+    cache.unlock();
+    cached
+}
+
+/// Returns the blocks cached by the current CPU to the heaps.
+fn flush() {
+    let mut cache = CACHE.get().lock();
+    let mut allocator = ALLOCATOR.0.lock();
+    cache.flush(&mut *allocator);
+    allocator.unlock();
+    cache.unlock();
+}
+
+#[cfg(feature = "ktest")]
+pub mod ktests {
+    use alloc::alloc::{alloc, dealloc};
+    use core::alloc::Layout;
+
+    use snafu::ensure_whatever;
+
+    use crate::{error::GenericError, interrupt, ktest::KernelTest};
+
+    pub static TESTS: &[KernelTest] = kernel_tests![reuse_freed_block];
+
+    fn reuse_freed_block() -> Result<(), GenericError> {
+        let layout = Layout::from_size_align(40, 8).unwrap();
+        // stays on the CPU, so both allocations use the same cache
+        let interrupt_guard = interrupt::push_disabled();
+        let first = unsafe { alloc(layout) };
+        ensure_whatever!(!first.is_null(), "failed to allocate");
+        unsafe {
+            dealloc(first, layout);
+        }
+        let second = unsafe { alloc(layout) };
+        ensure_whatever!(!second.is_null(), "failed to allocate");
+        unsafe {
+            dealloc(second, layout);
+        }
+        drop(interrupt_guard);
+        ensure_whatever!(
+            first == second,
+            "freed block {first:p} not reused, got {second:p}"
+        );
+        Ok(())
+    }
+}
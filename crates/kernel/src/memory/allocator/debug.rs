@@ -0,0 +1,340 @@
+//! Heap debugging enabled by the `heap-debug` feature.
+//!
+//! Each allocation is surrounded by redzones, which are checked when it is
+//! freed. Freed blocks are filled with a poison pattern and kept in a
+//! quarantine before being returned to the heap, and the pattern is checked
+//! when the block leaves the quarantine to detect writes after free. The live
+//! allocations are recorded with the backtraces of their callers for the leak
+//! reports.
+
+use core::{alloc::Layout, arch::asm, ptr, slice};
+
+/// Minimum size of the redzones before and after an allocation.
+const REDZONE: usize = 16;
+const REDZONE_BYTE: u8 = 0xfb;
+const FREED_BYTE: u8 = 0xfd;
+/// Maximum number of the blocks in the quarantine.
+const QUARANTINE_LEN: usize = 512;
+/// Maximum number of the bytes held by the quarantine.
+const QUARANTINE_BYTES: usize = 4 * 1024 * 1024;
+/// Maximum number of the tracked live allocations, a power of two.
+const MAX_TRACKED: usize = 8192;
+const _: () = assert!(MAX_TRACKED.is_power_of_two());
+/// Maximum distance between two adjacent frames on the stack.
+const MAX_FRAME_SIZE: usize = 64 * 1024;
+
+pub const BACKTRACE_DEPTH: usize = 8;
+
+/// Return addresses of the callers of an allocation, innermost first.
+///
+/// The first frames are in the allocation functions of `alloc`. Unused
+/// entries are zero.
+pub type Backtrace = [usize; BACKTRACE_DEPTH];
+
+/// Returns the layout of the block holding an allocation of `layout` with
+/// the redzones, and the offset of the allocation in the block.
+pub(super) fn block_layout(layout: Layout) -> Option<(Layout, usize)> {
+    let offset = usize::max(layout.align(), REDZONE);
+    let size = offset.checked_add(layout.size())?.checked_add(REDZONE)?;
+    let block = Layout::from_size_align(size, offset).ok()?;
+    Some((block, offset))
+}
+
+/// Returns the backtrace of the caller, walking the frame pointers.
+#[inline(never)]
+fn backtrace() -> Backtrace {
+    let mut backtrace = [0; BACKTRACE_DEPTH];
+    let mut fp: usize;
+    unsafe {
+        asm!("mv {0}, s0", out(reg) fp);
+    }
+    for slot in &mut backtrace {
+        if fp == 0 || !fp.is_multiple_of(size_of::<usize>()) {
+            break;
+        }
+        // the return address and the caller's frame pointer are saved just
+        // below the frame pointer
+        let frame = ptr::with_exposed_provenance::<usize>(fp);
+        let (ra, next_fp) = unsafe { (frame.sub(1).read(), frame.sub(2).read()) };
+        *slot = ra;
+        if next_fp <= fp || next_fp - fp > MAX_FRAME_SIZE {
+            break;
+        }
+        fp = next_fp;
+    }
+    backtrace
+}
+
+#[derive(Debug, Clone, Copy)]
+struct TrackedAlloc {
+    addr: usize,
+    size: usize,
+    /// Sequence number of the allocation, for the leak reports since a mark.
+    seq: u64,
+    backtrace: Backtrace,
+}
+
+/// Freed block to be put into the quarantine.
+#[derive(Debug, Clone, Copy)]
+#[must_use]
+pub(super) struct QuarantinedBlock {
+    block_addr: usize,
+    block: Layout,
+    offset: usize,
+    size: usize,
+    backtrace: Option<Backtrace>,
+}
+
+/// Statistics of the heap debugging.
+#[derive(Debug, Clone, Copy)]
+pub struct HeapDebugStats {
+    /// Number of the tracked live allocations.
+    pub tracked: usize,
+    /// Number of the live allocations not tracked as the registry was full.
+    pub untracked: usize,
+    pub quarantined: usize,
+    pub quarantined_bytes: usize,
+}
+
+pub(super) struct HeapDebug {
+    quarantine: [Option<QuarantinedBlock>; QUARANTINE_LEN],
+    /// Index of the oldest block in the quarantine.
+    quarantine_head: usize,
+    quarantine_len: usize,
+    quarantine_bytes: usize,
+    /// Open addressing table of the live allocations, keyed by the address.
+    allocs: [Option<TrackedAlloc>; MAX_TRACKED],
+    tracked: usize,
+    untracked: usize,
+    seq: u64,
+    mark: u64,
+}
+
+impl HeapDebug {
+    // only evaluated at compile time for the global allocator
+    #[expect(clippy::large_stack_arrays, clippy::large_stack_frames)]
+    pub(super) const fn new() -> Self {
+        Self {
+            quarantine: [None; QUARANTINE_LEN],
+            quarantine_head: 0,
+            quarantine_len: 0,
+            quarantine_bytes: 0,
+            allocs: [None; MAX_TRACKED],
+            tracked: 0,
+            untracked: 0,
+            seq: 0,
+            mark: 0,
+        }
+    }
+
+    pub(super) fn stats(&self) -> HeapDebugStats {
+        HeapDebugStats {
+            tracked: self.tracked,
+            untracked: self.untracked,
+            quarantined: self.quarantine_len,
+            quarantined_bytes: self.quarantine_bytes,
+        }
+    }
+
+    /// Fills the redzones of a newly allocated block and records the
+    /// allocation.
+    ///
+    /// Returns the pointer to the allocation.
+    pub(super) unsafe fn on_allocate(
+        &mut self,
+        block_ptr: *mut u8,
+        layout: Layout,
+        offset: usize,
+    ) -> *mut u8 {
+        unsafe {
+            block_ptr.write_bytes(REDZONE_BYTE, offset);
+            block_ptr
+                .add(offset + layout.size())
+                .write_bytes(REDZONE_BYTE, REDZONE);
+        }
+        let ptr = unsafe { block_ptr.add(offset) };
+        self.track(ptr.addr(), layout.size());
+        ptr
+    }
+
+    /// Checks the redzones of a block being freed, and poisons it.
+    ///
+    /// The block must be put into the quarantine by [`Self::quarantine`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the redzones are overwritten or the allocation is freed
+    /// twice.
+    pub(super) unsafe fn on_deallocate(
+        &mut self,
+        ptr: *mut u8,
+        layout: Layout,
+        block: Layout,
+        offset: usize,
+    ) -> QuarantinedBlock {
+        let tracked = self.untrack(ptr.addr());
+        assert!(
+            tracked.is_some() || self.untracked > 0,
+            "heap: double free or invalid free of {ptr:p} (size {})",
+            layout.size()
+        );
+        let backtrace = tracked.map(|alloc| alloc.backtrace);
+        if tracked.is_none() {
+            self.untracked -= 1;
+        }
+
+        let block_ptr = unsafe { ptr.sub(offset) };
+        let head = unsafe { slice::from_raw_parts(block_ptr, offset) };
+        let tail = unsafe { slice::from_raw_parts(ptr.add(layout.size()), REDZONE) };
+        for (redzone, name) in [(head, "underflow"), (tail, "overflow")] {
+            assert!(
+                redzone.iter().all(|&b| b == REDZONE_BYTE),
+                "heap: buffer {name} detected on free of {ptr:p} (size {}), allocated at \
+                 {backtrace:#x?}",
+                layout.size()
+            );
+        }
+        unsafe {
+            ptr.write_bytes(FREED_BYTE, layout.size());
+        }
+        QuarantinedBlock {
+            block_addr: block_ptr.expose_provenance(),
+            block,
+            offset,
+            size: layout.size(),
+            backtrace,
+        }
+    }
+
+    /// Puts a freed block into the quarantine.
+    ///
+    /// The quarantine must have room for the block, made by
+    /// [`Self::evict_for`].
+    pub(super) fn quarantine(&mut self, block: QuarantinedBlock) {
+        assert!(self.quarantine_len < QUARANTINE_LEN);
+        let index = (self.quarantine_head + self.quarantine_len) % QUARANTINE_LEN;
+        self.quarantine_bytes += block.block.size();
+        self.quarantine_len += 1;
+        self.quarantine[index] = Some(block);
+    }
+
+    /// Takes the oldest block from the quarantine if it is full for a block
+    /// of `size` bytes.
+    ///
+    /// The returned block can be returned to the heap.
+    pub(super) fn evict_for(&mut self, size: usize) -> Option<(*mut u8, Layout)> {
+        let full = self.quarantine_len == QUARANTINE_LEN
+            || self.quarantine_bytes + size > QUARANTINE_BYTES;
+        if !full {
+            return None;
+        }
+        self.evict_oldest()
+    }
+
+    /// Takes the oldest block from the quarantine.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the block is written after it is freed.
+    pub(super) fn evict_oldest(&mut self) -> Option<(*mut u8, Layout)> {
+        if self.quarantine_len == 0 {
+            return None;
+        }
+        let block = self.quarantine[self.quarantine_head].take().unwrap();
+        self.quarantine_head = (self.quarantine_head + 1) % QUARANTINE_LEN;
+        self.quarantine_len -= 1;
+        self.quarantine_bytes -= block.block.size();
+
+        let block_ptr = ptr::with_exposed_provenance_mut::<u8>(block.block_addr);
+        let ptr = unsafe { block_ptr.add(block.offset) };
+        let data = unsafe { slice::from_raw_parts(ptr, block.size) };
+        if let Some(pos) = data.iter().position(|&b| b != FREED_BYTE) {
+            panic!(
+                "heap: use after free detected at {:p} in {ptr:p} (size {}), allocated at {:#x?}",
+                data[pos..].as_ptr(),
+                block.size,
+                block.backtrace,
+            );
+        }
+        Some((block_ptr, block.block))
+    }
+
+    /// Starts the next leak report from the allocations made after now.
+    pub(super) fn mark(&mut self) {
+        self.mark = self.seq;
+    }
+
+    /// Calls `f` with the size and the backtrace of each live allocation made
+    /// after the last mark.
+    pub(super) fn for_each_leak<F>(&self, mut f: F)
+    where
+        F: FnMut(usize, &Backtrace),
+    {
+        for alloc in self.allocs.iter().flatten() {
+            if alloc.seq >= self.mark {
+                f(alloc.size, &alloc.backtrace);
+            }
+        }
+    }
+
+    fn slot_of(addr: usize) -> usize {
+        // allocations are at least 16 bytes aligned
+        (addr >> 4).wrapping_mul(0x9e37_79b9_7f4a_7c15) % MAX_TRACKED
+    }
+
+    fn track(&mut self, addr: usize, size: usize) {
+        // keep a slot free so that the probes always terminate
+        if self.tracked + 1 >= MAX_TRACKED {
+            self.untracked += 1;
+            return;
+        }
+        let mut slot = Self::slot_of(addr);
+        while self.allocs[slot].is_some() {
+            slot = (slot + 1) % MAX_TRACKED;
+        }
+        self.allocs[slot] = Some(TrackedAlloc {
+            addr,
+            size,
+            seq: self.seq,
+            backtrace: backtrace(),
+        });
+        self.seq += 1;
+        self.tracked += 1;
+    }
+
+    fn untrack(&mut self, addr: usize) -> Option<TrackedAlloc> {
+        let mut slot = Self::slot_of(addr);
+        loop {
+            let alloc = self.allocs[slot]?;
+            if alloc.addr == addr {
+                break;
+            }
+            slot = (slot + 1) % MAX_TRACKED;
+        }
+        let removed = self.allocs[slot].take();
+        self.tracked -= 1;
+
+        // shift the following entries of the probe sequence back into the
+        // hole, so that the lookups do not stop at it
+        let mut hole = slot;
+        let mut next = (slot + 1) % MAX_TRACKED;
+        while let Some(alloc) = self.allocs[next] {
+            let home = Self::slot_of(alloc.addr);
+            let distance_to_home = (next + MAX_TRACKED - home) % MAX_TRACKED;
+            let distance_to_hole = (next + MAX_TRACKED - hole) % MAX_TRACKED;
+            if distance_to_home >= distance_to_hole {
+                self.allocs[hole] = self.allocs[next].take();
+                hole = next;
+            }
+            next = (next + 1) % MAX_TRACKED;
+        }
+        removed
+    }
+}
+
+impl QuarantinedBlock {
+    /// Returns the size of the block including the redzones.
+    pub(super) fn size(&self) -> usize {
+        self.block.size()
+    }
+}
@@ -0,0 +1,621 @@
+use alloc::vec::Vec;
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    ops::Range,
+    ptr,
+};
+
+use allocator::fixed_size_block::{self, FixedSizeBlockAllocator};
+use range_set::RangeSet;
+use spin::Once;
+
+#[cfg(feature = "heap-debug")]
+pub use self::debug::{Backtrace, HeapDebugStats};
+use crate::{
+    cpu,
+    memory::{Align as _, PAGE_SIZE},
+    stats::{self, Counter},
+    sync::spinlock::{self, SpinMutex},
+};
+
+#[cfg(all(feature = "heap-cache", not(feature = "heap-debug")))]
+pub mod cache;
+#[cfg(feature = "heap-debug")]
+mod debug;
+
+#[global_allocator]
+static ALLOCATOR: LockedKernelAllocator = LockedKernelAllocator::new();
+
+/// End of the memory that the devices limited to 32-bit DMA addresses can
+/// access.
+const DMA32_END: usize = 1 << 32;
+/// Maximum number of the heaps, one for each pair of a zone and a NUMA node.
+const MAX_HEAPS: usize = 8;
+/// Size of the memory added to the heap at boot.
+///
+/// The rest of the memory is kept in the pool, and added to the heap as it
+/// runs low.
+const INITIAL_HEAP_SIZE: usize = 64 * 1024 * 1024;
+/// Free heap space below which the heap grows from the pool.
+const LOW_WATERMARK: usize = 4 * 1024 * 1024;
+/// Size of the memory added from the pool each time the heap grows.
+const GROW_SIZE: usize = 16 * 1024 * 1024;
+const MAX_POOL_RANGES: usize = 64;
+
+/// Physical memory zone, partitioned by the addresses the devices can access.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryZone {
+    /// Memory below 4 GiB.
+    Dma32,
+    /// Memory above 4 GiB.
+    Normal,
+}
+
+impl MemoryZone {
+    fn of(addr: usize) -> Self {
+        if addr < DMA32_END {
+            Self::Dma32
+        } else {
+            Self::Normal
+        }
+    }
+}
+
+/// Hint for choosing the heap that memory is allocated from.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AllocHint {
+    /// Zone the memory must be allocated from.
+    ///
+    /// If `None`, [`MemoryZone::Normal`] is preferred so that the low memory
+    /// is kept for the devices that require it.
+    pub zone: Option<MemoryZone>,
+    /// NUMA node preferred for the memory.
+    ///
+    /// The memory of the other nodes is used if the node has no free memory.
+    pub numa_node: Option<u32>,
+}
+
+/// Physical memory range added to the heap.
+#[derive(Debug, Clone)]
+pub struct HeapRange {
+    pub range: Range<usize>,
+    /// NUMA node of the memory, or `None` if unknown.
+    pub numa_node: Option<u32>,
+}
+
+struct Heap {
+    zone: MemoryZone,
+    numa_node: Option<u32>,
+    allocator: FixedSizeBlockAllocator,
+    ranges: RangeSet<128>,
+}
+
+struct KernelAllocator {
+    heaps: [Option<Heap>; MAX_HEAPS],
+    /// Memory not added to the heap yet.
+    ///
+    /// The pool is identity mapped with the rest of the memory, so growing the
+    /// heap does not update the page tables, which are allocated from the
+    /// heap.
+    pool: [Option<HeapRange>; MAX_POOL_RANGES],
+    heap_size: usize,
+    /// Bytes of the blocks used by the live allocations, including the blocks
+    /// cached by the CPUs.
+    allocated: usize,
+    #[cfg(feature = "heap-debug")]
+    debug: debug::HeapDebug,
+}
+
+impl KernelAllocator {
+    // only evaluated at compile time for the global allocator
+    #[expect(clippy::large_stack_arrays)]
+    #[cfg_attr(feature = "heap-debug", expect(clippy::large_stack_frames))]
+    const fn new() -> Self {
+        Self {
+            heaps: [const { None }; MAX_HEAPS],
+            pool: [const { None }; MAX_POOL_RANGES],
+            heap_size: 0,
+            allocated: 0,
+            #[cfg(feature = "heap-debug")]
+            debug: debug::HeapDebug::new(),
+        }
+    }
+
+    fn free_size(&self) -> usize {
+        self.heap_size.saturating_sub(self.allocated)
+    }
+
+    /// Adds `range` to the heap up to the initial heap size, and the rest to
+    /// the pool.
+    unsafe fn add_memory(&mut self, range: Range<usize>, numa_node: Option<u32>) {
+        let heap_len = usize::min(
+            INITIAL_HEAP_SIZE.saturating_sub(self.heap_size),
+            range.len(),
+        )
+        .page_align_up()
+        .min(range.len());
+        let (heap_range, pool_range) = (
+            range.start..range.start + heap_len,
+            range.start + heap_len..range.end,
+        );
+        if !heap_range.is_empty() {
+            unsafe {
+                self.add_heap(heap_range, numa_node);
+            }
+        }
+        if !pool_range.is_empty() {
+            let slot = self
+                .pool
+                .iter_mut()
+                .find(|slot| slot.is_none())
+                .expect("too many memory ranges in the heap pool");
+            *slot = Some(HeapRange {
+                range: pool_range,
+                numa_node,
+            });
+        }
+    }
+
+    /// Adds at least `size` bytes from the pool to the heap, preferring the
+    /// memory matching `hint`.
+    ///
+    /// Returns the number of bytes added, which is less than `size` if the
+    /// pool runs out.
+    fn grow(&mut self, size: usize, hint: AllocHint) -> usize {
+        let mut grown = 0;
+        while grown < size {
+            let Some(index) = self.find_pool_range(hint) else {
+                break;
+            };
+            let pool_range = self.pool[index].as_mut().unwrap();
+            let len = (size - grown).page_align_up().min(pool_range.range.len());
+            let range = pool_range.range.start..pool_range.range.start + len;
+            let numa_node = pool_range.numa_node;
+            pool_range.range.start += len;
+            if pool_range.range.is_empty() {
+                self.pool[index] = None;
+            }
+            unsafe {
+                self.add_heap(range, numa_node);
+            }
+            grown += len;
+        }
+        grown
+    }
+
+    fn find_pool_range(&self, hint: AllocHint) -> Option<usize> {
+        let matches = |pool_range: &HeapRange, local: bool| {
+            let in_zone = hint
+                .zone
+                .is_none_or(|zone| MemoryZone::of(pool_range.range.start) == zone);
+            let is_local = hint.numa_node.is_none() || pool_range.numa_node == hint.numa_node;
+            in_zone && (is_local || !local)
+        };
+        [true, false].into_iter().find_map(|local| {
+            self.pool.iter().position(|pool_range| {
+                pool_range
+                    .as_ref()
+                    .is_some_and(|pool_range| matches(pool_range, local))
+            })
+        })
+    }
+
+    unsafe fn add_heap(&mut self, range: Range<usize>, numa_node: Option<u32>) {
+        // split the range at the zone boundary
+        let pieces = [
+            range.start..usize::min(range.end, DMA32_END),
+            usize::max(range.start, DMA32_END)..range.end,
+        ];
+        for range in pieces.into_iter().filter(|range| !range.is_empty()) {
+            let zone = MemoryZone::of(range.start);
+            let heap = self.heap_mut(zone, numa_node);
+            unsafe {
+                heap.allocator
+                    .add_heap(ptr::with_exposed_provenance_mut(range.start), range.len());
+            }
+            let len = range.len();
+            heap.ranges.insert(range);
+            self.heap_size += len;
+        }
+    }
+
+    fn heap_mut(&mut self, zone: MemoryZone, numa_node: Option<u32>) -> &mut Heap {
+        let index = self
+            .heaps
+            .iter()
+            .position(|heap| {
+                heap.as_ref()
+                    .is_none_or(|heap| heap.zone == zone && heap.numa_node == numa_node)
+            })
+            .expect("too many heaps");
+        self.heaps[index].get_or_insert_with(|| Heap {
+            zone,
+            numa_node,
+            allocator: FixedSizeBlockAllocator::new(),
+            ranges: RangeSet::new(),
+        })
+    }
+
+    #[cfg(not(feature = "heap-debug"))]
+    fn allocate(&mut self, layout: Layout, hint: AllocHint) -> Option<*mut u8> {
+        self.allocate_block(layout, hint)
+    }
+
+    #[cfg(feature = "heap-debug")]
+    fn allocate(&mut self, layout: Layout, hint: AllocHint) -> Option<*mut u8> {
+        let (block, offset) = debug::block_layout(layout)?;
+        let block_ptr = self.allocate_block(block, hint).or_else(|| {
+            // the quarantined blocks are the last memory to be reused
+            while let Some((block_ptr, block)) = self.debug.evict_oldest() {
+                unsafe {
+                    self.deallocate_block(block_ptr, block);
+                }
+            }
+            self.allocate_block(block, hint)
+        })?;
+        Some(unsafe { self.debug.on_allocate(block_ptr, layout, offset) })
+    }
+
+    fn allocate_block(&mut self, layout: Layout, hint: AllocHint) -> Option<*mut u8> {
+        if self.free_size() < LOW_WATERMARK {
+            self.grow(GROW_SIZE, hint);
+        }
+        let ptr = self.allocate_from_heaps(layout, hint).or_else(|| {
+            let size = usize::max(GROW_SIZE, layout.size() + layout.align());
+            (self.grow(size, hint) > 0)
+                .then(|| self.allocate_from_heaps(layout, hint))
+                .flatten()
+        })?;
+        self.allocated += fixed_size_block::block_layout(layout).size();
+        Some(ptr)
+    }
+
+    fn allocate_from_heaps(&mut self, layout: Layout, hint: AllocHint) -> Option<*mut u8> {
+        let zones: &[MemoryZone] = match hint.zone {
+            Some(zone) => &[zone],
+            None => &[MemoryZone::Normal, MemoryZone::Dma32],
+        };
+        // the heaps of the preferred node are tried first
+        for local in [true, false] {
+            for zone in zones {
+                for heap in self.heaps.iter_mut().flatten() {
+                    let is_local = hint.numa_node.is_none() || heap.numa_node == hint.numa_node;
+                    if heap.zone != *zone || is_local != local {
+                        continue;
+                    }
+                    if let Some(ptr) = heap.allocator.allocate(layout) {
+                        return Some(ptr);
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    #[cfg(not(feature = "heap-debug"))]
+    unsafe fn deallocate(&mut self, ptr: *mut u8, layout: Layout) {
+        unsafe { self.deallocate_block(ptr, layout) }
+    }
+
+    #[cfg(feature = "heap-debug")]
+    unsafe fn deallocate(&mut self, ptr: *mut u8, layout: Layout) {
+        let (block, offset) = debug::block_layout(layout).unwrap();
+        let freed = unsafe { self.debug.on_deallocate(ptr, layout, block, offset) };
+        while let Some((block_ptr, block)) = self.debug.evict_for(freed.size()) {
+            unsafe {
+                self.deallocate_block(block_ptr, block);
+            }
+        }
+        self.debug.quarantine(freed);
+    }
+
+    unsafe fn deallocate_block(&mut self, ptr: *mut u8, layout: Layout) {
+        let addr = ptr.addr();
+        let heap = self
+            .heaps
+            .iter_mut()
+            .flatten()
+            .find(|heap| heap.ranges.iter().any(|range| range.contains(&addr)))
+            .expect("deallocating memory outside of the heaps");
+        unsafe {
+            heap.allocator.deallocate(ptr, layout);
+        }
+        self.allocated -= fixed_size_block::block_layout(layout).size();
+    }
+}
+
+struct LockedKernelAllocator(SpinMutex<KernelAllocator>);
+
+impl LockedKernelAllocator {
+    #[cfg_attr(feature = "heap-debug", expect(clippy::large_stack_frames))]
+    const fn new() -> Self {
+        Self(SpinMutex::with_class(
+            KernelAllocator::new(),
+            &spinlock::classes::KERNEL_HEAP,
+        ))
+    }
+}
+
+impl LockedKernelAllocator {
+    fn allocate(&self, layout: Layout, hint: AllocHint) -> *mut u8 {
+        if let Some(ptr) = self.0.lock().allocate(layout, hint) {
+            return ptr;
+        }
+        // the shrinkers free memory to the heap, so they are called without
+        // the lock
+        if shrink() == 0 {
+            return ptr::null_mut();
+        }
+        self.0.lock().allocate(layout, hint).unwrap_or_default()
+    }
+}
+
+unsafe impl GlobalAlloc for LockedKernelAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        stats::incr(Counter::HeapAllocs);
+        #[cfg(all(feature = "heap-cache", not(feature = "heap-debug")))]
+        if let Some(ptr) = cache::allocate(layout) {
+            return ptr;
+        }
+        self.allocate(layout, default_hint())
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        #[cfg(all(feature = "heap-cache", not(feature = "heap-debug")))]
+        if unsafe { cache::deallocate(ptr, layout) } {
+            return;
+        }
+        unsafe { self.0.lock().deallocate(ptr, layout) }
+    }
+}
+
+/// Prefers the NUMA node of the current CPU.
+fn default_hint() -> AllocHint {
+    AllocHint {
+        zone: None,
+        numa_node: cpu::try_current().and_then(cpu::Cpu::numa_node),
+    }
+}
+
+/// Allocates memory from the heap chosen by `hint`.
+///
+/// The memory is freed by [`alloc::alloc::dealloc`]. Returns a null pointer
+/// if no heap matching the hint has enough free memory.
+#[cfg_attr(not(feature = "ktest"), expect(dead_code))]
+pub fn alloc_with_hint(layout: Layout, hint: AllocHint) -> *mut u8 {
+    ALLOCATOR.allocate(layout, hint)
+}
+
+/// Heap usage statistics.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(not(feature = "shell"), expect(dead_code))]
+pub struct HeapStats {
+    /// Bytes of the memory added to the heap.
+    pub heap_size: usize,
+    /// Bytes of the blocks used by the live allocations, including the blocks
+    /// cached by the CPUs.
+    pub allocated: usize,
+    /// Bytes of the memory kept in the pool.
+    pub pool_size: usize,
+    #[cfg(feature = "heap-debug")]
+    pub debug: HeapDebugStats,
+}
+
+#[cfg_attr(not(feature = "shell"), expect(dead_code))]
+pub fn stats() -> HeapStats {
+    let allocator = ALLOCATOR.0.lock();
+    let stats = HeapStats {
+        heap_size: allocator.heap_size,
+        allocated: allocator.allocated,
+        pool_size: allocator
+            .pool
+            .iter()
+            .flatten()
+            .map(|pool_range| pool_range.range.len())
+            .sum(),
+        #[cfg(feature = "heap-debug")]
+        debug: allocator.debug.stats(),
+    };
+    allocator.unlock();
+    stats
+}
+
+/// Live allocations made from the same call site.
+#[cfg(feature = "heap-debug")]
+#[derive(Debug, Clone)]
+pub struct LeakRecord {
+    pub backtrace: Backtrace,
+    pub count: usize,
+    pub bytes: usize,
+}
+
+/// Returns the live allocations made after the last [`mark_leaks`], grouped
+/// by the backtraces and sorted by the bytes in descending order.
+#[cfg(feature = "heap-debug")]
+pub fn leaks() -> Vec<LeakRecord> {
+    use alloc::collections::btree_map::BTreeMap;
+    use core::cmp::Reverse;
+
+    // the records are copied into a vector allocated beforehand, as the heap
+    // cannot be used while it is locked
+    let capacity = ALLOCATOR.0.lock().debug.stats().tracked + 64;
+    let mut allocs = Vec::with_capacity(capacity);
+    let allocator = ALLOCATOR.0.lock();
+    allocator.debug.for_each_leak(|size, backtrace| {
+        if allocs.len() < allocs.capacity() {
+            allocs.push((size, *backtrace));
+        }
+    });
+    allocator.unlock();
+
+    let mut records = BTreeMap::<Backtrace, LeakRecord>::new();
+    for (size, backtrace) in allocs {
+        let record = records.entry(backtrace).or_insert(LeakRecord {
+            backtrace,
+            count: 0,
+            bytes: 0,
+        });
+        record.count += 1;
+        record.bytes += size;
+    }
+    let mut records = records.into_values().collect::<Vec<_>>();
+    records.sort_by_key(|record| Reverse(record.bytes));
+    records
+}
+
+/// Excludes the live allocations from the following leak reports.
+#[cfg(feature = "heap-debug")]
+pub fn mark_leaks() {
+    ALLOCATOR.0.lock().debug.mark();
+}
+
+/// Hook that frees cached memory when the heap is exhausted.
+///
+/// Returns the number of bytes freed. Shrinkers must not block, as they are
+/// called from the allocations.
+pub type Shrinker = fn() -> usize;
+
+static SHRINKERS: SpinMutex<Vec<Shrinker>> = SpinMutex::new(Vec::new());
+
+/// Registers a shrinker that is called when an allocation fails after the
+/// pool runs out.
+#[expect(dead_code)]
+pub fn register_shrinker(shrinker: Shrinker) {
+    SHRINKERS.lock().push(shrinker);
+}
+
+fn shrink() -> usize {
+    // an allocation failing in a shrinker or during the registration does not
+    // call the shrinkers again
+    let Some(shrinkers) = SHRINKERS.try_lock() else {
+        return 0;
+    };
+    let freed = shrinkers.iter().map(|shrinker| shrinker()).sum();
+    shrinkers.unlock();
+    freed
+}
+
+/// Adds at least `size` bytes from the pool to the heap.
+///
+/// The heap also grows automatically when its free space drops below the
+/// watermark. Returns the number of bytes added.
+#[expect(dead_code)]
+pub fn grow(size: usize) -> usize {
+    ALLOCATOR.0.lock().grow(size, default_hint())
+}
+
+pub fn init() {
+    #[repr(align(4096))]
+    struct BootHeap([UnsafeCell<MaybeUninit<u8>>; 128 * PAGE_SIZE]);
+    unsafe impl Sync for BootHeap {}
+
+    static BOOT_HEAP: BootHeap =
+        BootHeap([const { UnsafeCell::new(MaybeUninit::uninit()) }; 128 * PAGE_SIZE]);
+    static INITIALIZE: Once = Once::new();
+
+    INITIALIZE.call_once(|| unsafe {
+        let range = BOOT_HEAP.0.as_ptr_range();
+        add_heap_ranges([HeapRange {
+            range: range.start.expose_provenance()..range.end.expose_provenance(),
+            numa_node: None,
+        }]);
+    });
+}
+
+/// Adds the memory ranges to the heap.
+///
+/// The memory beyond the initial heap size is kept in the pool, and added to
+/// the heap by [`grow`].
+pub unsafe fn add_heap_ranges<I>(ranges: I)
+where
+    I: IntoIterator<Item = HeapRange>,
+{
+    let mut allocator = ALLOCATOR.0.lock();
+    for HeapRange { range, numa_node } in ranges {
+        unsafe {
+            allocator.add_memory(range, numa_node);
+        }
+    }
+}
+
+/// Allocates a zeroed page without waiting for the heap lock.
+///
+/// Returns `None` if the heap is exhausted or is locked by another context,
+/// so that this can be called from the fault handlers. The page is freed by
+/// [`alloc::alloc::dealloc`] with [`page_layout`].
+pub fn try_allocate_zeroed_page() -> Option<*mut u8> {
+    let mut allocator = ALLOCATOR.0.try_lock()?;
+    let page = allocator.allocate(page_layout(), default_hint());
+    allocator.unlock();
+    let page = page?;
+    unsafe {
+        page.write_bytes(0, PAGE_SIZE);
+    }
+    Some(page)
+}
+
+pub fn page_layout() -> Layout {
+    Layout::from_size_align(PAGE_SIZE, PAGE_SIZE).unwrap()
+}
+
+#[cfg(feature = "ktest")]
+pub mod ktests {
+    use alloc::{alloc::dealloc, vec};
+
+    use snafu::{OptionExt as _, ensure_whatever};
+
+    use super::{
+        AllocHint, DMA32_END, Layout, MemoryZone, PAGE_SIZE, alloc_with_hint, page_layout,
+        try_allocate_zeroed_page,
+    };
+    use crate::{error::GenericError, ktest::KernelTest};
+
+    pub static TESTS: &[KernelTest] =
+        kernel_tests![allocate_zeroed_page, allocate_large, allocate_dma32];
+
+    fn allocate_zeroed_page() -> Result<(), GenericError> {
+        let page = try_allocate_zeroed_page().whatever_context("failed to allocate page")?;
+        let aligned = page.addr().is_multiple_of(PAGE_SIZE);
+        let zeroed = unsafe { core::slice::from_raw_parts(page, PAGE_SIZE) }
+            .iter()
+            .all(|&b| b == 0);
+        unsafe {
+            dealloc(page, page_layout());
+        }
+        ensure_whatever!(aligned, "page {page:p} not aligned");
+        ensure_whatever!(zeroed, "page {page:p} not zeroed");
+        Ok(())
+    }
+
+    fn allocate_large() -> Result<(), GenericError> {
+        const SIZE: usize = 8 * 1024 * 1024;
+        let mut buf = vec![0_u8; SIZE];
+        for (i, b) in buf.iter_mut().enumerate() {
+            *b = i.to_le_bytes()[0] ^ (i / PAGE_SIZE).to_le_bytes()[0];
+        }
+        let intact = buf
+            .iter()
+            .enumerate()
+            .all(|(i, &b)| b == i.to_le_bytes()[0] ^ (i / PAGE_SIZE).to_le_bytes()[0]);
+        ensure_whatever!(intact, "buffer of {SIZE:#x} bytes corrupted");
+        Ok(())
+    }
+
+    fn allocate_dma32() -> Result<(), GenericError> {
+        let hint = AllocHint {
+            zone: Some(MemoryZone::Dma32),
+            numa_node: None,
+        };
+        let layout = Layout::from_size_align(4 * PAGE_SIZE, PAGE_SIZE).unwrap();
+        let ptr = alloc_with_hint(layout, hint);
+        ensure_whatever!(!ptr.is_null(), "failed to allocate DMA32 memory");
+        let end = ptr.addr() + layout.size();
+        unsafe {
+            dealloc(ptr, layout);
+        }
+        ensure_whatever!(end <= DMA32_END, "memory {ptr:p} not in DMA32 zone");
+        Ok(())
+    }
+}
@@ -0,0 +1,249 @@
+//! Address space identifiers of the user page tables.
+//!
+//! The ASIDs are allocated from the width implemented by the hardware, and
+//! recycled by generations. When all the ASIDs of a generation are used, a new
+//! generation starts, and each CPU flushes its whole TLB before it switches to
+//! an ASID of the new generation. The ASIDs active on the CPUs at the rollover
+//! are kept by their address spaces, as the CPUs may still run with them.
+//!
+//! ASID 0 is used by the kernel page table.
+
+use alloc::{vec, vec::Vec};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use platform_cast::CastFrom as _;
+use riscv::register::satp;
+use riscv_utils::asm;
+use spin::Once;
+
+use super::tlb;
+use crate::{cpu, error::GenericError, sync::spinlock::SpinMutex};
+
+const ASID_BITS: u32 = 16;
+
+/// Number of the ASIDs implemented by the hardware.
+static ASID_COUNT: Once<usize> = Once::new();
+
+static ALLOCATOR: SpinMutex<AsidAllocator> = SpinMutex::new(AsidAllocator::new());
+
+cpu_local! {
+    /// Encoded ASID the CPU last switched to, or zero if none.
+    static ACTIVE: AtomicU64 = AtomicU64::new(0);
+    /// Whether the TLB holds the entries of an older generation.
+    static FLUSH_PENDING: AtomicBool = AtomicBool::new(false);
+}
+
+initcall!(INITCALL, memory, init);
+
+/// Discovers the number of the ASID bits implemented by the hardware.
+///
+/// The ASID field of `satp` holds ones only in the implemented bits, so the
+/// field is written with all ones and read back. This must be called after the
+/// kernel page table is applied, with the interrupts disabled.
+fn init() {
+    ASID_COUNT.call_once(|| {
+        let kernel_satp = satp::read();
+        let mut probe = kernel_satp;
+        probe.set_asid((1 << ASID_BITS) - 1);
+        let asid = unsafe {
+            satp::write(probe);
+            let asid = satp::read().asid();
+            satp::write(kernel_satp);
+            asid
+        };
+        // the probe may have cached the kernel entries with the all-ones ASID
+        asm::sfence_vma_asid_all(asid);
+
+        let bits = asid.bit_width();
+        info!("{bits} ASID bits implemented");
+        1 << bits
+    });
+}
+
+fn asid_count() -> usize {
+    *ASID_COUNT.get().unwrap()
+}
+
+/// ASID of a user address space.
+///
+/// The ASID is allocated on the first activation, and is valid until the
+/// generation it belongs to ends.
+#[derive(Debug, Default)]
+pub struct Asid {
+    /// Generation in the upper bits and the ASID in the lower bits, or zero if
+    /// not allocated yet.
+    encoded: u64,
+}
+
+impl Asid {
+    pub const fn new() -> Self {
+        Self { encoded: 0 }
+    }
+
+    /// Returns the ASID to switch the current CPU to, and whether the TLB of
+    /// the current CPU must be flushed after switching.
+    ///
+    /// The caller must switch to the returned ASID with the interrupts
+    /// disabled, so that the current CPU does not change meanwhile.
+    pub fn activate(&mut self) -> (u16, bool) {
+        // every CPU may reserve an ASID at a rollover, so fewer ASIDs are
+        // treated as none, and the TLB entries are not tagged
+        if asid_count() <= cpu::get_all().len() + 1 {
+            return (0, true);
+        }
+
+        let mut allocator = ALLOCATOR.lock();
+        if generation(self.encoded) != allocator.generation {
+            self.encoded = allocator.allocate(self.encoded);
+        }
+        // updated under the lock, so that a rollover reserves the new ASID
+        ACTIVE.get().store(self.encoded, Ordering::Relaxed);
+        allocator.unlock();
+
+        let needs_flush = FLUSH_PENDING.get().swap(false, Ordering::Relaxed);
+        (asid_value(self.encoded), needs_flush)
+    }
+
+    /// Flushes the TLB entries of the ASID on all the CPUs.
+    ///
+    /// This is needed after the mappings of the address space are changed
+    /// while it may be cached in the TLB.
+    pub fn flush(&self) -> Result<(), GenericError> {
+        if !self.is_in_use() {
+            // no entries are tagged with the ASID, or the TLB is flushed on
+            // the next activation
+            return Ok(());
+        }
+        tlb::shootdown_asid(asid_value(self.encoded))
+    }
+
+    /// Returns whether the ASID is allocated and in use in the current
+    /// generation.
+    fn is_in_use(&self) -> bool {
+        if self.encoded == 0 {
+            return false;
+        }
+        let allocator = ALLOCATOR.lock();
+        let in_use = allocator.is_in_use(self.encoded);
+        allocator.unlock();
+        in_use
+    }
+}
+
+impl Drop for Asid {
+    /// Releases the ASID for reuse in its generation, after flushing its TLB
+    /// entries on all the CPUs.
+    ///
+    /// The address space must not be active on any CPU.
+    fn drop(&mut self) {
+        if !self.is_in_use() {
+            // the TLB is flushed before the ASID is used in a newer generation
+            return;
+        }
+        if let Err(e) = self.flush() {
+            let asid = asid_value(self.encoded);
+            warn!("failed to flush ASID {asid}, leaking it: {e}");
+            return;
+        }
+        ALLOCATOR.lock().release(self.encoded);
+    }
+}
+
+fn encode(generation: u64, asid: usize) -> u64 {
+    (generation << ASID_BITS) | u64::cast_from(asid)
+}
+
+fn generation(encoded: u64) -> u64 {
+    encoded >> ASID_BITS
+}
+
+fn asid_value(encoded: u64) -> u16 {
+    u16::try_from(encoded & ((1 << ASID_BITS) - 1)).unwrap()
+}
+
+#[derive(Debug)]
+struct AsidAllocator {
+    generation: u64,
+    /// Bitmap of the ASIDs used in the current generation.
+    used: Vec<u64>,
+    /// ASID to search a free one from.
+    next: usize,
+    /// Encoded ASIDs active on the CPUs when the current generation started.
+    reserved: Vec<u64>,
+}
+
+impl AsidAllocator {
+    const fn new() -> Self {
+        Self {
+            generation: 0,
+            used: Vec::new(),
+            next: 1,
+            reserved: Vec::new(),
+        }
+    }
+
+    /// Allocates an ASID of the current generation for the address space that
+    /// has `old` ASID.
+    fn allocate(&mut self, old: u64) -> u64 {
+        if self.generation == 0 {
+            self.rollover();
+        }
+        if old != 0 && self.reserved.contains(&old) {
+            return encode(self.generation, usize::from(asid_value(old)));
+        }
+        let asid = self.find_free().unwrap_or_else(|| {
+            self.rollover();
+            // the ASIDs outnumber the CPUs, so some are left unreserved
+            self.find_free().unwrap()
+        });
+        self.used[asid / 64] |= 1 << (asid % 64);
+        self.next = asid + 1;
+        encode(self.generation, asid)
+    }
+
+    fn find_free(&self) -> Option<usize> {
+        let count = asid_count();
+        (self.next..count)
+            .chain(1..self.next)
+            .find(|&asid| self.used[asid / 64] & (1 << (asid % 64)) == 0)
+    }
+
+    /// Returns whether the ASID of `encoded` is in use in the current
+    /// generation.
+    fn is_in_use(&self, encoded: u64) -> bool {
+        generation(encoded) == self.generation || self.reserved.contains(&encoded)
+    }
+
+    /// Makes the ASID of `encoded` free in the current generation.
+    fn release(&mut self, encoded: u64) {
+        if !self.is_in_use(encoded) {
+            return;
+        }
+        self.reserved.retain(|&reserved| reserved != encoded);
+        let asid = usize::from(asid_value(encoded));
+        self.used[asid / 64] &= !(1 << (asid % 64));
+    }
+
+    /// Starts a new generation, keeping the ASIDs active on the CPUs.
+    fn rollover(&mut self) {
+        self.generation += 1;
+        self.used = vec![0; asid_count().div_ceil(64)];
+        self.used[0] = 1;
+        self.next = 1;
+        self.reserved.clear();
+        for cpu in cpu::get_all() {
+            let Some(active) = ACTIVE.try_get_for(cpu.id()) else {
+                continue;
+            };
+            let encoded = active.load(Ordering::Relaxed);
+            if encoded != 0 {
+                let asid = usize::from(asid_value(encoded));
+                self.used[asid / 64] |= 1 << (asid % 64);
+                self.reserved.push(encoded);
+            }
+            if let Some(flush_pending) = FLUSH_PENDING.try_get_for(cpu.id()) {
+                flush_pending.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+}
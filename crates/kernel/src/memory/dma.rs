@@ -0,0 +1,181 @@
+//! Memory shared with the devices.
+//!
+//! The kernel memory is identity mapped, so the bus address of a buffer is its
+//! kernel address. Devices that do not snoop the CPU caches are marked with
+//! `dma-noncoherent` in the devicetree, and their buffers are written back and
+//! invalidated with the Zicbom instructions around the device accesses.
+
+use alloc::{
+    alloc::{alloc_zeroed, dealloc},
+    format,
+};
+use core::{
+    alloc::Layout,
+    ops::Range,
+    ptr::NonNull,
+    slice,
+    sync::atomic::{self, Ordering},
+};
+
+use riscv_utils::asm;
+use snafu::{OptionExt as _, ensure_whatever};
+use spin::Once;
+
+use crate::{
+    cpu::{self, Cpu},
+    error::GenericError,
+};
+
+/// Cache block size assumed if the CPUs do not report it.
+const DEFAULT_CACHE_BLOCK_SIZE: usize = 64;
+
+/// Cache block size of the Zicbom instructions, or `None` if some CPU does not
+/// support them.
+static CBOM_BLOCK_SIZE: Once<Option<usize>> = Once::new();
+
+/// Whether the device snoops the CPU caches on its memory accesses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Coherence {
+    Coherent,
+    /// The caches must be maintained by the driver with
+    /// [`DmaBuffer::sync_for_device`] and [`DmaBuffer::sync_for_cpu`].
+    NonCoherent,
+}
+
+fn cbom_block_size() -> Option<usize> {
+    *CBOM_BLOCK_SIZE.call_once(|| {
+        cpu::get_all()
+            .iter()
+            .map(Cpu::cbom_block_size)
+            .try_fold(0, |max, size| Some(usize::max(max, size?)))
+    })
+}
+
+/// Returns whether the caches can be maintained for non-coherent devices.
+pub fn has_cache_maintenance() -> bool {
+    cbom_block_size().is_some()
+}
+
+/// Returns the alignment that keeps the buffers from sharing cache blocks.
+pub fn cache_block_size() -> usize {
+    cbom_block_size().unwrap_or(DEFAULT_CACHE_BLOCK_SIZE)
+}
+
+/// Physically contiguous, zero-initialized memory passed to a device.
+///
+/// The allocation is rounded up to whole cache blocks, so that the cache
+/// maintenance of the buffer does not affect other memory.
+#[derive(Debug)]
+pub struct DmaBuffer {
+    ptr: NonNull<u8>,
+    len: usize,
+    layout: Layout,
+}
+
+unsafe impl Send for DmaBuffer {}
+unsafe impl Sync for DmaBuffer {}
+
+impl DmaBuffer {
+    /// Allocates a buffer of `len` bytes, aligned to `align` bytes.
+    pub fn new(len: usize, align: usize) -> Result<Self, GenericError> {
+        ensure_whatever!(len > 0, "empty DMA buffer");
+        let block_size = cache_block_size();
+        let align = usize::max(align, block_size);
+        let layout = Layout::from_size_align(len.next_multiple_of(block_size), align)
+            .ok()
+            .with_whatever_context(|| {
+                format!("invalid DMA buffer, len={len:#x}, align={align:#x}")
+            })?;
+        let ptr = NonNull::new(unsafe { alloc_zeroed(layout) })
+            .with_whatever_context(|| format!("failed to allocate DMA buffer, len={len:#x}"))?;
+        let buffer = Self { ptr, len, layout };
+        // the zeroed blocks must not be written back over the device writes
+        buffer.sync_for_device(0..len, Coherence::NonCoherent);
+        Ok(buffer)
+    }
+
+    /// Allocates a buffer holding a copy of `bytes`.
+    pub fn from_slice(bytes: &[u8], align: usize) -> Result<Self, GenericError> {
+        let mut buffer = Self::new(bytes.len(), align)?;
+        buffer.as_mut_slice().copy_from_slice(bytes);
+        Ok(buffer)
+    }
+
+    #[cfg_attr(not(any(feature = "virtio-blk", feature = "net")), expect(dead_code))]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns the address of the buffer seen by the devices.
+    pub fn bus_addr(&self) -> u64 {
+        u64::try_from(self.ptr.addr().get()).unwrap()
+    }
+
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.ptr.as_ptr()
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+
+    /// Writes back the CPU writes to `range` of the buffer, before the device
+    /// accesses it.
+    pub fn sync_for_device(&self, range: Range<usize>, coherence: Coherence) {
+        for addr in self.cache_blocks(range, coherence) {
+            asm::cbo_clean(addr);
+        }
+        // the write-backs complete before the device is notified
+        atomic::fence(Ordering::SeqCst);
+    }
+
+    /// Discards the cached contents of `range` of the buffer, before the CPU
+    /// reads the device writes.
+    pub fn sync_for_cpu(&self, range: Range<usize>, coherence: Coherence) {
+        atomic::fence(Ordering::SeqCst);
+        for addr in self.cache_blocks(range, coherence) {
+            // the CPU does not write to the buffer while the device owns it,
+            // and the earlier writes are written back by `sync_for_device`
+            unsafe {
+                asm::cbo_inval(addr);
+            }
+        }
+        atomic::fence(Ordering::SeqCst);
+    }
+
+    /// Returns the addresses of the cache blocks overlapping `range`, or
+    /// nothing if the maintenance is not needed.
+    fn cache_blocks(
+        &self,
+        range: Range<usize>,
+        coherence: Coherence,
+    ) -> impl Iterator<Item = usize> + use<> {
+        assert!(
+            self.as_slice().get(range.clone()).is_some(),
+            "range {range:#x?} out of DMA buffer, len={:#x}",
+            self.len
+        );
+        let block_size = cbom_block_size().filter(|_| coherence == Coherence::NonCoherent);
+        let (start, end, step) = match block_size {
+            Some(size) if !range.is_empty() => {
+                let base = self.ptr.addr().get();
+                ((base + range.start) / size * size, base + range.end, size)
+            }
+            _ => (0, 0, 1),
+        };
+        (start..end).step_by(step)
+    }
+}
+
+impl Drop for DmaBuffer {
+    fn drop(&mut self) {
+        // the owner must stop the device accesses before dropping the buffer.
+        unsafe {
+            dealloc(self.ptr.as_ptr(), self.layout);
+        }
+    }
+}
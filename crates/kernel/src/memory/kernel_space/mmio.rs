@@ -0,0 +1,275 @@
+use alloc::{borrow::ToOwned as _, collections::btree_map::BTreeMap, format, string::String};
+use core::{mem, ops::Range, ptr};
+
+use range_set::RangeSet;
+use snafu::{OptionExt as _, ResultExt as _, ensure_whatever, whatever};
+use spin::Once;
+use sv39::MapPageFlags;
+
+use super::KERNEL_PAGE_TABLE;
+use crate::{
+    cpu::{self, Cpu},
+    error::GenericError,
+    memory::{self, PAGE_SIZE, layout},
+    sync::spinlock::SpinMutex,
+};
+
+/// Free virtual address ranges in the MMIO region.
+static MMIO_SPACE: Once<SpinMutex<RangeSet<128>>> = Once::new();
+
+/// Physical address ranges of the mapped device registers, mapping their
+/// start addresses to their claims.
+static CLAIMS: SpinMutex<BTreeMap<usize, Claim>> = SpinMutex::new(BTreeMap::new());
+
+#[derive(Debug)]
+struct Claim {
+    end: usize,
+    owner: String,
+}
+
+pub(super) fn init() {
+    MMIO_SPACE.call_once(|| {
+        let mut space = RangeSet::new();
+        space.insert(layout::mmio_range());
+        SpinMutex::new(space)
+    });
+}
+
+/// Value of an MMIO register, accessed by a single volatile access.
+pub trait MmioValue: Copy {}
+
+impl MmioValue for u8 {}
+impl MmioValue for u16 {}
+impl MmioValue for u32 {}
+impl MmioValue for u64 {}
+
+/// MMIO region mapped in the MMIO region of the kernel address space.
+///
+/// The region is unmapped when this is dropped.
+#[derive(Debug)]
+pub struct MmioToken {
+    /// Virtual address of the first register.
+    base_addr: usize,
+    size: usize,
+    /// Pages allocated from the MMIO region, including the guard page.
+    virt_range: Range<usize>,
+    /// Released after the pages are unmapped.
+    _claim: MmioClaim,
+}
+
+impl MmioToken {
+    fn addr<T>(&self, offset: usize) -> usize {
+        assert!(offset + mem::size_of::<T>() <= self.size);
+        let addr = self.base_addr + offset;
+        assert!(addr.is_multiple_of(mem::align_of::<T>()));
+        addr
+    }
+
+    /// Reads the register at `offset`.
+    ///
+    /// # Safety
+    ///
+    /// The side effects of reading the register must not break the memory
+    /// safety.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the register is out of the region or is not aligned.
+    pub unsafe fn read<T>(&self, offset: usize) -> T
+    where
+        T: MmioValue,
+    {
+        let addr = self.addr::<T>(offset);
+        unsafe { ptr::with_exposed_provenance::<T>(addr).read_volatile() }
+    }
+
+    /// Writes `value` to the register at `offset`.
+    ///
+    /// # Safety
+    ///
+    /// The side effects of writing the register, such as starting a DMA
+    /// transfer, must not break the memory safety.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the register is out of the region or is not aligned.
+    pub unsafe fn write<T>(&self, offset: usize, value: T)
+    where
+        T: MmioValue,
+    {
+        let addr = self.addr::<T>(offset);
+        unsafe { ptr::with_exposed_provenance_mut::<T>(addr).write_volatile(value) }
+    }
+}
+
+impl Drop for MmioToken {
+    fn drop(&mut self) {
+        if let Err(e) = unmap_pages(self.virt_range.clone()) {
+            warn!("failed to unmap MMIO region {:#x?}: {e}", self.virt_range);
+        }
+    }
+}
+
+/// Physical address range of device registers claimed by an owner.
+///
+/// The range is released when this is dropped.
+#[derive(Debug)]
+struct MmioClaim {
+    start: usize,
+}
+
+impl MmioClaim {
+    /// Claims the physical address range `range` for `owner`.
+    ///
+    /// Fails if the range overlaps with a claimed range, which is usually an
+    /// error in the devicetree. The ranges are not expanded to the page
+    /// boundaries, as the registers of the devices may share a page.
+    fn new(range: Range<usize>, owner: &str) -> Result<Self, GenericError> {
+        let mut claims = CLAIMS.lock();
+        let overlap = claims
+            .range(..range.end)
+            .next_back()
+            .filter(|(_start, other)| range.start < other.end);
+        if let Some((&start, other)) = overlap {
+            whatever!(
+                "registers {range:#x?} of {owner} overlap {:#x?} claimed by {}",
+                start..other.end,
+                other.owner
+            );
+        }
+        claims.insert(
+            range.start,
+            Claim {
+                end: range.end,
+                owner: owner.to_owned(),
+            },
+        );
+        Ok(Self { start: range.start })
+    }
+}
+
+impl Drop for MmioClaim {
+    fn drop(&mut self) {
+        CLAIMS.lock().remove(&self.start);
+    }
+}
+
+/// Maps the device registers at the physical address range `range` in the MMIO
+/// region of the kernel address space.
+///
+/// The range is claimed for `owner`, such as the path of the device node,
+/// until the registers are unmapped. Overlapping claims are refused with the
+/// owners of both ranges.
+///
+/// Each mapping is followed by an unmapped guard page. The pages are mapped
+/// with the I/O memory type if all CPUs support the Svpbmt extension, so that
+/// the accesses are neither cached nor reordered.
+///
+/// # Safety
+///
+/// `range` must be the registers of a device, and must not overlap with the
+/// memory.
+pub unsafe fn map_mmio(range: Range<usize>, owner: &str) -> Result<MmioToken, GenericError> {
+    ensure_whatever!(!range.is_empty(), "empty MMIO region, range={range:#x?}");
+    let claim = MmioClaim::new(range.clone(), owner)?;
+    let phys_range = memory::expand_to_page_boundaries(range.clone());
+    let mut flags = MapPageFlags::RW;
+    if cpu::get_all().iter().all(Cpu::has_svpbmt) {
+        flags |= MapPageFlags::IO;
+    }
+
+    let virt_range = allocate_virt_range(phys_range.len() + PAGE_SIZE)
+        .with_whatever_context(|| format!("no MMIO space for range={range:#x?}"))?;
+    let map_range = virt_range.start..virt_range.end - PAGE_SIZE;
+
+    let mut kpgtbl = KERNEL_PAGE_TABLE.get().unwrap().lock();
+    let res = kpgtbl.map_range(map_range.clone(), phys_range.start, flags);
+    let pending = kpgtbl.take_pending();
+    kpgtbl.unlock();
+    if let Err(e) = res {
+        MMIO_SPACE.get().unwrap().lock().insert(virt_range);
+        return Err(e).with_whatever_context(|_| {
+            format!(
+                "failed to update kernel page table, range={range:#x?}, \
+                 virt_range={map_range:#x?}, flags={flags:?}"
+            )
+        });
+    }
+
+    pending.flush().with_whatever_context(|_| {
+        format!("failed to apply kernel page table changes, range={map_range:#x?}")
+    })?;
+
+    Ok(MmioToken {
+        base_addr: map_range.start + (range.start - phys_range.start),
+        size: range.len(),
+        virt_range,
+        _claim: claim,
+    })
+}
+
+fn allocate_virt_range(size: usize) -> Option<Range<usize>> {
+    let mut space = MMIO_SPACE.get().unwrap().lock();
+    let free = space.iter().find(|free| free.len() >= size)?;
+    let range = free.start..free.start + size;
+    space.remove(range.clone());
+    Some(range)
+}
+
+fn unmap_pages(virt_range: Range<usize>) -> Result<(), GenericError> {
+    let map_range = virt_range.start..virt_range.end - PAGE_SIZE;
+    let mut kpgtbl = KERNEL_PAGE_TABLE.get().unwrap().lock();
+    kpgtbl
+        .unmap_range(map_range.clone())
+        .with_whatever_context(|_| {
+            format!("failed to update kernel page table, range={map_range:#x?}")
+        })?;
+    let pending = kpgtbl.take_pending();
+    kpgtbl.unlock();
+
+    pending.flush().with_whatever_context(|_| {
+        format!("failed to apply kernel page table changes, range={map_range:#x?}")
+    })?;
+
+    // the range is reused only after the stale TLB entries are flushed
+    MMIO_SPACE.get().unwrap().lock().insert(virt_range);
+    Ok(())
+}
+
+#[cfg(feature = "ktest")]
+pub mod ktests {
+    use alloc::format;
+
+    use snafu::{ResultExt as _, ensure_whatever, whatever};
+
+    use super::MmioClaim;
+    use crate::{error::GenericError, ktest::KernelTest};
+
+    pub static TESTS: &[KernelTest] = kernel_tests![overlapping_claims];
+
+    /// Physical address without devices, so that the claims do not conflict
+    /// with the drivers.
+    const BASE: usize = 0x7f_0000_0000;
+
+    fn overlapping_claims() -> Result<(), GenericError> {
+        let first = MmioClaim::new(BASE..BASE + 0x100, "first")
+            .whatever_context("failed to claim first range")?;
+        // adjacent ranges may share a page
+        let _second = MmioClaim::new(BASE + 0x100..BASE + 0x200, "second")
+            .whatever_context("failed to claim adjacent range")?;
+
+        let Err(e) = MmioClaim::new(BASE + 0x80..BASE + 0x180, "third") else {
+            whatever!("overlapping range claimed");
+        };
+        let message = format!("{e}");
+        ensure_whatever!(
+            message.contains("third") && message.contains("first"),
+            "conflict does not name both owners: {message}"
+        );
+
+        drop(first);
+        let _third = MmioClaim::new(BASE..BASE + 0x80, "third")
+            .whatever_context("released range not claimable")?;
+        Ok(())
+    }
+}
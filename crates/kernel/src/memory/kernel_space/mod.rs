@@ -1,36 +1,68 @@
 use alloc::format;
 use core::{
+    fmt,
+    mem::{self, ManuallyDrop},
     ops::Range,
     sync::atomic::{AtomicBool, Ordering},
 };
 
-use riscv::register::satp::{self, Satp};
-use riscv_utils::asm;
-use sbi::rfence;
-use snafu::{OptionExt as _, ResultExt as _};
+use riscv::register::satp::Satp;
+use snafu::ResultExt as _;
 use spin::Once;
 use sv39::{
-    MapPageFlags, PageTableError, PageTableRoot,
-    address::{PhysAddr, VirtAddr},
+    MapPageFlags, Mapping, PageTableError, PageTableRoot,
+    address::{PhysAddr, VirtAddr, VirtPageNum},
 };
 
-use self::stack::StackSlot;
-use super::PAGE_SIZE;
-use crate::{cpu, error::GenericError, memory::Align as _, sync::spinlock::SpinMutex};
+#[cfg(feature = "ktest")]
+pub use self::mmio::ktests as mmio_ktests;
+pub use self::mmio::{MmioToken, map_mmio};
+#[cfg(feature = "ktest")]
+pub use self::stack::is_in_use as is_stack_in_use;
+#[cfg(feature = "ktest")]
+pub use self::stack::ktests as stack_ktests;
+#[cfg_attr(not(feature = "shell"), expect(unused_imports))]
+pub use self::stack::stats as stack_stats;
+#[cfg(debug_assertions)]
+pub use self::verify::verify;
+#[cfg(feature = "ktest")]
+pub use self::vmalloc::ktests as vmalloc_ktests;
+#[cfg_attr(not(feature = "ktest"), expect(unused_imports))]
+pub use self::vmalloc::{VmallocArea, vmalloc};
+use self::{stack::StackSlot, tlb::TlbBatch};
+use super::{PAGE_SIZE, layout};
+use crate::{
+    error::GenericError,
+    memory::Align as _,
+    sync::spinlock::{self, SpinMutex},
+};
 
+mod mmio;
 mod stack;
+mod tlb;
+#[cfg(debug_assertions)]
+mod verify;
+mod vmalloc;
 
 const KERNEL_ASID: u16 = 0;
 
+/// Maximum size of a kernel stack, which is also the default size.
+pub const MAX_KERNEL_STACK_SIZE: usize = stack::STACK_SIZE;
+
 #[derive(Debug)]
 struct KernelPageTable {
     pt: PageTableRoot,
+    /// Changes whose TLB entries are not flushed yet.
+    pending: TlbBatch,
 }
 
 impl KernelPageTable {
     fn new() -> Result<Self, PageTableError> {
         let pt = PageTableRoot::new(KERNEL_ASID)?;
-        Ok(Self { pt })
+        Ok(Self {
+            pt,
+            pending: TlbBatch::new(),
+        })
     }
 
     fn identity_map_range(
@@ -38,12 +70,32 @@ impl KernelPageTable {
         addr_range: Range<usize>,
         flags: MapPageFlags,
     ) -> Result<usize, PageTableError> {
+        self.map_range(addr_range.clone(), addr_range.start, flags)
+    }
+
+    fn map_range(
+        &mut self,
+        virt_range: Range<usize>,
+        phys_start: usize,
+        flags: MapPageFlags,
+    ) -> Result<usize, PageTableError> {
+        assert!(virt_range.start.is_page_aligned());
+        assert!(virt_range.end.is_page_aligned());
+        assert!(phys_start.is_page_aligned());
+        let start_vpn = VirtAddr::from_addr(virt_range.start).page_num();
+        let start_ppn = PhysAddr::from_addr(phys_start).page_num();
+        let count = virt_range.len() / PAGE_SIZE;
+        self.pending.add(virt_range);
+        self.pt.map_fixed_pages(start_vpn, start_ppn, count, flags)
+    }
+
+    fn unmap_range(&mut self, addr_range: Range<usize>) -> Result<(), PageTableError> {
         assert!(addr_range.start.is_page_aligned());
         assert!(addr_range.end.is_page_aligned());
         let start_vpn = VirtAddr::from_addr(addr_range.start).page_num();
-        let start_ppn = PhysAddr::from_addr(addr_range.start).page_num();
         let count = addr_range.len() / PAGE_SIZE;
-        self.pt.map_fixed_pages(start_vpn, start_ppn, count, flags)
+        self.pending.add(addr_range);
+        self.pt.unmap_pages(start_vpn, count)
     }
 
     fn allocate_virt_addr_range(
@@ -55,9 +107,35 @@ impl KernelPageTable {
         assert!(addr_range.end.is_page_aligned());
         let start_vpn = VirtAddr::from_addr(addr_range.start).page_num();
         let count = addr_range.len() / PAGE_SIZE;
+        self.pending.add(addr_range);
         self.pt.allocate_pages(start_vpn, count, flags)
     }
 
+    fn reserve_tables(&mut self, addr_range: Range<usize>) -> Result<(), PageTableError> {
+        assert!(addr_range.start.is_page_aligned());
+        assert!(addr_range.end.is_page_aligned());
+        let start_vpn = VirtAddr::from_addr(addr_range.start).page_num();
+        let count = addr_range.len() / PAGE_SIZE;
+        self.pt.reserve_tables(start_vpn, count)
+    }
+
+    /// Takes the changes made since the last call, which must be flushed
+    /// after the lock is released.
+    ///
+    /// The changes left by failed operations are flushed with the next
+    /// transaction.
+    fn take_pending(&mut self) -> TlbBatch {
+        mem::take(&mut self.pending)
+    }
+
+    fn lookup(&self, virt_addr: VirtAddr) -> MappingLookup {
+        if let Some(mapping) = self.pt.find_mapping(virt_addr) {
+            return MappingLookup::Mapped(mapping);
+        }
+        let (below, above) = self.pt.nearest_mappings(virt_addr);
+        MappingLookup::Unmapped { below, above }
+    }
+
     fn satp(&self) -> Satp {
         self.pt.satp()
     }
@@ -73,81 +151,159 @@ cpu_local! {
     static APPLIED: AtomicBool = AtomicBool::new(false);
 }
 
+/// Creates the kernel page table.
+///
+/// The virtual address layout of the dynamically mapped regions is determined
+/// here, which requires the chosen node to be initialized.
 pub fn init() -> Result<(), GenericError> {
+    super::layout::init_virt_layout();
+    mmio::init();
+    vmalloc::init();
     stack::init();
-    let kpgtbl = KernelPageTable::new().whatever_context("failed to create kernel page table")?;
-    KERNEL_PAGE_TABLE.call_once(|| SpinMutex::new(kpgtbl));
+    let mut kpgtbl =
+        KernelPageTable::new().whatever_context("failed to create kernel page table")?;
+
+    // the top-level entries of the dynamic regions are created up front, as
+    // the user page tables share the entries that exist when they are created.
+    for range in [
+        layout::kernel_stack_range(),
+        layout::mmio_range(),
+        layout::vmalloc_range(),
+    ] {
+        kpgtbl
+            .reserve_tables(range.start..range.start + PAGE_SIZE)
+            .with_whatever_context(|_| {
+                format!("failed to reserve kernel page tables, range={range:#x?}")
+            })?;
+    }
+
+    KERNEL_PAGE_TABLE
+        .call_once(|| SpinMutex::with_class(kpgtbl, &spinlock::classes::KERNEL_PAGE_TABLE));
     Ok(())
 }
 
 pub fn apply() {
     let kpgtbl = KERNEL_PAGE_TABLE.get().unwrap().lock();
     let satp = kpgtbl.satp();
-    let asid = kpgtbl.asid();
     kpgtbl.unlock();
 
-    // wait for any previous writes to the page table memory to finish.
-    asm::sfence_vma_asid_all(asid.into());
-
-    unsafe {
-        satp::write(satp);
-    }
-
-    asm::sfence_vma_asid_all(asid.into());
+    // the kernel page table is never freed and maps the whole kernel image.
+    let _prev = unsafe { sv39::activate_satp(satp) };
     APPLIED.get().store(true, Ordering::Release);
 }
 
-fn apply_page_table_changes(asid: u16, vaddr_range: Range<usize>) -> Result<(), GenericError> {
-    vaddr_range.clone().step_by(PAGE_SIZE).for_each(|vaddr| {
-        asm::sfence_vma(vaddr, asid.into());
-    });
-
-    for cpu_mask in cpu::remote_cpu_masks() {
-        rfence::remote_sfence_vma_asid(
-            cpu_mask.mask,
-            cpu_mask.base,
-            vaddr_range.start,
-            vaddr_range.len(),
-            asid.into(),
-        )
-        .with_whatever_context(|_e| {
-            format!(
-                "failed to remote sfence.vma for cpus `{cpu_mask:?}` with virtual address range \
-                 `{start:#x}..{end:#x}`",
-                start = vaddr_range.start,
-                end = vaddr_range.end,
-            )
-        })?;
-    }
-
-    Ok(())
-}
-
 pub fn identity_map_range(range: Range<usize>, flags: MapPageFlags) -> Result<(), GenericError> {
     let mut kpgtbl = KERNEL_PAGE_TABLE.get().unwrap().lock();
-    let asid = kpgtbl.asid();
     kpgtbl
         .identity_map_range(range.clone(), flags)
         .with_whatever_context(|_| {
             format!("failed to update kernel page table, range={range:#x?}, flags={flags:?}",)
         })?;
+    let pending = kpgtbl.take_pending();
     kpgtbl.unlock();
 
-    if APPLIED.get().load(Ordering::Acquire) {
-        apply_page_table_changes(asid, range.clone()).with_whatever_context(|_| {
-            format!("failed to apply kernel page table changes, asid={asid}, range={range:#x?}")
-        })?;
+    pending.flush().with_whatever_context(|_| {
+        format!("failed to apply kernel page table changes, range={range:#x?}")
+    })?;
+
+    Ok(())
+}
+
+/// Shares the kernel mappings outside of `user_range` with the user page table
+/// `pt`.
+///
+/// The kernel needs its mappings while entering and leaving U-mode. The
+/// mappings are not accessible from U-mode as they lack the `U` flag.
+pub fn share_kernel_mappings(
+    pt: &mut PageTableRoot,
+    user_range: Range<usize>,
+) -> Result<(), GenericError> {
+    let user_start = VirtAddr::from_addr(user_range.start).page_num();
+    let user_end = VirtAddr::from_addr(user_range.end - 1).page_num();
+
+    assert!(VirtPageNum::MIN < user_start && user_end < VirtPageNum::MAX);
+    let ranges = [
+        VirtPageNum::MIN..=VirtPageNum::new(user_start.value() - 1),
+        user_end + 1..=VirtPageNum::MAX,
+    ];
+
+    let kpgtbl = KERNEL_PAGE_TABLE.get().unwrap().lock();
+    for range in ranges {
+        pt.share_mappings(&kpgtbl.pt, range.clone())
+            .with_whatever_context(|_| {
+                format!("failed to share kernel mappings, vpn_range={range:#x?}")
+            })?;
     }
+    kpgtbl.unlock();
 
     Ok(())
 }
 
+/// Result of looking up a virtual address in the kernel page table.
+#[derive(Debug, Clone, Copy)]
+pub enum MappingLookup {
+    /// The address is mapped by the mapping.
+    Mapped(Mapping),
+    /// The address is not mapped.
+    ///
+    /// `below` and `above` are the nearest mappings around the address.
+    Unmapped {
+        below: Option<Mapping>,
+        above: Option<Mapping>,
+    },
+}
+
+/// Looks up `virt_addr` in the kernel page table.
+///
+/// Returns `None` if the kernel page table is not initialized or is locked by
+/// another context, so that this can be called from trap handlers.
+pub fn try_lookup(virt_addr: VirtAddr) -> Option<MappingLookup> {
+    let kpgtbl = KERNEL_PAGE_TABLE.get()?.try_lock()?;
+    let lookup = kpgtbl.lookup(virt_addr);
+    kpgtbl.unlock();
+    Some(lookup)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KernelStackError {
+    /// The requested size is zero or exceeds [`MAX_KERNEL_STACK_SIZE`].
+    InvalidSize,
+    /// No virtual address space is left for the stack.
+    NoSlot,
+    /// The pages or the page tables of the stack cannot be mapped.
+    MapFailed,
+}
+
+impl fmt::Display for KernelStackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::InvalidSize => "invalid kernel stack size",
+            Self::NoSlot => "no kernel stack slot available",
+            Self::MapFailed => "failed to map kernel stack pages",
+        };
+        f.write_str(s)
+    }
+}
+
+impl core::error::Error for KernelStackError {}
+
 #[derive(Debug)]
 pub struct KernelStack {
-    slot: StackSlot,
+    /// Slot returned once the pages are freed, or leaked if they cannot be.
+    slot: ManuallyDrop<StackSlot>,
+    /// Range that the pages of the stack are mapped in.
+    range: Range<usize>,
 }
 
 impl KernelStack {
+    fn new(slot: StackSlot, size: usize) -> Self {
+        let range = slot.top() - size..slot.top();
+        Self {
+            slot: ManuallyDrop::new(slot),
+            range,
+        }
+    }
+
     pub fn top(&self) -> usize {
         self.slot.top()
     }
@@ -155,24 +311,265 @@ impl KernelStack {
 
 impl Drop for KernelStack {
     fn drop(&mut self) {
-        unimplemented!("freeing kernel stack is not implemented yet");
+        // the stack must not be in use by any CPU here. the slot is reused
+        // only after the stale TLB entries are flushed.
+        if let Err(e) = vmalloc::release_area(self.range.clone()) {
+            warn!("failed to free kernel stack {:#x?}: {e}", self.range);
+            return;
+        }
+        unsafe {
+            ManuallyDrop::drop(&mut self.slot);
+        }
+    }
+}
+
+/// Allocates a kernel stack whose pages below the top one are mapped on their
+/// first access.
+///
+/// The stack can grow up to `size` bytes, rounded up to the page size. Accesses
+/// below it fault as stack overflows.
+pub fn allocate_kernel_stack(size: usize) -> Result<KernelStack, KernelStackError> {
+    if !(0 < size && size <= MAX_KERNEL_STACK_SIZE) {
+        return Err(KernelStackError::InvalidSize);
     }
+    let slot = StackSlot::allocate().ok_or(KernelStackError::NoSlot)?;
+    let stack = KernelStack::new(slot, size.page_align_up());
+    vmalloc::reserve_area(stack.range.clone()).map_err(|_e| KernelStackError::MapFailed)?;
+    map_stack_pages(stack.top() - PAGE_SIZE..stack.top())?;
+    Ok(stack)
 }
 
-pub fn allocate_kernel_stack() -> Result<KernelStack, GenericError> {
-    let slot = StackSlot::allocate().whatever_context("no stack slot available")?;
+/// Allocates a kernel stack whose pages are all mapped up front.
+///
+/// This is used for the stacks that are running while stack faults cannot be
+/// handled, such as the ones of the trap handlers.
+pub fn allocate_committed_kernel_stack() -> Result<KernelStack, KernelStackError> {
+    let slot = StackSlot::allocate().ok_or(KernelStackError::NoSlot)?;
+    let stack = KernelStack::new(slot, MAX_KERNEL_STACK_SIZE);
+    map_stack_pages(stack.range.clone())?;
+    Ok(stack)
+}
 
+/// Maps the pages of `range`, which are freed with the stack if this fails.
+fn map_stack_pages(range: Range<usize>) -> Result<(), KernelStackError> {
     let mut kpgtbl = KERNEL_PAGE_TABLE.get().unwrap().lock();
-    let asid = kpgtbl.asid();
-    kpgtbl
-        .allocate_virt_addr_range(slot.range(), MapPageFlags::RW)
-        .whatever_context("failed to update kernel page table")?;
+    let res = kpgtbl.allocate_virt_addr_range(range, MapPageFlags::RW);
+    let pending = kpgtbl.take_pending();
     kpgtbl.unlock();
 
-    if APPLIED.get().load(Ordering::Acquire) {
-        apply_page_table_changes(asid, slot.range())
-            .whatever_context("failed to apply page table changes")?;
+    res.map_err(|_e| KernelStackError::MapFailed)?;
+    pending.flush().map_err(|_e| KernelStackError::MapFailed)?;
+    Ok(())
+}
+
+#[cfg(feature = "ktest")]
+pub mod ktests {
+    use alloc::{alloc::dealloc, vec::Vec};
+    use core::ptr;
+
+    use snafu::{OptionExt as _, ResultExt as _, ensure_whatever};
+    use sv39::{
+        MapPageFlags, MappedRegion, PageTableRoot,
+        address::{PhysPageNum, VirtAddr, VirtPageNum},
+    };
+
+    use super::{KERNEL_PAGE_TABLE, KernelStackError, MappingLookup};
+    use crate::{
+        error::GenericError,
+        ktest::KernelTest,
+        memory::{PAGE_SIZE, allocator},
+    };
+
+    pub static TESTS: &[KernelTest] = kernel_tests![
+        export_import,
+        export_kernel_page_table,
+        writable_executable_refused,
+        reserved_flags_refused,
+        free_empty_tables,
+        free_kernel_stack
+    ];
+
+    fn region(vpn: u64, ppn: u64, page_count: usize, flags: MapPageFlags) -> MappedRegion {
+        MappedRegion {
+            virt_page_num: VirtPageNum::new(vpn),
+            phys_page_num: PhysPageNum::new(ppn),
+            page_count,
+            flags,
+        }
+    }
+
+    fn export(pt: &PageTableRoot) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        pt.export(|region| {
+            bytes.extend_from_slice(region);
+            Ok::<_, GenericError>(())
+        })
+        .unwrap();
+        bytes
     }
 
-    Ok(KernelStack { slot })
+    fn export_import() -> Result<(), GenericError> {
+        // the first two ranges are merged, crossing a 2 MiB page boundary
+        let expected = [
+            region(0x1f0, 0x8_01f0, 0x210, MapPageFlags::RW),
+            region(0x400, 0x8_0400, 0x10, MapPageFlags::R),
+            region(0x410, 0x9_0000, 1, MapPageFlags::R),
+        ];
+        let mut pt = PageTableRoot::new(1).whatever_context("failed to create page table")?;
+        for (vpn, ppn, count, flags) in [
+            (0x1f0, 0x8_01f0, 0x10, MapPageFlags::RW),
+            (0x200, 0x8_0200, 0x200, MapPageFlags::RW),
+            (0x400, 0x8_0400, 0x10, MapPageFlags::R),
+            (0x410, 0x9_0000, 1, MapPageFlags::R),
+        ] {
+            pt.map_fixed_pages(VirtPageNum::new(vpn), PhysPageNum::new(ppn), count, flags)
+                .whatever_context("failed to map pages")?;
+        }
+        let regions = pt.regions().collect::<Vec<_>>();
+        ensure_whatever!(regions == expected, "unexpected regions: {regions:#x?}");
+
+        let bytes = export(&pt);
+        ensure_whatever!(
+            bytes.len() == expected.len() * MappedRegion::ENCODED_SIZE,
+            "exported {} bytes",
+            bytes.len()
+        );
+        let imported = PageTableRoot::import(2, &bytes).whatever_context("failed to import")?;
+        let regions = imported.regions().collect::<Vec<_>>();
+        ensure_whatever!(regions == expected, "imported regions: {regions:#x?}");
+        ensure_whatever!(export(&imported) == bytes, "re-exported bytes differ");
+
+        ensure_whatever!(
+            PageTableRoot::import(3, &bytes[1..]).is_err(),
+            "truncated regions imported"
+        );
+        ensure_whatever!(
+            PageTableRoot::import(3, &[0; MappedRegion::ENCODED_SIZE]).is_err(),
+            "empty region imported"
+        );
+        Ok(())
+    }
+
+    fn export_kernel_page_table() -> Result<(), GenericError> {
+        let kpgtbl = KERNEL_PAGE_TABLE.get().unwrap().lock();
+        let regions = kpgtbl.pt.regions().collect::<Vec<_>>();
+        let bytes = export(&kpgtbl.pt);
+        kpgtbl.unlock();
+
+        let decoded = bytes
+            .as_chunks::<{ MappedRegion::ENCODED_SIZE }>()
+            .0
+            .iter()
+            .map(MappedRegion::decode)
+            .collect::<Result<Vec<_>, _>>()
+            .whatever_context("failed to decode regions")?;
+        ensure_whatever!(decoded == regions, "decoded regions differ");
+        ensure_whatever!(
+            regions
+                .windows(2)
+                .all(|w| w[0].virt_page_num + w[0].page_count <= w[1].virt_page_num),
+            "regions are not sorted"
+        );
+        Ok(())
+    }
+
+    fn writable_executable_refused() -> Result<(), GenericError> {
+        let mut pt = PageTableRoot::new(1).whatever_context("failed to create page table")?;
+        let (vpn, ppn) = (VirtPageNum::new(0x100), PhysPageNum::new(0x8_0100));
+        ensure_whatever!(
+            pt.map_fixed_pages(vpn, ppn, 1, MapPageFlags::RWX).is_err(),
+            "writable and executable page mapped"
+        );
+        ensure_whatever!(pt.regions().next().is_none(), "refused page left mapped");
+
+        pt.map_fixed_pages(vpn, ppn, 1, MapPageFlags::RWX | MapPageFlags::ALLOW_WX)
+            .whatever_context("failed to map page with ALLOW_WX")?;
+        let bytes = export(&pt);
+        PageTableRoot::import(2, &bytes).whatever_context("failed to import allowed page")?;
+        Ok(())
+    }
+
+    fn reserved_flags_refused() -> Result<(), GenericError> {
+        let mut pt = PageTableRoot::new(1).whatever_context("failed to create page table")?;
+        let (vpn, ppn) = (VirtPageNum::new(0x100), PhysPageNum::new(0x8_0100));
+        // not a leaf without any of R, W and X, and W without R is reserved
+        for flags in [MapPageFlags::U, MapPageFlags::W, MapPageFlags::UW] {
+            ensure_whatever!(
+                pt.map_fixed_pages(vpn, ppn, 1, flags).is_err(),
+                "page mapped with {flags:?}"
+            );
+            ensure_whatever!(
+                pt.allocate_pages(vpn, 1, flags).is_err(),
+                "page allocated with {flags:?}"
+            );
+        }
+        ensure_whatever!(pt.regions().next().is_none(), "refused page left mapped");
+        Ok(())
+    }
+
+    fn free_empty_tables() -> Result<(), GenericError> {
+        // a 1 GiB entry of the root table
+        const ENTRY_PAGES: usize = 1 << 18;
+
+        let mut pt = PageTableRoot::new(1).whatever_context("failed to create page table")?;
+        let vpn = VirtPageNum::new(0x100);
+        pt.allocate_pages(vpn, 1, MapPageFlags::RW)
+            .whatever_context("failed to allocate page")?;
+        let page = pt
+            .find_mapping(VirtAddr::min_in_page(vpn))
+            .whatever_context("allocated page not mapped")?
+            .min_phys_addr;
+
+        let freed = pt.free_tables(VirtPageNum::new(0), ENTRY_PAGES);
+        ensure_whatever!(freed == 0, "{freed} tables with a mapping freed");
+
+        pt.unmap_pages(vpn, 1)
+            .whatever_context("failed to unmap page")?;
+        unsafe {
+            dealloc(page.as_mut_ptr(), allocator::page_layout());
+        }
+        // the tables partially in the range are kept
+        let freed = pt.free_tables(vpn, 1);
+        ensure_whatever!(freed == 0, "{freed} tables partially in the range freed");
+        let freed = pt.free_tables(VirtPageNum::new(0), ENTRY_PAGES);
+        ensure_whatever!(freed == 2, "{freed} empty tables freed, expected 2");
+        ensure_whatever!(pt.regions().next().is_none(), "pages left mapped");
+        Ok(())
+    }
+
+    fn is_mapped(addr: usize) -> bool {
+        matches!(
+            super::try_lookup(VirtAddr::from_addr(addr)),
+            Some(MappingLookup::Mapped(_))
+        )
+    }
+
+    fn free_kernel_stack() -> Result<(), GenericError> {
+        ensure_whatever!(
+            matches!(
+                super::allocate_kernel_stack(0),
+                Err(KernelStackError::InvalidSize)
+            ),
+            "empty kernel stack allocated"
+        );
+
+        let stack = super::allocate_kernel_stack(2 * PAGE_SIZE)
+            .whatever_context("failed to allocate kernel stack")?;
+        let pages = [stack.top() - 2 * PAGE_SIZE, stack.top() - PAGE_SIZE];
+        // the page below the top one is mapped on its first access
+        unsafe {
+            ptr::with_exposed_provenance_mut::<u8>(pages[0]).write_volatile(1);
+        }
+        ensure_whatever!(
+            pages.iter().all(|&page| is_mapped(page)),
+            "kernel stack pages {pages:#x?} not mapped"
+        );
+
+        drop(stack);
+        ensure_whatever!(
+            !pages.iter().any(|&page| is_mapped(page)),
+            "freed kernel stack pages {pages:#x?} left mapped"
+        );
+        Ok(())
+    }
 }
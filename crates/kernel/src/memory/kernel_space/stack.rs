@@ -1,64 +1,143 @@
-use alloc::vec::Vec;
-use core::ops::Range;
+use alloc::{collections::btree_map::BTreeMap, vec::Vec};
+use core::{
+    ops::Range,
+    sync::atomic::{AtomicU64, Ordering},
+};
 
-use spin::{Once, mutex::SpinMutex};
+use platform_cast::CastFrom as _;
 
-const STACK_SIZE: usize = 128 * 1024;
+use super::vmalloc;
+use crate::{
+    interrupt::trap::{
+        TrapFrame,
+        fault::{self, AccessType, Fault},
+    },
+    memory::layout::{self, KERNEL_STACK_REGION_SIZE},
+    rand,
+    sync::spinlock::SpinMutex,
+};
+
+pub(super) const STACK_SIZE: usize = 128 * 1024;
 const STACK_PADDING_SIZE: usize = 128 * 1024;
-const STACK_ARENA_SIZE: usize = 1024 * 1024 * 1024;
-const NUM_STACK_SLOTS: usize = STACK_ARENA_SIZE / (STACK_SIZE + STACK_PADDING_SIZE);
-const STACK_ARENA_START: usize = 0xffff_ffc0_0000_0000;
-const STACK_ARENA_END: usize = STACK_ARENA_START + STACK_ARENA_SIZE;
+const SLOT_SIZE: usize = STACK_SIZE + STACK_PADDING_SIZE;
+const NUM_STACK_SLOTS: usize = KERNEL_STACK_REGION_SIZE / SLOT_SIZE;
+
+static STACK_SLOT_ALLOCATOR: SpinMutex<StackSlotAllocator> =
+    SpinMutex::new(StackSlotAllocator::new());
 
-static STACK_SLOT_ALLOCATOR: Once<SpinMutex<StackSlotAllocator>> = Once::new();
+/// Number of the faults in the unmapped parts of the slots in use.
+static GUARD_TRIPS: AtomicU64 = AtomicU64::new(0);
 
 type AllocatorChunk = u128;
 const CHUNK_BITS: usize = 128;
 
-pub fn init() {
-    STACK_SLOT_ALLOCATOR.call_once(|| SpinMutex::new(StackSlotAllocator::new()));
+/// Registers the fault hook counting the stack overflows.
+///
+/// This must be called after the vmalloc region is initialized, so that the
+/// faults on the demand-paged stack pages are handled before the hook.
+pub(super) fn init() {
+    fault::register_hook(handle_fault);
+}
+
+/// Usage of the kernel stack slots.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(not(feature = "shell"), expect(dead_code))]
+pub struct StackStats {
+    /// Number of the slots in use.
+    pub in_use: usize,
+    /// Largest number of the slots that have been in use at once.
+    pub peak: usize,
+    /// Number of the faults below the stacks, which are stack overflows.
+    pub guard_trips: u64,
+}
+
+#[cfg_attr(not(feature = "shell"), expect(dead_code))]
+pub fn stats() -> StackStats {
+    let allocator = STACK_SLOT_ALLOCATOR.lock();
+    let (in_use, peak) = (allocator.in_use, allocator.peak);
+    allocator.unlock();
+    StackStats {
+        in_use,
+        peak,
+        guard_trips: GUARD_TRIPS.load(Ordering::Relaxed),
+    }
+}
+
+/// Returns whether `addr` is in one of the slots in use.
+#[cfg(feature = "ktest")]
+pub fn is_in_use(addr: usize) -> bool {
+    STACK_SLOT_ALLOCATOR.lock().is_allocated(addr)
 }
 
 struct StackSlotAllocator {
+    /// Bitmap of the slots in the kernel stack region, extended on demand.
+    ///
+    /// The slots beyond the bitmap are free.
     allocated_slots: Vec<AllocatorChunk>,
     next_search_slot: usize,
+    /// Slots taken from the vmalloc region after the kernel stack region is
+    /// exhausted, mapping their start addresses to their end addresses.
+    vmalloc_slots: BTreeMap<usize, usize>,
+    in_use: usize,
+    peak: usize,
 }
 
 impl StackSlotAllocator {
     #[must_use]
-    fn new() -> Self {
-        let allocated_slots = alloc::vec![0; NUM_STACK_SLOTS.div_ceil(CHUNK_BITS)];
+    const fn new() -> Self {
         Self {
-            allocated_slots,
+            allocated_slots: Vec::new(),
             next_search_slot: 0,
+            vmalloc_slots: BTreeMap::new(),
+            in_use: 0,
+            peak: 0,
         }
     }
 
     fn slot_bit(&self, slot: usize) -> bool {
         let (chunk, bit) = (slot / CHUNK_BITS, slot % CHUNK_BITS);
-        self.allocated_slots[chunk] & (1 << bit) != 0
+        self.allocated_slots
+            .get(chunk)
+            .is_some_and(|chunk| chunk & (1 << bit) != 0)
     }
 
     fn set_slot_bit(&mut self, slot: usize) {
         let (chunk, bit) = (slot / CHUNK_BITS, slot % CHUNK_BITS);
+        if chunk >= self.allocated_slots.len() {
+            self.allocated_slots.resize(chunk + 1, 0);
+        }
         self.allocated_slots[chunk] |= 1 << bit;
     }
 
     fn clear_slot_bit(&mut self, slot: usize) {
         let (chunk, bit) = (slot / CHUNK_BITS, slot % CHUNK_BITS);
         self.allocated_slots[chunk] &= !(1 << bit);
+        while self.allocated_slots.last() == Some(&0) {
+            self.allocated_slots.pop();
+        }
     }
 
-    fn find_free_slot(&self) -> Option<usize> {
-        (self.next_search_slot..NUM_STACK_SLOTS)
-            .chain(0..self.next_search_slot)
+    fn find_free_slot(&self, start: usize) -> Option<usize> {
+        (start..NUM_STACK_SLOTS)
+            .chain(0..start)
             .find(|i| !self.slot_bit(*i))
     }
 
-    fn allocate_slot(&mut self) -> Option<usize> {
-        let slot = self.find_free_slot()?;
+    fn count_allocated(&mut self) {
+        self.in_use += 1;
+        self.peak = usize::max(self.peak, self.in_use);
+    }
+
+    /// Allocates a free slot, searching from the slot after the last one, or
+    /// from `random` modulo the number of the slots if it is given.
+    fn allocate_slot(&mut self, random: Option<u64>) -> Option<usize> {
+        let start = random.map_or(self.next_search_slot, |random| {
+            usize::cast_from(random % u64::try_from(NUM_STACK_SLOTS).unwrap())
+        });
+        let slot = self.find_free_slot(start)?;
         self.set_slot_bit(slot);
         self.next_search_slot = (slot + 1) % NUM_STACK_SLOTS;
+        self.count_allocated();
         Some(slot)
     }
 
@@ -66,35 +145,231 @@ impl StackSlotAllocator {
         assert!(slot < NUM_STACK_SLOTS);
         assert!(self.slot_bit(slot));
         self.clear_slot_bit(slot);
+        self.in_use -= 1;
+    }
+
+    fn add_vmalloc_slot(&mut self, range: Range<usize>) {
+        assert!(self.vmalloc_slots.insert(range.start, range.end).is_none());
+        self.count_allocated();
+    }
+
+    fn remove_vmalloc_slot(&mut self, range: &Range<usize>) {
+        assert_eq!(self.vmalloc_slots.remove(&range.start), Some(range.end));
+        self.in_use -= 1;
+    }
+
+    /// Returns whether `addr` is in one of the slots in use.
+    fn is_allocated(&self, addr: usize) -> bool {
+        let region = layout::kernel_stack_range();
+        if region.contains(&addr) {
+            return self.slot_bit((region.end - 1 - addr) / SLOT_SIZE);
+        }
+        self.vmalloc_slots
+            .range(..=addr)
+            .next_back()
+            .is_some_and(|(_start, end)| addr < *end)
+    }
+}
+
+/// Counts the page faults in the slots in use that no earlier hook handled.
+///
+/// The faults are left unhandled.
+fn handle_fault(fault: &Fault, _frame: &mut TrapFrame) -> bool {
+    let Fault::Page { addr, access } = *fault else {
+        return false;
+    };
+    if access == AccessType::Execute {
+        return false;
     }
+    let Some(allocator) = STACK_SLOT_ALLOCATOR.try_lock() else {
+        return false;
+    };
+    let in_slot = allocator.is_allocated(addr);
+    allocator.unlock();
+    if in_slot {
+        GUARD_TRIPS.fetch_add(1, Ordering::Relaxed);
+    }
+    false
+}
+
+/// Returns the stack range of the slot containing `addr` in the kernel stack
+/// region.
+///
+/// The rest of the slot is the guard area below the stack, which is never
+/// mapped.
+#[cfg(debug_assertions)]
+pub(super) fn slot_stack_range(addr: usize) -> Range<usize> {
+    let region = layout::kernel_stack_range();
+    assert!(region.contains(&addr));
+    let slot = (region.end - 1 - addr) / SLOT_SIZE;
+    let end = region.end - SLOT_SIZE * slot;
+    end - STACK_SIZE..end
 }
 
 #[derive(Debug)]
-pub(super) struct StackSlot {
-    slot: usize,
+pub(super) enum StackSlot {
+    /// Slot of the index in the kernel stack region.
+    Region(usize),
+    /// Slot taken from the vmalloc region, with the guard area at its bottom.
+    Vmalloc(Range<usize>),
 }
 
 impl StackSlot {
+    /// Allocates a slot, chosen at random if the kernel address space
+    /// randomization is enabled.
+    ///
+    /// The slot is taken from the vmalloc region if the kernel stack region is
+    /// exhausted.
     pub(super) fn allocate() -> Option<Self> {
-        let mut allocator = STACK_SLOT_ALLOCATOR.get().unwrap().lock();
-        let slot = allocator.allocate_slot()?;
-        Some(Self { slot })
+        let random = layout::is_kaslr_enabled().then(rand::next_u64);
+        let mut allocator = STACK_SLOT_ALLOCATOR.lock();
+        if let Some(slot) = allocator.allocate_slot(random) {
+            return Some(Self::Region(slot));
+        }
+        allocator.unlock();
+
+        let range = vmalloc::take_space(SLOT_SIZE)?;
+        STACK_SLOT_ALLOCATOR.lock().add_vmalloc_slot(range.clone());
+        Some(Self::Vmalloc(range))
     }
 
+    #[cfg_attr(not(feature = "ktest"), expect(dead_code))]
     pub fn range(&self) -> Range<usize> {
         let end = self.top();
         end - STACK_SIZE..end
     }
 
     pub fn top(&self) -> usize {
-        assert!(self.slot < NUM_STACK_SLOTS);
-        STACK_ARENA_END - (STACK_SIZE + STACK_PADDING_SIZE) * self.slot
+        match self {
+            Self::Region(slot) => {
+                assert!(*slot < NUM_STACK_SLOTS);
+                layout::kernel_stack_range().end - SLOT_SIZE * slot
+            }
+            Self::Vmalloc(range) => range.end,
+        }
     }
 }
 
 impl Drop for StackSlot {
     fn drop(&mut self) {
-        let mut allocator = STACK_SLOT_ALLOCATOR.get().unwrap().lock();
-        allocator.free_slot(self.slot);
+        let mut allocator = STACK_SLOT_ALLOCATOR.lock();
+        match self {
+            Self::Region(slot) => allocator.free_slot(*slot),
+            Self::Vmalloc(range) => {
+                allocator.remove_vmalloc_slot(range);
+                allocator.unlock();
+                vmalloc::return_space(range.clone());
+            }
+        }
+    }
+}
+
+#[cfg(feature = "ktest")]
+pub mod ktests {
+    use alloc::vec::Vec;
+    use core::iter;
+
+    use snafu::{OptionExt as _, ensure_whatever};
+
+    use super::{NUM_STACK_SLOTS, STACK_SIZE, StackSlot, StackSlotAllocator};
+    use crate::{
+        error::GenericError,
+        ktest::KernelTest,
+        memory::{PAGE_SIZE, layout},
+    };
+
+    pub static TESTS: &[KernelTest] =
+        kernel_tests![random_slots, random_slot_ranges, slots_from_vmalloc];
+
+    fn random_slots() -> Result<(), GenericError> {
+        let mut allocator = StackSlotAllocator::new();
+        let mut slots = Vec::new();
+        // the search wraps around from the random start, so every slot is
+        // allocated exactly once
+        for i in 0..NUM_STACK_SLOTS {
+            let random = u64::try_from(i)
+                .unwrap()
+                .wrapping_mul(0x9e37_79b9_7f4a_7c15);
+            let slot = allocator
+                .allocate_slot(Some(random))
+                .whatever_context("stack slots exhausted")?;
+            ensure_whatever!(slot < NUM_STACK_SLOTS, "slot {slot} out of range");
+            slots.push(slot);
+        }
+        ensure_whatever!(
+            allocator.allocate_slot(Some(0)).is_none(),
+            "slot allocated beyond the region"
+        );
+        slots.sort_unstable();
+        slots.dedup();
+        ensure_whatever!(slots.len() == NUM_STACK_SLOTS, "slot allocated twice");
+        Ok(())
+    }
+
+    fn random_slot_ranges() -> Result<(), GenericError> {
+        let region = layout::kernel_stack_range();
+        let slots = iter::repeat_with(StackSlot::allocate)
+            .take(16)
+            .collect::<Option<Vec<_>>>()
+            .whatever_context("failed to allocate stack slots")?;
+        for (i, slot) in slots.iter().enumerate() {
+            let range = slot.range();
+            ensure_whatever!(
+                region.start <= range.start && range.end <= region.end,
+                "stack {range:#x?} out of the region {region:#x?}"
+            );
+            ensure_whatever!(
+                range.len() == STACK_SIZE && range.start.is_multiple_of(PAGE_SIZE),
+                "stack {range:#x?} not page aligned"
+            );
+            for other in &slots[i + 1..] {
+                let other = other.range();
+                ensure_whatever!(
+                    range.end <= other.start || other.end <= range.start,
+                    "stacks {range:#x?} and {other:#x?} overlap"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn slots_from_vmalloc() -> Result<(), GenericError> {
+        let mut slots = Vec::new();
+        // the slots are taken from the vmalloc region once the kernel stack
+        // region is exhausted
+        loop {
+            let slot = StackSlot::allocate().whatever_context("failed to allocate stack slot")?;
+            let from_vmalloc = matches!(slot, StackSlot::Vmalloc(_));
+            slots.push(slot);
+            if from_vmalloc {
+                break;
+            }
+            ensure_whatever!(
+                slots.len() <= NUM_STACK_SLOTS,
+                "more slots than the kernel stack region allocated"
+            );
+        }
+
+        let range = slots.last().unwrap().range();
+        let vmalloc = layout::vmalloc_range();
+        ensure_whatever!(
+            vmalloc.start <= range.start && range.end <= vmalloc.end,
+            "stack {range:#x?} out of the vmalloc region {vmalloc:#x?}"
+        );
+        let stats = super::stats();
+        ensure_whatever!(
+            slots.len() <= stats.in_use && stats.in_use <= stats.peak,
+            "unexpected stats with {} slots: {stats:?}",
+            slots.len()
+        );
+
+        let count = slots.len();
+        drop(slots);
+        let freed = super::stats();
+        ensure_whatever!(
+            freed.in_use + count == stats.in_use,
+            "slots not freed: {freed:?}"
+        );
+        Ok(())
     }
 }
@@ -0,0 +1,54 @@
+use core::{ops::Range, sync::atomic::Ordering};
+
+use sv39::address::VirtAddr;
+
+use super::APPLIED;
+use crate::{error::GenericError, memory::tlb};
+
+/// Kernel virtual addresses whose mappings have changed while the kernel page
+/// table was locked.
+///
+/// The changes of a transaction are flushed at once after the lock is
+/// released, with a single SBI call per remote CPU mask. The kernel mappings
+/// are shared by all address spaces, so the entries of all ASIDs are flushed.
+#[derive(Debug, Default)]
+pub(super) struct TlbBatch {
+    /// Range covering all the changed addresses.
+    range: Option<Range<usize>>,
+}
+
+impl TlbBatch {
+    pub(super) const fn new() -> Self {
+        Self { range: None }
+    }
+
+    pub(super) fn add(&mut self, range: Range<usize>) {
+        if range.is_empty() {
+            return;
+        }
+        self.range = Some(match self.range.take() {
+            Some(current) => {
+                usize::min(current.start, range.start)..usize::max(current.end, range.end)
+            }
+            None => range,
+        });
+    }
+
+    /// Flushes the changed entries from the TLBs of all CPUs.
+    ///
+    /// Nothing is flushed before the kernel page table is applied, as applying
+    /// it flushes the whole TLB.
+    pub(super) fn flush(self) -> Result<(), GenericError> {
+        let Some(range) = self.range else {
+            return Ok(());
+        };
+        if !APPLIED.get().load(Ordering::Acquire) {
+            return Ok(());
+        }
+
+        tlb::shootdown(
+            VirtAddr::from_addr(range.start)..VirtAddr::from_addr(range.end),
+            None,
+        )
+    }
+}
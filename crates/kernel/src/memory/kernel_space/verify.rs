@@ -0,0 +1,115 @@
+use alloc::{format, string::String, vec::Vec};
+use core::ops::Range;
+
+use snafu::whatever;
+use sv39::{MapPageFlags, Mapping};
+
+use super::{KERNEL_PAGE_TABLE, stack};
+use crate::{
+    error::GenericError,
+    memory::layout::{self, HeapLayout},
+};
+
+/// Walks the kernel page table and checks the memory layout invariants.
+///
+/// No page may be writable and executable, the kernel image and the
+/// devicetree blob must be mapped with their permissions, and the guard areas
+/// of the kernel stacks must be unmapped. All violations are logged before
+/// the error is returned.
+pub fn verify(heap_layout: &HeapLayout) -> Result<(), GenericError> {
+    let kpgtbl = KERNEL_PAGE_TABLE.get().unwrap().lock();
+    let mappings = kpgtbl.pt.mappings().collect::<Vec<_>>();
+    kpgtbl.unlock();
+
+    let mut violations = Vec::new();
+    let stack_region = layout::kernel_stack_range();
+    for mapping in &mappings {
+        if mapping.flags.contains(MapPageFlags::W | MapPageFlags::X) {
+            violations.push(format!(
+                "writable and executable page, {}",
+                describe(mapping)
+            ));
+        }
+        let range = virt_range(mapping);
+        if stack_region.contains(&range.start) {
+            let stack = stack::slot_stack_range(range.start);
+            if range.end > stack.end {
+                violations.push(format!(
+                    "mapping crosses kernel stack {stack:#x?}, {}",
+                    describe(mapping)
+                ));
+            } else if range.start < stack.start {
+                violations.push(format!(
+                    "guard page of kernel stack {stack:#x?} is mapped, {}",
+                    describe(mapping)
+                ));
+            }
+        }
+    }
+
+    for (name, range, flags) in [
+        ("kernel text", layout::kernel_rx_range(), MapPageFlags::RX),
+        ("kernel rodata", layout::kernel_ro_range(), MapPageFlags::R),
+        ("kernel data", layout::kernel_rw_range(), MapPageFlags::RW),
+        ("devicetree blob", heap_layout.dtb_range(), MapPageFlags::R),
+    ] {
+        check_range(&mappings, name, &range, flags, &mut violations);
+    }
+
+    if violations.is_empty() {
+        info!("kernel page table verified, {} mappings", mappings.len());
+        return Ok(());
+    }
+    error!("kernel page table verification failed:");
+    for violation in &violations {
+        error!("  {violation}");
+    }
+    whatever!("{} violations found in kernel page table", violations.len());
+}
+
+/// Checks that `range` is entirely mapped with `expected` permissions.
+fn check_range(
+    mappings: &[Mapping],
+    name: &str,
+    range: &Range<usize>,
+    expected: MapPageFlags,
+    violations: &mut Vec<String>,
+) {
+    let mut mapped = 0;
+    for mapping in mappings {
+        let mapping_range = virt_range(mapping);
+        let start = usize::max(mapping_range.start, range.start);
+        let end = usize::min(mapping_range.end, range.end);
+        if start >= end {
+            continue;
+        }
+        mapped += end - start;
+        let flags = mapping.flags & MapPageFlags::URWX;
+        if flags != expected {
+            violations.push(format!(
+                "{name} {range:#x?} is mapped as {flags:?}, expected {expected:?}, {}",
+                describe(mapping)
+            ));
+        }
+    }
+    if mapped < range.len() {
+        violations.push(format!(
+            "{name} {range:#x?} is not fully mapped, {mapped:#x} of {:#x} bytes mapped",
+            range.len()
+        ));
+    }
+}
+
+fn virt_range(mapping: &Mapping) -> Range<usize> {
+    mapping.min_virt_addr.value()..mapping.max_virt_addr.value() + 1
+}
+
+fn describe(mapping: &Mapping) -> String {
+    format!(
+        "virt={:#x?}, phys={:#p}, flags={:?}, level={}",
+        virt_range(mapping),
+        mapping.min_phys_addr,
+        mapping.flags,
+        mapping.level
+    )
+}
@@ -0,0 +1,349 @@
+use alloc::{alloc::dealloc, collections::btree_map::BTreeMap, format, vec::Vec};
+use core::{ops::Range, ptr, slice};
+
+use range_set::RangeSet;
+use riscv_utils::asm;
+use snafu::{OptionExt as _, ResultExt as _, ensure_whatever};
+use sv39::{MapPageFlags, address::VirtAddr};
+
+use super::KERNEL_PAGE_TABLE;
+use crate::{
+    error::GenericError,
+    interrupt::trap::{
+        TrapFrame,
+        fault::{self, AccessType, Fault},
+    },
+    memory::{Align as _, PAGE_SIZE, allocator, layout},
+    sync::spinlock::SpinMutex,
+};
+
+/// Free virtual address ranges in the vmalloc region.
+static VMALLOC_SPACE: SpinMutex<RangeSet<128>> = SpinMutex::new(RangeSet::new());
+
+/// Demand-paged areas, mapping their start addresses to their end addresses.
+///
+/// The pages of the areas are mapped readable and writable by the fault
+/// handler on their first access.
+static DEMAND_AREAS: SpinMutex<BTreeMap<usize, usize>> = SpinMutex::new(BTreeMap::new());
+
+pub(super) fn init() {
+    VMALLOC_SPACE.lock().insert(layout::vmalloc_range());
+    fault::register_hook(handle_fault);
+}
+
+/// Registers `range` as a demand-paged area.
+///
+/// The lower level page tables covering the range are allocated here, so that
+/// the fault handler only allocates the pages.
+pub(super) fn reserve_area(range: Range<usize>) -> Result<(), GenericError> {
+    let mut kpgtbl = KERNEL_PAGE_TABLE.get().unwrap().lock();
+    let res = kpgtbl.reserve_tables(range.clone());
+    kpgtbl.unlock();
+    res.with_whatever_context(|_| {
+        format!("failed to reserve kernel page tables, range={range:#x?}")
+    })?;
+
+    DEMAND_AREAS.lock().insert(range.start, range.end);
+    Ok(())
+}
+
+/// Maps the page of a demand-paged area that caused `fault`.
+///
+/// The fault is left unhandled if the faulting context holds the locks of the
+/// kernel page table or the heap.
+fn handle_fault(fault: &Fault, _frame: &mut TrapFrame) -> bool {
+    let Fault::Page { addr, access } = *fault else {
+        return false;
+    };
+    if access == AccessType::Execute {
+        return false;
+    }
+
+    let Some(areas) = DEMAND_AREAS.try_lock() else {
+        return false;
+    };
+    let in_area = areas
+        .range(..=addr)
+        .next_back()
+        .is_some_and(|(_start, end)| addr < *end);
+    areas.unlock();
+    if !in_area {
+        return false;
+    }
+
+    let Some(mut kpgtbl) = KERNEL_PAGE_TABLE.get().and_then(SpinMutex::try_lock) else {
+        return false;
+    };
+    let asid = kpgtbl.asid();
+    let page_addr = addr.page_align_down();
+    // the page may have been mapped by another CPU since this CPU cached the
+    // invalid entry
+    if kpgtbl
+        .pt
+        .find_mapping(VirtAddr::from_addr(page_addr))
+        .is_none()
+    {
+        let Some(page) = allocator::try_allocate_zeroed_page() else {
+            kpgtbl.unlock();
+            return false;
+        };
+        let res = kpgtbl.map_range(
+            page_addr..page_addr + PAGE_SIZE,
+            page.expose_provenance(),
+            MapPageFlags::RW,
+        );
+        if res.is_err() {
+            kpgtbl.unlock();
+            unsafe {
+                dealloc(page, allocator::page_layout());
+            }
+            return false;
+        }
+    }
+    kpgtbl.unlock();
+
+    // no remote fence is needed, as the other CPUs fault on the page again if
+    // they cached the invalid entry. the page is also left in the pending
+    // changes, and flushed with the next transaction.
+    asm::sfence_vma(page_addr, asid.into());
+    true
+}
+
+/// Kernel memory whose pages are allocated on their first access.
+///
+/// The memory reads as zeros until it is written. It is not physically
+/// contiguous, so it must not be passed to the devices.
+#[cfg_attr(not(feature = "ktest"), expect(dead_code))]
+#[derive(Debug)]
+pub struct VmallocArea {
+    range: Range<usize>,
+}
+
+#[cfg_attr(not(feature = "ktest"), expect(dead_code))]
+impl VmallocArea {
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(ptr::with_exposed_provenance(self.range.start), self.len()) }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe {
+            slice::from_raw_parts_mut(
+                ptr::with_exposed_provenance_mut(self.range.start),
+                self.len(),
+            )
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.range.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.range.is_empty()
+    }
+
+    fn release(&self) -> Result<(), GenericError> {
+        let range = self.range.clone();
+        release_area(range.clone())?;
+        // the range is reused only after the stale TLB entries are flushed
+        return_space(range.start..range.end + PAGE_SIZE);
+        Ok(())
+    }
+}
+
+impl Drop for VmallocArea {
+    fn drop(&mut self) {
+        if let Err(e) = self.release() {
+            warn!("failed to release vmalloc area {:#x?}: {e}", self.range);
+        }
+    }
+}
+
+/// Reserves `size` bytes in the vmalloc region of the kernel address space.
+///
+/// No memory is allocated until the pages are accessed. Each area is followed
+/// by an unmapped guard page.
+#[cfg_attr(not(feature = "ktest"), expect(dead_code))]
+pub fn vmalloc(size: usize) -> Result<VmallocArea, GenericError> {
+    ensure_whatever!(size > 0, "empty vmalloc area");
+    let size = size.page_align_up();
+    let virt_range = take_space(size + PAGE_SIZE)
+        .with_whatever_context(|| format!("no vmalloc space for size={size:#x}"))?;
+
+    let range = virt_range.start..virt_range.start + size;
+    if let Err(e) = reserve_area(range.clone()) {
+        return_space(virt_range);
+        return Err(e);
+    }
+    Ok(VmallocArea { range })
+}
+
+/// Takes `size` bytes of free virtual address space from the vmalloc region.
+///
+/// `size` must be page aligned. Nothing is mapped in the returned range.
+pub(super) fn take_space(size: usize) -> Option<Range<usize>> {
+    assert!(size.is_page_aligned());
+    let mut space = VMALLOC_SPACE.lock();
+    let free = space.iter().find(|free| free.len() >= size)?;
+    let range = free.start..free.start + size;
+    space.remove(range.clone());
+    space.unlock();
+    Some(range)
+}
+
+/// Returns the range taken by [`take_space`] to the vmalloc region.
+///
+/// The range must have no mappings, and their stale TLB entries must have
+/// been flushed.
+pub(super) fn return_space(range: Range<usize>) {
+    VMALLOC_SPACE.lock().insert(range);
+}
+
+/// Unregisters the demand-paged area `range`, and frees its mapped pages.
+///
+/// The pages mapped in `range` outside of demand-paged areas are freed as
+/// well.
+pub(super) fn release_area(range: Range<usize>) -> Result<(), GenericError> {
+    DEMAND_AREAS.lock().remove(&range.start);
+
+    let mut kpgtbl = KERNEL_PAGE_TABLE.get().unwrap().lock();
+    let pages = range
+        .clone()
+        .step_by(PAGE_SIZE)
+        .filter_map(|addr| kpgtbl.pt.find_mapping(VirtAddr::from_addr(addr)))
+        .map(|mapping| mapping.min_phys_addr)
+        .collect::<Vec<_>>();
+    kpgtbl
+        .unmap_range(range.clone())
+        .with_whatever_context(|_| {
+            format!("failed to update kernel page table, range={range:#x?}")
+        })?;
+    let pending = kpgtbl.take_pending();
+    kpgtbl.unlock();
+
+    pending.flush().with_whatever_context(|_| {
+        format!("failed to apply kernel page table changes, range={range:#x?}")
+    })?;
+
+    // the pages are reused only after the stale TLB entries are flushed
+    for page in pages {
+        unsafe {
+            dealloc(page.as_mut_ptr::<u8>(), allocator::page_layout());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "ktest")]
+pub mod ktests {
+    use snafu::ensure_whatever;
+    use sv39::address::VirtAddr;
+
+    use super::{DEMAND_AREAS, KERNEL_PAGE_TABLE, VMALLOC_SPACE};
+    use crate::{
+        error::GenericError,
+        ktest::KernelTest,
+        memory::{
+            PAGE_SIZE,
+            kernel_space::{VmallocArea, vmalloc},
+            layout,
+        },
+    };
+
+    pub static TESTS: &[KernelTest] = kernel_tests![empty_area, touch_and_free, reuse_zeroed];
+
+    fn is_mapped(addr: usize) -> bool {
+        KERNEL_PAGE_TABLE
+            .get()
+            .unwrap()
+            .lock()
+            .pt
+            .find_mapping(VirtAddr::from_addr(addr))
+            .is_some()
+    }
+
+    fn empty_area() -> Result<(), GenericError> {
+        ensure_whatever!(vmalloc(0).is_err(), "empty vmalloc area allocated");
+        Ok(())
+    }
+
+    fn touch_and_free() -> Result<(), GenericError> {
+        let mut area = vmalloc(3 * PAGE_SIZE + 1)?;
+        let range = area.range.clone();
+        let region = layout::vmalloc_range();
+        ensure_whatever!(
+            region.start <= range.start && range.end + PAGE_SIZE <= region.end,
+            "area {range:#x?} out of the vmalloc region {region:#x?}"
+        );
+        ensure_whatever!(
+            !area.is_empty() && area.len() == 4 * PAGE_SIZE,
+            "area of {:#x} bytes not rounded up to pages",
+            area.len()
+        );
+        ensure_whatever!(
+            range
+                .clone()
+                .step_by(PAGE_SIZE)
+                .all(|addr| !is_mapped(addr)),
+            "pages mapped before the first access"
+        );
+
+        // touch the second page only, so the others stay unmapped
+        area.as_mut_slice()[PAGE_SIZE] = 0xa5;
+        ensure_whatever!(
+            is_mapped(range.start + PAGE_SIZE),
+            "page not mapped on the first access"
+        );
+        ensure_whatever!(
+            !is_mapped(range.start) && !is_mapped(range.start + 2 * PAGE_SIZE),
+            "untouched pages mapped"
+        );
+
+        for (i, byte) in area.as_mut_slice().iter_mut().enumerate() {
+            *byte = u8::try_from(i % 256).unwrap();
+        }
+        ensure_whatever!(
+            area.as_slice()
+                .iter()
+                .enumerate()
+                .all(|(i, byte)| usize::from(*byte) == i % 256),
+            "area content not preserved"
+        );
+        ensure_whatever!(!is_mapped(range.end), "guard page mapped");
+
+        drop(area);
+        ensure_whatever!(
+            range
+                .clone()
+                .step_by(PAGE_SIZE)
+                .all(|addr| !is_mapped(addr)),
+            "pages left mapped after the area is freed"
+        );
+        ensure_whatever!(
+            !DEMAND_AREAS.lock().contains_key(&range.start),
+            "area left registered after it is freed"
+        );
+        ensure_whatever!(
+            VMALLOC_SPACE
+                .lock()
+                .iter()
+                .any(|free| free.start <= range.start && range.end + PAGE_SIZE <= free.end),
+            "area {range:#x?} not returned to the vmalloc region"
+        );
+        Ok(())
+    }
+
+    fn reuse_zeroed() -> Result<(), GenericError> {
+        let mut area: VmallocArea = vmalloc(PAGE_SIZE)?;
+        area.as_mut_slice().fill(0xff);
+        drop(area);
+
+        // the freed pages are reused with fresh zeroed pages
+        let area = vmalloc(PAGE_SIZE)?;
+        ensure_whatever!(
+            area.as_slice().iter().all(|byte| *byte == 0),
+            "reused area not zeroed"
+        );
+        Ok(())
+    }
+}
@@ -1,18 +1,145 @@
-use alloc::format;
-use core::ops::Range;
+use alloc::{format, vec::Vec};
+use core::{
+    ops::Range,
+    sync::atomic::{AtomicBool, Ordering},
+};
 
 use devtree::{
     DeserializeNode, Devicetree,
-    model::property::Reg,
-    tree_cursor::{TreeCursor as _, TreeIterator as _},
+    model::{node::NodePath, property::Reg},
+    tree_cursor::{TreeCursor as _, TreeIterator as _, TreeIteratorAllocExt as _},
+    types::ByteString,
 };
 use platform_cast::CastFrom as _;
 use range_set::RangeSet;
-use snafu::ResultExt as _;
+use snafu::{ResultExt as _, ensure_whatever};
+use spin::Once;
 use sv39::MapPageFlags;
 
-use super::kernel_space;
-use crate::error::GenericError;
+use super::{Align as _, allocator::HeapRange, kernel_space, reserved};
+use crate::{
+    chosen,
+    cmdline::{FromParam as _, ParamDescriptor, Size},
+    error::GenericError,
+    rand,
+};
+
+// Virtual address layout of the kernel.
+//
+// The kernel image, the heap and the initrd are identity mapped in the lower
+// half, as the heap memory is passed to the devices and referenced from the
+// page tables by its physical address. The dynamically mapped regions are
+// placed in the higher half:
+//
+// ```text
+// 0xffff_ffc0_0000_0000 +------------------------+
+//                       | (KASLR offset)         |
+//                       +------------------------+ <- dynamic region base
+//                       | kernel stacks (1 GiB)  |
+//                       +------------------------+
+//                       | MMIO (1 GiB)           |
+//                       +------------------------+
+//                       | vmalloc (1 GiB)        |
+//                       +------------------------+
+//                       | (KASLR offset)         |
+// 0xffff_ffff_c000_0000 +------------------------+
+//                       | fixmap (1 GiB)         |
+//                       +------------------------+
+// ```
+//
+// The regions are aligned to the top-level page table entries, so that they
+// are shared with the user page tables as a whole.
+
+const HIGHER_HALF_START: usize = 0xffff_ffc0_0000_0000;
+const REGION_ALIGN: usize = 1024 * 1024 * 1024;
+pub const KERNEL_STACK_REGION_SIZE: usize = REGION_ALIGN;
+const MMIO_REGION_SIZE: usize = REGION_ALIGN;
+const VMALLOC_REGION_SIZE: usize = REGION_ALIGN;
+const DYNAMIC_REGION_SIZE: usize =
+    KERNEL_STACK_REGION_SIZE + MMIO_REGION_SIZE + VMALLOC_REGION_SIZE;
+/// Start of the region reserved for the fixed mappings, which extends to the
+/// end of the address space and is not randomized.
+const FIXMAP_START: usize = 0xffff_ffff_c000_0000;
+
+static VIRT_LAYOUT: Once<VirtLayout> = Once::new();
+
+#[derive(Debug)]
+struct VirtLayout {
+    kernel_stacks: Range<usize>,
+    mmio: Range<usize>,
+    vmalloc: Range<usize>,
+}
+
+impl VirtLayout {
+    /// Places the dynamic regions at an offset chosen by `kaslr_seed`, or at
+    /// the start of the higher half if it is `None`.
+    fn new(kaslr_seed: Option<u64>) -> Self {
+        let num_bases = (FIXMAP_START - HIGHER_HALF_START - DYNAMIC_REGION_SIZE) / REGION_ALIGN + 1;
+        let index = kaslr_seed.map_or(0, |seed| {
+            usize::cast_from(seed % u64::try_from(num_bases).unwrap())
+        });
+        let base = HIGHER_HALF_START + index * REGION_ALIGN;
+        let kernel_stack_range = base..base + KERNEL_STACK_REGION_SIZE;
+        let mmio_range = kernel_stack_range.end..kernel_stack_range.end + MMIO_REGION_SIZE;
+        let vmalloc_range = mmio_range.end..mmio_range.end + VMALLOC_REGION_SIZE;
+        assert!(vmalloc_range.end <= FIXMAP_START);
+        Self {
+            kernel_stacks: kernel_stack_range,
+            mmio: mmio_range,
+            vmalloc: vmalloc_range,
+        }
+    }
+}
+
+/// Whether the kernel address space randomization is disabled, given by the
+/// `nokaslr` option.
+static NOKASLR: AtomicBool = AtomicBool::new(false);
+
+pub static NOKASLR_PARAM: ParamDescriptor = ParamDescriptor {
+    name: "nokaslr",
+    handler: |value| {
+        NOKASLR.store(bool::from_param(value)?, Ordering::Relaxed);
+        Ok(())
+    },
+};
+
+/// Returns whether the kernel address space layout is randomized.
+pub fn is_kaslr_enabled() -> bool {
+    !NOKASLR.load(Ordering::Relaxed)
+}
+
+/// Determines the virtual address layout for the dynamically mapped regions.
+///
+/// The placement is randomized by `kaslr-seed` in the chosen node, or by the
+/// random number generator if the bootloader does not provide it, unless the
+/// `nokaslr` option is given.
+pub fn init_virt_layout() {
+    let kaslr_seed =
+        is_kaslr_enabled().then(|| chosen::kaslr_seed().unwrap_or_else(rand::next_u64));
+    let layout = VIRT_LAYOUT.call_once(|| VirtLayout::new(kaslr_seed));
+    info!(
+        "kernel virtual layout: stacks={:#x?}, mmio={:#x?}, vmalloc={:#x?}, kaslr={}",
+        layout.kernel_stacks,
+        layout.mmio,
+        layout.vmalloc,
+        if kaslr_seed.is_some() { "on" } else { "off" },
+    );
+}
+
+/// Returns the virtual address range of the kernel stacks.
+pub fn kernel_stack_range() -> Range<usize> {
+    VIRT_LAYOUT.get().unwrap().kernel_stacks.clone()
+}
+
+/// Returns the virtual address range where the device registers are mapped.
+pub fn mmio_range() -> Range<usize> {
+    VIRT_LAYOUT.get().unwrap().mmio.clone()
+}
+
+/// Returns the virtual address range of the demand-paged kernel allocations.
+pub fn vmalloc_range() -> Range<usize> {
+    VIRT_LAYOUT.get().unwrap().vmalloc.clone()
+}
 
 unsafe extern "C" {
     #[link_name = "__onix_kernel_start"]
@@ -46,38 +173,111 @@ pub fn bss_addr_range() -> Range<usize> {
     (&raw const BSS_START).addr()..(&raw const BSS_END).addr()
 }
 
+/// Physical memory used at boot, captured once by the boot CPU.
+///
+/// The addresses come from the linker symbols and the arguments passed by the
+/// firmware rather than constants, so that the later stages do not assume the
+/// RAM base of a particular platform.
+#[derive(Debug)]
+pub struct BootInfo {
+    /// Devicetree blob passed by the firmware.
+    pub dtb: Range<usize>,
+    /// Kernel image, including the `.bss` section.
+    pub kernel_image: Range<usize>,
+    /// Stack used by the boot CPU until the kernel stacks are allocated.
+    pub boot_stack: Range<usize>,
+}
+
+static BOOT_INFO: Once<BootInfo> = Once::new();
+
+/// Captures the boot information from the arguments passed by the firmware.
+///
+/// `dtb_len` is the size of the devicetree blob at `dtb_pa`.
+pub fn init_boot_info(dtb_pa: usize, dtb_len: usize) -> &'static BootInfo {
+    BOOT_INFO.call_once(|| BootInfo {
+        dtb: dtb_pa..dtb_pa + dtb_len,
+        kernel_image: linker_range(&raw const KERNEL_START, &raw const KERNEL_END),
+        boot_stack: linker_range(&raw const BOOT_STACK_START, &raw const BOOT_STACK_END),
+    })
+}
+
+pub fn boot_info() -> &'static BootInfo {
+    BOOT_INFO.get().unwrap()
+}
+
+fn linker_range(start: *const u8, end: *const u8) -> Range<usize> {
+    super::expand_to_page_boundaries(start.addr()..end.addr())
+}
+
 #[derive(Debug)]
 pub struct HeapLayout {
     available_ranges: RangeSet<128>,
+    memory_regions: Vec<MemoryRegion>,
+    initrd_range: Option<Range<usize>>,
+    /// Devicetree blob passed by the firmware.
+    dtb_range: Range<usize>,
+    boot_stack_range: Range<usize>,
 }
 
-#[derive(Debug, DeserializeNode)]
-struct Memory<'blob> {
-    #[devtree(property)]
-    reg: Reg<'blob>,
+/// Physical memory described by a `/memory` node.
+#[derive(Debug)]
+struct MemoryRegion {
+    path: ByteString,
+    numa_node: Option<u32>,
+    /// Ranges of the node that are not reserved.
+    available_ranges: RangeSet<128>,
 }
 
 #[derive(Debug, DeserializeNode)]
-pub struct ReservedMemoryRegion<'blob> {
+struct Memory<'blob> {
+    #[devtree(node)]
+    path: NodePath,
     #[devtree(property)]
     reg: Reg<'blob>,
+    #[devtree(property(name = "numa-node-id", default))]
+    numa_node_id: Option<u32>,
 }
 
+/// Size of the memory added to the heap, given by the `mem` option.
+static MEMORY_LIMIT: Once<usize> = Once::new();
+
+pub static MEMORY_LIMIT_PARAM: ParamDescriptor = ParamDescriptor {
+    name: "mem",
+    handler: |value| {
+        let Size(limit) = Size::from_param(value)?;
+        MEMORY_LIMIT.call_once(|| limit.page_align_up());
+        Ok(())
+    },
+};
+
 impl HeapLayout {
-    pub fn new(dt: &Devicetree) -> Result<Self, GenericError> {
+    /// Computes the heap layout from `dt`, which is a copy of the devicetree
+    /// blob in `boot_info`.
+    pub fn new(dt: &Devicetree, boot_info: &BootInfo) -> Result<Self, GenericError> {
         let mut available_ranges = RangeSet::<128>::new();
+        let mut memory_regions = Vec::new();
 
         let mut cursor = dt
             .tree_cursor()
             .whatever_context("failed to create tree cursor")?;
         let iter = cursor
             .read_descendant_nodes_by_glob("/memory")
+            .skip_disabled()
+            .sorted_by_unit_address()
+            .whatever_context("failed to read devicetree memory nodes")?
             .deserialize_node::<Memory>();
         for memory in iter {
             let memory = memory.whatever_context("failed to deserialize devicetree memory node")?;
+            let mut region = MemoryRegion {
+                path: memory.path.0,
+                numa_node: memory.numa_node_id,
+                available_ranges: RangeSet::new(),
+            };
             for reg in memory.reg {
                 available_ranges.insert(reg.range());
+                region.available_ranges.insert(reg.range());
             }
+            memory_regions.push(region);
         }
 
         for rsv in dt.memory_reservation_map() {
@@ -85,41 +285,88 @@ impl HeapLayout {
             available_ranges.remove(usize::cast_from(range.start)..usize::cast_from(range.end));
         }
 
-        let mut cursor = dt
-            .tree_cursor()
-            .whatever_context("failed to create tree cursor")?;
-        let iter = cursor
-            .read_descendant_nodes_by_glob("/reserved-memory/*")
-            .deserialize_node::<ReservedMemoryRegion>();
-        for reserved_node in iter {
-            let reserved_node = reserved_node
-                .whatever_context("failed to deserialize memory node in devicetree")?;
-            for reg in reserved_node.reg {
-                available_ranges.remove(reg.range());
+        let initrd_range = chosen::read_initrd_range(dt)?.map(super::expand_to_page_boundaries);
+        if let Some(initrd_range) = &initrd_range {
+            available_ranges.remove(initrd_range.clone());
+        }
+
+        // the blob passed by the firmware is kept intact and mapped read-only
+        let dtb_range = super::expand_to_page_boundaries(boot_info.dtb.clone());
+        available_ranges.remove(dtb_range.clone());
+
+        let kernel_range = boot_info.kernel_image.clone();
+        ensure_whatever!(
+            memory_regions.iter().any(|region| {
+                region
+                    .available_ranges
+                    .iter()
+                    .any(|range| range.start <= kernel_range.start && kernel_range.end <= range.end)
+            }),
+            "kernel image {kernel_range:#x?} is not in the memory of the devicetree"
+        );
+        available_ranges.remove(kernel_range);
+
+        reserved::init(dt, &mut available_ranges, boot_info.boot_stack.clone())
+            .whatever_context("failed to reserve memory regions")?;
+
+        // the overlapping nodes are not expected, but the memory is only
+        // added to the heap once
+        let mut remaining = available_ranges.clone();
+        for region in &mut memory_regions {
+            let unavailable = region.available_ranges.difference(&remaining);
+            for range in &unavailable {
+                region.available_ranges.remove(range.clone());
+            }
+            for range in &region.available_ranges {
+                remaining.remove(range.clone());
             }
+            info!(
+                "memory {}: numa_node={:?}, available={:#x?}",
+                region.path,
+                region.numa_node,
+                region.available_ranges.as_slice()
+            );
         }
 
-        available_ranges.remove(kernel_reserved_range());
-        Ok(Self { available_ranges })
+        Ok(Self {
+            available_ranges,
+            memory_regions,
+            initrd_range,
+            dtb_range,
+            boot_stack_range: boot_info.boot_stack.clone(),
+        })
     }
 
-    pub fn heap_ranges(&self) -> RangeSet<128> {
-        let mut heap_ranges = self.available_ranges.clone();
-        heap_ranges.remove(kernel_boot_stack_range());
-        heap_ranges
+    #[cfg_attr(not(debug_assertions), expect(dead_code))]
+    pub fn dtb_range(&self) -> Range<usize> {
+        self.dtb_range.clone()
     }
-}
 
-fn kernel_reserved_range() -> Range<usize> {
-    let kernel_start = (&raw const KERNEL_START).addr();
-    let kernel_end = (&raw const KERNEL_END).addr();
-    super::expand_to_page_boundaries(kernel_start..kernel_end)
-}
-
-pub fn kernel_boot_stack_range() -> Range<usize> {
-    let stack_start = (&raw const BOOT_STACK_START).addr();
-    let stack_end = (&raw const BOOT_STACK_END).addr();
-    super::expand_to_page_boundaries(stack_start..stack_end)
+    /// Returns the heap ranges, limited to the size given by the `mem` option,
+    /// with the NUMA nodes of the memory nodes they belong to.
+    pub fn heap_ranges(&self) -> impl Iterator<Item = HeapRange> {
+        let boot_stack_range = self.boot_stack_range.clone();
+        let mut remaining = MEMORY_LIMIT.get().copied().unwrap_or(usize::MAX);
+        self.memory_regions
+            .iter()
+            .flat_map(move |region| {
+                let mut ranges = region.available_ranges.clone();
+                ranges.remove(boot_stack_range.clone());
+                ranges.into_iter().map(|range| HeapRange {
+                    range,
+                    numa_node: region.numa_node,
+                })
+            })
+            .map_while(move |mut heap_range| {
+                if remaining == 0 {
+                    return None;
+                }
+                let len = heap_range.range.len().min(remaining);
+                heap_range.range.end = heap_range.range.start + len;
+                remaining -= len;
+                Some(heap_range)
+            })
+    }
 }
 
 pub fn kernel_rx_range() -> Range<usize> {
@@ -140,18 +387,37 @@ pub fn kernel_rw_range() -> Range<usize> {
     super::expand_to_page_boundaries(rw_start..rw_end)
 }
 
+/// Identity maps the kernel image and the memory in use into the kernel page
+/// table.
+///
+/// The kernel text is mapped RX, the read-only data R, and the writable data
+/// and the heap RW, so that no page is writable and executable.
 pub fn update_kernel_page_table(layout: &HeapLayout) -> Result<(), GenericError> {
     let fixed_pairs = [
         (kernel_rx_range(), MapPageFlags::RX),
         (kernel_ro_range(), MapPageFlags::R),
         (kernel_rw_range(), MapPageFlags::RW),
     ];
+    let initrd_pairs = layout
+        .initrd_range
+        .iter()
+        .chain([&layout.dtb_range])
+        .map(|range| (range.clone(), MapPageFlags::R));
     let heap_pairs = layout
         .available_ranges
         .iter()
         .map(|range| (range.clone(), MapPageFlags::RW));
+    let reserved_pairs = reserved::regions()
+        .iter()
+        .filter(|region| region.is_mapped())
+        .map(|region| (region.range(), MapPageFlags::RW));
 
-    for (range, flags) in fixed_pairs.into_iter().chain(heap_pairs) {
+    for (range, flags) in fixed_pairs
+        .into_iter()
+        .chain(initrd_pairs)
+        .chain(heap_pairs)
+        .chain(reserved_pairs)
+    {
         kernel_space::identity_map_range(range.clone(), flags).with_whatever_context(
             move |_| {
                 format!(
@@ -163,3 +429,36 @@ pub fn update_kernel_page_table(layout: &HeapLayout) -> Result<(), GenericError>
 
     Ok(())
 }
+
+#[cfg(feature = "ktest")]
+pub mod ktests {
+    use snafu::ensure_whatever;
+
+    use super::{FIXMAP_START, HIGHER_HALF_START, REGION_ALIGN, VirtLayout};
+    use crate::{error::GenericError, ktest::KernelTest};
+
+    pub static TESTS: &[KernelTest] = kernel_tests![random_virt_layouts];
+
+    fn random_virt_layouts() -> Result<(), GenericError> {
+        for seed in [None, Some(0), Some(1), Some(0xdead_beef), Some(u64::MAX)] {
+            let layout = VirtLayout::new(seed);
+            let regions = [&layout.kernel_stacks, &layout.mmio, &layout.vmalloc];
+            for region in regions {
+                ensure_whatever!(
+                    region.start.is_multiple_of(REGION_ALIGN)
+                        && region.end.is_multiple_of(REGION_ALIGN),
+                    "region {region:#x?} not aligned, seed={seed:?}"
+                );
+                ensure_whatever!(
+                    HIGHER_HALF_START <= region.start && region.end <= FIXMAP_START,
+                    "region {region:#x?} out of the dynamic area, seed={seed:?}"
+                );
+            }
+            ensure_whatever!(
+                regions.windows(2).all(|pair| pair[0].end == pair[1].start),
+                "regions not contiguous, seed={seed:?}: {layout:#x?}"
+            );
+        }
+        Ok(())
+    }
+}
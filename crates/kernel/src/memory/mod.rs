@@ -1,8 +1,12 @@
 use core::ops::Range;
 
 pub mod allocator;
+pub mod asid;
+pub mod dma;
 pub mod kernel_space;
 pub mod layout;
+pub mod reserved;
+pub mod tlb;
 
 pub const PAGE_SIZE: usize = sv39::PAGE_SIZE;
 
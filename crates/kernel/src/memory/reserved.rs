@@ -0,0 +1,295 @@
+//! Reserved memory regions described by the children of `/reserved-memory`.
+//!
+//! The regions are excluded from the heap when the heap layout is computed. A
+//! region given by `size` instead of `reg` is allocated from the memory at
+//! that time, within its `alloc-ranges` if given. The regions without `no-map`
+//! are identity mapped as the heap is.
+//!
+//! A driver claims the region referenced by the `memory-region` property of
+//! its node with [`ProbeContext::claim_memory_region`], and each region is
+//! claimed by one driver at most.
+//!
+//! [`ProbeContext::claim_memory_region`]: crate::drivers::registry::ProbeContext::claim_memory_region
+
+use alloc::{borrow::ToOwned, format, vec::Vec};
+use core::{
+    ops::Range,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use devtree::{
+    DeserializeNode, Devicetree,
+    de::util,
+    model::{
+        node::NodePath,
+        property::{ByteStrList, Phandle, Reg},
+    },
+    tree_cursor::{TreeCursor as _, TreeIterator as _},
+    types::{ByteStr, ByteString},
+};
+use platform_cast::CastFrom as _;
+use range_set::RangeSet;
+use snafu::{OptionExt as _, ResultExt as _, ensure_whatever, whatever};
+use spin::Once;
+
+use super::{Align as _, PAGE_SIZE};
+use crate::error::GenericError;
+
+static REGIONS: Once<Vec<ReservedRegion>> = Once::new();
+
+/// Memory region reserved by a child of `/reserved-memory`.
+#[derive(Debug)]
+pub struct ReservedRegion {
+    path: ByteString,
+    phandle: Option<Phandle>,
+    compatible: Vec<ByteString>,
+    range: Range<usize>,
+    no_map: bool,
+    reusable: bool,
+    claimed: AtomicBool,
+}
+
+impl ReservedRegion {
+    pub fn path(&self) -> &ByteStr {
+        ByteStr::new(&self.path)
+    }
+
+    /// Returns the physical address range of the region, expanded to the page
+    /// boundaries.
+    pub fn range(&self) -> Range<usize> {
+        self.range.clone()
+    }
+
+    pub fn is_compatible_to(&self, model: &str) -> bool {
+        self.compatible
+            .iter()
+            .any(|compatible| compatible.as_slice() == model.as_bytes())
+    }
+
+    /// Returns whether the region is identity mapped, i.e. it does not have
+    /// the `no-map` property.
+    pub fn is_mapped(&self) -> bool {
+        !self.no_map
+    }
+
+    /// Returns whether the region may be used by the kernel while its driver
+    /// does not use it, given by the `reusable` property.
+    ///
+    /// The reusable regions are not given to the heap yet.
+    pub fn is_reusable(&self) -> bool {
+        self.reusable
+    }
+
+    /// Claims the region for the caller.
+    ///
+    /// Returns an error if the region is already claimed.
+    pub fn claim(&self) -> Result<(), GenericError> {
+        ensure_whatever!(
+            !self.claimed.swap(true, Ordering::AcqRel),
+            "reserved memory {} is already claimed",
+            self.path
+        );
+        Ok(())
+    }
+}
+
+#[derive(Debug, DeserializeNode)]
+struct ReservedMemoryNode<'blob> {
+    #[devtree(node)]
+    path: NodePath,
+    #[devtree(property(default))]
+    phandle: Option<Phandle>,
+    #[devtree(property(default))]
+    compatible: Option<ByteStrList<'blob>>,
+    #[devtree(property(default))]
+    reg: Option<Reg<'blob>>,
+    #[devtree(property(
+        default,
+        deserialize_with = |de| util::deserialize_u64_or_u32_property(de).map(Some),
+    ))]
+    size: Option<u64>,
+    #[devtree(property(
+        default,
+        deserialize_with = |de| util::deserialize_u64_or_u32_property(de).map(Some),
+    ))]
+    alignment: Option<u64>,
+    #[devtree(property(name = "alloc-ranges", default))]
+    alloc_ranges: Option<Reg<'blob>>,
+    #[devtree(property(name = "no-map", default))]
+    no_map: bool,
+    #[devtree(property(default))]
+    reusable: bool,
+}
+
+/// Reads the reserved memory regions from `dt`, and removes them from
+/// `available_ranges`.
+///
+/// The regions given by `size` are allocated from `available_ranges` except
+/// `boot_stack_range`, so the other memory in use must be removed from it
+/// beforehand.
+pub(super) fn init(
+    dt: &Devicetree,
+    available_ranges: &mut RangeSet<128>,
+    boot_stack_range: Range<usize>,
+) -> Result<(), GenericError> {
+    let mut regions = Vec::new();
+
+    let mut cursor = dt
+        .tree_cursor()
+        .whatever_context("failed to create tree cursor")?;
+    let iter = cursor
+        .read_descendant_nodes_by_glob("/reserved-memory/*")
+        .skip_disabled()
+        .deserialize_node::<ReservedMemoryNode>();
+    let mut dynamic_nodes = Vec::new();
+    for node in iter {
+        let node = node.whatever_context("failed to deserialize reserved memory node")?;
+        let Some(reg) = node.reg else {
+            dynamic_nodes.push(node);
+            continue;
+        };
+        // the regions of a node with several `reg` entries are recorded
+        // separately, with the same phandle
+        for reg in reg {
+            let range = super::expand_to_page_boundaries(reg.range());
+            available_ranges.remove(range.clone());
+            regions.push(new_region(&node, range));
+        }
+    }
+
+    // allocated after all the static regions are removed
+    let mut free_ranges = available_ranges.clone();
+    free_ranges.remove(boot_stack_range);
+    for node in dynamic_nodes {
+        let path = &node.path.0;
+        let size = node
+            .size
+            .with_whatever_context(|| format!("reserved memory {path} has neither reg nor size"))?;
+        let size = usize::cast_from(size).page_align_up();
+        let align = usize::cast_from(node.alignment.unwrap_or(1)).max(PAGE_SIZE);
+        ensure_whatever!(
+            align.is_power_of_two(),
+            "alignment {align:#x} of reserved memory {path} is not a power of two"
+        );
+        let alloc_ranges = node
+            .alloc_ranges
+            .into_iter()
+            .flatten()
+            .map(|reg| reg.range())
+            .collect::<Vec<_>>();
+        let Some(range) = allocate(&free_ranges, size, align, &alloc_ranges) else {
+            whatever!("failed to allocate {size:#x} bytes for reserved memory {path}");
+        };
+        free_ranges.remove(range.clone());
+        available_ranges.remove(range.clone());
+        regions.push(new_region(&node, range));
+    }
+
+    for region in &regions {
+        info!(
+            "reserved memory {}: range={:#x?}, mapped={}, reusable={}",
+            region.path(),
+            region.range(),
+            region.is_mapped(),
+            region.is_reusable()
+        );
+    }
+    REGIONS.call_once(|| regions);
+    Ok(())
+}
+
+fn new_region(node: &ReservedMemoryNode<'_>, range: Range<usize>) -> ReservedRegion {
+    ReservedRegion {
+        path: node.path.0.clone(),
+        phandle: node.phandle,
+        compatible: node
+            .compatible
+            .iter()
+            .flat_map(ByteStrList::iter)
+            .map(ToOwned::to_owned)
+            .collect(),
+        range,
+        no_map: node.no_map,
+        reusable: node.reusable,
+        claimed: AtomicBool::new(false),
+    }
+}
+
+/// Finds the highest range of `size` bytes aligned to `align` in
+/// `available_ranges`, and within one of `within` if it is not empty.
+fn allocate(
+    available_ranges: &RangeSet<128>,
+    size: usize,
+    align: usize,
+    within: &[Range<usize>],
+) -> Option<Range<usize>> {
+    available_ranges
+        .iter()
+        .rev()
+        .flat_map(|range| {
+            let windows = if within.is_empty() {
+                Vec::from([range.clone()])
+            } else {
+                within
+                    .iter()
+                    .rev()
+                    .map(|window| window.start.max(range.start)..window.end.min(range.end))
+                    .filter(|window| !window.is_empty())
+                    .collect()
+            };
+            windows.into_iter()
+        })
+        .find_map(|window| {
+            let start = window.end.checked_sub(size)?.align_down(align);
+            (start >= window.start).then_some(start..start + size)
+        })
+}
+
+/// Returns the reserved memory regions.
+pub fn regions() -> &'static [ReservedRegion] {
+    REGIONS.get().map_or(&[], Vec::as_slice)
+}
+
+/// Claims the reserved memory region referenced by `phandle`.
+///
+/// Returns an error if no region has the phandle or the region is already
+/// claimed. If the node has several `reg` entries, the first one is claimed.
+pub fn claim(phandle: Phandle) -> Result<&'static ReservedRegion, GenericError> {
+    let region = regions()
+        .iter()
+        .find(|region| region.phandle == Some(phandle))
+        .with_whatever_context(|| format!("no reserved memory of phandle {phandle:?}"))?;
+    region.claim()?;
+    Ok(region)
+}
+
+#[cfg(feature = "ktest")]
+pub mod ktests {
+    use range_set::RangeSet;
+    use snafu::ensure_whatever;
+
+    use super::allocate;
+    use crate::{error::GenericError, ktest::KernelTest};
+
+    pub static TESTS: &[KernelTest] = kernel_tests![allocate_from_top];
+
+    fn allocate_from_top() -> Result<(), GenericError> {
+        let mut available = RangeSet::<128>::new();
+        available.insert(0x1000..0x9000);
+        available.insert(0x10000..0x13000);
+
+        let range = allocate(&available, 0x2000, 0x1000, &[]);
+        ensure_whatever!(range == Some(0x11000..0x13000), "got {range:#x?}");
+        let range = allocate(&available, 0x2000, 0x4000, &[]);
+        ensure_whatever!(range == Some(0x10000..0x12000), "got {range:#x?}");
+        let range = allocate(&available, 0x4000, 0x1000, &[]);
+        ensure_whatever!(range == Some(0x5000..0x9000), "got {range:#x?}");
+        let range = allocate(&available, 0x1000, 0x1000, &[0..0x3000, 0x20000..0x30000]);
+        ensure_whatever!(range == Some(0x2000..0x3000), "got {range:#x?}");
+        let range = allocate(&available, 0x8000, 0x1000, &[]);
+        ensure_whatever!(range == Some(0x1000..0x9000), "got {range:#x?}");
+        let range = allocate(&available, 0x9000, 0x1000, &[]);
+        ensure_whatever!(range.is_none(), "got {range:#x?}");
+        Ok(())
+    }
+}
@@ -0,0 +1,78 @@
+//! Flushing the TLB entries of the changed mappings on all CPUs.
+
+use alloc::format;
+use core::ops::Range;
+
+use riscv_utils::asm;
+use sbi::rfence;
+use snafu::ResultExt as _;
+use sv39::address::VirtAddr;
+
+use super::PAGE_SIZE;
+use crate::{cpu, error::GenericError};
+
+/// Number of pages above which the whole TLB is flushed instead of each page.
+const FLUSH_ALL_THRESHOLD: usize = 64;
+
+/// Flushes the TLB entries of `range` on the current CPU and the remote CPUs.
+///
+/// The entries of `asid` are flushed, or those of all the ASIDs if it is
+/// `None`, as for the global kernel mappings. The whole TLB is flushed instead
+/// if the range has too many pages.
+pub fn shootdown(range: Range<VirtAddr>, asid: Option<u16>) -> Result<(), GenericError> {
+    let start = range.start.value();
+    let len = range.end.checked_sub(range.start).unwrap_or(0);
+    if len == 0 {
+        return Ok(());
+    }
+
+    let flush_all = len / PAGE_SIZE > FLUSH_ALL_THRESHOLD;
+    match (flush_all, asid) {
+        (true, None) => asm::sfence_vma_all(),
+        (true, Some(asid)) => asm::sfence_vma_asid_all(asid.into()),
+        (false, None) => {
+            for vaddr in (start..start + len).step_by(PAGE_SIZE) {
+                asm::sfence_vma_addr(vaddr);
+            }
+        }
+        (false, Some(asid)) => {
+            for vaddr in (start..start + len).step_by(PAGE_SIZE) {
+                asm::sfence_vma(vaddr, asid.into());
+            }
+        }
+    }
+
+    // a size of `usize::MAX` flushes the whole address space
+    let (start, size) = if flush_all {
+        (0, usize::MAX)
+    } else {
+        (start, len)
+    };
+    for hart_mask in cpu::remote_cpu_masks() {
+        match asid {
+            None => rfence::remote_sfence_vma(hart_mask, start, size),
+            Some(asid) => rfence::remote_sfence_vma_asid(hart_mask, start, size, asid.into()),
+        }
+        .with_whatever_context(|_e| {
+            format!(
+                "failed to remote sfence.vma for cpus `{hart_mask:?}` with virtual address range \
+                 `{range:#x?}`"
+            )
+        })?;
+    }
+    Ok(())
+}
+
+/// Flushes all the TLB entries of `asid` on the current CPU and the remote
+/// CPUs.
+pub fn shootdown_asid(asid: u16) -> Result<(), GenericError> {
+    asm::sfence_vma_asid_all(asid.into());
+    for hart_mask in cpu::remote_cpu_masks() {
+        // a size of `usize::MAX` flushes the whole address space
+        rfence::remote_sfence_vma_asid(hart_mask, 0, usize::MAX, asid.into())
+            .with_whatever_context(|_e| {
+                format!("failed to remote sfence.vma for cpus `{hart_mask:?}` with ASID {asid}")
+            })?;
+    }
+    Ok(())
+}
@@ -0,0 +1,118 @@
+use alloc::vec::Vec;
+
+use super::{Interface, Ipv4Addr, MacAddr, NetError, POLL_INTERVAL, ethernet};
+use crate::interrupt::timer;
+
+const HTYPE_ETHERNET: u16 = 1;
+const OP_REQUEST: u16 = 1;
+const OP_REPLY: u16 = 2;
+
+const PACKET_LEN: usize = 28;
+
+/// Number of requests sent before giving up the resolution.
+const MAX_REQUESTS: usize = 3;
+/// Number of polls of the cache after each request.
+const POLLS_PER_REQUEST: usize = 10;
+
+/// Returns the cached ARP entries of the interface.
+#[cfg_attr(not(feature = "shell"), expect(dead_code))]
+pub fn entries(iface: &Interface) -> Vec<(Ipv4Addr, MacAddr)> {
+    iface
+        .arp_cache
+        .lock()
+        .iter()
+        .map(|(&ip, &mac)| (ip, mac))
+        .collect()
+}
+
+/// Handles the received ARP packet, learning the sender and replying to
+/// requests for our address.
+pub(super) fn handle(iface: &Interface, packet: &[u8]) {
+    let Some(packet) = packet.get(..PACKET_LEN) else {
+        return;
+    };
+    let be_u16 = |offset: usize| u16::from_be_bytes([packet[offset], packet[offset + 1]]);
+    if be_u16(0) != HTYPE_ETHERNET
+        || be_u16(2) != ethernet::ETHERTYPE_IPV4
+        || packet[4] != 6
+        || packet[5] != 4
+    {
+        return;
+    }
+    let op = be_u16(6);
+    let sender_mac = MacAddr(packet[8..14].try_into().unwrap());
+    let sender_ip = Ipv4Addr::from_octets(packet[14..18].try_into().unwrap());
+    let target_ip = Ipv4Addr::from_octets(packet[24..28].try_into().unwrap());
+
+    if !sender_ip.is_unspecified() {
+        iface.arp_cache.lock().insert(sender_ip, sender_mac);
+    }
+    let Some(config) = iface.config() else {
+        return;
+    };
+    if op == OP_REQUEST && target_ip == config.addr {
+        let reply = build(
+            OP_REPLY,
+            iface.mac_addr(),
+            config.addr,
+            sender_mac,
+            sender_ip,
+        );
+        if let Err(e) = iface.transmit(sender_mac, ethernet::ETHERTYPE_ARP, &reply) {
+            debug!("{}: failed to send ARP reply: {e}", iface.name());
+        }
+    }
+}
+
+fn build(
+    op: u16,
+    sender_mac: MacAddr,
+    sender_ip: Ipv4Addr,
+    target_mac: MacAddr,
+    target_ip: Ipv4Addr,
+) -> [u8; PACKET_LEN] {
+    let mut packet = [0; PACKET_LEN];
+    packet[0..2].copy_from_slice(&HTYPE_ETHERNET.to_be_bytes());
+    packet[2..4].copy_from_slice(&ethernet::ETHERTYPE_IPV4.to_be_bytes());
+    packet[4] = 6;
+    packet[5] = 4;
+    packet[6..8].copy_from_slice(&op.to_be_bytes());
+    packet[8..14].copy_from_slice(&sender_mac.0);
+    packet[14..18].copy_from_slice(&sender_ip.octets());
+    packet[18..24].copy_from_slice(&target_mac.0);
+    packet[24..28].copy_from_slice(&target_ip.octets());
+    packet
+}
+
+/// Resolves the MAC address of `ip`, which is on the link of the interface.
+///
+/// This blocks the current task until a reply is received, so must not be
+/// called in the interrupt context.
+pub(super) fn resolve(iface: &Interface, ip: Ipv4Addr) -> Result<MacAddr, NetError> {
+    if ip.is_broadcast()
+        || iface
+            .config()
+            .is_some_and(|config| config.broadcast() == ip)
+    {
+        return Ok(MacAddr::BROADCAST);
+    }
+    let lookup = || iface.arp_cache.lock().get(&ip).copied();
+    if let Some(mac) = lookup() {
+        return Ok(mac);
+    }
+
+    let sender_ip = iface
+        .config()
+        .map_or(Ipv4Addr::UNSPECIFIED, |config| config.addr);
+    let request = build(OP_REQUEST, iface.mac_addr(), sender_ip, MacAddr([0; 6]), ip);
+    for _ in 0..MAX_REQUESTS {
+        iface.transmit(MacAddr::BROADCAST, ethernet::ETHERTYPE_ARP, &request)?;
+        for _ in 0..POLLS_PER_REQUEST {
+            timer::sleep(POLL_INTERVAL);
+            if let Some(mac) = lookup() {
+                return Ok(mac);
+            }
+        }
+    }
+    Err(NetError::HostUnreachable)
+}
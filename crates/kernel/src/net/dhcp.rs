@@ -0,0 +1,224 @@
+use alloc::vec::Vec;
+use core::time::Duration;
+
+use snafu::{ResultExt as _, whatever};
+
+use super::{Interface, Ipv4Addr, Ipv4Config, NetError, SocketAddrV4, udp::UdpSocket};
+use crate::{error::GenericError, interrupt::timer, rand};
+
+const SERVER_PORT: u16 = 67;
+const CLIENT_PORT: u16 = 68;
+
+const OP_REQUEST: u8 = 1;
+const OP_REPLY: u8 = 2;
+const HTYPE_ETHERNET: u8 = 1;
+/// Asks the server to broadcast the replies, as we cannot receive unicast
+/// packets before an address is assigned.
+const FLAG_BROADCAST: u16 = 0x8000;
+const MAGIC_COOKIE: u32 = 0x6382_5363;
+
+const OPTIONS_OFFSET: usize = 240;
+/// Minimum length of the BOOTP message.
+const MIN_MESSAGE_LEN: usize = 300;
+
+const OPT_PAD: u8 = 0;
+const OPT_SUBNET_MASK: u8 = 1;
+const OPT_ROUTER: u8 = 3;
+const OPT_DNS: u8 = 6;
+const OPT_REQUESTED_ADDR: u8 = 50;
+const OPT_LEASE_TIME: u8 = 51;
+const OPT_MESSAGE_TYPE: u8 = 53;
+const OPT_SERVER_ID: u8 = 54;
+const OPT_PARAMETER_LIST: u8 = 55;
+const OPT_END: u8 = 255;
+
+const DHCPDISCOVER: u8 = 1;
+const DHCPOFFER: u8 = 2;
+const DHCPREQUEST: u8 = 3;
+const DHCPACK: u8 = 5;
+const DHCPNAK: u8 = 6;
+
+const MAX_ATTEMPTS: usize = 4;
+const REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Parsed DHCP reply.
+#[derive(Debug, Default)]
+struct Reply {
+    message_type: u8,
+    your_addr: Option<Ipv4Addr>,
+    server_id: Option<Ipv4Addr>,
+    subnet_mask: Option<Ipv4Addr>,
+    router: Option<Ipv4Addr>,
+    dns: Option<Ipv4Addr>,
+}
+
+/// Obtains an address for `iface` from a DHCP server and assigns it.
+///
+/// The lease is not renewed.
+#[cfg_attr(not(feature = "shell"), expect(dead_code))]
+pub fn configure(iface: &Interface) -> Result<Ipv4Config, GenericError> {
+    let socket =
+        UdpSocket::bind(CLIENT_PORT).whatever_context("failed to bind the DHCP client port")?;
+    let xid = transaction_id();
+
+    let offer = exchange(iface, &socket, xid, DHCPOFFER, &discover(iface, xid))?;
+    let (Some(addr), Some(server_id)) = (offer.your_addr, offer.server_id) else {
+        whatever!("invalid DHCPOFFER without an address or a server identifier");
+    };
+    let ack = exchange(
+        iface,
+        &socket,
+        xid,
+        DHCPACK,
+        &request(iface, xid, addr, server_id),
+    )?;
+
+    let mask = ack.subnet_mask.or(offer.subnet_mask);
+    let config = Ipv4Config {
+        addr: ack.your_addr.unwrap_or(addr),
+        prefix_len: mask.map_or(24, |mask| {
+            u8::try_from(mask.to_bits().leading_ones()).unwrap()
+        }),
+        gateway: ack.router.or(offer.router),
+        dns: ack.dns.or(offer.dns),
+    };
+    iface.set_config(Some(config));
+    Ok(config)
+}
+
+fn transaction_id() -> u32 {
+    let mut xid = [0; 4];
+    rand::fill(&mut xid);
+    u32::from_ne_bytes(xid)
+}
+
+/// Broadcasts `message` and waits for the reply of `expected` type,
+/// retransmitting it on timeout.
+fn exchange(
+    iface: &Interface,
+    socket: &UdpSocket,
+    xid: u32,
+    expected: u8,
+    message: &[u8],
+) -> Result<Reply, GenericError> {
+    let server = SocketAddrV4::new(Ipv4Addr::BROADCAST, SERVER_PORT);
+    let mut buf = [0; 1500];
+    for _ in 0..MAX_ATTEMPTS {
+        socket
+            .send_via(
+                iface,
+                Ipv4Addr::UNSPECIFIED,
+                Ipv4Addr::BROADCAST,
+                server,
+                message,
+            )
+            .whatever_context("failed to send the DHCP message")?;
+        let deadline = timer::now() + REPLY_TIMEOUT;
+        while let Some(timeout) = deadline.checked_duration_since(timer::now()) {
+            let len = match socket.recv_from(&mut buf, Some(timeout)) {
+                Ok((len, _)) => len,
+                Err(NetError::TimedOut) => break,
+                Err(e) => return Err(e).whatever_context("failed to receive the DHCP reply"),
+            };
+            let Some(reply) = parse_reply(iface, xid, &buf[..len]) else {
+                continue;
+            };
+            if reply.message_type == DHCPNAK {
+                whatever!("DHCP server declined the request");
+            }
+            if reply.message_type == expected {
+                return Ok(reply);
+            }
+        }
+    }
+    whatever!("no DHCP reply received from the server");
+}
+
+fn message(iface: &Interface, xid: u32, message_type: u8) -> Vec<u8> {
+    let mut message = Vec::with_capacity(MIN_MESSAGE_LEN);
+    message.extend_from_slice(&[OP_REQUEST, HTYPE_ETHERNET, 6, 0]);
+    message.extend_from_slice(&xid.to_be_bytes());
+    message.extend_from_slice(&[0, 0]);
+    message.extend_from_slice(&FLAG_BROADCAST.to_be_bytes());
+    // ciaddr, yiaddr, siaddr and giaddr
+    message.resize(28, 0);
+    message.extend_from_slice(&iface.mac_addr().0);
+    // the rest of chaddr, sname and file
+    message.resize(236, 0);
+    message.extend_from_slice(&MAGIC_COOKIE.to_be_bytes());
+    message.extend_from_slice(&[OPT_MESSAGE_TYPE, 1, message_type]);
+    message.extend_from_slice(&[
+        OPT_PARAMETER_LIST,
+        4,
+        OPT_SUBNET_MASK,
+        OPT_ROUTER,
+        OPT_DNS,
+        OPT_LEASE_TIME,
+    ]);
+    message
+}
+
+fn finish(mut message: Vec<u8>) -> Vec<u8> {
+    message.push(OPT_END);
+    if message.len() < MIN_MESSAGE_LEN {
+        message.resize(MIN_MESSAGE_LEN, OPT_PAD);
+    }
+    message
+}
+
+fn discover(iface: &Interface, xid: u32) -> Vec<u8> {
+    finish(message(iface, xid, DHCPDISCOVER))
+}
+
+fn request(iface: &Interface, xid: u32, addr: Ipv4Addr, server_id: Ipv4Addr) -> Vec<u8> {
+    let mut message = message(iface, xid, DHCPREQUEST);
+    message.extend_from_slice(&[OPT_REQUESTED_ADDR, 4]);
+    message.extend_from_slice(&addr.octets());
+    message.extend_from_slice(&[OPT_SERVER_ID, 4]);
+    message.extend_from_slice(&server_id.octets());
+    finish(message)
+}
+
+/// Parses the reply to our message, or returns `None` if the message is not
+/// for us.
+fn parse_reply(iface: &Interface, xid: u32, message: &[u8]) -> Option<Reply> {
+    if message.len() < OPTIONS_OFFSET
+        || message[0] != OP_REPLY
+        || message[4..8] != xid.to_be_bytes()
+        || message[28..34] != iface.mac_addr().0
+        || message[236..240] != MAGIC_COOKIE.to_be_bytes()
+    {
+        return None;
+    }
+    let ip = |bytes: &[u8]| Some(Ipv4Addr::from_octets(bytes.get(..4)?.try_into().unwrap()));
+    let mut reply = Reply {
+        your_addr: ip(&message[16..20]).filter(|addr| !addr.is_unspecified()),
+        ..Reply::default()
+    };
+
+    let mut options = &message[OPTIONS_OFFSET..];
+    while let [code, rest @ ..] = options {
+        match *code {
+            OPT_PAD => {
+                options = rest;
+                continue;
+            }
+            OPT_END => break,
+            _ => {}
+        }
+        let [len, rest @ ..] = rest else {
+            return None;
+        };
+        let (value, rest) = rest.split_at_checked(usize::from(*len))?;
+        match *code {
+            OPT_MESSAGE_TYPE => reply.message_type = *value.first()?,
+            OPT_SERVER_ID => reply.server_id = ip(value),
+            OPT_SUBNET_MASK => reply.subnet_mask = ip(value),
+            OPT_ROUTER => reply.router = ip(value),
+            OPT_DNS => reply.dns = ip(value),
+            _ => {}
+        }
+        options = rest;
+    }
+    Some(reply)
+}
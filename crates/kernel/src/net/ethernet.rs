@@ -0,0 +1,40 @@
+use alloc::vec::Vec;
+
+use super::MacAddr;
+
+pub(super) const ETHERTYPE_IPV4: u16 = 0x0800;
+pub(super) const ETHERTYPE_ARP: u16 = 0x0806;
+
+const HEADER_LEN: usize = 14;
+/// Minimum frame length without the frame check sequence.
+const MIN_FRAME_LEN: usize = 60;
+
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Header {
+    pub(super) dst: MacAddr,
+    pub(super) ethertype: u16,
+}
+
+/// Splits the frame into its header and payload.
+///
+/// The payload may contain the padding of short frames.
+pub(super) fn parse(frame: &[u8]) -> Option<(Header, &[u8])> {
+    let (header, payload) = frame.split_at_checked(HEADER_LEN)?;
+    let header = Header {
+        dst: MacAddr(header[0..6].try_into().unwrap()),
+        ethertype: u16::from_be_bytes([header[12], header[13]]),
+    };
+    Some((header, payload))
+}
+
+pub(super) fn build(dst: MacAddr, src: MacAddr, ethertype: u16, payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(usize::max(HEADER_LEN + payload.len(), MIN_FRAME_LEN));
+    frame.extend_from_slice(&dst.0);
+    frame.extend_from_slice(&src.0);
+    frame.extend_from_slice(&ethertype.to_be_bytes());
+    frame.extend_from_slice(payload);
+    if frame.len() < MIN_FRAME_LEN {
+        frame.resize(MIN_FRAME_LEN, 0);
+    }
+    frame
+}
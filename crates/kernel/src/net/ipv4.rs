@@ -0,0 +1,96 @@
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU16, Ordering};
+
+use super::{Interface, Ipv4Addr, NetError, arp, ethernet, udp};
+
+pub(super) const PROTOCOL_UDP: u8 = 17;
+
+const HEADER_LEN: usize = 20;
+const MTU: usize = 1500;
+/// Maximum payload length of a single (unfragmented) packet.
+pub(super) const MAX_PAYLOAD_LEN: usize = MTU - HEADER_LEN;
+
+const DEFAULT_TTL: u8 = 64;
+/// More fragments flag and the fragment offset.
+const FRAGMENT_MASK: u16 = 0x3fff;
+
+static NEXT_ID: AtomicU16 = AtomicU16::new(1);
+
+/// Handles the received IPv4 packet.
+///
+/// Fragmented packets are not reassembled and are dropped.
+pub(super) fn handle(iface: &Interface, packet: &[u8]) {
+    let Some(&version_ihl) = packet.first() else {
+        return;
+    };
+    let header_len = usize::from(version_ihl & 0x0f) * 4;
+    if version_ihl >> 4 != 4 || header_len < HEADER_LEN || packet.len() < header_len {
+        return;
+    }
+    let be_u16 = |offset: usize| u16::from_be_bytes([packet[offset], packet[offset + 1]]);
+    let total_len = usize::from(be_u16(2));
+    if total_len < header_len || total_len > packet.len() {
+        return;
+    }
+    if super::checksum(&packet[..header_len], 0) != 0 {
+        return;
+    }
+    if be_u16(6) & FRAGMENT_MASK != 0 {
+        debug!("{}: dropping fragmented IPv4 packet", iface.name());
+        return;
+    }
+    let protocol = packet[9];
+    let src = Ipv4Addr::from_octets(packet[12..16].try_into().unwrap());
+    let dst = Ipv4Addr::from_octets(packet[16..20].try_into().unwrap());
+
+    // accept everything until an address is assigned, for DHCP
+    if let Some(config) = iface.config()
+        && dst != config.addr
+        && dst != config.broadcast()
+        && !dst.is_broadcast()
+    {
+        return;
+    }
+
+    let payload = &packet[header_len..total_len];
+    if protocol == PROTOCOL_UDP {
+        udp::handle(src, dst, payload);
+    }
+}
+
+/// Sends the IPv4 packet to `dst` through `next_hop`, which is on the link of
+/// the interface.
+pub(super) fn send(
+    iface: &Interface,
+    src: Ipv4Addr,
+    dst: Ipv4Addr,
+    next_hop: Ipv4Addr,
+    protocol: u8,
+    payload: &[u8],
+) -> Result<(), NetError> {
+    if payload.len() > MAX_PAYLOAD_LEN {
+        return Err(NetError::MessageTooLong);
+    }
+    let dst_mac = arp::resolve(iface, next_hop)?;
+
+    let mut packet = Vec::with_capacity(HEADER_LEN + payload.len());
+    packet.push(0x45);
+    packet.push(0);
+    packet.extend_from_slice(
+        &u16::try_from(HEADER_LEN + payload.len())
+            .unwrap()
+            .to_be_bytes(),
+    );
+    packet.extend_from_slice(&NEXT_ID.fetch_add(1, Ordering::Relaxed).to_be_bytes());
+    packet.extend_from_slice(&[0, 0]);
+    packet.push(DEFAULT_TTL);
+    packet.push(protocol);
+    packet.extend_from_slice(&[0, 0]);
+    packet.extend_from_slice(&src.octets());
+    packet.extend_from_slice(&dst.octets());
+    let checksum = super::checksum(&packet, 0);
+    packet[10..12].copy_from_slice(&checksum.to_be_bytes());
+    packet.extend_from_slice(payload);
+
+    iface.transmit(dst_mac, ethernet::ETHERTYPE_IPV4, &packet)
+}
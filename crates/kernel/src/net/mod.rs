@@ -0,0 +1,269 @@
+use alloc::{collections::btree_map::BTreeMap, sync::Arc, vec::Vec};
+pub use core::net::{Ipv4Addr, SocketAddrV4};
+use core::{fmt, time::Duration};
+
+use crate::sync::spinlock::SpinMutex;
+
+pub mod arp;
+pub mod dhcp;
+mod ethernet;
+mod ipv4;
+pub mod socket;
+pub mod tftp;
+pub mod udp;
+
+/// Interval of polling for a reply when waiting with a timeout.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+static INTERFACES: SpinMutex<Vec<Arc<Interface>>> = SpinMutex::new(Vec::new());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetError {
+    InvalidArgument,
+    NotSocket,
+    MessageTooLong,
+    AddressInUse,
+    NetworkUnreachable,
+    NoBufferSpace,
+    TimedOut,
+    HostUnreachable,
+}
+
+impl NetError {
+    pub fn errno(self) -> isize {
+        match self {
+            Self::InvalidArgument => 22,
+            Self::NotSocket => 88,
+            Self::MessageTooLong => 90,
+            Self::AddressInUse => 98,
+            Self::NetworkUnreachable => 101,
+            Self::NoBufferSpace => 105,
+            Self::TimedOut => 110,
+            Self::HostUnreachable => 113,
+        }
+    }
+}
+
+impl fmt::Display for NetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::InvalidArgument => "invalid argument",
+            Self::NotSocket => "socket operation on non-socket",
+            Self::MessageTooLong => "message too long",
+            Self::AddressInUse => "address already in use",
+            Self::NetworkUnreachable => "network is unreachable",
+            Self::NoBufferSpace => "no buffer space available",
+            Self::TimedOut => "connection timed out",
+            Self::HostUnreachable => "no route to host",
+        };
+        f.write_str(s)
+    }
+}
+
+impl core::error::Error for NetError {}
+
+/// Ethernet MAC address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MacAddr(pub [u8; 6]);
+
+impl MacAddr {
+    pub const BROADCAST: Self = Self([0xff; 6]);
+}
+
+impl fmt::Display for MacAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, byte) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(":")?;
+            }
+            write!(f, "{byte:02x}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Network device sending and receiving ethernet frames.
+pub trait NetDevice: fmt::Debug + Send + Sync {
+    fn name(&self) -> &str;
+    fn mac_addr(&self) -> MacAddr;
+
+    /// Queues `frame` for transmission, without the frame check sequence.
+    ///
+    /// This can be called in the interrupt context.
+    fn transmit(&self, frame: &[u8]) -> Result<(), NetError>;
+}
+
+/// IPv4 address assigned to an interface.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ipv4Config {
+    pub addr: Ipv4Addr,
+    pub prefix_len: u8,
+    pub gateway: Option<Ipv4Addr>,
+    pub dns: Option<Ipv4Addr>,
+}
+
+impl Ipv4Config {
+    fn netmask(&self) -> u32 {
+        u32::MAX
+            .checked_shl(32 - u32::from(self.prefix_len))
+            .unwrap_or(0)
+    }
+
+    /// Returns whether `addr` is on the directly connected subnet.
+    fn contains(&self, addr: Ipv4Addr) -> bool {
+        (self.addr.to_bits() ^ addr.to_bits()) & self.netmask() == 0
+    }
+
+    fn broadcast(&self) -> Ipv4Addr {
+        Ipv4Addr::from_bits(self.addr.to_bits() | !self.netmask())
+    }
+}
+
+impl fmt::Display for Ipv4Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)?;
+        if let Some(gateway) = self.gateway {
+            write!(f, " via {gateway}")?;
+        }
+        if let Some(dns) = self.dns {
+            write!(f, " dns {dns}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Network interface, a network device with its protocol state.
+#[derive(Debug)]
+pub struct Interface {
+    device: Arc<dyn NetDevice>,
+    config: SpinMutex<Option<Ipv4Config>>,
+    arp_cache: SpinMutex<BTreeMap<Ipv4Addr, MacAddr>>,
+}
+
+/// Registers `device` as an unconfigured interface.
+pub fn register(device: Arc<dyn NetDevice>) -> Arc<Interface> {
+    info!(
+        "network interface {}: mac={}",
+        device.name(),
+        device.mac_addr()
+    );
+    let iface = Arc::new(Interface {
+        device,
+        config: SpinMutex::new(None),
+        arp_cache: SpinMutex::new(BTreeMap::new()),
+    });
+    INTERFACES.lock().push(Arc::clone(&iface));
+    iface
+}
+
+pub fn interfaces() -> Vec<Arc<Interface>> {
+    INTERFACES.lock().clone()
+}
+
+#[cfg_attr(not(feature = "shell"), expect(dead_code))]
+pub fn find_interface(name: &str) -> Option<Arc<Interface>> {
+    INTERFACES
+        .lock()
+        .iter()
+        .find(|iface| iface.name() == name)
+        .cloned()
+}
+
+impl Interface {
+    pub fn name(&self) -> &str {
+        self.device.name()
+    }
+
+    pub fn mac_addr(&self) -> MacAddr {
+        self.device.mac_addr()
+    }
+
+    pub fn config(&self) -> Option<Ipv4Config> {
+        *self.config.lock()
+    }
+
+    pub fn set_config(&self, config: Option<Ipv4Config>) {
+        if let Some(config) = config {
+            info!("{}: configured {config}", self.name());
+        } else {
+            info!("{}: unconfigured", self.name());
+        }
+        *self.config.lock() = config;
+    }
+
+    /// Handles the received ethernet frame.
+    ///
+    /// This is called by the device driver, possibly in the interrupt context.
+    pub fn receive(&self, frame: &[u8]) {
+        let Some((header, payload)) = ethernet::parse(frame) else {
+            return;
+        };
+        if header.dst != self.mac_addr() && header.dst != MacAddr::BROADCAST {
+            return;
+        }
+        match header.ethertype {
+            ethernet::ETHERTYPE_ARP => arp::handle(self, payload),
+            ethernet::ETHERTYPE_IPV4 => ipv4::handle(self, payload),
+            _ => {}
+        }
+    }
+
+    fn transmit(&self, dst: MacAddr, ethertype: u16, payload: &[u8]) -> Result<(), NetError> {
+        let frame = ethernet::build(dst, self.mac_addr(), ethertype, payload);
+        self.device.transmit(&frame)
+    }
+}
+
+/// Outgoing path of the packets to a destination.
+#[derive(Debug)]
+struct Route {
+    iface: Arc<Interface>,
+    src: Ipv4Addr,
+    next_hop: Ipv4Addr,
+}
+
+/// Finds the route to `dst` among the configured interfaces.
+///
+/// Directly connected subnets are preferred over the default gateways.
+fn route(dst: Ipv4Addr) -> Result<Route, NetError> {
+    let interfaces = interfaces();
+    let configured = || {
+        interfaces
+            .iter()
+            .filter_map(|iface| Some((iface, iface.config()?)))
+    };
+    let direct = configured().find(|(_, config)| {
+        dst.is_broadcast() || config.contains(dst) || config.broadcast() == dst
+    });
+    if let Some((iface, config)) = direct {
+        return Ok(Route {
+            iface: Arc::clone(iface),
+            src: config.addr,
+            next_hop: dst,
+        });
+    }
+    let (iface, config, gateway) = configured()
+        .find_map(|(iface, config)| Some((iface, config, config.gateway?)))
+        .ok_or(NetError::NetworkUnreachable)?;
+    Ok(Route {
+        iface: Arc::clone(iface),
+        src: config.addr,
+        next_hop: gateway,
+    })
+}
+
+/// Computes the internet checksum of `data`, continuing from the partial sum
+/// `sum`.
+fn checksum(data: &[u8], mut sum: u32) -> u16 {
+    let (chunks, rest) = data.as_chunks::<2>();
+    for chunk in chunks {
+        sum += u32::from(u16::from_be_bytes(*chunk));
+    }
+    if let [last] = rest {
+        sum += u32::from(*last) << 8;
+    }
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !u16::try_from(sum).unwrap()
+}
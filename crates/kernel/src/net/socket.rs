@@ -0,0 +1,77 @@
+use alloc::sync::Arc;
+use core::any::Any;
+
+use super::{NetError, udp::UdpSocket};
+use crate::{
+    sync::spinlock::SpinMutex,
+    vfs::{File, FileType, Metadata, VfsError},
+};
+
+/// Open file description of a UDP socket.
+///
+/// The socket is bound to an ephemeral port on the first use if not bound
+/// explicitly. Reading receives a datagram, discarding its sender. Writing is
+/// not supported, as the socket has no default destination.
+#[derive(Debug)]
+pub struct SocketFile {
+    socket: SpinMutex<Option<Arc<UdpSocket>>>,
+}
+
+impl SocketFile {
+    pub fn new() -> Self {
+        Self {
+            socket: SpinMutex::new(None),
+        }
+    }
+
+    /// Returns the socket file if `file` is one.
+    pub fn from_file(file: &dyn File) -> Option<&Self> {
+        (file as &dyn Any).downcast_ref()
+    }
+
+    /// Binds the socket to `port`, or to an ephemeral port if `port` is 0.
+    pub fn bind(&self, port: u16) -> Result<(), NetError> {
+        let mut socket = self.socket.lock();
+        if socket.is_some() {
+            return Err(NetError::InvalidArgument);
+        }
+        *socket = Some(UdpSocket::bind(port)?);
+        Ok(())
+    }
+
+    /// Returns the bound socket, binding it to an ephemeral port if needed.
+    pub fn socket(&self) -> Result<Arc<UdpSocket>, NetError> {
+        let mut socket = self.socket.lock();
+        if let Some(socket) = &*socket {
+            return Ok(Arc::clone(socket));
+        }
+        let bound = UdpSocket::bind(0)?;
+        *socket = Some(Arc::clone(&bound));
+        Ok(bound)
+    }
+}
+
+impl File for SocketFile {
+    fn read(&self, buf: &mut [u8]) -> Result<usize, VfsError> {
+        let socket = self.socket().map_err(|_e| VfsError::Io)?;
+        let (len, _src) = socket.recv_from(buf, None).map_err(|_e| VfsError::Io)?;
+        Ok(len)
+    }
+
+    fn write(&self, _buf: &[u8]) -> Result<usize, VfsError> {
+        Err(VfsError::InvalidArgument)
+    }
+
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            ino: 0,
+            file_type: FileType::Socket,
+            perm: 0o777,
+            size: 0,
+        }
+    }
+
+    fn sync(&self) -> Result<(), VfsError> {
+        Err(VfsError::InvalidArgument)
+    }
+}
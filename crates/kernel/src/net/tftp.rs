@@ -0,0 +1,99 @@
+use alloc::{format, vec::Vec};
+use core::time::Duration;
+
+use snafu::{ResultExt as _, whatever};
+
+use super::{Ipv4Addr, NetError, SocketAddrV4, udp::UdpSocket};
+use crate::{
+    error::GenericError,
+    vfs::{self, FileType},
+};
+
+const SERVER_PORT: u16 = 69;
+
+const OP_RRQ: u16 = 1;
+const OP_DATA: u16 = 3;
+const OP_ACK: u16 = 4;
+const OP_ERROR: u16 = 5;
+
+const BLOCK_SIZE: usize = 512;
+const MAX_RETRANSMITS: usize = 5;
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Downloads `filename` from the TFTP server into the new file at `path`.
+///
+/// Returns the size of the file.
+#[cfg_attr(not(feature = "shell"), expect(dead_code))]
+pub fn fetch(server: Ipv4Addr, filename: &str, path: &str) -> Result<usize, GenericError> {
+    let file = vfs::create(path, FileType::Regular)
+        .with_whatever_context(|_| format!("cannot create {path}"))?;
+    let socket = UdpSocket::bind(0).whatever_context("failed to bind a UDP port")?;
+
+    let mut packet = Vec::new();
+    packet.extend_from_slice(&OP_RRQ.to_be_bytes());
+    packet.extend_from_slice(filename.as_bytes());
+    packet.push(0);
+    packet.extend_from_slice(b"octet\0");
+
+    // the server replies from a new port, which identifies the transfer
+    let mut peer = SocketAddrV4::new(server, SERVER_PORT);
+    let mut transfer_port = None;
+    let mut block = 1_u16;
+    let mut size = 0;
+    let mut buf = [0; 4 + BLOCK_SIZE];
+    loop {
+        let mut retransmits = 0;
+        let (len, src) = loop {
+            socket
+                .send_to(&packet, peer)
+                .whatever_context("failed to send TFTP packet")?;
+            match socket.recv_from(&mut buf, Some(TIMEOUT)) {
+                Ok((len, src))
+                    if *src.ip() == server && transfer_port.is_none_or(|p| p == src.port()) =>
+                {
+                    break (len, src);
+                }
+                Ok(_) => {}
+                Err(NetError::TimedOut) if retransmits < MAX_RETRANSMITS => retransmits += 1,
+                Err(e) => return Err(e).whatever_context("no response from the TFTP server"),
+            }
+        };
+        let Some((header, data)) = buf[..len].split_at_checked(4) else {
+            continue;
+        };
+        let opcode = u16::from_be_bytes([header[0], header[1]]);
+        let number = u16::from_be_bytes([header[2], header[3]]);
+        match opcode {
+            OP_DATA => {}
+            OP_ERROR => {
+                let message = data.split(|&b| b == 0).next().unwrap_or_default();
+                whatever!(
+                    "TFTP error {number}: {}",
+                    str::from_utf8(message).unwrap_or("(invalid message)")
+                );
+            }
+            _ => {
+                whatever!("unexpected TFTP opcode {opcode}");
+            }
+        }
+        transfer_port = Some(src.port());
+        peer = src;
+        if number == block {
+            file.write_at(size, data)
+                .with_whatever_context(|_| format!("cannot write to {path}"))?;
+            size += data.len();
+            block = block.wrapping_add(1);
+        }
+        // acknowledge the duplicate block again, as our ACK may be lost
+        packet.clear();
+        packet.extend_from_slice(&OP_ACK.to_be_bytes());
+        packet.extend_from_slice(&number.to_be_bytes());
+        if number == block.wrapping_sub(1) && data.len() < BLOCK_SIZE {
+            socket
+                .send_to(&packet, peer)
+                .whatever_context("failed to send TFTP packet")?;
+            break;
+        }
+    }
+    Ok(size)
+}
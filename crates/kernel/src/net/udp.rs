@@ -0,0 +1,198 @@
+use alloc::{
+    collections::{btree_map::BTreeMap, vec_deque::VecDeque},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::time::Duration;
+
+use super::{Interface, Ipv4Addr, NetError, POLL_INTERVAL, SocketAddrV4, ipv4};
+use crate::{
+    interrupt::timer,
+    sync::spinlock::{SpinMutex, SpinMutexCondVar},
+};
+
+const HEADER_LEN: usize = 8;
+/// Maximum number of datagrams queued on a socket; later ones are dropped.
+const MAX_QUEUED: usize = 64;
+
+const EPHEMERAL_PORTS: core::ops::RangeInclusive<u16> = 49152..=65535;
+
+static SOCKETS: SpinMutex<BTreeMap<u16, Weak<UdpSocket>>> = SpinMutex::new(BTreeMap::new());
+
+#[derive(Debug)]
+struct Datagram {
+    src: SocketAddrV4,
+    data: Vec<u8>,
+}
+
+/// UDP socket bound to a local port on all interfaces.
+///
+/// The port is released when the socket is dropped.
+#[derive(Debug)]
+pub struct UdpSocket {
+    port: u16,
+    queue: SpinMutex<VecDeque<Datagram>>,
+    received: SpinMutexCondVar,
+}
+
+impl UdpSocket {
+    /// Binds a new socket to `port`, or to a free ephemeral port if `port` is
+    /// 0.
+    pub fn bind(port: u16) -> Result<Arc<Self>, NetError> {
+        let mut sockets = SOCKETS.lock();
+        sockets.retain(|_, socket| socket.strong_count() > 0);
+        let port = if port == 0 {
+            EPHEMERAL_PORTS
+                .clone()
+                .find(|port| !sockets.contains_key(port))
+                .ok_or(NetError::AddressInUse)?
+        } else {
+            if sockets.contains_key(&port) {
+                return Err(NetError::AddressInUse);
+            }
+            port
+        };
+        let socket = Arc::new(Self {
+            port,
+            queue: SpinMutex::new(VecDeque::new()),
+            received: SpinMutexCondVar::new(),
+        });
+        sockets.insert(port, Arc::downgrade(&socket));
+        Ok(socket)
+    }
+
+    /// Sends `data` to `dst`, routed through the configured interfaces.
+    pub fn send_to(&self, data: &[u8], dst: SocketAddrV4) -> Result<usize, NetError> {
+        let route = super::route(*dst.ip())?;
+        self.send_via(&route.iface, route.src, route.next_hop, dst, data)
+    }
+
+    /// Sends `data` to `dst` from the address `src` of `iface`, which needs not
+    /// be configured yet.
+    pub(super) fn send_via(
+        &self,
+        iface: &Interface,
+        src: Ipv4Addr,
+        next_hop: Ipv4Addr,
+        dst: SocketAddrV4,
+        data: &[u8],
+    ) -> Result<usize, NetError> {
+        let len = HEADER_LEN + data.len();
+        if len > ipv4::MAX_PAYLOAD_LEN {
+            return Err(NetError::MessageTooLong);
+        }
+        let mut datagram = Vec::with_capacity(len);
+        datagram.extend_from_slice(&self.port.to_be_bytes());
+        datagram.extend_from_slice(&dst.port().to_be_bytes());
+        datagram.extend_from_slice(&u16::try_from(len).unwrap().to_be_bytes());
+        datagram.extend_from_slice(&[0, 0]);
+        datagram.extend_from_slice(data);
+        let checksum = match checksum(src, *dst.ip(), &datagram) {
+            // zero means no checksum
+            0 => 0xffff,
+            checksum => checksum,
+        };
+        datagram[6..8].copy_from_slice(&checksum.to_be_bytes());
+
+        ipv4::send(
+            iface,
+            src,
+            *dst.ip(),
+            next_hop,
+            ipv4::PROTOCOL_UDP,
+            &datagram,
+        )?;
+        Ok(data.len())
+    }
+
+    /// Receives a datagram into `buf`, discarding the bytes that do not fit.
+    ///
+    /// Returns the length of the received data and the sender. If `timeout`
+    /// is given and no datagram is received in time, returns
+    /// [`NetError::TimedOut`].
+    pub fn recv_from(
+        &self,
+        buf: &mut [u8],
+        timeout: Option<Duration>,
+    ) -> Result<(usize, SocketAddrV4), NetError> {
+        let datagram = match timeout {
+            None => {
+                let mut queue = self.queue.lock();
+                loop {
+                    if let Some(datagram) = queue.pop_front() {
+                        break datagram;
+                    }
+                    queue = self.received.wait(queue);
+                }
+            }
+            Some(timeout) => {
+                let deadline = timer::now().saturating_add(timeout);
+                loop {
+                    if let Some(datagram) = self.queue.lock().pop_front() {
+                        break datagram;
+                    }
+                    if timer::now() >= deadline {
+                        return Err(NetError::TimedOut);
+                    }
+                    timer::sleep(POLL_INTERVAL);
+                }
+            }
+        };
+        let len = usize::min(buf.len(), datagram.data.len());
+        buf[..len].copy_from_slice(&datagram.data[..len]);
+        Ok((len, datagram.src))
+    }
+}
+
+impl Drop for UdpSocket {
+    fn drop(&mut self) {
+        let mut sockets = SOCKETS.lock();
+        if sockets
+            .get(&self.port)
+            .is_some_and(|socket| socket.strong_count() == 0)
+        {
+            sockets.remove(&self.port);
+        }
+    }
+}
+
+/// Computes the UDP checksum with the IPv4 pseudo header.
+fn checksum(src: Ipv4Addr, dst: Ipv4Addr, datagram: &[u8]) -> u16 {
+    let mut pseudo_header = [0; 12];
+    pseudo_header[0..4].copy_from_slice(&src.octets());
+    pseudo_header[4..8].copy_from_slice(&dst.octets());
+    pseudo_header[9] = ipv4::PROTOCOL_UDP;
+    pseudo_header[10..12].copy_from_slice(&u16::try_from(datagram.len()).unwrap().to_be_bytes());
+    let partial = !super::checksum(&pseudo_header, 0);
+    super::checksum(datagram, u32::from(partial))
+}
+
+/// Handles the received UDP datagram, queueing it on the bound socket.
+pub(super) fn handle(src: Ipv4Addr, dst: Ipv4Addr, datagram: &[u8]) {
+    let Some(header) = datagram.get(..HEADER_LEN) else {
+        return;
+    };
+    let be_u16 = |offset: usize| u16::from_be_bytes([header[offset], header[offset + 1]]);
+    let len = usize::from(be_u16(4));
+    if len < HEADER_LEN || len > datagram.len() {
+        return;
+    }
+    let datagram = &datagram[..len];
+    if be_u16(6) != 0 && checksum(src, dst, datagram) != 0 {
+        return;
+    }
+
+    let Some(socket) = SOCKETS.lock().get(&be_u16(2)).and_then(Weak::upgrade) else {
+        return;
+    };
+    let mut queue = socket.queue.lock();
+    if queue.len() >= MAX_QUEUED {
+        return;
+    }
+    queue.push_back(Datagram {
+        src: SocketAddrV4::new(src, be_u16(0)),
+        data: datagram[HEADER_LEN..].to_vec(),
+    });
+    drop(queue);
+    socket.received.notify_all();
+}
@@ -0,0 +1,227 @@
+//! Shutting down and rebooting the system.
+//!
+//! The subsystems clean up before the system is reset by the shutdown hooks
+//! registered with [`register_shutdown_hook`]. The hooks are run in the order
+//! of their priorities, and in the registration order for the same priority:
+//! the file systems are synced while the other subsystems still work, then
+//! the secondary CPUs are stopped, the devices are reset, and the console
+//! output is flushed last.
+//!
+//! A panic reboots the system if the `panic.reboot_secs` tunable is set. Only
+//! the hooks that can run in the panic handler are run then.
+
+use alloc::vec::Vec;
+use core::{
+    fmt, hint,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use sbi::system_reset::{self, ResetReason, ResetType};
+
+use crate::{
+    console, error::GenericError, interrupt::timer, sync::spinlock::SpinMutex, tunables::Tunable,
+};
+
+/// Priority of the hooks writing the cached data to the storage.
+pub const PRIORITY_FILESYSTEM: u8 = 0x10;
+/// Priority of the hooks stopping the secondary CPUs.
+pub const PRIORITY_CPU: u8 = 0x20;
+/// Priority of the hooks resetting the devices, after no other CPU uses them.
+pub const PRIORITY_DEVICE: u8 = 0x30;
+/// Priority of the hooks flushing the console output.
+pub const PRIORITY_CONSOLE: u8 = 0xf0;
+
+/// Seconds to wait before rebooting after a panic.
+pub static PANIC_REBOOT_SECS: Tunable<u64> = Tunable::new(
+    "panic.reboot_secs",
+    "seconds to wait before rebooting after a panic, or 0 to halt",
+    0,
+);
+
+/// Hook cleaning up a subsystem before the system is reset.
+///
+/// A hook called with [`ShutdownKind::Panic`] runs in the panic handler with
+/// the interrupts disabled. It must neither block nor write to the console,
+/// and should return immediately if it cannot do so.
+pub type ShutdownHook = fn(ShutdownKind) -> Result<(), GenericError>;
+
+#[derive(Debug)]
+struct Hook {
+    priority: u8,
+    hook: ShutdownHook,
+}
+
+static HOOKS: SpinMutex<Vec<Hook>> = SpinMutex::new(Vec::new());
+
+/// Set when the shutdown hooks start to run.
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+
+initcall!(INITCALL, memory, init);
+
+/// Registers the hooks of the modules declared before the `initcall!` macro.
+fn init() {
+    register_shutdown_hook(PRIORITY_CONSOLE, console::flush);
+}
+
+/// Reason of the shutdown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShutdownKind {
+    PowerOff,
+    Reboot,
+    /// Reboot after a kernel panic.
+    Panic,
+}
+
+impl fmt::Display for ShutdownKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::PowerOff => "power off",
+            Self::Reboot => "reboot",
+            Self::Panic => "reboot after panic",
+        };
+        f.write_str(s)
+    }
+}
+
+/// Registers a hook that is called before the system is reset.
+///
+/// Hooks of a lower `priority` run first; see the `PRIORITY_*` constants.
+pub fn register_shutdown_hook(priority: u8, hook: ShutdownHook) {
+    let mut hooks = HOOKS.lock();
+    let index = hooks.partition_point(|other| other.priority <= priority);
+    hooks.insert(index, Hook { priority, hook });
+}
+
+/// Runs the shutdown hooks, and powers off the system.
+#[cfg_attr(not(feature = "shell"), expect(dead_code))]
+pub fn power_off() -> ! {
+    shutdown(ShutdownKind::PowerOff)
+}
+
+/// Runs the shutdown hooks, and reboots the system.
+#[cfg_attr(not(feature = "shell"), expect(dead_code))]
+pub fn reboot() -> ! {
+    shutdown(ShutdownKind::Reboot)
+}
+
+fn shutdown(kind: ShutdownKind) -> ! {
+    if SHUTTING_DOWN.swap(true, Ordering::AcqRel) {
+        // the system is reset by the other caller
+        loop {
+            hint::spin_loop();
+        }
+    }
+    info!("{kind}: running shutdown hooks");
+    // the hooks may sleep, so they are not called with the list locked
+    let hooks = HOOKS
+        .lock()
+        .iter()
+        .map(|hook| hook.hook)
+        .collect::<Vec<_>>();
+    for hook in hooks {
+        if let Err(e) = hook(kind) {
+            warn!("shutdown hook failed: {e}");
+        }
+    }
+    info!("{kind}: resetting system");
+    reset(kind)
+}
+
+/// Returns the time to wait before rebooting after a panic, if the system is
+/// rebooted.
+#[cfg_attr(feature = "ktest", expect(dead_code))]
+pub fn panic_reboot_delay() -> Option<Duration> {
+    let secs = PANIC_REBOOT_SECS.get();
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
+
+/// Waits for `delay` and reboots the system, called by the panic handler.
+///
+/// The hooks are skipped if the panic happens while they run, or while they
+/// are being registered.
+#[cfg_attr(feature = "ktest", expect(dead_code))]
+pub fn reboot_after_panic(delay: Duration) -> ! {
+    // the system is rebooted at once if the timer is not set up yet
+    if let Some(start) = timer::try_now() {
+        while timer::try_now().is_some_and(|now| now.saturating_duration_since(start) < delay) {
+            hint::spin_loop();
+        }
+    }
+    let hooks = if SHUTTING_DOWN.swap(true, Ordering::AcqRel) {
+        None
+    } else {
+        HOOKS.try_lock()
+    };
+    if let Some(hooks) = hooks {
+        // there is nowhere to report the errors
+        for hook in hooks.iter() {
+            let _ = (hook.hook)(ShutdownKind::Panic);
+        }
+        hooks.unlock();
+    }
+    reset(ShutdownKind::Panic)
+}
+
+fn reset(kind: ShutdownKind) -> ! {
+    let (reset_type, reason) = match kind {
+        ShutdownKind::PowerOff => (ResetType::Shutdown, ResetReason::NoReason),
+        ShutdownKind::Reboot => (ResetType::ColdReboot, ResetReason::NoReason),
+        ShutdownKind::Panic => (ResetType::ColdReboot, ResetReason::SystemFailure),
+    };
+    let Err(e) = system_reset::system_reset(reset_type, reason);
+    // the console cannot be written to after a panic
+    if kind != ShutdownKind::Panic {
+        error!("failed to reset system: {e}");
+    }
+    loop {
+        hint::spin_loop();
+    }
+}
+
+#[cfg(feature = "ktest")]
+pub mod ktests {
+    use core::ptr;
+
+    use snafu::ensure_whatever;
+
+    use super::{HOOKS, ShutdownHook, ShutdownKind, register_shutdown_hook};
+    use crate::{error::GenericError, ktest::KernelTest};
+
+    pub static TESTS: &[KernelTest] = kernel_tests![hooks_in_priority_order];
+
+    #[expect(clippy::unnecessary_wraps)]
+    fn first(_kind: ShutdownKind) -> Result<(), GenericError> {
+        Ok(())
+    }
+
+    #[expect(clippy::unnecessary_wraps)]
+    fn second(_kind: ShutdownKind) -> Result<(), GenericError> {
+        Ok(())
+    }
+
+    fn hooks_in_priority_order() -> Result<(), GenericError> {
+        register_shutdown_hook(u8::MAX, second);
+        register_shutdown_hook(u8::MAX - 1, first);
+        register_shutdown_hook(u8::MAX, first);
+
+        let mut hooks = HOOKS.lock();
+        ensure_whatever!(
+            hooks.is_sorted_by_key(|hook| hook.priority),
+            "hooks not sorted by priority"
+        );
+        let len = hooks.len();
+        let tail = hooks.split_off(len - 3);
+        hooks.unlock();
+        let expected: [(u8, ShutdownHook); 3] =
+            [(u8::MAX - 1, first), (u8::MAX, second), (u8::MAX, first)];
+        ensure_whatever!(
+            tail.iter()
+                .zip(expected)
+                .all(|(hook, (priority, f))| hook.priority == priority
+                    && ptr::fn_addr_eq(hook.hook, f)),
+            "unexpected hook order: {tail:x?}"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,112 @@
+/// "expand 32-byte k"
+const CONSTANTS: [u32; 4] = [0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574];
+
+pub(super) const KEY_LEN: usize = 32;
+pub(super) const BLOCK_LEN: usize = 64;
+
+/// `ChaCha20` keystream generator with fast key erasure.
+///
+/// The key is replaced by the next keystream block after each request, so
+/// that earlier outputs cannot be recovered from the current state.
+#[derive(Debug)]
+pub(super) struct ChaCha20 {
+    key: [u32; 8],
+    counter: u64,
+}
+
+impl ChaCha20 {
+    /// Generator with the all-zero key, which must be reseeded before use.
+    pub(super) const UNSEEDED: Self = Self {
+        key: [0; 8],
+        counter: 0,
+    };
+
+    pub(super) fn new(key: &[u8; KEY_LEN]) -> Self {
+        Self {
+            key: words(key),
+            counter: 0,
+        }
+    }
+
+    /// Mixes `seed` into the key.
+    pub(super) fn reseed(&mut self, seed: &[u8; KEY_LEN]) {
+        for (key, seed) in self.key.iter_mut().zip(words(seed)) {
+            *key ^= seed;
+        }
+        self.rekey();
+    }
+
+    pub(super) fn fill(&mut self, buf: &mut [u8]) {
+        for chunk in buf.chunks_mut(BLOCK_LEN) {
+            let block = self.next_block();
+            chunk.copy_from_slice(&block[..chunk.len()]);
+        }
+        self.rekey();
+    }
+
+    fn rekey(&mut self) {
+        let block = self.next_block();
+        self.key = words(block[..KEY_LEN].try_into().unwrap());
+        self.counter = 0;
+    }
+
+    fn next_block(&mut self) -> [u8; BLOCK_LEN] {
+        let block = block(&self.key, self.counter);
+        self.counter += 1;
+        block
+    }
+}
+
+fn words(bytes: &[u8; KEY_LEN]) -> [u32; 8] {
+    let mut words = [0; 8];
+    for (word, bytes) in words.iter_mut().zip(bytes.as_chunks::<4>().0) {
+        *word = u32::from_le_bytes(*bytes);
+    }
+    words
+}
+
+fn quarter_round(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(12);
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(7);
+}
+
+/// Computes the `ChaCha20` block of `key` at the 64-bit `counter`, with a zero
+/// nonce.
+#[expect(clippy::cast_possible_truncation)]
+fn block(key: &[u32; 8], counter: u64) -> [u8; BLOCK_LEN] {
+    let mut input = [0; 16];
+    input[..4].copy_from_slice(&CONSTANTS);
+    input[4..12].copy_from_slice(key);
+    input[12] = counter as u32;
+    input[13] = (counter >> 32) as u32;
+
+    let mut state = input;
+    for _ in 0..10 {
+        quarter_round(&mut state, 0, 4, 8, 12);
+        quarter_round(&mut state, 1, 5, 9, 13);
+        quarter_round(&mut state, 2, 6, 10, 14);
+        quarter_round(&mut state, 3, 7, 11, 15);
+        quarter_round(&mut state, 0, 5, 10, 15);
+        quarter_round(&mut state, 1, 6, 11, 12);
+        quarter_round(&mut state, 2, 7, 8, 13);
+        quarter_round(&mut state, 3, 4, 9, 14);
+    }
+
+    let mut output = [0; BLOCK_LEN];
+    for ((output, state), input) in output
+        .as_chunks_mut::<4>()
+        .0
+        .iter_mut()
+        .zip(state)
+        .zip(input)
+    {
+        *output = state.wrapping_add(input).to_le_bytes();
+    }
+    output
+}
@@ -0,0 +1,165 @@
+//! Kernel cryptographically secure random number generator.
+//!
+//! Random bytes are generated by `ChaCha20` keyed from an entropy pool, which
+//! is filled by the hardware entropy sources. The generator is reseeded
+//! periodically, both after an interval and after an amount of output.
+
+use alloc::{sync::Arc, vec::Vec};
+use core::{fmt, time::Duration};
+
+use self::chacha::{ChaCha20, KEY_LEN};
+use crate::{
+    interrupt::timer::{self, Instant},
+    sync::spinlock::SpinMutex,
+};
+
+mod chacha;
+
+const RESEED_INTERVAL: Duration = Duration::from_secs(300);
+const RESEED_OUTPUT_LEN: usize = 1024 * 1024;
+
+static STATE: SpinMutex<State> = SpinMutex::new(State {
+    rng: ChaCha20::UNSEEDED,
+    pool: [0; KEY_LEN],
+    pool_entropy: 0,
+    seeded: false,
+    warned_unseeded: false,
+    last_reseed: Instant::ZERO,
+    output_since_reseed: 0,
+});
+
+static SOURCES: SpinMutex<Vec<Arc<dyn EntropySource>>> = SpinMutex::new(Vec::new());
+
+/// Hardware source of random bytes.
+pub trait EntropySource: fmt::Debug + Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Requests random bytes, which the source passes to [`add_entropy`] when
+    /// they are available.
+    ///
+    /// This can be called in the interrupt context, and must not block.
+    fn request(&self);
+}
+
+#[derive(Debug)]
+struct State {
+    rng: ChaCha20,
+    /// Entropy collected for the next reseed.
+    pool: [u8; KEY_LEN],
+    /// Number of the random bytes mixed into `pool`, saturating at its
+    /// length.
+    pool_entropy: usize,
+    seeded: bool,
+    warned_unseeded: bool,
+    last_reseed: Instant,
+    output_since_reseed: usize,
+}
+
+impl State {
+    fn is_pool_full(&self) -> bool {
+        self.pool_entropy >= KEY_LEN
+    }
+
+    fn reseed(&mut self, now: Instant) {
+        self.rng.reseed(&self.pool);
+        self.pool = [0; KEY_LEN];
+        self.pool_entropy = 0;
+        self.seeded = true;
+        self.last_reseed = now;
+        self.output_since_reseed = 0;
+    }
+
+    fn is_reseed_due(&self, now: Instant) -> bool {
+        now.duration_since(self.last_reseed) >= RESEED_INTERVAL
+            || self.output_since_reseed >= RESEED_OUTPUT_LEN
+    }
+}
+
+/// Registers `source` and requests the initial seed from it.
+pub fn register_source(source: Arc<dyn EntropySource>) {
+    info!("entropy source {} registered", source.name());
+    source.request();
+    SOURCES.lock().push(source);
+}
+
+fn request_entropy() {
+    let sources = SOURCES.lock().clone();
+    for source in sources {
+        source.request();
+    }
+}
+
+/// Returns whether the entropy pool needs more random bytes.
+pub fn wants_entropy() -> bool {
+    !STATE.lock().is_pool_full()
+}
+
+/// Returns whether the generator has been seeded from an entropy source.
+#[cfg_attr(not(feature = "shell"), expect(dead_code))]
+pub fn is_seeded() -> bool {
+    STATE.lock().seeded
+}
+
+/// Mixes the random bytes from an entropy source into the pool.
+///
+/// The generator is seeded as soon as the pool is first filled.
+pub fn add_entropy(data: &[u8]) {
+    let mut state = STATE.lock();
+    for chunk in data.chunks(KEY_LEN) {
+        for (pool, byte) in state.pool.iter_mut().zip(chunk) {
+            *pool ^= byte;
+        }
+        // compress the pool, so that a biased source cannot cancel out earlier
+        // inputs
+        let mut mixer = ChaCha20::new(&state.pool);
+        mixer.fill(&mut state.pool);
+        state.pool_entropy = usize::min(state.pool_entropy + chunk.len(), KEY_LEN);
+    }
+    if !state.seeded && state.is_pool_full() {
+        let now = timer::try_now().unwrap_or(Instant::ZERO);
+        state.reseed(now);
+        drop(state);
+        info!("random number generator seeded");
+        request_entropy();
+    }
+}
+
+/// Returns a cryptographically secure random number.
+///
+/// See [`fill`] for the output before the generator is seeded.
+pub fn next_u64() -> u64 {
+    let mut bytes = [0; 8];
+    fill(&mut bytes);
+    u64::from_le_bytes(bytes)
+}
+
+/// Fills `buf` with cryptographically secure random bytes.
+///
+/// If no entropy source has seeded the generator yet, the output is only as
+/// unpredictable as the timer, and a warning is logged once.
+pub fn fill(buf: &mut [u8]) {
+    let now = timer::try_now().unwrap_or(Instant::ZERO);
+    let mut state = STATE.lock();
+    let mut requested = false;
+    if !state.seeded {
+        let ticks = now.duration_since_epoc().as_nanos().to_le_bytes();
+        let mut seed = [0; KEY_LEN];
+        seed[..ticks.len()].copy_from_slice(&ticks);
+        state.rng.reseed(&seed);
+        if !state.warned_unseeded {
+            state.warned_unseeded = true;
+            warn!("random bytes requested before the generator is seeded");
+        }
+    } else if state.is_reseed_due(now) {
+        if state.is_pool_full() {
+            state.reseed(now);
+        }
+        requested = true;
+    }
+    state.rng.fill(buf);
+    state.output_since_reseed = state.output_since_reseed.saturating_add(buf.len());
+    drop(state);
+    if requested {
+        request_entropy();
+    }
+}
@@ -0,0 +1,94 @@
+use alloc::{format, sync::Arc};
+
+use snafu::{OptionExt as _, ResultExt as _, whatever};
+
+use super::{Command, Output};
+use crate::{
+    block::{self, BlockDevice, SECTOR_SIZE},
+    error::GenericError,
+};
+
+pub(super) const COMMAND: Command = Command {
+    name: "blk",
+    usage: "blk [list|read|fill] ...",
+    description: "list block devices or read and write sectors",
+    run,
+};
+
+const USAGE: &str = "\
+usage: blk [list]
+       blk read <device> <sector>
+       blk fill <device> <sector> <byte>";
+
+fn run(out: &mut Output, args: &[&str]) -> Result<(), GenericError> {
+    match args {
+        [] | ["list"] => {
+            list(out);
+            Ok(())
+        }
+        ["read", device, sector] => read(out, device, sector),
+        ["fill", device, sector, byte] => fill(device, sector, byte),
+        _ => {
+            whatever!("invalid arguments\n{USAGE}");
+        }
+    }
+}
+
+fn list(out: &mut Output) {
+    writeln!(out, "{:<8} {:>12} {:>12} mode", "name", "sectors", "KiB");
+    for device in block::devices() {
+        writeln!(
+            out,
+            "{:<8} {:>12} {:>12} {}",
+            device.name(),
+            device.num_sectors(),
+            device.num_sectors() * u64::try_from(SECTOR_SIZE).unwrap() / 1024,
+            if device.is_read_only() { "ro" } else { "rw" }
+        );
+    }
+}
+
+fn read(out: &mut Output, device: &str, sector: &str) -> Result<(), GenericError> {
+    let device = find_device(device)?;
+    let sector = parse(sector)?;
+    let mut buf = [0; SECTOR_SIZE];
+    block::read(&*device, sector, &mut buf)
+        .with_whatever_context(|_| format!("cannot read sector {sector} of {}", device.name()))?;
+    for (i, line) in buf.chunks(16).enumerate() {
+        write!(out, "{:04x}:", i * 16);
+        for byte in line {
+            write!(out, " {byte:02x}");
+        }
+        write!(out, "  ");
+        for &byte in line {
+            let ch = if byte.is_ascii_graphic() || byte == b' ' {
+                char::from(byte)
+            } else {
+                '.'
+            };
+            write!(out, "{ch}");
+        }
+        writeln!(out);
+    }
+    Ok(())
+}
+
+fn fill(device: &str, sector: &str, byte: &str) -> Result<(), GenericError> {
+    let device = find_device(device)?;
+    let sector = parse(sector)?;
+    let byte = u8::from_str_radix(byte.trim_start_matches("0x"), 16)
+        .with_whatever_context(|_| format!("invalid byte `{byte}`"))?;
+    block::write(&*device, sector, &[byte; SECTOR_SIZE])
+        .with_whatever_context(|_| format!("cannot write sector {sector} of {}", device.name()))?;
+    block::flush(&*device).with_whatever_context(|_| format!("cannot flush {}", device.name()))?;
+    Ok(())
+}
+
+fn find_device(name: &str) -> Result<Arc<dyn BlockDevice>, GenericError> {
+    block::find(name).with_whatever_context(|| format!("block device {name} not found"))
+}
+
+fn parse(s: &str) -> Result<u64, GenericError> {
+    s.parse()
+        .with_whatever_context(|_| format!("invalid number `{s}`"))
+}
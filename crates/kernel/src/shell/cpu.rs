@@ -0,0 +1,83 @@
+use alloc::format;
+
+use snafu::{ResultExt as _, whatever};
+
+use super::{Command, Output};
+use crate::{
+    cpu::{
+        self, Cpuid, hotplug,
+        idle::{self, IdleState},
+    },
+    error::GenericError,
+};
+
+pub(super) const COMMAND: Command = Command {
+    name: "cpu",
+    usage: "cpu [idle|online <cpuid>|offline <cpuid>]",
+    description: "show CPU states and idle statistics, or stop and restart a CPU",
+    run,
+};
+
+const USAGE: &str = "\
+usage: cpu
+       cpu idle
+       cpu online <cpuid>
+       cpu offline <cpuid>";
+
+fn run(out: &mut Output, args: &[&str]) -> Result<(), GenericError> {
+    match args {
+        [] => {
+            for cpu in cpu::get_all() {
+                let boot = if cpu.id() == cpu::boot_cpuid() {
+                    " (boot)"
+                } else {
+                    ""
+                };
+                let state = hotplug::state(cpu.id());
+                writeln!(out, "CPU#{}{boot}: {state:?}", cpu.id());
+            }
+            Ok(())
+        }
+        ["idle"] => {
+            idle_stats(out);
+            Ok(())
+        }
+        ["online", cpuid] => hotplug::online(parse_cpuid(cpuid)?),
+        ["offline", cpuid] => hotplug::offline(parse_cpuid(cpuid)?),
+        _ => {
+            whatever!("invalid arguments\n{USAGE}");
+        }
+    }
+}
+
+fn parse_cpuid(s: &str) -> Result<Cpuid, GenericError> {
+    let cpuid = s
+        .parse()
+        .with_whatever_context(|_| format!("invalid number `{s}`"))?;
+    Ok(Cpuid::from_raw(cpuid))
+}
+
+fn idle_stats(out: &mut Output) {
+    writeln!(
+        out,
+        "{:>6} {:>14} {:>10} {:>14} {:>8}",
+        "cpu", "state", "entries", "residency", "early"
+    );
+    for cpu in cpu::get_all() {
+        let Some(stats) = idle::stats(cpu.id()) else {
+            continue;
+        };
+        for (state, stats) in IdleState::ALL.into_iter().zip(stats) {
+            let disabled = if state.is_usable() { "" } else { " (disabled)" };
+            writeln!(
+                out,
+                "{:>6} {:>14} {:>10} {:>14} {:>8}{disabled}",
+                format!("CPU#{}", cpu.id()),
+                state.name(),
+                stats.entries,
+                format!("{:?}", stats.residency),
+                stats.early_wakeups,
+            );
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use alloc::{format, string::String, sync::Arc};
+
+use snafu::{ResultExt as _, whatever};
+
+use super::{Command, Output};
+use crate::{
+    error::GenericError,
+    vfs::{self, FileType, OpenMode, VfsError},
+};
+
+pub(super) const LS_COMMAND: Command = Command {
+    name: "ls",
+    usage: "ls [path]",
+    description: "list directory contents",
+    run: ls,
+};
+
+pub(super) const CAT_COMMAND: Command = Command {
+    name: "cat",
+    usage: "cat <path>",
+    description: "print file contents",
+    run: cat,
+};
+
+pub(super) const WRITE_COMMAND: Command = Command {
+    name: "write",
+    usage: "write <path> <text>...",
+    description: "append a line to a file, creating it if needed",
+    run: write,
+};
+
+pub(super) const MKDIR_COMMAND: Command = Command {
+    name: "mkdir",
+    usage: "mkdir <path>",
+    description: "create a directory",
+    run: mkdir,
+};
+
+pub(super) const MOUNT_COMMAND: Command = Command {
+    name: "mount",
+    usage: "mount [<type> <device> <path>]",
+    description: "list mounts or mount a block device",
+    run: mount,
+};
+
+pub(super) const SYNC_COMMAND: Command = Command {
+    name: "sync",
+    usage: "sync",
+    description: "write cached file system data to the devices",
+    run: sync,
+};
+
+fn ls(out: &mut Output, args: &[&str]) -> Result<(), GenericError> {
+    let path = match args {
+        [] => "/",
+        [path] => path,
+        _ => {
+            whatever!("invalid arguments\nusage: ls [path]");
+        }
+    };
+    let dentry = vfs::resolve(path).with_whatever_context(|_| format!("cannot access {path}"))?;
+    let entries = dentry
+        .inode()
+        .read_dir()
+        .with_whatever_context(|_| format!("cannot list {}", dentry.path()))?;
+    for entry in entries {
+        let Ok(inode) = dentry.inode().lookup(&entry.name) else {
+            continue;
+        };
+        let metadata = inode.metadata();
+        let suffix = if entry.file_type == FileType::Directory {
+            "/"
+        } else {
+            ""
+        };
+        writeln!(
+            out,
+            "{:06o} {:>8} {}{suffix}",
+            metadata.mode(),
+            metadata.size,
+            entry.name
+        );
+    }
+    Ok(())
+}
+
+fn cat(out: &mut Output, args: &[&str]) -> Result<(), GenericError> {
+    let [path] = args else {
+        whatever!("invalid arguments\nusage: cat <path>");
+    };
+    let file = vfs::open(path, OpenMode::ReadOnly)
+        .with_whatever_context(|_| format!("cannot open {path}"))?;
+    let mut buf = [0; 512];
+    loop {
+        let nread = file
+            .read(&mut buf)
+            .with_whatever_context(|_| format!("cannot read {path}"))?;
+        if nread == 0 {
+            break;
+        }
+        write!(out, "{}", String::from_utf8_lossy(&buf[..nread]));
+    }
+    Ok(())
+}
+
+fn write(_out: &mut Output, args: &[&str]) -> Result<(), GenericError> {
+    let [path, words @ ..] = args else {
+        whatever!("invalid arguments\nusage: write <path> <text>...");
+    };
+    let inode = match vfs::create(path, FileType::Regular) {
+        Ok(inode) => inode,
+        Err(VfsError::AlreadyExists) => Arc::clone(
+            vfs::resolve(path)
+                .with_whatever_context(|_| format!("cannot access {path}"))?
+                .inode(),
+        ),
+        Err(e) => return Err(e).with_whatever_context(|_| format!("cannot create {path}")),
+    };
+    let mut line = words.join(" ");
+    line.push('\n');
+    inode
+        .write_at(inode.metadata().size, line.as_bytes())
+        .with_whatever_context(|_| format!("cannot write {path}"))?;
+    Ok(())
+}
+
+fn mkdir(_out: &mut Output, args: &[&str]) -> Result<(), GenericError> {
+    let [path] = args else {
+        whatever!("invalid arguments\nusage: mkdir <path>");
+    };
+    vfs::create(path, FileType::Directory)
+        .with_whatever_context(|_| format!("cannot create directory {path}"))?;
+    Ok(())
+}
+
+fn mount(out: &mut Output, args: &[&str]) -> Result<(), GenericError> {
+    match args {
+        [] => {
+            for (path, fs) in vfs::mount::mounts() {
+                writeln!(out, "{} on {path}", fs.name());
+            }
+            Ok(())
+        }
+        [fs_type, device, path] => vfs::mount_device(fs_type, device, path)
+            .with_whatever_context(|_| format!("cannot mount {device} at {path}")),
+        _ => {
+            whatever!("invalid arguments\nusage: mount [<type> <device> <path>]");
+        }
+    }
+}
+
+fn sync(_out: &mut Output, _args: &[&str]) -> Result<(), GenericError> {
+    vfs::sync_all().whatever_context("failed to sync file systems")
+}
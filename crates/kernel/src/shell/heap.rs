@@ -0,0 +1,86 @@
+use snafu::whatever;
+
+use super::{Command, Output};
+use crate::{
+    error::GenericError,
+    memory::{allocator, kernel_space},
+};
+
+pub(super) const COMMAND: Command = Command {
+    name: "heap",
+    usage: "heap [leaks [mark]]",
+    description: "show heap usage or report allocations not freed",
+    run,
+};
+
+const USAGE: &str = "\
+usage: heap
+       heap leaks
+       heap leaks mark";
+
+fn run(out: &mut Output, args: &[&str]) -> Result<(), GenericError> {
+    match args {
+        [] => {
+            stats(out);
+            Ok(())
+        }
+        #[cfg(feature = "heap-debug")]
+        ["leaks"] => {
+            leaks(out);
+            Ok(())
+        }
+        #[cfg(feature = "heap-debug")]
+        ["leaks", "mark"] => {
+            allocator::mark_leaks();
+            Ok(())
+        }
+        #[cfg(not(feature = "heap-debug"))]
+        ["leaks", ..] => {
+            whatever!("leak tracking requires the kernel built with the `heap-debug` feature");
+        }
+        _ => {
+            whatever!("invalid arguments\n{USAGE}");
+        }
+    }
+}
+
+fn stats(out: &mut Output) {
+    let stats = allocator::stats();
+    writeln!(out, "heap:      {:>10} KiB", stats.heap_size / 1024);
+    writeln!(out, "allocated: {:>10} KiB", stats.allocated / 1024);
+    writeln!(out, "pool:      {:>10} KiB", stats.pool_size / 1024);
+    let stacks = kernel_space::stack_stats();
+    writeln!(
+        out,
+        "stacks:    {:>10} in use (peak {}, {} guard page faults)",
+        stacks.in_use, stacks.peak, stacks.guard_trips
+    );
+    #[cfg(feature = "heap-debug")]
+    {
+        let debug = stats.debug;
+        writeln!(
+            out,
+            "tracked:   {:>10} allocations ({} untracked)",
+            debug.tracked, debug.untracked
+        );
+        writeln!(
+            out,
+            "quarantine: {:>9} blocks, {} KiB",
+            debug.quarantined,
+            debug.quarantined_bytes / 1024
+        );
+    }
+}
+
+#[cfg(feature = "heap-debug")]
+fn leaks(out: &mut Output) {
+    let leaks = allocator::leaks();
+    for leak in &leaks {
+        writeln!(out, "{} bytes in {} allocations", leak.bytes, leak.count);
+        for ra in leak.backtrace.iter().take_while(|&&ra| ra != 0) {
+            writeln!(out, "    {ra:#018x}");
+        }
+    }
+    let bytes = leaks.iter().map(|leak| leak.bytes).sum::<usize>();
+    writeln!(out, "{bytes} bytes in {} call sites", leaks.len());
+}
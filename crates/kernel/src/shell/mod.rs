@@ -0,0 +1,146 @@
+use alloc::{string::String, sync::Arc, vec::Vec};
+use core::fmt;
+
+use crate::{
+    chosen, console,
+    drivers::serial::{self, SerialDevice},
+    error::GenericError,
+    task::kthread,
+    tty,
+};
+
+mod blk;
+mod cpu;
+mod fs;
+mod heap;
+#[cfg(feature = "net")]
+mod net;
+#[cfg(feature = "plic")]
+mod plic;
+mod power;
+mod rand;
+mod stats;
+mod sysctl;
+mod tasks;
+mod trace;
+
+const PROMPT: &str = "onix> ";
+
+struct Command {
+    name: &'static str,
+    usage: &'static str,
+    description: &'static str,
+    run: fn(&mut Output, &[&str]) -> Result<(), GenericError>,
+}
+
+const COMMANDS: &[Command] = &[
+    Command {
+        name: "help",
+        usage: "help",
+        description: "show available commands",
+        run: help,
+    },
+    blk::COMMAND,
+    cpu::COMMAND,
+    fs::LS_COMMAND,
+    fs::CAT_COMMAND,
+    fs::WRITE_COMMAND,
+    fs::MKDIR_COMMAND,
+    fs::MOUNT_COMMAND,
+    fs::SYNC_COMMAND,
+    heap::COMMAND,
+    #[cfg(feature = "net")]
+    net::NET_COMMAND,
+    #[cfg(feature = "net")]
+    net::UDP_COMMAND,
+    #[cfg(feature = "net")]
+    net::TFTP_COMMAND,
+    #[cfg(feature = "plic")]
+    plic::COMMAND,
+    power::POWEROFF_COMMAND,
+    power::REBOOT_COMMAND,
+    rand::COMMAND,
+    stats::COMMAND,
+    sysctl::COMMAND,
+    tasks::COMMAND,
+    trace::COMMAND,
+];
+
+pub fn spawn() {
+    kthread::Builder::new()
+        .name("shell")
+        .spawn(shell_task)
+        .unwrap();
+}
+
+fn shell_task() -> ! {
+    let stdout_path = chosen::stdout_path().unwrap();
+    let serial_stdout = serial::find_serial_by_dtree_path(stdout_path).unwrap();
+
+    let mut out = Output {
+        serial: serial_stdout,
+    };
+    let tty = tty::console().unwrap();
+    let mut line = String::new();
+    loop {
+        write!(out, "{PROMPT}");
+        line.clear();
+        if tty.read_line(&mut line).is_err() {
+            // the line is discarded by Ctrl-C
+            continue;
+        }
+
+        let args = line.split_ascii_whitespace().collect::<Vec<_>>();
+        let Some((name, args)) = args.split_first() else {
+            continue;
+        };
+        let Some(command) = COMMANDS.iter().find(|command| command.name == *name) else {
+            writeln!(out, "unknown command: {name} (try `help`)");
+            continue;
+        };
+        if let Err(e) = (command.run)(&mut out, args) {
+            writeln!(out, "{name}: {e}");
+        }
+    }
+}
+
+#[expect(clippy::unnecessary_wraps)]
+fn help(out: &mut Output, _args: &[&str]) -> Result<(), GenericError> {
+    for command in COMMANDS {
+        writeln!(out, "{:<32} {}", command.usage, command.description);
+    }
+    Ok(())
+}
+
+/// Shell output written to the serial device.
+///
+/// Writes never fail; `write!`/`writeln!` on this type evaluate to `()`.
+struct Output {
+    serial: Arc<SerialDevice>,
+}
+
+impl Output {
+    fn write_fmt(&mut self, args: fmt::Arguments<'_>) {
+        let _ = fmt::Write::write_fmt(self, args);
+    }
+
+    fn write_bytes(&self, mut bytes: &[u8]) {
+        console::write_to_sinks(bytes);
+        while !bytes.is_empty() {
+            let nwritten = self.serial.write(bytes);
+            bytes = &bytes[nwritten..];
+        }
+    }
+}
+
+impl fmt::Write for Output {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for (i, part) in s.split('\n').enumerate() {
+            if i > 0 {
+                self.write_bytes(b"\r\n");
+            }
+            self.write_bytes(part.as_bytes());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,187 @@
+use alloc::{format, string::String, sync::Arc};
+use core::time::Duration;
+
+use snafu::{OptionExt as _, ResultExt as _, whatever};
+
+use super::{Command, Output};
+use crate::{
+    error::GenericError,
+    net::{
+        self, Interface, Ipv4Addr, Ipv4Config, NetError, SocketAddrV4, arp, dhcp, tftp,
+        udp::UdpSocket,
+    },
+};
+
+pub(super) const NET_COMMAND: Command = Command {
+    name: "net",
+    usage: "net [list|dhcp|addr|arp] ...",
+    description: "list or configure network interfaces",
+    run: net,
+};
+
+pub(super) const UDP_COMMAND: Command = Command {
+    name: "udp",
+    usage: "udp [send|echo] ...",
+    description: "send UDP datagrams or run a UDP echo server",
+    run: udp,
+};
+
+pub(super) const TFTP_COMMAND: Command = Command {
+    name: "tftp",
+    usage: "tftp <server> <file> <path>",
+    description: "download a file from a TFTP server",
+    run: tftp,
+};
+
+const NET_USAGE: &str = "\
+usage: net [list]
+       net dhcp <interface>
+       net addr <interface> <address>/<prefix> [gateway]
+       net arp";
+
+const UDP_USAGE: &str = "\
+usage: udp send <address> <port> <text>...
+       udp echo <port> [count]";
+
+/// Time to wait for the reply of `udp send`.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+
+fn net(out: &mut Output, args: &[&str]) -> Result<(), GenericError> {
+    match args {
+        [] | ["list"] => {
+            for iface in net::interfaces() {
+                let config = iface.config().map_or_else(
+                    || String::from("unconfigured"),
+                    |config| format!("{config}"),
+                );
+                writeln!(out, "{:<8} {} {config}", iface.name(), iface.mac_addr());
+            }
+        }
+        ["dhcp", iface] => {
+            let iface = find_interface(iface)?;
+            let config = dhcp::configure(&iface)
+                .with_whatever_context(|_| format!("DHCP failed on {}", iface.name()))?;
+            writeln!(out, "{}: {config}", iface.name());
+        }
+        ["addr", iface, addr, rest @ ..] => {
+            let iface = find_interface(iface)?;
+            let (addr, prefix_len) = addr
+                .split_once('/')
+                .whatever_context("address must be in <address>/<prefix> form")?;
+            let prefix_len = prefix_len
+                .parse()
+                .ok()
+                .filter(|&len| len <= 32)
+                .with_whatever_context(|| format!("invalid prefix length `{prefix_len}`"))?;
+            let gateway = match rest {
+                [] => None,
+                [gateway] => Some(parse_addr(gateway)?),
+                _ => {
+                    whatever!("invalid arguments\n{NET_USAGE}");
+                }
+            };
+            iface.set_config(Some(Ipv4Config {
+                addr: parse_addr(addr)?,
+                prefix_len,
+                gateway,
+                dns: None,
+            }));
+        }
+        ["arp"] => {
+            for iface in net::interfaces() {
+                for (ip, mac) in arp::entries(&iface) {
+                    writeln!(out, "{:<15} {mac} {}", ip, iface.name());
+                }
+            }
+        }
+        _ => {
+            whatever!("invalid arguments\n{NET_USAGE}");
+        }
+    }
+    Ok(())
+}
+
+fn udp(out: &mut Output, args: &[&str]) -> Result<(), GenericError> {
+    match args {
+        ["send", addr, port, text @ ..] => {
+            let dst = SocketAddrV4::new(parse_addr(addr)?, parse_port(port)?);
+            let socket = UdpSocket::bind(0).whatever_context("failed to bind a UDP port")?;
+            let data = text.join(" ");
+            socket
+                .send_to(data.as_bytes(), dst)
+                .with_whatever_context(|_| format!("cannot send to {dst}"))?;
+            let mut buf = [0; 1500];
+            match socket.recv_from(&mut buf, Some(REPLY_TIMEOUT)) {
+                Ok((len, src)) => print_datagram(out, src, &buf[..len]),
+                Err(NetError::TimedOut) => writeln!(out, "no reply"),
+                Err(e) => return Err(e).whatever_context("cannot receive the reply"),
+            }
+        }
+        ["echo", port, rest @ ..] => {
+            let count = match rest {
+                [] => 1,
+                [count] => count
+                    .parse()
+                    .with_whatever_context(|_| format!("invalid count `{count}`"))?,
+                _ => {
+                    whatever!("invalid arguments\n{UDP_USAGE}");
+                }
+            };
+            let port = parse_port(port)?;
+            let socket = UdpSocket::bind(port)
+                .with_whatever_context(|_| format!("cannot bind UDP port {port}"))?;
+            let mut buf = [0; 1500];
+            for _ in 0..count {
+                let (len, src) = socket
+                    .recv_from(&mut buf, None)
+                    .whatever_context("cannot receive a datagram")?;
+                print_datagram(out, src, &buf[..len]);
+                socket
+                    .send_to(&buf[..len], src)
+                    .with_whatever_context(|_| format!("cannot reply to {src}"))?;
+            }
+        }
+        _ => {
+            whatever!("invalid arguments\n{UDP_USAGE}");
+        }
+    }
+    Ok(())
+}
+
+fn tftp(out: &mut Output, args: &[&str]) -> Result<(), GenericError> {
+    let [server, file, path] = args else {
+        whatever!("invalid arguments\nusage: tftp <server> <file> <path>");
+    };
+    let size = tftp::fetch(parse_addr(server)?, file, path)
+        .with_whatever_context(|_| format!("cannot download {file} from {server}"))?;
+    writeln!(out, "{size} bytes received");
+    Ok(())
+}
+
+fn print_datagram(out: &mut Output, src: SocketAddrV4, data: &[u8]) {
+    let text = data
+        .iter()
+        .map(|&byte| {
+            if byte.is_ascii_graphic() || byte == b' ' {
+                char::from(byte)
+            } else {
+                '.'
+            }
+        })
+        .collect::<String>();
+    writeln!(out, "{src}: {text}");
+}
+
+fn find_interface(name: &str) -> Result<Arc<Interface>, GenericError> {
+    net::find_interface(name).with_whatever_context(|| format!("interface {name} not found"))
+}
+
+fn parse_addr(s: &str) -> Result<Ipv4Addr, GenericError> {
+    s.parse()
+        .with_whatever_context(|_| format!("invalid IPv4 address `{s}`"))
+}
+
+fn parse_port(s: &str) -> Result<u16, GenericError> {
+    s.parse()
+        .with_whatever_context(|_| format!("invalid port `{s}`"))
+}
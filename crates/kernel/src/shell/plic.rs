@@ -0,0 +1,133 @@
+use alloc::{format, sync::Arc};
+use core::str::FromStr;
+
+use snafu::{OptionExt as _, ResultExt as _, whatever};
+
+use super::{Command, Output};
+use crate::{
+    cpu::{self, Cpuid},
+    drivers::irq::plic::{self, Plic, PlicSource},
+    error::GenericError,
+};
+
+pub(super) const COMMAND: Command = Command {
+    name: "plic",
+    usage: "plic [stats|mask|unmask|affinity|threshold] ...",
+    description: "show PLIC statistics or configure interrupt sources",
+    run,
+};
+
+const USAGE: &str = "\
+usage: plic [stats]
+       plic mask <plic> <source>
+       plic unmask <plic> <source>
+       plic affinity <plic> <source> <cpuid>
+       plic threshold <plic> <cpuid> <threshold>
+       plic threshold <plic> reset";
+
+fn run(out: &mut Output, args: &[&str]) -> Result<(), GenericError> {
+    match args {
+        [] | ["stats"] => {
+            stats(out);
+            Ok(())
+        }
+        ["mask", plic, source] => {
+            let (plic, source) = find_source(plic, source)?;
+            plic.mask(source)
+        }
+        ["unmask", plic, source] => {
+            let (plic, source) = find_source(plic, source)?;
+            plic.unmask(source)
+        }
+        ["affinity", plic, source, cpuid] => {
+            let (plic, source) = find_source(plic, source)?;
+            let cpuid = Cpuid::from_raw(parse(cpuid)?);
+            plic.set_affinity(source, cpuid)
+        }
+        ["threshold", plic, "reset"] => {
+            find_plic(plic)?.reset_thresholds();
+            Ok(())
+        }
+        ["threshold", plic, cpuid, threshold] => {
+            let plic = find_plic(plic)?;
+            let cpuid = Cpuid::from_raw(parse(cpuid)?);
+            plic.set_threshold(cpuid, parse(threshold)?)
+        }
+        _ => {
+            whatever!("invalid arguments\n{USAGE}");
+        }
+    }
+}
+
+fn stats(out: &mut Output) {
+    for (index, plic) in plic::get_all().iter().enumerate() {
+        writeln!(
+            out,
+            "PLIC#{index} {} (spurious claims: {})",
+            plic.path(),
+            plic.spurious_claims()
+        );
+        for cpu in cpu::get_all() {
+            if let Some(threshold) = plic.threshold(cpu.id()) {
+                writeln!(out, "  CPU#{} threshold={threshold}", cpu.id());
+            }
+        }
+        writeln!(
+            out,
+            "  {:>6} {:<24} {:<8} {:>8} {:>8} {:>12} {:>12}",
+            "source", "name", "state", "fires", "spurious", "avg", "max"
+        );
+        for info in plic.source_infos() {
+            let state = match (info.enabled, info.masked) {
+                (_, true) => "masked",
+                (true, false) => "enabled",
+                (false, false) => "disabled",
+            };
+            let avg = info
+                .stats
+                .total_handler_time
+                .checked_div(info.stats.fires.try_into().unwrap_or(u32::MAX))
+                .unwrap_or_default();
+            writeln!(
+                out,
+                "  {:>6} {:<24} {:<8} {:>8} {:>8} {:>12} {:>12}",
+                info.source.value(),
+                info.name.as_deref().unwrap_or("-"),
+                state,
+                info.stats.fires,
+                info.stats.spurious,
+                format!("{avg:?}"),
+                format!("{:?}", info.stats.max_handler_time),
+            );
+            if let Some(affinity) = &info.affinity {
+                writeln!(out, "         affinity: {affinity:?}");
+            }
+        }
+    }
+}
+
+fn find_plic(index: &str) -> Result<Arc<Plic>, GenericError> {
+    let index = parse::<usize>(index)?;
+    plic::get_all()
+        .get(index)
+        .map(Arc::clone)
+        .with_whatever_context(|| format!("PLIC#{index} not found"))
+}
+
+fn find_source(plic: &str, source: &str) -> Result<(Arc<Plic>, PlicSource), GenericError> {
+    let plic = find_plic(plic)?;
+    let id = parse(source)?;
+    let source = plic
+        .source(id)
+        .with_whatever_context(|| format!("invalid interrupt source {id}"))?;
+    Ok((plic, source))
+}
+
+fn parse<T>(s: &str) -> Result<T, GenericError>
+where
+    T: FromStr,
+    T::Err: core::error::Error + Send + Sync + 'static,
+{
+    s.parse()
+        .with_whatever_context(|_| format!("invalid number `{s}`"))
+}
@@ -0,0 +1,24 @@
+use super::{Command, Output};
+use crate::{error::GenericError, power};
+
+pub(super) const POWEROFF_COMMAND: Command = Command {
+    name: "poweroff",
+    usage: "poweroff",
+    description: "shut down and power off the system",
+    run: poweroff,
+};
+
+pub(super) const REBOOT_COMMAND: Command = Command {
+    name: "reboot",
+    usage: "reboot",
+    description: "shut down and reboot the system",
+    run: reboot,
+};
+
+fn poweroff(_out: &mut Output, _args: &[&str]) -> Result<(), GenericError> {
+    power::power_off()
+}
+
+fn reboot(_out: &mut Output, _args: &[&str]) -> Result<(), GenericError> {
+    power::reboot()
+}
@@ -0,0 +1,44 @@
+use alloc::{format, vec};
+
+use snafu::{ResultExt as _, ensure_whatever, whatever};
+
+use super::{Command, Output};
+use crate::{error::GenericError, rand};
+
+pub(super) const COMMAND: Command = Command {
+    name: "rand",
+    usage: "rand [bytes]",
+    description: "print random bytes from the kernel random number generator",
+    run,
+};
+
+/// Maximum number of bytes printed at once.
+const MAX_LEN: usize = 4096;
+
+fn run(out: &mut Output, args: &[&str]) -> Result<(), GenericError> {
+    let len = match args {
+        [] => 32,
+        [len] => len
+            .parse()
+            .with_whatever_context(|_| format!("invalid number `{len}`"))?,
+        _ => {
+            whatever!("invalid arguments\nusage: rand [bytes]");
+        }
+    };
+    ensure_whatever!(len <= MAX_LEN, "at most {MAX_LEN} bytes can be printed");
+    if !rand::is_seeded() {
+        writeln!(
+            out,
+            "warning: the generator is not seeded by an entropy source"
+        );
+    }
+    let mut buf = vec![0; len];
+    rand::fill(&mut buf);
+    for line in buf.chunks(32) {
+        for byte in line {
+            write!(out, "{byte:02x}");
+        }
+        writeln!(out);
+    }
+    Ok(())
+}
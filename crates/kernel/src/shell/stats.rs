@@ -0,0 +1,71 @@
+use alloc::{format, vec::Vec};
+
+use snafu::whatever;
+
+use super::{Command, Output};
+use crate::{
+    cpu,
+    error::GenericError,
+    interrupt::timer::{self, TimerStats},
+    stats::{self, Counter},
+};
+
+pub(super) const COMMAND: Command = Command {
+    name: "stats",
+    usage: "stats",
+    description: "show event counters of the CPUs",
+    run,
+};
+
+/// Name of a timer statistic, and the function to read it.
+type TimerCounter = (&'static str, fn(&TimerStats) -> u64);
+
+const TIMER_COUNTERS: &[TimerCounter] = &[
+    ("timer.irqs", |stats| stats.interrupts),
+    ("timer.spurious", |stats| stats.spurious_interrupts),
+    ("timer.ticks", |stats| stats.ticks),
+    ("timer.wakeups", |stats| stats.task_wakeups),
+    ("timer.tick_stop", |stats| stats.tick_stops),
+    ("timer.tick_start", |stats| stats.tick_restarts),
+];
+
+fn run(out: &mut Output, args: &[&str]) -> Result<(), GenericError> {
+    if !args.is_empty() {
+        whatever!("invalid arguments\nusage: stats");
+    }
+
+    write!(out, "{:<16} {:>12}", "counter", "total");
+    for cpu in cpu::get_all() {
+        write!(out, " {:>12}", format!("CPU#{}", cpu.id()));
+    }
+    writeln!(out);
+    for counter in Counter::ALL {
+        write!(out, "{:<16} {:>12}", counter.name(), stats::sum(counter));
+        for cpu in cpu::get_all() {
+            match stats::get(cpu.id(), counter) {
+                Some(count) => write!(out, " {count:>12}"),
+                None => write!(out, " {:>12}", "-"),
+            }
+        }
+        writeln!(out);
+    }
+    for &(name, get) in TIMER_COUNTERS {
+        let counts = cpu::get_all()
+            .iter()
+            .map(|cpu| timer::stats(cpu.id()).map(|stats| get(&stats)))
+            .collect::<Vec<_>>();
+        write!(
+            out,
+            "{name:<16} {:>12}",
+            counts.iter().flatten().sum::<u64>()
+        );
+        for count in counts {
+            match count {
+                Some(count) => write!(out, " {count:>12}"),
+                None => write!(out, " {:>12}", "-"),
+            }
+        }
+        writeln!(out);
+    }
+    Ok(())
+}
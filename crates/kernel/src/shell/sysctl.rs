@@ -0,0 +1,51 @@
+use alloc::format;
+
+use snafu::{OptionExt as _, ResultExt as _, whatever};
+
+use super::{Command, Output};
+use crate::{
+    error::GenericError,
+    tunables::{self, DisplayValue},
+};
+
+pub(super) const COMMAND: Command = Command {
+    name: "sysctl",
+    usage: "sysctl [name [value]]",
+    description: "show or set the runtime tunables",
+    run,
+};
+
+fn run(out: &mut Output, args: &[&str]) -> Result<(), GenericError> {
+    match args {
+        [] => {
+            for tunable in tunables::iter() {
+                writeln!(
+                    out,
+                    "{:<24} {:<12} {}",
+                    tunable.name(),
+                    DisplayValue(tunable),
+                    tunable.description()
+                );
+            }
+        }
+        [name] => {
+            let tunable = find(name)?;
+            writeln!(out, "{name} = {}", DisplayValue(tunable));
+        }
+        [name, value] => {
+            let tunable = find(name)?;
+            tunable
+                .set_str(value)
+                .with_whatever_context(|_| format!("cannot set {name}"))?;
+            writeln!(out, "{name} = {}", DisplayValue(tunable));
+        }
+        _ => {
+            whatever!("invalid arguments\nusage: sysctl [name [value]]");
+        }
+    }
+    Ok(())
+}
+
+fn find(name: &str) -> Result<&'static dyn tunables::DynTunable, GenericError> {
+    tunables::find(name.as_bytes()).with_whatever_context(|| format!("unknown tunable `{name}`"))
+}
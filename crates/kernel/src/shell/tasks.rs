@@ -0,0 +1,110 @@
+use alloc::{format, string::String};
+use core::time::Duration;
+
+use snafu::{OptionExt as _, ResultExt as _, ensure_whatever, whatever};
+
+use super::{Command, Output};
+use crate::{
+    cpu::{CpuSet, Cpuid},
+    error::GenericError,
+    task::{self, TaskId, scheduler},
+};
+
+pub(super) const COMMAND: Command = Command {
+    name: "tasks",
+    usage: "tasks [affinity <task> <cpuid>...]",
+    description: "show CPU time and context switches of the CPUs and tasks, or set CPU affinity",
+    run,
+};
+
+const USAGE: &str = "\
+usage: tasks
+       tasks affinity <task> <cpuid>...";
+
+fn run(out: &mut Output, args: &[&str]) -> Result<(), GenericError> {
+    match args {
+        [] => {
+            show(out);
+            Ok(())
+        }
+        ["affinity", task, cpuids @ ..] if !cpuids.is_empty() => set_affinity(task, cpuids),
+        _ => {
+            whatever!("invalid arguments\n{USAGE}");
+        }
+    }
+}
+
+fn set_affinity(task: &str, cpuids: &[&str]) -> Result<(), GenericError> {
+    let id = task
+        .parse()
+        .with_whatever_context(|_| format!("invalid task id `{task}`"))?;
+    let task = task::get(TaskId::from_raw(id)).with_whatever_context(|| format!("no task {id}"))?;
+    let affinity = cpuids
+        .iter()
+        .map(|cpuid| {
+            let cpuid = cpuid
+                .parse()
+                .with_whatever_context(|_| format!("invalid number `{cpuid}`"))?;
+            ensure_whatever!(
+                cpuid < CpuSet::CAPACITY,
+                "CPU#{cpuid} exceeds CPU set capacity"
+            );
+            Ok(Cpuid::from_raw(cpuid))
+        })
+        .collect::<Result<CpuSet, GenericError>>()?;
+    task::set_affinity(&task, affinity)
+}
+
+fn show(out: &mut Output) {
+    let stats = scheduler::stats();
+
+    writeln!(out, "uptime: {:?}", stats.uptime);
+    writeln!(
+        out,
+        "{:>6} {:>14} {:>6} {:>10} {:>8}",
+        "cpu", "idle", "idle%", "switches", "task"
+    );
+    for cpu in &stats.cpus {
+        let current_task = cpu
+            .current_task
+            .map_or_else(|| "-".into(), |task| format!("{task}"));
+        writeln!(
+            out,
+            "{:>6} {:>14} {:>6} {:>10} {:>8}",
+            format!("CPU#{}", cpu.cpuid),
+            format!("{:?}", cpu.idle_time),
+            percent(cpu.idle_time, stats.uptime),
+            cpu.switches,
+            current_task,
+        );
+    }
+
+    writeln!(out);
+    writeln!(
+        out,
+        "{:>6} {:<20} {:<10} {:>14} {:>14} {:>6} {:>10} affinity",
+        "task", "name", "state", "runtime", "user", "cpu%", "switches"
+    );
+    for task in &stats.tasks {
+        writeln!(
+            out,
+            "{:>6} {:<20} {:<10} {:>14} {:>14} {:>6} {:>10} {:?}",
+            task.id,
+            task.name.as_deref().unwrap_or("-"),
+            format!("{:?}", task.state),
+            format!("{:?}", task.runtime),
+            format!("{:?}", task.user_time),
+            percent(task.runtime, stats.uptime),
+            task.switches,
+            task.affinity,
+        );
+    }
+}
+
+fn percent(time: Duration, total: Duration) -> String {
+    if total.is_zero() {
+        return "-".into();
+    }
+    let permille = time.as_nanos() * 1000 / total.as_nanos();
+    format!("{}.{}", permille / 10, permille % 10)
+}
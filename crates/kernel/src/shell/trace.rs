@@ -0,0 +1,62 @@
+use alloc::{format, string::String};
+use core::fmt::Write as _;
+
+use snafu::{ResultExt as _, whatever};
+
+use super::{Command, Output};
+use crate::{
+    error::GenericError,
+    trace,
+    vfs::{self, FileType},
+};
+
+pub(super) const COMMAND: Command = Command {
+    name: "trace",
+    usage: "trace [on|off|clear|dump [path]]",
+    description: "control the kernel event trace or dump the recorded events",
+    run,
+};
+
+const USAGE: &str = "\
+usage: trace
+       trace on
+       trace off
+       trace clear
+       trace dump [path]";
+
+fn run(out: &mut Output, args: &[&str]) -> Result<(), GenericError> {
+    match args {
+        [] => {
+            let state = if trace::is_enabled() { "on" } else { "off" };
+            writeln!(out, "trace: {state}");
+        }
+        ["on"] => trace::set_enabled(true),
+        ["off"] => trace::set_enabled(false),
+        ["clear"] => trace::clear(),
+        ["dump"] => {
+            for record in trace::snapshot() {
+                writeln!(out, "{record}");
+            }
+        }
+        ["dump", path] => dump_to_file(out, path)?,
+        _ => {
+            whatever!("invalid arguments\n{USAGE}");
+        }
+    }
+    Ok(())
+}
+
+fn dump_to_file(out: &mut Output, path: &str) -> Result<(), GenericError> {
+    let records = trace::snapshot();
+    let mut text = String::new();
+    for record in &records {
+        writeln!(text, "{record}").unwrap();
+    }
+    let inode = vfs::create(path, FileType::Regular)
+        .with_whatever_context(|_| format!("cannot create {path}"))?;
+    inode
+        .write_at(0, text.as_bytes())
+        .with_whatever_context(|_| format!("cannot write {path}"))?;
+    writeln!(out, "{} events written to {path}", records.len());
+    Ok(())
+}
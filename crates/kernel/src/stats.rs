@@ -0,0 +1,123 @@
+//! Per-CPU event counters.
+//!
+//! Each CPU counts the events in its own counters by relaxed atomic
+//! increments, so the counters can be incremented in any context, including
+//! the interrupt handlers and the heap allocator, without locks. The counts of
+//! the CPUs are summed up when read.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::cpu::{self, Cpuid};
+
+cpu_local! {
+    static COUNTERS: [AtomicU64; Counter::ALL.len()] = [const { AtomicU64::new(0) }; _];
+}
+
+/// Event counted by each CPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Counter {
+    /// Supervisor software interrupts, i.e. the IPIs.
+    SoftwareInterrupts,
+    /// Supervisor timer interrupts.
+    TimerInterrupts,
+    /// Supervisor external interrupts, from the PLIC or the APLIC.
+    ExternalInterrupts,
+    /// Switches from the scheduler to the tasks.
+    ContextSwitches,
+    /// System calls from the user processes.
+    Syscalls,
+    /// Page faults in the kernel and the user processes.
+    PageFaults,
+    /// Allocations from the kernel heap.
+    HeapAllocs,
+}
+
+impl Counter {
+    pub const ALL: [Self; 7] = [
+        Self::SoftwareInterrupts,
+        Self::TimerInterrupts,
+        Self::ExternalInterrupts,
+        Self::ContextSwitches,
+        Self::Syscalls,
+        Self::PageFaults,
+        Self::HeapAllocs,
+    ];
+
+    #[cfg_attr(not(feature = "shell"), expect(dead_code))]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::SoftwareInterrupts => "irq.software",
+            Self::TimerInterrupts => "irq.timer",
+            Self::ExternalInterrupts => "irq.external",
+            Self::ContextSwitches => "sched.switches",
+            Self::Syscalls => "syscalls",
+            Self::PageFaults => "page_faults",
+            Self::HeapAllocs => "heap.allocs",
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Increments `counter` of the current CPU.
+///
+/// Does nothing before the per-CPU data of the current CPU is set up.
+pub fn incr(counter: Counter) {
+    if let Some(counters) = COUNTERS.try_get() {
+        counters[counter.index()].fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Returns the count of `counter` of the given CPU.
+#[cfg_attr(not(feature = "shell"), expect(dead_code))]
+pub fn get(cpuid: Cpuid, counter: Counter) -> Option<u64> {
+    let counters = COUNTERS.try_get_for(cpuid)?;
+    Some(counters[counter.index()].load(Ordering::Relaxed))
+}
+
+/// Returns the count of `counter` summed up over all the CPUs.
+#[cfg_attr(not(feature = "shell"), expect(dead_code))]
+pub fn sum(counter: Counter) -> u64 {
+    cpu::get_all()
+        .iter()
+        .filter_map(|cpu| get(cpu.id(), counter))
+        .sum()
+}
+
+#[cfg(feature = "ktest")]
+pub mod ktests {
+    use snafu::ensure_whatever;
+
+    use super::{Counter, get, incr, sum};
+    use crate::{cpu, error::GenericError, interrupt, ktest::KernelTest};
+
+    pub static TESTS: &[KernelTest] = kernel_tests![incr_counts_on_current_cpu];
+
+    fn incr_counts_on_current_cpu() -> Result<(), GenericError> {
+        // not incremented by the interrupt handlers
+        const COUNTER: Counter = Counter::Syscalls;
+
+        let interrupt_guard = interrupt::push_disabled();
+        let cpuid = cpu::current().id();
+        let before = get(cpuid, COUNTER);
+        let total_before = sum(COUNTER);
+        for _ in 0..3 {
+            incr(COUNTER);
+        }
+        let after = get(cpuid, COUNTER);
+        let total_after = sum(COUNTER);
+        interrupt_guard.pop();
+
+        ensure_whatever!(
+            after == before.map(|count| count + 3),
+            "count changed from {before:?} to {after:?}"
+        );
+        ensure_whatever!(
+            total_after >= total_before + 3,
+            "total changed from {total_before} to {total_after}"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,658 @@
+//! Queues passing data between the interrupt handlers and the tasks.
+//!
+//! The queues are lock-free, and the waiting tasks are woken up by a
+//! [`Notifier`], so that no spin lock is held while a task blocks.
+
+use alloc::{boxed::Box, sync::Arc};
+use core::{
+    cell::UnsafeCell,
+    fmt,
+    mem::MaybeUninit,
+    sync::atomic::{self, AtomicBool, AtomicUsize, Ordering},
+};
+
+use super::spinlock::{SpinMutex, SpinMutexCondVar};
+
+/// Wakes up the tasks waiting for a condition that changes without a lock.
+#[derive(Debug)]
+pub struct Notifier {
+    /// Orders the condition checks of the waiters before the notifications.
+    lock: SpinMutex<()>,
+    changed: SpinMutexCondVar,
+    waiters: AtomicUsize,
+}
+
+impl Notifier {
+    pub const fn new() -> Self {
+        Self {
+            lock: SpinMutex::new(()),
+            changed: SpinMutexCondVar::new(),
+            waiters: AtomicUsize::new(0),
+        }
+    }
+
+    /// Blocks the current task until `poll` returns `Some`.
+    ///
+    /// `poll` is called again after each notification. It is called with the
+    /// interrupts disabled, so it must not block.
+    pub fn wait_until<F, R>(&self, mut poll: F) -> R
+    where
+        F: FnMut() -> Option<R>,
+    {
+        let mut guard = self.lock.lock();
+        self.waiters.fetch_add(1, Ordering::Relaxed);
+        // pairs with the fence in `notify_all`, so that either the waiter sees
+        // the change or the notifier sees the waiter
+        atomic::fence(Ordering::SeqCst);
+        let res = loop {
+            if let Some(res) = poll() {
+                break res;
+            }
+            guard = self.changed.wait(guard);
+        };
+        self.waiters.fetch_sub(1, Ordering::Relaxed);
+        guard.unlock();
+        res
+    }
+
+    /// Wakes up all the waiting tasks, after the condition has changed.
+    ///
+    /// This can be called from the interrupt handlers.
+    pub fn notify_all(&self) {
+        atomic::fence(Ordering::SeqCst);
+        if self.waiters.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+        // a waiter that checked the condition before the change is waiting
+        // once the lock is released
+        self.lock.lock().unlock();
+        self.changed.notify_all();
+    }
+}
+
+/// Bounded ring buffer with a single producer and a single consumer.
+///
+/// The producer and the consumer are claimed by [`SpscRing::try_producer`] and
+/// [`SpscRing::try_consumer`], and they push and pop without locks. This fits
+/// an interrupt handler passing bytes to the reading tasks.
+pub struct SpscRing<T, const N: usize> {
+    slots: [UnsafeCell<MaybeUninit<T>>; N],
+    /// Number of the popped values.
+    head: AtomicUsize,
+    /// Number of the pushed values.
+    tail: AtomicUsize,
+    producer_claimed: AtomicBool,
+    consumer_claimed: AtomicBool,
+}
+
+unsafe impl<T, const N: usize> Sync for SpscRing<T, N> where T: Send {}
+
+impl<T, const N: usize> fmt::Debug for SpscRing<T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SpscRing")
+            .field("len", &self.len())
+            .field("capacity", &N)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T, const N: usize> SpscRing<T, N> {
+    pub const fn new() -> Self {
+        assert!(N > 0, "empty ring buffer");
+        Self {
+            slots: [const { UnsafeCell::new(MaybeUninit::uninit()) }; N],
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            producer_claimed: AtomicBool::new(false),
+            consumer_claimed: AtomicBool::new(false),
+        }
+    }
+
+    /// Returns the number of the buffered values.
+    ///
+    /// The value may be outdated by the other side.
+    pub fn len(&self) -> usize {
+        let head = self.head.load(Ordering::Acquire);
+        let tail = self.tail.load(Ordering::Acquire);
+        tail.wrapping_sub(head)
+    }
+
+    #[expect(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Claims the producer side, or returns `None` if it is claimed already.
+    pub fn try_producer(&self) -> Option<SpscProducer<'_, T, N>> {
+        (!self.producer_claimed.swap(true, Ordering::Acquire))
+            .then_some(SpscProducer { ring: self })
+    }
+
+    /// Claims the consumer side, or returns `None` if it is claimed already.
+    pub fn try_consumer(&self) -> Option<SpscConsumer<'_, T, N>> {
+        (!self.consumer_claimed.swap(true, Ordering::Acquire))
+            .then_some(SpscConsumer { ring: self })
+    }
+
+    fn slot(&self, index: usize) -> *mut MaybeUninit<T> {
+        self.slots[index % N].get()
+    }
+}
+
+impl<T, const N: usize> Default for SpscRing<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> Drop for SpscRing<T, N> {
+    fn drop(&mut self) {
+        let head = *self.head.get_mut();
+        let tail = *self.tail.get_mut();
+        for index in head..tail {
+            unsafe {
+                (*self.slot(index)).assume_init_drop();
+            }
+        }
+    }
+}
+
+/// Producer side of a [`SpscRing`], released on drop.
+#[derive(Debug)]
+pub struct SpscProducer<'a, T, const N: usize> {
+    ring: &'a SpscRing<T, N>,
+}
+
+impl<T, const N: usize> SpscProducer<'_, T, N> {
+    /// Pushes `value`, or returns it back if the ring is full.
+    // the exclusive borrow keeps the producer to a single context
+    #[expect(clippy::needless_pass_by_ref_mut)]
+    pub fn push(&mut self, value: T) -> Result<(), T> {
+        let ring = self.ring;
+        let tail = ring.tail.load(Ordering::Relaxed);
+        let head = ring.head.load(Ordering::Acquire);
+        if tail.wrapping_sub(head) == N {
+            return Err(value);
+        }
+        unsafe {
+            (*ring.slot(tail)).write(value);
+        }
+        ring.tail.store(tail.wrapping_add(1), Ordering::Release);
+        Ok(())
+    }
+}
+
+impl<T, const N: usize> Drop for SpscProducer<'_, T, N> {
+    fn drop(&mut self) {
+        self.ring.producer_claimed.store(false, Ordering::Release);
+    }
+}
+
+/// Consumer side of a [`SpscRing`], released on drop.
+#[derive(Debug)]
+pub struct SpscConsumer<'a, T, const N: usize> {
+    ring: &'a SpscRing<T, N>,
+}
+
+impl<T, const N: usize> SpscConsumer<'_, T, N> {
+    /// Pops the oldest value, or returns `None` if the ring is empty.
+    #[expect(clippy::needless_pass_by_ref_mut)]
+    pub fn pop(&mut self) -> Option<T> {
+        let ring = self.ring;
+        let head = ring.head.load(Ordering::Relaxed);
+        let tail = ring.tail.load(Ordering::Acquire);
+        if head == tail {
+            return None;
+        }
+        let value = unsafe { (*ring.slot(head)).assume_init_read() };
+        ring.head.store(head.wrapping_add(1), Ordering::Release);
+        Some(value)
+    }
+
+    /// Pops the oldest values into `values`, and returns the number of them.
+    pub fn pop_slice(&mut self, values: &mut [T]) -> usize {
+        let mut count = 0;
+        for dst in values {
+            let Some(value) = self.pop() else {
+                break;
+            };
+            *dst = value;
+            count += 1;
+        }
+        count
+    }
+}
+
+impl<T, const N: usize> Drop for SpscConsumer<'_, T, N> {
+    fn drop(&mut self) {
+        self.ring.consumer_claimed.store(false, Ordering::Release);
+    }
+}
+
+/// Error of [`Sender::try_send`], returning the value not sent.
+#[cfg_attr(not(feature = "ktest"), expect(dead_code))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrySendError<T> {
+    Full(T),
+    /// The receiver is dropped.
+    Disconnected(T),
+}
+
+/// Error of [`Receiver::try_recv`].
+#[cfg_attr(not(feature = "ktest"), expect(dead_code))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryRecvError {
+    Empty,
+    /// All the senders are dropped, and no value is left.
+    Disconnected,
+}
+
+/// Creates a bounded channel with multiple senders and a single receiver.
+///
+/// The values are queued without locks, so the senders may also be used in
+/// the interrupt handlers with [`Sender::try_send`].
+#[cfg_attr(not(feature = "ktest"), expect(dead_code))]
+pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>)
+where
+    T: Send,
+{
+    assert!(capacity > 0, "empty channel");
+    let shared = Arc::new(Shared {
+        slots: (0..capacity)
+            .map(|index| Slot {
+                seq: AtomicUsize::new(index),
+                value: UnsafeCell::new(MaybeUninit::uninit()),
+            })
+            .collect(),
+        head: AtomicUsize::new(0),
+        tail: AtomicUsize::new(0),
+        senders: AtomicUsize::new(1),
+        receiver_alive: AtomicBool::new(true),
+        not_empty: Notifier::new(),
+        not_full: Notifier::new(),
+    });
+    (
+        Sender {
+            shared: Arc::clone(&shared),
+        },
+        Receiver { shared },
+    )
+}
+
+#[cfg_attr(not(feature = "ktest"), expect(dead_code))]
+struct Slot<T> {
+    /// Position of the next push to the slot, or that plus one if the slot
+    /// holds the value of that push.
+    seq: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+/// Bounded queue with the per-slot sequence numbers of Dmitry Vyukov, popped
+/// only by the receiver.
+#[cfg_attr(not(feature = "ktest"), expect(dead_code))]
+struct Shared<T> {
+    slots: Box<[Slot<T>]>,
+    /// Position of the next pop.
+    head: AtomicUsize,
+    /// Position of the next push.
+    tail: AtomicUsize,
+    senders: AtomicUsize,
+    receiver_alive: AtomicBool,
+    not_empty: Notifier,
+    not_full: Notifier,
+}
+
+unsafe impl<T> Send for Shared<T> where T: Send {}
+unsafe impl<T> Sync for Shared<T> where T: Send {}
+
+#[cfg_attr(not(feature = "ktest"), expect(dead_code))]
+impl<T> Shared<T> {
+    fn slot(&self, pos: usize) -> &Slot<T> {
+        &self.slots[pos % self.slots.len()]
+    }
+
+    fn push(&self, value: T) -> Result<(), T> {
+        let mut pos = self.tail.load(Ordering::Relaxed);
+        loop {
+            let slot = self.slot(pos);
+            let seq = slot.seq.load(Ordering::Acquire);
+            #[expect(clippy::cast_possible_wrap)]
+            let diff = seq.wrapping_sub(pos) as isize;
+            if diff < 0 {
+                return Err(value);
+            }
+            if diff > 0 {
+                // another sender has pushed to the position
+                pos = self.tail.load(Ordering::Relaxed);
+                continue;
+            }
+            match self.tail.compare_exchange_weak(
+                pos,
+                pos.wrapping_add(1),
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => {
+                    unsafe {
+                        (*slot.value.get()).write(value);
+                    }
+                    slot.seq.store(pos.wrapping_add(1), Ordering::Release);
+                    return Ok(());
+                }
+                Err(current) => pos = current,
+            }
+        }
+    }
+
+    fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        if !self.receiver_alive.load(Ordering::Acquire) {
+            return Err(TrySendError::Disconnected(value));
+        }
+        self.push(value).map_err(TrySendError::Full)
+    }
+
+    /// Must be called only by the receiver.
+    fn try_recv(&self) -> Result<T, TryRecvError> {
+        if let Some(value) = self.pop() {
+            return Ok(value);
+        }
+        // the values sent before the last sender is dropped are received
+        // first
+        if self.senders.load(Ordering::Acquire) == 0 {
+            return self.pop().ok_or(TryRecvError::Disconnected);
+        }
+        Err(TryRecvError::Empty)
+    }
+
+    /// Pops the oldest value. Must be called only by the receiver.
+    fn pop(&self) -> Option<T> {
+        let pos = self.head.load(Ordering::Relaxed);
+        let slot = self.slot(pos);
+        if slot.seq.load(Ordering::Acquire) != pos.wrapping_add(1) {
+            return None;
+        }
+        let value = unsafe { (*slot.value.get()).assume_init_read() };
+        slot.seq
+            .store(pos.wrapping_add(self.slots.len()), Ordering::Release);
+        self.head.store(pos.wrapping_add(1), Ordering::Relaxed);
+        Some(value)
+    }
+}
+
+impl<T> Drop for Shared<T> {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
+
+/// Sending side of a [`channel`].
+#[cfg_attr(not(feature = "ktest"), expect(dead_code))]
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sender").finish_non_exhaustive()
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.senders.fetch_add(1, Ordering::Relaxed);
+        Self {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        if self.shared.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.shared.not_empty.notify_all();
+        }
+    }
+}
+
+#[cfg_attr(not(feature = "ktest"), expect(dead_code))]
+impl<T> Sender<T> {
+    /// Sends `value` without waiting.
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        self.shared.try_send(value)?;
+        self.shared.not_empty.notify_all();
+        Ok(())
+    }
+
+    /// Sends `value`, waiting while the channel is full.
+    ///
+    /// Returns the value back if the receiver is dropped. This must not be
+    /// called from the interrupt handlers.
+    pub fn send(&self, value: T) -> Result<(), T> {
+        let mut value = Some(value);
+        // the receiver is notified after the wait, as it notifies the senders
+        // in the reverse order
+        self.shared
+            .not_full
+            .wait_until(|| match self.shared.try_send(value.take().unwrap()) {
+                Ok(()) => Some(Ok(())),
+                Err(TrySendError::Disconnected(v)) => Some(Err(v)),
+                Err(TrySendError::Full(v)) => {
+                    value = Some(v);
+                    None
+                }
+            })?;
+        self.shared.not_empty.notify_all();
+        Ok(())
+    }
+}
+
+/// Receiving side of a [`channel`].
+#[cfg_attr(not(feature = "ktest"), expect(dead_code))]
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Receiver").finish_non_exhaustive()
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.shared.receiver_alive.store(false, Ordering::Release);
+        self.shared.not_full.notify_all();
+    }
+}
+
+#[cfg_attr(not(feature = "ktest"), expect(dead_code))]
+impl<T> Receiver<T> {
+    /// Receives the oldest value without waiting.
+    // the exclusive borrow keeps the receiver to a single context
+    #[expect(clippy::needless_pass_by_ref_mut)]
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let value = self.shared.try_recv()?;
+        self.shared.not_full.notify_all();
+        Ok(value)
+    }
+
+    /// Receives the oldest value, waiting while the channel is empty.
+    ///
+    /// Returns `None` if all the senders are dropped and no value is left.
+    /// This must not be called from the interrupt handlers.
+    #[expect(clippy::needless_pass_by_ref_mut)]
+    pub fn recv(&mut self) -> Option<T> {
+        let shared = &*self.shared;
+        let value = shared.not_empty.wait_until(|| match shared.try_recv() {
+            Ok(value) => Some(Some(value)),
+            Err(TryRecvError::Disconnected) => Some(None),
+            Err(TryRecvError::Empty) => None,
+        })?;
+        shared.not_full.notify_all();
+        Some(value)
+    }
+}
+
+#[cfg(feature = "ktest")]
+pub mod ktests {
+    use alloc::vec::Vec;
+    use core::{sync::atomic::Ordering, time::Duration};
+
+    use snafu::{OptionExt as _, ensure_whatever};
+
+    use super::{SpscRing, TryRecvError, TrySendError, channel};
+    use crate::{
+        error::GenericError,
+        interrupt::timer::{self, Instant},
+        ktest::KernelTest,
+        task::kthread,
+    };
+
+    pub static TESTS: &[KernelTest] = kernel_tests![
+        spsc_ring_wraps_around,
+        channel_multiple_senders,
+        channel_try_send_full,
+        channel_disconnect,
+        channel_recv_woken_by_send,
+    ];
+
+    fn spsc_ring_wraps_around() -> Result<(), GenericError> {
+        let ring = SpscRing::<u32, 4>::new();
+        let mut producer = ring
+            .try_producer()
+            .whatever_context("producer not claimed")?;
+        let mut consumer = ring
+            .try_consumer()
+            .whatever_context("consumer not claimed")?;
+        ensure_whatever!(ring.try_producer().is_none(), "producer claimed twice");
+
+        for round in 0..3 {
+            for i in 0..4 {
+                ensure_whatever!(producer.push(round * 4 + i).is_ok(), "ring full early");
+            }
+            ensure_whatever!(producer.push(0).is_err(), "ring not full");
+            for i in 0..4 {
+                let value = consumer.pop();
+                ensure_whatever!(value == Some(round * 4 + i), "popped {value:?}");
+            }
+            ensure_whatever!(consumer.pop().is_none(), "ring not empty");
+        }
+        Ok(())
+    }
+
+    fn channel_multiple_senders() -> Result<(), GenericError> {
+        const SENDERS: usize = 4;
+        const VALUES: usize = 64;
+
+        // the capacity is smaller than the values, so that the senders wait
+        let (tx, mut rx) = channel(4);
+        let handles = (0..SENDERS)
+            .map(|sender| {
+                let tx = tx.clone();
+                kthread::Builder::new()
+                    .name("ktest-channel")
+                    .spawn(move || {
+                        for value in 0..VALUES {
+                            if tx.send((sender, value)).is_err() {
+                                return false;
+                            }
+                        }
+                        true
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        drop(tx);
+
+        let mut next = [0; SENDERS];
+        while let Some((sender, value)) = rx.recv() {
+            ensure_whatever!(
+                value == next[sender],
+                "received {value} from sender {sender}, expected {}",
+                next[sender]
+            );
+            next[sender] += 1;
+        }
+        for (sender, handle) in handles.into_iter().enumerate() {
+            ensure_whatever!(handle.join(), "sender {sender} disconnected");
+        }
+        ensure_whatever!(
+            next.iter().all(|&count| count == VALUES),
+            "received {next:?} values"
+        );
+        Ok(())
+    }
+
+    fn channel_try_send_full() -> Result<(), GenericError> {
+        let (tx, mut rx) = channel(2);
+        ensure_whatever!(tx.try_send(1).is_ok(), "channel full early");
+        ensure_whatever!(tx.try_send(2).is_ok(), "channel full early");
+        let res = tx.try_send(3);
+        ensure_whatever!(
+            res == Err(TrySendError::Full(3)),
+            "sent to full channel: {res:?}"
+        );
+
+        let res = rx.try_recv();
+        ensure_whatever!(res == Ok(1), "received {res:?}");
+        ensure_whatever!(tx.try_send(3).is_ok(), "channel full after receive");
+        for expected in [2, 3] {
+            let res = rx.try_recv();
+            ensure_whatever!(res == Ok(expected), "received {res:?}");
+        }
+        let res = rx.try_recv();
+        ensure_whatever!(res == Err(TryRecvError::Empty), "received {res:?}");
+        Ok(())
+    }
+
+    fn channel_disconnect() -> Result<(), GenericError> {
+        // the values sent before the last sender is dropped are received
+        let (tx, mut rx) = channel(4);
+        let tx2 = tx.clone();
+        ensure_whatever!(tx.try_send(1).is_ok(), "channel full early");
+        drop(tx);
+        ensure_whatever!(tx2.try_send(2).is_ok(), "channel full early");
+        drop(tx2);
+        for expected in [1, 2] {
+            let res = rx.try_recv();
+            ensure_whatever!(res == Ok(expected), "received {res:?}");
+        }
+        let res = rx.try_recv();
+        ensure_whatever!(res == Err(TryRecvError::Disconnected), "received {res:?}");
+        ensure_whatever!(rx.recv().is_none(), "received from disconnected channel");
+
+        let (tx, rx) = channel(1);
+        drop(rx);
+        let res = tx.try_send(1);
+        ensure_whatever!(
+            res == Err(TrySendError::Disconnected(1)),
+            "sent to disconnected channel: {res:?}"
+        );
+        let res = tx.send(2);
+        ensure_whatever!(res == Err(2), "sent to disconnected channel: {res:?}");
+        Ok(())
+    }
+
+    fn channel_recv_woken_by_send() -> Result<(), GenericError> {
+        const TIMEOUT: Duration = Duration::from_secs(1);
+
+        let (tx, mut rx) = channel(1);
+        let handle = kthread::Builder::new()
+            .name("ktest-channel")
+            .spawn(move || rx.recv())?;
+
+        let start = Instant::now();
+        while tx.shared.not_empty.waiters.load(Ordering::Relaxed) == 0 {
+            ensure_whatever!(
+                start.elapsed() < TIMEOUT,
+                "receiver not blocked in {TIMEOUT:?}"
+            );
+            timer::sleep(Duration::from_millis(1));
+        }
+        ensure_whatever!(!handle.is_finished(), "receiver returned without a value");
+
+        ensure_whatever!(tx.send(42).is_ok(), "receiver disconnected");
+        let value = handle.join();
+        ensure_whatever!(value == Some(42), "received {value:?}");
+        Ok(())
+    }
+}
@@ -1 +1,4 @@
+pub mod channel;
+pub mod mutex;
+pub mod rcu;
 pub mod spinlock;
@@ -0,0 +1,76 @@
+use core::{
+    cell::UnsafeCell,
+    fmt,
+    ops::{Deref, DerefMut},
+};
+
+use super::spinlock::{SpinMutex, SpinMutexCondVar};
+
+/// Mutex that blocks the current task while another task holds the lock.
+///
+/// Unlike [`SpinMutex`], interrupts stay enabled while the lock is held, so the
+/// holder can wait for I/O completion. It must not be locked in the interrupt
+/// context.
+pub struct Mutex<T> {
+    locked: SpinMutex<bool>,
+    unlocked: SpinMutexCondVar,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T> Sync for Mutex<T> where T: Send {}
+
+impl<T> fmt::Debug for Mutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Mutex")
+            .field("locked", &*self.locked.lock())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> Mutex<T> {
+    pub const fn new(data: T) -> Self {
+        Self {
+            locked: SpinMutex::new(false),
+            unlocked: SpinMutexCondVar::new(),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        let mut locked = self.locked.lock();
+        while *locked {
+            locked = self.unlocked.wait(locked);
+        }
+        *locked = true;
+        locked.unlock();
+        MutexGuard { mutex: self }
+    }
+}
+
+pub struct MutexGuard<'a, T> {
+    mutex: &'a Mutex<T>,
+}
+
+impl<T> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        let mut locked = self.mutex.locked.lock();
+        assert!(*locked, "MutexGuard dropped without holding the lock");
+        *locked = false;
+        locked.unlock();
+        self.mutex.unlocked.notify_one();
+    }
+}
+
+impl<T> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
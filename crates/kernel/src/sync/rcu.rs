@@ -0,0 +1,231 @@
+//! Read-copy-update for the data that is mostly read.
+//!
+//! The readers access the current version without locks, with the interrupts
+//! disabled. The updaters publish a modified copy, and the old version is
+//! freed after a grace period, in which every CPU has passed a quiescent
+//! state. The scheduler of each CPU reports a quiescent state between the
+//! tasks, as no reader can be running then. Idle CPUs are quiescent until they
+//! wake up, so they do not delay the grace periods.
+//!
+//! The CPUs that have not started the scheduler may be running readers, so
+//! no old version is freed until all CPUs have started it. A stopped CPU is
+//! quiescent until it is restarted.
+
+use alloc::{boxed::Box, vec::Vec};
+#[cfg(debug_assertions)]
+use core::sync::atomic::AtomicUsize;
+use core::{
+    convert::Infallible,
+    fmt, mem,
+    ops::Deref,
+    ptr,
+    sync::atomic::{AtomicPtr, AtomicU64, Ordering},
+};
+
+use super::spinlock::SpinMutex;
+use crate::{
+    cpu::{self, Cpuid, hotplug::HotplugHook},
+    interrupt::{self, InterruptGuard},
+};
+
+/// Epoch that the retirements advance.
+static EPOCH: AtomicU64 = AtomicU64::new(1);
+
+/// Quiescent epoch meaning the CPU is not running any reader.
+const ALWAYS_QUIESCENT: u64 = u64::MAX;
+
+/// Old versions waiting for their grace periods, with their epochs.
+static RETIRED: SpinMutex<Vec<(u64, Box<dyn Send>)>> = SpinMutex::new(Vec::new());
+
+cpu_local! {
+    /// Epoch of the last quiescent state of the CPU, or zero if none.
+    static QUIESCENT_EPOCH: AtomicU64 = AtomicU64::new(0);
+    #[cfg(debug_assertions)]
+    static READ_DEPTH: AtomicUsize = AtomicUsize::new(0);
+}
+
+/// Reports that the current CPU is not running any reader, and frees the old
+/// versions whose grace periods have ended.
+pub fn quiescent_state() {
+    #[cfg(debug_assertions)]
+    assert_eq!(
+        READ_DEPTH.get().load(Ordering::Relaxed),
+        0,
+        "RCU read-side critical section held across a quiescent state"
+    );
+    QUIESCENT_EPOCH
+        .get()
+        .store(EPOCH.load(Ordering::SeqCst), Ordering::SeqCst);
+    reclaim();
+}
+
+/// Reports that the current CPU stays quiescent until [`exit_idle`].
+pub fn enter_idle() {
+    QUIESCENT_EPOCH
+        .get()
+        .store(ALWAYS_QUIESCENT, Ordering::SeqCst);
+}
+
+pub fn exit_idle() {
+    quiescent_state();
+}
+
+pub static HOTPLUG_HOOK: HotplugHook = HotplugHook {
+    name: "rcu",
+    online: || {},
+    offline: enter_idle,
+};
+
+/// Makes the versions retired from now on wait for the stopped CPU, before
+/// the CPU is restarted.
+pub fn prepare_online(cpuid: Cpuid) {
+    QUIESCENT_EPOCH
+        .try_get_for(cpuid)
+        .unwrap()
+        .store(EPOCH.load(Ordering::SeqCst), Ordering::SeqCst);
+}
+
+fn retire(old: Box<dyn Send>) {
+    let epoch = EPOCH.fetch_add(1, Ordering::SeqCst) + 1;
+    RETIRED.lock().push((epoch, old));
+}
+
+fn reclaim() {
+    let Some(mut retired) = RETIRED.try_lock() else {
+        return;
+    };
+    if retired.is_empty() {
+        return;
+    }
+    let completed = cpu::get_all()
+        .iter()
+        .filter_map(|cpu| QUIESCENT_EPOCH.try_get_for(cpu.id()))
+        .map(|epoch| epoch.load(Ordering::SeqCst))
+        .min()
+        .unwrap_or(ALWAYS_QUIESCENT);
+    let (expired, pending) = mem::take(&mut *retired)
+        .into_iter()
+        .partition::<Vec<_>, _>(|(epoch, _old)| *epoch <= completed);
+    *retired = pending;
+    retired.unlock();
+    drop(expired);
+}
+
+/// Value read by the interrupt handlers without locks, and updated by copying.
+pub struct Rcu<T> {
+    /// Version before the first update.
+    initial: T,
+    /// Version published by the last update, or null if not updated yet.
+    current: AtomicPtr<T>,
+    update_lock: SpinMutex<()>,
+}
+
+unsafe impl<T> Send for Rcu<T> where T: Send + Sync {}
+unsafe impl<T> Sync for Rcu<T> where T: Send + Sync {}
+
+impl<T> fmt::Debug for Rcu<T>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Rcu").field(&&*self.read()).finish()
+    }
+}
+
+impl<T> Rcu<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            initial: value,
+            current: AtomicPtr::new(ptr::null_mut()),
+            update_lock: SpinMutex::new(()),
+        }
+    }
+
+    /// Returns the current version.
+    ///
+    /// The interrupts are disabled until the guard is dropped, and the current
+    /// task must not block meanwhile.
+    pub fn read(&self) -> RcuReadGuard<'_, T> {
+        let interrupt_guard = interrupt::push_disabled();
+        #[cfg(debug_assertions)]
+        if let Some(depth) = READ_DEPTH.try_get() {
+            depth.fetch_add(1, Ordering::Relaxed);
+        }
+        let current = self.current.load(Ordering::Acquire);
+        let value = unsafe { current.as_ref() }.unwrap_or(&self.initial);
+        RcuReadGuard {
+            value,
+            _interrupt_guard: interrupt_guard,
+        }
+    }
+}
+
+impl<T> Rcu<T>
+where
+    T: Clone + Send + 'static,
+{
+    /// Publishes a copy of the current version modified by `f`.
+    pub fn update<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        let Ok(res) = self.try_update(|value| Ok::<_, Infallible>(f(value)));
+        res
+    }
+
+    /// Publishes a copy of the current version modified by `f`, unless `f`
+    /// fails.
+    ///
+    /// The updates are serialized, and `f` is called with the interrupts
+    /// disabled.
+    pub fn try_update<F, R, E>(&self, f: F) -> Result<R, E>
+    where
+        F: FnOnce(&mut T) -> Result<R, E>,
+    {
+        let guard = self.update_lock.lock();
+        let old = self.current.load(Ordering::Relaxed);
+        let mut value = unsafe { old.as_ref() }.unwrap_or(&self.initial).clone();
+        let res = f(&mut value);
+        if res.is_ok() {
+            let new = Box::into_raw(Box::new(value));
+            self.current.store(new, Ordering::SeqCst);
+            if !old.is_null() {
+                retire(unsafe { Box::from_raw(old) });
+            }
+        }
+        guard.unlock();
+        res
+    }
+}
+
+impl<T> Drop for Rcu<T> {
+    fn drop(&mut self) {
+        let current = *self.current.get_mut();
+        if !current.is_null() {
+            drop(unsafe { Box::from_raw(current) });
+        }
+    }
+}
+
+/// Version of an [`Rcu`] kept until the guard is dropped.
+pub struct RcuReadGuard<'a, T> {
+    value: &'a T,
+    _interrupt_guard: InterruptGuard,
+}
+
+impl<T> Deref for RcuReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        self.value
+    }
+}
+
+impl<T> Drop for RcuReadGuard<'_, T> {
+    fn drop(&mut self) {
+        #[cfg(debug_assertions)]
+        if let Some(depth) = READ_DEPTH.try_get() {
+            depth.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
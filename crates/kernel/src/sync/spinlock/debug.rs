@@ -0,0 +1,181 @@
+use core::{
+    fmt,
+    panic::Location,
+    ptr,
+    sync::atomic::{AtomicPtr, AtomicU64, AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use crate::{
+    cpu::{self, Cpuid},
+    interrupt::timer::{self, Instant},
+    task::{TaskId, scheduler},
+};
+
+/// Time a CPU may spin on a lock before the lock is considered deadlocked.
+const SPIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Number of spins between the timeout checks.
+const SPINS_PER_CHECK: u32 = 1 << 16;
+
+cpu_local! {
+    /// Orders of the lock classes held by the CPU, one bit per order.
+    static HELD_CLASSES: AtomicU64 = AtomicU64::new(0);
+}
+
+/// Ordering class of the locks.
+///
+/// A lock with a class may only be acquired while the CPU holds no lock whose
+/// class has the same or a greater order. Locks acquired by `try_lock` are not
+/// checked, as they never wait, but they still count as held.
+#[derive(Debug)]
+pub struct LockClass {
+    name: &'static str,
+    order: u32,
+}
+
+impl LockClass {
+    pub const fn new(name: &'static str, order: u32) -> Self {
+        assert!(order < u64::BITS, "lock class order out of range");
+        Self { name, order }
+    }
+
+    fn bit(&self) -> u64 {
+        1 << self.order
+    }
+}
+
+/// Owner of a lock, recorded to diagnose deadlocks.
+#[derive(Debug)]
+pub(super) struct DebugState {
+    class: Option<&'static LockClass>,
+    /// Cpuid of the owner plus one, or zero if not locked.
+    owner_cpu: AtomicUsize,
+    owner_task: AtomicU64,
+    locked_at: AtomicPtr<Location<'static>>,
+}
+
+impl DebugState {
+    pub(super) const fn new(class: Option<&'static LockClass>) -> Self {
+        Self {
+            class,
+            owner_cpu: AtomicUsize::new(0),
+            owner_task: AtomicU64::new(TaskId::INVALID.value()),
+            locked_at: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    /// Checks that the current CPU may wait for the lock.
+    pub(super) fn check_lock(&self, caller: &Location<'_>) {
+        if let Some(cpu) = cpu::try_current()
+            && self.owner_cpu.load(Ordering::Relaxed) == cpu.id().value() + 1
+        {
+            panic!(
+                "recursive acquisition of spin lock at {caller}, {}",
+                self.describe_owner()
+            );
+        }
+
+        let Some(class) = self.class else {
+            return;
+        };
+        let Some(held) = HELD_CLASSES.try_get() else {
+            return;
+        };
+        let conflicting = held.load(Ordering::Relaxed) & !(class.bit() - 1);
+        assert!(
+            conflicting == 0,
+            "lock order violation at {caller}, `{}` (order {}) acquired while holding a lock of \
+             order {}",
+            class.name,
+            class.order,
+            u64::BITS - 1 - conflicting.leading_zeros(),
+        );
+    }
+
+    /// Panics if the current CPU has been waiting for the lock for too long.
+    pub(super) fn check_spin(
+        &self,
+        caller: &Location<'_>,
+        spins: &mut u32,
+        start: &mut Option<Instant>,
+    ) {
+        *spins += 1;
+        if *spins < SPINS_PER_CHECK {
+            return;
+        }
+        *spins = 0;
+        let Some(now) = timer::try_now() else {
+            return;
+        };
+        let start = *start.get_or_insert(now);
+        assert!(
+            now.duration_since(start) < SPIN_TIMEOUT,
+            "spin lock not acquired in {SPIN_TIMEOUT:?} at {caller}, {}",
+            self.describe_owner()
+        );
+    }
+
+    pub(super) fn on_acquire(&self, caller: &'static Location<'static>) {
+        let cpuid = cpu::try_current().map_or(0, |cpu| cpu.id().value() + 1);
+        let task = scheduler::try_current_task_id().unwrap_or(TaskId::INVALID);
+        self.owner_cpu.store(cpuid, Ordering::Relaxed);
+        self.owner_task.store(task.value(), Ordering::Relaxed);
+        self.locked_at
+            .store(ptr::from_ref(caller).cast_mut(), Ordering::Relaxed);
+        if let Some(class) = self.class
+            && let Some(held) = HELD_CLASSES.try_get()
+        {
+            held.fetch_or(class.bit(), Ordering::Relaxed);
+        }
+    }
+
+    pub(super) fn on_release(&self) {
+        if let Some(class) = self.class
+            && let Some(held) = HELD_CLASSES.try_get()
+        {
+            held.fetch_and(!class.bit(), Ordering::Relaxed);
+        }
+        self.owner_cpu.store(0, Ordering::Relaxed);
+        self.owner_task
+            .store(TaskId::INVALID.value(), Ordering::Relaxed);
+    }
+
+    fn describe_owner(&self) -> OwnerDisplay {
+        let cpu = match self.owner_cpu.load(Ordering::Relaxed) {
+            0 => None,
+            id => Some(Cpuid::from_raw(id - 1)),
+        };
+        let task = Some(TaskId::from_raw(self.owner_task.load(Ordering::Relaxed)))
+            .filter(|task| *task != TaskId::INVALID);
+        let locked_at = unsafe { self.locked_at.load(Ordering::Relaxed).as_ref() };
+        OwnerDisplay {
+            cpu,
+            task,
+            locked_at,
+        }
+    }
+}
+
+struct OwnerDisplay {
+    cpu: Option<Cpuid>,
+    task: Option<TaskId>,
+    locked_at: Option<&'static Location<'static>>,
+}
+
+impl fmt::Display for OwnerDisplay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "held by ")?;
+        match self.cpu {
+            Some(cpu) => write!(f, "CPU#{cpu}")?,
+            None => write!(f, "unknown CPU")?,
+        }
+        if let Some(task) = self.task {
+            write!(f, ", task {task}")?;
+        }
+        if let Some(locked_at) = self.locked_at {
+            write!(f, ", locked at {locked_at}")?;
+        }
+        Ok(())
+    }
+}
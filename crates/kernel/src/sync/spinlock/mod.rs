@@ -10,15 +10,57 @@ use core::{
     sync::atomic::{AtomicBool, AtomicU64, Ordering},
 };
 
+#[cfg(debug_assertions)]
+use self::debug::DebugState;
+pub use self::debug_class::LockClass;
 use crate::{
     interrupt::{self, InterruptGuard},
     task::{self, Task, scheduler},
 };
 
+#[cfg(debug_assertions)]
+mod debug;
+
+#[cfg(debug_assertions)]
+mod debug_class {
+    pub use super::debug::LockClass;
+}
+
+#[cfg(not(debug_assertions))]
+mod debug_class {
+    /// Ordering class of the locks, checked only in debug builds.
+    #[derive(Debug)]
+    pub struct LockClass;
+
+    impl LockClass {
+        pub const fn new(_name: &'static str, _order: u32) -> Self {
+            Self
+        }
+    }
+}
+
+/// Lock classes of the kernel, from the outermost.
+pub mod classes {
+    use super::LockClass;
+
+    /// The page tables are allocated from the heap while the table is locked.
+    pub static KERNEL_PAGE_TABLE: LockClass = LockClass::new("kernel page table", 0);
+    /// The per-CPU heap caches are refilled from the heap while locked.
+    #[cfg(all(feature = "heap-cache", not(feature = "heap-debug")))]
+    pub static HEAP_CACHE: LockClass = LockClass::new("heap cache", 1);
+    pub static KERNEL_HEAP: LockClass = LockClass::new("kernel heap", 2);
+}
+
+/// Spin lock that disables the interrupts while held.
+///
+/// In debug builds, the owner CPU, task and location of the lock are recorded,
+/// and recursive acquisitions, lock order violations of the [`LockClass`]es
+/// and locks that are not acquired in time panic with the owner.
 pub struct SpinMutex<T> {
     locked: AtomicBool,
     data: UnsafeCell<T>,
-    locked_at: UnsafeCell<&'static Location<'static>>,
+    #[cfg(debug_assertions)]
+    debug: DebugState,
 }
 
 impl<T> Default for SpinMutex<T>
@@ -27,11 +69,7 @@ where
 {
     #[track_caller]
     fn default() -> Self {
-        Self {
-            locked: AtomicBool::new(false),
-            data: UnsafeCell::new(T::default()),
-            locked_at: UnsafeCell::new(Location::caller()),
-        }
+        Self::new(T::default())
     }
 }
 
@@ -56,7 +94,19 @@ impl<T> SpinMutex<T> {
         Self {
             locked: AtomicBool::new(false),
             data: UnsafeCell::new(data),
-            locked_at: UnsafeCell::new(Location::caller()),
+            #[cfg(debug_assertions)]
+            debug: DebugState::new(None),
+        }
+    }
+
+    /// Creates a lock of `class`, acquired in the order of the classes.
+    #[cfg_attr(not(debug_assertions), expect(unused_variables))]
+    pub const fn with_class(data: T, class: &'static LockClass) -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            data: UnsafeCell::new(data),
+            #[cfg(debug_assertions)]
+            debug: DebugState::new(Some(class)),
         }
     }
 
@@ -64,13 +114,20 @@ impl<T> SpinMutex<T> {
     pub fn lock(&self) -> SpinMutexGuard<'_, T> {
         let interrupt_guard = interrupt::push_disabled();
 
+        #[cfg(debug_assertions)]
+        let (mut spins, mut start) = {
+            self.debug.check_lock(Location::caller());
+            (0, None)
+        };
         while self.locked.swap(true, Ordering::Acquire) {
+            #[cfg(debug_assertions)]
+            self.debug
+                .check_spin(Location::caller(), &mut spins, &mut start);
             hint::spin_loop();
         }
 
-        unsafe {
-            *self.locked_at.get() = Location::caller();
-        }
+        #[cfg(debug_assertions)]
+        self.debug.on_acquire(Location::caller());
 
         SpinMutexGuard {
             mutex: self,
@@ -86,9 +143,8 @@ impl<T> SpinMutex<T> {
             return None;
         }
 
-        unsafe {
-            *self.locked_at.get() = Location::caller();
-        }
+        #[cfg(debug_assertions)]
+        self.debug.on_acquire(Location::caller());
 
         Some(SpinMutexGuard {
             mutex: self,
@@ -110,6 +166,13 @@ impl<T> SpinMutex<T> {
     }
 }
 
+/// Spin lock protecting state that is also accessed by the interrupt handlers.
+///
+/// Every [`SpinMutex`] keeps the interrupts disabled while its guard is alive,
+/// so this is the same type. The name documents that the code holding the
+/// guard relies on that, in place of asserting it.
+pub type IrqSpinMutex<T> = SpinMutex<T>;
+
 pub struct SpinMutexGuard<'a, T> {
     mutex: &'a SpinMutex<T>,
     _interrupt_guard: InterruptGuard,
@@ -124,6 +187,8 @@ impl<T> Drop for SpinMutexGuard<'_, T> {
             self.mutex.is_locked(),
             "SpinMutexGuard dropped without holding the lock"
         );
+        #[cfg(debug_assertions)]
+        self.mutex.debug.on_release();
         self.mutex.locked.store(false, Ordering::Release);
     }
 }
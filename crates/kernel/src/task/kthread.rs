@@ -0,0 +1,149 @@
+//! Kernel threads running Rust closures.
+//!
+//! A kernel thread is a task whose entry is a closure boxed by [`Builder`].
+//! The task exits when the closure returns, and its return value is passed to
+//! the [`JoinHandle`].
+
+use alloc::{boxed::Box, string::String, sync::Arc};
+use core::ffi::c_void;
+
+use super::TaskId;
+use crate::{
+    cpu::CpuSet,
+    error::GenericError,
+    memory::kernel_space::MAX_KERNEL_STACK_SIZE,
+    sync::spinlock::{SpinMutex, SpinMutexCondVar},
+    task,
+};
+
+/// Configuration of a kernel thread to spawn.
+#[derive(Debug, Default)]
+#[must_use]
+pub struct Builder {
+    name: Option<String>,
+    stack_size: Option<usize>,
+    affinity: CpuSet,
+}
+
+impl Builder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Names the thread for the debugging output.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Limits the kernel stack of the thread to `size` bytes.
+    ///
+    /// The size is rounded up to the page size, and defaults to
+    /// [`MAX_KERNEL_STACK_SIZE`].
+    #[cfg_attr(not(feature = "ktest"), expect(dead_code))]
+    pub fn stack_size(mut self, size: usize) -> Self {
+        self.stack_size = Some(size);
+        self
+    }
+
+    /// Restricts the CPUs that the thread runs on, all the CPUs by default.
+    ///
+    /// See [`task::set_affinity`] for the affinity of the running threads.
+    #[cfg_attr(not(feature = "ktest"), expect(dead_code))]
+    pub fn affinity(mut self, affinity: CpuSet) -> Self {
+        self.affinity = affinity;
+        self
+    }
+
+    /// Spawns a thread running `f`.
+    pub fn spawn<F, T>(self, f: F) -> Result<JoinHandle<T>, GenericError>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let packet = Arc::new(Packet {
+            result: SpinMutex::new(None),
+            finished: SpinMutexCondVar::new(),
+        });
+        let start = Box::new(Start {
+            f,
+            packet: Arc::clone(&packet),
+        });
+        let arg = Box::into_raw(start);
+        let stack_size = self.stack_size.unwrap_or(MAX_KERNEL_STACK_SIZE);
+        let id = match task::spawn(
+            entry::<F, T>,
+            arg.cast(),
+            self.name,
+            stack_size,
+            self.affinity,
+        ) {
+            Ok(id) => id,
+            Err(e) => {
+                // the task is not started, so the closure is still owned here
+                drop(unsafe { Box::from_raw(arg) });
+                return Err(e);
+            }
+        };
+        Ok(JoinHandle { id, packet })
+    }
+}
+
+/// Argument of [`entry`], owned by the spawned task.
+struct Start<F, T> {
+    f: F,
+    packet: Arc<Packet<T>>,
+}
+
+#[derive(Debug)]
+struct Packet<T> {
+    result: SpinMutex<Option<T>>,
+    finished: SpinMutexCondVar,
+}
+
+extern "C" fn entry<F, T>(arg: *mut c_void) -> !
+where
+    F: FnOnce() -> T,
+{
+    let start: Box<Start<F, T>> = unsafe { Box::from_raw(arg.cast()) };
+    let Start { f, packet } = *start;
+    let value = f();
+    *packet.result.lock() = Some(value);
+    packet.finished.notify_all();
+    drop(packet);
+    task::exit();
+}
+
+/// Owned permission to wait for a kernel thread to finish.
+///
+/// Dropping the handle detaches the thread.
+#[derive(Debug)]
+pub struct JoinHandle<T> {
+    #[cfg_attr(not(feature = "ktest"), expect(dead_code))]
+    id: TaskId,
+    packet: Arc<Packet<T>>,
+}
+
+impl<T> JoinHandle<T> {
+    #[cfg_attr(not(feature = "ktest"), expect(dead_code))]
+    pub fn id(&self) -> TaskId {
+        self.id
+    }
+
+    #[cfg_attr(not(feature = "ktest"), expect(dead_code))]
+    pub fn is_finished(&self) -> bool {
+        self.packet.result.lock().is_some()
+    }
+
+    /// Waits for the thread to finish, and returns the value of its closure.
+    #[cfg_attr(not(feature = "ktest"), expect(dead_code))]
+    pub fn join(self) -> T {
+        let mut result = self.packet.result.lock();
+        loop {
+            if let Some(value) = result.take() {
+                return value;
+            }
+            result = self.packet.finished.wait(result);
+        }
+    }
+}
@@ -1,22 +1,27 @@
 use alloc::{
     collections::btree_map::BTreeMap,
+    string::String,
     sync::{Arc, Weak},
 };
 use core::{
     ffi::c_void,
     fmt,
     sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
 };
 
-use snafu::ResultExt as _;
+use snafu::{ResultExt as _, ensure_whatever};
 
 use self::scheduler::Context;
 use crate::{
+    cpu::{self, CpuSet},
     error::GenericError,
+    interrupt::timer::Instant,
     memory::kernel_space::{self, KernelStack},
     sync::spinlock::{SpinMutex, SpinMutexGuard},
 };
 
+pub mod kthread;
 pub mod scheduler;
 
 static TASK_MAP: SpinMutex<BTreeMap<TaskId, Arc<Task>>> = SpinMutex::new(BTreeMap::new());
@@ -38,6 +43,14 @@ impl TaskId {
         let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
         Self(id)
     }
+
+    pub const fn value(self) -> u64 {
+        self.0
+    }
+
+    pub const fn from_raw(value: u64) -> Self {
+        Self(value)
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
@@ -45,6 +58,7 @@ pub enum TaskState {
     Runnable,
     Running,
     Sleep,
+    Exited,
 }
 
 #[derive(Debug)]
@@ -52,11 +66,23 @@ pub struct TaskSharedData {
     state: TaskState,
     sched_context: Context,
     task: Weak<Task>,
+    /// CPU time of the task, excluding the current run.
+    runtime: Duration,
+    /// CPU time of the task spent in U-mode, included in the CPU time.
+    user_time: Duration,
+    /// Number of times the task has been switched to.
+    switches: u64,
+    /// Time the current run started, valid while the task is running.
+    scheduled_at: Instant,
+    /// CPUs the task may run on.
+    affinity: CpuSet,
 }
 
 #[derive(Debug)]
 pub struct Task {
     id: TaskId,
+    /// Name for the debugging output.
+    name: Option<String>,
     _kernel_stack: KernelStack,
     pub shared: SpinMutex<TaskSharedData>,
 }
@@ -65,17 +91,26 @@ impl Task {
     fn new(
         entry: extern "C" fn(*mut c_void) -> !,
         arg: *mut c_void,
+        name: Option<String>,
+        stack_size: usize,
+        affinity: CpuSet,
     ) -> Result<Arc<Self>, GenericError> {
-        let kernel_stack = kernel_space::allocate_kernel_stack()
+        let kernel_stack = kernel_space::allocate_kernel_stack(stack_size)
             .whatever_context("failed to allocate kernel stack")?;
         let sched_context = Context::new(&kernel_stack, entry, arg);
         let task = Arc::new_cyclic(|task| Self {
             id: TaskId::new(),
+            name,
             _kernel_stack: kernel_stack,
             shared: SpinMutex::new(TaskSharedData {
                 state: TaskState::Runnable,
                 sched_context,
                 task: Weak::clone(task),
+                runtime: Duration::ZERO,
+                user_time: Duration::ZERO,
+                switches: 0,
+                scheduled_at: Instant::ZERO,
+                affinity,
             }),
         });
         Ok(task)
@@ -84,23 +119,42 @@ impl Task {
     pub fn id(&self) -> TaskId {
         self.id
     }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
 }
 
-pub fn spawn(
+/// Spawns a task running `entry(arg)`.
+///
+/// Use [`kthread::Builder`] to spawn a task running a closure.
+fn spawn(
     entry: extern "C" fn(*mut c_void) -> !,
     arg: *mut c_void,
+    name: Option<String>,
+    stack_size: usize,
+    affinity: CpuSet,
 ) -> Result<TaskId, GenericError> {
-    let task = Task::new(entry, arg)?;
+    ensure_whatever!(!affinity.is_empty(), "empty CPU affinity");
+    let task = Task::new(entry, arg, name, stack_size, affinity)?;
     assert!(
         TASK_MAP
             .lock()
             .insert(task.id(), Arc::clone(&task))
             .is_none()
     );
-    scheduler::push_task(Arc::downgrade(&task));
+    scheduler::push_task(Arc::downgrade(&task), affinity);
     Ok(task.id())
 }
 
+/// Returns the task of the id.
+///
+/// Exited tasks are returned until the scheduler reaps them.
+#[cfg_attr(not(any(feature = "shell", feature = "ktest")), expect(dead_code))]
+pub fn get(id: TaskId) -> Option<Arc<Task>> {
+    TASK_MAP.lock().get(&id).map(Arc::clone)
+}
+
 pub fn pause(shared: &mut SpinMutexGuard<'_, TaskSharedData>) {
     assert!(Weak::ptr_eq(
         &shared.task,
@@ -115,6 +169,51 @@ pub fn pause(shared: &mut SpinMutexGuard<'_, TaskSharedData>) {
 pub fn resume(shared: &mut SpinMutexGuard<'_, TaskSharedData>) {
     if shared.state == TaskState::Sleep {
         shared.state = TaskState::Runnable;
-        scheduler::push_task(Weak::clone(&shared.task));
+        scheduler::push_task(Weak::clone(&shared.task), shared.affinity);
     }
 }
+
+/// Restricts the CPUs that the task runs on to `affinity`.
+///
+/// The task runs on any CPU while none of `affinity` is online. The current
+/// task moves to another CPU before this returns if the current CPU is not
+/// in `affinity`, and the other running tasks move when they are switched
+/// out.
+#[cfg_attr(not(feature = "shell"), expect(dead_code))]
+pub fn set_affinity(task: &Task, affinity: CpuSet) -> Result<(), GenericError> {
+    ensure_whatever!(!affinity.is_empty(), "empty CPU affinity");
+    let mut shared = task.shared.lock();
+    shared.affinity = affinity;
+    if scheduler::try_current_task_id() == Some(task.id())
+        && !scheduler::can_run_on(&affinity, cpu::current().id())
+    {
+        scheduler::yield_execution(&mut shared);
+    }
+    shared.unlock();
+    Ok(())
+}
+
+/// Removes the exited task from the task map, once the scheduler has switched
+/// away from it.
+///
+/// The kernel stack of the task is freed when the last reference to the task
+/// is dropped.
+fn reap(id: TaskId) {
+    let task = TASK_MAP.lock().remove(&id);
+    drop(task);
+}
+
+/// Terminates the current task.
+///
+/// The task is reaped by the scheduler once it has switched away from the
+/// task.
+pub fn exit() -> ! {
+    // the scheduler holds the running task, so no reference is kept in this
+    // frame, which is never unwound
+    let task = Arc::as_ptr(&scheduler::current_task());
+    let task = unsafe { &*task };
+    let mut shared = task.shared.lock();
+    shared.state = TaskState::Exited;
+    scheduler::return_to_scheduler(&mut shared);
+    unreachable!("exited task {} was rescheduled", task.id());
+}
@@ -1,20 +1,44 @@
 use alloc::{
     collections::vec_deque::VecDeque,
+    string::String,
     sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::{
+    cell::UnsafeCell,
+    ffi::c_void,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
 };
-use core::{cell::UnsafeCell, ffi::c_void};
 
 pub use self::context::Context;
-use super::{Task, TaskSharedData};
+use super::{TASK_MAP, Task, TaskId, TaskSharedData};
 use crate::{
-    cpu, interrupt,
-    sync::spinlock::{SpinMutex, SpinMutexGuard},
+    cpu::{self, CpuSet, Cpuid, hotplug, idle},
+    interrupt::{
+        self,
+        timer::{self, Instant},
+    },
+    stats::{self, Counter},
+    sync::{
+        rcu,
+        spinlock::{IrqSpinMutex, SpinMutex, SpinMutexGuard},
+    },
     task::TaskState,
 };
 
 mod context;
 
-static RUNNABLE_TASKS: SpinMutex<VecDeque<Weak<Task>>> = SpinMutex::new(VecDeque::new());
+static RUNNABLE_TASKS: SpinMutex<VecDeque<RunnableTask>> = SpinMutex::new(VecDeque::new());
+
+/// Task in the run queue.
+#[derive(Debug)]
+struct RunnableTask {
+    task: Weak<Task>,
+    /// Affinity of the task when it is queued, readable without locking the
+    /// task.
+    affinity: CpuSet,
+}
 
 cpu_local! {
     static SCHEDULER_STATE: SchedulerState = SchedulerState::new();
@@ -23,7 +47,13 @@ cpu_local! {
 #[derive(Debug)]
 struct SchedulerState {
     context: UnsafeCell<Context>,
-    current_task: SpinMutex<Option<Arc<Task>>>,
+    current_task: IrqSpinMutex<Option<Arc<Task>>>,
+    /// Id of the current task, readable without the lock.
+    current_task_id: AtomicU64,
+    /// Time spent waiting for interrupts in nanoseconds.
+    idle_nanos: AtomicU64,
+    /// Number of switches to the tasks.
+    switches: AtomicU64,
 }
 
 unsafe impl Sync for SchedulerState {}
@@ -32,19 +62,27 @@ impl SchedulerState {
     const fn new() -> Self {
         Self {
             context: UnsafeCell::new(Context::zeroed()),
-            current_task: SpinMutex::new(None),
+            current_task: IrqSpinMutex::new(None),
+            current_task_id: AtomicU64::new(TaskId::INVALID.value()),
+            idle_nanos: AtomicU64::new(0),
+            switches: AtomicU64::new(0),
         }
     }
 
     fn set_current_task(&self, task: Option<Arc<Task>>) {
-        assert!(!interrupt::is_enabled());
+        let id = task.as_ref().map_or(TaskId::INVALID, |task| task.id());
         *self.current_task.lock() = task;
+        self.current_task_id.store(id.value(), Ordering::Relaxed);
     }
 
     fn try_current_task(&self) -> Option<Arc<Task>> {
-        assert!(!interrupt::is_enabled());
         self.current_task.lock().as_ref().map(Arc::clone)
     }
+
+    fn current_task_id(&self) -> Option<TaskId> {
+        let id = TaskId::from_raw(self.current_task_id.load(Ordering::Relaxed));
+        (id != TaskId::INVALID).then_some(id)
+    }
 }
 
 #[track_caller]
@@ -65,12 +103,16 @@ pub fn start() -> ! {
     let cpu = cpu::current();
     let sched_state = get_state();
     assert!(sched_state.try_current_task().is_none());
+    rcu::quiescent_state();
 
     loop {
         interrupt::enable();
         interrupt::disable();
 
-        while let Some(task) = { RUNNABLE_TASKS.lock().pop_front() } {
+        // the CPU going offline stops between the tasks
+        while !hotplug::is_stop_requested()
+            && let Some(task) = pop_task(cpu.id())
+        {
             let Some(task) = Weak::upgrade(&task) else {
                 continue;
             };
@@ -78,13 +120,24 @@ pub fn start() -> ! {
             if shared.state != TaskState::Runnable {
                 continue;
             }
+            // the affinity may be changed while the task is queued
+            if !can_run_on(&shared.affinity, cpu.id()) {
+                push_task(Weak::clone(&shared.task), shared.affinity);
+                continue;
+            }
             shared.state = TaskState::Running;
+            shared.switches += 1;
+            shared.scheduled_at = timer::now();
+            sched_state.switches.fetch_add(1, Ordering::Relaxed);
+            stats::incr(Counter::ContextSwitches);
 
             sched_state.set_current_task(Some(Arc::clone(&task)));
 
-            // Interrupt state is a property of this kernel thread, not this CPU,
-            // but the state is saved per CPU. so we need to restore it manually.
+            // Interrupt state is a property of this kernel thread, not this
+            // CPU, but the state is saved per CPU. so we need to
+            // restore it manually.
             let int_state = interrupt::save_state();
+            trace_event!(SchedSwitch, task.id().value(), 0_u64);
             unsafe {
                 context::switch(sched_state.context.get(), &raw const shared.sched_context);
             }
@@ -92,16 +145,72 @@ pub fn start() -> ! {
 
             // assert that scheduler task runs on the same CPU
             assert_eq!(cpu.id(), cpu::current().id());
+            let ran = timer::now().saturating_duration_since(shared.scheduled_at);
+            shared.runtime += ran;
+            trace_event!(
+                SchedReturn,
+                task.id().value(),
+                u64::try_from(ran.as_nanos()).unwrap_or(u64::MAX)
+            );
             sched_state.set_current_task(None);
+            // the exited task is off its kernel stack now
+            let exited = shared.state == TaskState::Exited;
+            shared.unlock();
+            if exited {
+                super::reap(task.id());
+            }
+            drop(task);
+            rcu::quiescent_state();
         }
 
-        interrupt::wait();
+        if hotplug::is_stop_requested() {
+            hotplug::stop_current();
+        }
+
+        rcu::enter_idle();
+        let idle = idle::enter();
+        sched_state
+            .idle_nanos
+            .fetch_add(u64::try_from(idle.as_nanos()).unwrap(), Ordering::Relaxed);
+        rcu::exit_idle();
     }
 }
 
 #[track_caller]
-pub(super) fn push_task(task: Weak<Task>) {
-    RUNNABLE_TASKS.lock().push_back(task);
+pub(super) fn push_task(task: Weak<Task>, affinity: CpuSet) {
+    RUNNABLE_TASKS
+        .lock()
+        .push_back(RunnableTask { task, affinity });
+    interrupt::timer::notify_runnable();
+}
+
+/// Takes the first task in the run queue that can run on the CPU.
+fn pop_task(cpuid: Cpuid) -> Option<Weak<Task>> {
+    let mut queue = RUNNABLE_TASKS.lock();
+    let index = queue
+        .iter()
+        .position(|task| can_run_on(&task.affinity, cpuid))?;
+    queue.remove(index).map(|task| task.task)
+}
+
+/// Returns whether a task with `affinity` can run on the CPU.
+///
+/// A task runs on any CPU while none of its affinity is online, so that it
+/// does not stall when its CPUs go offline.
+pub(super) fn can_run_on(affinity: &CpuSet, cpuid: Cpuid) -> bool {
+    affinity.contains(cpuid)
+        || !cpu::get_all()
+            .iter()
+            .any(|cpu| affinity.contains(cpu.id()) && hotplug::is_online(cpu.id()))
+}
+
+/// Returns whether the run queue has tasks that can run on the current CPU.
+pub fn has_runnable_tasks() -> bool {
+    let cpuid = cpu::current().id();
+    RUNNABLE_TASKS
+        .lock()
+        .iter()
+        .any(|task| can_run_on(&task.affinity, cpuid))
 }
 
 #[track_caller]
@@ -110,6 +219,16 @@ pub fn try_current_task() -> Option<Arc<Task>> {
     try_get_state()?.try_current_task()
 }
 
+/// Returns the id of the current task without locking the scheduler state.
+pub fn try_current_task_id() -> Option<TaskId> {
+    try_get_state()?.current_task_id()
+}
+
+/// Returns the id of the task running on the given CPU.
+pub fn current_task_id_on(cpuid: Cpuid) -> Option<TaskId> {
+    SCHEDULER_STATE.try_get_for(cpuid)?.current_task_id()
+}
+
 #[track_caller]
 pub fn current_task() -> Arc<Task> {
     try_current_task().unwrap()
@@ -126,7 +245,7 @@ pub(super) fn return_to_scheduler(shared: &mut SpinMutexGuard<TaskSharedData>) {
     assert!(Weak::ptr_eq(&shared.task, &Arc::downgrade(&current_task())));
     assert_ne!(shared.state, TaskState::Running);
     if shared.state == TaskState::Runnable {
-        push_task(Weak::clone(&shared.task));
+        push_task(Weak::clone(&shared.task), shared.affinity);
     }
 
     let sched_state = get_state();
@@ -140,10 +259,303 @@ pub(super) fn return_to_scheduler(shared: &mut SpinMutexGuard<TaskSharedData>) {
     int_state.restore();
 }
 
+/// Adds `time` spent in U-mode to the CPU time of the current task.
+pub fn account_user_time(time: Duration) {
+    let task = current_task();
+    task.shared.lock().user_time += time;
+}
+
+/// Scheduling statistics of a task.
+#[derive(Debug, Clone)]
+#[cfg_attr(not(feature = "shell"), expect(dead_code))]
+pub struct TaskStats {
+    pub id: TaskId,
+    pub name: Option<String>,
+    pub state: TaskState,
+    /// CPU time of the task, including the current run.
+    pub runtime: Duration,
+    /// CPU time of the task spent in U-mode, included in `runtime`.
+    ///
+    /// The current stay in U-mode is not counted until the task traps back
+    /// to the kernel.
+    pub user_time: Duration,
+    /// Number of times the task has been switched to.
+    pub switches: u64,
+    pub affinity: CpuSet,
+}
+
+/// Scheduling statistics of a CPU.
+#[derive(Debug, Clone)]
+#[cfg_attr(not(feature = "shell"), expect(dead_code))]
+pub struct CpuStats {
+    pub cpuid: Cpuid,
+    /// Time spent waiting for interrupts.
+    ///
+    /// The current idle period is not counted until the CPU wakes up.
+    pub idle_time: Duration,
+    /// Number of switches to the tasks.
+    pub switches: u64,
+    pub current_task: Option<TaskId>,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(not(feature = "shell"), expect(dead_code))]
+pub struct SchedulerStats {
+    /// Time since the timer started, against which the CPU times are measured.
+    pub uptime: Duration,
+    pub cpus: Vec<CpuStats>,
+    /// Statistics of the tasks, in the order of their ids.
+    pub tasks: Vec<TaskStats>,
+}
+
+/// Returns the scheduling statistics of the CPUs and the tasks.
+#[cfg_attr(not(feature = "shell"), expect(dead_code))]
+pub fn stats() -> SchedulerStats {
+    let now = timer::now();
+
+    let cpus = cpu::get_all()
+        .iter()
+        .filter_map(|cpu| {
+            let state = SCHEDULER_STATE.try_get_for(cpu.id())?;
+            Some(CpuStats {
+                cpuid: cpu.id(),
+                idle_time: Duration::from_nanos(state.idle_nanos.load(Ordering::Relaxed)),
+                switches: state.switches.load(Ordering::Relaxed),
+                current_task: state.current_task_id(),
+            })
+        })
+        .collect();
+
+    // the task map is not locked while locking the tasks
+    let tasks = TASK_MAP.lock().values().map(Arc::clone).collect::<Vec<_>>();
+    let tasks = tasks.iter().map(|task| task_stats_at(task, now)).collect();
+
+    SchedulerStats {
+        uptime: now.duration_since_epoc(),
+        cpus,
+        tasks,
+    }
+}
+
+/// Returns the scheduling statistics of the task, which may have been reaped
+/// already.
+#[cfg_attr(not(feature = "ktest"), expect(dead_code))]
+pub fn task_stats(task: &Task) -> TaskStats {
+    task_stats_at(task, timer::now())
+}
+
+fn task_stats_at(task: &Task, now: Instant) -> TaskStats {
+    let shared = task.shared.lock();
+    let mut runtime = shared.runtime;
+    if shared.state == TaskState::Running {
+        runtime += now.saturating_duration_since(shared.scheduled_at);
+    }
+    TaskStats {
+        id: task.id(),
+        name: task.name().map(String::from),
+        state: shared.state,
+        runtime,
+        user_time: shared.user_time,
+        switches: shared.switches,
+        affinity: shared.affinity,
+    }
+}
+
 fn task_entry(entry: extern "C" fn(*mut c_void) -> !, arg: *mut c_void) -> ! {
     assert!(!interrupt::in_interrupt_handler());
     let task = current_task();
     unsafe { task.shared.remember_locked() }.unlock();
+    // the task is not kept alive by its own frame, which is never unwound
+    drop(task);
     interrupt::enable();
     entry(arg);
 }
+
+#[cfg(feature = "ktest")]
+pub mod ktests {
+    use alloc::vec::Vec;
+    use core::{hint, ptr, time::Duration};
+
+    use snafu::{OptionExt as _, ensure_whatever};
+
+    use super::{current_task, stats, yield_execution};
+    use crate::{
+        cpu::{self, CpuSet, hotplug},
+        error::GenericError,
+        interrupt::timer::{self, Instant},
+        ktest::KernelTest,
+        memory::{
+            PAGE_SIZE,
+            kernel_space::{self, MAX_KERNEL_STACK_SIZE},
+        },
+        task::{
+            self,
+            kthread::{self, JoinHandle},
+        },
+    };
+
+    pub static TESTS: &[KernelTest] = kernel_tests![
+        yield_returns,
+        spawned_tasks_run,
+        small_stack_runs,
+        stats_count_switches,
+        affinity_pins_task,
+        exited_task_reaped,
+    ];
+
+    fn yield_returns() -> Result<(), GenericError> {
+        let task = current_task();
+        for _ in 0..10 {
+            let mut shared = task.shared.lock();
+            yield_execution(&mut shared);
+        }
+        let current = current_task();
+        ensure_whatever!(
+            current.id() == task.id(),
+            "resumed as task {}",
+            current.id()
+        );
+        Ok(())
+    }
+
+    fn spawned_tasks_run() -> Result<(), GenericError> {
+        const TASKS: usize = 8;
+        const TIMEOUT: Duration = Duration::from_secs(1);
+
+        let handles = (0..TASKS)
+            .map(|i| {
+                kthread::Builder::new()
+                    .name("ktest-spawn")
+                    .spawn(move || i * 2)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let start = Instant::now();
+        while !handles.iter().all(JoinHandle::is_finished) {
+            ensure_whatever!(
+                start.elapsed() < TIMEOUT,
+                "only {} of {TASKS} tasks finished in {TIMEOUT:?}",
+                handles.iter().filter(|handle| handle.is_finished()).count()
+            );
+            timer::sleep(Duration::from_millis(1));
+        }
+        for (i, handle) in handles.into_iter().enumerate() {
+            let value = handle.join();
+            ensure_whatever!(value == i * 2, "task {i} returned {value}");
+        }
+        Ok(())
+    }
+
+    fn small_stack_runs() -> Result<(), GenericError> {
+        let handle = kthread::Builder::new()
+            .name("ktest-small-stack")
+            .stack_size(2 * PAGE_SIZE)
+            .spawn(|| {
+                let buf = [1_u8; PAGE_SIZE];
+                hint::black_box(&buf)
+                    .iter()
+                    .map(|&b| usize::from(b))
+                    .sum::<usize>()
+            })?;
+        let sum = handle.join();
+        ensure_whatever!(sum == PAGE_SIZE, "task returned {sum}");
+        ensure_whatever!(
+            kthread::Builder::new()
+                .stack_size(MAX_KERNEL_STACK_SIZE + 1)
+                .spawn(|| {})
+                .is_err(),
+            "oversized stack allocated"
+        );
+        Ok(())
+    }
+
+    fn stats_count_switches() -> Result<(), GenericError> {
+        const YIELDS: u64 = 10;
+
+        let task = current_task();
+        let find = || {
+            stats()
+                .tasks
+                .into_iter()
+                .find(|stats| stats.id == task.id())
+        };
+        let before = find().whatever_context("current task not found")?;
+        for _ in 0..YIELDS {
+            let mut shared = task.shared.lock();
+            yield_execution(&mut shared);
+        }
+        let after = find().whatever_context("current task not found")?;
+        ensure_whatever!(
+            after.switches >= before.switches + YIELDS,
+            "switches counted from {} to {} in {YIELDS} yields",
+            before.switches,
+            after.switches
+        );
+        ensure_whatever!(
+            after.runtime > before.runtime,
+            "runtime not increased from {:?}",
+            before.runtime
+        );
+        Ok(())
+    }
+
+    fn affinity_pins_task() -> Result<(), GenericError> {
+        for cpu in cpu::get_all() {
+            if !hotplug::is_online(cpu.id()) {
+                continue;
+            }
+            let handle = kthread::Builder::new()
+                .name("ktest-affinity")
+                .affinity(CpuSet::single(cpu.id()))
+                .spawn(|| {
+                    let mut cpuids = Vec::new();
+                    for _ in 0..10 {
+                        cpuids.push(cpu::current().id());
+                        let task = current_task();
+                        let mut shared = task.shared.lock();
+                        yield_execution(&mut shared);
+                    }
+                    cpuids
+                })?;
+            let cpuids = handle.join();
+            ensure_whatever!(
+                cpuids.iter().all(|&cpuid| cpuid == cpu.id()),
+                "task pinned to CPU#{} ran on {cpuids:?}",
+                cpu.id()
+            );
+        }
+        Ok(())
+    }
+
+    fn exited_task_reaped() -> Result<(), GenericError> {
+        const TIMEOUT: Duration = Duration::from_secs(1);
+
+        let in_use = kernel_space::stack_stats().in_use;
+        let handle = kthread::Builder::new().name("ktest-exit").spawn(|| {
+            let local = 0_u8;
+            ptr::from_ref(hint::black_box(&local)).addr()
+        })?;
+        let id = handle.id();
+        let stack_addr = handle.join();
+
+        // the task is reaped once the scheduler switches away from it
+        let start = Instant::now();
+        while task::get(id).is_some() {
+            ensure_whatever!(
+                start.elapsed() < TIMEOUT,
+                "exited task {id} not reaped in {TIMEOUT:?}"
+            );
+            timer::sleep(Duration::from_millis(1));
+        }
+        ensure_whatever!(
+            !kernel_space::is_stack_in_use(stack_addr),
+            "stack slot of exited task {id} still in use"
+        );
+        let stats = kernel_space::stack_stats();
+        ensure_whatever!(
+            stats.in_use <= in_use,
+            "{} stack slots in use after the task exited, {in_use} before",
+            stats.in_use
+        );
+        Ok(())
+    }
+}
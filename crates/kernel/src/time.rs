@@ -0,0 +1,144 @@
+//! Monotonic and wall-clock time.
+//!
+//! [`Instant`] is a monotonic clock driven by the CPU timer and starts at zero
+//! on boot. [`SystemTime`] is the wall-clock time, computed from [`Instant`]
+//! plus an offset read from the system RTC once at boot.
+
+use core::{fmt, ops::Add, time::Duration};
+
+use spin::Once;
+
+use crate::drivers::rtc;
+pub use crate::interrupt::timer::Instant;
+
+const SECS_PER_MINUTE: u64 = 60;
+const SECS_PER_HOUR: u64 = 60 * SECS_PER_MINUTE;
+const SECS_PER_DAY: u64 = 24 * SECS_PER_HOUR;
+
+/// Offset between the monotonic clock epoch (boot) and the UNIX epoch.
+static BOOT_TIME: Once<Duration> = Once::new();
+
+initcall!(INITCALL, late, init);
+
+fn init() {
+    let boot_time = BOOT_TIME.call_once(|| {
+        let Some(rtc) = rtc::system_rtc() else {
+            warn!("no RTC device found, wall-clock time starts at the UNIX epoch");
+            return Duration::ZERO;
+        };
+        let now = Instant::now();
+        let wall_clock = rtc.read_time();
+        info!("wall-clock time initialized from {}", rtc.path());
+        wall_clock.saturating_sub(now.duration_since_epoc())
+    });
+    info!("boot time: {}", SystemTime(*boot_time));
+    info!("current time: {}", SystemTime::now());
+}
+
+/// A measurement of the wall-clock time.
+///
+/// Unlike [`Instant`], this is not guaranteed to be monotonic across RTC
+/// adjustments, but it can be related to real-world dates.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SystemTime(Duration);
+
+impl fmt::Debug for SystemTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl SystemTime {
+    pub const UNIX_EPOCH: Self = Self(Duration::ZERO);
+
+    pub fn try_now() -> Option<Self> {
+        let boot_time = BOOT_TIME.get()?;
+        let now = crate::interrupt::timer::try_now()?;
+        Some(Self(*boot_time + now.duration_since_epoc()))
+    }
+
+    #[track_caller]
+    pub fn now() -> Self {
+        Self::try_now().unwrap()
+    }
+
+    pub fn duration_since(&self, earlier: Self) -> Option<Duration> {
+        self.0.checked_sub(earlier.0)
+    }
+
+    pub fn duration_since_unix_epoch(&self) -> Duration {
+        self.duration_since(Self::UNIX_EPOCH).unwrap()
+    }
+
+    /// Returns the calendar date and the time of day in UTC.
+    pub fn to_date_time(self) -> DateTime {
+        let secs = self.duration_since_unix_epoch().as_secs();
+        let (year, month, day) = civil_from_days(secs / SECS_PER_DAY);
+        let secs_of_day = secs % SECS_PER_DAY;
+        DateTime {
+            year,
+            month,
+            day,
+            hour: secs_of_day / SECS_PER_HOUR,
+            minute: secs_of_day % SECS_PER_HOUR / SECS_PER_MINUTE,
+            second: secs_of_day % SECS_PER_MINUTE,
+        }
+    }
+}
+
+/// Broken-down UTC time in the proleptic Gregorian calendar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: u64,
+    /// Month of the year, in `1..=12`.
+    pub month: u64,
+    /// Day of the month, in `1..=31`.
+    pub day: u64,
+    pub hour: u64,
+    pub minute: u64,
+    pub second: u64,
+}
+
+impl Add<Duration> for SystemTime {
+    type Output = Self;
+
+    fn add(self, rhs: Duration) -> Self::Output {
+        Self(self.0 + rhs)
+    }
+}
+
+/// Formats the time as an RFC 3339 UTC timestamp.
+impl fmt::Display for SystemTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let DateTime {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+        } = self.to_date_time();
+        write!(
+            f,
+            "{year:04}-{month:02}-{day:02}T{hour:02}:{minute:02}:{second:02}.{:06}Z",
+            self.duration_since_unix_epoch().subsec_micros()
+        )
+    }
+}
+
+/// Converts days since the UNIX epoch into a (year, month, day) triple in the
+/// proleptic Gregorian calendar.
+///
+/// See <https://howardhinnant.github.io/date_algorithms.html#civil_from_days>.
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
@@ -0,0 +1,345 @@
+//! Binary trace of the kernel events.
+//!
+//! Each CPU records the events into its own ring of fixed-size entries, with
+//! the raw timer ticks and two arguments, so that recording does not take a
+//! lock nor format anything. The oldest entries are overwritten when the ring
+//! is full. The tracing is disabled by default, and enabled by the `trace`
+//! option or by the `trace` shell command.
+//!
+//! The entries are read by [`snapshot`] while the CPUs keep recording. Each
+//! entry has a sequence number, written last, and the entries overwritten
+//! while being read are skipped.
+
+use alloc::vec::Vec;
+use core::{
+    fmt,
+    sync::atomic::{self, AtomicBool, AtomicU64, AtomicUsize, Ordering},
+};
+
+use platform_cast::CastFrom as _;
+use sbi::{SbiRet, hook::CallHook};
+
+use crate::{
+    cmdline::{FromParam as _, ParamDescriptor},
+    cpu::{self, Cpu, Cpuid},
+    interrupt::{
+        self,
+        timer::{self, Instant},
+    },
+};
+
+/// Records an event into the trace ring of the current CPU, if the tracing is
+/// enabled.
+macro_rules! trace_event {
+    ($event:ident, $a0:expr, $a1:expr) => {
+        if $crate::trace::is_enabled() {
+            $crate::trace::record(
+                $crate::trace::TraceEvent::$event,
+                $crate::trace::TraceArg::into_arg($a0),
+                $crate::trace::TraceArg::into_arg($a1),
+            );
+        }
+    };
+}
+
+/// Number of the entries in the ring of each CPU.
+const RING_SIZE: usize = 256;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Timer ticks before which the entries are dropped by [`clear`].
+static CLEARED_AT: AtomicU64 = AtomicU64::new(0);
+
+cpu_local! {
+    static RING: TraceRing = TraceRing::new();
+}
+
+pub static TRACE_PARAM: ParamDescriptor = ParamDescriptor {
+    name: "trace",
+    handler: |value| {
+        set_enabled(bool::from_param(value)?);
+        Ok(())
+    },
+};
+
+static SBI_HOOK: CallHook = CallHook {
+    enter: |extension_id, function_id| trace_event!(SbiEnter, extension_id, function_id),
+    exit: |extension_id, _function_id, ret: &SbiRet| trace_event!(SbiExit, extension_id, ret.error),
+};
+
+initcall!(INITCALL, early, init);
+
+/// Starts tracing the SBI calls.
+fn init() {
+    sbi::hook::set_hook(&SBI_HOOK);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Kind of a traced event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u16)]
+pub enum TraceEvent {
+    /// The scheduler switches to a task.
+    SchedSwitch,
+    /// A task returns to the scheduler.
+    SchedReturn,
+    IrqEnter,
+    IrqExit,
+    SbiEnter,
+    SbiExit,
+    SyscallEnter,
+    SyscallExit,
+}
+
+impl TraceEvent {
+    const ALL: [Self; 8] = [
+        Self::SchedSwitch,
+        Self::SchedReturn,
+        Self::IrqEnter,
+        Self::IrqExit,
+        Self::SbiEnter,
+        Self::SbiExit,
+        Self::SyscallEnter,
+        Self::SyscallExit,
+    ];
+
+    fn from_raw(value: u64) -> Option<Self> {
+        Self::ALL.get(usize::try_from(value).ok()?).copied()
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::SchedSwitch => "sched_switch",
+            Self::SchedReturn => "sched_return",
+            Self::IrqEnter => "irq_enter",
+            Self::IrqExit => "irq_exit",
+            Self::SbiEnter => "sbi_enter",
+            Self::SbiExit => "sbi_exit",
+            Self::SyscallEnter => "syscall_enter",
+            Self::SyscallExit => "syscall_exit",
+        }
+    }
+
+    /// Returns the names of the arguments, or empty if unused.
+    pub fn arg_names(self) -> [&'static str; 2] {
+        match self {
+            Self::SchedSwitch => ["task", ""],
+            Self::SchedReturn => ["task", "ran_ns"],
+            Self::IrqEnter | Self::IrqExit => ["hwirq", ""],
+            Self::SbiEnter => ["eid", "fid"],
+            Self::SbiExit => ["eid", "error"],
+            Self::SyscallEnter => ["number", "a0"],
+            Self::SyscallExit => ["number", "ret"],
+        }
+    }
+}
+
+/// Value recorded as an argument of an event.
+pub trait TraceArg {
+    fn into_arg(self) -> u64;
+}
+
+impl TraceArg for u64 {
+    fn into_arg(self) -> u64 {
+        self
+    }
+}
+
+impl TraceArg for usize {
+    fn into_arg(self) -> u64 {
+        u64::cast_from(self)
+    }
+}
+
+impl TraceArg for isize {
+    fn into_arg(self) -> u64 {
+        u64::cast_from(self.cast_unsigned())
+    }
+}
+
+struct TraceRing {
+    /// Index of the next entry to write, counted from the start.
+    next: AtomicUsize,
+    entries: [TraceEntry; RING_SIZE],
+}
+
+impl TraceRing {
+    const fn new() -> Self {
+        Self {
+            next: AtomicUsize::new(0),
+            entries: [const { TraceEntry::new() }; RING_SIZE],
+        }
+    }
+}
+
+struct TraceEntry {
+    /// Index of the entry plus one, or zero while the entry is written.
+    seq: AtomicU64,
+    ticks: AtomicU64,
+    event: AtomicU64,
+    args: [AtomicU64; 2],
+}
+
+impl TraceEntry {
+    const fn new() -> Self {
+        Self {
+            seq: AtomicU64::new(0),
+            ticks: AtomicU64::new(0),
+            event: AtomicU64::new(0),
+            args: [AtomicU64::new(0), AtomicU64::new(0)],
+        }
+    }
+}
+
+/// Records an event into the trace ring of the current CPU.
+///
+/// Use [`trace_event!`] instead, which skips the arguments while the tracing
+/// is disabled.
+pub fn record(event: TraceEvent, a0: u64, a1: u64) {
+    // the ring is written only by its CPU, with the interrupts disabled
+    let interrupt_guard = interrupt::push_disabled();
+    if let Some(ring) = RING.try_get() {
+        let index = ring.next.load(Ordering::Relaxed);
+        ring.next.store(index.wrapping_add(1), Ordering::Relaxed);
+        let entry = &ring.entries[index % RING_SIZE];
+
+        entry.seq.store(0, Ordering::Relaxed);
+        atomic::fence(Ordering::Release);
+        entry.ticks.store(timer::read_ticks(), Ordering::Relaxed);
+        entry.event.store(event as u64, Ordering::Relaxed);
+        entry.args[0].store(a0, Ordering::Relaxed);
+        entry.args[1].store(a1, Ordering::Relaxed);
+        entry
+            .seq
+            .store(u64::cast_from(index) + 1, Ordering::Release);
+    }
+    interrupt_guard.pop();
+}
+
+/// Event read from the trace rings.
+#[derive(Debug, Clone, Copy)]
+pub struct TraceRecord {
+    pub cpuid: Cpuid,
+    pub time: Instant,
+    pub event: TraceEvent,
+    pub args: [u64; 2],
+}
+
+impl fmt::Display for TraceRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:>16?} CPU#{} {}",
+            self.time,
+            self.cpuid,
+            self.event.name()
+        )?;
+        for (name, value) in self.event.arg_names().iter().zip(self.args) {
+            if !name.is_empty() {
+                write!(f, " {name}={value:#x}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the events recorded by all the CPUs, in the order of the time.
+#[cfg_attr(not(feature = "shell"), expect(dead_code))]
+pub fn snapshot() -> Vec<TraceRecord> {
+    let mut records = cpu::get_all()
+        .iter()
+        .flat_map(records_on)
+        .collect::<Vec<_>>();
+    records.sort_by_key(|record| record.time);
+    records
+}
+
+/// Returns up to `count` events recorded by `cpu`, from the newest.
+///
+/// Unlike [`snapshot`], this does not allocate, so it can be used in the
+/// panic handler.
+pub fn recent_records(cpu: &Cpu, count: usize) -> impl Iterator<Item = TraceRecord> {
+    records_on(cpu).rev().take(count)
+}
+
+/// Returns the events recorded by `cpu`, from the oldest.
+fn records_on(cpu: &Cpu) -> impl DoubleEndedIterator<Item = TraceRecord> {
+    let cleared_at = CLEARED_AT.load(Ordering::Relaxed);
+    let cpuid = cpu.id();
+    let timer_frequency = cpu.timer_frequency();
+    RING.try_get_for(cpuid).into_iter().flat_map(move |ring| {
+        let next = ring.next.load(Ordering::Relaxed);
+        (0..RING_SIZE).filter_map(move |i| {
+            let entry = &ring.entries[next.wrapping_add(i) % RING_SIZE];
+            let seq = entry.seq.load(Ordering::Acquire);
+            if seq == 0 {
+                return None;
+            }
+            let ticks = entry.ticks.load(Ordering::Relaxed);
+            let event = entry.event.load(Ordering::Relaxed);
+            let args = [
+                entry.args[0].load(Ordering::Relaxed),
+                entry.args[1].load(Ordering::Relaxed),
+            ];
+            atomic::fence(Ordering::Acquire);
+            // skip the entry overwritten while read
+            if entry.seq.load(Ordering::Relaxed) != seq || ticks < cleared_at {
+                return None;
+            }
+            Some(TraceRecord {
+                cpuid,
+                time: Instant::from_timer_ticks(ticks, timer_frequency),
+                event: TraceEvent::from_raw(event)?,
+                args,
+            })
+        })
+    })
+}
+
+/// Drops the events recorded so far.
+#[cfg_attr(not(feature = "shell"), expect(dead_code))]
+pub fn clear() {
+    CLEARED_AT.store(timer::read_ticks(), Ordering::Relaxed);
+}
+
+#[cfg(feature = "ktest")]
+pub mod ktests {
+    use alloc::vec::Vec;
+
+    use snafu::ensure_whatever;
+
+    use super::{TraceEvent, is_enabled, record, set_enabled, snapshot};
+    use crate::{error::GenericError, ktest::KernelTest};
+
+    pub static TESTS: &[KernelTest] = kernel_tests![recorded_events_in_snapshot];
+
+    fn recorded_events_in_snapshot() -> Result<(), GenericError> {
+        const MARKER: u64 = 0x7472_6163_6500;
+        let enabled = is_enabled();
+        set_enabled(true);
+        for i in 0..3 {
+            record(TraceEvent::SyscallEnter, MARKER, i);
+        }
+        set_enabled(enabled);
+
+        let records = snapshot()
+            .into_iter()
+            .filter(|record| record.args[0] == MARKER)
+            .collect::<Vec<_>>();
+        ensure_whatever!(records.len() == 3, "{} events recorded", records.len());
+        for (i, record) in (0..).zip(&records) {
+            ensure_whatever!(
+                record.event == TraceEvent::SyscallEnter && record.args[1] == i,
+                "unexpected event {record}"
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,368 @@
+//! Line discipline between a serial device and its readers.
+//!
+//! In the canonical mode, the input is edited a line at a time and passed to
+//! the readers when the line is completed, and the interrupt characters are
+//! turned into signals for the foreground process group. In the raw mode, the
+//! received bytes are passed as they are.
+
+use alloc::{collections::vec_deque::VecDeque, string::String, sync::Arc, vec::Vec};
+
+use spin::Once;
+
+use crate::{
+    chosen, console,
+    drivers::serial::{self, SerialDevice},
+    sync::spinlock::SpinMutex,
+    user::ProcessId,
+};
+
+/// Maximum length of an edited line; the bytes beyond it are dropped.
+const MAX_LINE_LEN: usize = 1024;
+
+const CTRL_C: u8 = 0x03;
+const BACKSPACE: u8 = 0x08;
+const BELL: u8 = 0x07;
+const CTRL_U: u8 = 0x15;
+const CTRL_BACKSLASH: u8 = 0x1c;
+const DELETE: u8 = 0x7f;
+
+static CONSOLE: Once<Arc<Tty>> = Once::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TtyMode {
+    /// The input is edited and read a line at a time.
+    Canonical,
+    /// The input is read as received, without echo and signals.
+    Raw,
+}
+
+/// Signal raised by an interrupt character in the canonical mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TtySignal {
+    /// Raised by Ctrl-C.
+    Interrupt,
+    /// Raised by Ctrl-\.
+    Quit,
+}
+
+/// Hook called with a signal and the foreground process group when an
+/// interrupt character is received.
+///
+/// The hook is called from the reading task, without the TTY locked.
+pub type SignalHook = Arc<dyn Fn(TtySignal, Option<ProcessId>) + Send + Sync>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EditEvent {
+    LineCompleted,
+    Signal(TtySignal),
+}
+
+/// Line being edited in the canonical mode.
+#[derive(Debug, Default)]
+struct LineEditor {
+    line: Vec<u8>,
+}
+
+impl LineEditor {
+    /// Applies an input byte to the line, appending the bytes to echo to
+    /// `echo`.
+    ///
+    /// The line is cleared when a signal is raised, and left as it is when
+    /// the line is completed.
+    fn feed(&mut self, byte: u8, echo: &mut Vec<u8>) -> Option<EditEvent> {
+        match byte {
+            b'\r' | b'\n' => {
+                echo.extend_from_slice(b"\r\n");
+                return Some(EditEvent::LineCompleted);
+            }
+            BACKSPACE | DELETE => {
+                if self.line.pop().is_some() {
+                    echo.extend_from_slice(b"\x08 \x08");
+                }
+            }
+            CTRL_U => {
+                for _ in self.line.drain(..) {
+                    echo.extend_from_slice(b"\x08 \x08");
+                }
+            }
+            CTRL_C | CTRL_BACKSLASH => {
+                let (signal, name) = if byte == CTRL_C {
+                    (TtySignal::Interrupt, b"^C")
+                } else {
+                    (TtySignal::Quit, b"^\\")
+                };
+                echo.extend_from_slice(name);
+                echo.extend_from_slice(b"\r\n");
+                self.line.clear();
+                return Some(EditEvent::Signal(signal));
+            }
+            b' '..=b'~' => {
+                if self.line.len() < MAX_LINE_LEN {
+                    self.line.push(byte);
+                    echo.push(byte);
+                } else {
+                    echo.push(BELL);
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+}
+
+#[derive(Default)]
+struct TtyState {
+    echo: bool,
+    raw: bool,
+    foreground: Option<ProcessId>,
+    signal_hook: Option<SignalHook>,
+    /// Bytes of the completed lines not read yet by [`Tty::read`].
+    pending: VecDeque<u8>,
+}
+
+/// Serial device with a line discipline.
+pub struct Tty {
+    input: Arc<SerialDevice>,
+    output: Arc<SerialDevice>,
+    state: SpinMutex<TtyState>,
+}
+
+impl Tty {
+    /// Creates a TTY in the canonical mode with echo, reading from `input`
+    /// and echoing to `output`.
+    pub fn new(input: Arc<SerialDevice>, output: Arc<SerialDevice>) -> Self {
+        Self {
+            input,
+            output,
+            state: SpinMutex::new(TtyState {
+                echo: true,
+                ..TtyState::default()
+            }),
+        }
+    }
+
+    #[expect(dead_code)]
+    pub fn mode(&self) -> TtyMode {
+        if self.state.lock().raw {
+            TtyMode::Raw
+        } else {
+            TtyMode::Canonical
+        }
+    }
+
+    /// Switches the mode.
+    ///
+    /// The input of the completed lines not read yet is kept, and read in the
+    /// raw mode as well.
+    #[expect(dead_code)]
+    pub fn set_mode(&self, mode: TtyMode) {
+        self.state.lock().raw = mode == TtyMode::Raw;
+    }
+
+    /// Enables echoing the edited line in the canonical mode.
+    #[expect(dead_code)]
+    pub fn set_echo(&self, echo: bool) {
+        self.state.lock().echo = echo;
+    }
+
+    /// Sets the process group that the signals are sent to.
+    pub fn set_foreground(&self, group: Option<ProcessId>) {
+        self.state.lock().foreground = group;
+    }
+
+    pub fn set_signal_hook(&self, hook: Option<SignalHook>) {
+        self.state.lock().signal_hook = hook;
+    }
+
+    /// Passes the bytes typed on another input device, such as a keyboard, as
+    /// if they were received by the input device.
+    #[cfg_attr(not(feature = "input"), expect(dead_code))]
+    pub fn receive_input(&self, bytes: &[u8]) {
+        self.input.push_received(bytes);
+    }
+
+    /// Reads the input, waiting until at least one byte is available.
+    ///
+    /// In the canonical mode, this waits until a line is completed, and
+    /// returns the line terminated by `\n`, which may be split across
+    /// several reads. Returns the signal if the line is discarded by an
+    /// interrupt character.
+    pub fn read(&self, bytes: &mut [u8]) -> Result<usize, TtySignal> {
+        if bytes.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            let mut state = self.state.lock();
+            if !state.pending.is_empty() {
+                let nread = usize::min(bytes.len(), state.pending.len());
+                for (dst, src) in bytes.iter_mut().zip(state.pending.drain(..nread)) {
+                    *dst = src;
+                }
+                return Ok(nread);
+            }
+            let raw = state.raw;
+            state.unlock();
+
+            if raw {
+                return Ok(self.input.read(bytes));
+            }
+            let mut line = Vec::new();
+            self.edit_line(&mut line)?;
+            line.push(b'\n');
+            self.state.lock().pending.extend(line);
+        }
+    }
+
+    /// Reads a line in the canonical mode, without the line terminator.
+    ///
+    /// Returns the signal if the line is discarded by an interrupt character.
+    #[cfg_attr(not(feature = "shell"), expect(dead_code))]
+    pub fn read_line(&self, line: &mut String) -> Result<(), TtySignal> {
+        let mut bytes = Vec::new();
+        self.edit_line(&mut bytes)?;
+        // only the printable ASCII characters are accepted
+        line.push_str(str::from_utf8(&bytes).unwrap());
+        Ok(())
+    }
+
+    fn edit_line(&self, line: &mut Vec<u8>) -> Result<(), TtySignal> {
+        let mut editor = LineEditor {
+            line: core::mem::take(line),
+        };
+        let mut echo = Vec::new();
+        loop {
+            // read a byte at a time to leave the bytes after the line in the
+            // device for the next read
+            let mut bytes = [0; 1];
+            if self.input.read(&mut bytes) == 0 {
+                continue;
+            }
+            let event = editor.feed(bytes[0], &mut echo);
+            if self.state.lock().echo {
+                self.write_all(&echo);
+            }
+            echo.clear();
+            match event {
+                Some(EditEvent::LineCompleted) => {
+                    *line = editor.line;
+                    return Ok(());
+                }
+                Some(EditEvent::Signal(signal)) => {
+                    self.raise(signal);
+                    return Err(signal);
+                }
+                None => {}
+            }
+        }
+    }
+
+    fn raise(&self, signal: TtySignal) {
+        let state = self.state.lock();
+        let foreground = state.foreground;
+        let hook = state.signal_hook.clone();
+        state.unlock();
+        if let Some(hook) = hook {
+            hook(signal, foreground);
+        }
+    }
+
+    fn write_all(&self, mut bytes: &[u8]) {
+        // the echo is shown on the displays as well, for the input typed on a
+        // keyboard
+        console::write_to_sinks(bytes);
+        while !bytes.is_empty() {
+            let nwritten = self.output.write(bytes);
+            bytes = &bytes[nwritten..];
+        }
+    }
+}
+
+/// Returns the TTY of the kernel console, reading from the stdin device and
+/// echoing to the stdout device.
+///
+/// Returns `None` if the devices are not found.
+pub fn console() -> Option<&'static Arc<Tty>> {
+    CONSOLE
+        .try_call_once(|| {
+            let output = chosen::stdout_path().and_then(serial::find_serial_by_dtree_path);
+            let input = chosen::stdin_path().and_then(serial::find_serial_by_dtree_path);
+            let (Some(input), Some(output)) = (input, output) else {
+                return Err(());
+            };
+            Ok(Arc::new(Tty::new(input, output)))
+        })
+        .ok()
+}
+
+#[cfg(feature = "ktest")]
+pub mod ktests {
+    use alloc::vec::Vec;
+
+    use snafu::ensure_whatever;
+
+    use super::{EditEvent, LineEditor, MAX_LINE_LEN, TtySignal};
+    use crate::{error::GenericError, ktest::KernelTest};
+
+    pub static TESTS: &[KernelTest] = kernel_tests![edit_line, kill_line, interrupt, long_line];
+
+    fn feed_all(editor: &mut LineEditor, input: &[u8]) -> (Option<EditEvent>, Vec<u8>) {
+        let mut echo = Vec::new();
+        let mut last = None;
+        for &byte in input {
+            last = editor.feed(byte, &mut echo);
+        }
+        (last, echo)
+    }
+
+    fn edit_line() -> Result<(), GenericError> {
+        let mut editor = LineEditor::default();
+        let (event, echo) = feed_all(&mut editor, b"lx\x7fs\x01\r");
+        ensure_whatever!(
+            event == Some(EditEvent::LineCompleted),
+            "unexpected event: {event:?}"
+        );
+        ensure_whatever!(editor.line == b"ls", "unexpected line: {:?}", editor.line);
+        ensure_whatever!(echo == b"lx\x08 \x08s\r\n", "unexpected echo: {echo:?}");
+        Ok(())
+    }
+
+    fn kill_line() -> Result<(), GenericError> {
+        let mut editor = LineEditor::default();
+        let (event, echo) = feed_all(&mut editor, b"ab\x15");
+        ensure_whatever!(event.is_none(), "unexpected event: {event:?}");
+        ensure_whatever!(editor.line.is_empty(), "line not killed");
+        ensure_whatever!(echo == b"ab\x08 \x08\x08 \x08", "unexpected echo: {echo:?}");
+        Ok(())
+    }
+
+    fn interrupt() -> Result<(), GenericError> {
+        let mut editor = LineEditor::default();
+        let (event, echo) = feed_all(&mut editor, b"sleep\x03");
+        ensure_whatever!(
+            event == Some(EditEvent::Signal(TtySignal::Interrupt)),
+            "unexpected event: {event:?}"
+        );
+        ensure_whatever!(editor.line.is_empty(), "line not discarded");
+        ensure_whatever!(echo.ends_with(b"^C\r\n"), "unexpected echo: {echo:?}");
+
+        let (event, _echo) = feed_all(&mut editor, b"\x1c");
+        ensure_whatever!(
+            event == Some(EditEvent::Signal(TtySignal::Quit)),
+            "unexpected event: {event:?}"
+        );
+        Ok(())
+    }
+
+    fn long_line() -> Result<(), GenericError> {
+        let mut editor = LineEditor::default();
+        let input = [b'a'; MAX_LINE_LEN + 1];
+        let (_event, echo) = feed_all(&mut editor, &input);
+        ensure_whatever!(
+            editor.line.len() == MAX_LINE_LEN,
+            "unexpected line length {}",
+            editor.line.len()
+        );
+        ensure_whatever!(echo.last() == Some(&0x07), "bell not echoed");
+        Ok(())
+    }
+}
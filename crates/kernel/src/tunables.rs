@@ -0,0 +1,271 @@
+//! Named parameters of the subsystems adjustable at runtime.
+//!
+//! Each tunable is a static [`Tunable`] listed in [`TUNABLES`]. Its value is
+//! read by the subsystem with [`Tunable::get`], and can be changed with the
+//! `sysctl` shell command, or at boot by giving `<name>=<value>` on the kernel
+//! command line.
+
+use core::{
+    fmt,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
+
+use snafu::{ensure_whatever, whatever};
+
+use crate::{cpu::idle, drivers::serial, error::GenericError, interrupt::timer, log, power};
+
+/// Tunables of the kernel.
+static TUNABLES: &[&dyn DynTunable] = &[
+    &log::LOG_LEVEL,
+    &timer::SCHED_SLICE_MS,
+    &idle::MAX_STATE,
+    &serial::DEFAULT_BAUD_RATE,
+    &power::PANIC_REBOOT_SECS,
+];
+
+/// Type of the value of a tunable.
+///
+/// The value is stored in an `AtomicU64`, so that it can be read from any
+/// context without locking.
+pub trait TunableValue: Copy + Send + Sync + 'static {
+    fn parse(value: &str) -> Result<Self, GenericError>;
+    fn fmt(self, f: &mut fmt::Formatter<'_>) -> fmt::Result;
+    fn into_raw(self) -> u64;
+    fn from_raw(raw: u64) -> Self;
+}
+
+/// Runtime adjustable parameter.
+#[derive(Debug)]
+pub struct Tunable<T> {
+    name: &'static str,
+    description: &'static str,
+    default: T,
+    check: Option<fn(T) -> Result<(), GenericError>>,
+    /// Whether `value` holds a value set after the boot.
+    is_set: AtomicBool,
+    value: AtomicU64,
+}
+
+impl<T> Tunable<T>
+where
+    T: TunableValue,
+{
+    pub const fn new(name: &'static str, description: &'static str, default: T) -> Self {
+        Self {
+            name,
+            description,
+            default,
+            check: None,
+            is_set: AtomicBool::new(false),
+            value: AtomicU64::new(0),
+        }
+    }
+
+    /// Sets the function validating the values given to [`Self::set`].
+    pub const fn with_check(mut self, check: fn(T) -> Result<(), GenericError>) -> Self {
+        self.check = Some(check);
+        self
+    }
+
+    pub fn get(&self) -> T {
+        if !self.is_set.load(Ordering::Acquire) {
+            return self.default;
+        }
+        T::from_raw(self.value.load(Ordering::Relaxed))
+    }
+
+    pub fn set(&self, value: T) -> Result<(), GenericError> {
+        if let Some(check) = self.check {
+            check(value)?;
+        }
+        self.value.store(value.into_raw(), Ordering::Relaxed);
+        self.is_set.store(true, Ordering::Release);
+        Ok(())
+    }
+}
+
+/// Type-erased [`Tunable`] for the registry.
+pub trait DynTunable: Sync {
+    fn name(&self) -> &'static str;
+    #[cfg_attr(not(feature = "shell"), expect(dead_code))]
+    fn description(&self) -> &'static str;
+    /// Writes the current value.
+    fn fmt_value(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result;
+    /// Parses and sets the value.
+    fn set_str(&self, value: &str) -> Result<(), GenericError>;
+}
+
+impl<T> DynTunable for Tunable<T>
+where
+    T: TunableValue,
+{
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn description(&self) -> &'static str {
+        self.description
+    }
+
+    fn fmt_value(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.get().fmt(f)
+    }
+
+    fn set_str(&self, value: &str) -> Result<(), GenericError> {
+        self.set(T::parse(value)?)
+    }
+}
+
+/// Displays the current value of a tunable.
+#[derive(Debug)]
+pub struct DisplayValue<'a>(pub &'a dyn DynTunable);
+
+impl fmt::Display for DisplayValue<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt_value(f)
+    }
+}
+
+impl fmt::Debug for dyn DynTunable + '_ {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tunable")
+            .field("name", &self.name())
+            .field("value", &format_args!("{}", DisplayValue(self)))
+            .finish()
+    }
+}
+
+/// Returns the tunables of the kernel.
+pub fn iter() -> impl Iterator<Item = &'static dyn DynTunable> {
+    TUNABLES.iter().copied()
+}
+
+/// Returns the tunable named `name`.
+pub fn find(name: &[u8]) -> Option<&'static dyn DynTunable> {
+    iter().find(|tunable| tunable.name().as_bytes() == name)
+}
+
+fn parse_number<T>(value: &str) -> Result<T, GenericError>
+where
+    T: core::str::FromStr,
+{
+    let Ok(number) = value.parse() else {
+        whatever!("invalid number: {value}");
+    };
+    Ok(number)
+}
+
+impl TunableValue for bool {
+    fn parse(value: &str) -> Result<Self, GenericError> {
+        match value {
+            "1" | "y" | "yes" | "on" | "true" => Ok(true),
+            "0" | "n" | "no" | "off" | "false" => Ok(false),
+            _ => {
+                whatever!("invalid boolean: {value}");
+            }
+        }
+    }
+
+    fn fmt(self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self, f)
+    }
+
+    fn into_raw(self) -> u64 {
+        self.into()
+    }
+
+    fn from_raw(raw: u64) -> Self {
+        raw != 0
+    }
+}
+
+impl TunableValue for u32 {
+    fn parse(value: &str) -> Result<Self, GenericError> {
+        parse_number(value)
+    }
+
+    fn fmt(self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self, f)
+    }
+
+    fn into_raw(self) -> u64 {
+        self.into()
+    }
+
+    #[expect(clippy::cast_possible_truncation)]
+    fn from_raw(raw: u64) -> Self {
+        raw as Self
+    }
+}
+
+impl TunableValue for u64 {
+    fn parse(value: &str) -> Result<Self, GenericError> {
+        parse_number(value)
+    }
+
+    fn fmt(self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self, f)
+    }
+
+    fn into_raw(self) -> u64 {
+        self
+    }
+
+    fn from_raw(raw: u64) -> Self {
+        raw
+    }
+}
+
+/// Checks that the value is not zero.
+pub fn non_zero<T>(value: T) -> Result<(), GenericError>
+where
+    T: TunableValue + Default + PartialEq,
+{
+    ensure_whatever!(value != T::default(), "value must not be zero");
+    Ok(())
+}
+
+#[cfg(feature = "ktest")]
+pub mod ktests {
+    use alloc::format;
+
+    use snafu::ensure_whatever;
+
+    use super::{DisplayValue, DynTunable as _, GenericError, Tunable, non_zero};
+    use crate::ktest::KernelTest;
+
+    pub static TESTS: &[KernelTest] = kernel_tests![get_and_set, set_str, registry];
+
+    fn get_and_set() -> Result<(), GenericError> {
+        let tunable = Tunable::new("test.value", "test", 5_u32).with_check(non_zero);
+        ensure_whatever!(tunable.get() == 5, "default is {}", tunable.get());
+        tunable.set(7)?;
+        ensure_whatever!(tunable.get() == 7, "set value is {}", tunable.get());
+        ensure_whatever!(tunable.set(0).is_err(), "zero accepted");
+        ensure_whatever!(tunable.get() == 7, "rejected value is stored");
+        Ok(())
+    }
+
+    fn set_str() -> Result<(), GenericError> {
+        let tunable = Tunable::new("test.flag", "test", false);
+        tunable.set_str("on")?;
+        let value = format!("{}", DisplayValue(&tunable));
+        ensure_whatever!(value == "true", "value displayed as {value}");
+        ensure_whatever!(tunable.set_str("maybe").is_err(), "maybe accepted");
+        ensure_whatever!(tunable.get(), "rejected value is stored");
+        Ok(())
+    }
+
+    fn registry() -> Result<(), GenericError> {
+        for tunable in super::iter() {
+            let found = super::find(tunable.name().as_bytes());
+            ensure_whatever!(
+                found.is_some_and(|found| found.name() == tunable.name()),
+                "{} is not found",
+                tunable.name()
+            );
+        }
+        ensure_whatever!(super::find(b"no.such").is_none(), "unknown name found");
+        Ok(())
+    }
+}
@@ -0,0 +1,203 @@
+//! Futexes, waiting for the user words to change.
+//!
+//! The tasks waiting on a word are queued in a bucket chosen by the hash of
+//! the physical address of the word, so that the processes sharing the page
+//! share the futex. The value of the word is checked with the bucket locked,
+//! so that a wakeup after the value is changed is not missed.
+
+use alloc::{
+    collections::vec_deque::VecDeque,
+    sync::{Arc, Weak},
+};
+use core::{
+    fmt, ptr,
+    sync::atomic::{AtomicU32, Ordering},
+    time::Duration,
+};
+
+use crate::{
+    interrupt::timer,
+    sync::spinlock::SpinMutex,
+    task::{self, Task, scheduler},
+};
+
+const BUCKET_COUNT: usize = 64;
+
+static BUCKETS: [SpinMutex<VecDeque<Waiter>>; BUCKET_COUNT] =
+    [const { SpinMutex::new(VecDeque::new()) }; _];
+
+#[derive(Debug)]
+struct Waiter {
+    /// Address of the word waited on.
+    key: usize,
+    task: Weak<Task>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FutexError {
+    /// The word does not hold the expected value.
+    WouldBlock,
+    /// The word is not woken up in time.
+    TimedOut,
+}
+
+impl fmt::Display for FutexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::WouldBlock => "futex value changed",
+            Self::TimedOut => "futex wait timed out",
+        };
+        f.write_str(s)
+    }
+}
+
+fn key_of(word: &AtomicU32) -> usize {
+    ptr::from_ref(word).addr()
+}
+
+fn bucket_of(key: usize) -> &'static SpinMutex<VecDeque<Waiter>> {
+    &BUCKETS[(key / size_of::<u32>()) % BUCKET_COUNT]
+}
+
+/// Blocks the current task until `word` is woken up by [`wake`], if `word`
+/// holds `expected`.
+///
+/// Fails with [`FutexError::WouldBlock`] without blocking if `word` holds
+/// another value, or with [`FutexError::TimedOut`] if `timeout` is given and
+/// elapses first.
+pub fn wait(word: &AtomicU32, expected: u32, timeout: Option<Duration>) -> Result<(), FutexError> {
+    let key = key_of(word);
+    let bucket = bucket_of(key);
+    let task = scheduler::current_task();
+    let deadline = timeout.map(|timeout| timer::now().saturating_add(timeout));
+
+    let mut waiters = bucket.lock();
+    if word.load(Ordering::SeqCst) != expected {
+        return Err(FutexError::WouldBlock);
+    }
+    waiters.push_back(Waiter {
+        key,
+        task: Arc::downgrade(&task),
+    });
+    loop {
+        if let Some(deadline) = deadline {
+            if timer::now() >= deadline {
+                waiters.retain(|waiter| !ptr::eq(waiter.task.as_ptr(), Arc::as_ptr(&task)));
+                return Err(FutexError::TimedOut);
+            }
+            timer::arm_wakeup(&task, deadline);
+        }
+
+        let mut shared = task.shared.lock();
+        waiters.unlock();
+        task::pause(&mut shared);
+        shared.unlock();
+
+        // the waiter is dequeued when woken up, and the task may be resumed
+        // for other reasons
+        waiters = bucket.lock();
+        if !waiters
+            .iter()
+            .any(|waiter| ptr::eq(waiter.task.as_ptr(), Arc::as_ptr(&task)))
+        {
+            return Ok(());
+        }
+    }
+}
+
+/// Wakes up at most `count` tasks waiting on `word`, in the order they
+/// started waiting.
+///
+/// Returns the number of the tasks woken up.
+pub fn wake(word: &AtomicU32, count: usize) -> usize {
+    let key = key_of(word);
+    let mut waiters = bucket_of(key).lock();
+    let mut woken = 0;
+    waiters.retain(|waiter| {
+        if woken == count || waiter.key != key {
+            return true;
+        }
+        if let Some(task) = Weak::upgrade(&waiter.task) {
+            let mut shared = task.shared.lock();
+            task::resume(&mut shared);
+            woken += 1;
+        }
+        false
+    });
+    waiters.unlock();
+    woken
+}
+
+#[cfg(feature = "ktest")]
+pub mod ktests {
+    use alloc::sync::Arc;
+    use core::{sync::atomic::AtomicU32, time::Duration};
+
+    use snafu::ensure_whatever;
+
+    use super::{FutexError, wait, wake};
+    use crate::{
+        error::GenericError,
+        interrupt::timer::{self, Instant},
+        ktest::KernelTest,
+        task::kthread,
+    };
+
+    pub static TESTS: &[KernelTest] = kernel_tests![
+        wait_fails_on_changed_value,
+        wait_times_out,
+        wake_resumes_waiter,
+        wake_without_waiters,
+    ];
+
+    fn wait_fails_on_changed_value() -> Result<(), GenericError> {
+        let word = AtomicU32::new(1);
+        let res = wait(&word, 0, None);
+        ensure_whatever!(res == Err(FutexError::WouldBlock), "wait returned {res:?}");
+        Ok(())
+    }
+
+    fn wait_times_out() -> Result<(), GenericError> {
+        const TIMEOUT: Duration = Duration::from_millis(20);
+
+        let word = AtomicU32::new(0);
+        let start = Instant::now();
+        let res = wait(&word, 0, Some(TIMEOUT));
+        let elapsed = start.elapsed();
+        ensure_whatever!(res == Err(FutexError::TimedOut), "wait returned {res:?}");
+        ensure_whatever!(elapsed >= TIMEOUT, "timed out after {elapsed:?}");
+        ensure_whatever!(wake(&word, 1) == 0, "timed out waiter left queued");
+        Ok(())
+    }
+
+    fn wake_resumes_waiter() -> Result<(), GenericError> {
+        const TIMEOUT: Duration = Duration::from_secs(1);
+
+        let word = Arc::new(AtomicU32::new(0));
+        let handle = kthread::Builder::new().name("ktest-futex").spawn({
+            let word = Arc::clone(&word);
+            move || wait(&word, 0, None)
+        })?;
+
+        // the waiter may not be queued yet
+        let start = Instant::now();
+        while wake(&word, 1) == 0 {
+            ensure_whatever!(
+                !handle.is_finished(),
+                "waiter returned without being woken up"
+            );
+            ensure_whatever!(start.elapsed() < TIMEOUT, "no waiter in {TIMEOUT:?}");
+            timer::sleep(Duration::from_millis(1));
+        }
+        let res = handle.join();
+        ensure_whatever!(res.is_ok(), "wait returned {res:?}");
+        Ok(())
+    }
+
+    fn wake_without_waiters() -> Result<(), GenericError> {
+        let word = AtomicU32::new(0);
+        let woken = wake(&word, usize::MAX);
+        ensure_whatever!(woken == 0, "woke up {woken} tasks");
+        Ok(())
+    }
+}
@@ -0,0 +1,57 @@
+use core::{arch::global_asm, ptr, slice};
+
+use super::syscall::{SYS_EXIT, SYS_SLEEP, SYS_WRITE, SYS_YIELD};
+
+// Built-in user program started by the kernel.
+//
+// The program prints a message a few times, then exits. It is position
+// independent so that it can be copied to the user space.
+global_asm!(
+    ".pushsection .rodata.user_init, \"a\"",
+    ".option push",
+    ".option norelax",
+    ".balign 4",
+    ".global user_init_start",
+    "user_init_start:",
+    "    li s0, 3",
+    "1:",
+    "    li a7, {sys_write}",
+    "    li a0, 1",
+    "    lla a1, 2f",
+    "    li a2, 3f - 2f",
+    "    ecall",
+    "    li a7, {sys_sleep}",
+    "    li a0, {sleep_nanos}",
+    "    ecall",
+    "    li a7, {sys_yield}",
+    "    ecall",
+    "    addi s0, s0, -1",
+    "    bnez s0, 1b",
+    "    li a7, {sys_exit}",
+    "    li a0, 0",
+    "    ecall",
+    "    unimp",
+    "2:",
+    "    .ascii \"Hello from user mode!\\n\"",
+    "3:",
+    ".global user_init_end",
+    "user_init_end:",
+    ".option pop",
+    ".popsection",
+    sys_exit = const SYS_EXIT,
+    sys_write = const SYS_WRITE,
+    sys_yield = const SYS_YIELD,
+    sys_sleep = const SYS_SLEEP,
+    sleep_nanos = const 500_000_000,
+);
+
+unsafe extern "C" {
+    static user_init_start: u8;
+    static user_init_end: u8;
+}
+
+pub(super) fn program() -> &'static [u8] {
+    let start = ptr::addr_of!(user_init_start);
+    let end = ptr::addr_of!(user_init_end);
+    unsafe { slice::from_raw_parts(start, end.addr() - start.addr()) }
+}
@@ -0,0 +1,771 @@
+use alloc::{boxed::Box, format, sync::Arc};
+use core::ops::Range;
+
+use cpio::FileType;
+use elf::{EM_RISCV, Elf, ProgramHeader, SegmentFlags, SegmentType};
+use platform_cast::CastFrom as _;
+use riscv::interrupt::{Exception, Trap};
+use snafu::{OptionExt as _, ResultExt as _, ensure_whatever};
+use sv39::MapPageFlags;
+
+pub use self::process::ProcessId;
+use self::{
+    process::{Process, RegionKind},
+    signal::Signal,
+};
+use crate::{
+    error::GenericError,
+    initramfs,
+    interrupt::trap::{
+        self, UserTrapFrame,
+        fault::{AccessType, Fault},
+    },
+    memory::{Align as _, PAGE_SIZE},
+    stats::{self, Counter},
+    task::kthread::{self, JoinHandle},
+    tty::{self, TtySignal},
+};
+
+pub mod futex;
+mod init;
+mod process;
+pub mod signal;
+mod syscall;
+pub mod wait;
+
+/// Virtual address range available to U-mode.
+///
+/// The range does not overlap the kernel mappings, which are shared with every
+/// user page table.
+const USER_SPACE: Range<usize> = 0x0000_0020_0000_0000..0x0000_0040_0000_0000;
+const _: () =
+    assert!(USER_SPACE.start.is_multiple_of(1 << 30) && USER_SPACE.end.is_multiple_of(1 << 30));
+const USER_TEXT_START: usize = USER_SPACE.start;
+const USER_STACK_SIZE: usize = 16 * PAGE_SIZE;
+// leave an unmapped guard page at the end of the user space.
+const USER_STACK_TOP: usize = USER_SPACE.end - PAGE_SIZE;
+const USER_STACK_BOTTOM: usize = USER_STACK_TOP - USER_STACK_SIZE;
+
+/// Spawns the first user task.
+///
+/// The ELF executable `/init` in the initramfs is used if exists, otherwise
+/// the built-in program is used. The process group of init becomes the
+/// foreground of the console.
+pub fn spawn_init() -> Result<ProcessId, GenericError> {
+    let mut process = Process::new().whatever_context("failed to create process")?;
+    let id = process.id();
+    let frame = if let Some(node) = initramfs::lookup("/init")
+        && node.file_type() == FileType::Regular
+    {
+        info!("starting /init from initramfs");
+        let elf = parse_executable(node.data()).whatever_context("invalid /init")?;
+        load_elf(&mut process, &elf)?
+    } else {
+        load_program(&mut process, init::program())?
+    };
+    start(process, &frame, None, kthread::Builder::new().name("user"))?;
+    if let Some(console) = tty::console() {
+        console.set_signal_hook(Some(Arc::new(raise_tty_signal)));
+        console.set_foreground(Some(id));
+    }
+    Ok(id)
+}
+
+/// Maps `program` and the stack into the empty user space of `process`, and
+/// returns the user context starting the program.
+///
+/// `program` is position independent code that is loaded at the start of the
+/// user space and executed from its first byte.
+fn load_program(process: &mut Process, program: &[u8]) -> Result<UserTrapFrame, GenericError> {
+    let text_range = USER_TEXT_START..USER_TEXT_START + program.len().next_multiple_of(PAGE_SIZE);
+    process.map_region(RegionKind::Text, text_range, MapPageFlags::URX)?;
+    process
+        .load(USER_TEXT_START, program)
+        .whatever_context("failed to load user program")?;
+    map_stack(process, USER_TEXT_START)
+}
+
+/// Parses `image` as an ELF executable for this machine, with the loadable
+/// segments in the user space.
+fn parse_executable(image: &[u8]) -> Result<Elf<'_>, GenericError> {
+    let elf = Elf::parse(image).whatever_context("invalid ELF header")?;
+    ensure_whatever!(
+        elf.file_type() == elf::FileType::Executable,
+        "not an executable, type: {:?}",
+        elf.file_type()
+    );
+    ensure_whatever!(
+        elf.machine() == EM_RISCV,
+        "unsupported machine: {}",
+        elf.machine()
+    );
+    // the loadable segments are sorted by the address, and are mapped below
+    // the stack
+    let mut min_start = USER_SPACE.start;
+    for header in elf.program_headers() {
+        let header = header.whatever_context("invalid ELF program header")?;
+        if header.segment_type() != SegmentType::Load || header.mem_size() == 0 {
+            continue;
+        }
+        let start = usize::cast_from(header.vaddr());
+        let end = start
+            .checked_add(usize::cast_from(header.mem_size()))
+            .filter(|&end| min_start <= start.page_align_down() && end <= USER_STACK_BOTTOM)
+            .with_whatever_context(|| {
+                format!("segment at {start:#x} overlaps the other mappings")
+            })?;
+        ensure_whatever!(
+            segment_map_flags(&header).is_some(),
+            "segment at {start:#x} has invalid flags {:?}",
+            header.flags()
+        );
+        min_start = end.page_align_up();
+    }
+    Ok(elf)
+}
+
+/// Returns the page flags to map the loadable segment `header` with.
+///
+/// Returns `None` if the segment has no permissions, or is writable and
+/// executable at once.
+fn segment_map_flags(header: &ProgramHeader<'_>) -> Option<MapPageFlags> {
+    let segment_flags = header.flags();
+    if !segment_flags.intersects(SegmentFlags::all())
+        || segment_flags.contains(SegmentFlags::W | SegmentFlags::X)
+    {
+        return None;
+    }
+    let mut flags = MapPageFlags::U;
+    if segment_flags.contains(SegmentFlags::R) {
+        flags |= MapPageFlags::R;
+    }
+    // writable pages must also be readable
+    if segment_flags.contains(SegmentFlags::W) {
+        flags |= MapPageFlags::RW;
+    }
+    if segment_flags.contains(SegmentFlags::X) {
+        flags |= MapPageFlags::X;
+    }
+    Some(flags)
+}
+
+/// Maps the loadable segments of `elf` and the stack into the empty user
+/// space of `process`, and returns the user context starting the program at
+/// its entry point.
+///
+/// `elf` must be checked by [`parse_executable`] beforehand, so that loading
+/// fails only if the memory runs out.
+fn load_elf(process: &mut Process, elf: &Elf<'_>) -> Result<UserTrapFrame, GenericError> {
+    for header in elf.program_headers() {
+        let header = header.whatever_context("invalid ELF program header")?;
+        if header.segment_type() != SegmentType::Load || header.mem_size() == 0 {
+            continue;
+        }
+        let start = usize::cast_from(header.vaddr());
+        let end = start
+            .checked_add(usize::cast_from(header.mem_size()))
+            .whatever_context("ELF segment overflow")?;
+        let flags = segment_map_flags(&header)
+            .with_whatever_context(|| format!("invalid flags of ELF segment at {start:#x}"))?;
+        let kind = if flags.contains(MapPageFlags::W) {
+            RegionKind::Data
+        } else {
+            RegionKind::Text
+        };
+        process.map_region(kind, start.page_align_down()..end.page_align_up(), flags)?;
+        // the rest of the segment is left zeroed by `map_region`
+        process
+            .load(start, header.data())
+            .whatever_context("failed to load ELF segment")?;
+    }
+    map_stack(process, usize::cast_from(elf.entry()))
+}
+
+/// Maps the stack into `process`, and returns the user context starting at
+/// `entry`.
+fn map_stack(process: &mut Process, entry: usize) -> Result<UserTrapFrame, GenericError> {
+    let stack_range = USER_STACK_BOTTOM..USER_STACK_TOP;
+    process.map_region(RegionKind::Stack, stack_range, MapPageFlags::URW)?;
+
+    let mut frame = UserTrapFrame::default();
+    frame.regs.sepc = entry;
+    frame.regs.sp = USER_STACK_TOP;
+    Ok(frame)
+}
+
+/// Starts a task running `process` from `frame`, as a child of `parent` if
+/// given.
+fn start(
+    process: Process,
+    frame: &UserTrapFrame,
+    parent: Option<ProcessId>,
+    builder: kthread::Builder,
+) -> Result<JoinHandle<isize>, GenericError> {
+    let id = process.id();
+    signal::register(id, process.group(), Arc::clone(process.signals()));
+    wait::register(id, parent);
+    let context = Box::new(UserContext {
+        process,
+        frame: *frame,
+        exit_code: None,
+    });
+    builder.spawn(move || user_task(context)).inspect_err(|_e| {
+        signal::unregister(id);
+        wait::exit(id, -1);
+    })
+}
+
+#[derive(Debug)]
+struct UserContext {
+    process: Process,
+    frame: UserTrapFrame,
+    exit_code: Option<isize>,
+}
+
+fn user_task(mut context: Box<UserContext>) -> isize {
+    let id = context.process.id();
+    let exit_code = loop {
+        // the pending signals are delivered on return to U-mode
+        if let Some(signal) = context.process.signals().take() {
+            info!("process {id} terminated by {signal}");
+            break signal.exit_code();
+        }
+
+        let satp = context.process.satp();
+        match trap::run_user(&mut context.frame, satp, context.process.asid_mut()) {
+            Trap::Interrupt(_) => {}
+            Trap::Exception(Exception::UserEnvCall) => {
+                // return to the instruction after `ecall`.
+                context.frame.regs.sepc += 4;
+                syscall::dispatch(&mut context);
+            }
+            Trap::Exception(e) => {
+                let fault = Fault::decode(e, &context.frame.regs);
+                if let Fault::Page { addr, access } = fault {
+                    stats::incr(Counter::PageFaults);
+                    // the first write to a page shared copy-on-write
+                    if access == AccessType::Write {
+                        match context.process.break_cow(addr) {
+                            Ok(true) => continue,
+                            Ok(false) => {}
+                            Err(e) => {
+                                warn!("process {id} failed to copy page at {addr:#x}: {e}");
+                            }
+                        }
+                    }
+                }
+                warn!(
+                    "process {id} caused {fault}, sepc={:#x}",
+                    context.frame.regs.sepc
+                );
+                context.process.signals().raise(Signal::SegmentationFault);
+            }
+        }
+        if let Some(exit_code) = context.exit_code {
+            break exit_code;
+        }
+    };
+
+    info!("process {id} exited with code {exit_code}");
+    signal::unregister(id);
+    // the user space is freed before the parent is notified
+    drop(context);
+    wait::exit(id, exit_code);
+    exit_code
+}
+
+/// Sends the signal raised by the console to the foreground process group.
+fn raise_tty_signal(signal: TtySignal, foreground: Option<ProcessId>) {
+    let signal = match signal {
+        TtySignal::Interrupt => Signal::Interrupt,
+        // SIGQUIT is not supported
+        TtySignal::Quit => return,
+    };
+    if let Some(group) = foreground {
+        signal::send_group(group, signal);
+    }
+}
+
+#[cfg(feature = "ktest")]
+pub mod ktests {
+    use alloc::vec::Vec;
+    use core::{arch::global_asm, ptr, slice, time::Duration};
+
+    use elf::{EM_RISCV, SegmentFlags};
+    use platform_cast::CastFrom as _;
+    use snafu::{OptionExt as _, ResultExt as _, ensure_whatever};
+    use sv39::MapPageFlags;
+
+    use super::{
+        Process, RegionKind, USER_STACK_TOP, USER_TEXT_START, load_elf, load_program,
+        parse_executable, start,
+        syscall::{SYS_EXIT, SYS_FORK, SYS_WAIT},
+    };
+    use crate::{
+        cpu::{self, CpuSet},
+        error::GenericError,
+        interrupt::timer::{self, Instant, SCHED_SLICE_MS},
+        ktest::KernelTest,
+        memory::{PAGE_SIZE, allocator},
+        task::{
+            self,
+            kthread::{self, JoinHandle},
+            scheduler,
+        },
+    };
+
+    pub static TESTS: &[KernelTest] = kernel_tests![
+        spinning_task_is_preempted,
+        fork_and_wait,
+        dropped_process_frees_memory,
+        fork_copies_on_write,
+        load_elf_segments,
+        reject_invalid_elf,
+    ];
+
+    // Spins for the timer ticks passed in `a0` without trapping to the kernel,
+    // then exits with 0 if the other registers still hold the values set
+    // before spinning, or 1 otherwise.
+    global_asm!(
+        ".pushsection .rodata.user_spin, \"a\"",
+        ".option push",
+        ".option norelax",
+        ".balign 4",
+        ".global user_spin_start",
+        "user_spin_start:",
+        "    li ra, 1",
+        "    li gp, 2",
+        "    li tp, 3",
+        "    li t2, 4",
+        "    li t3, 5",
+        "    li t4, 6",
+        "    li t5, 7",
+        "    li t6, 8",
+        "    li s0, 9",
+        "    li s1, 10",
+        "    li s2, 11",
+        "    li s3, 12",
+        "    li s4, 13",
+        "    li s5, 14",
+        "    li s6, 15",
+        "    li s7, 16",
+        "    li s8, 17",
+        "    li s9, 18",
+        "    li s10, 19",
+        "    li s11, 20",
+        "    li a1, 21",
+        "    li a2, 22",
+        "    li a3, 23",
+        "    li a4, 24",
+        "    li a5, 25",
+        "    li a6, 26",
+        "    li a7, 27",
+        "    rdtime t0",
+        "    add t0, t0, a0",
+        "1:",
+        "    rdtime t1",
+        "    bltu t1, t0, 1b",
+        "    li t1, 1",
+        "    bne ra, t1, 2f",
+        "    li t1, 2",
+        "    bne gp, t1, 2f",
+        "    li t1, 3",
+        "    bne tp, t1, 2f",
+        "    li t1, 4",
+        "    bne t2, t1, 2f",
+        "    li t1, 5",
+        "    bne t3, t1, 2f",
+        "    li t1, 6",
+        "    bne t4, t1, 2f",
+        "    li t1, 7",
+        "    bne t5, t1, 2f",
+        "    li t1, 8",
+        "    bne t6, t1, 2f",
+        "    li t1, 9",
+        "    bne s0, t1, 2f",
+        "    li t1, 10",
+        "    bne s1, t1, 2f",
+        "    li t1, 11",
+        "    bne s2, t1, 2f",
+        "    li t1, 12",
+        "    bne s3, t1, 2f",
+        "    li t1, 13",
+        "    bne s4, t1, 2f",
+        "    li t1, 14",
+        "    bne s5, t1, 2f",
+        "    li t1, 15",
+        "    bne s6, t1, 2f",
+        "    li t1, 16",
+        "    bne s7, t1, 2f",
+        "    li t1, 17",
+        "    bne s8, t1, 2f",
+        "    li t1, 18",
+        "    bne s9, t1, 2f",
+        "    li t1, 19",
+        "    bne s10, t1, 2f",
+        "    li t1, 20",
+        "    bne s11, t1, 2f",
+        "    li t1, 21",
+        "    bne a1, t1, 2f",
+        "    li t1, 22",
+        "    bne a2, t1, 2f",
+        "    li t1, 23",
+        "    bne a3, t1, 2f",
+        "    li t1, 24",
+        "    bne a4, t1, 2f",
+        "    li t1, 25",
+        "    bne a5, t1, 2f",
+        "    li t1, 26",
+        "    bne a6, t1, 2f",
+        "    li t1, 27",
+        "    bne a7, t1, 2f",
+        "    li a0, 0",
+        "    j 3f",
+        "2:",
+        "    li a0, 1",
+        "3:",
+        "    li a7, {sys_exit}",
+        "    ecall",
+        "    unimp",
+        ".global user_spin_end",
+        "user_spin_end:",
+        ".option pop",
+        ".popsection",
+        sys_exit = const SYS_EXIT,
+    );
+
+    // Forks a child that exits with the value stored on the stack before
+    // forking, after overwriting it. Exits with 0 if the parent reaps the
+    // child with the value, and still sees the value on its own stack, or 1
+    // otherwise.
+    global_asm!(
+        ".pushsection .rodata.user_fork, \"a\"",
+        ".option push",
+        ".option norelax",
+        ".balign 4",
+        ".global user_fork_start",
+        "user_fork_start:",
+        "    li t0, 42",
+        "    sd t0, -16(sp)",
+        "    li a7, {sys_fork}",
+        "    ecall",
+        "    bltz a0, 2f",
+        "    beqz a0, 1f",
+        // parent
+        "    mv s0, a0",
+        "    li a7, {sys_wait}",
+        "    addi a1, sp, -8",
+        "    ecall",
+        "    bne a0, s0, 2f",
+        "    li t1, 42",
+        "    ld t0, -8(sp)",
+        "    bne t0, t1, 2f",
+        "    ld t0, -16(sp)",
+        "    bne t0, t1, 2f",
+        "    li a0, 0",
+        "    j 3f",
+        // child
+        "1:",
+        "    ld a0, -16(sp)",
+        "    li t0, 43",
+        "    sd t0, -16(sp)",
+        "    j 3f",
+        "2:",
+        "    li a0, 1",
+        "3:",
+        "    li a7, {sys_exit}",
+        "    ecall",
+        "    unimp",
+        ".global user_fork_end",
+        "user_fork_end:",
+        ".option pop",
+        ".popsection",
+        sys_exit = const SYS_EXIT,
+        sys_fork = const SYS_FORK,
+        sys_wait = const SYS_WAIT,
+    );
+
+    unsafe extern "C" {
+        static user_spin_start: u8;
+        static user_spin_end: u8;
+        static user_fork_start: u8;
+        static user_fork_end: u8;
+    }
+
+    fn spin_program() -> &'static [u8] {
+        let start = ptr::addr_of!(user_spin_start);
+        let end = ptr::addr_of!(user_spin_end);
+        unsafe { slice::from_raw_parts(start, end.addr() - start.addr()) }
+    }
+
+    /// Spawns a task that runs `program` in U-mode with `arg` in `a0`, and
+    /// returns the handle to wait for its exit code.
+    fn spawn_with(
+        program: &[u8],
+        arg: usize,
+        builder: kthread::Builder,
+    ) -> Result<JoinHandle<isize>, GenericError> {
+        let mut process = Process::new().whatever_context("failed to create process")?;
+        let mut frame = load_program(&mut process, program)?;
+        frame.regs.a0 = arg;
+        start(process, &frame, None, builder)
+    }
+
+    fn fork_program() -> &'static [u8] {
+        let start = ptr::addr_of!(user_fork_start);
+        let end = ptr::addr_of!(user_fork_end);
+        unsafe { slice::from_raw_parts(start, end.addr() - start.addr()) }
+    }
+
+    fn spinning_task_is_preempted() -> Result<(), GenericError> {
+        const TIMEOUT: Duration = Duration::from_secs(10);
+
+        let cpu = cpu::current();
+        let affinity = CpuSet::single(cpu.id());
+        let spin_ms = SCHED_SLICE_MS.get() * 4;
+        let spin_ticks = cpu.timer_frequency() * spin_ms / 1000;
+
+        let spinner = spawn_with(
+            spin_program(),
+            usize::try_from(spin_ticks).unwrap(),
+            kthread::Builder::new()
+                .name("ktest-user-spin")
+                .affinity(affinity),
+        )?;
+        // the task is reaped once it exits
+        let spinner_task = task::get(spinner.id()).whatever_context("spinning task not found")?;
+        // queued after the spinner on the same CPU, runs only if the spinner
+        // is preempted
+        let other = kthread::Builder::new()
+            .name("ktest-user-other")
+            .affinity(affinity)
+            .spawn(|| {})?;
+
+        let start = Instant::now();
+        while !other.is_finished() {
+            ensure_whatever!(
+                start.elapsed() < TIMEOUT,
+                "task starved by the spinning user task"
+            );
+            timer::sleep(Duration::from_millis(1));
+        }
+        ensure_whatever!(
+            !spinner.is_finished(),
+            "spinning user task finished before the other task"
+        );
+
+        while !spinner.is_finished() {
+            ensure_whatever!(
+                start.elapsed() < TIMEOUT,
+                "spinning user task did not finish in {TIMEOUT:?}"
+            );
+            timer::sleep(Duration::from_millis(1));
+        }
+        let exit_code = spinner.join();
+        ensure_whatever!(
+            exit_code == 0,
+            "user registers not preserved across preemption"
+        );
+
+        let stats = scheduler::task_stats(&spinner_task);
+        ensure_whatever!(
+            stats.switches >= 2,
+            "spinning user task switched to {} times",
+            stats.switches
+        );
+        ensure_whatever!(
+            !stats.user_time.is_zero() && stats.user_time <= stats.runtime,
+            "user time {:?} out of range, runtime: {:?}",
+            stats.user_time,
+            stats.runtime
+        );
+        Ok(())
+    }
+
+    fn fork_and_wait() -> Result<(), GenericError> {
+        const TIMEOUT: Duration = Duration::from_secs(5);
+
+        let handle = spawn_with(
+            fork_program(),
+            0,
+            kthread::Builder::new().name("ktest-user-fork"),
+        )?;
+        let start = Instant::now();
+        while !handle.is_finished() {
+            ensure_whatever!(
+                start.elapsed() < TIMEOUT,
+                "forking user task did not finish in {TIMEOUT:?}"
+            );
+            timer::sleep(Duration::from_millis(1));
+        }
+        let exit_code = handle.join();
+        ensure_whatever!(exit_code == 0, "forking user task exited with {exit_code}");
+        Ok(())
+    }
+
+    fn dropped_process_frees_memory() -> Result<(), GenericError> {
+        const PAGES: usize = 256;
+
+        let before = allocator::stats().allocated;
+        let mut process = Process::new().whatever_context("failed to create process")?;
+        let range = USER_TEXT_START..USER_TEXT_START + PAGES * PAGE_SIZE;
+        process.map_region(RegionKind::Heap, range, MapPageFlags::URW)?;
+        let during = allocator::stats().allocated;
+        ensure_whatever!(
+            during >= before + PAGES * PAGE_SIZE,
+            "{PAGES} pages not allocated, {before} bytes before and {during} bytes after"
+        );
+
+        drop(process);
+        // allowing for the allocations of the other tasks meanwhile
+        let after = allocator::stats().allocated;
+        ensure_whatever!(
+            after < before + PAGES * PAGE_SIZE / 2,
+            "pages of the dropped process not freed, {before} bytes before and {after} bytes after"
+        );
+        Ok(())
+    }
+
+    fn fork_copies_on_write() -> Result<(), GenericError> {
+        const PAGES: usize = 256;
+
+        let mut parent = Process::new().whatever_context("failed to create process")?;
+        let range = USER_TEXT_START..USER_TEXT_START + PAGES * PAGE_SIZE;
+        parent.map_region(RegionKind::Heap, range, MapPageFlags::URW)?;
+        parent.copy_to_user(USER_TEXT_START, b"parent")?;
+
+        let before = allocator::stats().allocated;
+        let mut child = parent.fork()?;
+        let forked = allocator::stats().allocated;
+        ensure_whatever!(
+            forked < before + PAGES * PAGE_SIZE / 2,
+            "pages copied on fork, {before} bytes before and {forked} bytes after"
+        );
+
+        let mut buf = [0; 6];
+        child.copy_to_user(USER_TEXT_START, b"child!")?;
+        parent.copy_from_user(&mut buf, USER_TEXT_START)?;
+        ensure_whatever!(&buf == b"parent", "parent reads {buf:?}");
+        child.copy_from_user(&mut buf, USER_TEXT_START)?;
+        ensure_whatever!(&buf == b"child!", "child reads {buf:?}");
+
+        // the page is no longer shared, so is written in place
+        parent.copy_to_user(USER_TEXT_START, b"PARENT")?;
+        parent.copy_from_user(&mut buf, USER_TEXT_START)?;
+        ensure_whatever!(&buf == b"PARENT", "parent reads {buf:?}");
+        child.copy_from_user(&mut buf, USER_TEXT_START)?;
+        ensure_whatever!(&buf == b"child!", "child reads {buf:?}");
+
+        drop(child);
+        drop(parent);
+        // allowing for the allocations of the other tasks meanwhile
+        let after = allocator::stats().allocated;
+        ensure_whatever!(
+            after + PAGES * PAGE_SIZE / 2 < before,
+            "pages of the dropped processes not freed, {before} bytes before and {after} bytes \
+             after"
+        );
+        Ok(())
+    }
+
+    const DATA_START: usize = USER_TEXT_START + 2 * PAGE_SIZE + 0x10;
+    const DATA_MEM_SIZE: usize = 2 * PAGE_SIZE;
+
+    /// Builds an executable with an executable segment of `text` at the start
+    /// of the user space, and a segment of `data` followed by zeroes at
+    /// `DATA_START` with `data_flags`.
+    fn build_elf(machine: u16, data_flags: SegmentFlags, text: &[u8], data: &[u8]) -> Vec<u8> {
+        const HEADER_LEN: usize = 64;
+        const PROGRAM_HEADER_LEN: usize = 56;
+
+        let segments = [
+            (
+                SegmentFlags::R | SegmentFlags::X,
+                USER_TEXT_START,
+                text.len(),
+                text,
+            ),
+            (data_flags, DATA_START, DATA_MEM_SIZE, data),
+        ];
+        let mut elf = Vec::new();
+        elf.extend_from_slice(b"\x7fELF\x02\x01\x01");
+        elf.resize(16, 0);
+        elf.extend_from_slice(&2_u16.to_le_bytes());
+        elf.extend_from_slice(&machine.to_le_bytes());
+        elf.extend_from_slice(&1_u32.to_le_bytes());
+        elf.extend_from_slice(&u64::cast_from(USER_TEXT_START).to_le_bytes());
+        elf.extend_from_slice(&u64::cast_from(HEADER_LEN).to_le_bytes());
+        elf.resize(54, 0);
+        elf.extend_from_slice(&u16::try_from(PROGRAM_HEADER_LEN).unwrap().to_le_bytes());
+        elf.extend_from_slice(&u16::try_from(segments.len()).unwrap().to_le_bytes());
+        elf.resize(HEADER_LEN, 0);
+
+        let mut offset = HEADER_LEN + segments.len() * PROGRAM_HEADER_LEN;
+        for (flags, vaddr, mem_size, data) in segments {
+            elf.extend_from_slice(&1_u32.to_le_bytes());
+            elf.extend_from_slice(&flags.bits().to_le_bytes());
+            for value in [offset, vaddr, vaddr, data.len(), mem_size, PAGE_SIZE] {
+                elf.extend_from_slice(&u64::cast_from(value).to_le_bytes());
+            }
+            offset += data.len();
+        }
+        for (_flags, _vaddr, _mem_size, data) in segments {
+            elf.extend_from_slice(data);
+        }
+        elf
+    }
+
+    fn load_elf_segments() -> Result<(), GenericError> {
+        let image = build_elf(
+            EM_RISCV,
+            SegmentFlags::R | SegmentFlags::W,
+            b"text",
+            b"data",
+        );
+        let elf = parse_executable(&image)?;
+        let mut process = Process::new().whatever_context("failed to create process")?;
+        let frame = load_elf(&mut process, &elf)?;
+        ensure_whatever!(
+            frame.regs.sepc == USER_TEXT_START && frame.regs.sp == USER_STACK_TOP,
+            "unexpected start of program, sepc={:#x}, sp={:#x}",
+            frame.regs.sepc,
+            frame.regs.sp
+        );
+
+        let mut buf = [0xff; 4];
+        process.copy_from_user(&mut buf, USER_TEXT_START)?;
+        ensure_whatever!(&buf == b"text", "text segment reads {buf:?}");
+        process.copy_from_user(&mut buf, DATA_START)?;
+        ensure_whatever!(&buf == b"data", "data segment reads {buf:?}");
+        process.copy_from_user(&mut buf, DATA_START + DATA_MEM_SIZE - 4)?;
+        ensure_whatever!(buf == [0; 4], "zero-filled part reads {buf:?}");
+
+        ensure_whatever!(
+            process.copy_to_user(USER_TEXT_START, b"TEXT").is_err(),
+            "text segment is writable"
+        );
+        process.copy_to_user(DATA_START, b"DATA")?;
+        Ok(())
+    }
+
+    fn reject_invalid_elf() -> Result<(), GenericError> {
+        // x86-64
+        let image = build_elf(62, SegmentFlags::R, b"text", b"data");
+        ensure_whatever!(
+            parse_executable(&image).is_err(),
+            "executable for another machine accepted"
+        );
+        for flags in [SegmentFlags::empty(), SegmentFlags::all()] {
+            let image = build_elf(EM_RISCV, flags, b"text", b"data");
+            ensure_whatever!(
+                parse_executable(&image).is_err(),
+                "segment with flags {flags:?} accepted"
+            );
+        }
+        ensure_whatever!(
+            parse_executable(b"text").is_err(),
+            "flat program accepted as ELF"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,555 @@
+use alloc::{
+    alloc::{alloc, dealloc},
+    collections::btree_map::BTreeMap,
+    format,
+    sync::Arc,
+    vec::Vec,
+};
+use core::{
+    alloc::Layout,
+    fmt, mem,
+    ops::Range,
+    ptr,
+    sync::atomic::{AtomicU32, AtomicU64, Ordering},
+};
+
+use riscv::register::satp::Satp;
+use snafu::{OptionExt as _, ResultExt as _, ensure_whatever, whatever};
+use sv39::{
+    MapPageFlags, PageTableRoot,
+    address::{PhysAddr, PhysPageNum, VirtAddr},
+};
+
+use super::{USER_SPACE, signal::PendingSignals};
+use crate::{
+    error::GenericError,
+    memory::{Align as _, PAGE_SIZE, asid::Asid, kernel_space},
+    sync::spinlock::SpinMutex,
+    vfs::{self, FdTable, OpenMode},
+};
+
+/// Number of the processes sharing each page copy-on-write, keyed by the first
+/// physical page of the leaf mapping.
+///
+/// Pages owned by a single process are not counted.
+static SHARED_PAGES: SpinMutex<BTreeMap<PhysPageNum, usize>> = SpinMutex::new(BTreeMap::new());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ProcessId(u64);
+
+impl fmt::Display for ProcessId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl ProcessId {
+    fn new() -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        Self(id)
+    }
+
+    pub const fn value(self) -> u64 {
+        self.0
+    }
+
+    pub const fn from_raw(value: u64) -> Self {
+        Self(value)
+    }
+}
+
+/// Purpose of a memory region of a process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegionKind {
+    Text,
+    Data,
+    Stack,
+    #[cfg_attr(not(feature = "ktest"), expect(dead_code))]
+    Heap,
+}
+
+impl fmt::Display for RegionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::Text => "text",
+            Self::Data => "data",
+            Self::Stack => "stack",
+            Self::Heap => "heap",
+        };
+        f.write_str(s)
+    }
+}
+
+/// Page-aligned virtual address range mapped into a process.
+#[derive(Debug, Clone)]
+pub struct Region {
+    range: Range<usize>,
+    kind: RegionKind,
+    flags: MapPageFlags,
+}
+
+impl Region {
+    #[expect(dead_code)]
+    pub fn range(&self) -> Range<usize> {
+        self.range.clone()
+    }
+
+    #[expect(dead_code)]
+    pub fn kind(&self) -> RegionKind {
+        self.kind
+    }
+
+    #[expect(dead_code)]
+    pub fn flags(&self) -> MapPageFlags {
+        self.flags
+    }
+}
+
+/// User process, owning the address space its tasks run in.
+///
+/// The user pages, the page tables and the ASID are freed on drop.
+#[derive(Debug)]
+pub struct Process {
+    id: ProcessId,
+    /// Id of the leader of the process group.
+    group: ProcessId,
+    signals: Arc<PendingSignals>,
+    pt: PageTableRoot,
+    asid: Asid,
+    regions: BTreeMap<usize, Region>,
+    fds: FdTable,
+}
+
+impl Process {
+    pub fn new() -> Result<Self, GenericError> {
+        // stdin, stdout and stderr
+        let mut fds = FdTable::new();
+        for mode in [OpenMode::ReadOnly, OpenMode::WriteOnly, OpenMode::WriteOnly] {
+            let console =
+                vfs::open("/dev/console", mode).whatever_context("failed to open console")?;
+            fds.insert(console)
+                .whatever_context("failed to set up standard file descriptors")?;
+        }
+        let id = ProcessId::new();
+        Self::with_fds(id, id, Arc::new(PendingSignals::new()), fds)
+    }
+
+    /// Creates a process with an empty user space.
+    fn with_fds(
+        id: ProcessId,
+        group: ProcessId,
+        signals: Arc<PendingSignals>,
+        fds: FdTable,
+    ) -> Result<Self, GenericError> {
+        // the ASID in `satp` is replaced with the allocated one on each switch
+        let mut pt = PageTableRoot::new(0).whatever_context("failed to create page table")?;
+        kernel_space::share_kernel_mappings(&mut pt, USER_SPACE)?;
+
+        Ok(Self {
+            id,
+            group,
+            signals,
+            pt,
+            asid: Asid::new(),
+            regions: BTreeMap::new(),
+            fds,
+        })
+    }
+
+    /// Creates a child process in the same process group with the memory
+    /// regions shared copy-on-write, sharing the open files.
+    ///
+    /// The pages are copied by [`Process::break_cow`] on the first write.
+    pub fn fork(&mut self) -> Result<Self, GenericError> {
+        let mut child = Self::with_fds(
+            ProcessId::new(),
+            self.group,
+            Arc::new(PendingSignals::new()),
+            self.fds.clone(),
+        )?;
+        for region in self.regions.values() {
+            let range = region.range.clone();
+            let start_vpn = VirtAddr::from_addr(range.start).page_num();
+            let count = range.len() / PAGE_SIZE;
+            let shared = match self.pt.share_pages_cow(&mut child.pt, start_vpn, count) {
+                Ok(shared) => shared,
+                Err(e) => {
+                    // the pages mapped to the child so far are still owned by
+                    // the parent, so are unmapped without freeing
+                    if let Err(e) = child.pt.unmap_pages(start_vpn, count) {
+                        warn!("failed to unmap {} region {range:#x?}: {e}", region.kind);
+                    }
+                    whatever!("failed to share {} region {range:#x?}: {e}", region.kind);
+                }
+            };
+            let mut pages = SHARED_PAGES.lock();
+            for mapping in shared {
+                *pages.entry(mapping.min_phys_addr.page_num()).or_insert(1) += 1;
+            }
+            pages.unlock();
+            child.regions.insert(range.start, region.clone());
+        }
+        // the shared pages are made read-only in the parent
+        self.asid.flush()?;
+        Ok(child)
+    }
+
+    /// Creates an empty user space replacing the process on exec, keeping its
+    /// ids, pending signals and open files.
+    pub fn new_image(&self) -> Result<Self, GenericError> {
+        Self::with_fds(
+            self.id,
+            self.group,
+            Arc::clone(&self.signals),
+            self.fds.clone(),
+        )
+    }
+
+    pub fn id(&self) -> ProcessId {
+        self.id
+    }
+
+    pub fn group(&self) -> ProcessId {
+        self.group
+    }
+
+    pub fn signals(&self) -> &Arc<PendingSignals> {
+        &self.signals
+    }
+
+    pub fn satp(&self) -> Satp {
+        self.pt.satp()
+    }
+
+    pub fn asid_mut(&mut self) -> &mut Asid {
+        &mut self.asid
+    }
+
+    pub fn fds(&self) -> &FdTable {
+        &self.fds
+    }
+
+    pub fn fds_mut(&mut self) -> &mut FdTable {
+        &mut self.fds
+    }
+
+    #[expect(dead_code)]
+    pub fn regions(&self) -> impl Iterator<Item = &Region> {
+        self.regions.values()
+    }
+
+    fn find_region(&self, addr: usize) -> Option<&Region> {
+        let (_start, region) = self.regions.range(..=addr).next_back()?;
+        region.range.contains(&addr).then_some(region)
+    }
+
+    /// Allocates zeroed pages for `range` and adds them as a region of `kind`.
+    pub fn map_region(
+        &mut self,
+        kind: RegionKind,
+        range: Range<usize>,
+        flags: MapPageFlags,
+    ) -> Result<(), GenericError> {
+        ensure_whatever!(
+            !range.is_empty() && range.start.is_page_aligned() && range.end.is_page_aligned(),
+            "invalid {kind} region {range:#x?}"
+        );
+        ensure_whatever!(
+            USER_SPACE.start <= range.start && range.end <= USER_SPACE.end,
+            "{kind} region {range:#x?} is out of the user space"
+        );
+        ensure_whatever!(
+            flags.contains(MapPageFlags::U),
+            "{kind} region {range:#x?} must be accessible from U-mode"
+        );
+        let overlapping = self
+            .regions
+            .values()
+            .find(|region| region.range.start < range.end && range.start < region.range.end);
+        if let Some(region) = overlapping {
+            whatever!(
+                "{kind} region {range:#x?} overlaps {} region {:#x?}",
+                region.kind,
+                region.range
+            );
+        }
+
+        let start_vpn = VirtAddr::from_addr(range.start).page_num();
+        let count = range.len() / PAGE_SIZE;
+        let mapped = self
+            .pt
+            .allocate_pages(start_vpn, count, flags)
+            .with_whatever_context(|_| format!("failed to map {kind} region {range:#x?}"))?;
+        ensure_whatever!(
+            mapped == count,
+            "failed to map all pages of {kind} region {range:#x?}"
+        );
+
+        self.regions
+            .insert(range.start, Region { range, kind, flags });
+        Ok(())
+    }
+
+    /// Unmaps the pages of `region` from the page table, and frees them.
+    ///
+    /// The page table must not be active on any CPU, as the stale TLB entries
+    /// are left until the ASID is released.
+    fn unmap_region(&mut self, region: &Region) -> Result<(), GenericError> {
+        let range = region.range.clone();
+        let mut mappings = Vec::new();
+        let mut addr = range.start;
+        while addr < range.end {
+            match self.pt.find_mapping(VirtAddr::from_addr(addr)) {
+                Some(mapping) => {
+                    addr = mapping.max_virt_addr.value() + 1;
+                    mappings.push(mapping);
+                }
+                None => addr += PAGE_SIZE,
+            }
+        }
+
+        let start_vpn = VirtAddr::from_addr(range.start).page_num();
+        self.pt
+            .unmap_pages(start_vpn, range.len() / PAGE_SIZE)
+            .with_whatever_context(|_| {
+                format!("failed to unmap {} region {range:#x?}", region.kind)
+            })?;
+        // the pages are allocated per leaf entry by `allocate_pages`
+        for mapping in mappings {
+            if mapping.flags.contains(MapPageFlags::COW)
+                && unshare_page(mapping.min_phys_addr.page_num())
+            {
+                // still used by the other processes
+                continue;
+            }
+            let size = mapping.max_virt_addr.value() - mapping.min_virt_addr.value() + 1;
+            let layout = Layout::from_size_align(size, size).unwrap();
+            unsafe {
+                dealloc(mapping.min_phys_addr.as_mut_ptr(), layout);
+            }
+        }
+        Ok(())
+    }
+
+    /// Gives the process its own copy of the copy-on-write page at the user
+    /// address `addr`, making it writable.
+    ///
+    /// Returns `false` if the page is not copy-on-write, or is in a region
+    /// without the write permission.
+    pub fn break_cow(&mut self, addr: usize) -> Result<bool, GenericError> {
+        let Some(region) = self.find_region(addr) else {
+            return Ok(false);
+        };
+        let flags = region.flags;
+        let va = VirtAddr::from_addr(addr);
+        let Some(mapping) = self.pt.find_mapping(va) else {
+            return Ok(false);
+        };
+        if !flags.contains(MapPageFlags::W) || !mapping.flags.contains(MapPageFlags::COW) {
+            return Ok(false);
+        }
+
+        let size = mapping.max_virt_addr.value() - mapping.min_virt_addr.value() + 1;
+        let layout = Layout::from_size_align(size, size).unwrap();
+        let old_page = mapping.min_phys_addr.as_mut_ptr::<u8>();
+        let new_page = unsafe { alloc(layout) };
+        ensure_whatever!(
+            !new_page.is_null(),
+            "failed to allocate copy of user page at {addr:#x}"
+        );
+        let mut pages = SHARED_PAGES.lock();
+        let page = if let Some(count) = pages.get_mut(&mapping.min_phys_addr.page_num()) {
+            // copied under the lock, as the last sharer may write to the page
+            // once it is released
+            unsafe {
+                ptr::copy_nonoverlapping(old_page, new_page, size);
+            }
+            *count -= 1;
+            if *count == 1 {
+                pages.remove(&mapping.min_phys_addr.page_num());
+            }
+            new_page
+        } else {
+            // the other sharers have already copied or freed the page
+            unsafe {
+                dealloc(new_page, layout);
+            }
+            old_page
+        };
+        pages.unlock();
+
+        let vpn = mapping.min_virt_addr.page_num();
+        let count = size / PAGE_SIZE;
+        self.pt
+            .unmap_pages(vpn, count)
+            .with_whatever_context(|_| format!("failed to unmap user page at {addr:#x}"))?;
+        self.pt
+            .map_fixed_pages(vpn, PhysAddr::from_ptr(page).page_num(), count, flags)
+            .with_whatever_context(|_| format!("failed to remap user page at {addr:#x}"))?;
+        self.asid.flush()?;
+        Ok(true)
+    }
+
+    /// Returns the kernel pointers to the memory backing `len` bytes at the
+    /// user address `addr`, split at page boundaries.
+    ///
+    /// Fails unless the whole range is in regions with `flags`, and is mapped
+    /// in the page table.
+    fn user_chunks(
+        &self,
+        addr: usize,
+        len: usize,
+        flags: MapPageFlags,
+    ) -> Result<Vec<(*mut u8, usize)>, GenericError> {
+        let end = addr
+            .checked_add(len)
+            .whatever_context("user address range overflow")?;
+
+        let mut chunks = Vec::new();
+        let mut addr = addr;
+        while addr < end {
+            let region = self
+                .find_region(addr)
+                .with_whatever_context(|| format!("user address {addr:#x} is not mapped"))?;
+            ensure_whatever!(
+                region.flags.contains(flags),
+                "user address {addr:#x} in {} region is not accessible, flags={:?}",
+                region.kind,
+                region.flags
+            );
+            let va = VirtAddr::from_addr(addr);
+            let pa = self
+                .pt
+                .find_mapping(va)
+                .and_then(|mapping| mapping.translate(va))
+                .with_whatever_context(|| {
+                    format!("user address {addr:#x} is missing in the page table")
+                })?;
+            let chunk_len = usize::min(end - addr, PAGE_SIZE - va.offset());
+            // physical memory is identity mapped in the kernel space.
+            chunks.push((pa.as_mut_ptr::<u8>(), chunk_len));
+            addr += chunk_len;
+        }
+        Ok(chunks)
+    }
+
+    /// Copies `src` to the user address `dst`, regardless of the write
+    /// permission of the region.
+    ///
+    /// This is used to load program images into read-only regions.
+    pub fn load(&mut self, dst: usize, src: &[u8]) -> Result<(), GenericError> {
+        self.write_chunks(dst, src, MapPageFlags::U)
+    }
+
+    /// Copies `src` to the user address `dst`.
+    pub fn copy_to_user(&mut self, dst: usize, src: &[u8]) -> Result<(), GenericError> {
+        self.write_chunks(dst, src, MapPageFlags::UW)
+    }
+
+    fn write_chunks(
+        &mut self,
+        dst: usize,
+        src: &[u8],
+        flags: MapPageFlags,
+    ) -> Result<(), GenericError> {
+        let end = dst
+            .checked_add(src.len())
+            .whatever_context("user address range overflow")?;
+        for page in (dst.page_align_down()..end).step_by(PAGE_SIZE) {
+            self.break_cow(page)?;
+        }
+        let mut src = src;
+        for (ptr, len) in self.user_chunks(dst, src.len(), flags)? {
+            let (chunk, rest) = src.split_at(len);
+            unsafe {
+                ptr::copy_nonoverlapping(chunk.as_ptr(), ptr, len);
+            }
+            src = rest;
+        }
+        Ok(())
+    }
+
+    /// Fills `dst` with the bytes at the user address `src`.
+    pub fn copy_from_user(&self, dst: &mut [u8], src: usize) -> Result<(), GenericError> {
+        let mut dst = dst;
+        for (ptr, len) in self.user_chunks(src, dst.len(), MapPageFlags::UR)? {
+            let (chunk, rest) = dst.split_at_mut(len);
+            unsafe {
+                ptr::copy_nonoverlapping(ptr, chunk.as_mut_ptr(), len);
+            }
+            dst = rest;
+        }
+        Ok(())
+    }
+
+    /// Returns the 32-bit word at the user address `addr`, to be used as a
+    /// futex.
+    ///
+    /// The word is accessed through the identity mapping of the physical
+    /// memory, so its address is the physical address of the user word.
+    pub fn futex_word(&self, addr: usize) -> Result<&AtomicU32, GenericError> {
+        ensure_whatever!(
+            addr.is_multiple_of(align_of::<AtomicU32>()),
+            "misaligned futex address {addr:#x}"
+        );
+        let chunks = self.user_chunks(addr, size_of::<AtomicU32>(), MapPageFlags::UR)?;
+        // an aligned word does not cross the page boundary
+        let (ptr, _len) = chunks[0];
+        Ok(unsafe { AtomicU32::from_ptr(ptr.cast()) })
+    }
+
+    /// Reads a NUL-terminated string at the user address `src`.
+    ///
+    /// Returns the bytes before the NUL terminator. Fails if no terminator is
+    /// found in the first `max_len` bytes.
+    pub fn strncpy_from_user(&self, src: usize, max_len: usize) -> Result<Vec<u8>, GenericError> {
+        let mut bytes = Vec::new();
+        let mut addr = src;
+        while bytes.len() < max_len {
+            // read up to the page boundary to avoid faulting on the next page.
+            let len = usize::min(
+                max_len - bytes.len(),
+                addr.page_align_down() + PAGE_SIZE - addr,
+            );
+            let mut chunk = [0; PAGE_SIZE];
+            let chunk = &mut chunk[..len];
+            self.copy_from_user(chunk, addr)?;
+            if let Some(nul) = chunk.iter().position(|&b| b == 0) {
+                bytes.extend_from_slice(&chunk[..nul]);
+                return Ok(bytes);
+            }
+            bytes.extend_from_slice(chunk);
+            addr += len;
+        }
+        whatever!("user string at {src:#x} is longer than {max_len} bytes");
+    }
+}
+
+/// Drops a reference to the page shared copy-on-write at `ppn`.
+///
+/// Returns `true` if the page is still used by the other processes, or `false`
+/// if it is owned by the caller.
+fn unshare_page(ppn: PhysPageNum) -> bool {
+    let mut pages = SHARED_PAGES.lock();
+    let Some(count) = pages.get_mut(&ppn) else {
+        return false;
+    };
+    *count -= 1;
+    if *count == 1 {
+        pages.remove(&ppn);
+    }
+    true
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        for region in mem::take(&mut self.regions).into_values() {
+            if let Err(e) = self.unmap_region(&region) {
+                warn!("failed to free memory of process {}: {e}", self.id);
+            }
+        }
+        let start_vpn = VirtAddr::from_addr(USER_SPACE.start).page_num();
+        self.pt.free_tables(start_vpn, USER_SPACE.len() / PAGE_SIZE);
+    }
+}
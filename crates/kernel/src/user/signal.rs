@@ -0,0 +1,212 @@
+//! Signals sent to the user processes.
+//!
+//! A signal is recorded in the pending set of the process, and delivered when
+//! the process returns to U-mode. Only the default actions are supported,
+//! which terminate the process for all the signals.
+
+use alloc::{collections::btree_map::BTreeMap, sync::Arc};
+use core::{
+    fmt,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use super::process::ProcessId;
+use crate::sync::spinlock::SpinMutex;
+
+/// Pending signals of the running processes, and their process groups.
+static PROCESSES: SpinMutex<BTreeMap<ProcessId, Registered>> = SpinMutex::new(BTreeMap::new());
+
+#[derive(Debug)]
+struct Registered {
+    group: ProcessId,
+    pending: Arc<PendingSignals>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    /// Interrupt from the terminal, `SIGINT`.
+    Interrupt = 2,
+    /// Termination that cannot be caught, `SIGKILL`.
+    Kill = 9,
+    /// Invalid memory access, `SIGSEGV`.
+    SegmentationFault = 11,
+    /// Termination request, `SIGTERM`.
+    Terminate = 15,
+}
+
+impl Signal {
+    /// Signals in the order they are delivered.
+    const ALL: [Self; 4] = [
+        Self::Kill,
+        Self::SegmentationFault,
+        Self::Interrupt,
+        Self::Terminate,
+    ];
+
+    pub fn from_number(number: usize) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|signal| signal.number() == number)
+    }
+
+    pub fn number(self) -> usize {
+        self as usize
+    }
+
+    /// Returns the exit code of the process terminated by the signal.
+    pub fn exit_code(self) -> isize {
+        128 + self as isize
+    }
+
+    fn bit(self) -> u32 {
+        1 << self.number()
+    }
+}
+
+impl fmt::Display for Signal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::Interrupt => "SIGINT",
+            Self::Kill => "SIGKILL",
+            Self::SegmentationFault => "SIGSEGV",
+            Self::Terminate => "SIGTERM",
+        };
+        f.write_str(s)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignalError {
+    /// No running process has the id.
+    NoProcess,
+}
+
+impl fmt::Display for SignalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoProcess => f.write_str("no such process"),
+        }
+    }
+}
+
+/// Signals sent to a process and not delivered yet.
+#[derive(Debug, Default)]
+pub struct PendingSignals {
+    bits: AtomicU32,
+}
+
+impl PendingSignals {
+    pub const fn new() -> Self {
+        Self {
+            bits: AtomicU32::new(0),
+        }
+    }
+
+    pub fn raise(&self, signal: Signal) {
+        self.bits.fetch_or(signal.bit(), Ordering::Relaxed);
+    }
+
+    /// Takes the pending signal to deliver first.
+    pub fn take(&self) -> Option<Signal> {
+        let bits = self.bits.load(Ordering::Relaxed);
+        let signal = Signal::ALL
+            .into_iter()
+            .find(|signal| bits & signal.bit() != 0)?;
+        self.bits.fetch_and(!signal.bit(), Ordering::Relaxed);
+        Some(signal)
+    }
+}
+
+/// Registers a running process in `group`, receiving the signals in
+/// `pending`.
+pub fn register(id: ProcessId, group: ProcessId, pending: Arc<PendingSignals>) {
+    let prev = PROCESSES.lock().insert(id, Registered { group, pending });
+    assert!(prev.is_none(), "process {id} registered twice");
+}
+
+/// Unregisters an exited process.
+pub fn unregister(id: ProcessId) {
+    PROCESSES.lock().remove(&id);
+}
+
+/// Sends `signal` to the process.
+pub fn send(id: ProcessId, signal: Signal) -> Result<(), SignalError> {
+    let processes = PROCESSES.lock();
+    let process = processes.get(&id).ok_or(SignalError::NoProcess)?;
+    process.pending.raise(signal);
+    processes.unlock();
+    Ok(())
+}
+
+/// Sends `signal` to the processes in the group, and returns the number of
+/// them.
+pub fn send_group(group: ProcessId, signal: Signal) -> usize {
+    let processes = PROCESSES.lock();
+    let mut count = 0;
+    for process in processes.values().filter(|process| process.group == group) {
+        process.pending.raise(signal);
+        count += 1;
+    }
+    processes.unlock();
+    count
+}
+
+#[cfg(feature = "ktest")]
+pub mod ktests {
+    use alloc::sync::Arc;
+
+    use snafu::ensure_whatever;
+
+    use super::{PendingSignals, Signal, SignalError, register, send, send_group, unregister};
+    use crate::{error::GenericError, ktest::KernelTest, user::process::ProcessId};
+
+    pub static TESTS: &[KernelTest] =
+        kernel_tests![kill_delivered_first, send_to_group, send_to_exited_process,];
+
+    // not allocated to the processes in the tests
+    const LEADER: ProcessId = ProcessId::from_raw(u64::MAX - 3);
+    const MEMBER: ProcessId = ProcessId::from_raw(u64::MAX - 4);
+    const OTHER: ProcessId = ProcessId::from_raw(u64::MAX - 5);
+
+    fn kill_delivered_first() -> Result<(), GenericError> {
+        let pending = Arc::new(PendingSignals::new());
+        register(LEADER, LEADER, Arc::clone(&pending));
+        let sent = send(LEADER, Signal::Interrupt).and_then(|()| send(LEADER, Signal::Kill));
+        unregister(LEADER);
+
+        ensure_whatever!(sent.is_ok(), "send failed: {sent:?}");
+        let taken = [pending.take(), pending.take(), pending.take()];
+        ensure_whatever!(
+            taken == [Some(Signal::Kill), Some(Signal::Interrupt), None],
+            "signals taken in {taken:?}"
+        );
+        Ok(())
+    }
+
+    fn send_to_group() -> Result<(), GenericError> {
+        let pendings = [(); 3].map(|()| Arc::new(PendingSignals::new()));
+        register(LEADER, LEADER, Arc::clone(&pendings[0]));
+        register(MEMBER, LEADER, Arc::clone(&pendings[1]));
+        register(OTHER, OTHER, Arc::clone(&pendings[2]));
+        let count = send_group(LEADER, Signal::Interrupt);
+        for id in [LEADER, MEMBER, OTHER] {
+            unregister(id);
+        }
+
+        ensure_whatever!(count == 2, "sent to {count} processes");
+        let taken = pendings.each_ref().map(|pending| pending.take());
+        ensure_whatever!(
+            taken == [Some(Signal::Interrupt), Some(Signal::Interrupt), None],
+            "signals taken: {taken:?}"
+        );
+        Ok(())
+    }
+
+    fn send_to_exited_process() -> Result<(), GenericError> {
+        register(LEADER, LEADER, Arc::new(PendingSignals::new()));
+        unregister(LEADER);
+        let res = send(LEADER, Signal::Terminate);
+        ensure_whatever!(res == Err(SignalError::NoProcess), "send returned {res:?}");
+        Ok(())
+    }
+}
@@ -0,0 +1,491 @@
+use alloc::{string::String, vec, vec::Vec};
+use core::{fmt, time::Duration};
+
+use dataview::{Pod, PodMethods as _};
+use platform_cast::CastFrom as _;
+
+use super::{
+    UserContext,
+    futex::{self, FutexError},
+    process::ProcessId,
+    signal::{self, Signal, SignalError},
+    wait::{self, WaitError},
+};
+use crate::{
+    interrupt::timer,
+    stats::{self, Counter},
+    task::{kthread, scheduler},
+    vfs::{self, FileType, OpenMode, VfsError, mount::PATH_MAX},
+};
+
+#[cfg(feature = "net")]
+mod net;
+
+pub const SYS_EXIT: usize = 0;
+pub const SYS_WRITE: usize = 1;
+pub const SYS_YIELD: usize = 2;
+pub const SYS_SLEEP: usize = 3;
+pub const SYS_OPEN: usize = 4;
+pub const SYS_READ: usize = 5;
+pub const SYS_CLOSE: usize = 6;
+pub const SYS_STAT: usize = 7;
+pub const SYS_FSYNC: usize = 8;
+pub const SYS_MKDIR: usize = 9;
+#[cfg_attr(not(feature = "net"), expect(dead_code))]
+pub const SYS_SOCKET: usize = 10;
+#[cfg_attr(not(feature = "net"), expect(dead_code))]
+pub const SYS_BIND: usize = 11;
+#[cfg_attr(not(feature = "net"), expect(dead_code))]
+pub const SYS_SENDTO: usize = 12;
+#[cfg_attr(not(feature = "net"), expect(dead_code))]
+pub const SYS_RECVFROM: usize = 13;
+pub const SYS_FUTEX_WAIT: usize = 14;
+pub const SYS_FUTEX_WAKE: usize = 15;
+pub const SYS_FORK: usize = 16;
+pub const SYS_EXEC: usize = 17;
+pub const SYS_WAIT: usize = 18;
+pub const SYS_KILL: usize = 19;
+
+/// Maximum number of bytes transferred by a single `read` or `write` call.
+const IO_MAX_LEN: usize = 4096;
+
+struct Syscall {
+    number: usize,
+    name: &'static str,
+    handler: fn(&mut UserContext, &[usize; 6]) -> Result<usize, SyscallError>,
+}
+
+/// System calls, keyed by the syscall number passed in `a7`.
+///
+/// Arguments are passed in `a0`-`a5`, and the result is returned in `a0`.
+/// Errors are returned as negated error numbers.
+const SYSCALLS: &[Syscall] = &[
+    Syscall {
+        number: SYS_EXIT,
+        name: "exit",
+        handler: sys_exit,
+    },
+    Syscall {
+        number: SYS_WRITE,
+        name: "write",
+        handler: sys_write,
+    },
+    Syscall {
+        number: SYS_YIELD,
+        name: "yield",
+        handler: sys_yield,
+    },
+    Syscall {
+        number: SYS_SLEEP,
+        name: "sleep",
+        handler: sys_sleep,
+    },
+    Syscall {
+        number: SYS_OPEN,
+        name: "open",
+        handler: sys_open,
+    },
+    Syscall {
+        number: SYS_READ,
+        name: "read",
+        handler: sys_read,
+    },
+    Syscall {
+        number: SYS_CLOSE,
+        name: "close",
+        handler: sys_close,
+    },
+    Syscall {
+        number: SYS_STAT,
+        name: "stat",
+        handler: sys_stat,
+    },
+    Syscall {
+        number: SYS_FSYNC,
+        name: "fsync",
+        handler: sys_fsync,
+    },
+    Syscall {
+        number: SYS_MKDIR,
+        name: "mkdir",
+        handler: sys_mkdir,
+    },
+    Syscall {
+        number: SYS_FUTEX_WAIT,
+        name: "futex_wait",
+        handler: sys_futex_wait,
+    },
+    Syscall {
+        number: SYS_FUTEX_WAKE,
+        name: "futex_wake",
+        handler: sys_futex_wake,
+    },
+    Syscall {
+        number: SYS_FORK,
+        name: "fork",
+        handler: sys_fork,
+    },
+    Syscall {
+        number: SYS_EXEC,
+        name: "exec",
+        handler: sys_exec,
+    },
+    Syscall {
+        number: SYS_WAIT,
+        name: "wait",
+        handler: sys_wait,
+    },
+    Syscall {
+        number: SYS_KILL,
+        name: "kill",
+        handler: sys_kill,
+    },
+];
+
+/// System call tables of the subsystems, searched in order.
+const SYSCALL_TABLES: &[&[Syscall]] = &[
+    SYSCALLS,
+    #[cfg(feature = "net")]
+    net::SYSCALLS,
+];
+
+/// File status returned by `stat`.
+#[derive(Debug, Clone, Copy, Pod)]
+#[repr(C)]
+struct Stat {
+    ino: u64,
+    mode: u32,
+    reserved: u32,
+    size: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SyscallError {
+    BadAddress,
+    ExecFormat,
+    InvalidArgument,
+    NotImplemented,
+    OutOfMemory,
+    Futex(FutexError),
+    Signal(SignalError),
+    Wait(WaitError),
+    Vfs(VfsError),
+    #[cfg(feature = "net")]
+    Net(crate::net::NetError),
+}
+
+impl SyscallError {
+    fn errno(self) -> isize {
+        match self {
+            Self::BadAddress => 14,
+            Self::ExecFormat => 8,
+            Self::InvalidArgument => 22,
+            Self::NotImplemented => 38,
+            Self::OutOfMemory => 12,
+            Self::Futex(FutexError::WouldBlock) => 11,
+            Self::Futex(FutexError::TimedOut) => 110,
+            Self::Signal(SignalError::NoProcess) => 3,
+            Self::Wait(WaitError::NoChild) => 10,
+            Self::Vfs(e) => e.errno(),
+            #[cfg(feature = "net")]
+            Self::Net(e) => e.errno(),
+        }
+    }
+}
+
+impl fmt::Display for SyscallError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::BadAddress => "bad address",
+            Self::ExecFormat => "exec format error",
+            Self::InvalidArgument => "invalid argument",
+            Self::NotImplemented => "function not implemented",
+            Self::OutOfMemory => "out of memory",
+            Self::Futex(e) => return fmt::Display::fmt(e, f),
+            Self::Signal(e) => return fmt::Display::fmt(e, f),
+            Self::Wait(e) => return fmt::Display::fmt(e, f),
+            Self::Vfs(e) => return fmt::Display::fmt(e, f),
+            #[cfg(feature = "net")]
+            Self::Net(e) => return fmt::Display::fmt(e, f),
+        };
+        f.write_str(s)
+    }
+}
+
+impl From<FutexError> for SyscallError {
+    fn from(e: FutexError) -> Self {
+        Self::Futex(e)
+    }
+}
+
+impl From<SignalError> for SyscallError {
+    fn from(e: SignalError) -> Self {
+        Self::Signal(e)
+    }
+}
+
+impl From<WaitError> for SyscallError {
+    fn from(e: WaitError) -> Self {
+        Self::Wait(e)
+    }
+}
+
+impl From<VfsError> for SyscallError {
+    fn from(e: VfsError) -> Self {
+        Self::Vfs(e)
+    }
+}
+
+/// Handles the system call requested by the user context.
+pub(super) fn dispatch(context: &mut UserContext) {
+    let regs = &context.frame.regs;
+    let number = regs.a7;
+    let args = [regs.a0, regs.a1, regs.a2, regs.a3, regs.a4, regs.a5];
+    trace_event!(SyscallEnter, number, args[0]);
+    stats::incr(Counter::Syscalls);
+
+    let result = if let Some(syscall) = SYSCALL_TABLES
+        .iter()
+        .flat_map(|table| table.iter())
+        .find(|syscall| syscall.number == number)
+    {
+        (syscall.handler)(context, &args).inspect_err(|e| {
+            debug!("syscall {}({args:#x?}) failed: {e}", syscall.name);
+        })
+    } else {
+        debug!("unknown syscall {number}");
+        Err(SyscallError::NotImplemented)
+    };
+
+    context.frame.regs.a0 = match result {
+        Ok(value) => value,
+        Err(e) => e.errno().wrapping_neg().cast_unsigned(),
+    };
+    trace_event!(SyscallExit, number, context.frame.regs.a0);
+}
+
+#[expect(clippy::unnecessary_wraps)]
+fn sys_exit(context: &mut UserContext, args: &[usize; 6]) -> Result<usize, SyscallError> {
+    context.exit_code = Some(args[0].cast_signed());
+    Ok(0)
+}
+
+fn sys_write(context: &mut UserContext, args: &[usize; 6]) -> Result<usize, SyscallError> {
+    let [fd, addr, len, ..] = *args;
+    let file = context.process.fds().get(fd)?;
+    let len = usize::min(len, IO_MAX_LEN);
+    let mut data = vec![0; len];
+    context
+        .process
+        .copy_from_user(&mut data, addr)
+        .map_err(|_e| SyscallError::BadAddress)?;
+    Ok(file.write(&data)?)
+}
+
+fn sys_read(context: &mut UserContext, args: &[usize; 6]) -> Result<usize, SyscallError> {
+    let [fd, addr, len, ..] = *args;
+    let file = context.process.fds().get(fd)?;
+    let len = usize::min(len, IO_MAX_LEN);
+    let mut data = vec![0; len];
+    let nread = file.read(&mut data)?;
+    context
+        .process
+        .copy_to_user(addr, &data[..nread])
+        .map_err(|_e| SyscallError::BadAddress)?;
+    Ok(nread)
+}
+
+fn sys_open(context: &mut UserContext, args: &[usize; 6]) -> Result<usize, SyscallError> {
+    let [path_addr, flags, ..] = *args;
+    let path = read_user_path(context, path_addr)?;
+    let mode = OpenMode::from_flags(flags)?;
+    if flags & vfs::O_CREAT != 0 {
+        match vfs::create(&path, FileType::Regular) {
+            Ok(_) | Err(VfsError::AlreadyExists) => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    let file = vfs::open(&path, mode)?;
+    Ok(context.process.fds_mut().insert(file)?)
+}
+
+fn sys_close(context: &mut UserContext, args: &[usize; 6]) -> Result<usize, SyscallError> {
+    let [fd, ..] = *args;
+    context.process.fds_mut().remove(fd)?;
+    Ok(0)
+}
+
+fn sys_stat(context: &mut UserContext, args: &[usize; 6]) -> Result<usize, SyscallError> {
+    let [path_addr, stat_addr, ..] = *args;
+    let path = read_user_path(context, path_addr)?;
+    let metadata = vfs::stat(&path)?;
+    let stat = Stat {
+        ino: metadata.ino,
+        mode: metadata.mode(),
+        reserved: 0,
+        size: u64::cast_from(metadata.size),
+    };
+    context
+        .process
+        .copy_to_user(stat_addr, stat.as_bytes())
+        .map_err(|_e| SyscallError::BadAddress)?;
+    Ok(0)
+}
+
+fn sys_fsync(context: &mut UserContext, args: &[usize; 6]) -> Result<usize, SyscallError> {
+    let [fd, ..] = *args;
+    context.process.fds().get(fd)?.sync()?;
+    Ok(0)
+}
+
+fn sys_mkdir(context: &mut UserContext, args: &[usize; 6]) -> Result<usize, SyscallError> {
+    let [path_addr, ..] = *args;
+    let path = read_user_path(context, path_addr)?;
+    vfs::create(&path, FileType::Directory)?;
+    Ok(0)
+}
+
+fn read_user_path(context: &UserContext, addr: usize) -> Result<String, SyscallError> {
+    let bytes = context
+        .process
+        .strncpy_from_user(addr, PATH_MAX)
+        .map_err(|_e| SyscallError::BadAddress)?;
+    String::from_utf8(bytes).map_err(|_e| SyscallError::InvalidArgument)
+}
+
+#[expect(clippy::unnecessary_wraps)]
+fn sys_yield(_context: &mut UserContext, _args: &[usize; 6]) -> Result<usize, SyscallError> {
+    let task = scheduler::current_task();
+    let mut shared = task.shared.lock();
+    scheduler::yield_execution(&mut shared);
+    shared.unlock();
+    Ok(0)
+}
+
+#[expect(clippy::unnecessary_wraps)]
+fn sys_sleep(_context: &mut UserContext, args: &[usize; 6]) -> Result<usize, SyscallError> {
+    let nanos = u64::cast_from(args[0]);
+    timer::sleep(Duration::from_nanos(nanos));
+    Ok(0)
+}
+
+/// Blocks until the 32-bit word at `addr` is woken up, if it holds
+/// `expected`.
+///
+/// `timeout` is in nanoseconds, and 0 waits without a timeout.
+fn sys_futex_wait(context: &mut UserContext, args: &[usize; 6]) -> Result<usize, SyscallError> {
+    let [addr, expected, timeout, ..] = *args;
+    if !addr.is_multiple_of(size_of::<u32>()) {
+        return Err(SyscallError::InvalidArgument);
+    }
+    let expected = u32::try_from(expected).map_err(|_e| SyscallError::InvalidArgument)?;
+    let timeout = (timeout != 0).then(|| Duration::from_nanos(u64::cast_from(timeout)));
+    let word = context
+        .process
+        .futex_word(addr)
+        .map_err(|_e| SyscallError::BadAddress)?;
+    futex::wait(word, expected, timeout)?;
+    Ok(0)
+}
+
+/// Wakes up at most `count` tasks waiting on the 32-bit word at `addr`, and
+/// returns the number of the tasks woken up.
+fn sys_futex_wake(context: &mut UserContext, args: &[usize; 6]) -> Result<usize, SyscallError> {
+    let [addr, count, ..] = *args;
+    if !addr.is_multiple_of(size_of::<u32>()) {
+        return Err(SyscallError::InvalidArgument);
+    }
+    let word = context
+        .process
+        .futex_word(addr)
+        .map_err(|_e| SyscallError::BadAddress)?;
+    Ok(futex::wake(word, count))
+}
+
+/// Creates a child process running a copy of the current one.
+///
+/// Returns the id of the child to the parent, and 0 to the child.
+fn sys_fork(context: &mut UserContext, _args: &[usize; 6]) -> Result<usize, SyscallError> {
+    let child = context
+        .process
+        .fork()
+        .map_err(|_e| SyscallError::OutOfMemory)?;
+    let id = child.id();
+    let mut frame = context.frame;
+    frame.regs.a0 = 0;
+    super::start(
+        child,
+        &frame,
+        Some(context.process.id()),
+        kthread::Builder::new().name("user"),
+    )
+    .map_err(|_e| SyscallError::OutOfMemory)?;
+    Ok(usize::cast_from(id.value()))
+}
+
+/// Replaces the program of the current process with the ELF executable at the
+/// path, keeping the open files.
+fn sys_exec(context: &mut UserContext, args: &[usize; 6]) -> Result<usize, SyscallError> {
+    let [path_addr, ..] = *args;
+    let path = read_user_path(context, path_addr)?;
+    let program = read_program(&path)?;
+    let elf = super::parse_executable(&program).map_err(|_e| SyscallError::ExecFormat)?;
+    let mut image = context
+        .process
+        .new_image()
+        .map_err(|_e| SyscallError::OutOfMemory)?;
+    // the image is checked by `parse_executable`, so loading fails only if
+    // the memory runs out
+    let frame = super::load_elf(&mut image, &elf).map_err(|_e| SyscallError::OutOfMemory)?;
+    // the old image is freed on drop
+    context.process = image;
+    context.frame = frame;
+    // the result is returned in `a0` of the new program
+    Ok(0)
+}
+
+fn read_program(path: &str) -> Result<Vec<u8>, SyscallError> {
+    let metadata = vfs::stat(path)?;
+    match metadata.file_type {
+        FileType::Regular => {}
+        FileType::Directory => return Err(VfsError::IsDirectory.into()),
+        _ => return Err(SyscallError::InvalidArgument),
+    }
+    let file = vfs::open(path, OpenMode::ReadOnly)?;
+    let mut program = vec![0; metadata.size];
+    let mut len = 0;
+    while len < program.len() {
+        let nread = file.read(&mut program[len..])?;
+        if nread == 0 {
+            break;
+        }
+        len += nread;
+    }
+    program.truncate(len);
+    Ok(program)
+}
+
+/// Waits for the child process of the id to exit, or any child if the id is
+/// `usize::MAX`, and reaps it.
+///
+/// Returns the id of the child, and stores its exit code as `isize` at
+/// `status_addr` unless it is 0.
+fn sys_wait(context: &mut UserContext, args: &[usize; 6]) -> Result<usize, SyscallError> {
+    let [pid, status_addr, ..] = *args;
+    let child = (pid != usize::MAX).then(|| ProcessId::from_raw(u64::cast_from(pid)));
+    let (id, exit_code) = wait::wait(context.process.id(), child)?;
+    if status_addr != 0 {
+        context
+            .process
+            .copy_to_user(status_addr, &exit_code.to_ne_bytes())
+            .map_err(|_e| SyscallError::BadAddress)?;
+    }
+    Ok(usize::cast_from(id.value()))
+}
+
+fn sys_kill(_context: &mut UserContext, args: &[usize; 6]) -> Result<usize, SyscallError> {
+    let [pid, signum, ..] = *args;
+    let signal = Signal::from_number(signum).ok_or(SyscallError::InvalidArgument)?;
+    signal::send(ProcessId::from_raw(u64::cast_from(pid)), signal)?;
+    Ok(0)
+}
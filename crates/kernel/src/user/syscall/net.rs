@@ -0,0 +1,170 @@
+//! System calls of the network stack.
+
+use alloc::{sync::Arc, vec};
+
+use dataview::{Pod, PodMethods as _};
+use platform_cast::CastFrom as _;
+
+use super::{
+    IO_MAX_LEN, SYS_BIND, SYS_RECVFROM, SYS_SENDTO, SYS_SOCKET, Syscall, SyscallError, UserContext,
+};
+use crate::net::{Ipv4Addr, NetError, SocketAddrV4, socket::SocketFile};
+
+pub const AF_INET: usize = 2;
+pub const SOCK_DGRAM: usize = 2;
+const IPPROTO_UDP: usize = 17;
+
+/// System calls of the sockets.
+pub(super) const SYSCALLS: &[Syscall] = &[
+    Syscall {
+        number: SYS_SOCKET,
+        name: "socket",
+        handler: sys_socket,
+    },
+    Syscall {
+        number: SYS_BIND,
+        name: "bind",
+        handler: sys_bind,
+    },
+    Syscall {
+        number: SYS_SENDTO,
+        name: "sendto",
+        handler: sys_sendto,
+    },
+    Syscall {
+        number: SYS_RECVFROM,
+        name: "recvfrom",
+        handler: sys_recvfrom,
+    },
+];
+
+/// IPv4 socket address, with the port and the address in network byte order.
+#[derive(Debug, Clone, Copy, Pod)]
+#[repr(C)]
+struct SockAddrIn {
+    family: u16,
+    port: [u8; 2],
+    addr: [u8; 4],
+    zero: [u8; 8],
+}
+
+impl SockAddrIn {
+    fn new(addr: SocketAddrV4) -> Self {
+        Self {
+            family: u16::try_from(AF_INET).unwrap(),
+            port: addr.port().to_be_bytes(),
+            addr: addr.ip().octets(),
+            zero: [0; 8],
+        }
+    }
+
+    fn to_socket_addr(self) -> Result<SocketAddrV4, SyscallError> {
+        if usize::from(self.family) != AF_INET {
+            return Err(SyscallError::Net(NetError::InvalidArgument));
+        }
+        Ok(SocketAddrV4::new(
+            Ipv4Addr::from_octets(self.addr),
+            u16::from_be_bytes(self.port),
+        ))
+    }
+}
+
+impl From<NetError> for SyscallError {
+    fn from(e: NetError) -> Self {
+        Self::Net(e)
+    }
+}
+
+fn sys_socket(context: &mut UserContext, args: &[usize; 6]) -> Result<usize, SyscallError> {
+    let [domain, socket_type, protocol, ..] = *args;
+    if domain != AF_INET || socket_type != SOCK_DGRAM || !matches!(protocol, 0 | IPPROTO_UDP) {
+        return Err(SyscallError::Net(NetError::InvalidArgument));
+    }
+    let file = Arc::new(SocketFile::new());
+    Ok(context.process.fds_mut().insert(file)?)
+}
+
+fn sys_bind(context: &mut UserContext, args: &[usize; 6]) -> Result<usize, SyscallError> {
+    let [fd, addr, addr_len, ..] = *args;
+    let file = context.process.fds().get(fd)?;
+    let socket = SocketFile::from_file(&*file).ok_or(NetError::NotSocket)?;
+    let addr = read_user_sockaddr(context, addr, addr_len)?;
+    // only the wildcard address is supported
+    if !addr.ip().is_unspecified() {
+        return Err(SyscallError::Net(NetError::InvalidArgument));
+    }
+    socket.bind(addr.port())?;
+    Ok(0)
+}
+
+fn sys_sendto(context: &mut UserContext, args: &[usize; 6]) -> Result<usize, SyscallError> {
+    let [fd, buf, len, flags, addr, addr_len] = *args;
+    if flags != 0 {
+        return Err(SyscallError::Net(NetError::InvalidArgument));
+    }
+    let file = context.process.fds().get(fd)?;
+    let socket = SocketFile::from_file(&*file).ok_or(NetError::NotSocket)?;
+    let dst = read_user_sockaddr(context, addr, addr_len)?;
+    let len = usize::min(len, IO_MAX_LEN);
+    let mut data = vec![0; len];
+    context
+        .process
+        .copy_from_user(&mut data, buf)
+        .map_err(|_e| SyscallError::BadAddress)?;
+    Ok(socket.socket()?.send_to(&data, dst)?)
+}
+
+fn sys_recvfrom(context: &mut UserContext, args: &[usize; 6]) -> Result<usize, SyscallError> {
+    let [fd, buf, len, flags, addr, addr_len_addr] = *args;
+    if flags != 0 {
+        return Err(SyscallError::Net(NetError::InvalidArgument));
+    }
+    let file = context.process.fds().get(fd)?;
+    let socket = SocketFile::from_file(&*file).ok_or(NetError::NotSocket)?;
+    let len = usize::min(len, IO_MAX_LEN);
+    let mut data = vec![0; len];
+    let (nread, src) = socket.socket()?.recv_from(&mut data, None)?;
+    context
+        .process
+        .copy_to_user(buf, &data[..nread])
+        .map_err(|_e| SyscallError::BadAddress)?;
+    // the sender is optional
+    if addr != 0 {
+        let sockaddr = SockAddrIn::new(src);
+        let mut addr_len = [0; size_of::<u32>()];
+        context
+            .process
+            .copy_from_user(&mut addr_len, addr_len_addr)
+            .map_err(|_e| SyscallError::BadAddress)?;
+        let addr_len = usize::min(
+            usize::cast_from(u32::from_ne_bytes(addr_len)),
+            size_of::<SockAddrIn>(),
+        );
+        context
+            .process
+            .copy_to_user(addr, &sockaddr.as_bytes()[..addr_len])
+            .map_err(|_e| SyscallError::BadAddress)?;
+        let full_len = u32::try_from(size_of::<SockAddrIn>()).unwrap();
+        context
+            .process
+            .copy_to_user(addr_len_addr, &full_len.to_ne_bytes())
+            .map_err(|_e| SyscallError::BadAddress)?;
+    }
+    Ok(nread)
+}
+
+fn read_user_sockaddr(
+    context: &UserContext,
+    addr: usize,
+    addr_len: usize,
+) -> Result<SocketAddrV4, SyscallError> {
+    if addr_len < size_of::<SockAddrIn>() {
+        return Err(SyscallError::Net(NetError::InvalidArgument));
+    }
+    let mut sockaddr = dataview::zeroed::<SockAddrIn>();
+    context
+        .process
+        .copy_from_user(sockaddr.as_bytes_mut(), addr)
+        .map_err(|_e| SyscallError::BadAddress)?;
+    sockaddr.to_socket_addr()
+}
@@ -0,0 +1,180 @@
+//! Exit statuses of the processes, waited for by their parents.
+//!
+//! An exited process stays as a zombie until its parent reaps it by
+//! [`wait`]. The children of an exited process are orphaned, and are reaped
+//! as soon as they exit.
+
+use alloc::collections::btree_map::BTreeMap;
+use core::fmt;
+
+use super::process::ProcessId;
+use crate::sync::spinlock::{SpinMutex, SpinMutexCondVar};
+
+static PROCESSES: SpinMutex<BTreeMap<ProcessId, Status>> = SpinMutex::new(BTreeMap::new());
+static CHILD_EXITED: SpinMutexCondVar = SpinMutexCondVar::new();
+
+#[derive(Debug)]
+struct Status {
+    parent: Option<ProcessId>,
+    /// Exit code, set when the process exits.
+    exit_code: Option<isize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitError {
+    /// The process has no child to wait for.
+    NoChild,
+}
+
+impl fmt::Display for WaitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoChild => f.write_str("no child processes"),
+        }
+    }
+}
+
+/// Registers a running process, which is a child of `parent` if given.
+pub fn register(id: ProcessId, parent: Option<ProcessId>) {
+    let prev = PROCESSES.lock().insert(
+        id,
+        Status {
+            parent,
+            exit_code: None,
+        },
+    );
+    assert!(prev.is_none(), "process {id} registered twice");
+}
+
+/// Records the exit of the process, and wakes up its waiting parent.
+pub fn exit(id: ProcessId, exit_code: isize) {
+    let mut processes = PROCESSES.lock();
+    processes.retain(|_id, status| {
+        if status.parent != Some(id) {
+            return true;
+        }
+        status.parent = None;
+        status.exit_code.is_none()
+    });
+    match processes.get_mut(&id) {
+        Some(status) if status.parent.is_some() => status.exit_code = Some(exit_code),
+        Some(_) => {
+            processes.remove(&id);
+        }
+        None => {
+            warn!("exited process {id} is not registered");
+        }
+    }
+    processes.unlock();
+    CHILD_EXITED.notify_all();
+}
+
+/// Waits for a child of `parent` to exit, and reaps it.
+///
+/// Waits for any child if `child` is `None`. Returns the id and the exit code
+/// of the reaped child.
+pub fn wait(parent: ProcessId, child: Option<ProcessId>) -> Result<(ProcessId, isize), WaitError> {
+    let mut processes = PROCESSES.lock();
+    loop {
+        let mut has_child = false;
+        let mut exited = None;
+        for (&id, status) in processes.iter() {
+            if status.parent != Some(parent) || child.is_some_and(|child| id != child) {
+                continue;
+            }
+            has_child = true;
+            if let Some(exit_code) = status.exit_code {
+                exited = Some((id, exit_code));
+                break;
+            }
+        }
+        if let Some((id, exit_code)) = exited {
+            processes.remove(&id);
+            return Ok((id, exit_code));
+        }
+        if !has_child {
+            return Err(WaitError::NoChild);
+        }
+        processes = CHILD_EXITED.wait(processes);
+    }
+}
+
+#[cfg(feature = "ktest")]
+pub mod ktests {
+    use core::time::Duration;
+
+    use snafu::ensure_whatever;
+
+    use super::{PROCESSES, WaitError, exit, register, wait};
+    use crate::{
+        error::GenericError, interrupt::timer, ktest::KernelTest, task::kthread,
+        user::process::ProcessId,
+    };
+
+    pub static TESTS: &[KernelTest] = kernel_tests![
+        wait_reaps_exited_child,
+        wait_blocks_until_exit,
+        orphans_are_reaped,
+        exit_of_unregistered_is_ignored,
+    ];
+
+    // not allocated to the processes in the tests
+    const PARENT: ProcessId = ProcessId::from_raw(u64::MAX - 1);
+    const CHILD: ProcessId = ProcessId::from_raw(u64::MAX - 2);
+
+    fn wait_reaps_exited_child() -> Result<(), GenericError> {
+        register(PARENT, None);
+        register(CHILD, Some(PARENT));
+        exit(CHILD, 3);
+        let first = wait(PARENT, None);
+        let second = wait(PARENT, None);
+        exit(PARENT, 0);
+
+        ensure_whatever!(first == Ok((CHILD, 3)), "first wait returned {first:?}");
+        ensure_whatever!(
+            second == Err(WaitError::NoChild),
+            "second wait returned {second:?}"
+        );
+        Ok(())
+    }
+
+    fn wait_blocks_until_exit() -> Result<(), GenericError> {
+        register(PARENT, None);
+        register(CHILD, Some(PARENT));
+        let handle = kthread::Builder::new()
+            .name("ktest-wait")
+            .spawn(|| wait(PARENT, Some(CHILD)))?;
+        timer::sleep(Duration::from_millis(10));
+        let finished_early = handle.is_finished();
+        exit(CHILD, 5);
+        let res = handle.join();
+        exit(PARENT, 0);
+
+        ensure_whatever!(!finished_early, "wait returned before the child exited");
+        ensure_whatever!(res == Ok((CHILD, 5)), "wait returned {res:?}");
+        Ok(())
+    }
+
+    fn orphans_are_reaped() -> Result<(), GenericError> {
+        register(PARENT, None);
+        register(CHILD, Some(PARENT));
+        exit(PARENT, 0);
+        exit(CHILD, 1);
+
+        let processes = PROCESSES.lock();
+        let left = processes.contains_key(&PARENT) || processes.contains_key(&CHILD);
+        processes.unlock();
+        ensure_whatever!(!left, "exited processes left");
+        Ok(())
+    }
+
+    fn exit_of_unregistered_is_ignored() -> Result<(), GenericError> {
+        register(PARENT, None);
+        exit(CHILD, 1);
+        let res = wait(PARENT, None);
+        exit(PARENT, 0);
+
+        ensure_whatever!(res == Err(WaitError::NoChild), "wait returned {res:?}");
+        Ok(())
+    }
+}
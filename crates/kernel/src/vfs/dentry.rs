@@ -0,0 +1,26 @@
+use alloc::{string::String, sync::Arc};
+
+use super::Inode;
+
+/// Inode reached by path resolution, together with its normalized path.
+#[derive(Debug, Clone)]
+pub struct Dentry {
+    path: String,
+    inode: Arc<dyn Inode>,
+}
+
+impl Dentry {
+    pub(super) fn new(path: String, inode: Arc<dyn Inode>) -> Self {
+        Self { path, inode }
+    }
+
+    /// Returns the absolute path, without `.`, `..` and redundant slashes.
+    #[cfg_attr(not(feature = "shell"), expect(dead_code))]
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn inode(&self) -> &Arc<dyn Inode> {
+        &self.inode
+    }
+}
@@ -0,0 +1,192 @@
+use alloc::{collections::btree_map::BTreeMap, format, string::String, sync::Arc, vec::Vec};
+
+use super::{DirEntry, FileSystem, FileType, Inode, Metadata, VfsError};
+#[cfg(feature = "input")]
+use crate::input::{self, EventDevice};
+use crate::{
+    drivers::serial::{self, SerialDevice},
+    tty,
+};
+
+/// File system exposing the devices, mounted at `/dev`.
+///
+/// It contains `console`, the kernel console, `ttyS<N>` for each serial
+/// device, and `input/event<N>` for each input device.
+#[derive(Debug)]
+pub(super) struct DevFs {
+    root: Arc<DevDir>,
+}
+
+impl DevFs {
+    pub(super) fn new() -> Arc<Self> {
+        let mut nodes = BTreeMap::<String, Arc<dyn Inode>>::new();
+        nodes.insert("console".into(), Arc::new(ConsoleInode {}));
+        let serials = serial::devices();
+        for (i, device) in serials.iter().enumerate() {
+            nodes.insert(
+                format!("ttyS{i}"),
+                Arc::new(SerialInode {
+                    ino: DevDir::ROOT_INO + 2 + u64::try_from(i).unwrap(),
+                    device: Arc::clone(device),
+                }),
+            );
+        }
+        #[cfg(feature = "input")]
+        {
+            let mut events = BTreeMap::<String, Arc<dyn Inode>>::new();
+            let dir_ino = DevDir::ROOT_INO + 2 + u64::try_from(serials.len()).unwrap();
+            for (i, device) in input::devices().into_iter().enumerate() {
+                events.insert(
+                    format!("event{i}"),
+                    Arc::new(EventInode {
+                        ino: dir_ino + 1 + u64::try_from(i).unwrap(),
+                        device,
+                    }),
+                );
+            }
+            nodes.insert(
+                "input".into(),
+                Arc::new(DevDir {
+                    ino: dir_ino,
+                    nodes: events,
+                }),
+            );
+        }
+        Arc::new(Self {
+            root: Arc::new(DevDir {
+                ino: DevDir::ROOT_INO,
+                nodes,
+            }),
+        })
+    }
+}
+
+impl FileSystem for DevFs {
+    fn name(&self) -> &'static str {
+        "devfs"
+    }
+
+    fn root(&self) -> Arc<dyn Inode> {
+        Arc::clone(&self.root) as Arc<dyn Inode>
+    }
+}
+
+#[derive(Debug)]
+struct DevDir {
+    ino: u64,
+    nodes: BTreeMap<String, Arc<dyn Inode>>,
+}
+
+impl DevDir {
+    const ROOT_INO: u64 = 1;
+}
+
+impl Inode for DevDir {
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            ino: self.ino,
+            file_type: FileType::Directory,
+            perm: 0o755,
+            size: 0,
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Result<Arc<dyn Inode>, VfsError> {
+        self.nodes
+            .get(name)
+            .map(Arc::clone)
+            .ok_or(VfsError::NotFound)
+    }
+
+    fn read_dir(&self) -> Result<Vec<DirEntry>, VfsError> {
+        let entries = self
+            .nodes
+            .iter()
+            .map(|(name, inode)| DirEntry {
+                name: name.clone(),
+                file_type: inode.metadata().file_type,
+            })
+            .collect();
+        Ok(entries)
+    }
+}
+
+/// Kernel console.
+///
+/// Writes go to the kernel console, and reads come from the console TTY, which
+/// edits the input a line at a time.
+#[derive(Debug)]
+struct ConsoleInode {}
+
+impl Inode for ConsoleInode {
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            ino: DevDir::ROOT_INO + 1,
+            file_type: FileType::CharDevice,
+            perm: 0o620,
+            size: 0,
+        }
+    }
+
+    fn read_at(&self, _offset: usize, buf: &mut [u8]) -> Result<usize, VfsError> {
+        let Some(console) = tty::console() else {
+            // no input device, behaves as end of file
+            return Ok(0);
+        };
+        console.read(buf).map_err(|_signal| VfsError::Interrupted)
+    }
+
+    fn write_at(&self, _offset: usize, buf: &[u8]) -> Result<usize, VfsError> {
+        print!("{}", String::from_utf8_lossy(buf));
+        Ok(buf.len())
+    }
+}
+
+#[derive(Debug)]
+struct SerialInode {
+    ino: u64,
+    device: Arc<SerialDevice>,
+}
+
+impl Inode for SerialInode {
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            ino: self.ino,
+            file_type: FileType::CharDevice,
+            perm: 0o660,
+            size: 0,
+        }
+    }
+
+    fn read_at(&self, _offset: usize, buf: &mut [u8]) -> Result<usize, VfsError> {
+        Ok(self.device.read(buf))
+    }
+
+    fn write_at(&self, _offset: usize, buf: &[u8]) -> Result<usize, VfsError> {
+        Ok(self.device.write(buf))
+    }
+}
+
+/// Input device, read as [`InputEvent`](crate::input::InputEvent)s.
+#[cfg(feature = "input")]
+#[derive(Debug)]
+struct EventInode {
+    ino: u64,
+    device: Arc<EventDevice>,
+}
+
+#[cfg(feature = "input")]
+impl Inode for EventInode {
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            ino: self.ino,
+            file_type: FileType::CharDevice,
+            perm: 0o640,
+            size: 0,
+        }
+    }
+
+    fn read_at(&self, _offset: usize, buf: &mut [u8]) -> Result<usize, VfsError> {
+        Ok(self.device.read(buf))
+    }
+}
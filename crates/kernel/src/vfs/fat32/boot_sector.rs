@@ -0,0 +1,92 @@
+use platform_cast::CastFrom as _;
+
+use super::{le_u16, le_u32};
+use crate::block::SECTOR_SIZE;
+
+/// FAT32 BIOS parameter block, read from the first sector of the volume.
+///
+/// Sector numbers are relative to the start of the volume.
+#[derive(Debug, Clone)]
+pub(super) struct BootSector {
+    pub(super) sectors_per_cluster: u32,
+    pub(super) reserved_sectors: u32,
+    pub(super) num_fats: u32,
+    pub(super) fat_size: u32,
+    pub(super) total_sectors: u32,
+    pub(super) root_cluster: u32,
+    pub(super) fs_info_sector: u32,
+}
+
+impl BootSector {
+    /// Parses and validates the boot sector.
+    pub(super) fn parse(sector: &[u8; SECTOR_SIZE]) -> Result<Self, &'static str> {
+        if sector[510..512] != [0x55, 0xaa] {
+            return Err("missing boot sector signature");
+        }
+        if !matches!(sector[0], 0xeb | 0xe9) {
+            return Err("missing boot sector jump instruction");
+        }
+        if usize::from(le_u16(sector, 11)) != SECTOR_SIZE {
+            return Err("unsupported sector size");
+        }
+        let sectors_per_cluster = u32::from(sector[13]);
+        if !sectors_per_cluster.is_power_of_two() {
+            return Err("invalid number of sectors per cluster");
+        }
+        let reserved_sectors = u32::from(le_u16(sector, 14));
+        let num_fats = u32::from(sector[16]);
+        if reserved_sectors == 0 || num_fats == 0 {
+            return Err("invalid reserved sectors or number of FATs");
+        }
+        // FAT12/16 have a fixed root directory and a 16-bit FAT size
+        if le_u16(sector, 17) != 0 || le_u16(sector, 22) != 0 {
+            return Err("not a FAT32 volume");
+        }
+        let total_sectors = match le_u16(sector, 19) {
+            0 => le_u32(sector, 32),
+            n => u32::from(n),
+        };
+        let bs = Self {
+            sectors_per_cluster,
+            reserved_sectors,
+            num_fats,
+            fat_size: le_u32(sector, 36),
+            total_sectors,
+            root_cluster: le_u32(sector, 44),
+            fs_info_sector: u32::from(le_u16(sector, 48)),
+        };
+
+        let data_start = u64::from(reserved_sectors) + u64::from(num_fats) * u64::from(bs.fat_size);
+        if bs.fat_size == 0 || u64::from(total_sectors) <= data_start {
+            return Err("invalid FAT size or total sectors");
+        }
+        let fat_entries = u64::from(bs.fat_size) * (u64::cast_from(SECTOR_SIZE) / 4);
+        if fat_entries < u64::from(bs.cluster_count()) + 2 {
+            return Err("FAT is too small for the data area");
+        }
+        if !bs.is_valid_cluster(bs.root_cluster) {
+            return Err("invalid root directory cluster");
+        }
+        Ok(bs)
+    }
+
+    /// Returns the first sector of the data area.
+    pub(super) fn data_start(&self) -> u32 {
+        self.reserved_sectors + self.num_fats * self.fat_size
+    }
+
+    /// Returns the number of clusters in the data area.
+    pub(super) fn cluster_count(&self) -> u32 {
+        (self.total_sectors - self.data_start()) / self.sectors_per_cluster
+    }
+
+    pub(super) fn cluster_size(&self) -> usize {
+        usize::cast_from(self.sectors_per_cluster) * SECTOR_SIZE
+    }
+
+    /// Returns whether `cluster` is in the data area, cluster 0 and 1 being
+    /// reserved.
+    pub(super) fn is_valid_cluster(&self, cluster: u32) -> bool {
+        (2..self.cluster_count() + 2).contains(&cluster)
+    }
+}
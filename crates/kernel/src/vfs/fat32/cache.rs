@@ -0,0 +1,106 @@
+use alloc::{boxed::Box, collections::btree_map::BTreeMap, sync::Arc};
+
+use crate::block::{self, BlockDevice, BlockError, SECTOR_SIZE};
+
+/// Maximum number of cached sectors.
+const CAPACITY: usize = 256;
+
+/// Write-back cache of the device sectors.
+///
+/// Dirty sectors are written to the device when evicted or on [`Self::sync`].
+#[derive(Debug)]
+pub(super) struct SectorCache {
+    device: Arc<dyn BlockDevice>,
+    entries: BTreeMap<u64, CachedSector>,
+    tick: u64,
+}
+
+#[derive(Debug)]
+struct CachedSector {
+    data: Box<[u8; SECTOR_SIZE]>,
+    dirty: bool,
+    last_used: u64,
+}
+
+impl SectorCache {
+    pub(super) fn new(device: Arc<dyn BlockDevice>) -> Self {
+        Self {
+            device,
+            entries: BTreeMap::new(),
+            tick: 0,
+        }
+    }
+
+    pub(super) fn device(&self) -> &dyn BlockDevice {
+        &*self.device
+    }
+
+    pub(super) fn read(&mut self, sector: u64) -> Result<&[u8; SECTOR_SIZE], BlockError> {
+        Ok(&self.entry(sector, true)?.data)
+    }
+
+    pub(super) fn write(&mut self, sector: u64) -> Result<&mut [u8; SECTOR_SIZE], BlockError> {
+        let entry = self.entry(sector, true)?;
+        entry.dirty = true;
+        Ok(&mut entry.data)
+    }
+
+    /// Returns the sector for writing without reading its current contents.
+    pub(super) fn overwrite(&mut self, sector: u64) -> Result<&mut [u8; SECTOR_SIZE], BlockError> {
+        let entry = self.entry(sector, false)?;
+        entry.dirty = true;
+        Ok(&mut entry.data)
+    }
+
+    fn entry(&mut self, sector: u64, fill: bool) -> Result<&mut CachedSector, BlockError> {
+        self.tick += 1;
+        if !self.entries.contains_key(&sector) {
+            if self.entries.len() >= CAPACITY {
+                self.evict()?;
+            }
+            let mut data = Box::new([0; SECTOR_SIZE]);
+            if fill {
+                block::read(&*self.device, sector, &mut *data)?;
+            }
+            self.entries.insert(
+                sector,
+                CachedSector {
+                    data,
+                    dirty: false,
+                    last_used: 0,
+                },
+            );
+        }
+        let entry = self.entries.get_mut(&sector).unwrap();
+        entry.last_used = self.tick;
+        Ok(entry)
+    }
+
+    /// Evicts the least recently used sector.
+    fn evict(&mut self) -> Result<(), BlockError> {
+        let Some((&sector, _)) = self.entries.iter().min_by_key(|(_, entry)| entry.last_used)
+        else {
+            return Ok(());
+        };
+        let entry = &self.entries[&sector];
+        if entry.dirty {
+            block::write(&*self.device, sector, &*entry.data)?;
+        }
+        self.entries.remove(&sector);
+        Ok(())
+    }
+
+    /// Writes the dirty sectors and flushes the device write cache.
+    pub(super) fn sync(&mut self) -> Result<(), BlockError> {
+        let mut written = false;
+        for (&sector, entry) in self.entries.iter_mut().filter(|(_, entry)| entry.dirty) {
+            block::write(&*self.device, sector, &*entry.data)?;
+            entry.dirty = false;
+            written = true;
+        }
+        if written {
+            block::flush(&*self.device)?;
+        }
+        Ok(())
+    }
+}
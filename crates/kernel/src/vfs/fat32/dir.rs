@@ -0,0 +1,369 @@
+use alloc::{format, string::String, vec, vec::Vec};
+
+use super::{le_u16, le_u32, set_le_u16, set_le_u32};
+use crate::vfs::VfsError;
+
+pub(super) const ENTRY_SIZE: usize = 32;
+
+pub(super) const ATTR_READ_ONLY: u8 = 0x01;
+pub(super) const ATTR_VOLUME_ID: u8 = 0x08;
+pub(super) const ATTR_DIRECTORY: u8 = 0x10;
+pub(super) const ATTR_ARCHIVE: u8 = 0x20;
+const ATTR_LONG_NAME: u8 = 0x0f;
+const ATTR_LONG_NAME_MASK: u8 = 0x3f;
+
+/// The first name byte of a deleted entry.
+const DELETED: u8 = 0xe5;
+/// The first name byte of the end-of-directory entry.
+const END: u8 = 0x00;
+
+/// The `NTRes` flags indicating the lowercase base name and extension.
+const LOWERCASE_BASE: u8 = 0x08;
+const LOWERCASE_EXT: u8 = 0x10;
+
+/// Flag of the sequence number on the last long-name entry.
+const LAST_LONG_ENTRY: u8 = 0x40;
+/// Number of UTF-16 code units stored in a long-name entry.
+const LONG_NAME_CHARS: usize = 13;
+/// Byte offsets of the UTF-16 code units in a long-name entry.
+const LONG_NAME_OFFSETS: [usize; LONG_NAME_CHARS] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+const MAX_NAME_LEN: usize = 255;
+
+pub(super) type RawEntry = [u8; ENTRY_SIZE];
+
+/// Position of a directory entry on the device.
+///
+/// Directories are never compacted, so the position identifies the entry for
+/// its lifetime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(super) struct EntryLocation {
+    pub(super) sector: u64,
+    pub(super) offset: usize,
+}
+
+/// Short (8.3) directory entry, with the long name if present.
+#[derive(Debug, Clone)]
+pub(super) struct Entry {
+    pub(super) name: String,
+    pub(super) short_name: [u8; 11],
+    pub(super) attr: u8,
+    pub(super) first_cluster: u32,
+    pub(super) size: u32,
+    pub(super) location: EntryLocation,
+}
+
+impl Entry {
+    pub(super) fn is_directory(&self) -> bool {
+        self.attr & ATTR_DIRECTORY != 0
+    }
+
+    /// Returns whether the entry is named `name`, ignoring the ASCII case as
+    /// FAT names are case-insensitive.
+    pub(super) fn has_name(&self, name: &str) -> bool {
+        self.name.eq_ignore_ascii_case(name)
+    }
+
+    /// Returns whether this is the `.` or `..` entry.
+    pub(super) fn is_dot(&self) -> bool {
+        self.short_name[0] == b'.'
+    }
+}
+
+/// Classification of a raw directory slot.
+pub(super) enum Slot {
+    /// The entry and all the following entries are free.
+    End,
+    Deleted,
+    LongName,
+    Short,
+}
+
+pub(super) fn classify(raw: &RawEntry) -> Slot {
+    match raw[0] {
+        END => Slot::End,
+        DELETED => Slot::Deleted,
+        _ if raw[11] & ATTR_LONG_NAME_MASK == ATTR_LONG_NAME => Slot::LongName,
+        _ => Slot::Short,
+    }
+}
+
+/// Assembles the entries of a directory while the slots are fed in order.
+#[derive(Debug, Default)]
+pub(super) struct EntryParser {
+    long_name: Vec<[u16; LONG_NAME_CHARS]>,
+    checksum: u8,
+    /// Sequence number of the next expected long-name entry, 0 if none.
+    next_seq: u8,
+}
+
+impl EntryParser {
+    /// Feeds the slot at `location`, returning the entry completed by it.
+    pub(super) fn feed(&mut self, raw: &RawEntry, location: EntryLocation) -> Option<Entry> {
+        match classify(raw) {
+            Slot::End | Slot::Deleted => {
+                self.reset();
+                None
+            }
+            Slot::LongName => {
+                self.feed_long_name(raw);
+                None
+            }
+            Slot::Short => {
+                let long_name = self.take_long_name(raw);
+                if raw[11] & ATTR_VOLUME_ID != 0 {
+                    return None;
+                }
+                let short_name = raw[..11].try_into().unwrap();
+                Some(Entry {
+                    name: long_name.unwrap_or_else(|| decode_short_name(raw)),
+                    short_name,
+                    attr: raw[11],
+                    first_cluster: first_cluster(raw),
+                    size: le_u32(raw, 28),
+                    location,
+                })
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.long_name.clear();
+        self.next_seq = 0;
+    }
+
+    fn feed_long_name(&mut self, raw: &RawEntry) {
+        let seq = raw[0] & !LAST_LONG_ENTRY;
+        if raw[0] & LAST_LONG_ENTRY != 0 {
+            self.reset();
+            if seq == 0 || usize::from(seq) > MAX_NAME_LEN.div_ceil(LONG_NAME_CHARS) {
+                return;
+            }
+            self.long_name = vec![[0; LONG_NAME_CHARS]; usize::from(seq)];
+            self.checksum = raw[13];
+            self.next_seq = seq;
+        }
+        if seq == 0 || seq != self.next_seq || raw[13] != self.checksum {
+            self.reset();
+            return;
+        }
+        let part = &mut self.long_name[usize::from(seq) - 1];
+        for (unit, &offset) in part.iter_mut().zip(&LONG_NAME_OFFSETS) {
+            *unit = le_u16(raw, offset);
+        }
+        self.next_seq -= 1;
+    }
+
+    /// Returns the long name preceding the short entry `raw`, if it is
+    /// complete and belongs to the entry.
+    fn take_long_name(&mut self, raw: &RawEntry) -> Option<String> {
+        let complete = !self.long_name.is_empty() && self.next_seq == 0;
+        let checksum = self.checksum;
+        let parts = core::mem::take(&mut self.long_name);
+        self.reset();
+        if !complete || checksum != short_name_checksum(raw[..11].try_into().unwrap()) {
+            return None;
+        }
+        let units = parts
+            .iter()
+            .flatten()
+            .copied()
+            .take_while(|&unit| unit != 0x0000);
+        char::decode_utf16(units)
+            .collect::<Result<String, _>>()
+            .ok()
+            .filter(|name| !name.is_empty())
+    }
+}
+
+fn first_cluster(raw: &RawEntry) -> u32 {
+    (u32::from(le_u16(raw, 20)) << 16) | u32::from(le_u16(raw, 26))
+}
+
+fn decode_short_name(raw: &RawEntry) -> String {
+    let mut base = raw[..8].trim_ascii_end().to_vec();
+    let mut ext = raw[8..11].trim_ascii_end().to_vec();
+    // 0xe5 is a valid first character in some code pages
+    if base.first() == Some(&0x05) {
+        base[0] = DELETED;
+    }
+    if raw[12] & LOWERCASE_BASE != 0 {
+        base.make_ascii_lowercase();
+    }
+    if raw[12] & LOWERCASE_EXT != 0 {
+        ext.make_ascii_lowercase();
+    }
+    // the OEM code page is unknown, so the bytes are decoded as Latin-1
+    let mut name = base.iter().copied().map(char::from).collect::<String>();
+    if !ext.is_empty() {
+        name.push('.');
+        name.extend(ext.iter().copied().map(char::from));
+    }
+    name
+}
+
+pub(super) fn short_name_checksum(short_name: &[u8; 11]) -> u8 {
+    short_name
+        .iter()
+        .fold(0, |sum: u8, &byte| sum.rotate_right(1).wrapping_add(byte))
+}
+
+/// Checks that `name` can be used as a long file name.
+pub(super) fn validate_name(name: &str) -> Result<(), VfsError> {
+    if name.is_empty() || name == "." || name == ".." {
+        return Err(VfsError::InvalidArgument);
+    }
+    if name.encode_utf16().count() > MAX_NAME_LEN {
+        return Err(VfsError::NameTooLong);
+    }
+    let invalid = |c: char| c.is_ascii_control() || "\"*/:<>?\\|".contains(c);
+    if name.contains(invalid) || name.ends_with(['.', ' ']) {
+        return Err(VfsError::InvalidArgument);
+    }
+    Ok(())
+}
+
+fn is_short_name_char(byte: u8) -> bool {
+    byte.is_ascii_uppercase() || byte.is_ascii_digit() || b"!#$%&'()-@^_`{}~".contains(&byte)
+}
+
+/// Returns the short name if `name` is a valid uppercase 8.3 name, which does
+/// not need long-name entries.
+fn exact_short_name(name: &str) -> Option<[u8; 11]> {
+    let (base, ext) = name.rsplit_once('.').unwrap_or((name, ""));
+    if base.is_empty() || base.len() > 8 || ext.len() > 3 {
+        return None;
+    }
+    if !base.bytes().chain(ext.bytes()).all(is_short_name_char) {
+        return None;
+    }
+    let mut short_name = [b' '; 11];
+    short_name[..base.len()].copy_from_slice(base.as_bytes());
+    short_name[8..8 + ext.len()].copy_from_slice(ext.as_bytes());
+    Some(short_name)
+}
+
+/// Generates a `BASE~N.EXT` short name for `name` which is not used in the
+/// directory.
+fn numbered_short_name(name: &str, exists: impl Fn(&[u8; 11]) -> bool) -> Option<[u8; 11]> {
+    let to_short = |s: &str| -> Vec<u8> {
+        s.chars()
+            .filter(|&c| c != ' ' && c != '.')
+            .map(|c| {
+                u8::try_from(c.to_ascii_uppercase())
+                    .ok()
+                    .filter(|&b| is_short_name_char(b))
+                    .unwrap_or(b'_')
+            })
+            .collect()
+    };
+    let (base, ext) = match name.trim_start_matches('.').rsplit_once('.') {
+        Some((base, ext)) if !base.is_empty() => (to_short(base), to_short(ext)),
+        _ => (to_short(name), Vec::new()),
+    };
+
+    let mut short_name = [b' '; 11];
+    for (dst, &src) in short_name[8..].iter_mut().zip(&ext) {
+        *dst = src;
+    }
+    for n in 1..1_000_000 {
+        let tail = format!("~{n}");
+        let base_len = usize::min(base.len(), 8 - tail.len());
+        short_name[..8].fill(b' ');
+        short_name[..base_len].copy_from_slice(&base[..base_len]);
+        short_name[base_len..base_len + tail.len()].copy_from_slice(tail.as_bytes());
+        if !exists(&short_name) {
+            return Some(short_name);
+        }
+    }
+    None
+}
+
+/// Builds the raw entries for a new entry named `name`, the long-name entries
+/// followed by the short entry.
+pub(super) fn build_entries(
+    name: &str,
+    attr: u8,
+    first_cluster: u32,
+    timestamp: (u16, u16),
+    exists: impl Fn(&[u8; 11]) -> bool,
+) -> Result<Vec<RawEntry>, VfsError> {
+    let (short_name, needs_long_name) = match exact_short_name(name) {
+        Some(short_name) if !exists(&short_name) => (short_name, false),
+        _ => (
+            numbered_short_name(name, exists).ok_or(VfsError::NoSpace)?,
+            true,
+        ),
+    };
+
+    let mut entries = Vec::new();
+    if needs_long_name {
+        let checksum = short_name_checksum(&short_name);
+        let units = name.encode_utf16().collect::<Vec<_>>();
+        let parts = units.chunks(LONG_NAME_CHARS).collect::<Vec<_>>();
+        for (i, part) in parts.iter().enumerate().rev() {
+            let mut raw = [0; ENTRY_SIZE];
+            raw[0] = u8::try_from(i + 1).unwrap();
+            if i + 1 == parts.len() {
+                raw[0] |= LAST_LONG_ENTRY;
+            }
+            raw[11] = ATTR_LONG_NAME;
+            raw[13] = checksum;
+            for (j, &offset) in LONG_NAME_OFFSETS.iter().enumerate() {
+                // the name is terminated by NUL and padded with 0xffff
+                let unit = match j.cmp(&part.len()) {
+                    core::cmp::Ordering::Less => part[j],
+                    core::cmp::Ordering::Equal => 0x0000,
+                    core::cmp::Ordering::Greater => 0xffff,
+                };
+                set_le_u16(&mut raw, offset, unit);
+            }
+            entries.push(raw);
+        }
+    }
+    entries.push(short_entry(short_name, attr, first_cluster, timestamp));
+    Ok(entries)
+}
+
+/// Builds a short entry with the creation and modification times set to
+/// `timestamp`.
+pub(super) fn short_entry(
+    short_name: [u8; 11],
+    attr: u8,
+    first_cluster: u32,
+    (date, time): (u16, u16),
+) -> RawEntry {
+    let mut raw = [0; ENTRY_SIZE];
+    raw[..11].copy_from_slice(&short_name);
+    raw[11] = attr;
+    set_le_u16(&mut raw, 14, time);
+    set_le_u16(&mut raw, 16, date);
+    set_le_u16(&mut raw, 18, date);
+    set_le_u16(&mut raw, 22, time);
+    set_le_u16(&mut raw, 24, date);
+    set_first_cluster(&mut raw, first_cluster);
+    raw
+}
+
+pub(super) fn set_first_cluster(raw: &mut [u8], cluster: u32) {
+    let [b0, b1, b2, b3] = cluster.to_le_bytes();
+    set_le_u16(raw, 20, u16::from_le_bytes([b2, b3]));
+    set_le_u16(raw, 26, u16::from_le_bytes([b0, b1]));
+}
+
+/// Updates the first cluster, the size and the modification time of the
+/// short entry `raw`.
+pub(super) fn update_entry(
+    raw: &mut [u8],
+    first_cluster: u32,
+    size: u32,
+    (date, time): (u16, u16),
+) {
+    set_first_cluster(raw, first_cluster);
+    set_le_u32(raw, 28, size);
+    if raw[11] & ATTR_DIRECTORY == 0 {
+        raw[11] |= ATTR_ARCHIVE;
+    }
+    set_le_u16(raw, 18, date);
+    set_le_u16(raw, 22, time);
+    set_le_u16(raw, 24, date);
+}
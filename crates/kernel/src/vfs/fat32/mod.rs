@@ -0,0 +1,857 @@
+use alloc::{
+    collections::btree_map::BTreeMap,
+    format,
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+
+use platform_cast::CastFrom as _;
+
+use self::{
+    boot_sector::BootSector,
+    cache::SectorCache,
+    dir::{ENTRY_SIZE, Entry, EntryLocation},
+    volume::Volume,
+};
+use super::{DirEntry, FileSystem, FileType, Inode, Metadata, VfsError};
+use crate::{
+    block::{BlockDevice, SECTOR_SIZE},
+    sync::{mutex::Mutex, spinlock::SpinMutex},
+};
+
+mod boot_sector;
+mod cache;
+mod dir;
+mod volume;
+
+/// FAT32 file system on a block device.
+///
+/// Files can be created, read and written, but not truncated, renamed or
+/// removed. The data is cached in memory until [`FileSystem::sync`].
+#[derive(Debug)]
+pub(super) struct Fat32Fs {
+    this: Weak<Self>,
+    device_name: String,
+    root_cluster: u32,
+    volume: Mutex<Volume>,
+    /// Live inodes, so that each file has a single inode caching its size.
+    inodes: SpinMutex<BTreeMap<EntryLocation, Weak<Fat32Inode>>>,
+}
+
+impl Fat32Fs {
+    /// Mounts the FAT32 volume on `device`, which is either the whole device
+    /// or the first FAT32 partition in its MBR partition table.
+    pub(super) fn mount(device: Arc<dyn BlockDevice>) -> Result<Arc<dyn FileSystem>, VfsError> {
+        let device_name = String::from(device.name());
+        let mut cache = SectorCache::new(device);
+        let (start, bs) = find_volume(&mut cache).map_err(|e| {
+            warn!("fat32: no FAT32 volume found on {device_name}: {e}");
+            VfsError::InvalidArgument
+        })?;
+        let volume = Volume::new(cache, start, bs)?;
+        info!(
+            "fat32: {device_name}: {} clusters of {} bytes, {} free",
+            volume.cluster_count(),
+            volume.cluster_size(),
+            volume
+                .free_count()
+                .map_or_else(|| "unknown".into(), |count| format!("{count}"))
+        );
+        let fs = Arc::new_cyclic(|this| Self {
+            this: Weak::clone(this),
+            device_name,
+            root_cluster: volume.root_cluster(),
+            volume: Mutex::new(volume),
+            inodes: SpinMutex::new(BTreeMap::new()),
+        });
+        Ok(fs)
+    }
+
+    /// Returns the inode of the directory entry, sharing the live one.
+    fn inode(self: &Arc<Self>, entry: &Entry) -> Arc<dyn Inode> {
+        let mut inodes = self.inodes.lock();
+        if let Some(inode) = inodes.get(&entry.location).and_then(Weak::upgrade) {
+            return inode;
+        }
+        inodes.retain(|_, inode| inode.strong_count() > 0);
+
+        let file_type = if entry.is_directory() {
+            FileType::Directory
+        } else {
+            FileType::Regular
+        };
+        let inode = Arc::new(Fat32Inode {
+            fs: Arc::clone(self),
+            location: Some(entry.location),
+            file_type,
+            read_only: entry.attr & dir::ATTR_READ_ONLY != 0,
+            state: SpinMutex::new(InodeState {
+                first_cluster: entry.first_cluster,
+                size: if entry.is_directory() { 0 } else { entry.size },
+            }),
+        });
+        inodes.insert(entry.location, Arc::downgrade(&inode));
+        inode
+    }
+}
+
+impl FileSystem for Fat32Fs {
+    fn name(&self) -> &'static str {
+        "fat32"
+    }
+
+    fn root(&self) -> Arc<dyn Inode> {
+        Arc::new(Fat32Inode {
+            fs: self.this.upgrade().unwrap(),
+            location: None,
+            file_type: FileType::Directory,
+            read_only: false,
+            state: SpinMutex::new(InodeState {
+                first_cluster: self.root_cluster,
+                size: 0,
+            }),
+        })
+    }
+
+    fn sync(&self) -> Result<(), VfsError> {
+        self.volume.lock().sync().inspect_err(|e| {
+            warn!("fat32: failed to sync {}: {e}", self.device_name);
+        })
+    }
+}
+
+/// Finds the boot sector of the volume, returning the first sector of the
+/// volume.
+fn find_volume(cache: &mut SectorCache) -> Result<(u64, BootSector), &'static str> {
+    let io_error = |_| "failed to read the device";
+    let first = *cache.read(0).map_err(io_error)?;
+    let mut error = match BootSector::parse(&first) {
+        Ok(bs) => return Ok((0, bs)),
+        Err(e) => e,
+    };
+    // the MBR shares the signature with the boot sector
+    if first[510..512] == [0x55, 0xaa] {
+        for entry in first[446..510].as_chunks::<16>().0 {
+            // FAT32 with CHS or LBA addressing
+            if !matches!(entry[4], 0x0b | 0x0c) {
+                continue;
+            }
+            let start = u64::from(le_u32(entry, 8));
+            match BootSector::parse(cache.read(start).map_err(io_error)?) {
+                Ok(bs) => return Ok((start, bs)),
+                Err(e) => error = e,
+            }
+        }
+    }
+    Err(error)
+}
+
+#[derive(Debug, Clone, Copy)]
+struct InodeState {
+    /// First cluster of the data, or 0 for an empty file.
+    first_cluster: u32,
+    size: u32,
+}
+
+#[derive(Debug)]
+struct Fat32Inode {
+    fs: Arc<Fat32Fs>,
+    /// Location of the entry in the parent directory, `None` for the root.
+    location: Option<EntryLocation>,
+    file_type: FileType,
+    read_only: bool,
+    state: SpinMutex<InodeState>,
+}
+
+impl Fat32Inode {
+    const ROOT_INO: u64 = 1;
+
+    fn ino(&self) -> u64 {
+        match self.location {
+            // the boot sector has no entries, so the number is never 1
+            Some(location) => {
+                let per_sector = u64::cast_from(SECTOR_SIZE / ENTRY_SIZE);
+                location.sector * per_sector + u64::cast_from(location.offset / ENTRY_SIZE)
+            }
+            None => Self::ROOT_INO,
+        }
+    }
+
+    fn ensure_directory(&self) -> Result<(), VfsError> {
+        if self.file_type != FileType::Directory {
+            return Err(VfsError::NotDirectory);
+        }
+        Ok(())
+    }
+
+    fn ensure_regular(&self) -> Result<(), VfsError> {
+        if self.file_type == FileType::Directory {
+            return Err(VfsError::IsDirectory);
+        }
+        Ok(())
+    }
+
+    fn first_cluster(&self) -> u32 {
+        self.state.lock().first_cluster
+    }
+
+    fn entries(&self) -> Result<Vec<Entry>, VfsError> {
+        self.ensure_directory()?;
+        let mut entries = self.fs.volume.lock().read_dir(self.first_cluster())?;
+        entries.retain(|entry| !entry.is_dot());
+        Ok(entries)
+    }
+
+    /// Extends the cluster chain to hold `len` bytes.
+    ///
+    /// The chain is recorded in `state` even if the allocation fails midway,
+    /// so that the allocated clusters are not leaked.
+    fn grow_chain(
+        volume: &mut Volume,
+        state: &mut InodeState,
+        chain: &mut Vec<u32>,
+        len: usize,
+    ) -> Result<(), VfsError> {
+        while chain.len() * volume.cluster_size() < len {
+            let cluster = volume.allocate_cluster(chain.last().copied())?;
+            if chain.is_empty() {
+                state.first_cluster = cluster;
+            }
+            chain.push(cluster);
+        }
+        Ok(())
+    }
+}
+
+impl Inode for Fat32Inode {
+    fn metadata(&self) -> Metadata {
+        let state = *self.state.lock();
+        let perm = match (self.file_type, self.read_only) {
+            (FileType::Directory, false) => 0o755,
+            (FileType::Directory, true) => 0o555,
+            (_, false) => 0o644,
+            (_, true) => 0o444,
+        };
+        Metadata {
+            ino: self.ino(),
+            file_type: self.file_type,
+            perm,
+            size: usize::cast_from(state.size),
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Result<Arc<dyn Inode>, VfsError> {
+        let entry = self
+            .entries()?
+            .into_iter()
+            .find(|entry| entry.has_name(name))
+            .ok_or(VfsError::NotFound)?;
+        Ok(self.fs.inode(&entry))
+    }
+
+    fn read_dir(&self) -> Result<Vec<DirEntry>, VfsError> {
+        let entries = self
+            .entries()?
+            .into_iter()
+            .map(|entry| DirEntry {
+                file_type: if entry.is_directory() {
+                    FileType::Directory
+                } else {
+                    FileType::Regular
+                },
+                name: entry.name,
+            })
+            .collect();
+        Ok(entries)
+    }
+
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize, VfsError> {
+        self.ensure_regular()?;
+        let mut volume = self.fs.volume.lock();
+        let state = *self.state.lock();
+        let size = usize::cast_from(state.size);
+        if offset >= size {
+            return Ok(0);
+        }
+        let len = usize::min(buf.len(), size - offset);
+        let chain = volume.cluster_chain(state.first_cluster)?;
+        if chain.len() * volume.cluster_size() < size {
+            warn!("fat32: cluster chain of inode {} is too short", self.ino());
+            return Err(VfsError::Io);
+        }
+        volume.read_chain(&chain, offset, &mut buf[..len])?;
+        Ok(len)
+    }
+
+    fn write_at(&self, offset: usize, buf: &[u8]) -> Result<usize, VfsError> {
+        self.ensure_regular()?;
+        let location = self.location.unwrap();
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        // the file size is a 32-bit field
+        let end = offset
+            .checked_add(buf.len())
+            .filter(|&end| u32::try_from(end).is_ok())
+            .ok_or(VfsError::NoSpace)?;
+
+        let mut volume = self.fs.volume.lock();
+        if volume.is_read_only() {
+            return Err(VfsError::ReadOnlyFileSystem);
+        }
+        let mut state = *self.state.lock();
+        let mut chain = volume.cluster_chain(state.first_cluster)?;
+        let grown = Self::grow_chain(&mut volume, &mut state, &mut chain, end);
+        if let Err(e) = grown {
+            volume.update_entry(location, state.first_cluster, state.size)?;
+            *self.state.lock() = state;
+            return Err(e);
+        }
+
+        let size = usize::cast_from(state.size);
+        // the tail of the last cluster may contain stale data
+        let mut pos = size;
+        while pos < offset {
+            let zeros = [0; SECTOR_SIZE];
+            let len = usize::min(zeros.len(), offset - pos);
+            volume.write_chain(&chain, pos, &zeros[..len])?;
+            pos += len;
+        }
+        volume.write_chain(&chain, offset, buf)?;
+
+        state.size = u32::try_from(usize::max(size, end)).unwrap();
+        volume.update_entry(location, state.first_cluster, state.size)?;
+        *self.state.lock() = state;
+        Ok(buf.len())
+    }
+
+    fn create(&self, name: &str, file_type: FileType) -> Result<Arc<dyn Inode>, VfsError> {
+        self.ensure_directory()?;
+        dir::validate_name(name)?;
+        let attr = match file_type {
+            FileType::Regular => dir::ATTR_ARCHIVE,
+            FileType::Directory => dir::ATTR_DIRECTORY,
+            _ => return Err(VfsError::InvalidArgument),
+        };
+
+        let dir_cluster = self.first_cluster();
+        let mut volume = self.fs.volume.lock();
+        let mut entry = volume.add_entry(dir_cluster, name, attr, 0)?;
+        if file_type == FileType::Directory {
+            // allocated after the entry is added, so that it is not leaked if
+            // the entry cannot be added
+            entry.first_cluster = volume.create_dir_cluster(dir_cluster)?;
+            volume.update_entry(entry.location, entry.first_cluster, 0)?;
+        }
+        drop(volume);
+        Ok(self.fs.inode(&entry))
+    }
+
+    fn sync(&self) -> Result<(), VfsError> {
+        self.fs.sync()
+    }
+}
+
+fn le_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(bytes[offset..offset + 2].try_into().unwrap())
+}
+
+fn le_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn set_le_u16(bytes: &mut [u8], offset: usize, value: u16) {
+    bytes[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+fn set_le_u32(bytes: &mut [u8], offset: usize, value: u32) {
+    bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+#[cfg(feature = "ktest")]
+pub mod ktests {
+    use alloc::{string::String, sync::Arc, vec, vec::Vec};
+    use core::ptr;
+
+    use platform_cast::CastFrom as _;
+    use snafu::{ResultExt as _, ensure_whatever};
+
+    use super::{
+        Fat32Fs, SECTOR_SIZE, cache::SectorCache, dir, find_volume, le_u32, set_le_u16, set_le_u32,
+        volume::Volume,
+    };
+    use crate::{
+        block::{Bio, BioOp, BlockDevice, BlockError},
+        error::GenericError,
+        ktest::KernelTest,
+        sync::spinlock::SpinMutex,
+        vfs::{FileType, VfsError},
+    };
+
+    pub static TESTS: &[KernelTest] = kernel_tests![
+        long_name_round_trip,
+        short_name_entry,
+        short_name_collisions,
+        allocate_clusters,
+        allocate_until_full,
+        chain_loop_detected,
+        colliding_names_in_directory,
+        directory_grows,
+        remount_preserves_files,
+    ];
+
+    const RESERVED_SECTORS: u32 = 2;
+    const FS_INFO_SECTOR: u32 = 1;
+    const NUM_FATS: u32 = 2;
+    /// One FAT sector holds 128 entries.
+    const FAT_SIZE: u32 = 1;
+    const CLUSTER_COUNT: u32 = 64;
+    const DATA_START: u32 = RESERVED_SECTORS + NUM_FATS * FAT_SIZE;
+    const ROOT_CLUSTER: u32 = 2;
+    const END_OF_CHAIN: u32 = 0x0fff_ffff;
+    const TIMESTAMP: (u16, u16) = (0x0021, 0);
+
+    /// Block device backed by memory, which completes the requests on submit.
+    #[derive(Debug)]
+    struct RamDisk {
+        data: SpinMutex<Vec<u8>>,
+    }
+
+    impl RamDisk {
+        /// Creates a disk with an empty FAT32 volume of one-sector clusters.
+        fn formatted() -> Arc<Self> {
+            let num_sectors = DATA_START + CLUSTER_COUNT;
+            let mut data = vec![0; sector_offset(num_sectors)];
+
+            let bs = &mut data[..SECTOR_SIZE];
+            bs[0] = 0xeb;
+            set_le_u16(bs, 11, u16::try_from(SECTOR_SIZE).unwrap());
+            bs[13] = 1;
+            set_le_u16(bs, 14, u16::try_from(RESERVED_SECTORS).unwrap());
+            bs[16] = u8::try_from(NUM_FATS).unwrap();
+            set_le_u32(bs, 32, num_sectors);
+            set_le_u32(bs, 36, FAT_SIZE);
+            set_le_u32(bs, 44, ROOT_CLUSTER);
+            set_le_u16(bs, 48, u16::try_from(FS_INFO_SECTOR).unwrap());
+            bs[510..].copy_from_slice(&[0x55, 0xaa]);
+
+            let fs_info = &mut data[sector_offset(FS_INFO_SECTOR)..][..SECTOR_SIZE];
+            set_le_u32(fs_info, 0, 0x4161_5252);
+            set_le_u32(fs_info, 484, 0x6141_7272);
+            set_le_u32(fs_info, 488, CLUSTER_COUNT - 1);
+            set_le_u32(fs_info, 492, ROOT_CLUSTER + 1);
+            set_le_u32(fs_info, 508, 0xaa55_0000);
+
+            for fat in 0..NUM_FATS {
+                set_le_u32(&mut data, fat_entry_offset(fat, 0), 0x0fff_fff8);
+                set_le_u32(&mut data, fat_entry_offset(fat, 1), END_OF_CHAIN);
+                set_le_u32(&mut data, fat_entry_offset(fat, ROOT_CLUSTER), END_OF_CHAIN);
+            }
+
+            Arc::new(Self {
+                data: SpinMutex::new(data),
+            })
+        }
+
+        /// Returns the FAT entry of `cluster` in the FAT `fat`.
+        fn fat_entry(&self, fat: u32, cluster: u32) -> u32 {
+            le_u32(&self.data.lock(), fat_entry_offset(fat, cluster))
+        }
+
+        fn set_fat_entry(&self, cluster: u32, value: u32) {
+            let mut data = self.data.lock();
+            for fat in 0..NUM_FATS {
+                set_le_u32(&mut data, fat_entry_offset(fat, cluster), value);
+            }
+        }
+    }
+
+    fn sector_offset(sector: u32) -> usize {
+        usize::cast_from(sector) * SECTOR_SIZE
+    }
+
+    /// Returns the disk offset of the entry of `cluster` in the FAT `fat`.
+    fn fat_entry_offset(fat: u32, cluster: u32) -> usize {
+        sector_offset(RESERVED_SECTORS + fat * FAT_SIZE) + usize::cast_from(cluster) * 4
+    }
+
+    impl BlockDevice for RamDisk {
+        fn name(&self) -> &'static str {
+            "ktest-ram"
+        }
+
+        fn num_sectors(&self) -> u64 {
+            u64::cast_from(self.data.lock().len() / SECTOR_SIZE)
+        }
+
+        fn is_read_only(&self) -> bool {
+            false
+        }
+
+        fn submit(&self, bio: Arc<Bio>) -> Result<(), BlockError> {
+            let start = usize::cast_from(bio.sector()) * SECTOR_SIZE;
+            let mut data = self.data.lock();
+            bio.with_data(|buf| match (bio.op(), buf) {
+                (BioOp::Read, Some(buf)) => {
+                    let src = &data[start..start + buf.len()];
+                    // written as the device does, the buffer is owned by the
+                    // request
+                    unsafe {
+                        ptr::copy_nonoverlapping(src.as_ptr(), buf.as_ptr(), src.len());
+                    }
+                }
+                (BioOp::Write, Some(buf)) => {
+                    data[start..start + buf.len()].copy_from_slice(buf.as_slice());
+                }
+                _ => {}
+            });
+            data.unlock();
+            bio.complete(Ok(()));
+            Ok(())
+        }
+    }
+
+    fn open_volume(disk: &Arc<RamDisk>) -> Result<Volume, GenericError> {
+        let mut cache = SectorCache::new(Arc::clone(disk) as Arc<dyn BlockDevice>);
+        let found = find_volume(&mut cache);
+        let Ok((start, bs)) = found else {
+            snafu::whatever!("no volume found: {found:?}");
+        };
+        Volume::new(cache, start, bs).whatever_context("failed to open volume")
+    }
+
+    /// Feeds `raws` to a parser, returning the parsed entries.
+    fn parse(raws: &[dir::RawEntry]) -> Vec<dir::Entry> {
+        let mut parser = dir::EntryParser::default();
+        raws.iter()
+            .enumerate()
+            .filter_map(|(i, raw)| {
+                let location = dir::EntryLocation {
+                    sector: 0,
+                    offset: i * dir::ENTRY_SIZE,
+                };
+                parser.feed(raw, location)
+            })
+            .collect()
+    }
+
+    fn short_name_of(raws: &[dir::RawEntry]) -> [u8; 11] {
+        raws.last().unwrap()[..11].try_into().unwrap()
+    }
+
+    fn long_name_round_trip() -> Result<(), GenericError> {
+        let long = "a long file name spanning several entries.txt";
+        let max = "x".repeat(255);
+        let names = [
+            "readme.txt",
+            "Mixed Case",
+            ".hidden",
+            "exactly 13 ch",
+            long,
+            "\u{65e5}\u{672c}\u{8a9e}.txt",
+            &max,
+        ];
+        for name in names {
+            let raws = dir::build_entries(name, dir::ATTR_ARCHIVE, 5, TIMESTAMP, |_| false)
+                .whatever_context("failed to build entries")?;
+            let expected = name.encode_utf16().count().div_ceil(13) + 1;
+            ensure_whatever!(
+                raws.len() == expected,
+                "{name:?}: {} raw entries, expected {expected}",
+                raws.len()
+            );
+            let entries = parse(&raws);
+            ensure_whatever!(entries.len() == 1, "{name:?}: {} entries", entries.len());
+            let entry = &entries[0];
+            ensure_whatever!(entry.name == name, "{name:?}: parsed as {:?}", entry.name);
+            ensure_whatever!(
+                entry.first_cluster == 5 && !entry.is_directory(),
+                "{name:?}: parsed as {entry:?}"
+            );
+        }
+
+        // a long name is dropped if its checksum does not match the short entry
+        let mut raws = dir::build_entries(long, dir::ATTR_ARCHIVE, 5, TIMESTAMP, |_| false)
+            .whatever_context("failed to build entries")?;
+        raws.last_mut().unwrap()[0] = b'X';
+        let entries = parse(&raws);
+        ensure_whatever!(
+            entries.len() == 1 && entries[0].name != long,
+            "orphaned long name used: {entries:?}"
+        );
+        Ok(())
+    }
+
+    fn short_name_entry() -> Result<(), GenericError> {
+        let raws = dir::build_entries("README.TXT", dir::ATTR_ARCHIVE, 0, TIMESTAMP, |_| false)
+            .whatever_context("failed to build entries")?;
+        ensure_whatever!(raws.len() == 1, "long name entries for an 8.3 name");
+        ensure_whatever!(
+            short_name_of(&raws) == *b"README  TXT",
+            "short name {:?}",
+            String::from_utf8_lossy(&short_name_of(&raws))
+        );
+        let entries = parse(&raws);
+        ensure_whatever!(
+            entries.len() == 1 && entries[0].name == "README.TXT",
+            "parsed as {entries:?}"
+        );
+        Ok(())
+    }
+
+    fn short_name_collisions() -> Result<(), GenericError> {
+        let name = "long file name.txt";
+        let mut taken = Vec::<[u8; 11]>::new();
+        for expected in [b"LONGFI~1TXT", b"LONGFI~2TXT", b"LONGFI~3TXT"] {
+            let raws = dir::build_entries(name, dir::ATTR_ARCHIVE, 0, TIMESTAMP, |short| {
+                taken.contains(short)
+            })
+            .whatever_context("failed to build entries")?;
+            let short_name = short_name_of(&raws);
+            ensure_whatever!(
+                short_name == *expected,
+                "short name {:?}, expected {:?}",
+                String::from_utf8_lossy(&short_name),
+                String::from_utf8_lossy(expected)
+            );
+            taken.push(short_name);
+        }
+
+        // a taken 8.3 name gets a numbered short name and keeps the long name
+        let exists = |short: &[u8; 11]| short == b"README  TXT";
+        let raws = dir::build_entries("README.TXT", dir::ATTR_ARCHIVE, 0, TIMESTAMP, exists)
+            .whatever_context("failed to build entries")?;
+        ensure_whatever!(
+            short_name_of(&raws) == *b"README~1TXT",
+            "short name {:?}",
+            String::from_utf8_lossy(&short_name_of(&raws))
+        );
+        let entries = parse(&raws);
+        ensure_whatever!(
+            entries.len() == 1 && entries[0].name == "README.TXT",
+            "parsed as {entries:?}"
+        );
+
+        // the tail shortens the base name as the number grows
+        let raws = dir::build_entries(name, dir::ATTR_ARCHIVE, 0, TIMESTAMP, |short| {
+            short[..8] != *b"LONG~100"
+        })
+        .whatever_context("failed to build entries")?;
+        ensure_whatever!(
+            short_name_of(&raws) == *b"LONG~100TXT",
+            "short name {:?}",
+            String::from_utf8_lossy(&short_name_of(&raws))
+        );
+        Ok(())
+    }
+
+    fn allocate_clusters() -> Result<(), GenericError> {
+        let disk = RamDisk::formatted();
+        let mut volume = open_volume(&disk)?;
+        let free = volume.free_count();
+        ensure_whatever!(
+            free == Some(CLUSTER_COUNT - 1),
+            "free count {free:?} read from FSInfo"
+        );
+
+        let first = volume
+            .allocate_cluster(None)
+            .whatever_context("failed to allocate cluster")?;
+        let second = volume
+            .allocate_cluster(Some(first))
+            .whatever_context("failed to allocate cluster")?;
+        let chain = volume
+            .cluster_chain(first)
+            .whatever_context("failed to read chain")?;
+        ensure_whatever!(chain == [first, second], "chain {chain:?}");
+        let free = volume.free_count();
+        ensure_whatever!(free == Some(CLUSTER_COUNT - 3), "free count {free:?}");
+
+        // the data crosses the cluster boundary
+        let data = (0..=u8::MAX)
+            .cycle()
+            .take(SECTOR_SIZE + 64)
+            .collect::<Vec<_>>();
+        volume
+            .write_chain(&chain, 32, &data)
+            .whatever_context("failed to write chain")?;
+        let mut buf = vec![0; data.len()];
+        volume
+            .read_chain(&chain, 32, &mut buf)
+            .whatever_context("failed to read chain")?;
+        ensure_whatever!(buf == data, "data read back differs");
+
+        volume.sync().whatever_context("failed to sync")?;
+        for fat in 0..NUM_FATS {
+            let entries = (disk.fat_entry(fat, first), disk.fat_entry(fat, second));
+            ensure_whatever!(
+                entries == (second, END_OF_CHAIN),
+                "FAT {fat}: entries {entries:x?} on the disk"
+            );
+        }
+        let fs_info = le_u32(&disk.data.lock(), sector_offset(FS_INFO_SECTOR) + 488);
+        ensure_whatever!(
+            fs_info == CLUSTER_COUNT - 3,
+            "free count {fs_info} in FSInfo"
+        );
+
+        // the free count is read back from FSInfo
+        let volume = open_volume(&disk)?;
+        let free = volume.free_count();
+        ensure_whatever!(free == Some(CLUSTER_COUNT - 3), "free count {free:?}");
+        Ok(())
+    }
+
+    fn allocate_until_full() -> Result<(), GenericError> {
+        let disk = RamDisk::formatted();
+        let mut volume = open_volume(&disk)?;
+        let mut last = None;
+        let mut allocated = Vec::new();
+        let res = loop {
+            match volume.allocate_cluster(last) {
+                Ok(cluster) => {
+                    allocated.push(cluster);
+                    last = Some(cluster);
+                }
+                Err(e) => break e,
+            }
+        };
+        ensure_whatever!(res == VfsError::NoSpace, "allocation failed with {res:?}");
+        ensure_whatever!(
+            allocated.len() == usize::cast_from(CLUSTER_COUNT - 1),
+            "{} clusters allocated",
+            allocated.len()
+        );
+        let chain = volume
+            .cluster_chain(allocated[0])
+            .whatever_context("failed to read chain")?;
+        ensure_whatever!(chain == allocated, "chain differs from the allocation");
+        ensure_whatever!(
+            !chain.contains(&ROOT_CLUSTER),
+            "root directory cluster allocated"
+        );
+        Ok(())
+    }
+
+    fn chain_loop_detected() -> Result<(), GenericError> {
+        let disk = RamDisk::formatted();
+        disk.set_fat_entry(3, 4);
+        disk.set_fat_entry(4, 3);
+        let mut volume = open_volume(&disk)?;
+        let res = volume.cluster_chain(3);
+        ensure_whatever!(res == Err(VfsError::Io), "loop read as {res:?}");
+        disk.set_fat_entry(4, 1);
+        let mut volume = open_volume(&disk)?;
+        let res = volume.cluster_chain(3);
+        ensure_whatever!(res == Err(VfsError::Io), "invalid link read as {res:?}");
+        Ok(())
+    }
+
+    fn colliding_names_in_directory() -> Result<(), GenericError> {
+        let disk = RamDisk::formatted();
+        let mut volume = open_volume(&disk)?;
+        let names = [
+            "long file name.txt",
+            "long file name 2.txt",
+            "LongFileName.txt",
+        ];
+        for name in names {
+            volume
+                .add_entry(ROOT_CLUSTER, name, dir::ATTR_ARCHIVE, 0)
+                .whatever_context("failed to add entry")?;
+        }
+        let res = volume.add_entry(ROOT_CLUSTER, "LONG FILE NAME.TXT", dir::ATTR_ARCHIVE, 0);
+        ensure_whatever!(
+            matches!(res, Err(VfsError::AlreadyExists)),
+            "name differing in case added: {res:?}"
+        );
+
+        let entries = volume
+            .read_dir(ROOT_CLUSTER)
+            .whatever_context("failed to read directory")?;
+        let mut short_names = entries.iter().map(|e| e.short_name).collect::<Vec<_>>();
+        short_names.sort_unstable();
+        ensure_whatever!(
+            short_names == [*b"LONGFI~1TXT", *b"LONGFI~2TXT", *b"LONGFI~3TXT"],
+            "short names {short_names:?}"
+        );
+        ensure_whatever!(
+            entries.iter().map(|e| e.name.as_str()).eq(names),
+            "entries {entries:?}"
+        );
+        Ok(())
+    }
+
+    fn directory_grows() -> Result<(), GenericError> {
+        let disk = RamDisk::formatted();
+        let mut volume = open_volume(&disk)?;
+        // 2 slots per entry, 16 slots per cluster
+        let names = (0..20)
+            .map(|i| alloc::format!("file {i}"))
+            .collect::<Vec<_>>();
+        for name in &names {
+            volume
+                .add_entry(ROOT_CLUSTER, name, dir::ATTR_ARCHIVE, 0)
+                .whatever_context("failed to add entry")?;
+        }
+        let chain = volume
+            .cluster_chain(ROOT_CLUSTER)
+            .whatever_context("failed to read chain")?;
+        ensure_whatever!(
+            chain.len() == 3,
+            "root directory of {} clusters",
+            chain.len()
+        );
+        let entries = volume
+            .read_dir(ROOT_CLUSTER)
+            .whatever_context("failed to read directory")?;
+        ensure_whatever!(
+            entries.iter().map(|e| &e.name).eq(&names),
+            "entries {entries:?}"
+        );
+        Ok(())
+    }
+
+    fn remount_preserves_files() -> Result<(), GenericError> {
+        let disk = RamDisk::formatted();
+        let data = (1..=u8::MAX)
+            .cycle()
+            .take(3 * SECTOR_SIZE)
+            .collect::<Vec<_>>();
+        {
+            let fs = Fat32Fs::mount(Arc::clone(&disk) as Arc<dyn BlockDevice>)
+                .whatever_context("failed to mount")?;
+            let dir = fs
+                .root()
+                .create("Sub Directory", FileType::Directory)
+                .whatever_context("failed to create directory")?;
+            let file = dir
+                .create("data file.bin", FileType::Regular)
+                .whatever_context("failed to create file")?;
+            file.write_at(100, &data)
+                .whatever_context("failed to write file")?;
+            fs.sync().whatever_context("failed to sync")?;
+        }
+
+        let fs = Fat32Fs::mount(Arc::clone(&disk) as Arc<dyn BlockDevice>)
+            .whatever_context("failed to remount")?;
+        let file = fs
+            .root()
+            .lookup("sub directory")
+            .and_then(|dir| dir.lookup("DATA FILE.BIN"))
+            .whatever_context("file not found after remount")?;
+        let size = file.metadata().size;
+        ensure_whatever!(size == 100 + data.len(), "file size {size}");
+        let mut buf = vec![0xff; size];
+        let len = file
+            .read_at(0, &mut buf)
+            .whatever_context("failed to read file")?;
+        ensure_whatever!(len == size, "{len} bytes read");
+        ensure_whatever!(
+            buf[..100].iter().all(|&b| b == 0) && buf[100..] == data,
+            "data read back differs"
+        );
+        Ok(())
+    }
+}
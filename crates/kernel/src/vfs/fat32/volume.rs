@@ -0,0 +1,442 @@
+use alloc::vec::Vec;
+
+use platform_cast::CastFrom as _;
+
+use super::{
+    boot_sector::BootSector,
+    cache::SectorCache,
+    dir::{self, ENTRY_SIZE, Entry, EntryLocation, EntryParser, RawEntry, Slot},
+    le_u32, set_le_u32,
+};
+use crate::{
+    block::SECTOR_SIZE,
+    time::{DateTime, SystemTime},
+    vfs::VfsError,
+};
+
+/// Mask of the cluster number in a FAT entry, the upper 4 bits are reserved.
+const FAT_ENTRY_MASK: u32 = 0x0fff_ffff;
+/// FAT entries equal or greater than this value mark the end of a chain.
+const FAT_END_OF_CHAIN: u32 = 0x0fff_fff8;
+const FAT_FREE: u32 = 0;
+
+const FS_INFO_LEAD_SIGNATURE: u32 = 0x4161_5252;
+const FS_INFO_STRUCT_SIGNATURE: u32 = 0x6141_7272;
+const FS_INFO_TRAIL_SIGNATURE: u32 = 0xaa55_0000;
+/// Value of the `FSInfo` fields when the value is unknown.
+const FS_INFO_UNKNOWN: u32 = 0xffff_ffff;
+
+/// Mounted FAT32 volume, accessed through the sector cache.
+#[derive(Debug)]
+pub(super) struct Volume {
+    cache: SectorCache,
+    /// First device sector of the volume.
+    start: u64,
+    bs: BootSector,
+    /// Number of free clusters, if known.
+    free_count: Option<u32>,
+    /// Cluster from which the free cluster search starts.
+    next_free: u32,
+    fs_info_dirty: bool,
+}
+
+impl Volume {
+    pub(super) fn new(
+        mut cache: SectorCache,
+        start: u64,
+        bs: BootSector,
+    ) -> Result<Self, VfsError> {
+        let mut free_count = None;
+        let mut next_free = 2;
+        if bs.fs_info_sector != 0 && bs.fs_info_sector < bs.reserved_sectors {
+            let sector = cache.read(start + u64::from(bs.fs_info_sector))?;
+            if le_u32(sector, 0) == FS_INFO_LEAD_SIGNATURE
+                && le_u32(sector, 484) == FS_INFO_STRUCT_SIGNATURE
+                && le_u32(sector, 508) == FS_INFO_TRAIL_SIGNATURE
+            {
+                free_count = Some(le_u32(sector, 488))
+                    .filter(|&count| count != FS_INFO_UNKNOWN && count <= bs.cluster_count());
+                next_free = Some(le_u32(sector, 492))
+                    .filter(|&cluster| bs.is_valid_cluster(cluster))
+                    .unwrap_or(2);
+            }
+        }
+        Ok(Self {
+            cache,
+            start,
+            bs,
+            free_count,
+            next_free,
+            fs_info_dirty: false,
+        })
+    }
+
+    pub(super) fn root_cluster(&self) -> u32 {
+        self.bs.root_cluster
+    }
+
+    pub(super) fn cluster_count(&self) -> u32 {
+        self.bs.cluster_count()
+    }
+
+    pub(super) fn cluster_size(&self) -> usize {
+        self.bs.cluster_size()
+    }
+
+    pub(super) fn free_count(&self) -> Option<u32> {
+        self.free_count
+    }
+
+    pub(super) fn is_read_only(&self) -> bool {
+        self.cache.device().is_read_only()
+    }
+
+    fn fat_entry_location(&self, cluster: u32) -> (u64, usize) {
+        let byte = usize::cast_from(cluster) * 4;
+        let sector =
+            self.start + u64::from(self.bs.reserved_sectors) + u64::cast_from(byte / SECTOR_SIZE);
+        (sector, byte % SECTOR_SIZE)
+    }
+
+    fn fat_entry(&mut self, cluster: u32) -> Result<u32, VfsError> {
+        let (sector, offset) = self.fat_entry_location(cluster);
+        Ok(le_u32(self.cache.read(sector)?, offset) & FAT_ENTRY_MASK)
+    }
+
+    /// Sets the FAT entry of `cluster` in all FATs.
+    fn set_fat_entry(&mut self, cluster: u32, value: u32) -> Result<(), VfsError> {
+        let (sector, offset) = self.fat_entry_location(cluster);
+        for i in 0..self.bs.num_fats {
+            let fat_sector = sector + u64::from(i) * u64::from(self.bs.fat_size);
+            let data = self.cache.write(fat_sector)?;
+            let reserved = le_u32(data, offset) & !FAT_ENTRY_MASK;
+            set_le_u32(data, offset, reserved | (value & FAT_ENTRY_MASK));
+        }
+        Ok(())
+    }
+
+    /// Returns the cluster following `cluster` in its chain.
+    fn next_cluster(&mut self, cluster: u32) -> Result<Option<u32>, VfsError> {
+        let next = self.fat_entry(cluster)?;
+        if next >= FAT_END_OF_CHAIN {
+            return Ok(None);
+        }
+        if !self.bs.is_valid_cluster(next) {
+            warn!("fat32: corrupted cluster chain, cluster {cluster} links to {next:#x}");
+            return Err(VfsError::Io);
+        }
+        Ok(Some(next))
+    }
+
+    /// Returns the clusters of the chain starting at `first`.
+    ///
+    /// Cluster 0 is the empty chain of an empty file.
+    pub(super) fn cluster_chain(&mut self, first: u32) -> Result<Vec<u32>, VfsError> {
+        let mut chain = Vec::new();
+        if first == 0 {
+            return Ok(chain);
+        }
+        if !self.bs.is_valid_cluster(first) {
+            warn!("fat32: invalid first cluster {first:#x}");
+            return Err(VfsError::Io);
+        }
+        let mut cluster = Some(first);
+        while let Some(current) = cluster {
+            if chain.len() >= usize::cast_from(self.bs.cluster_count()) {
+                warn!("fat32: cluster chain starting at {first} has a loop");
+                return Err(VfsError::Io);
+            }
+            chain.push(current);
+            cluster = self.next_cluster(current)?;
+        }
+        Ok(chain)
+    }
+
+    /// Allocates a zero-filled cluster and appends it to the chain ending at
+    /// `last`.
+    pub(super) fn allocate_cluster(&mut self, last: Option<u32>) -> Result<u32, VfsError> {
+        if self.is_read_only() {
+            return Err(VfsError::ReadOnlyFileSystem);
+        }
+        if self.free_count == Some(0) {
+            return Err(VfsError::NoSpace);
+        }
+        let count = self.bs.cluster_count();
+        let mut found = None;
+        for i in 0..count {
+            let cluster = 2 + (self.next_free - 2 + i) % count;
+            if self.fat_entry(cluster)? == FAT_FREE {
+                found = Some(cluster);
+                break;
+            }
+        }
+        let cluster = found.ok_or(VfsError::NoSpace)?;
+
+        self.set_fat_entry(cluster, FAT_END_OF_CHAIN)?;
+        if let Some(last) = last {
+            self.set_fat_entry(last, cluster)?;
+        }
+        self.free_count = self.free_count.map(|count| count - 1);
+        self.next_free = if cluster + 1 < count + 2 {
+            cluster + 1
+        } else {
+            2
+        };
+        self.fs_info_dirty = true;
+
+        let first_sector = self.cluster_sector(cluster);
+        for sector in first_sector..first_sector + u64::from(self.bs.sectors_per_cluster) {
+            self.cache.overwrite(sector)?.fill(0);
+        }
+        Ok(cluster)
+    }
+
+    fn cluster_sector(&self, cluster: u32) -> u64 {
+        assert!(self.bs.is_valid_cluster(cluster));
+        self.start
+            + u64::from(self.bs.data_start())
+            + u64::from(cluster - 2) * u64::from(self.bs.sectors_per_cluster)
+    }
+
+    /// Returns the device sector and the offset in it of the byte at `pos` in
+    /// the data of `chain`.
+    fn locate(&self, chain: &[u32], pos: usize) -> (u64, usize) {
+        let cluster_size = self.cluster_size();
+        let cluster = chain[pos / cluster_size];
+        let offset = pos % cluster_size;
+        (
+            self.cluster_sector(cluster) + u64::cast_from(offset / SECTOR_SIZE),
+            offset % SECTOR_SIZE,
+        )
+    }
+
+    /// Reads the data of `chain` at `pos` into `buf`.
+    pub(super) fn read_chain(
+        &mut self,
+        chain: &[u32],
+        mut pos: usize,
+        mut buf: &mut [u8],
+    ) -> Result<(), VfsError> {
+        while !buf.is_empty() {
+            let (sector, offset) = self.locate(chain, pos);
+            let len = usize::min(SECTOR_SIZE - offset, buf.len());
+            let data = self.cache.read(sector)?;
+            buf[..len].copy_from_slice(&data[offset..offset + len]);
+            buf = &mut buf[len..];
+            pos += len;
+        }
+        Ok(())
+    }
+
+    /// Writes `buf` to the data of `chain` at `pos`.
+    pub(super) fn write_chain(
+        &mut self,
+        chain: &[u32],
+        mut pos: usize,
+        mut buf: &[u8],
+    ) -> Result<(), VfsError> {
+        while !buf.is_empty() {
+            let (sector, offset) = self.locate(chain, pos);
+            let len = usize::min(SECTOR_SIZE - offset, buf.len());
+            let data = if offset == 0 && len == SECTOR_SIZE {
+                self.cache.overwrite(sector)?
+            } else {
+                self.cache.write(sector)?
+            };
+            data[offset..offset + len].copy_from_slice(&buf[..len]);
+            buf = &buf[len..];
+            pos += len;
+        }
+        Ok(())
+    }
+
+    /// Calls `f` with each slot of the directory starting at `first_cluster`,
+    /// until `f` returns `false`.
+    fn for_each_slot(
+        &mut self,
+        first_cluster: u32,
+        mut f: impl FnMut(&RawEntry, EntryLocation) -> bool,
+    ) -> Result<(), VfsError> {
+        let chain = self.cluster_chain(first_cluster)?;
+        for &cluster in &chain {
+            let first_sector = self.cluster_sector(cluster);
+            for sector in first_sector..first_sector + u64::from(self.bs.sectors_per_cluster) {
+                let data = self.cache.read(sector)?;
+                for (i, raw) in data.as_chunks::<ENTRY_SIZE>().0.iter().enumerate() {
+                    let location = EntryLocation {
+                        sector,
+                        offset: i * ENTRY_SIZE,
+                    };
+                    if !f(raw, location) {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the entries of the directory starting at `first_cluster`.
+    pub(super) fn read_dir(&mut self, first_cluster: u32) -> Result<Vec<Entry>, VfsError> {
+        let mut parser = EntryParser::default();
+        let mut entries = Vec::new();
+        self.for_each_slot(first_cluster, |raw, location| {
+            if matches!(dir::classify(raw), Slot::End) {
+                return false;
+            }
+            entries.extend(parser.feed(raw, location));
+            true
+        })?;
+        Ok(entries)
+    }
+
+    /// Finds `count` consecutive free slots in the directory starting at
+    /// `first_cluster`, extending the directory if needed.
+    fn find_free_slots(
+        &mut self,
+        first_cluster: u32,
+        count: usize,
+    ) -> Result<Vec<EntryLocation>, VfsError> {
+        let mut run = Vec::new();
+        self.for_each_slot(first_cluster, |raw, location| {
+            match dir::classify(raw) {
+                Slot::End | Slot::Deleted => run.push(location),
+                Slot::LongName | Slot::Short => run.clear(),
+            }
+            run.len() < count
+        })?;
+        while run.len() < count {
+            let last = *self.cluster_chain(first_cluster)?.last().unwrap();
+            let cluster = self.allocate_cluster(Some(last))?;
+            let first_sector = self.cluster_sector(cluster);
+            'sectors: for sector in
+                first_sector..first_sector + u64::from(self.bs.sectors_per_cluster)
+            {
+                for offset in (0..SECTOR_SIZE).step_by(ENTRY_SIZE) {
+                    run.push(EntryLocation { sector, offset });
+                    if run.len() == count {
+                        break 'sectors;
+                    }
+                }
+            }
+        }
+        Ok(run)
+    }
+
+    fn write_raw_entry(&mut self, location: EntryLocation, raw: &RawEntry) -> Result<(), VfsError> {
+        let data = self.cache.write(location.sector)?;
+        data[location.offset..location.offset + ENTRY_SIZE].copy_from_slice(raw);
+        Ok(())
+    }
+
+    /// Adds the entry `name` to the directory starting at `dir_cluster`.
+    pub(super) fn add_entry(
+        &mut self,
+        dir_cluster: u32,
+        name: &str,
+        attr: u8,
+        first_cluster: u32,
+    ) -> Result<Entry, VfsError> {
+        if self.is_read_only() {
+            return Err(VfsError::ReadOnlyFileSystem);
+        }
+        let existing = self.read_dir(dir_cluster)?;
+        if existing.iter().any(|entry| entry.has_name(name)) {
+            return Err(VfsError::AlreadyExists);
+        }
+        let raws = dir::build_entries(name, attr, first_cluster, timestamp(), |short_name| {
+            existing.iter().any(|entry| entry.short_name == *short_name)
+        })?;
+        let locations = self.find_free_slots(dir_cluster, raws.len())?;
+        for (raw, &location) in raws.iter().zip(&locations) {
+            self.write_raw_entry(location, raw)?;
+        }
+
+        let mut parser = EntryParser::default();
+        let entry = raws
+            .iter()
+            .zip(&locations)
+            .find_map(|(raw, &location)| parser.feed(raw, location))
+            .unwrap();
+        Ok(entry)
+    }
+
+    /// Creates a directory in the directory starting at `parent_cluster`.
+    ///
+    /// Returns the first cluster of the new directory, which contains the `.`
+    /// and `..` entries.
+    pub(super) fn create_dir_cluster(&mut self, parent_cluster: u32) -> Result<u32, VfsError> {
+        let cluster = self.allocate_cluster(None)?;
+        // `..` refers to the root directory by cluster 0
+        let parent_cluster = if parent_cluster == self.bs.root_cluster {
+            0
+        } else {
+            parent_cluster
+        };
+        let sector = self.cluster_sector(cluster);
+        let attr = dir::ATTR_DIRECTORY;
+        let dot = dir::short_entry(*b".          ", attr, cluster, timestamp());
+        let dot_dot = dir::short_entry(*b"..         ", attr, parent_cluster, timestamp());
+        self.write_raw_entry(EntryLocation { sector, offset: 0 }, &dot)?;
+        self.write_raw_entry(
+            EntryLocation {
+                sector,
+                offset: ENTRY_SIZE,
+            },
+            &dot_dot,
+        )?;
+        Ok(cluster)
+    }
+
+    /// Updates the first cluster, the size and the modification time of the
+    /// entry at `location`.
+    pub(super) fn update_entry(
+        &mut self,
+        location: EntryLocation,
+        first_cluster: u32,
+        size: u32,
+    ) -> Result<(), VfsError> {
+        let data = self.cache.write(location.sector)?;
+        let raw = &mut data[location.offset..location.offset + ENTRY_SIZE];
+        dir::update_entry(raw, first_cluster, size, timestamp());
+        Ok(())
+    }
+
+    /// Writes the `FSInfo` sector and the dirty sectors to the device.
+    pub(super) fn sync(&mut self) -> Result<(), VfsError> {
+        if self.fs_info_dirty && self.bs.fs_info_sector != 0 {
+            let sector = self.start + u64::from(self.bs.fs_info_sector);
+            let data = self.cache.write(sector)?;
+            if le_u32(data, 0) == FS_INFO_LEAD_SIGNATURE {
+                set_le_u32(data, 488, self.free_count.unwrap_or(FS_INFO_UNKNOWN));
+                set_le_u32(data, 492, self.next_free);
+            }
+            self.fs_info_dirty = false;
+        }
+        self.cache.sync()?;
+        Ok(())
+    }
+}
+
+/// Returns the current time in the FAT `(date, time)` format.
+///
+/// FAT timestamps have a 2-second resolution and range from 1980 to 2107.
+fn timestamp() -> (u16, u16) {
+    let now = SystemTime::try_now().unwrap_or(SystemTime::UNIX_EPOCH);
+    let DateTime {
+        year,
+        month,
+        day,
+        hour,
+        minute,
+        second,
+    } = now.to_date_time();
+    let Some(year) = year.checked_sub(1980).filter(|&year| year < 128) else {
+        // 1980-01-01 00:00:00
+        return (0x0021, 0);
+    };
+    let field = |value: u64| u16::try_from(value).unwrap();
+    let date = (field(year) << 9) | (field(month) << 5) | field(day);
+    let time = (field(hour) << 11) | (field(minute) << 5) | field(second / 2);
+    (date, time)
+}
@@ -0,0 +1,53 @@
+use alloc::{sync::Arc, vec::Vec};
+
+use super::{File, VfsError};
+
+/// Per-process table of open files, indexed by file descriptor.
+///
+/// Cloning the table shares the open files, including their offsets.
+#[derive(Debug, Default, Clone)]
+pub struct FdTable {
+    files: Vec<Option<Arc<dyn File>>>,
+}
+
+impl FdTable {
+    /// Maximum number of open files per process.
+    const MAX_FDS: usize = 64;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `file` at the lowest free file descriptor and returns it.
+    pub fn insert(&mut self, file: Arc<dyn File>) -> Result<usize, VfsError> {
+        if let Some(fd) = self.files.iter().position(Option::is_none) {
+            self.files[fd] = Some(file);
+            return Ok(fd);
+        }
+        if self.files.len() >= Self::MAX_FDS {
+            return Err(VfsError::TooManyOpenFiles);
+        }
+        self.files.push(Some(file));
+        Ok(self.files.len() - 1)
+    }
+
+    pub fn get(&self, fd: usize) -> Result<Arc<dyn File>, VfsError> {
+        self.files
+            .get(fd)
+            .and_then(Option::as_ref)
+            .map(Arc::clone)
+            .ok_or(VfsError::BadFileDescriptor)
+    }
+
+    pub fn remove(&mut self, fd: usize) -> Result<Arc<dyn File>, VfsError> {
+        let file = self
+            .files
+            .get_mut(fd)
+            .and_then(Option::take)
+            .ok_or(VfsError::BadFileDescriptor)?;
+        while self.files.last().is_some_and(Option::is_none) {
+            self.files.pop();
+        }
+        Ok(file)
+    }
+}
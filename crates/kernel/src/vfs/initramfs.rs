@@ -0,0 +1,99 @@
+use alloc::{format, string::String, sync::Arc, vec::Vec};
+
+use super::{DirEntry, FileSystem, FileType, Inode, Metadata, VfsError};
+use crate::initramfs::{self, Node};
+
+/// Read-only file system backed by the initramfs.
+#[derive(Debug)]
+pub(super) struct InitramfsFs {}
+
+impl InitramfsFs {
+    pub(super) fn new() -> Arc<Self> {
+        Arc::new(Self {})
+    }
+}
+
+impl FileSystem for InitramfsFs {
+    fn name(&self) -> &'static str {
+        "initramfs"
+    }
+
+    fn root(&self) -> Arc<dyn Inode> {
+        Arc::new(InitramfsInode {
+            path: "/".into(),
+            node: initramfs::lookup("/").unwrap(),
+        })
+    }
+}
+
+#[derive(Debug)]
+struct InitramfsInode {
+    path: String,
+    node: &'static Node,
+}
+
+impl InitramfsInode {
+    fn child_path(&self, name: &str) -> String {
+        if self.path == "/" {
+            format!("/{name}")
+        } else {
+            format!("{}/{name}", self.path)
+        }
+    }
+}
+
+fn file_type(node: &Node) -> FileType {
+    match node.file_type() {
+        cpio::FileType::Regular => FileType::Regular,
+        cpio::FileType::Directory => FileType::Directory,
+        cpio::FileType::Symlink => FileType::Symlink,
+        cpio::FileType::CharDevice => FileType::CharDevice,
+        cpio::FileType::BlockDevice => FileType::BlockDevice,
+        cpio::FileType::Fifo => FileType::Fifo,
+        cpio::FileType::Socket => FileType::Socket,
+        cpio::FileType::Unknown(_) => FileType::Unknown,
+    }
+}
+
+impl Inode for InitramfsInode {
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            ino: u64::from(self.node.ino()),
+            file_type: file_type(self.node),
+            perm: self.node.mode() & 0o7777,
+            size: self.node.data().len(),
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Result<Arc<dyn Inode>, VfsError> {
+        if file_type(self.node) != FileType::Directory {
+            return Err(VfsError::NotDirectory);
+        }
+        let path = self.child_path(name);
+        let node = initramfs::lookup(&path).ok_or(VfsError::NotFound)?;
+        Ok(Arc::new(Self { path, node }))
+    }
+
+    fn read_dir(&self) -> Result<Vec<DirEntry>, VfsError> {
+        if file_type(self.node) != FileType::Directory {
+            return Err(VfsError::NotDirectory);
+        }
+        let entries = initramfs::read_dir(&self.path)
+            .map(|(name, node)| DirEntry {
+                name: name.into(),
+                file_type: file_type(node),
+            })
+            .collect();
+        Ok(entries)
+    }
+
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize, VfsError> {
+        let data = self.node.data();
+        let Some(rest) = data.get(offset..) else {
+            return Ok(0);
+        };
+        let len = usize::min(rest.len(), buf.len());
+        buf[..len].copy_from_slice(&rest[..len]);
+        Ok(len)
+    }
+}
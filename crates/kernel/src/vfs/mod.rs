@@ -0,0 +1,368 @@
+use alloc::{string::String, sync::Arc, vec::Vec};
+use core::{any::Any, fmt};
+
+use snafu::ResultExt as _;
+
+#[cfg(feature = "ktest")]
+pub use self::fat32::ktests as fat32_ktests;
+pub use self::{
+    dentry::Dentry,
+    fd::FdTable,
+    mount::{mount, resolve},
+};
+use crate::{
+    block::{self, BlockDevice, BlockError},
+    error::GenericError,
+    power::{self, ShutdownKind},
+    sync::spinlock::SpinMutex,
+};
+
+mod dentry;
+mod devfs;
+mod fat32;
+mod fd;
+mod initramfs;
+pub mod mount;
+
+initcall!(INITCALL, late, init);
+
+/// Mounts the initramfs at `/` and the devfs at `/dev`.
+fn init() -> Result<(), GenericError> {
+    mount("/", initramfs::InitramfsFs::new()).whatever_context("failed to mount initramfs")?;
+    mount("/dev", devfs::DevFs::new()).whatever_context("failed to mount devfs")?;
+    power::register_shutdown_hook(power::PRIORITY_FILESYSTEM, sync_on_shutdown);
+    Ok(())
+}
+
+fn sync_on_shutdown(kind: ShutdownKind) -> Result<(), GenericError> {
+    // the file systems may sleep on the block devices
+    if kind == ShutdownKind::Panic {
+        return Ok(());
+    }
+    sync_all().whatever_context("failed to sync file systems")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VfsError {
+    NotFound,
+    Interrupted,
+    Io,
+    BadFileDescriptor,
+    Busy,
+    AlreadyExists,
+    NotDirectory,
+    IsDirectory,
+    InvalidArgument,
+    TooManyOpenFiles,
+    NoSpace,
+    ReadOnlyFileSystem,
+    NameTooLong,
+}
+
+impl VfsError {
+    pub fn errno(self) -> isize {
+        match self {
+            Self::NotFound => 2,
+            Self::Interrupted => 4,
+            Self::Io => 5,
+            Self::BadFileDescriptor => 9,
+            Self::Busy => 16,
+            Self::AlreadyExists => 17,
+            Self::NotDirectory => 20,
+            Self::IsDirectory => 21,
+            Self::InvalidArgument => 22,
+            Self::TooManyOpenFiles => 24,
+            Self::NoSpace => 28,
+            Self::ReadOnlyFileSystem => 30,
+            Self::NameTooLong => 36,
+        }
+    }
+}
+
+impl fmt::Display for VfsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::NotFound => "no such file or directory",
+            Self::Interrupted => "interrupted system call",
+            Self::Io => "input/output error",
+            Self::BadFileDescriptor => "bad file descriptor",
+            Self::Busy => "device or resource busy",
+            Self::AlreadyExists => "file exists",
+            Self::NotDirectory => "not a directory",
+            Self::IsDirectory => "is a directory",
+            Self::InvalidArgument => "invalid argument",
+            Self::TooManyOpenFiles => "too many open files",
+            Self::NoSpace => "no space left on device",
+            Self::ReadOnlyFileSystem => "read-only file system",
+            Self::NameTooLong => "file name too long",
+        };
+        f.write_str(s)
+    }
+}
+
+impl core::error::Error for VfsError {}
+
+impl From<BlockError> for VfsError {
+    fn from(e: BlockError) -> Self {
+        match e {
+            BlockError::ReadOnly => Self::ReadOnlyFileSystem,
+            BlockError::Io
+            | BlockError::Unsupported
+            | BlockError::InvalidRequest
+            | BlockError::NoMemory => Self::Io,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileType {
+    Regular,
+    Directory,
+    Symlink,
+    CharDevice,
+    BlockDevice,
+    Fifo,
+    Socket,
+    Unknown,
+}
+
+impl FileType {
+    /// Returns the `S_IFMT` bits of the file mode.
+    pub fn mode_bits(self) -> u32 {
+        match self {
+            Self::Regular => 0o100_000,
+            Self::Directory => 0o040_000,
+            Self::Symlink => 0o120_000,
+            Self::CharDevice => 0o020_000,
+            Self::BlockDevice => 0o060_000,
+            Self::Fifo => 0o010_000,
+            Self::Socket => 0o140_000,
+            Self::Unknown => 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Metadata {
+    pub ino: u64,
+    pub file_type: FileType,
+    /// Permission bits.
+    pub perm: u32,
+    pub size: usize,
+}
+
+impl Metadata {
+    /// Returns the file mode, including the file type bits.
+    pub fn mode(&self) -> u32 {
+        self.file_type.mode_bits() | (self.perm & 0o7777)
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(not(feature = "shell"), expect(dead_code))]
+pub struct DirEntry {
+    pub name: String,
+    pub file_type: FileType,
+}
+
+/// Access mode of an open file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpenMode {
+    ReadOnly,
+    WriteOnly,
+    ReadWrite,
+}
+
+/// `open` flag creating a regular file if the path does not exist.
+pub const O_CREAT: usize = 0o100;
+
+impl OpenMode {
+    const O_ACCMODE: usize = 0o3;
+
+    /// Parses the Linux compatible `open` flags.
+    ///
+    /// Only the access mode bits and [`O_CREAT`] are supported.
+    pub fn from_flags(flags: usize) -> Result<Self, VfsError> {
+        if flags & !(Self::O_ACCMODE | O_CREAT) != 0 {
+            return Err(VfsError::InvalidArgument);
+        }
+        match flags & Self::O_ACCMODE {
+            0 => Ok(Self::ReadOnly),
+            1 => Ok(Self::WriteOnly),
+            2 => Ok(Self::ReadWrite),
+            _ => Err(VfsError::InvalidArgument),
+        }
+    }
+
+    fn is_readable(self) -> bool {
+        matches!(self, Self::ReadOnly | Self::ReadWrite)
+    }
+
+    fn is_writable(self) -> bool {
+        matches!(self, Self::WriteOnly | Self::ReadWrite)
+    }
+}
+
+/// File system object, such as a regular file, a directory or a device.
+pub trait Inode: fmt::Debug + Send + Sync {
+    fn metadata(&self) -> Metadata;
+
+    /// Returns the child named `name` of this directory.
+    fn lookup(&self, _name: &str) -> Result<Arc<dyn Inode>, VfsError> {
+        Err(VfsError::NotDirectory)
+    }
+
+    /// Returns the children of this directory.
+    #[cfg_attr(not(feature = "shell"), expect(dead_code))]
+    fn read_dir(&self) -> Result<Vec<DirEntry>, VfsError> {
+        Err(VfsError::NotDirectory)
+    }
+
+    /// Reads the contents at `offset` into `buf`, returning the number of
+    /// bytes read.
+    ///
+    /// Devices without a position ignore `offset`.
+    fn read_at(&self, _offset: usize, _buf: &mut [u8]) -> Result<usize, VfsError> {
+        Err(VfsError::InvalidArgument)
+    }
+
+    /// Writes `buf` at `offset`, returning the number of bytes written.
+    fn write_at(&self, _offset: usize, _buf: &[u8]) -> Result<usize, VfsError> {
+        Err(VfsError::ReadOnlyFileSystem)
+    }
+
+    /// Creates the child named `name` of this directory.
+    fn create(&self, _name: &str, _file_type: FileType) -> Result<Arc<dyn Inode>, VfsError> {
+        Err(VfsError::ReadOnlyFileSystem)
+    }
+
+    /// Writes the cached contents and metadata of this inode to the storage.
+    fn sync(&self) -> Result<(), VfsError> {
+        Ok(())
+    }
+}
+
+/// Mounted file system.
+pub trait FileSystem: fmt::Debug + Send + Sync {
+    fn name(&self) -> &'static str;
+    fn root(&self) -> Arc<dyn Inode>;
+
+    /// Writes all the cached data to the storage.
+    fn sync(&self) -> Result<(), VfsError> {
+        Ok(())
+    }
+}
+
+type MountFn = fn(Arc<dyn BlockDevice>) -> Result<Arc<dyn FileSystem>, VfsError>;
+
+/// File system type that can be mounted from a block device.
+struct FileSystemType {
+    name: &'static str,
+    mount: MountFn,
+}
+
+const FILE_SYSTEM_TYPES: &[FileSystemType] = &[FileSystemType {
+    name: "fat32",
+    mount: fat32::Fat32Fs::mount,
+}];
+
+/// Open file description, shared by duplicated file descriptors.
+pub trait File: Any + fmt::Debug + Send + Sync {
+    fn read(&self, buf: &mut [u8]) -> Result<usize, VfsError>;
+    fn write(&self, buf: &[u8]) -> Result<usize, VfsError>;
+    #[expect(dead_code)]
+    fn metadata(&self) -> Metadata;
+    fn sync(&self) -> Result<(), VfsError>;
+}
+
+/// Mounts the block device named `device` at `path` as a `fs_type` file
+/// system.
+#[cfg_attr(not(feature = "shell"), expect(dead_code))]
+pub fn mount_device(fs_type: &str, device: &str, path: &str) -> Result<(), VfsError> {
+    let fs_type = FILE_SYSTEM_TYPES
+        .iter()
+        .find(|ty| ty.name == fs_type)
+        .ok_or(VfsError::InvalidArgument)?;
+    let device = block::find(device).ok_or(VfsError::NotFound)?;
+    mount(path, (fs_type.mount)(device)?)
+}
+
+/// Writes the cached data of all the mounted file systems to the storage.
+pub fn sync_all() -> Result<(), VfsError> {
+    for (_path, fs) in mount::mounts() {
+        fs.sync()?;
+    }
+    Ok(())
+}
+
+/// Opens the file at the absolute `path`.
+pub fn open(path: &str, mode: OpenMode) -> Result<Arc<dyn File>, VfsError> {
+    let dentry = resolve(path)?;
+    let inode = dentry.inode();
+    if mode.is_writable() && inode.metadata().file_type == FileType::Directory {
+        return Err(VfsError::IsDirectory);
+    }
+    Ok(Arc::new(InodeFile {
+        inode: Arc::clone(inode),
+        mode,
+        offset: SpinMutex::new(0),
+    }))
+}
+
+/// Creates a file of `file_type` at the absolute `path`.
+pub fn create(path: &str, file_type: FileType) -> Result<Arc<dyn Inode>, VfsError> {
+    let (parent, name) = mount::split_parent(path)?;
+    let parent = resolve(&parent)?;
+    match parent.inode().lookup(&name) {
+        Ok(_) => return Err(VfsError::AlreadyExists),
+        Err(VfsError::NotFound) => {}
+        Err(e) => return Err(e),
+    }
+    parent.inode().create(&name, file_type)
+}
+
+/// Returns the metadata of the file at the absolute `path`.
+pub fn stat(path: &str) -> Result<Metadata, VfsError> {
+    Ok(resolve(path)?.inode().metadata())
+}
+
+/// File opened on an inode, reading and writing at its own offset.
+#[derive(Debug)]
+struct InodeFile {
+    inode: Arc<dyn Inode>,
+    mode: OpenMode,
+    offset: SpinMutex<usize>,
+}
+
+impl File for InodeFile {
+    fn read(&self, buf: &mut [u8]) -> Result<usize, VfsError> {
+        if !self.mode.is_readable() {
+            return Err(VfsError::BadFileDescriptor);
+        }
+        if self.inode.metadata().file_type == FileType::Directory {
+            return Err(VfsError::IsDirectory);
+        }
+        let offset = *self.offset.lock();
+        let nread = self.inode.read_at(offset, buf)?;
+        *self.offset.lock() += nread;
+        Ok(nread)
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize, VfsError> {
+        if !self.mode.is_writable() {
+            return Err(VfsError::BadFileDescriptor);
+        }
+        let offset = *self.offset.lock();
+        let nwritten = self.inode.write_at(offset, buf)?;
+        *self.offset.lock() += nwritten;
+        Ok(nwritten)
+    }
+
+    fn metadata(&self) -> Metadata {
+        self.inode.metadata()
+    }
+
+    fn sync(&self) -> Result<(), VfsError> {
+        self.inode.sync()
+    }
+}
@@ -0,0 +1,129 @@
+use alloc::{collections::btree_map::BTreeMap, string::String, sync::Arc, vec::Vec};
+
+use super::{Dentry, FileSystem, FileType, VfsError};
+use crate::sync::spinlock::SpinMutex;
+
+/// Maximum length of a path, including the NUL terminator in user space.
+pub const PATH_MAX: usize = 4096;
+
+/// Mounted file systems, keyed by the normalized mount point path.
+static MOUNTS: SpinMutex<BTreeMap<String, Arc<dyn FileSystem>>> = SpinMutex::new(BTreeMap::new());
+
+/// Mounts `fs` at the absolute `path`.
+///
+/// The mount point does not need to exist in the parent file system, as the
+/// initramfs may not provide directories such as `/dev`, but its parent must be
+/// a directory.
+pub fn mount(path: &str, fs: Arc<dyn FileSystem>) -> Result<(), VfsError> {
+    let path = normalize(path)?;
+    if path != "/" {
+        match resolve(&path) {
+            Ok(dentry) if dentry.inode().metadata().file_type != FileType::Directory => {
+                return Err(VfsError::NotDirectory);
+            }
+            Ok(_) => {}
+            Err(VfsError::NotFound) => {
+                let parent = resolve(parent_path(&path))?;
+                if parent.inode().metadata().file_type != FileType::Directory {
+                    return Err(VfsError::NotDirectory);
+                }
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    let mut mounts = MOUNTS.lock();
+    if mounts.contains_key(&path) {
+        return Err(VfsError::Busy);
+    }
+    info!("mounted {} at {path}", fs.name());
+    mounts.insert(path, fs);
+    Ok(())
+}
+
+/// Returns the mounted file systems and their mount points.
+pub fn mounts() -> Vec<(String, Arc<dyn FileSystem>)> {
+    MOUNTS
+        .lock()
+        .iter()
+        .map(|(path, fs)| (path.clone(), Arc::clone(fs)))
+        .collect()
+}
+
+/// Resolves the absolute `path` to the inode it refers to.
+///
+/// `.` and `..` are resolved lexically, and symbolic links are not followed.
+pub fn resolve(path: &str) -> Result<Dentry, VfsError> {
+    let path = normalize(path)?;
+    let (mount_len, fs) = {
+        let mounts = MOUNTS.lock();
+        let (mount_path, fs) = mounts
+            .iter()
+            .filter(|(mount_path, _fs)| is_under(&path, mount_path))
+            .max_by_key(|(mount_path, _fs)| mount_path.len())
+            .ok_or(VfsError::NotFound)?;
+        (mount_path.len(), Arc::clone(fs))
+    };
+
+    let mut inode = fs.root();
+    for name in path[mount_len..].split('/').filter(|name| !name.is_empty()) {
+        inode = inode.lookup(name)?;
+    }
+    Ok(Dentry::new(path, inode))
+}
+
+fn normalize(path: &str) -> Result<String, VfsError> {
+    if path.len() >= PATH_MAX {
+        return Err(VfsError::NameTooLong);
+    }
+    // relative paths are not supported, as there is no working directory yet.
+    if !path.starts_with('/') {
+        return Err(VfsError::InvalidArgument);
+    }
+
+    let mut components = Vec::new();
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            _ => components.push(component),
+        }
+    }
+
+    let mut normalized = String::new();
+    for component in &components {
+        normalized.push('/');
+        normalized.push_str(component);
+    }
+    if normalized.is_empty() {
+        normalized.push('/');
+    }
+    Ok(normalized)
+}
+
+/// Splits the absolute `path` into the normalized parent path and the last
+/// component.
+pub(super) fn split_parent(path: &str) -> Result<(String, String), VfsError> {
+    let path = normalize(path)?;
+    if path == "/" {
+        return Err(VfsError::AlreadyExists);
+    }
+    let name = &path[path.rfind('/').unwrap() + 1..];
+    Ok((parent_path(&path).into(), name.into()))
+}
+
+fn parent_path(path: &str) -> &str {
+    match path.rfind('/') {
+        Some(0) | None => "/",
+        Some(i) => &path[..i],
+    }
+}
+
+fn is_under(path: &str, mount_path: &str) -> bool {
+    mount_path == "/"
+        || path
+            .strip_prefix(mount_path)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
@@ -7,7 +7,7 @@
 //! # Features
 //!
 //! - **Automatic merging**: Overlapping or adjacent ranges are automatically
-//!   merged
+//!   merged, or only overlapping ones with [`RangeSet::with_merge_adjacent`]
 //! - **Sorted order**: Ranges are kept sorted by their start positions
 //! - **No-std support**: Can be used in `no_std` environments
 //! - **Fixed capacity**: Uses `ArrayVec` for stack-allocated storage
@@ -35,13 +35,21 @@
 //!
 //! - Insert: O(n) worst case, where n is the number of existing ranges
 //! - Remove: O(n) worst case
+//! - Pop or take from either end: O(1)
 //! - Iteration: O(1) per element
 //! - Memory: Stack-allocated with fixed capacity
 
 #![cfg_attr(coverage_nightly, feature(coverage_attribute))]
 #![no_std]
 
-use core::{iter::FusedIterator, mem, ops::Range, slice};
+use core::{
+    fmt,
+    hash::{Hash, Hasher},
+    iter::FusedIterator,
+    mem,
+    ops::Range,
+    slice,
+};
 
 use arrayvec::ArrayVec;
 
@@ -61,9 +69,45 @@ use arrayvec::ArrayVec;
 /// set.insert(3..7);
 /// assert_eq!(set.as_slice(), &[1..7]); // Ranges are merged
 /// ```
-#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+#[derive(Clone)]
 pub struct RangeSet<const CAP: usize> {
+    /// The ranges, preceded by `head` entries already removed from the front.
+    ///
+    /// The removed entries are left in place so that the front can be removed
+    /// without moving the other ranges, and are dropped by [`Self::compact`].
     ranges: ArrayVec<Range<usize>, CAP>,
+    head: usize,
+    merge_adjacent: bool,
+}
+
+impl<const CAP: usize> fmt::Debug for RangeSet<CAP> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RangeSet")
+            .field("ranges", &self.as_slice())
+            .field("merge_adjacent", &self.merge_adjacent)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<const CAP: usize> PartialEq for RangeSet<CAP> {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_slice() == other.as_slice() && self.merge_adjacent == other.merge_adjacent
+    }
+}
+
+impl<const CAP: usize> Eq for RangeSet<CAP> {}
+
+impl<const CAP: usize> Hash for RangeSet<CAP> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_slice().hash(state);
+        self.merge_adjacent.hash(state);
+    }
+}
+
+impl<const CAP: usize> Default for RangeSet<CAP> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<const CAP: usize> RangeSet<CAP> {
@@ -79,11 +123,53 @@ impl<const CAP: usize> RangeSet<CAP> {
     /// ```
     #[must_use]
     pub const fn new() -> Self {
+        Self::with_merge_adjacent(true)
+    }
+
+    /// Creates a new empty `RangeSet` with the merge policy of the adjacent
+    /// ranges.
+    ///
+    /// If `merge_adjacent` is `false`, ranges touching but not overlapping
+    /// each other are kept distinct, which is useful for tracking
+    /// allocations that must be told apart.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use range_set::RangeSet;
+    ///
+    /// let mut set = RangeSet::<10>::with_merge_adjacent(false);
+    /// set.insert(1..3);
+    /// set.insert(3..5);
+    /// assert_eq!(set.as_slice(), &[1..3, 3..5]); // Adjacent ranges are kept
+    ///
+    /// set.insert(4..6);
+    /// assert_eq!(set.as_slice(), &[1..3, 3..6]); // Overlapping ranges are merged
+    /// ```
+    #[must_use]
+    pub const fn with_merge_adjacent(merge_adjacent: bool) -> Self {
         Self {
             ranges: ArrayVec::new_const(),
+            head: 0,
+            merge_adjacent,
         }
     }
 
+    /// Returns `true` if adjacent ranges are merged on insertion.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use range_set::RangeSet;
+    ///
+    /// assert!(RangeSet::<10>::new().merges_adjacent());
+    /// assert!(!RangeSet::<10>::with_merge_adjacent(false).merges_adjacent());
+    /// ```
+    #[must_use]
+    pub const fn merges_adjacent(&self) -> bool {
+        self.merge_adjacent
+    }
+
     /// Returns an iterator over the ranges in the set.
     ///
     /// The ranges are returned in sorted order by their start positions.
@@ -101,7 +187,34 @@ impl<const CAP: usize> RangeSet<CAP> {
     /// assert_eq!(ranges, vec![1..3, 5..7]);
     /// ```
     pub fn iter(&self) -> slice::Iter<'_, Range<usize>> {
-        self.ranges.iter()
+        self.as_slice().iter()
+    }
+
+    /// Returns an iterator over the indices contained in the ranges of the
+    /// set, in ascending order.
+    ///
+    /// [`Iterator::nth`] and [`DoubleEndedIterator::nth_back`] skip whole
+    /// ranges without visiting their indices.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use range_set::RangeSet;
+    ///
+    /// let set: RangeSet<10> = [1..3, 5..7].into_iter().collect();
+    ///
+    /// let indices: Vec<_> = set.indices().collect();
+    /// assert_eq!(indices, vec![1, 2, 5, 6]);
+    /// assert_eq!(set.indices().nth(2), Some(5));
+    /// assert_eq!(set.indices().next_back(), Some(6));
+    /// ```
+    #[must_use]
+    pub fn indices(&self) -> Indices<'_> {
+        Indices {
+            ranges: self.as_slice().iter(),
+            front: 0..0,
+            back: 0..0,
+        }
     }
 
     /// Returns a slice containing all ranges in the set.
@@ -121,7 +234,7 @@ impl<const CAP: usize> RangeSet<CAP> {
     /// ```
     #[must_use]
     pub fn as_slice(&self) -> &[Range<usize>] {
-        self.ranges.as_slice()
+        &self.ranges[self.head..]
     }
 
     /// Returns `true` if the set contains no ranges.
@@ -139,13 +252,15 @@ impl<const CAP: usize> RangeSet<CAP> {
     /// ```
     #[must_use]
     pub fn is_empty(&self) -> bool {
-        self.ranges.is_empty()
+        self.as_slice().is_empty()
     }
 
     /// Inserts a range into the set.
     ///
     /// If the range overlaps with existing ranges or is adjacent to them,
-    /// they will be automatically merged into a single range.
+    /// they will be automatically merged into a single range. Adjacent ranges
+    /// are not merged if the set is created with
+    /// [`with_merge_adjacent(false)`](Self::with_merge_adjacent).
     ///
     /// # Panics
     ///
@@ -170,18 +285,19 @@ impl<const CAP: usize> RangeSet<CAP> {
             return;
         }
 
+        self.compact();
         let mut inserted = false;
         let mut ir = insert_range;
         let mut ranges = mem::take(&mut self.ranges).into_iter();
         for r in ranges.by_ref() {
-            if ir.end < r.start {
+            if ir.end < r.start || (!self.merge_adjacent && ir.end == r.start) {
                 inserted = true;
                 self.ranges.push(ir.clone());
                 self.ranges.push(r);
                 break;
             }
 
-            if ir.start > r.end {
+            if ir.start > r.end || (!self.merge_adjacent && ir.start == r.end) {
                 self.ranges.push(r);
                 continue;
             }
@@ -226,6 +342,7 @@ impl<const CAP: usize> RangeSet<CAP> {
             return;
         }
 
+        self.compact();
         let rr = remove_range;
         let mut ranges = mem::take(&mut self.ranges).into_iter();
         for r in ranges.by_ref() {
@@ -247,6 +364,103 @@ impl<const CAP: usize> RangeSet<CAP> {
         self.ranges.extend(ranges);
     }
 
+    /// Removes the lowest range from the set and returns it, or `None` if the
+    /// set is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use range_set::RangeSet;
+    ///
+    /// let mut set: RangeSet<10> = [1..3, 5..7].into_iter().collect();
+    /// assert_eq!(set.pop_first(), Some(1..3));
+    /// assert_eq!(set.as_slice(), &[5..7]);
+    /// ```
+    pub fn pop_first(&mut self) -> Option<Range<usize>> {
+        let first = self.ranges.get(self.head)?.clone();
+        self.advance_head();
+        Some(first)
+    }
+
+    /// Removes the highest range from the set and returns it, or `None` if the
+    /// set is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use range_set::RangeSet;
+    ///
+    /// let mut set: RangeSet<10> = [1..3, 5..7].into_iter().collect();
+    /// assert_eq!(set.pop_last(), Some(5..7));
+    /// assert_eq!(set.as_slice(), &[1..3]);
+    /// ```
+    pub fn pop_last(&mut self) -> Option<Range<usize>> {
+        if self.is_empty() {
+            return None;
+        }
+        let last = self.ranges.pop();
+        if self.is_empty() {
+            self.clear();
+        }
+        last
+    }
+
+    /// Removes the first `len` indices of the lowest range from the set and
+    /// returns them.
+    ///
+    /// Returns `None` without changing the set if `len` is zero, the set is
+    /// empty, or the lowest range is shorter than `len`. The higher ranges are
+    /// not looked at, so that the indices are handed out in ascending order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use range_set::RangeSet;
+    ///
+    /// let mut set: RangeSet<10> = [1..5, 8..20].into_iter().collect();
+    /// assert_eq!(set.take_front(3), Some(1..4));
+    /// assert_eq!(set.take_front(1), Some(4..5));
+    /// assert_eq!(set.as_slice(), &[8..20]);
+    /// assert_eq!(set.take_front(16), None);
+    /// ```
+    pub fn take_front(&mut self, len: usize) -> Option<Range<usize>> {
+        if len == 0 {
+            return None;
+        }
+        let first = self.ranges.get_mut(self.head)?;
+        if first.len() < len {
+            return None;
+        }
+        let taken = first.start..first.start + len;
+        first.start = taken.end;
+        if first.start == first.end {
+            self.advance_head();
+        }
+        Some(taken)
+    }
+
+    /// Drops the lowest range, which must exist.
+    fn advance_head(&mut self) {
+        self.head += 1;
+        if self.head == self.ranges.len() {
+            self.clear();
+        }
+    }
+
+    fn clear(&mut self) {
+        self.ranges.clear();
+        self.head = 0;
+    }
+
+    /// Moves the ranges to the start of the storage, dropping the entries
+    /// removed from the front.
+    fn compact(&mut self) {
+        if self.head > 0 {
+            self.ranges.drain(..self.head);
+            self.head = 0;
+        }
+    }
+
     /// Returns a new `RangeSet` containing the ranges in this set that are not
     /// in the other set.
     ///
@@ -269,7 +483,7 @@ impl<const CAP: usize> RangeSet<CAP> {
     #[must_use]
     pub fn difference(&self, other: &Self) -> Self {
         let mut result = self.clone();
-        for r in &other.ranges {
+        for r in other {
             result.remove(r.clone());
         }
         result
@@ -286,6 +500,7 @@ impl<const CAP: usize> FromIterator<Range<usize>> for RangeSet<CAP> {
 
 impl<const CAP: usize> Extend<Range<usize>> for RangeSet<CAP> {
     fn extend<T: IntoIterator<Item = Range<usize>>>(&mut self, iter: T) {
+        self.compact();
         for range in iter {
             self.ranges.push(range);
         }
@@ -296,7 +511,8 @@ impl<const CAP: usize> IntoIterator for RangeSet<CAP> {
     type Item = Range<usize>;
     type IntoIter = IntoIter<CAP>;
 
-    fn into_iter(self) -> Self::IntoIter {
+    fn into_iter(mut self) -> Self::IntoIter {
+        self.compact();
         IntoIter {
             iter: self.ranges.into_iter(),
         }
@@ -386,10 +602,98 @@ impl<'a, const CAP: usize> IntoIterator for &'a RangeSet<CAP> {
     type IntoIter = slice::Iter<'a, Range<usize>>;
 
     fn into_iter(self) -> Self::IntoIter {
-        self.ranges.iter()
+        self.iter()
+    }
+}
+
+/// An iterator over the indices contained in a `RangeSet`.
+///
+/// This struct is created by the [`indices`](RangeSet::indices) method on
+/// `RangeSet`.
+#[derive(Debug, Clone)]
+pub struct Indices<'a> {
+    ranges: slice::Iter<'a, Range<usize>>,
+    /// Rest of the range taken from the front.
+    front: Range<usize>,
+    /// Rest of the range taken from the back.
+    back: Range<usize>,
+}
+
+impl Iterator for Indices<'_> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(index) = self.front.next() {
+                return Some(index);
+            }
+            let Some(range) = self.ranges.next() else {
+                return self.back.next();
+            };
+            self.front = range.clone();
+        }
+    }
+
+    fn nth(&mut self, mut n: usize) -> Option<Self::Item> {
+        loop {
+            let len = self.front.len();
+            if n < len {
+                return self.front.nth(n);
+            }
+            n -= len;
+            self.front.start = self.front.end;
+            let Some(range) = self.ranges.next() else {
+                return self.back.nth(n);
+            };
+            self.front = range.clone();
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self
+            .ranges
+            .as_slice()
+            .iter()
+            .chain([&self.front, &self.back])
+            .try_fold(0_usize, |sum, range| sum.checked_add(range.len()));
+        match len {
+            Some(len) => (len, Some(len)),
+            None => (usize::MAX, None),
+        }
+    }
+}
+
+impl DoubleEndedIterator for Indices<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(index) = self.back.next_back() {
+                return Some(index);
+            }
+            let Some(range) = self.ranges.next_back() else {
+                return self.front.next_back();
+            };
+            self.back = range.clone();
+        }
+    }
+
+    fn nth_back(&mut self, mut n: usize) -> Option<Self::Item> {
+        loop {
+            let len = self.back.len();
+            if n < len {
+                return self.back.nth_back(n);
+            }
+            n -= len;
+            self.back.end = self.back.start;
+            let Some(range) = self.ranges.next_back() else {
+                return self.front.nth_back(n);
+            };
+            self.back = range.clone();
+        }
     }
 }
 
+impl FusedIterator for Indices<'_> {}
+
 #[cfg_attr(coverage_nightly, coverage(off))]
 #[cfg(test)]
 mod tests {
@@ -417,6 +721,42 @@ mod tests {
         assert_eq!(set.as_slice(), &[1..5]);
     }
 
+    #[test]
+    fn test_insert_adjacent_without_merge() {
+        let mut set = RangeSet::<128>::with_merge_adjacent(false);
+        set.insert(3..5);
+        set.insert(1..3);
+        set.insert(5..7);
+        assert_eq!(set.as_slice(), &[1..3, 3..5, 5..7]);
+    }
+
+    #[test]
+    fn test_insert_overlapping_without_merge() {
+        let mut set = RangeSet::<128>::with_merge_adjacent(false);
+        set.insert(1..3);
+        set.insert(3..5);
+        set.insert(7..9);
+        set.insert(4..7);
+        assert_eq!(set.as_slice(), &[1..3, 3..7, 7..9]);
+    }
+
+    #[test]
+    fn test_remove_without_merge() {
+        let mut set = RangeSet::<128>::with_merge_adjacent(false);
+        set.insert(1..3);
+        set.insert(3..5);
+        set.remove(2..4);
+        assert_eq!(set.as_slice(), &[1..2, 4..5]);
+        set.insert(2..4);
+        assert_eq!(set.as_slice(), &[1..2, 2..4, 4..5]);
+    }
+
+    #[test]
+    fn test_default_merges_adjacent() {
+        assert!(RangeSet::<128>::default().merges_adjacent());
+        assert_eq!(RangeSet::<128>::default(), RangeSet::new());
+    }
+
     #[test]
     #[expect(clippy::single_range_in_vec_init)]
     fn test_insert_multiple_merges() {
@@ -543,6 +883,67 @@ mod tests {
         set.remove(5..2);
     }
 
+    #[test]
+    fn test_pop() {
+        let mut set: RangeSet<128> = [1..3, 5..7, 9..11].into_iter().collect();
+        assert_eq!(set.pop_first(), Some(1..3));
+        assert_eq!(set.pop_last(), Some(9..11));
+        assert_eq!(set.pop_last(), Some(5..7));
+        assert!(set.is_empty());
+        assert_eq!(set.pop_first(), None);
+        assert_eq!(set.pop_last(), None);
+    }
+
+    #[test]
+    fn test_take_front() {
+        let mut set: RangeSet<128> = [1..5, 8..10].into_iter().collect();
+        assert_eq!(set.take_front(0), None);
+        assert_eq!(set.as_slice(), &[1..5, 8..10]);
+        assert_eq!(set.take_front(2), Some(1..3));
+        assert_eq!(set.as_slice(), &[3..5, 8..10]);
+        assert_eq!(set.take_front(3), None);
+        assert_eq!(set.as_slice(), &[3..5, 8..10]);
+        assert_eq!(set.take_front(2), Some(3..5));
+        assert_eq!(set.take_front(2), Some(8..10));
+        assert!(set.is_empty());
+        assert_eq!(set.take_front(0), None);
+    }
+
+    #[test]
+    fn test_pop_then_modify() {
+        let mut set: RangeSet<4> = [1..3, 5..7, 9..11, 13..15].into_iter().collect();
+        assert_eq!(set.pop_first(), Some(1..3));
+        assert_eq!(set.take_front(2), Some(5..7));
+        assert_eq!(set.as_slice(), &[9..11, 13..15]);
+        assert_eq!(set, [9..11, 13..15].into_iter().collect::<RangeSet<4>>());
+
+        // the removed entries are dropped to make room
+        set.insert(17..19);
+        set.insert(21..23);
+        assert_eq!(set.as_slice(), &[9..11, 13..15, 17..19, 21..23]);
+        assert_eq!(set.pop_first(), Some(9..11));
+        set.remove(14..18);
+        assert_eq!(set.as_slice(), &[13..14, 18..19, 21..23]);
+        assert_eq!(set.pop_first(), Some(13..14));
+        set.extend([25..27, 29..31]);
+        assert_eq!(
+            set.clone().into_iter().collect::<Vec<_>>(),
+            [18..19, 21..23, 25..27, 29..31]
+        );
+        assert_eq!(set.indices().next(), Some(18));
+    }
+
+    #[test]
+    #[expect(clippy::single_range_in_vec_init)]
+    fn test_take_front_without_merge() {
+        let mut set = RangeSet::<128>::with_merge_adjacent(false);
+        set.insert(1..3);
+        set.insert(3..5);
+        assert_eq!(set.take_front(2), Some(1..3));
+        assert_eq!(set.take_front(1), Some(3..4));
+        assert_eq!(set.as_slice(), &[4..5]);
+    }
+
     #[test]
     fn test_extend() {
         let mut set = RangeSet::<128>::new();
@@ -569,4 +970,69 @@ mod tests {
         let ranges: Vec<_> = set.iter().cloned().collect();
         assert_eq!(ranges, vec![1..3, 4..6]);
     }
+
+    #[test]
+    fn test_indices() {
+        let set: RangeSet<128> = [1..3, 5..6, 8..11].into_iter().collect();
+        let indices: Vec<_> = set.indices().collect();
+        assert_eq!(indices, vec![1, 2, 5, 8, 9, 10]);
+        let indices: Vec<_> = set.indices().rev().collect();
+        assert_eq!(indices, vec![10, 9, 8, 5, 2, 1]);
+        assert_eq!(set.indices().size_hint(), (6, Some(6)));
+
+        let set = RangeSet::<128>::new();
+        assert_eq!(set.indices().next(), None);
+        assert_eq!(set.indices().next_back(), None);
+        assert_eq!(set.indices().size_hint(), (0, Some(0)));
+    }
+
+    #[test]
+    fn test_indices_from_both_ends() {
+        let set: RangeSet<128> = [1..3, 5..6, 8..11].into_iter().collect();
+        let mut iter = set.indices();
+        assert_eq!(iter.next(), Some(1));
+        assert_eq!(iter.next_back(), Some(10));
+        assert_eq!(iter.next_back(), Some(9));
+        assert_eq!(iter.size_hint(), (3, Some(3)));
+        assert_eq!(iter.next(), Some(2));
+        assert_eq!(iter.next(), Some(5));
+        assert_eq!(iter.next(), Some(8));
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next_back(), None);
+
+        // a single range shared by both ends
+        let mut set = RangeSet::<128>::new();
+        set.insert(1..4);
+        let mut iter = set.indices();
+        assert_eq!(iter.next_back(), Some(3));
+        assert_eq!(iter.next(), Some(1));
+        assert_eq!(iter.next(), Some(2));
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next_back(), None);
+    }
+
+    #[test]
+    fn test_indices_nth() {
+        let set: RangeSet<128> = [1..3, 5..6, 8..11].into_iter().collect();
+        let mut iter = set.indices();
+        assert_eq!(iter.nth(1), Some(2));
+        assert_eq!(iter.next(), Some(5));
+        assert_eq!(iter.nth(1), Some(9));
+        assert_eq!(iter.nth(1), None);
+        assert_eq!(iter.next(), None);
+
+        let mut iter = set.indices();
+        assert_eq!(iter.nth_back(3), Some(5));
+        assert_eq!(iter.nth_back(1), Some(1));
+        assert_eq!(iter.next_back(), None);
+
+        let mut iter = set.indices();
+        assert_eq!(iter.next_back(), Some(10));
+        assert_eq!(iter.nth(4), Some(9));
+        assert_eq!(iter.next(), None);
+
+        let mut set = RangeSet::<128>::new();
+        set.insert(0..usize::MAX);
+        assert_eq!(set.indices().nth(usize::MAX - 1), Some(usize::MAX - 1));
+    }
 }
@@ -12,6 +12,20 @@ pub fn sfence_vma(vaddr: usize, asid: usize) {
     }
 }
 
+/// Flushes the TLB entries of `vaddr` for all address spaces.
+pub fn sfence_vma_addr(vaddr: usize) {
+    cfg_if::cfg_if! {
+        if #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))] {
+            unsafe {
+                core::arch::asm!("sfence.vma {}, zero", in(reg) vaddr);
+            }
+        } else {
+            let _ = vaddr;
+            unimplemented!("unsupported architecture")
+        }
+    }
+}
+
 pub fn sfence_vma_asid_all(asid: usize) {
     cfg_if::cfg_if! {
         if #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))] {
@@ -36,3 +50,36 @@ pub fn sfence_vma_all() {
         }
     }
 }
+
+/// Writes back the cache block containing `addr` (Zicbom `cbo.clean`).
+pub fn cbo_clean(addr: usize) {
+    cfg_if::cfg_if! {
+        if #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))] {
+            unsafe {
+                // encoded directly, as the target does not enable Zicbom
+                core::arch::asm!(".insn i 0x0f, 2, x0, {}, 1", in(reg) addr);
+            }
+        } else {
+            let _ = addr;
+            unimplemented!("unsupported architecture")
+        }
+    }
+}
+
+/// Invalidates the cache block containing `addr` (Zicbom `cbo.inval`).
+///
+/// # Safety
+///
+/// The writes to the cache block that are not written back are discarded.
+pub unsafe fn cbo_inval(addr: usize) {
+    cfg_if::cfg_if! {
+        if #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))] {
+            unsafe {
+                core::arch::asm!(".insn i 0x0f, 2, x0, {}, 0", in(reg) addr);
+            }
+        } else {
+            let _ = addr;
+            unimplemented!("unsupported architecture")
+        }
+    }
+}
@@ -0,0 +1,17 @@
+//! SBI Base Extension interface.
+//!
+//! This module provides functions to interact with the SBI Base Extension,
+//! allowing the supervisor-mode software to query the SBI implementation.
+
+use crate::SbiRet;
+
+pub const EXTENSION_ID: usize = 0x10;
+
+/// Returns whether the extension `extension_id` is available.
+///
+/// The returned value is 0 if the extension is not available, and an
+/// extension-specific non-zero value otherwise.
+pub fn probe_extension(extension_id: usize) -> SbiRet {
+    const FUNCTION_ID: usize = 0x3;
+    unsafe { crate::ecall1(extension_id, EXTENSION_ID, FUNCTION_ID) }
+}
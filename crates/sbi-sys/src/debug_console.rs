@@ -4,10 +4,30 @@
 //! Extension, allowing reading from and writing to the debug console using SBI
 //! calls.
 
+use platform_cast::CastFrom as _;
+
 use crate::SbiRet;
 
 pub const EXTENSION_ID: usize = 0x44_42_43_4E; // 'DBCN' in ASCII
 
+/// Splits a physical address into the lower and upper `XLEN` bits, passed as
+/// `base_addr_lo` and `base_addr_hi`.
+///
+/// The upper bits are always zero on RV64.
+#[must_use]
+pub fn split_address(addr: u64) -> (usize, usize) {
+    match () {
+        #[cfg(target_pointer_width = "64")]
+        () => (usize::cast_from(addr), 0),
+        #[cfg(target_pointer_width = "32")]
+        () => {
+            #[expect(clippy::cast_possible_truncation)]
+            let (lo, hi) = (addr as u32, (addr >> 32) as u32);
+            (usize::cast_from(lo), usize::cast_from(hi))
+        }
+    }
+}
+
 /// Writes bytes to the debug console from input memory.
 ///
 /// # Safety
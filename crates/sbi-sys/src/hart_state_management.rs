@@ -74,6 +74,12 @@ pub fn hart_get_status(hartid: usize) -> SbiRet {
     unsafe { crate::ecall1(hartid, EXTENSION_ID, FUNCTION_ID) }
 }
 
+/// Default retentive suspend, which preserves the hart registers and CSRs.
+pub const SUSPEND_TYPE_DEFAULT_RETENTIVE: u32 = 0x0000_0000;
+/// Default non-retentive suspend, which resumes the hart at the resume address
+/// without preserving the hart registers and CSRs.
+pub const SUSPEND_TYPE_DEFAULT_NON_RETENTIVE: u32 = 0x8000_0000;
+
 /// Requests the SBI implementation to put the calling hart in a platform
 /// specific suspend (or low power) state.
 ///
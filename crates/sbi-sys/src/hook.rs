@@ -0,0 +1,58 @@
+//! Hook called around every SBI call, for tracing the calls.
+
+use core::{
+    ptr,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+use crate::SbiRet;
+
+static HOOK: AtomicPtr<CallHook> = AtomicPtr::new(ptr::null_mut());
+
+/// Functions called before and after every SBI call.
+///
+/// The functions are called in the context of the caller, so they must not
+/// make SBI calls themselves.
+#[derive(Debug)]
+pub struct CallHook {
+    /// Called with the extension ID and the function ID before the call.
+    pub enter: fn(usize, usize),
+    /// Called with the extension ID, the function ID and the result after the
+    /// call.
+    pub exit: fn(usize, usize, &SbiRet),
+}
+
+/// Sets the hook called around every SBI call.
+pub fn set_hook(hook: &'static CallHook) {
+    HOOK.store(ptr::from_ref(hook).cast_mut(), Ordering::Release);
+}
+
+#[cfg_attr(
+    not(any(target_arch = "riscv32", target_arch = "riscv64")),
+    expect(dead_code)
+)]
+fn get() -> Option<&'static CallHook> {
+    unsafe { HOOK.load(Ordering::Acquire).as_ref() }
+}
+
+#[inline]
+#[cfg_attr(
+    not(any(target_arch = "riscv32", target_arch = "riscv64")),
+    expect(dead_code)
+)]
+pub(crate) fn enter(extension_id: usize, function_id: usize) {
+    if let Some(hook) = get() {
+        (hook.enter)(extension_id, function_id);
+    }
+}
+
+#[inline]
+#[cfg_attr(
+    not(any(target_arch = "riscv32", target_arch = "riscv64")),
+    expect(dead_code)
+)]
+pub(crate) fn exit(extension_id: usize, function_id: usize, ret: &SbiRet) {
+    if let Some(hook) = get() {
+        (hook.exit)(extension_id, function_id, ret);
+    }
+}
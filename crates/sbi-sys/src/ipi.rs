@@ -0,0 +1,17 @@
+//! SBI IPI Extension interface.
+//!
+//! This module provides functions to interact with the SBI IPI Extension,
+//! allowing the supervisor-mode software to send inter-processor interrupts.
+
+use crate::SbiRet;
+
+pub const EXTENSION_ID: usize = 0x73_50_49; // 'sPI' in ASCII
+
+/// Sends an inter-processor interrupt to all the harts defined in `hart_mask`.
+///
+/// Interprocessor interrupts manifest at the receiving harts as the supervisor
+/// software interrupts.
+pub fn send_ipi(hart_mask: usize, hart_mask_base: usize) -> SbiRet {
+    const FUNCTION_ID: usize = 0x0;
+    unsafe { crate::ecall2(hart_mask, hart_mask_base, EXTENSION_ID, FUNCTION_ID) }
+}
@@ -0,0 +1,17 @@
+//! SBI Legacy Extensions interface.
+//!
+//! This module provides functions of the deprecated SBI v0.1 extensions, for
+//! the SBI implementations without their replacement extensions.
+
+use crate::SbiRet;
+
+pub const CONSOLE_PUTCHAR_EXTENSION_ID: usize = 0x01;
+
+/// Writes a byte to the debug console.
+///
+/// Legacy extensions return only the error code, and the `value` of the
+/// returned `SbiRet` is unspecified.
+pub fn console_putchar(byte: u8) -> SbiRet {
+    const FUNCTION_ID: usize = 0x0;
+    unsafe { crate::ecall1(usize::from(byte), CONSOLE_PUTCHAR_EXTENSION_ID, FUNCTION_ID) }
+}
@@ -10,9 +10,14 @@
 
 use core::{error::Error, fmt, num::NonZeroIsize};
 
+pub mod base;
 pub mod debug_console;
 pub mod hart_state_management;
+pub mod hook;
+pub mod ipi;
+pub mod legacy;
 pub mod rfence;
+pub mod system_reset;
 
 /// Represents an SBI error code.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -118,6 +123,7 @@ pub unsafe fn ecall0(extension_id: usize, function_id: usize) -> SbiRet {
         () => {
             let error;
             let value;
+            hook::enter(extension_id, function_id);
             unsafe {
                 core::arch::asm!(
                     "ecall",
@@ -128,7 +134,9 @@ pub unsafe fn ecall0(extension_id: usize, function_id: usize) -> SbiRet {
                 );
             }
 
-            SbiRet { error, value }
+            let ret = SbiRet { error, value };
+            hook::exit(extension_id, function_id, &ret);
+            ret
         }
         #[cfg(not(any(target_arch = "riscv32", target_arch = "riscv64")))]
         () => unimplemented!(),
@@ -153,6 +161,7 @@ pub unsafe fn ecall1(arg0: usize, extension_id: usize, function_id: usize) -> Sb
             let error;
             let value;
 
+            hook::enter(extension_id, function_id);
             unsafe {
                 core::arch::asm!(
                     "ecall",
@@ -163,7 +172,9 @@ pub unsafe fn ecall1(arg0: usize, extension_id: usize, function_id: usize) -> Sb
                 );
             }
 
-            SbiRet { error, value }
+            let ret = SbiRet { error, value };
+            hook::exit(extension_id, function_id, &ret);
+            ret
         }
         #[cfg(not(any(target_arch = "riscv32", target_arch = "riscv64")))]
         () => unimplemented!(),
@@ -188,6 +199,7 @@ pub unsafe fn ecall2(arg0: usize, arg1: usize, extension_id: usize, function_id:
             let error;
             let value;
 
+            hook::enter(extension_id, function_id);
             unsafe {
                 core::arch::asm!(
                     "ecall",
@@ -198,7 +210,9 @@ pub unsafe fn ecall2(arg0: usize, arg1: usize, extension_id: usize, function_id:
                 );
             }
 
-            SbiRet { error, value }
+            let ret = SbiRet { error, value };
+            hook::exit(extension_id, function_id, &ret);
+            ret
         }
         #[cfg(not(any(target_arch = "riscv32", target_arch = "riscv64")))]
         () => unimplemented!(),
@@ -229,6 +243,7 @@ pub unsafe fn ecall3(
             let error;
             let value;
 
+            hook::enter(extension_id, function_id);
             unsafe {
                 core::arch::asm!(
                     "ecall",
@@ -240,7 +255,9 @@ pub unsafe fn ecall3(
                 );
             }
 
-            SbiRet { error, value }
+            let ret = SbiRet { error, value };
+            hook::exit(extension_id, function_id, &ret);
+            ret
         }
         #[cfg(not(any(target_arch = "riscv32", target_arch = "riscv64")))]
         () => unimplemented!(),
@@ -272,6 +289,7 @@ pub unsafe fn ecall4(
             let error;
             let value;
 
+            hook::enter(extension_id, function_id);
             unsafe {
                 core::arch::asm!(
                     "ecall",
@@ -284,7 +302,9 @@ pub unsafe fn ecall4(
                 );
             }
 
-            SbiRet { error, value }
+            let ret = SbiRet { error, value };
+            hook::exit(extension_id, function_id, &ret);
+            ret
         }
         #[cfg(not(any(target_arch = "riscv32", target_arch = "riscv64")))]
         () => unimplemented!(),
@@ -317,6 +337,7 @@ pub unsafe fn ecall5(
             let error;
             let value;
 
+            hook::enter(extension_id, function_id);
             unsafe {
                 core::arch::asm!(
                     "ecall",
@@ -330,7 +351,9 @@ pub unsafe fn ecall5(
                 );
             }
 
-            SbiRet { error, value }
+            let ret = SbiRet { error, value };
+            hook::exit(extension_id, function_id, &ret);
+            ret
         }
         #[cfg(not(any(target_arch = "riscv32", target_arch = "riscv64")))]
         () => unimplemented!(),
@@ -365,6 +388,7 @@ pub unsafe fn ecall6(
             let error;
             let value;
 
+            hook::enter(extension_id, function_id);
             unsafe {
                 core::arch::asm!(
                     "ecall",
@@ -379,7 +403,9 @@ pub unsafe fn ecall6(
                 );
             }
 
-            SbiRet { error, value }
+            let ret = SbiRet { error, value };
+            hook::exit(extension_id, function_id, &ret);
+            ret
         }
         #[cfg(not(any(target_arch = "riscv32", target_arch = "riscv64")))]
         () => unimplemented!(),
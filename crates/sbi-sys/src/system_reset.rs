@@ -0,0 +1,38 @@
+//! SBI System Reset Extension interface.
+//!
+//! This module provides functions to interact with the SBI System Reset
+//! Extension, allowing the supervisor-mode software to request a system-level
+//! reboot or shutdown.
+
+use platform_cast::CastInto as _;
+
+use crate::SbiRet;
+
+pub const EXTENSION_ID: usize = 0x53_52_53_54; // 'SRST' in ASCII
+
+/// Shuts down the system.
+pub const RESET_TYPE_SHUTDOWN: u32 = 0x0;
+/// Powers off all the hardware and performs a cold boot.
+pub const RESET_TYPE_COLD_REBOOT: u32 = 0x1;
+/// Resets the processors and some hardware, and performs a warm boot.
+pub const RESET_TYPE_WARM_REBOOT: u32 = 0x2;
+
+/// No reason for the reset.
+pub const RESET_REASON_NO_REASON: u32 = 0x0;
+/// The reset is caused by a system failure.
+pub const RESET_REASON_SYSTEM_FAILURE: u32 = 0x1;
+
+/// Resets the system based on the provided `reset_type` and `reset_reason`.
+///
+/// This is a synchronous call and does not return if it succeeds.
+pub fn system_reset(reset_type: u32, reset_reason: u32) -> SbiRet {
+    const FUNCTION_ID: usize = 0x0;
+    unsafe {
+        crate::ecall2(
+            reset_type.cast_into(),
+            reset_reason.cast_into(),
+            EXTENSION_ID,
+            FUNCTION_ID,
+        )
+    }
+}
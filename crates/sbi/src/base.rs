@@ -0,0 +1,17 @@
+//! High-level interface for the SBI Base Extension.
+//!
+//! This module provides safe Rust wrappers for querying the SBI
+//! implementation.
+
+use sbi_sys::base;
+
+/// Returns whether the extension `extension_id` is available.
+///
+/// Legacy extensions cannot be probed reliably, as SBI implementations may
+/// not report them.
+#[must_use]
+pub fn probe_extension(extension_id: usize) -> bool {
+    base::probe_extension(extension_id)
+        .into_result()
+        .is_ok_and(|value| value != 0)
+}
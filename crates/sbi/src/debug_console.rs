@@ -3,23 +3,30 @@
 //! This module provides safe Rust wrappers for reading from and writing to the
 //! SBI debug console.
 
+use platform_cast::CastFrom as _;
 use sbi_sys::{SbiError, debug_console};
 
+pub const EXTENSION_ID: usize = debug_console::EXTENSION_ID;
+
 /// Writes bytes to the debug console from input memory.
+///
+/// The address of `bytes` is passed as the physical address, so `bytes` must
+/// be identity mapped.
 pub fn write(bytes: &[u8]) -> Result<usize, SbiError> {
     let num_bytes = bytes.len();
-    let base_addr_lo = bytes.as_ptr().addr();
-    let base_addr_hi = 0; // Assuming no high address part is needed for this example
+    let (base_addr_lo, base_addr_hi) = physical_address(bytes.as_ptr());
     let ret = unsafe { debug_console::write(num_bytes, base_addr_lo, base_addr_hi) };
     let written_bytes = ret.into_result()?;
     Ok(written_bytes.cast_unsigned())
 }
 
 /// Reads bytes from the debug console into output memory.
+///
+/// The address of `bytes` is passed as the physical address, so `bytes` must
+/// be identity mapped.
 pub fn read(bytes: &mut [u8]) -> Result<usize, SbiError> {
     let num_bytes = bytes.len();
-    let base_addr_lo = bytes.as_mut_ptr().addr();
-    let base_addr_hi = 0; // Assuming no high address part is needed for this example
+    let (base_addr_lo, base_addr_hi) = physical_address(bytes.as_mut_ptr());
     let ret = unsafe { debug_console::read(num_bytes, base_addr_lo, base_addr_hi) };
     let read_bytes = ret.into_result()?;
     Ok(read_bytes.cast_unsigned())
@@ -31,3 +38,8 @@ pub fn write_byte(byte: u8) -> Result<(), SbiError> {
     let _ = ret.into_result()?;
     Ok(())
 }
+
+/// Returns the lower and upper `XLEN` bits of the physical address of `ptr`.
+fn physical_address<T>(ptr: *const T) -> (usize, usize) {
+    debug_console::split_address(u64::cast_from(ptr.addr()))
+}
@@ -0,0 +1,81 @@
+use core::fmt;
+
+/// Set of harts passed to the SBI calls.
+///
+/// The harts are given as a bit mask, in which bit `i` selects the hart
+/// `base + i`, so a mask covers up to `usize::BITS` consecutive harts.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct HartMask {
+    mask: usize,
+    base: usize,
+}
+
+impl HartMask {
+    /// Hart mask base selecting all the harts, ignoring the mask.
+    const BASE_ALL: usize = usize::MAX;
+
+    /// Creates a hart mask selecting `base + i` for each bit `i` set in
+    /// `mask`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `base` is `usize::MAX`, which selects all the harts; use
+    /// [`HartMask::all`] for it.
+    #[must_use]
+    pub const fn new(mask: usize, base: usize) -> Self {
+        assert!(base != Self::BASE_ALL, "use `HartMask::all` for all harts");
+        Self { mask, base }
+    }
+
+    /// Creates a hart mask selecting only `hartid`.
+    #[must_use]
+    pub const fn from_hart(hartid: usize) -> Self {
+        Self::new(1, hartid)
+    }
+
+    /// Creates a hart mask selecting all the harts.
+    #[must_use]
+    pub const fn all() -> Self {
+        Self {
+            mask: 0,
+            base: Self::BASE_ALL,
+        }
+    }
+
+    #[must_use]
+    pub const fn mask(self) -> usize {
+        self.mask
+    }
+
+    #[must_use]
+    pub const fn base(self) -> usize {
+        self.base
+    }
+
+    /// Returns whether `hartid` is selected.
+    #[must_use]
+    pub const fn contains(self, hartid: usize) -> bool {
+        if self.base == Self::BASE_ALL {
+            return true;
+        }
+        match hartid.checked_sub(self.base) {
+            Some(bit) if bit < usize::BITS as usize => self.mask & (1 << bit) != 0,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Debug for HartMask {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.base == Self::BASE_ALL {
+            return f.write_str("all");
+        }
+        f.debug_set()
+            .entries(
+                (0..usize::BITS)
+                    .filter(|&i| self.mask & (1 << i) != 0)
+                    .map(|i| self.base + i as usize),
+            )
+            .finish()
+    }
+}
@@ -1,5 +1,8 @@
 use core::convert::Infallible;
 
+pub use sbi_sys::hart_state_management::{
+    SUSPEND_TYPE_DEFAULT_NON_RETENTIVE, SUSPEND_TYPE_DEFAULT_RETENTIVE,
+};
 use sbi_sys::{
     SbiError,
     hart_state_management::{
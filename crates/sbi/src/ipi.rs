@@ -0,0 +1,20 @@
+//! SBI IPI Extension interface.
+//!
+//! This module provides functions to send inter-processor interrupts to other
+//! harts.
+
+use sbi_sys::{SbiError, ipi};
+
+use crate::HartMask;
+
+pub const EXTENSION_ID: usize = 0x73_50_49; // 'sPI' in ASCII
+
+/// Sends an inter-processor interrupt to all the harts defined in `hart_mask`.
+///
+/// Interprocessor interrupts manifest at the receiving harts as the supervisor
+/// software interrupts.
+pub fn send_ipi(hart_mask: HartMask) -> Result<(), SbiError> {
+    let ret = ipi::send_ipi(hart_mask.mask(), hart_mask.base());
+    ret.into_result()?;
+    Ok(())
+}
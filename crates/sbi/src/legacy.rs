@@ -0,0 +1,13 @@
+//! High-level interface for the SBI Legacy Extensions.
+//!
+//! This module provides safe Rust wrappers for the deprecated SBI v0.1
+//! extensions.
+
+use sbi_sys::{SbiError, legacy};
+
+/// Writes a byte to the debug console.
+pub fn console_putchar(byte: u8) -> Result<(), SbiError> {
+    let ret = legacy::console_putchar(byte);
+    let _ = ret.into_result()?;
+    Ok(())
+}
@@ -1,7 +1,14 @@
 #![no_std]
 
-pub use sbi_sys::SbiError;
+pub use sbi_sys::{SbiError, SbiRet, hook};
 
+pub use self::hart_mask::HartMask;
+
+pub mod base;
 pub mod debug_console;
+mod hart_mask;
 pub mod hart_state_management;
+pub mod ipi;
+pub mod legacy;
 pub mod rfence;
+pub mod system_reset;
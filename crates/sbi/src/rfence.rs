@@ -5,11 +5,13 @@
 
 use sbi_sys::{SbiError, rfence};
 
+use crate::HartMask;
+
 pub const EXTENSION_ID: usize = 0x52_46_4E_43; // 'RFNC' in ASCII
 
 /// Instructs remote harts to execute `FENCE.I` instruction.
-pub fn remote_fence_i(hart_mask: usize, hart_mask_base: usize) -> Result<(), SbiError> {
-    let ret = rfence::remote_fence_i(hart_mask, hart_mask_base);
+pub fn remote_fence_i(hart_mask: HartMask) -> Result<(), SbiError> {
+    let ret = rfence::remote_fence_i(hart_mask.mask(), hart_mask.base());
     ret.into_result()?;
     Ok(())
 }
@@ -19,12 +21,11 @@ pub fn remote_fence_i(hart_mask: usize, hart_mask_base: usize) -> Result<(), Sbi
 /// This covers the range of virtual addresses between `start_addr` and
 /// `start_addr + size`.
 pub fn remote_sfence_vma(
-    hart_mask: usize,
-    hart_mask_base: usize,
+    hart_mask: HartMask,
     start_addr: usize,
     size: usize,
 ) -> Result<(), SbiError> {
-    let ret = rfence::remote_sfence_vma(hart_mask, hart_mask_base, start_addr, size);
+    let ret = rfence::remote_sfence_vma(hart_mask.mask(), hart_mask.base(), start_addr, size);
     ret.into_result()?;
     Ok(())
 }
@@ -34,13 +35,13 @@ pub fn remote_sfence_vma(
 /// This covers the range of virtual addresses between `start_addr` and
 /// `start_addr + size`. This covers only the given `ASID`.
 pub fn remote_sfence_vma_asid(
-    hart_mask: usize,
-    hart_mask_base: usize,
+    hart_mask: HartMask,
     start_addr: usize,
     size: usize,
     asid: usize,
 ) -> Result<(), SbiError> {
-    let ret = rfence::remote_sfence_vma_asid(hart_mask, hart_mask_base, start_addr, size, asid);
+    let ret =
+        rfence::remote_sfence_vma_asid(hart_mask.mask(), hart_mask.base(), start_addr, size, asid);
     ret.into_result()?;
     Ok(())
 }
@@ -51,13 +52,13 @@ pub fn remote_sfence_vma_asid(
 /// `start_addr + size` only for the given `VMID`. This function call is only
 /// valid for harts implementing hypervisor extension.
 pub fn remote_hfence_gvma_vmid(
-    hart_mask: usize,
-    hart_mask_base: usize,
+    hart_mask: HartMask,
     start_addr: usize,
     size: usize,
     vmid: usize,
 ) -> Result<(), SbiError> {
-    let ret = rfence::remote_hfence_gvma_vmid(hart_mask, hart_mask_base, start_addr, size, vmid);
+    let ret =
+        rfence::remote_hfence_gvma_vmid(hart_mask.mask(), hart_mask.base(), start_addr, size, vmid);
     ret.into_result()?;
     Ok(())
 }
@@ -68,12 +69,11 @@ pub fn remote_hfence_gvma_vmid(
 /// `start_addr + size` for all the guets. This function call is only valid for
 /// harts implementing hypervisor extension.
 pub fn sbi_remote_hfence_gvma(
-    hart_mask: usize,
-    hart_mask_base: usize,
+    hart_mask: HartMask,
     start_addr: usize,
     size: usize,
 ) -> Result<(), SbiError> {
-    let ret = rfence::sbi_remote_hfence_gvma(hart_mask, hart_mask_base, start_addr, size);
+    let ret = rfence::sbi_remote_hfence_gvma(hart_mask.mask(), hart_mask.base(), start_addr, size);
     ret.into_result()?;
     Ok(())
 }
@@ -86,14 +86,18 @@ pub fn sbi_remote_hfence_gvma(
 /// of calling hart. This function call is only valid for
 /// harts implementing hypervisor extension.
 pub fn sbi_remote_hfence_vvma_asid(
-    hart_mask: usize,
-    hart_mask_base: usize,
+    hart_mask: HartMask,
     start_addr: usize,
     size: usize,
     asid: usize,
 ) -> Result<(), SbiError> {
-    let ret =
-        rfence::sbi_remote_hfence_vvma_asid(hart_mask, hart_mask_base, start_addr, size, asid);
+    let ret = rfence::sbi_remote_hfence_vvma_asid(
+        hart_mask.mask(),
+        hart_mask.base(),
+        start_addr,
+        size,
+        asid,
+    );
     ret.into_result()?;
     Ok(())
 }
@@ -106,12 +110,11 @@ pub fn sbi_remote_hfence_vvma_asid(
 /// This function call is only valid for harts implementing hypervisor
 /// extension.
 pub fn sbi_remote_hfence_vvma(
-    hart_mask: usize,
-    hart_mask_base: usize,
+    hart_mask: HartMask,
     start_addr: usize,
     size: usize,
 ) -> Result<(), SbiError> {
-    let ret = rfence::sbi_remote_hfence_vvma(hart_mask, hart_mask_base, start_addr, size);
+    let ret = rfence::sbi_remote_hfence_vvma(hart_mask.mask(), hart_mask.base(), start_addr, size);
     ret.into_result()?;
     Ok(())
 }
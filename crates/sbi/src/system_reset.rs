@@ -0,0 +1,67 @@
+//! High-level interface for the SBI System Reset Extension.
+//!
+//! This module provides safe Rust wrappers for shutting down and rebooting the
+//! system.
+
+use core::convert::Infallible;
+
+use sbi_sys::{
+    SbiError,
+    system_reset::{
+        self, RESET_REASON_NO_REASON, RESET_REASON_SYSTEM_FAILURE, RESET_TYPE_COLD_REBOOT,
+        RESET_TYPE_SHUTDOWN, RESET_TYPE_WARM_REBOOT,
+    },
+};
+
+pub const EXTENSION_ID: usize = system_reset::EXTENSION_ID;
+
+/// Types of the system reset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResetType {
+    /// Shuts down the system.
+    Shutdown,
+    /// Powers off all the hardware and performs a cold boot.
+    ColdReboot,
+    /// Resets the processors and some hardware, and performs a warm boot.
+    WarmReboot,
+}
+
+impl ResetType {
+    fn to_sbi_type(self) -> u32 {
+        match self {
+            Self::Shutdown => RESET_TYPE_SHUTDOWN,
+            Self::ColdReboot => RESET_TYPE_COLD_REBOOT,
+            Self::WarmReboot => RESET_TYPE_WARM_REBOOT,
+        }
+    }
+}
+
+/// Reasons of the system reset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResetReason {
+    /// No reason for the reset.
+    NoReason,
+    /// The reset is caused by a system failure.
+    SystemFailure,
+}
+
+impl ResetReason {
+    fn to_sbi_reason(self) -> u32 {
+        match self {
+            Self::NoReason => RESET_REASON_NO_REASON,
+            Self::SystemFailure => RESET_REASON_SYSTEM_FAILURE,
+        }
+    }
+}
+
+/// Resets the system based on the provided `reset_type` and `reset_reason`.
+///
+/// This call does not return if it succeeds.
+pub fn system_reset(
+    reset_type: ResetType,
+    reset_reason: ResetReason,
+) -> Result<Infallible, SbiError> {
+    let ret = system_reset::system_reset(reset_type.to_sbi_type(), reset_reason.to_sbi_reason());
+    ret.into_result()?;
+    unreachable!("SBI system reset should not return on success");
+}
@@ -1,8 +1,10 @@
 #![feature(error_generic_member_access)]
 #![no_std]
 
+#[cfg(feature = "alloc")]
 extern crate alloc;
 
+#[cfg(feature = "alloc")]
 use alloc::{boxed::Box, string::String};
 use core::{
     error::{self, Error},
@@ -11,7 +13,16 @@ use core::{
 };
 
 use ansi_term::{Color, WithFg};
-use snafu::{GenerateImplicitData, Snafu};
+use snafu::GenerateImplicitData;
+#[cfg(feature = "alloc")]
+use snafu::Snafu;
+
+use self::multi_error::ItemFailures;
+#[cfg(not(feature = "alloc"))]
+pub use self::multi_error::MAX_FAILURES;
+pub use self::multi_error::MultiError;
+
+mod multi_error;
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct LocationWrap(pub &'static core::panic::Location<'static>);
@@ -42,6 +53,7 @@ impl fmt::Display for LocationWrap {
     }
 }
 
+#[cfg(feature = "alloc")]
 #[derive(Debug, Snafu)]
 #[snafu(whatever, display("{message}"))]
 #[snafu(provide(ref, priority, Location => location.0))]
@@ -74,9 +86,7 @@ where
 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(f, "Error: {}", WithFg::new(Color::Red, &self.error))?;
-        if let Some(loc) = error::request_ref::<Location>(&self.error) {
-            writeln!(f, "  at {}", WithFg::new(Color::DarkGray, loc))?;
-        }
+        write_details(f, &self.error, 2)?;
         let mut source = self.error.source();
         if source.is_some() {
             writeln!(f)?;
@@ -85,14 +95,50 @@ where
         let mut index = 0;
         while let Some(s) = source {
             writeln!(f, "{index:4}: {}", WithFg::new(Color::Red, s))?;
-            if let Some(loc) = error::request_ref::<Location>(s) {
-                writeln!(f, "      at {}", WithFg::new(Color::DarkGray, loc))?;
-            }
+            write_details(f, s, 6)?;
+            source = s.source();
+            index += 1;
+        }
+        Ok(())
+    }
+}
+
+/// Writes the location of `error`, and the failures of the items if it is a
+/// [`MultiError`], indented by `indent` columns.
+///
+/// Each failure is shown with the index of its item, followed by its own
+/// sources.
+fn write_details(f: &mut fmt::Formatter<'_>, error: &dyn Error, indent: usize) -> fmt::Result {
+    if let Some(loc) = error::request_ref::<Location>(error) {
+        writeln!(f, "{:indent$}at {}", "", WithFg::new(Color::DarkGray, loc))?;
+    }
+    let Some(failures) = error::request_ref::<dyn ItemFailures>(error) else {
+        return Ok(());
+    };
+    failures.try_for_each_failure(&mut |item, failure| {
+        writeln!(
+            f,
+            "{:indent$}[{item}]: {}",
+            "",
+            WithFg::new(Color::Red, failure)
+        )?;
+        let indent = indent + 4;
+        write_details(f, failure, indent)?;
+        let mut source = failure.source();
+        let mut index = 0;
+        while let Some(s) = source {
+            writeln!(f, "{:indent$}{index}: {}", "", WithFg::new(Color::Red, s))?;
+            write_details(f, s, indent + 3)?;
             source = s.source();
             index += 1;
         }
         Ok(())
+    })?;
+    let omitted = failures.omitted();
+    if omitted > 0 {
+        writeln!(f, "{:indent$}... and {omitted} more", "")?;
     }
+    Ok(())
 }
 
 impl<E> Report<E> {
@@ -0,0 +1,185 @@
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+use core::{
+    error::{Error, Request},
+    fmt,
+    panic::Location,
+};
+
+use crate::LocationWrap;
+
+/// Maximum number of the failures kept by a [`MultiError`] without the `alloc`
+/// feature.
+///
+/// The failures beyond the capacity are counted, but not kept.
+#[cfg(not(feature = "alloc"))]
+pub const MAX_FAILURES: usize = 8;
+
+/// Failures of the items of a batch operation, such as initializing all the
+/// devices of a driver.
+///
+/// Each result of the items is recorded with [`record`](Self::record), so that
+/// the operation goes on with the rest of the items after a failure, and all
+/// the failures are reported together at the end. The failures are shown with
+/// the indices of their items by [`Report`](crate::Report).
+///
+/// With the `alloc` feature, all the failures are kept. Otherwise, the first
+/// [`MAX_FAILURES`] failures are kept and the rest are only counted.
+///
+/// # Examples
+///
+/// ```
+/// use snafu_utils::MultiError;
+///
+/// let mut errors = MultiError::new("numbers");
+/// let numbers = ["1", "x", "3", "y"]
+///     .into_iter()
+///     .filter_map(|s| errors.record(s.parse::<u32>()))
+///     .collect::<Vec<_>>();
+/// assert_eq!(numbers, [1, 3]);
+/// assert_eq!(errors.to_string(), "2 of 4 numbers failed");
+/// assert!(errors.into_result().is_err());
+/// ```
+#[derive(Debug)]
+pub struct MultiError<E> {
+    items: &'static str,
+    attempted: usize,
+    failed: usize,
+    #[cfg(feature = "alloc")]
+    failures: Vec<(usize, E)>,
+    #[cfg(not(feature = "alloc"))]
+    failures: [Option<(usize, E)>; MAX_FAILURES],
+    location: LocationWrap,
+}
+
+impl<E> MultiError<E> {
+    /// Creates an empty collector for the items described by `items`, e.g.
+    /// `"serial devices"`.
+    #[track_caller]
+    #[must_use]
+    pub fn new(items: &'static str) -> Self {
+        Self {
+            items,
+            attempted: 0,
+            failed: 0,
+            #[cfg(feature = "alloc")]
+            failures: Vec::new(),
+            #[cfg(not(feature = "alloc"))]
+            failures: [const { None }; MAX_FAILURES],
+            location: LocationWrap::default(),
+        }
+    }
+
+    /// Records the result of the next item, and returns its value if it
+    /// succeeded.
+    pub fn record<T>(&mut self, result: Result<T, E>) -> Option<T> {
+        let index = self.attempted;
+        self.attempted += 1;
+        match result {
+            Ok(value) => Some(value),
+            Err(error) => {
+                self.push_failure(index, error);
+                None
+            }
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    fn push_failure(&mut self, index: usize, error: E) {
+        self.failed += 1;
+        self.failures.push((index, error));
+    }
+
+    #[cfg(not(feature = "alloc"))]
+    fn push_failure(&mut self, index: usize, error: E) {
+        if let Some(slot) = self.failures.get_mut(self.failed) {
+            *slot = Some((index, error));
+        }
+        self.failed += 1;
+    }
+
+    /// Returns the number of the recorded items.
+    #[must_use]
+    pub fn attempted(&self) -> usize {
+        self.attempted
+    }
+
+    /// Returns the number of the failed items, including those not kept.
+    #[must_use]
+    pub fn failed(&self) -> usize {
+        self.failed
+    }
+
+    /// Returns `true` if no item has failed.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.failed == 0
+    }
+
+    /// Returns the kept failures with the indices of their items.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &E)> {
+        #[cfg(feature = "alloc")]
+        let failures = self.failures.iter();
+        #[cfg(not(feature = "alloc"))]
+        let failures = self.failures.iter().flatten();
+        failures.map(|(index, error)| (*index, error))
+    }
+
+    /// Returns `Ok(())` if no item has failed, or the failures otherwise.
+    ///
+    /// # Errors
+    ///
+    /// Returns `self` if any item has failed.
+    pub fn into_result(self) -> Result<(), Self> {
+        if self.is_empty() { Ok(()) } else { Err(self) }
+    }
+}
+
+impl<E> fmt::Display for MultiError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} {} failed",
+            self.failed, self.attempted, self.items
+        )
+    }
+}
+
+impl<E> Error for MultiError<E>
+where
+    E: Error + 'static,
+{
+    fn provide<'a>(&'a self, request: &mut Request<'a>) {
+        request
+            .provide_ref::<Location>(self.location.0)
+            .provide_ref::<dyn ItemFailures>(self);
+    }
+}
+
+/// Failures of the items, requested from the errors by
+/// [`Report`](crate::Report).
+pub(crate) trait ItemFailures {
+    fn try_for_each_failure(
+        &self,
+        f: &mut dyn FnMut(usize, &dyn Error) -> fmt::Result,
+    ) -> fmt::Result;
+
+    /// Returns the number of the failures not kept.
+    fn omitted(&self) -> usize;
+}
+
+impl<E> ItemFailures for MultiError<E>
+where
+    E: Error,
+{
+    fn try_for_each_failure(
+        &self,
+        f: &mut dyn FnMut(usize, &dyn Error) -> fmt::Result,
+    ) -> fmt::Result {
+        self.iter().try_for_each(|(index, error)| f(index, error))
+    }
+
+    fn omitted(&self) -> usize {
+        self.failed - self.iter().count()
+    }
+}
@@ -341,6 +341,15 @@ impl VirtAddr {
         Self(addr)
     }
 
+    /// Creates a virtual address from a raw address value.
+    ///
+    /// Returns `None` if the address is not properly sign-extended.
+    #[must_use]
+    pub fn try_from_addr(addr: usize) -> Option<Self> {
+        let addr = addr.cast_into();
+        (addr == Self::sign_extend(addr)).then_some(Self(addr))
+    }
+
     /// Creates a virtual address from a pointer.
     #[must_use]
     pub fn from_ptr<T>(ptr: *const T) -> Self {
@@ -61,6 +61,18 @@ bitflags! {
         /// If set, this virtual address has been written to.
         const D = 1 << 7;
 
+        /// Copy-on-write Bit of page table entry.
+        ///
+        /// One of the bits reserved for the software (RSW), ignored by the
+        /// hardware.
+        const COW = 1 << 8;
+
+        /// Non-cacheable main memory type of page table entry (Svpbmt).
+        const NC = 1 << 61;
+
+        /// Non-cacheable I/O memory type of page table entry (Svpbmt).
+        const IO = 1 << 62;
+
         const RW = Self::R.bits() | Self::W.bits();
         const RX = Self::R.bits() | Self::X.bits();
         const RWX = Self::R.bits() | Self::W.bits() | Self::X.bits();
@@ -72,6 +84,24 @@ bitflags! {
     }
 }
 
+impl PageFlags {
+    /// Returns why the flags are a reserved encoding of a leaf entry, if they
+    /// are.
+    ///
+    /// The privileged architecture reserves the writable but not readable
+    /// pages, and the entries without any of `R`, `W` and `X` point to the
+    /// next level table instead of mapping a page.
+    fn reserved_leaf_reason(self) -> Option<&'static str> {
+        if !self.intersects(Self::R | Self::W | Self::X) {
+            return Some("leaf entries must be readable, writable or executable");
+        }
+        if self.contains(Self::W) && !self.contains(Self::R) {
+            return Some("writable but not readable pages are reserved");
+        }
+        None
+    }
+}
+
 impl From<MapPageFlags> for PageFlags {
     fn from(form: MapPageFlags) -> Self {
         let mut flags = Self::empty();
@@ -87,6 +117,46 @@ impl From<MapPageFlags> for PageFlags {
         if form.contains(MapPageFlags::U) {
             flags |= Self::U;
         }
+        if form.contains(MapPageFlags::COW) {
+            flags |= Self::COW;
+        }
+        if form.contains(MapPageFlags::NC) {
+            flags |= Self::NC;
+        }
+        if form.contains(MapPageFlags::IO) {
+            flags |= Self::IO;
+        }
+        flags
+    }
+}
+
+impl From<PageFlags> for MapPageFlags {
+    fn from(from: PageFlags) -> Self {
+        let mut flags = Self::empty();
+        if from.contains(PageFlags::R) {
+            flags |= Self::R;
+        }
+        if from.contains(PageFlags::W) {
+            flags |= Self::W;
+        }
+        if from.contains(PageFlags::X) {
+            flags |= Self::X;
+        }
+        if from.contains(PageFlags::W | PageFlags::X) {
+            flags |= Self::ALLOW_WX;
+        }
+        if from.contains(PageFlags::U) {
+            flags |= Self::U;
+        }
+        if from.contains(PageFlags::COW) {
+            flags |= Self::COW;
+        }
+        if from.contains(PageFlags::NC) {
+            flags |= Self::NC;
+        }
+        if from.contains(PageFlags::IO) {
+            flags |= Self::IO;
+        }
         flags
     }
 }
@@ -99,12 +169,21 @@ impl From<MapPageFlags> for PageFlags {
 #[derive(Pod)]
 pub(super) struct PageTableEntry(u64);
 
-const FLAGS_MASK: u64 = (1 << 10) - 1;
+impl PageTableEntry {
+    /// Returns a shallow copy of the entry that points to the same page or
+    /// next level table.
+    pub(super) fn share(&self) -> Self {
+        Self(self.0)
+    }
+}
+
+const FLAGS_MASK: u64 = ((1 << 10) - 1) | PBMT_MASK;
+const PBMT_MASK: u64 = 0b11 << 61;
 const FLAGS_SHIFT: usize = 0;
 const PHYS_PAGE_NUM_MASK: u64 = ((1 << 44) - 1) << 10;
 const PHYS_PAGE_NUM_SHIFT: usize = 10;
 
-const _: () = assert!(FLAGS_MASK.count_ones() == 10);
+const _: () = assert!(FLAGS_MASK.count_ones() == 12);
 const _: () = assert!(PHYS_PAGE_NUM_MASK.count_ones() == 44);
 
 pub(super) struct PageTableEntryRef<R> {
@@ -122,6 +201,10 @@ impl<R> PageTableEntryRef<R> {
         }
     }
 
+    pub(super) fn level(&self) -> usize {
+        self.level
+    }
+
     pub(super) fn vpn_count(&self) -> usize {
         1 << (self.level * 9)
     }
@@ -204,6 +287,19 @@ where
     }
 }
 
+impl<'pt> PageTableEntryRef<&'pt PageTableEntry> {
+    /// Returns the next level table, borrowed for the lifetime of the entry.
+    pub(super) fn into_next_level_table(self) -> Option<PageTableRef<&'pt PageTable>> {
+        if !self.is_non_leaf() {
+            return None;
+        }
+        let ptr = self.phys_addr()?.as_ptr::<PageTable>();
+        assert!(ptr.is_aligned());
+        let pt = unsafe { ptr.as_ref() }?;
+        Some(PageTableRef::new(pt, self.level - 1, self.base_vpn))
+    }
+}
+
 impl<R> PageTableEntryRef<R>
 where
     R: DerefMut<Target = PageTableEntry>,
@@ -221,6 +317,10 @@ where
         *self.pte = PageTableEntry(phys_page_num_bits | flags_bits);
     }
 
+    pub(super) fn clear(&mut self) {
+        *self.pte = PageTableEntry(0);
+    }
+
     #[expect(clippy::needless_pass_by_ref_mut)]
     pub(super) fn next_level_table_mut(&mut self) -> Option<PageTableRef<&mut PageTable>> {
         if !self.is_non_leaf() {
@@ -232,6 +332,25 @@ where
         Some(PageTableRef::new(pt, self.level - 1, self.base_vpn))
     }
 
+    /// Makes the page of the leaf entry read-only, and marks it copy-on-write.
+    pub(super) fn protect_cow(&mut self) {
+        assert!(self.is_leaf());
+        let flags = (self.flags() - PageFlags::W) | PageFlags::COW;
+        self.update(self.phys_page_num().unwrap(), flags);
+    }
+
+    /// Clears the entry pointing to the next level table, and frees the table.
+    ///
+    /// The table must have no valid entries, and must not be shared with the
+    /// other page tables.
+    pub(super) fn free_next_level_table(&mut self) {
+        assert!(self.is_non_leaf());
+        let ptr = self.phys_addr().unwrap().as_mut_ptr::<PageTable>();
+        assert!(ptr.is_aligned());
+        self.clear();
+        drop(unsafe { Box::from_raw(ptr) });
+    }
+
     pub(super) fn get_or_insert_next_level_table(
         &mut self,
     ) -> Result<PageTableRef<&mut PageTable>, PageTableError> {
@@ -277,10 +396,7 @@ where
         #[cfg_attr(not(test), expect(clippy::wildcard_imports))]
         use super::page_table_error::*;
 
-        ensure!(
-            !flags.is_empty() && flags & MapPageFlags::URWX == flags,
-            InvalidMapFlagsSnafu { flags }
-        );
+        check_leaf_flags(flags)?;
         ensure!(
             !self.is_valid(),
             AlreadyMappedSnafu {
@@ -306,10 +422,7 @@ where
         #[cfg_attr(not(test), expect(clippy::wildcard_imports))]
         use super::page_table_error::*;
 
-        ensure!(
-            !flags.is_empty() && flags & MapPageFlags::URWX == flags,
-            InvalidMapFlagsSnafu { flags }
-        );
+        check_leaf_flags(flags)?;
         ensure!(!self.is_valid(), AlreadyMappedSnafu { phys_page_num });
 
         let page_flags = PageFlags::V | PageFlags::from(flags);
@@ -317,3 +430,81 @@ where
         Ok(())
     }
 }
+
+/// Checks that `flags` can map a page.
+///
+/// With debug assertions enabled, this also rejects the flags encoding
+/// reserved leaf entries, which the hardware would fault on only when the
+/// page is accessed.
+fn check_leaf_flags(flags: MapPageFlags) -> Result<(), PageTableError> {
+    #[cfg_attr(not(test), expect(clippy::wildcard_imports))]
+    use super::page_table_error::*;
+
+    ensure!(
+        flags.is_valid_for_leaf(),
+        InvalidMapFlagsSnafu {
+            flags,
+            reason: "unknown bits, no permissions, or multiple memory types"
+        }
+    );
+    if let Some(reason) = PageFlags::from(flags).reserved_leaf_reason() {
+        return InvalidMapFlagsSnafu { flags, reason }.fail();
+    }
+    ensure!(flags.is_wx_allowed(), WritableExecutableSnafu { flags });
+    ensure!(
+        !flags.contains(MapPageFlags::W | MapPageFlags::COW),
+        InvalidMapFlagsSnafu {
+            flags,
+            reason: "copy-on-write pages must not be writable"
+        }
+    );
+    Ok(())
+}
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reserved_leaf_flags_are_rejected() {
+        for flags in [
+            MapPageFlags::empty(),
+            MapPageFlags::U,
+            MapPageFlags::W,
+            MapPageFlags::UW,
+            MapPageFlags::W | MapPageFlags::X,
+            MapPageFlags::UR | MapPageFlags::NC | MapPageFlags::IO,
+        ] {
+            let res = check_leaf_flags(flags);
+            assert!(
+                matches!(res, Err(PageTableError::InvalidMapFlags { .. })),
+                "{flags:?}: {res:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn leaf_flags_are_accepted() {
+        for flags in [
+            MapPageFlags::R,
+            MapPageFlags::X,
+            MapPageFlags::UR,
+            MapPageFlags::URW,
+            MapPageFlags::URX,
+            MapPageFlags::URWX | MapPageFlags::ALLOW_WX,
+            MapPageFlags::UR | MapPageFlags::COW,
+        ] {
+            check_leaf_flags(flags).unwrap();
+        }
+    }
+
+    #[test]
+    fn writable_executable_flags_need_permission() {
+        let res = check_leaf_flags(MapPageFlags::URWX);
+        assert!(
+            matches!(res, Err(PageTableError::WritableExecutable { .. })),
+            "{res:?}"
+        );
+    }
+}
@@ -1,30 +1,36 @@
 #![feature(allocator_api)]
 #![feature(error_generic_member_access)]
+#![cfg_attr(coverage_nightly, feature(coverage_attribute))]
 #![no_std]
 
 extern crate alloc;
 
-use alloc::boxed::Box;
+use alloc::{boxed::Box, vec::Vec};
 use core::{
     alloc::{AllocError, Layout},
     fmt::{self, DebugMap},
+    ops::RangeInclusive,
     panic::Location,
 };
 
 use bitflags::bitflags;
 use platform_cast::CastInto as _;
-use riscv::register::satp::{Mode, Satp};
-use snafu::Snafu;
+use riscv::register::satp::{self, Mode, Satp};
+use riscv_utils::asm;
+use snafu::{Snafu, ensure};
 use snafu_utils::LocationWrap;
 
+pub use self::region::MappedRegion;
 use self::{
     address::{PhysAddr, PhysPageNum, VirtAddr, VirtPageNum},
     entry::{PageFlags, PageTableEntry, PageTableEntryRef},
+    region::MergeMappings,
     table::{PageTable, PageTableRef},
 };
 
 pub mod address;
 mod entry;
+mod region;
 mod table;
 
 pub const PAGE_SIZE: usize = 4096;
@@ -59,13 +65,45 @@ pub enum PageTableError {
         #[snafu(implicit)]
         location: LocationWrap,
     },
-    #[snafu(display("invalid flags for mapping page: {flags:?}"))]
+    #[snafu(display("attempted to unmap a part of a mapping, virt_page_num: {virt_page_num:#x}"))]
+    #[snafu(provide(ref, priority, Location => location.0))]
+    PartialUnmap {
+        virt_page_num: VirtPageNum,
+        #[snafu(implicit)]
+        location: LocationWrap,
+    },
+    #[snafu(display(
+        "attempted to change the flags of a part of a mapping, virt_page_num: {virt_page_num:#x}"
+    ))]
+    #[snafu(provide(ref, priority, Location => location.0))]
+    PartialProtect {
+        virt_page_num: VirtPageNum,
+        #[snafu(implicit)]
+        location: LocationWrap,
+    },
+    #[snafu(display("invalid flags for mapping page: {flags:?}, {reason}"))]
     #[snafu(provide(ref, priority, Location => location.0))]
     InvalidMapFlags {
+        flags: MapPageFlags,
+        reason: &'static str,
+        #[snafu(implicit)]
+        location: LocationWrap,
+    },
+    #[snafu(display(
+        "attempted to map a writable and executable page without ALLOW_WX, flags: {flags:?}"
+    ))]
+    #[snafu(provide(ref, priority, Location => location.0))]
+    WritableExecutable {
         flags: MapPageFlags,
         #[snafu(implicit)]
         location: LocationWrap,
     },
+    #[snafu(display("invalid serialized mapped region"))]
+    #[snafu(provide(ref, priority, Location => location.0))]
+    InvalidRegion {
+        #[snafu(implicit)]
+        location: LocationWrap,
+    },
 }
 
 bitflags! {
@@ -94,6 +132,29 @@ bitflags! {
         /// If set, userspace can access this virtual address.
         const U = 1 << 3;
 
+        /// Non-cacheable, idempotent, weakly-ordered main memory type.
+        ///
+        /// This requires the Svpbmt extension.
+        const NC = 1 << 4;
+
+        /// Non-cacheable, non-idempotent, strongly-ordered I/O memory type.
+        ///
+        /// This requires the Svpbmt extension.
+        const IO = 1 << 5;
+
+        /// Allows the page to be writable and executable at once.
+        ///
+        /// Mapping a page with both `W` and `X` fails without this flag. The
+        /// flags of the existing writable and executable pages include it.
+        const ALLOW_WX = 1 << 6;
+
+        /// Marks the page as shared copy-on-write.
+        ///
+        /// The flag is stored in a bit reserved for the software, and is not
+        /// interpreted by the hardware. Copy-on-write pages must not be
+        /// writable, so that writes to them fault.
+        const COW = 1 << 7;
+
         const RW = Self::R.bits() | Self::W.bits();
         const RX = Self::R.bits() | Self::X.bits();
         const RWX = Self::R.bits() | Self::W.bits() | Self::X.bits();
@@ -105,6 +166,101 @@ bitflags! {
     }
 }
 
+impl MapPageFlags {
+    /// Returns whether the flags are valid for a leaf entry.
+    fn is_valid_for_leaf(self) -> bool {
+        !(self & Self::RWX).is_empty()
+            && Self::all().contains(self)
+            && !self.contains(Self::NC | Self::IO)
+    }
+
+    /// Returns whether the flags are not writable and executable at once,
+    /// or allow it explicitly.
+    fn is_wx_allowed(self) -> bool {
+        !self.contains(Self::W | Self::X) || self.contains(Self::ALLOW_WX)
+    }
+}
+
+/// Leaf entry of a page table, mapping a contiguous virtual address range to
+/// physical memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mapping {
+    /// First virtual address of the mapping.
+    pub min_virt_addr: VirtAddr,
+    /// Last virtual address of the mapping.
+    pub max_virt_addr: VirtAddr,
+    /// Physical address mapped to `min_virt_addr`.
+    pub min_phys_addr: PhysAddr,
+    /// Permission flags of the mapping.
+    pub flags: MapPageFlags,
+    /// Level of the page table that holds the leaf entry.
+    ///
+    /// Level 0 maps a 4 KiB page, level 1 a 2 MiB page and level 2 a 1 GiB
+    /// page.
+    pub level: usize,
+}
+
+impl Mapping {
+    fn from_entry(entry: &PageTableEntryRef<&PageTableEntry>) -> Option<Self> {
+        if !entry.is_leaf() {
+            return None;
+        }
+        Some(Self {
+            min_virt_addr: entry.min_virt_addr(),
+            max_virt_addr: entry.max_virt_addr(),
+            min_phys_addr: entry.min_phys_addr()?,
+            flags: entry.flags().into(),
+            level: entry.level(),
+        })
+    }
+
+    /// Returns `true` if the mapping contains `virt_addr`.
+    #[must_use]
+    pub fn contains(&self, virt_addr: VirtAddr) -> bool {
+        self.min_virt_addr <= virt_addr && virt_addr <= self.max_virt_addr
+    }
+
+    /// Translates `virt_addr` into the physical address it is mapped to.
+    ///
+    /// Returns `None` if the mapping does not contain `virt_addr`.
+    #[must_use]
+    pub fn translate(&self, virt_addr: VirtAddr) -> Option<PhysAddr> {
+        if !self.contains(virt_addr) {
+            return None;
+        }
+        let page_index = virt_addr
+            .page_num()
+            .checked_sub(self.min_virt_addr.page_num())?;
+        let phys_page_num = self.min_phys_addr.page_num().checked_add(page_index)?;
+        Some(PhysAddr::from_parts(phys_page_num, virt_addr.offset()))
+    }
+}
+
+/// Writes the SATP register of the current hart, and returns the previous
+/// value.
+///
+/// The write is surrounded by `sfence.vma` for the ASID of `satp`: the first
+/// one orders the preceding stores to the page table memory before the page
+/// table walks, and the second one drops the translations cached for the ASID
+/// by a previous user of it.
+///
+/// # Safety
+///
+/// The page table referenced by `satp` must map the code, the stack, and all
+/// the data accessed by the current hart after the write, and must stay alive
+/// while it is active.
+#[must_use = "the previous SATP value is needed to restore the address space"]
+pub unsafe fn activate_satp(new: Satp) -> Satp {
+    let asid = new.asid();
+    asm::sfence_vma_asid_all(asid);
+    let old = satp::read();
+    unsafe {
+        satp::write(new);
+    }
+    asm::sfence_vma_asid_all(asid);
+    old
+}
+
 /// Root of an SV39 page table hierarchy.
 ///
 /// This structure represents the top-level page table and provides methods
@@ -152,6 +308,21 @@ impl PageTableRoot {
         satp
     }
 
+    /// Activates this page table on the current hart.
+    ///
+    /// Returns the previous SATP register value, which can be restored with
+    /// [`activate_satp`]. See [`activate_satp`] for the activation sequence.
+    ///
+    /// # Safety
+    ///
+    /// The page table must map the code, the stack, and all the data
+    /// accessed by the current hart after the activation, and must stay alive
+    /// while it is active.
+    #[must_use = "the previous SATP value is needed to restore the address space"]
+    pub unsafe fn activate(&self) -> Satp {
+        unsafe { activate_satp(self.satp()) }
+    }
+
     /// Returns the Address Space Identifier (ASID) for this page table.
     #[must_use]
     pub fn asid(&self) -> u16 {
@@ -216,6 +387,214 @@ impl PageTableRoot {
         self.as_mut()
             .map_fixed_pages(virt_page_num, phys_page_num, count, flags)
     }
+
+    /// Unmaps pages starting from the specified virtual page number.
+    ///
+    /// Pages in the range that are not mapped are skipped. The unmapped
+    /// physical pages are not freed, and the lower level tables are freed
+    /// only by [`Self::free_tables`].
+    ///
+    /// The caller must flush the TLB entries of the unmapped range.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the range covers only a part of a huge page
+    /// mapping. The pages before the mapping are unmapped in this case.
+    pub fn unmap_pages(
+        &mut self,
+        virt_page_num: VirtPageNum,
+        count: usize,
+    ) -> Result<(), PageTableError> {
+        self.as_mut().unmap_pages(virt_page_num, count)?;
+        Ok(())
+    }
+
+    /// Shares the pages starting from the specified virtual page number with
+    /// `target` copy-on-write, and returns the shared leaf mappings.
+    ///
+    /// The pages are made read-only and marked [`MapPageFlags::COW`] in this
+    /// page table, and the same physical pages are mapped with the same flags
+    /// in `target`. Pages in the range that are not mapped are skipped.
+    ///
+    /// The caller must flush the TLB entries of the range in this page table.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the range covers only a part of a huge page
+    /// mapping, or mapping the pages in `target` fails. The pages of this page
+    /// table may be left marked copy-on-write in this case.
+    pub fn share_pages_cow(
+        &mut self,
+        target: &mut Self,
+        virt_page_num: VirtPageNum,
+        count: usize,
+    ) -> Result<Vec<Mapping>, PageTableError> {
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+        self.as_mut().protect_cow(virt_page_num, count)?;
+
+        let last_vpn = virt_page_num + (count - 1);
+        let shared = self
+            .mappings()
+            .skip_while(|mapping| mapping.max_virt_addr.page_num() < virt_page_num)
+            .take_while(|mapping| mapping.min_virt_addr.page_num() <= last_vpn)
+            .collect::<Vec<_>>();
+        for mapping in &shared {
+            let vpn = mapping.min_virt_addr.page_num();
+            let page_count = (mapping.max_virt_addr.page_num() - vpn) + 1;
+            let mapped = target.map_fixed_pages(
+                vpn,
+                mapping.min_phys_addr.page_num(),
+                page_count,
+                mapping.flags,
+            )?;
+            debug_assert_eq!(mapped, page_count);
+        }
+        Ok(shared)
+    }
+
+    /// Frees the lower level tables covering only the pages starting from the
+    /// specified virtual page number, if no pages are mapped in them.
+    ///
+    /// Returns the number of the freed tables. The tables shared by
+    /// [`Self::share_mappings`] must not be in the range, and the caller must
+    /// flush the TLB entries of the range before the freed memory is reused.
+    pub fn free_tables(&mut self, virt_page_num: VirtPageNum, count: usize) -> usize {
+        self.as_mut().free_tables(virt_page_num, count)
+    }
+
+    /// Allocates the lower level tables covering the pages starting from the
+    /// specified virtual page number, without mapping the pages.
+    ///
+    /// Mapping pages in the range with [`Self::map_fixed_pages`] later does not
+    /// allocate memory.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if allocation fails or the range overlaps with a huge
+    /// page mapping.
+    pub fn reserve_tables(
+        &mut self,
+        virt_page_num: VirtPageNum,
+        count: usize,
+    ) -> Result<(), PageTableError> {
+        self.as_mut().reserve_tables(virt_page_num, count)?;
+        Ok(())
+    }
+
+    /// Shares the top-level entries of `source` covering `vpn_range` with this
+    /// page table.
+    ///
+    /// The shared entries point to the same pages and lower level tables as
+    /// `source`, so later changes to the lower level tables are visible from
+    /// both page tables. Shared tables are not freed when either page table is
+    /// dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `vpn_range` is not aligned to the top-level entry boundaries.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if this page table already has entries in the range.
+    pub fn share_mappings(
+        &mut self,
+        source: &Self,
+        vpn_range: RangeInclusive<VirtPageNum>,
+    ) -> Result<(), PageTableError> {
+        assert!(vpn_range.start().is_level_aligned(2));
+        assert!(*vpn_range.end() == VirtPageNum::MAX || (*vpn_range.end() + 1).is_level_aligned(2));
+        self.as_mut().share_entries(&source.as_ref(), vpn_range)
+    }
+
+    /// Walks the page table and returns the leaf mapping containing
+    /// `virt_addr`.
+    ///
+    /// Returns `None` if `virt_addr` is not mapped.
+    #[must_use]
+    pub fn find_mapping(&self, virt_addr: VirtAddr) -> Option<Mapping> {
+        self.as_ref().find_mapping(virt_addr.page_num())
+    }
+
+    /// Returns an iterator over the leaf mappings in the ascending order of
+    /// the virtual addresses.
+    pub fn mappings(&self) -> impl Iterator<Item = Mapping> + '_ {
+        self.as_ref().into_mappings()
+    }
+
+    /// Returns the leaf mappings nearest to `virt_addr`.
+    ///
+    /// The first element is the last mapping that ends below `virt_addr` and
+    /// the second element is the first mapping that starts above it. Mappings
+    /// containing `virt_addr` are not returned.
+    #[must_use]
+    pub fn nearest_mappings(&self, virt_addr: VirtAddr) -> (Option<Mapping>, Option<Mapping>) {
+        let vpn = virt_addr.page_num();
+        let pt = self.as_ref();
+        (pt.find_mapping_before(vpn), pt.find_mapping_after(vpn))
+    }
+
+    /// Returns an iterator over the mapped regions in the ascending order of
+    /// the virtual addresses.
+    ///
+    /// Adjacent leaf mappings contiguous in both the virtual and physical
+    /// address spaces with the same flags are merged into one region.
+    pub fn regions(&self) -> impl Iterator<Item = MappedRegion> + '_ {
+        MergeMappings::new(self.mappings())
+    }
+
+    /// Writes the mapped regions in the serialized form.
+    ///
+    /// Each region returned by [`Self::regions`] is passed to
+    /// `regions_writer` as [`MappedRegion::ENCODED_SIZE`] bytes, so that the
+    /// description of the address space can be handed to a debugger or saved
+    /// in a crash dump. The frames of the page table are not included.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error returned by `regions_writer`.
+    pub fn export<W, E>(&self, mut regions_writer: W) -> Result<(), E>
+    where
+        W: FnMut(&[u8]) -> Result<(), E>,
+    {
+        for region in self.regions() {
+            regions_writer(&region.encode())?;
+        }
+        Ok(())
+    }
+
+    /// Creates a new page table with the specified ASID, mapping the regions
+    /// serialized by [`Self::export`].
+    ///
+    /// The regions are mapped with [`Self::map_fixed_pages`], so the new page
+    /// table refers to the same physical pages as the exported one, while the
+    /// page sizes may differ.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `bytes` is not a sequence of valid regions,
+    /// allocation fails, or the regions overlap.
+    pub fn import(asid: u16, bytes: &[u8]) -> Result<Self, PageTableError> {
+        #[cfg_attr(not(test), expect(clippy::wildcard_imports))]
+        use self::page_table_error::*;
+
+        let (regions, rest) = bytes.as_chunks::<{ MappedRegion::ENCODED_SIZE }>();
+        ensure!(rest.is_empty(), InvalidRegionSnafu);
+
+        let mut root = Self::new(asid)?;
+        for bytes in regions {
+            let region = MappedRegion::decode(bytes)?;
+            let mapped = root.map_fixed_pages(
+                region.virt_page_num,
+                region.phys_page_num,
+                region.page_count,
+                region.flags,
+            )?;
+            debug_assert_eq!(mapped, region.page_count);
+        }
+        Ok(root)
+    }
 }
 
 impl fmt::Debug for PageTableRoot {
@@ -0,0 +1,156 @@
+use platform_cast::CastFrom as _;
+use snafu::ensure;
+
+use super::{
+    MapPageFlags, Mapping, PageTableError,
+    address::{PhysPageNum, VirtPageNum},
+};
+
+/// Contiguous virtual pages mapped to contiguous physical pages with the same
+/// flags.
+///
+/// Unlike [`Mapping`], a region is not bound to a single page table entry:
+/// adjacent leaf mappings are merged into one region regardless of their
+/// levels, so the regions describe the address space independently of how
+/// the pages are split into entries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MappedRegion {
+    /// First virtual page of the region.
+    pub virt_page_num: VirtPageNum,
+    /// Physical page mapped to `virt_page_num`.
+    pub phys_page_num: PhysPageNum,
+    /// Number of pages in the region.
+    pub page_count: usize,
+    /// Permission flags of the region.
+    pub flags: MapPageFlags,
+}
+
+impl MappedRegion {
+    /// Size of a region in the serialized form.
+    ///
+    /// A region is serialized as the little-endian virtual page number (8
+    /// bytes), physical page number (8 bytes), page count (4 bytes) and flags
+    /// (4 bytes).
+    pub const ENCODED_SIZE: usize = 24;
+
+    fn from_mapping(mapping: &Mapping) -> Self {
+        let virt_page_num = mapping.min_virt_addr.page_num();
+        Self {
+            virt_page_num,
+            phys_page_num: mapping.min_phys_addr.page_num(),
+            page_count: mapping
+                .max_virt_addr
+                .page_num()
+                .checked_sub(virt_page_num)
+                .unwrap()
+                + 1,
+            flags: mapping.flags,
+        }
+    }
+
+    /// Extends the region with `other` if `other` starts right after the
+    /// region in both the virtual and physical address spaces.
+    fn try_merge(&mut self, other: &Self) -> bool {
+        let contiguous = other.virt_page_num.checked_sub(self.virt_page_num)
+            == Some(self.page_count)
+            && other.phys_page_num.checked_sub(self.phys_page_num) == Some(self.page_count);
+        if !contiguous || self.flags != other.flags {
+            return false;
+        }
+        self.page_count += other.page_count;
+        true
+    }
+
+    /// Returns the serialized form of the region.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `page_count` exceeds `u32::MAX`.
+    #[must_use]
+    pub fn encode(&self) -> [u8; Self::ENCODED_SIZE] {
+        let page_count = u32::try_from(self.page_count).unwrap();
+        let flags = u32::try_from(self.flags.bits()).unwrap();
+        let mut bytes = [0; Self::ENCODED_SIZE];
+        bytes[0..8].copy_from_slice(&self.virt_page_num.value().to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.phys_page_num.value().to_le_bytes());
+        bytes[16..20].copy_from_slice(&page_count.to_le_bytes());
+        bytes[20..24].copy_from_slice(&flags.to_le_bytes());
+        bytes
+    }
+
+    /// Parses the serialized form of a region.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the region is empty, exceeds the virtual or
+    /// physical address space, or has unknown flags.
+    pub fn decode(bytes: &[u8; Self::ENCODED_SIZE]) -> Result<Self, PageTableError> {
+        #[cfg_attr(not(test), expect(clippy::wildcard_imports))]
+        use super::page_table_error::*;
+
+        let word = |range: core::ops::Range<usize>| {
+            let mut buf = [0; 8];
+            buf[..range.len()].copy_from_slice(&bytes[range]);
+            u64::from_le_bytes(buf)
+        };
+        let vpn = word(0..8);
+        let ppn = word(8..16);
+        let page_count = word(16..20);
+        let flags = MapPageFlags::from_bits(word(20..24));
+
+        let valid = page_count > 0
+            && vpn
+                .checked_add(page_count - 1)
+                .is_some_and(|max| max <= VirtPageNum::MAX.value())
+            && ppn
+                .checked_add(page_count - 1)
+                .is_some_and(|max| max <= PhysPageNum::MAX.value());
+        ensure!(valid, InvalidRegionSnafu);
+        let Some(flags) = flags else {
+            return InvalidRegionSnafu.fail();
+        };
+
+        Ok(Self {
+            virt_page_num: VirtPageNum::new(vpn),
+            phys_page_num: PhysPageNum::new(ppn),
+            page_count: usize::cast_from(page_count),
+            flags,
+        })
+    }
+}
+
+/// Iterator merging adjacent leaf mappings into regions.
+pub(super) struct MergeMappings<I> {
+    mappings: I,
+    pending: Option<MappedRegion>,
+}
+
+impl<I> MergeMappings<I> {
+    pub(super) fn new(mappings: I) -> Self {
+        Self {
+            mappings,
+            pending: None,
+        }
+    }
+}
+
+impl<I> Iterator for MergeMappings<I>
+where
+    I: Iterator<Item = Mapping>,
+{
+    type Item = MappedRegion;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for mapping in self.mappings.by_ref() {
+            let region = MappedRegion::from_mapping(&mapping);
+            let Some(pending) = &mut self.pending else {
+                self.pending = Some(region);
+                continue;
+            };
+            if !pending.try_merge(&region) {
+                return self.pending.replace(region);
+            }
+        }
+        self.pending.take()
+    }
+}
@@ -1,15 +1,15 @@
-use alloc::boxed::Box;
+use alloc::{boxed::Box, vec, vec::Vec};
 use core::{
     iter::{Enumerate, FusedIterator},
-    ops::{Deref, DerefMut},
+    ops::{Deref, DerefMut, RangeInclusive},
     slice,
 };
 
 use dataview::Pod;
-use snafu::ResultExt as _;
+use snafu::{ResultExt as _, ensure};
 
 use super::{
-    MapPageFlags, PageTableError,
+    MapPageFlags, Mapping, PageTableError,
     address::{PhysAddr, PhysPageNum, VirtAddr, VirtPageNum},
     entry::{PageTableEntry, PageTableEntryRef},
 };
@@ -95,12 +95,99 @@ where
     pub(super) fn max_virt_addr(&self) -> VirtAddr {
         VirtAddr::max_in_page(self.max_vpn())
     }
+
+    pub(super) fn find_mapping(&self, vpn: VirtPageNum) -> Option<Mapping> {
+        let entry = self.entry(vpn.level_index(self.level));
+        if let Some(pt) = entry.next_level_table() {
+            return pt.find_mapping(vpn);
+        }
+        Mapping::from_entry(&entry)
+    }
+
+    /// Returns the last leaf mapping that ends before `vpn`.
+    pub(super) fn find_mapping_before(&self, vpn: VirtPageNum) -> Option<Mapping> {
+        if vpn <= self.min_vpn() {
+            return None;
+        }
+        let last_index = if vpn > self.max_vpn() {
+            NUM_ENTRIES - 1
+        } else {
+            vpn.level_index(self.level)
+        };
+        for index in (0..=last_index).rev() {
+            let entry = self.entry(index);
+            if let Some(pt) = entry.next_level_table() {
+                if let Some(mapping) = pt.find_mapping_before(vpn) {
+                    return Some(mapping);
+                }
+                continue;
+            }
+            if entry.max_vpn() < vpn
+                && let Some(mapping) = Mapping::from_entry(&entry)
+            {
+                return Some(mapping);
+            }
+        }
+        None
+    }
+
+    /// Returns the first leaf mapping that starts after `vpn`.
+    pub(super) fn find_mapping_after(&self, vpn: VirtPageNum) -> Option<Mapping> {
+        if vpn >= self.max_vpn() {
+            return None;
+        }
+        let first_index = if vpn < self.min_vpn() {
+            0
+        } else {
+            vpn.level_index(self.level)
+        };
+        for index in first_index..NUM_ENTRIES {
+            let entry = self.entry(index);
+            if let Some(pt) = entry.next_level_table() {
+                if let Some(mapping) = pt.find_mapping_after(vpn) {
+                    return Some(mapping);
+                }
+                continue;
+            }
+            if entry.min_vpn() > vpn
+                && let Some(mapping) = Mapping::from_entry(&entry)
+            {
+                return Some(mapping);
+            }
+        }
+        None
+    }
 }
 
 impl<R> PageTableRef<R>
 where
     R: DerefMut<Target = PageTable>,
 {
+    pub(super) fn share_entries(
+        &mut self,
+        source: &PageTableRef<&PageTable>,
+        vpn_range: RangeInclusive<VirtPageNum>,
+    ) -> Result<(), PageTableError> {
+        #[cfg_attr(not(test), expect(clippy::wildcard_imports))]
+        use super::page_table_error::*;
+
+        let indices =
+            vpn_range.start().level_index(self.level)..=vpn_range.end().level_index(self.level);
+        for index in indices.clone() {
+            let entry = self.entry(index);
+            ensure!(
+                !entry.is_valid(),
+                AlreadyMappedSnafu {
+                    phys_page_num: entry.phys_page_num().unwrap()
+                }
+            );
+        }
+        for index in indices {
+            self.pt.0[index] = source.pt.0[index].share();
+        }
+        Ok(())
+    }
+
     fn entry_mut(&mut self, index: usize) -> PageTableEntryRef<&mut PageTableEntry> {
         let level = self.level;
         let base_vpn = self.entry_base_vpn(index);
@@ -141,6 +228,153 @@ where
         Ok(mapped_count)
     }
 
+    pub(super) fn unmap_pages(
+        &mut self,
+        vpn_base: VirtPageNum,
+        count: usize,
+    ) -> Result<usize, PageTableError> {
+        #[cfg_attr(not(test), expect(clippy::wildcard_imports))]
+        use super::page_table_error::*;
+
+        let page_count_per_entry = 1 << (self.level * 9);
+
+        let mut unmapped_count = 0;
+        for level_index in vpn_base.level_index(self.level)..NUM_ENTRIES {
+            if unmapped_count >= count {
+                break;
+            }
+
+            let vpn = vpn_base + unmapped_count;
+            assert_eq!(level_index, vpn.level_index(self.level));
+            assert!(self.min_vpn() <= vpn && vpn <= self.max_vpn());
+
+            let mut entry = self.entry_mut(level_index);
+            if let Some(mut next_level_pt) = entry.next_level_table_mut() {
+                unmapped_count += next_level_pt.unmap_pages(vpn, count - unmapped_count)?;
+                continue;
+            }
+
+            if entry.is_leaf() {
+                ensure!(
+                    vpn.is_level_aligned(entry.level())
+                        && (count - unmapped_count) >= page_count_per_entry,
+                    PartialUnmapSnafu { virt_page_num: vpn }
+                );
+                entry.clear();
+            }
+            let entry_count = entry.max_vpn().checked_sub(vpn).unwrap() + 1;
+            unmapped_count += usize::min(entry_count, count - unmapped_count);
+        }
+        assert!(unmapped_count <= count);
+
+        Ok(unmapped_count)
+    }
+
+    /// Makes the pages in the range read-only and marks them copy-on-write.
+    pub(super) fn protect_cow(
+        &mut self,
+        vpn_base: VirtPageNum,
+        count: usize,
+    ) -> Result<usize, PageTableError> {
+        #[cfg_attr(not(test), expect(clippy::wildcard_imports))]
+        use super::page_table_error::*;
+
+        let page_count_per_entry = 1 << (self.level * 9);
+
+        let mut protected_count = 0;
+        for level_index in vpn_base.level_index(self.level)..NUM_ENTRIES {
+            if protected_count >= count {
+                break;
+            }
+
+            let vpn = vpn_base + protected_count;
+            assert_eq!(level_index, vpn.level_index(self.level));
+            assert!(self.min_vpn() <= vpn && vpn <= self.max_vpn());
+
+            let mut entry = self.entry_mut(level_index);
+            if let Some(mut next_level_pt) = entry.next_level_table_mut() {
+                protected_count += next_level_pt.protect_cow(vpn, count - protected_count)?;
+                continue;
+            }
+
+            if entry.is_leaf() {
+                ensure!(
+                    vpn.is_level_aligned(entry.level())
+                        && (count - protected_count) >= page_count_per_entry,
+                    PartialProtectSnafu { virt_page_num: vpn }
+                );
+                entry.protect_cow();
+            }
+            let entry_count = entry.max_vpn().checked_sub(vpn).unwrap() + 1;
+            protected_count += usize::min(entry_count, count - protected_count);
+        }
+        assert!(protected_count <= count);
+
+        Ok(protected_count)
+    }
+
+    /// Frees the lower level tables covering only the pages in the range that
+    /// have no valid entries, and returns the number of the freed tables.
+    pub(super) fn free_tables(&mut self, vpn_base: VirtPageNum, count: usize) -> usize {
+        let mut freed = 0;
+        let mut walked_count = 0;
+        for level_index in vpn_base.level_index(self.level)..NUM_ENTRIES {
+            if walked_count >= count {
+                break;
+            }
+
+            let vpn = vpn_base + walked_count;
+            assert_eq!(level_index, vpn.level_index(self.level));
+            assert!(self.min_vpn() <= vpn && vpn <= self.max_vpn());
+
+            let mut entry = self.entry_mut(level_index);
+            let entry_count = entry.max_vpn().checked_sub(vpn).unwrap() + 1;
+            let walk_count = usize::min(entry_count, count - walked_count);
+            // the tables partially in the range are kept
+            let whole_entry = walk_count == entry.vpn_count();
+            if let Some(mut next_level_pt) = entry.next_level_table_mut() {
+                freed += next_level_pt.free_tables(vpn, walk_count);
+                let is_empty = next_level_pt.entries().all(|entry| !entry.is_valid());
+                if whole_entry && is_empty {
+                    entry.free_next_level_table();
+                    freed += 1;
+                }
+            }
+            walked_count += walk_count;
+        }
+        assert!(walked_count <= count);
+
+        freed
+    }
+
+    pub(super) fn reserve_tables(
+        &mut self,
+        vpn_base: VirtPageNum,
+        count: usize,
+    ) -> Result<usize, PageTableError> {
+        let mut reserved_count = 0;
+        for level_index in vpn_base.level_index(self.level)..NUM_ENTRIES {
+            if reserved_count >= count {
+                break;
+            }
+
+            let vpn = vpn_base + reserved_count;
+            assert_eq!(level_index, vpn.level_index(self.level));
+            assert!(self.min_vpn() <= vpn && vpn <= self.max_vpn());
+
+            let mut entry = self.entry_mut(level_index);
+            let entry_count = entry.max_vpn().checked_sub(vpn).unwrap() + 1;
+            let mut next_level_pt = entry.get_or_insert_next_level_table()?;
+            if next_level_pt.level > 0 {
+                next_level_pt.reserve_tables(vpn, count - reserved_count)?;
+            }
+            reserved_count += usize::min(entry_count, count - reserved_count);
+        }
+        assert!(reserved_count <= count);
+
+        Ok(reserved_count)
+    }
+
     pub(super) fn map_fixed_pages(
         &mut self,
         vpn_base: VirtPageNum,
@@ -181,6 +415,23 @@ where
     }
 }
 
+impl<'pt> PageTableRef<&'pt PageTable> {
+    /// Returns an iterator over the leaf mappings in the address order.
+    pub(super) fn into_mappings(self) -> Mappings<'pt> {
+        Mappings {
+            stack: vec![self.into_entries()],
+        }
+    }
+
+    fn into_entries(self) -> Entries<'pt> {
+        Entries {
+            iter: self.pt.0.iter().enumerate(),
+            level: self.level,
+            base_vpn: self.base_vpn,
+        }
+    }
+}
+
 pub(super) struct Entries<'pt> {
     iter: Enumerate<slice::Iter<'pt, PageTableEntry>>,
     level: usize,
@@ -202,3 +453,31 @@ impl<'pt> Iterator for Entries<'pt> {
 }
 
 impl FusedIterator for Entries<'_> {}
+
+/// Iterator over the leaf mappings of a page table hierarchy.
+pub(super) struct Mappings<'pt> {
+    /// Entries of the tables from the root to the table being walked.
+    stack: Vec<Entries<'pt>>,
+}
+
+impl Iterator for Mappings<'_> {
+    type Item = Mapping;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(entries) = self.stack.last_mut() {
+            let Some(entry) = entries.next() else {
+                self.stack.pop();
+                continue;
+            };
+            if let Some(mapping) = Mapping::from_entry(&entry) {
+                return Some(mapping);
+            }
+            if let Some(pt) = entry.into_next_level_table() {
+                self.stack.push(pt.into_entries());
+            }
+        }
+        None
+    }
+}
+
+impl FusedIterator for Mappings<'_> {}